@@ -0,0 +1,448 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/.
+*/
+
+// Per-country rules for validating and formatting addresses, so that both
+// platforms render them the same way. The rules are a subset of Google's
+// libaddressinput data (https://chromium-i18n.appspot.com/ssl-address), using
+// the same format strings:
+//
+// - `%N` name, `%O` organization, `%A` street address, `%D` dependent
+//   locality (`address_level3`), `%C` city (`address_level2`), `%S` state or
+//   province (`address_level1`) and `%Z` postal code.
+// - `%n` is a line break, and anything else is literal text.
+//
+// Countries we don't have rules for use those for "ZZ", which only require a
+// street address and city.
+
+use crate::db::models::address::{Address, UpdatableAddressFields};
+use regex::Regex;
+use std::collections::HashMap;
+
+/// The address fields which can appear in a format string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressField {
+    Name,
+    Organization,
+    StreetAddress,
+    AddressLevel3,
+    AddressLevel2,
+    AddressLevel1,
+    PostalCode,
+}
+
+impl AddressField {
+    fn from_code(code: char) -> Option<Self> {
+        Some(match code {
+            'N' => Self::Name,
+            'O' => Self::Organization,
+            'A' => Self::StreetAddress,
+            'D' => Self::AddressLevel3,
+            'C' => Self::AddressLevel2,
+            'S' => Self::AddressLevel1,
+            'Z' => Self::PostalCode,
+            _ => return None,
+        })
+    }
+}
+
+/// A problem found by `validate_address()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressValidationError {
+    /// A field which the address's country requires is empty.
+    MissingRequiredField { field: AddressField },
+    /// The postal code doesn't have the format used in the address's country.
+    InvalidPostalCode,
+}
+
+struct CountryFormat {
+    // The format used in the country's own language.
+    fmt: &'static str,
+    // The format used for other languages (ie, with latin script), if
+    // different.
+    lfmt: Option<&'static str>,
+    // The language `fmt` is for, if `lfmt` is specified.
+    lang: Option<&'static str>,
+    // The fields which must be filled in, as format codes.
+    require: &'static str,
+    // A regex matching the whole postal code.
+    zip: Option<&'static str>,
+}
+
+const DEFAULT_FORMAT: CountryFormat = CountryFormat {
+    fmt: "%N%n%O%n%A%n%C",
+    lfmt: None,
+    lang: None,
+    require: "AC",
+    zip: None,
+};
+
+const fn format(fmt: &'static str, require: &'static str, zip: &'static str) -> CountryFormat {
+    CountryFormat {
+        fmt,
+        lfmt: None,
+        lang: None,
+        require,
+        zip: Some(zip),
+    }
+}
+
+const COUNTRY_FORMATS: &[(&str, CountryFormat)] = &[
+    ("AT", format("%O%n%N%n%A%n%Z %C", "ACZ", r"\d{4}")),
+    ("AU", format("%O%n%N%n%A%n%C %S %Z", "ACSZ", r"\d{4}")),
+    ("BE", format("%O%n%N%n%A%n%Z %C", "ACZ", r"\d{4}")),
+    (
+        "BR",
+        format("%O%n%N%n%A%n%D%n%C-%S%n%Z", "ASCZ", r"\d{5}-?\d{3}"),
+    ),
+    (
+        "CA",
+        format(
+            "%N%n%O%n%A%n%C %S %Z",
+            "ACSZ",
+            r"[ABCEGHJKLMNPRSTVXY]\d[ABCEGHJ-NPRSTV-Z] ?\d[ABCEGHJ-NPRSTV-Z]\d",
+        ),
+    ),
+    ("CH", format("%O%n%N%n%A%nCH-%Z %C", "ACZ", r"\d{4}")),
+    (
+        "CN",
+        CountryFormat {
+            fmt: "%Z%n%S%C%D%n%A%n%O%n%N",
+            lfmt: Some("%N%n%O%n%A%n%D%n%C%n%S, %Z"),
+            lang: Some("zh"),
+            require: "ACS",
+            zip: Some(r"\d{6}"),
+        },
+    ),
+    ("DE", format("%N%n%O%n%A%n%Z %C", "ACZ", r"\d{5}")),
+    ("ES", format("%N%n%O%n%A%n%Z %C %S", "ACSZ", r"\d{5}")),
+    ("FR", format("%O%n%N%n%A%n%Z %C", "ACZ", r"\d{2} ?\d{3}")),
+    (
+        "GB",
+        format(
+            "%N%n%O%n%A%n%C%n%Z",
+            "ACZ",
+            r"GIR ?0AA|[A-PR-UWYZ][A-HK-Y]?\d[\dA-HJKMNPR-Y]? ?\d[ABD-HJLNP-UW-Z]{2}",
+        ),
+    ),
+    ("IN", format("%N%n%O%n%A%n%C %Z%n%S", "ACSZ", r"\d{6}")),
+    ("IT", format("%N%n%O%n%A%n%Z %C %S", "ACSZ", r"\d{5}")),
+    (
+        "JP",
+        CountryFormat {
+            fmt: "〒%Z%n%S%n%A%n%O%n%N",
+            lfmt: Some("%N%n%O%n%A, %S%n%Z"),
+            lang: Some("ja"),
+            require: "ASZ",
+            zip: Some(r"\d{3}-?\d{4}"),
+        },
+    ),
+    ("MX", format("%N%n%O%n%A%n%D%n%Z %C, %S", "ACSZ", r"\d{5}")),
+    ("NL", format("%O%n%N%n%A%n%Z %C", "ACZ", r"\d{4} ?[A-Z]{2}")),
+    ("PL", format("%N%n%O%n%A%n%Z %C", "ACZ", r"\d{2}-\d{3}")),
+    ("SE", format("%O%n%N%n%A%nSE-%Z %C", "ACZ", r"\d{3} ?\d{2}")),
+    (
+        "US",
+        format("%N%n%O%n%A%n%C, %S %Z", "ACSZ", r"\d{5}(?:[ \-]\d{4})?"),
+    ),
+];
+
+lazy_static::lazy_static! {
+    static ref FORMATS: HashMap<&'static str, &'static CountryFormat> =
+        COUNTRY_FORMATS.iter().map(|(country, format)| (*country, format)).collect();
+
+    static ref POSTAL_CODE_REGEXES: HashMap<&'static str, Regex> = COUNTRY_FORMATS
+        .iter()
+        .filter_map(|(country, format)| {
+            let zip = format.zip?;
+            let regex = Regex::new(&format!("^(?i:{})$", zip)).expect("postal code regexes are valid");
+            Some((*country, regex))
+        })
+        .collect();
+}
+
+fn country_format(country: &str) -> &'static CountryFormat {
+    FORMATS
+        .get(country.trim().to_ascii_uppercase().as_str())
+        .copied()
+        .unwrap_or(&DEFAULT_FORMAT)
+}
+
+// The fields we need from either an `Address` or `UpdatableAddressFields`.
+struct AddressParts<'a> {
+    name: &'a str,
+    organization: &'a str,
+    street_address: &'a str,
+    address_level3: &'a str,
+    address_level2: &'a str,
+    address_level1: &'a str,
+    postal_code: &'a str,
+    country: &'a str,
+}
+
+impl<'a> AddressParts<'a> {
+    fn get(&self, field: AddressField) -> &'a str {
+        match field {
+            AddressField::Name => self.name,
+            AddressField::Organization => self.organization,
+            AddressField::StreetAddress => self.street_address,
+            AddressField::AddressLevel3 => self.address_level3,
+            AddressField::AddressLevel2 => self.address_level2,
+            AddressField::AddressLevel1 => self.address_level1,
+            AddressField::PostalCode => self.postal_code,
+        }
+        .trim()
+    }
+}
+
+impl<'a> From<&'a UpdatableAddressFields> for AddressParts<'a> {
+    fn from(a: &'a UpdatableAddressFields) -> Self {
+        Self {
+            name: &a.name,
+            organization: &a.organization,
+            street_address: &a.street_address,
+            address_level3: &a.address_level3,
+            address_level2: &a.address_level2,
+            address_level1: &a.address_level1,
+            postal_code: &a.postal_code,
+            country: &a.country,
+        }
+    }
+}
+
+impl<'a> From<&'a Address> for AddressParts<'a> {
+    fn from(a: &'a Address) -> Self {
+        Self {
+            name: &a.name,
+            organization: &a.organization,
+            street_address: &a.street_address,
+            address_level3: &a.address_level3,
+            address_level2: &a.address_level2,
+            address_level1: &a.address_level1,
+            postal_code: &a.postal_code,
+            country: &a.country,
+        }
+    }
+}
+
+/// Checks an address against the rules for its country, returning the
+/// problems found. An empty list means the address is valid.
+pub fn validate_address(address: UpdatableAddressFields) -> Vec<AddressValidationError> {
+    let parts = AddressParts::from(&address);
+    let format = country_format(parts.country);
+    let mut errors: Vec<_> = format
+        .require
+        .chars()
+        .filter_map(AddressField::from_code)
+        .filter(|field| parts.get(*field).is_empty())
+        .map(|field| AddressValidationError::MissingRequiredField { field })
+        .collect();
+    let postal_code = parts.get(AddressField::PostalCode);
+    if !postal_code.is_empty() {
+        let country = parts.country.trim().to_ascii_uppercase();
+        if let Some(regex) = POSTAL_CODE_REGEXES.get(country.as_str()) {
+            if !regex.is_match(postal_code) {
+                errors.push(AddressValidationError::InvalidPostalCode);
+            }
+        }
+    }
+    errors
+}
+
+/// Formats an address the way it's written in its country, as lines separated
+/// by `\n`. `locale` is a BCP 47 tag, like "en-US", and decides whether
+/// countries with their own script, like Japan, use their local format.
+pub fn format_address(address: Address, locale: String) -> String {
+    let parts = AddressParts::from(&address);
+    let format = country_format(parts.country);
+    let language = locale.split(['-', '_']).next().unwrap_or_default();
+    let fmt = match (format.lang, format.lfmt) {
+        (Some(lang), Some(lfmt)) if !lang.eq_ignore_ascii_case(language) => lfmt,
+        _ => format.fmt,
+    };
+    fmt.split("%n")
+        .flat_map(|line| format_line(line, &parts))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Formats a single line of a format string, which may expand to several lines
+// if it has a multi-line street address. A separator between two fields is
+// only kept if both of them have values, so a missing state doesn't leave us
+// with "Springfield,  12345", nor a missing city with ", IL 12345". Text
+// before the first field, like "〒", is a prefix rather than a separator, and
+// is kept if that field has a value.
+fn format_line(line: &str, parts: &AddressParts<'_>) -> Vec<String> {
+    let mut result = String::new();
+    let mut pending_literal = String::new();
+    let mut seen_field = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            pending_literal.push(c);
+            continue;
+        }
+        let Some(field) = chars.next().and_then(AddressField::from_code) else {
+            continue;
+        };
+        let value = parts.get(field);
+        let is_prefix = !seen_field;
+        seen_field = true;
+        if value.is_empty() {
+            pending_literal.clear();
+            continue;
+        }
+        // Only keep a separator if there's a value before it, as well as this one.
+        if is_prefix || !result.is_empty() {
+            result.push_str(&pending_literal);
+        }
+        pending_literal.clear();
+        result.push_str(value);
+    }
+    // Literal text at the end of a line (which none of our formats have) is
+    // dropped along with the separators.
+    result
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(country: &str) -> UpdatableAddressFields {
+        UpdatableAddressFields {
+            name: "Jane Doe".to_string(),
+            street_address: "123 Main St\nApt 4".to_string(),
+            address_level2: "Springfield".to_string(),
+            address_level1: "IL".to_string(),
+            postal_code: "62701".to_string(),
+            country: country.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn to_address(fields: UpdatableAddressFields) -> Address {
+        Address {
+            name: fields.name,
+            organization: fields.organization,
+            street_address: fields.street_address,
+            address_level3: fields.address_level3,
+            address_level2: fields.address_level2,
+            address_level1: fields.address_level1,
+            postal_code: fields.postal_code,
+            country: fields.country,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_regexes_compile() {
+        assert_eq!(POSTAL_CODE_REGEXES.len(), COUNTRY_FORMATS.len());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(validate_address(address("US")), vec![]);
+        assert_eq!(validate_address(address("us")), vec![]);
+
+        let mut a = address("US");
+        a.address_level1 = "".to_string();
+        a.postal_code = "6270".to_string();
+        assert_eq!(
+            validate_address(a),
+            vec![
+                AddressValidationError::MissingRequiredField {
+                    field: AddressField::AddressLevel1
+                },
+                AddressValidationError::InvalidPostalCode,
+            ]
+        );
+
+        // Germany doesn't need a state.
+        let mut a = address("DE");
+        a.address_level1 = "".to_string();
+        a.postal_code = "10115".to_string();
+        assert_eq!(validate_address(a), vec![]);
+
+        let mut a = address("CA");
+        a.postal_code = "k1a 0b1".to_string();
+        assert_eq!(validate_address(a), vec![]);
+
+        // Countries we don't have rules for only need a street and city, and
+        // any postal code is fine.
+        let mut a = address("XX");
+        a.address_level1 = "".to_string();
+        a.postal_code = "anything".to_string();
+        assert_eq!(validate_address(a), vec![]);
+        assert_eq!(
+            validate_address(UpdatableAddressFields::default()),
+            vec![
+                AddressValidationError::MissingRequiredField {
+                    field: AddressField::StreetAddress
+                },
+                AddressValidationError::MissingRequiredField {
+                    field: AddressField::AddressLevel2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            format_address(to_address(address("US")), "en-US".to_string()),
+            "Jane Doe\n123 Main St\nApt 4\nSpringfield, IL 62701"
+        );
+
+        // Missing fields don't leave stray separators.
+        let mut a = address("US");
+        a.address_level1 = "".to_string();
+        assert_eq!(
+            format_address(to_address(a), "en-US".to_string()),
+            "Jane Doe\n123 Main St\nApt 4\nSpringfield 62701"
+        );
+        let mut a = address("US");
+        a.address_level2 = "".to_string();
+        assert_eq!(
+            format_address(to_address(a), "en-US".to_string()),
+            "Jane Doe\n123 Main St\nApt 4\nIL 62701"
+        );
+        let mut a = address("US");
+        a.postal_code = "".to_string();
+        assert_eq!(
+            format_address(to_address(a), "en-US".to_string()),
+            "Jane Doe\n123 Main St\nApt 4\nSpringfield, IL"
+        );
+
+        let mut a = address("DE");
+        a.organization = "Mozilla".to_string();
+        a.postal_code = "10115".to_string();
+        a.address_level2 = "Berlin".to_string();
+        assert_eq!(
+            format_address(to_address(a), "de-DE".to_string()),
+            "Jane Doe\nMozilla\n123 Main St\nApt 4\n10115 Berlin"
+        );
+
+        let a = UpdatableAddressFields {
+            name: "山田太郎".to_string(),
+            street_address: "千代田区千代田1-1".to_string(),
+            address_level1: "東京都".to_string(),
+            postal_code: "100-0001".to_string(),
+            country: "JP".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_address(to_address(a.clone()), "ja-JP".to_string()),
+            "〒100-0001\n東京都\n千代田区千代田1-1\n山田太郎"
+        );
+        assert_eq!(
+            format_address(to_address(a), "en-US".to_string()),
+            "山田太郎\n千代田区千代田1-1, 東京都\n100-0001"
+        );
+    }
+}
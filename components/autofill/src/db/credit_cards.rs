@@ -10,9 +10,11 @@ use crate::db::{
     },
     schema::{CREDIT_CARD_COMMON_COLS, CREDIT_CARD_COMMON_VALS},
 };
+use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
 
 use rusqlite::{Connection, Transaction};
+use sql_support::ConnExt;
 use sync_guid::Guid;
 use types::Timestamp;
 
@@ -211,6 +213,53 @@ pub fn scrub_encrypted_credit_card_data(conn: &Connection) -> Result<()> {
     Ok(())
 }
 
+/// Re-encrypts the stored card numbers, and the copies of the server records
+/// in the mirror, with a new key. It all happens in one transaction, so if
+/// anything can't be decrypted with the old key nothing is changed.
+///
+/// The numbers themselves don't change, so neither do the change counters.
+/// Numbers which were scrubbed are left empty, as they still need to be
+/// fetched from the server again.
+pub fn rotate_encryption_key(
+    conn: &Connection,
+    old_encdec: &EncryptorDecryptor,
+    new_encdec: &EncryptorDecryptor,
+) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    let numbers: Vec<(Guid, String)> = tx.query_rows_and_then(
+        "SELECT guid, cc_number_enc FROM credit_cards_data WHERE cc_number_enc != ''",
+        [],
+        |row| -> Result<_> { Ok((row.get("guid")?, row.get("cc_number_enc")?)) },
+    )?;
+    for (guid, cc_number_enc) in numbers {
+        let cc_number = old_encdec.decrypt(&cc_number_enc, "cc_number")?;
+        tx.execute(
+            "UPDATE credit_cards_data SET cc_number_enc = :cc_number_enc WHERE guid = :guid",
+            rusqlite::named_params! {
+                ":cc_number_enc": new_encdec.encrypt(&cc_number, "cc_number")?,
+                ":guid": guid,
+            },
+        )?;
+    }
+    let payloads: Vec<(Guid, String)> = tx.query_rows_and_then(
+        "SELECT guid, payload FROM credit_cards_mirror",
+        [],
+        |row| -> Result<_> { Ok((row.get("guid")?, row.get("payload")?)) },
+    )?;
+    for (guid, payload) in payloads {
+        let payload = old_encdec.decrypt(&payload, "mirror payload")?;
+        tx.execute(
+            "UPDATE credit_cards_mirror SET payload = :payload WHERE guid = :guid",
+            rusqlite::named_params! {
+                ":payload": new_encdec.encrypt(&payload, "mirror payload")?,
+                ":guid": guid,
+            },
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
 pub fn touch(conn: &Connection, guid: &Guid) -> Result<()> {
     let tx = conn.unchecked_transaction()?;
     let now_ms = Timestamp::now();
@@ -235,7 +284,6 @@ pub fn touch(conn: &Connection, guid: &Guid) -> Result<()> {
 pub(crate) mod tests {
     use super::*;
     use crate::db::test::new_mem_db;
-    use crate::encryption::EncryptorDecryptor;
     use sync15::bso::IncomingBso;
 
     pub fn get_all(
@@ -615,6 +663,70 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_rotate_encryption_key() -> Result<()> {
+        let db = new_mem_db();
+        let old_encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+        let new_encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+        let card = add_credit_card(
+            &db,
+            UpdatableCreditCardFields {
+                cc_name: "john deer".to_string(),
+                cc_number_enc: old_encdec.encrypt("1234567812345678", "cc_number")?,
+                cc_number_last_4: "5678".to_string(),
+                cc_exp_month: 10,
+                cc_exp_year: 2025,
+                cc_type: "mastercard".to_string(),
+            },
+        )?;
+        let scrubbed = add_credit_card(
+            &db,
+            UpdatableCreditCardFields {
+                cc_name: "jane doe".to_string(),
+                cc_number_enc: "".to_string(),
+                cc_number_last_4: "1234".to_string(),
+                cc_exp_month: 3,
+                cc_exp_year: 2022,
+                cc_type: "visa".to_string(),
+            },
+        )?;
+        db.execute(
+            "INSERT INTO credit_cards_mirror (guid, payload) VALUES (:guid, :payload)",
+            rusqlite::named_params! {
+                ":guid": card.guid,
+                ":payload": old_encdec.encrypt("{\"cc-number\":\"1234567812345678\"}", "payload")?,
+            },
+        )?;
+        let mirror_payload = || db.query_one::<String>("SELECT payload FROM credit_cards_mirror");
+
+        // A key which can't decrypt the data changes nothing.
+        let wrong_encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+        assert!(matches!(
+            rotate_encryption_key(&db, &wrong_encdec, &new_encdec),
+            Err(Error::CryptoError(_))
+        ));
+        assert_eq!(
+            get_credit_card(&db, &card.guid)?.cc_number_enc,
+            card.cc_number_enc
+        );
+
+        rotate_encryption_key(&db, &old_encdec, &new_encdec)?;
+        let rotated = get_credit_card(&db, &card.guid)?;
+        assert_eq!(
+            new_encdec.decrypt(&rotated.cc_number_enc, "cc_number")?,
+            "1234567812345678"
+        );
+        assert_eq!(rotated.metadata.sync_change_counter, 0);
+        assert_eq!(get_credit_card(&db, &scrubbed.guid)?.cc_number_enc, "");
+        assert_eq!(
+            new_encdec.decrypt(&mirror_payload()?, "payload")?,
+            "{\"cc-number\":\"1234567812345678\"}"
+        );
+        assert!(old_encdec.decrypt(&mirror_payload()?, "payload").is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_credit_card_trigger_on_create() -> Result<()> {
         let db = new_mem_db();
@@ -5,6 +5,7 @@
 use crate::db::models::address::{Address, UpdatableAddressFields};
 use crate::db::models::credit_card::{CreditCard, UpdatableCreditCardFields};
 use crate::db::{addresses, credit_cards, AutofillDb};
+use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
 use error_support::handle_error;
 use rusqlite::{
@@ -162,6 +163,19 @@ impl Store {
         Ok(())
     }
 
+    /// Re-encrypts everything encrypted with `old_key` with `new_key`, for
+    /// when the key may have been compromised, or is moving to a new
+    /// keystore. Sync must be given the new key afterwards.
+    #[handle_error(Error)]
+    pub fn rotate_autofill_key(&self, old_key: String, new_key: String) -> ApiResult<()> {
+        // Currently only credit cards have encrypted data
+        credit_cards::rotate_encryption_key(
+            &self.db.lock().unwrap().writer,
+            &EncryptorDecryptor::new(&old_key)?,
+            &EncryptorDecryptor::new(&new_key)?,
+        )
+    }
+
     // This allows the embedding app to say "make this instance available to
     // the sync manager". The implementation is more like "offer to sync mgr"
     // (thereby avoiding us needing to link with the sync manager) but
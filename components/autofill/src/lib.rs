@@ -6,6 +6,7 @@
 #![allow(unknown_lints)]
 #![warn(rust_2018_idioms)]
 
+pub mod address_format;
 pub mod db;
 pub mod encryption;
 pub mod error;
@@ -15,6 +16,9 @@ pub mod sync;
 pub use crate::db::store::get_registered_sync_engine;
 
 // Expose stuff needed by the uniffi generated code.
+use crate::address_format::{
+    format_address, validate_address, AddressField, AddressValidationError,
+};
 use crate::db::models::address::*;
 use crate::db::models::credit_card::*;
 use crate::db::store::Store;
@@ -55,6 +55,17 @@ impl SyncEngineStorageImpl<InternalAddress> for AddressesEngineStorageImpl {
         Ok(())
     }
 
+    fn wipe_storage(&self, tx: &Transaction<'_>) -> Result<()> {
+        // The mirror is cleared first so that the delete trigger doesn't create
+        // tombstones, which would delete the records on other devices.
+        tx.execute_batch(
+            "DELETE FROM addresses_mirror;
+            DELETE FROM addresses_data;
+            DELETE FROM addresses_tombstones;",
+        )?;
+        Ok(())
+    }
+
     fn get_outgoing_impl(
         &self,
         enc_key: &Option<String>,
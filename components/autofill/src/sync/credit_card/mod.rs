@@ -58,6 +58,17 @@ impl SyncEngineStorageImpl<InternalCreditCard> for CreditCardsEngineStorageImpl
         Ok(())
     }
 
+    fn wipe_storage(&self, tx: &Transaction<'_>) -> Result<()> {
+        // The mirror is cleared first so that the delete trigger doesn't create
+        // tombstones, which would delete the records on other devices.
+        tx.execute_batch(
+            "DELETE FROM credit_cards_mirror;
+            DELETE FROM credit_cards_data;
+            DELETE FROM credit_cards_tombstones;",
+        )?;
+        Ok(())
+    }
+
     fn get_outgoing_impl(
         &self,
         enc_key: &Option<String>,
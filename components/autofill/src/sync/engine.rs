@@ -34,6 +34,7 @@ pub trait SyncEngineStorageImpl<T> {
         enc_key: &Option<String>,
     ) -> Result<Box<dyn ProcessIncomingRecordImpl<Record = T>>>;
     fn reset_storage(&self, conn: &Transaction<'_>) -> Result<()>;
+    fn wipe_storage(&self, conn: &Transaction<'_>) -> Result<()>;
     fn get_outgoing_impl(
         &self,
         enc_key: &Option<String>,
@@ -228,7 +229,10 @@ impl<T: SyncRecord + std::fmt::Debug> SyncEngine for ConfigSyncEngine<T> {
     }
 
     fn wipe(&self) -> anyhow::Result<()> {
-        log::warn!("not implemented as there isn't a valid use case for it");
+        let db = &self.store.db.lock().unwrap();
+        let tx = db.unchecked_transaction()?;
+        self.storage_impl.wipe_storage(&tx)?;
+        tx.commit()?;
         Ok(())
     }
 }
@@ -434,4 +438,47 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_engine_wipe() -> Result<()> {
+        let engine = create_engine();
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+        let synced = InternalCreditCard {
+            guid: Guid::random(),
+            cc_name: "Ms Jane Doe".to_string(),
+            cc_number_enc: encdec.encrypt("12341232412341234", "cc_number")?,
+            cc_number_last_4: "1234".to_string(),
+            cc_exp_month: 12,
+            cc_exp_year: 2021,
+            cc_type: "visa".to_string(),
+            ..Default::default()
+        };
+        let unsynced = InternalCreditCard {
+            guid: Guid::random(),
+            ..synced.clone()
+        };
+        {
+            let db = &engine.store.db.lock().unwrap();
+            let tx = db.writer.unchecked_transaction()?;
+            add_internal_credit_card(&tx, &synced)?;
+            add_internal_credit_card(&tx, &unsynced)?;
+            test_insert_mirror_record(
+                &tx,
+                synced
+                    .clone()
+                    .into_test_incoming_bso(&encdec, Default::default()),
+            );
+            tx.commit()?;
+        }
+
+        engine.wipe().expect("should work");
+
+        let conn = &engine.store.db.lock().unwrap().writer;
+        assert!(get_all(conn, "credit_cards_data".to_string())?.is_empty());
+        // Nothing is left to upload, so the next sync doesn't delete the
+        // cards on other devices.
+        assert!(get_all(conn, "credit_cards_tombstones".to_string())?.is_empty());
+        assert!(get_all(conn, "credit_cards_mirror".to_string())?.is_empty());
+        Ok(())
+    }
 }
@@ -275,7 +275,7 @@ class FxaClient(inner: FirefoxAccount, persistCallback: PersistCallback?) : Auto
      * caller should indicate to the user that there are authentication issues and allow them to
      * re-login by starting a new OAuth flow.
      *
-     * @param scope Single OAuth scope (no spaces) for which the client wants access
+     * @param scope OAuth scope for which the client wants access. Use [parseScope] to get one from a string.
      * @param ttl time in seconds for which the token will be valid
      * @return [AccessTokenInfo] that stores the token, along with its scopes and keys when complete
      * @throws FxaException.Network Network error while requesting the access token.
@@ -283,7 +283,7 @@ class FxaClient(inner: FirefoxAccount, persistCallback: PersistCallback?) : Auto
      * @throws FxaException.SyncScopedKeyMissingInServerResponse we received an access token for the
      * sync scoped, but the sync key that should accompany it was missing.
      */
-    fun getAccessToken(scope: String, ttl: Long? = null): AccessTokenInfo {
+    fun getAccessToken(scope: Scope, ttl: Long? = null): AccessTokenInfo {
         return withMetrics {
             try {
                 this.inner.getAccessToken(scope, ttl)
@@ -0,0 +1,30 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+package mozilla.appservices.fxaclient
+
+// `FxaException` is a flat error, which UniFFI can't pass back into Rust, so the mapping
+// from `FxaError::code()` is mirrored here.
+
+/**
+ * The stable code for this error, for keying localized error messages.
+ */
+val FxaException.code: FxaErrorCode get() = when (this) {
+    is FxaException.Authentication -> FxaErrorCode.AUTHENTICATION
+    is FxaException.Network -> FxaErrorCode.NETWORK
+    is FxaException.NoExistingAuthFlow -> FxaErrorCode.NO_EXISTING_AUTH_FLOW
+    is FxaException.WrongAuthFlow -> FxaErrorCode.WRONG_AUTH_FLOW
+    is FxaException.OriginMismatch -> FxaErrorCode.ORIGIN_MISMATCH
+    is FxaException.SyncScopedKeyMissingInServerResponse -> FxaErrorCode.SYNC_SCOPED_KEY_MISSING_IN_SERVER_RESPONSE
+    is FxaException.Panic -> FxaErrorCode.PANIC
+    is FxaException.Other -> FxaErrorCode.OTHER
+    is FxaException.StateConflict -> FxaErrorCode.STATE_CONFLICT
+    is FxaException.IncorrectPassword -> FxaErrorCode.INCORRECT_PASSWORD
+    is FxaException.TwoFactorRequired -> FxaErrorCode.TWO_FACTOR_REQUIRED
+}
+
+/**
+ * How the application should treat this error in its UI.
+ */
+val FxaException.userFacingHint: FxaErrorHint get() = fxaErrorHint(code)
@@ -207,7 +207,7 @@ open class FxAccountManager {
 
     /// Try to get an OAuth access token.
     public func getAccessToken(
-        scope: String,
+        scope: Scope,
         ttl: UInt64? = nil,
         completionHandler: @escaping (Result<AccessTokenInfo, Error>) -> Void
     ) {
@@ -540,7 +540,7 @@ open class FxAccountManager {
                         }
                         account.clearAccessTokenCache()
                         // Make sure we're back on track by re-requesting the profile access token.
-                        _ = try account.getAccessToken(scope: OAuthScope.profile)
+                        _ = try account.getAccessToken(scope: .profile)
                         return .recoveredFromAuthenticationProblem
                     } catch {
                         onError()
@@ -211,7 +211,7 @@ class PersistedFirefoxAccount {
         return try URL(string: inner.getManageDevicesUrl(entrypoint: entrypoint))!
     }
 
-    public func getAccessToken(scope: String, ttl: UInt64? = nil) throws -> AccessTokenInfo {
+    public func getAccessToken(scope: Scope, ttl: UInt64? = nil) throws -> AccessTokenInfo {
         defer { tryPersistState() }
         return try notifyAuthErrors {
             try self.inner.getAccessToken(scope: scope, ttl: ttl == nil ? nil : Int64(clamping: ttl!))
@@ -281,6 +281,32 @@ class PersistedFirefoxAccount {
     }
 }
 
+// `FxaError` is a flat error, which UniFFI can't pass back into Rust, so the mapping
+// from `FxaError::code()` is mirrored here.
+public extension FxaError {
+    /// The stable code for this error, for keying localized error messages.
+    var code: FxaErrorCode {
+        switch self {
+        case .Authentication: return .authentication
+        case .Network: return .network
+        case .NoExistingAuthFlow: return .noExistingAuthFlow
+        case .WrongAuthFlow: return .wrongAuthFlow
+        case .OriginMismatch: return .originMismatch
+        case .SyncScopedKeyMissingInServerResponse: return .syncScopedKeyMissingInServerResponse
+        case .Panic: return .panic
+        case .Other: return .other
+        case .StateConflict: return .stateConflict
+        case .IncorrectPassword: return .incorrectPassword
+        case .TwoFactorRequired: return .twoFactorRequired
+        }
+    }
+
+    /// How the application should treat this error in its UI.
+    var userFacingHint: FxaErrorHint {
+        return fxaErrorHint(code: code)
+    }
+}
+
 public protocol PersistCallback {
     func persist(json: String)
 }
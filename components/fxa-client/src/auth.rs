@@ -23,8 +23,9 @@
 //! Technical details of the pairing flow can be found in the [Firefox Accounts
 //! documentation hub](https://mozilla.github.io/ecosystem-platform/docs/features/firefox-accounts/pairing).
 
-use crate::{ApiResult, DeviceConfig, Error, FirefoxAccount};
+use crate::{AccountEvent, ApiResult, DeviceConfig, Error, FirefoxAccount};
 use error_support::handle_error;
+use std::collections::HashMap;
 
 impl FirefoxAccount {
     /// Get the current state
@@ -35,12 +36,32 @@ impl FirefoxAccount {
     /// Process an event (login, logout, etc).
     ///
     /// On success, returns the new state.
-    /// On error, the state will remain the same.
+    /// On error, the state will remain the same, unless the current flow timed out before the
+    /// event was handled (see [Self::check_state_timeout]).
     #[handle_error(Error)]
     pub fn process_event(&self, event: FxaEvent) -> ApiResult<FxaState> {
         self.internal.lock().process_event(event)
     }
 
+    /// Set how long the state machine waits in [FxaState::Authenticating] and
+    /// [FxaState::TwoFactorRequired] before giving up on the flow.
+    pub fn set_state_timeouts(&self, timeouts: FxaStateTimeouts) {
+        self.internal.lock().set_state_timeouts(timeouts)
+    }
+
+    /// Give up on the current flow if it's been waiting for longer than its timeout.
+    ///
+    /// If it has, this sends [FxaEvent::FlowTimedOut], which returns the state machine to
+    /// [FxaState::Disconnected] so that a new flow can be started.  [Self::process_event] does
+    /// this before handling each event, but the application should also call this when it's
+    /// resumed, so that it doesn't show a stale sign-in screen.
+    ///
+    /// Returns the current state, which is unchanged if the flow hasn't timed out.
+    #[handle_error(Error)]
+    pub fn check_state_timeout(&self) -> ApiResult<FxaState> {
+        self.internal.lock().check_state_timeout()
+    }
+
     /// Get the high-level authentication state of the client
     ///
     /// TODO: remove this and the FxaRustAuthState type from the public API
@@ -89,6 +110,26 @@ impl FirefoxAccount {
         self.internal.lock().begin_oauth_flow(&scopes, entrypoint)
     }
 
+    /// Initiate a web-based OAuth sign-in flow, with extra options.
+    ///
+    /// This is like [`begin_oauth_flow`](FirefoxAccount::begin_oauth_flow), but also
+    /// lets the application ask for a particular prompt, sign in as a specific account,
+    /// or add its own query parameters to the URL, rather than editing the URL it gets
+    /// back.
+    ///
+    /// # Arguments
+    ///
+    ///   - `options` - the [`OAuthFlowOptions`] for the flow.
+    ///
+    /// # Notes
+    ///
+    ///    - Throws if `options.extra_params` tries to set a parameter which is
+    ///      managed by this library, such as `scope`, `state` or the PKCE parameters.
+    #[handle_error(Error)]
+    pub fn begin_oauth_flow_with_options(&self, options: OAuthFlowOptions) -> ApiResult<String> {
+        self.internal.lock().begin_oauth_flow_with_options(&options)
+    }
+
     /// Get the URL at which to begin a device-pairing signin flow.
     ///
     /// If the user wants to sign in using device pairing, call this method and then
@@ -150,11 +191,43 @@ impl FirefoxAccount {
     ///
     ///   - `code` - the OAuth authorization code obtained from the redirect URI.
     ///   - `state` - the OAuth state parameter obtained from the redirect URI.
+    ///
+    /// # Notes
+    ///
+    ///    - Throws [`TwoFactorRequired`](crate::FxaError::TwoFactorRequired) if the account
+    ///      has two-step authentication enabled and the user hasn't entered a code yet. The
+    ///      flow stays active, so the application can call
+    ///      [`verify_totp_code`](FirefoxAccount::verify_totp_code) and then this method again
+    ///      with the same arguments.
     #[handle_error(Error)]
     pub fn complete_oauth_flow(&self, code: &str, state: &str) -> ApiResult<()> {
         self.internal.lock().complete_oauth_flow(code, state)
     }
 
+    /// Verify the current session with a code from the user's authenticator app.
+    ///
+    /// Returns `false` if the code was wrong, in which case the application should ask the
+    /// user for it again.
+    ///
+    /// # Arguments
+    ///
+    ///   - `code` - the 6-digit TOTP code, or a backup authentication code.
+    #[handle_error(Error)]
+    pub fn verify_totp_code(&self, code: &str) -> ApiResult<bool> {
+        self.internal.lock().verify_totp_code(code)
+    }
+
+    /// Take the account events which were queued by other methods, rather than received in a
+    /// push message.
+    ///
+    /// Applications should call this after [`complete_oauth_flow`](FirefoxAccount::complete_oauth_flow),
+    /// or once [`process_event`](FirefoxAccount::process_event) reaches [`FxaState::Connected`]
+    /// from an OAuth flow, and handle the events like the ones from [`handle_push_message`](FirefoxAccount::handle_push_message).
+    /// Currently, the only event queued this way is [`AccountEvent::KeysMismatch`].
+    pub fn take_pending_account_events(&self) -> Vec<AccountEvent> {
+        self.internal.lock().take_pending_account_events()
+    }
+
     /// Check authorization status for this application.
     ///
     /// **💾 This method alters the persisted account state.**
@@ -167,6 +240,24 @@ impl FirefoxAccount {
         Ok(self.internal.lock().check_authorization_status()?.into())
     }
 
+    /// Check whether the user's session is still valid.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    ///
+    /// This is a lightweight request to the FxA server which applications can make periodically
+    /// (for example, when they come to the foreground) to notice that the user has changed their
+    /// password or signed this device out from elsewhere, rather than waiting for the next sync
+    /// or token request to fail.
+    ///
+    /// Returns `false` if the session is no longer valid, in which case the account moves to
+    /// [FxaState::AuthIssues] and the application should ask the user to sign in again.
+    /// Throws [FxaError::Network](crate::FxaError::Network) if the server couldn't be reached,
+    /// which says nothing about whether the session is valid.
+    #[handle_error(Error)]
+    pub fn check_session_validity(&self) -> ApiResult<bool> {
+        self.internal.lock().check_session_validity()
+    }
+
     /// Disconnect from the user's account.
     ///
     /// **💾 This method alters the persisted account state.**
@@ -179,10 +270,27 @@ impl FirefoxAccount {
     /// user's last-seen profile information, if any. This may be useful in helping
     /// the user to reconnect to their account. If reconnecting to the same account
     /// is not desired then the application should discard the persisted account state.
+    ///
+    /// If the device record or refresh token can't be destroyed on the server, for example
+    /// because the device is offline, that's remembered in the persisted account state, and
+    /// can be retried with [`retry_pending_disconnect`](FirefoxAccount::retry_pending_disconnect).
     pub fn disconnect(&self) {
         self.internal.lock().disconnect()
     }
 
+    /// Try again to destroy any device records and refresh tokens that
+    /// [`disconnect`](FirefoxAccount::disconnect) couldn't.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    ///
+    /// Applications should call this when connectivity returns, or on startup. It does
+    /// nothing if there's nothing pending, and throws if some of the work is still pending
+    /// afterwards, in which case it can be called again later.
+    #[handle_error(Error)]
+    pub fn retry_pending_disconnect(&self) -> ApiResult<()> {
+        self.internal.lock().retry_pending_disconnect()
+    }
+
     /// Update the state based on authentication issues.
     ///
     /// **💾 This method alters the persisted account state.**
@@ -204,6 +312,45 @@ impl FirefoxAccount {
     }
 }
 
+/// Options for [`begin_oauth_flow_with_options`](FirefoxAccount::begin_oauth_flow_with_options).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OAuthFlowOptions {
+    /// The OAuth scopes to request.
+    pub scopes: Vec<String>,
+    /// Metrics identifier for the UX entrypoint.
+    pub entrypoint: String,
+    /// Extra query parameters to add to the URL, such as metrics parameters.
+    pub extra_params: HashMap<String, String>,
+    /// How the user should be prompted.
+    pub prompt: Option<OAuthPrompt>,
+    /// The email of the account the user must sign in as. If this isn't set and
+    /// the user has signed in before, they must sign in as that account again.
+    pub login_hint: Option<String>,
+}
+
+/// The OAuth `prompt` parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OAuthPrompt {
+    /// Make the user enter their password again, even if they're signed in.
+    Login,
+    /// Show the user the permissions being requested, even if they've already
+    /// granted them.
+    Consent,
+    /// Don't show any UI (`prompt=none`). The flow fails if the user would
+    /// need to interact with it.
+    Silent,
+}
+
+impl OAuthPrompt {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            OAuthPrompt::Login => "login",
+            OAuthPrompt::Consent => "consent",
+            OAuthPrompt::Silent => "none",
+        }
+    }
+}
+
 /// Information about the authorization state of the application.
 ///
 /// This struct represents metadata about whether the application is currently
@@ -238,6 +385,12 @@ pub enum FxaState {
     Disconnected,
     /// User is currently performing an OAuth flow
     Authenticating { oauth_url: String },
+    /// The account has two-step authentication enabled, and the user needs to enter a code from
+    /// their authenticator app before the OAuth flow can be completed.
+    ///
+    /// Ask the user for the code and send [FxaEvent::SubmitTotpCode].  The state machine
+    /// completes the flow from [FxaEvent::CompleteOAuthFlow] once the code is accepted.
+    TwoFactorRequired,
     /// User is currently connected to FxA
     Connected,
     /// User was connected to FxA, but we observed issues with the auth tokens.
@@ -245,6 +398,26 @@ pub enum FxaState {
     AuthIssues,
 }
 
+/// How long the state machine waits in states where the user needs to do something, before
+/// giving up on the flow with [FxaEvent::FlowTimedOut].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FxaStateTimeouts {
+    /// Milliseconds to wait in [FxaState::Authenticating] for the OAuth flow to be completed.
+    pub authenticating_ms: i64,
+    /// Milliseconds to wait in [FxaState::TwoFactorRequired] for a code. This is shorter,
+    /// since the OAuth code the state holds expires quickly.
+    pub two_factor_required_ms: i64,
+}
+
+impl Default for FxaStateTimeouts {
+    fn default() -> Self {
+        Self {
+            authenticating_ms: 60 * 60 * 1000,
+            two_factor_required_ms: 10 * 60 * 1000,
+        }
+    }
+}
+
 /// Fxa event
 ///
 /// These are the events that consumers send to [crate::FxaStateMachine::process_event]
@@ -273,8 +446,20 @@ pub enum FxaEvent {
     ///
     /// Send this event after the user has navigated through the OAuth flow and has reached the
     /// redirect URI.  Extract `code` and `state` from the query parameters or web channel.  If
-    /// successful the state machine will transition to [FxaState::Connected].
+    /// successful the state machine will transition to [FxaState::Connected].  If the account
+    /// has two-step authentication enabled, it will transition to
+    /// [FxaState::TwoFactorRequired] instead.
+    ///
+    /// Once connected, call [FirefoxAccount::take_pending_account_events] to check whether the
+    /// account's keys changed unexpectedly since the user last signed in.
     CompleteOAuthFlow { code: String, state: String },
+    /// Submit a code from the user's authenticator app.
+    ///
+    /// Send this from [FxaState::TwoFactorRequired].  If the code is right, the OAuth flow is
+    /// completed and the state machine will transition to [FxaState::Connected].  If it's wrong,
+    /// the state machine stays at [FxaState::TwoFactorRequired] so the user can try again.  It
+    /// also stays there after a network error.
+    SubmitTotpCode { code: String },
     /// Cancel an OAuth flow.
     ///
     /// Use this to cancel an in-progress OAuth, returning to [FxaState::Disconnected] so the
@@ -292,6 +477,13 @@ pub enum FxaEvent {
     /// Send this when the user is asking to be logged out.  The state machine will transition to
     /// [FxaState::Disconnected].
     Disconnect,
+    /// Give up on an OAuth flow which has been waiting too long.
+    ///
+    /// The state machine sends this itself when it's been in [FxaState::Authenticating] or
+    /// [FxaState::TwoFactorRequired] for longer than the [FxaStateTimeouts] allow.  Like
+    /// [FxaEvent::CancelOAuthFlow], it returns to [FxaState::Disconnected] so the process can
+    /// begin again.
+    FlowTimedOut,
     /// Force a call to [FirefoxAccount::get_profile]
     ///
     /// This is used for testing the auth/network retry code, since it hits the network and
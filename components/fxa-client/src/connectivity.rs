@@ -0,0 +1,46 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! # Connectivity
+//!
+//! When a request to the server fails, the application usually can't tell whether the user
+//! is offline, stuck behind a captive portal, or seeing an FxA outage, so it can't tell the
+//! user whether to check their connection or try again later. This module lets it find out
+//! by probing the server before showing an auth error.
+
+use crate::{ApiResult, Error, FirefoxAccount};
+use error_support::handle_error;
+
+impl FirefoxAccount {
+    /// Check whether the FxA server is reachable.
+    ///
+    /// This makes a single unauthenticated request for the server's client configuration
+    /// document, and classifies the response. It doesn't change the account state, so it's
+    /// safe to call whatever state the account is in.
+    #[handle_error(Error)]
+    pub fn check_connectivity(&self) -> ApiResult<FxaConnectivity> {
+        self.internal.lock().check_connectivity()
+    }
+}
+
+/// The result of [`FirefoxAccount::check_connectivity`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FxaConnectivity {
+    pub status: FxaConnectivityStatus,
+    /// Milliseconds until the request succeeded or failed.
+    pub latency_ms: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FxaConnectivityStatus {
+    /// The server returned its configuration document.
+    Ok,
+    /// The request couldn't be made, so the device is probably offline.
+    Offline,
+    /// Something other than the server answered: the request was redirected, the network
+    /// asked us to authenticate, or the response wasn't the configuration document.
+    CaptivePortalSuspected,
+    /// The server answered with an error.
+    ServerDown,
+}
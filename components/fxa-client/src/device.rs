@@ -19,7 +19,7 @@ use error_support::handle_error;
 use serde::{Deserialize, Serialize};
 use sync15::DeviceType;
 
-use crate::{ApiResult, DevicePushSubscription, Error, FirefoxAccount};
+use crate::{AccountEvent, ApiResult, DevicePushSubscription, Error, FirefoxAccount};
 
 impl FirefoxAccount {
     /// Create a new device record for this application.
@@ -194,6 +194,78 @@ impl FirefoxAccount {
             .lock()
             .ensure_capabilities(&supported_capabilities)
     }
+
+    /// Ensure that the device record for this application still exists.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    ///
+    /// The FxA server removes device records which haven't been used for a while. When that
+    /// happens, the device disappears from other devices' "send tab" menus, and commands sent
+    /// to it are lost. This method checks that the device record still exists and still
+    /// advertises the commands for its capabilities. If the record is gone, it registers it
+    /// again with the name, type, push subscription and capabilities it last registered.
+    ///
+    /// Applications should call this method periodically, for example on startup along
+    /// with [`ensure_capabilities`](FirefoxAccount::ensure_capabilities).
+    ///
+    /// Returns an [`AccountEvent::DeviceReregistered`] if the device had to be registered
+    /// again, which the application should handle like the events from
+    /// [`handle_push_message`](FirefoxAccount::handle_push_message), or `None` otherwise.
+    ///
+    /// # Notes
+    ///
+    ///    - If the application has never registered a device record, or doesn't know enough
+    ///      about the one it registered to do so again, this method throws an
+    ///      [`Other`](FxaError::Other) error, and the application should call
+    ///      [`initialize_device`](FirefoxAccount::initialize_device).
+    ///    - Device registration is only available to applications that have been
+    ///      granted the `https://identity.mozilla.com/apps/oldsync` scope.
+    #[handle_error(Error)]
+    pub fn ensure_device_registration(&self) -> ApiResult<Option<AccountEvent>> {
+        self.internal.lock().ensure_device_registration()
+    }
+
+    /// Get the device commands currently advertised for this application.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    ///
+    /// This method returns the commands in this application's device record, as seen by the
+    /// FxA server, and whether the keys registered for each one are current. Keys become
+    /// stale when the user's sync keys change, or when the application loses the private keys
+    /// it registered. Other devices can't send a command to this one while its keys are stale.
+    ///
+    /// The capabilities last passed to [`ensure_capabilities`](FirefoxAccount::ensure_capabilities)
+    /// which aren't advertised at all are also returned, with a
+    /// [`Missing`](CommandKeyFreshness::Missing) status.
+    ///
+    /// # Notes
+    ///
+    ///    - Device metadata is only visible to applications that have been
+    ///      granted the `https://identity.mozilla.com/apps/oldsync` scope.
+    #[handle_error(Error)]
+    pub fn get_local_device_capabilities(&self) -> ApiResult<Vec<LocalDeviceCapability>> {
+        self.internal.lock().get_local_device_capabilities()
+    }
+
+    /// Compare a device configuration with this application's device record.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    ///
+    /// This method returns how the device record differs from `config`, so that the
+    /// application can decide whether it needs to update or re-register its device, such as by
+    /// calling [`ensure_capabilities`](FirefoxAccount::ensure_capabilities). See
+    /// [`DeviceConfig::diff`] for the details.
+    ///
+    /// # Notes
+    ///
+    ///    - If the application has not registered a device record, this method throws an
+    ///      [`Other`](FxaError::Other) error.
+    ///    - Device metadata is only visible to applications that have been
+    ///      granted the `https://identity.mozilla.com/apps/oldsync` scope.
+    #[handle_error(Error)]
+    pub fn diff_device_config(&self, config: DeviceConfig) -> ApiResult<DeviceConfigDiff> {
+        self.internal.lock().diff_device_config(&config)
+    }
 }
 
 /// Device configuration
@@ -204,6 +276,90 @@ pub struct DeviceConfig {
     pub capabilities: Vec<DeviceCapability>,
 }
 
+impl DeviceConfig {
+    /// Describes how `device` and its advertised `capabilities`, as returned by
+    /// [`FirefoxAccount::get_local_device_capabilities`], differ from this configuration.
+    pub fn diff(
+        &self,
+        device: &LocalDevice,
+        capabilities: &[LocalDeviceCapability],
+    ) -> DeviceConfigDiff {
+        let advertised = |capability: &DeviceCapability| {
+            capabilities.iter().find(|c| {
+                &c.capability == capability && c.key_freshness != CommandKeyFreshness::Missing
+            })
+        };
+        let mut diff = DeviceConfigDiff {
+            name_changed: self.name != device.display_name,
+            device_type_changed: self.device_type != device.device_type,
+            ..Default::default()
+        };
+        for capability in &self.capabilities {
+            match advertised(capability) {
+                None => diff.added_capabilities.push(capability.clone()),
+                Some(c) if c.key_freshness == CommandKeyFreshness::Stale => {
+                    diff.stale_capabilities.push(capability.clone())
+                }
+                Some(_) => (),
+            }
+        }
+        for c in capabilities {
+            if c.key_freshness != CommandKeyFreshness::Missing
+                && !self.capabilities.contains(&c.capability)
+            {
+                diff.removed_capabilities.push(c.capability.clone());
+            }
+        }
+        diff.needs_reregistration = !diff.added_capabilities.is_empty()
+            || !diff.removed_capabilities.is_empty()
+            || !diff.stale_capabilities.is_empty();
+        diff
+    }
+}
+
+/// How a [`DeviceConfig`] differs from the device record, from
+/// [`FirefoxAccount::diff_device_config`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeviceConfigDiff {
+    pub name_changed: bool,
+    pub device_type_changed: bool,
+    /// Capabilities in the config which the device record doesn't advertise.
+    pub added_capabilities: Vec<DeviceCapability>,
+    /// Capabilities the device record advertises which aren't in the config.
+    pub removed_capabilities: Vec<DeviceCapability>,
+    /// Capabilities in the config which the device record advertises with stale keys.
+    pub stale_capabilities: Vec<DeviceCapability>,
+    /// Whether the capabilities need registering again, with
+    /// [`ensure_capabilities`](FirefoxAccount::ensure_capabilities). A changed name or type
+    /// only needs an update, with [`set_device_name`](FirefoxAccount::set_device_name).
+    pub needs_reregistration: bool,
+}
+
+/// A device command advertised for this application, from
+/// [`FirefoxAccount::get_local_device_capabilities`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LocalDeviceCapability {
+    pub capability: DeviceCapability,
+    /// The name of the FxA device command for the capability.
+    pub command: String,
+    pub key_freshness: CommandKeyFreshness,
+}
+
+/// Whether the keys advertised for a device command are the ones this application would
+/// use to decrypt commands sent to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandKeyFreshness {
+    /// The command is advertised with this application's current keys.
+    Current,
+    /// The command is advertised, but with keys encrypted with an old sync key, or that this
+    /// application no longer has. Other devices can't send the command until it's registered
+    /// again.
+    Stale,
+    /// The application has the capability, but its device record doesn't advertise the
+    /// command.
+    Missing,
+}
+
 /// Local device that's connecting to FxA
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LocalDevice {
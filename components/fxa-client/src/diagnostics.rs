@@ -0,0 +1,96 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! # Diagnostics
+//!
+//! When something goes wrong with a user's account, the interesting details are usually spread
+//! through the logs, if they were captured at all. This module lets the application ask for a
+//! snapshot of the account's health instead, suitable for rendering in an about:sync-style page
+//! or attaching to a support request.
+//!
+//! The report is sanitized: it never contains tokens, keys, or the user's profile data.
+
+use crate::{internal::util, Error, FirefoxAccount, FxaRustAuthState};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+
+/// The number of recent errors kept for the diagnostics report.
+const MAX_RECENT_ERRORS: usize = 10;
+
+lazy_static::lazy_static! {
+    static ref RECENT_ERRORS: Mutex<VecDeque<FxaDiagnosticsError>> = Mutex::new(VecDeque::new());
+}
+
+impl FirefoxAccount {
+    /// Collect a sanitized report on the health of the account.
+    ///
+    /// This is cheap, doesn't touch the network and never fails, so it's safe to call from
+    /// support tooling whatever state the account is in.
+    pub fn collect_diagnostics(&self) -> FxaDiagnostics {
+        self.internal.lock().collect_diagnostics()
+    }
+}
+
+/// A snapshot of the health of a [`FirefoxAccount`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FxaDiagnostics {
+    /// The name of the state machine state, eg "Connected".
+    pub state: String,
+    pub auth_state: FxaRustAuthState,
+    pub has_refresh_token: bool,
+    pub has_session_token: bool,
+    /// The scopes we hold scoped keys for.
+    pub scoped_key_scopes: Vec<String>,
+    pub access_tokens: Vec<AccessTokenDiagnostics>,
+    /// Whether we've registered a device record with the server.
+    pub device_registered: bool,
+    /// Whether the server has acknowledged our current device capabilities.
+    pub device_capabilities_registered: bool,
+    /// The most recent errors seen by any `FirefoxAccount` in this process, oldest first.
+    pub recent_errors: Vec<FxaDiagnosticsError>,
+}
+
+/// A cached access token, without the token itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccessTokenDiagnostics {
+    pub scope: String,
+    /// Seconds until the token expires; negative if it already has.
+    pub expires_in: i64,
+    pub has_scoped_key: bool,
+}
+
+/// An error, without its message. Messages can include server responses, URLs or scopes, so
+/// only the kind of error is kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FxaDiagnosticsError {
+    /// When the error happened, in milliseconds since the epoch.
+    pub timestamp: i64,
+    /// The name of the internal error, eg "RemoteError".
+    pub kind: String,
+    /// For errors from the FxA servers, the HTTP status and the server's error number.
+    pub status: Option<u64>,
+    pub errno: Option<u64>,
+}
+
+/// Remember an error for the diagnostics report.
+pub(crate) fn record_error(e: &Error) {
+    let (status, errno) = match e {
+        Error::RemoteError { code, errno, .. } => (Some(*code), Some(*errno)),
+        _ => (None, None),
+    };
+    let mut recent_errors = RECENT_ERRORS.lock();
+    if recent_errors.len() == MAX_RECENT_ERRORS {
+        recent_errors.pop_front();
+    }
+    recent_errors.push_back(FxaDiagnosticsError {
+        timestamp: util::now() as i64,
+        kind: e.kind().to_string(),
+        status,
+        errno,
+    });
+}
+
+pub(crate) fn recent_errors() -> Vec<FxaDiagnosticsError> {
+    RECENT_ERRORS.lock().iter().cloned().collect()
+}
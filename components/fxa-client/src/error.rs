@@ -52,11 +52,119 @@ pub enum FxaError {
     /// **Note:** This error is currently only thrown in the Kotlin language bindings.
     #[error("panic in native code")]
     Panic,
+    /// Thrown when saving the state to a file with [`FirefoxAccount::to_json_with_lock`] if
+    /// another process saved it since this one last read or wrote it, or if the other process
+    /// held the lock for too long. The application should reload the state and retry.
+    #[error("the state file was changed by another process")]
+    StateConflict,
+    /// Thrown by [`FirefoxAccount::create_recovery_key`] if the password is wrong. The
+    /// application should ask the user for it again.
+    #[error("incorrect password")]
+    IncorrectPassword,
+    /// Thrown by [`FirefoxAccount::complete_oauth_flow`] if the account has two-step
+    /// authentication enabled and the session hasn't passed the challenge yet. The application
+    /// should ask the user for a code, pass it to [`FirefoxAccount::verify_totp_code`], then
+    /// complete the flow again.
+    #[error("two-step authentication required")]
+    TwoFactorRequired,
     /// A catch-all for other unspecified errors.
     #[error("other error: {0}")]
     Other(String),
 }
 
+impl FxaError {
+    /// The stable code for this error, for applications to map to a localized message.
+    pub fn code(&self) -> FxaErrorCode {
+        match self {
+            FxaError::Authentication => FxaErrorCode::Authentication,
+            FxaError::Network => FxaErrorCode::Network,
+            FxaError::NoExistingAuthFlow => FxaErrorCode::NoExistingAuthFlow,
+            FxaError::WrongAuthFlow => FxaErrorCode::WrongAuthFlow,
+            FxaError::OriginMismatch => FxaErrorCode::OriginMismatch,
+            FxaError::SyncScopedKeyMissingInServerResponse => {
+                FxaErrorCode::SyncScopedKeyMissingInServerResponse
+            }
+            FxaError::Panic => FxaErrorCode::Panic,
+            FxaError::Other(_) => FxaErrorCode::Other,
+            FxaError::StateConflict => FxaErrorCode::StateConflict,
+            FxaError::IncorrectPassword => FxaErrorCode::IncorrectPassword,
+            FxaError::TwoFactorRequired => FxaErrorCode::TwoFactorRequired,
+        }
+    }
+
+    /// How the application should treat this error in its UI.
+    pub fn user_facing_hint(&self) -> FxaErrorHint {
+        self.code().user_facing_hint()
+    }
+}
+
+/// Stable codes for each kind of [`FxaError`].
+///
+/// The error messages are meant for developers and may change at any time, so applications
+/// which show errors to users should key their localized strings off these codes instead.
+/// The numeric values are never reused or renumbered; new codes are only ever added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FxaErrorCode {
+    Authentication = 1,
+    Network = 2,
+    NoExistingAuthFlow = 3,
+    WrongAuthFlow = 4,
+    OriginMismatch = 5,
+    SyncScopedKeyMissingInServerResponse = 6,
+    Panic = 7,
+    Other = 8,
+    StateConflict = 9,
+    IncorrectPassword = 10,
+    TwoFactorRequired = 11,
+}
+
+impl FxaErrorCode {
+    /// The numeric value of this code.
+    pub fn value(self) -> u32 {
+        self as u32
+    }
+
+    /// How the application should treat an error with this code in its UI.
+    pub fn user_facing_hint(self) -> FxaErrorHint {
+        match self {
+            FxaErrorCode::Network
+            | FxaErrorCode::StateConflict
+            | FxaErrorCode::IncorrectPassword
+            | FxaErrorCode::TwoFactorRequired => FxaErrorHint::Retryable,
+            FxaErrorCode::Authentication | FxaErrorCode::SyncScopedKeyMissingInServerResponse => {
+                FxaErrorHint::NeedsReauth
+            }
+            FxaErrorCode::NoExistingAuthFlow
+            | FxaErrorCode::WrongAuthFlow
+            | FxaErrorCode::OriginMismatch
+            | FxaErrorCode::Panic
+            | FxaErrorCode::Other => FxaErrorHint::Permanent,
+        }
+    }
+}
+
+/// A broad categorization of [`FxaError`]s, so that applications treat them consistently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FxaErrorHint {
+    /// The operation may succeed if it's retried later, for example once the device is back
+    /// online. The application might show a "try again" message.
+    Retryable,
+    /// The user needs to sign in again before the operation can succeed.
+    NeedsReauth,
+    /// Retrying won't help. The application should show a generic error message.
+    Permanent,
+}
+
+/// Get the numeric value of an error code.
+pub fn fxa_error_code_value(code: FxaErrorCode) -> u32 {
+    code.value()
+}
+
+/// Get the UI treatment for an error code.
+pub fn fxa_error_hint(code: FxaErrorCode) -> FxaErrorHint {
+    code.user_facing_hint()
+}
+
 /// FxA internal error type
 /// These are used in the internal code. This error type is never returned to the consumer.
 #[derive(Debug, thiserror::Error)]
@@ -70,6 +178,9 @@ pub enum Error {
     #[error("Multiple OAuth scopes requested")]
     MultipleScopesRequested,
 
+    #[error("Invalid OAuth scope: {0:?}")]
+    InvalidScope(String),
+
     #[error("No cached token for scope {0}")]
     NoCachedToken(String),
 
@@ -121,9 +232,15 @@ pub enum Error {
     #[error("Unsupported command: {0}")]
     UnsupportedCommand(&'static str),
 
+    #[error("OAuth parameter {0} can't be overridden")]
+    ReservedOAuthParameter(String),
+
     #[error("Missing URL parameter: {0}")]
     MissingUrlParameter(&'static str),
 
+    #[error("Invalid authorization parameter: {0}")]
+    InvalidAuthorizationParameter(&'static str),
+
     #[error("Null pointer passed to FFI")]
     NullPointer,
 
@@ -193,6 +310,98 @@ pub enum Error {
 
     #[error("Internal error in the state machine: {0}")]
     StateMachineLogicError(String),
+
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Timed out waiting for the lock on the state file")]
+    StateFileLocked,
+
+    #[error("The state file was written by another process (generation {0})")]
+    StateConflict(u64),
+
+    #[error("The session must pass a two-step authentication challenge")]
+    TwoFactorRequired,
+
+    // The email is the one the account was created with, so it's left out of the message.
+    #[error("The password was stretched with a different email than the account's original one")]
+    IncorrectEmailCase(String),
+}
+
+// The errno the auth server responds with when a request is made with the wrong password.
+const ERRNO_INCORRECT_PASSWORD: u64 = 103;
+// The errno the auth server responds with when a request needs a verified session. That can
+// mean the user hasn't confirmed their email yet, or, for accounts with two-step authentication,
+// that the session hasn't passed the TOTP challenge.
+const ERRNO_UNVERIFIED_SESSION: u64 = 138;
+
+impl Error {
+    /// The name of the variant, for diagnostics which mustn't include the error's details.
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Error::BackoffError(..) => "BackoffError",
+            Error::UnknownOAuthState => "UnknownOAuthState",
+            Error::MultipleScopesRequested => "MultipleScopesRequested",
+            Error::InvalidScope(..) => "InvalidScope",
+            Error::NoCachedToken(..) => "NoCachedToken",
+            Error::NoScopedKey(..) => "NoScopedKey",
+            Error::NoRefreshToken => "NoRefreshToken",
+            Error::NoSessionToken => "NoSessionToken",
+            Error::NoMigrationData => "NoMigrationData",
+            Error::NoCurrentDeviceId => "NoCurrentDeviceId",
+            Error::UnknownTargetDevice(..) => "UnknownTargetDevice",
+            Error::ApiClientError(..) => "ApiClientError",
+            Error::IllegalState(..) => "IllegalState",
+            Error::UnknownCommand(..) => "UnknownCommand",
+            Error::SendTabDiagnosisError(..) => "SendTabDiagnosisError",
+            Error::XorLengthMismatch(..) => "XorLengthMismatch",
+            Error::OriginMismatch(..) => "OriginMismatch",
+            Error::MismatchedKeys => "MismatchedKeys",
+            Error::SyncScopedKeyMissingInServerResponse => "SyncScopedKeyMissingInServerResponse",
+            Error::ScopeNotAllowed(..) => "ScopeNotAllowed",
+            Error::UnsupportedCommand(..) => "UnsupportedCommand",
+            Error::ReservedOAuthParameter(..) => "ReservedOAuthParameter",
+            Error::MissingUrlParameter(..) => "MissingUrlParameter",
+            Error::InvalidAuthorizationParameter(..) => "InvalidAuthorizationParameter",
+            Error::NullPointer => "NullPointer",
+            Error::InvalidBufferLength(..) => "InvalidBufferLength",
+            Error::AuthCircuitBreakerError => "AuthCircuitBreakerError",
+            Error::RemoteError { .. } => "RemoteError",
+            Error::CryptoError(..) => "CryptoError",
+            Error::EceError(..) => "EceError",
+            Error::HexDecodeError(..) => "HexDecodeError",
+            Error::Base64Decode(..) => "Base64Decode",
+            Error::JsonError(..) => "JsonError",
+            Error::JwCryptoError(..) => "JwCryptoError",
+            Error::UTF8DecodeError(..) => "UTF8DecodeError",
+            Error::RequestError(..) => "RequestError",
+            Error::MalformedUrl(..) => "MalformedUrl",
+            Error::UnexpectedStatus(..) => "UnexpectedStatus",
+            Error::SyncError(..) => "SyncError",
+            Error::HawkError(..) => "HawkError",
+            Error::IntegerConversionError(..) => "IntegerConversionError",
+            Error::CommandNotFound => "CommandNotFound",
+            Error::InvalidPushEvent => "InvalidPushEvent",
+            Error::InvalidStateTransition(..) => "InvalidStateTransition",
+            Error::StateMachineLogicError(..) => "StateMachineLogicError",
+            Error::IoError(..) => "IoError",
+            Error::StateFileLocked => "StateFileLocked",
+            Error::StateConflict(..) => "StateConflict",
+            Error::TwoFactorRequired => "TwoFactorRequired",
+            Error::IncorrectEmailCase(..) => "IncorrectEmailCase",
+        }
+    }
+
+    /// Did the server refuse the request because the session isn't verified?
+    pub(crate) fn is_unverified_session(&self) -> bool {
+        matches!(
+            self,
+            Error::RemoteError {
+                errno: ERRNO_UNVERIFIED_SESSION,
+                ..
+            }
+        )
+    }
 }
 
 // Define how our internal errors are handled and converted to external errors
@@ -200,8 +409,22 @@ pub enum Error {
 impl GetErrorHandling for Error {
     type ExternalError = FxaError;
 
+    // Every error we hand back to the application, or handle in the state machine, is
+    // converted by `convert_log_report_error`, which makes it the place to remember them for
+    // diagnostics.
+    fn note_error(&self) {
+        crate::diagnostics::record_error(self);
+    }
+
     fn get_error_handling(&self) -> ErrorHandling<Self::ExternalError> {
         match self {
+            Error::RemoteError {
+                errno: ERRNO_INCORRECT_PASSWORD,
+                ..
+            } => ErrorHandling::convert(FxaError::IncorrectPassword).log_warning(),
+            Error::TwoFactorRequired => {
+                ErrorHandling::convert(FxaError::TwoFactorRequired).log_warning()
+            }
             Error::RemoteError { code: 401, .. }
             | Error::NoRefreshToken
             | Error::NoScopedKey(_)
@@ -223,8 +446,41 @@ impl GetErrorHandling for Error {
                     .report_error("fxa-state-machine-error")
             }
             Error::OriginMismatch(_) => ErrorHandling::convert(FxaError::OriginMismatch),
+            Error::StateFileLocked | Error::StateConflict(_) => {
+                ErrorHandling::convert(FxaError::StateConflict).log_warning()
+            }
             _ => ErrorHandling::convert(FxaError::Other(self.to_string()))
                 .report_error("fxa-client-other-error"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_are_stable() {
+        let errors = [
+            (FxaError::Authentication, 1, FxaErrorHint::NeedsReauth),
+            (FxaError::Network, 2, FxaErrorHint::Retryable),
+            (FxaError::NoExistingAuthFlow, 3, FxaErrorHint::Permanent),
+            (FxaError::WrongAuthFlow, 4, FxaErrorHint::Permanent),
+            (FxaError::OriginMismatch, 5, FxaErrorHint::Permanent),
+            (
+                FxaError::SyncScopedKeyMissingInServerResponse,
+                6,
+                FxaErrorHint::NeedsReauth,
+            ),
+            (FxaError::Panic, 7, FxaErrorHint::Permanent),
+            (FxaError::Other("oops".into()), 8, FxaErrorHint::Permanent),
+            (FxaError::StateConflict, 9, FxaErrorHint::Retryable),
+            (FxaError::IncorrectPassword, 10, FxaErrorHint::Retryable),
+            (FxaError::TwoFactorRequired, 11, FxaErrorHint::Retryable),
+        ];
+        for (error, value, hint) in errors {
+            assert_eq!(fxa_error_code_value(error.code()), value, "{:?}", error);
+            assert_eq!(error.user_facing_hint(), hint, "{:?}", error);
+        }
+    }
+}
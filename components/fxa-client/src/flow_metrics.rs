@@ -0,0 +1,68 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! # Sign-in flow metrics
+//!
+//! The state machine times each OAuth flow, from the `BeginOAuthFlow` or `BeginPairingFlow`
+//! event that starts it, through each event processed while it's in progress, until it's
+//! completed (by reaching [`FxaState::Connected`](crate::FxaState::Connected)), timed out
+//! (by waiting for longer than the [`FxaStateTimeouts`](crate::FxaStateTimeouts) allow) or
+//! abandoned (by leaving [`FxaState::Authenticating`](crate::FxaState::Authenticating) any
+//! other way, including starting another flow).
+//!
+//! The application can collect the finished flows with
+//! [`take_flow_metrics`](FirefoxAccount::take_flow_metrics) and submit them with its own
+//! telemetry, to see where users drop out of signing in. Flows are only kept in memory, so
+//! one that's in progress when the application exits isn't reported.
+
+use crate::FirefoxAccount;
+
+impl FirefoxAccount {
+    /// Return the metrics for the sign-in flows which have finished since the last call,
+    /// oldest first.
+    pub fn take_flow_metrics(&self) -> Vec<OAuthFlowMetrics> {
+        self.internal.lock().take_flow_metrics()
+    }
+}
+
+/// Metrics for a finished OAuth flow.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OAuthFlowMetrics {
+    /// The metrics identifier for the UX entrypoint which began the flow.
+    pub entrypoint: String,
+    /// Whether the flow was begun with a pairing URL.
+    pub is_pairing: bool,
+    pub outcome: OAuthFlowOutcome,
+    /// Milliseconds from beginning the flow until it finished.
+    pub duration_ms: i64,
+    /// The events processed during the flow, in order, starting with the one which began it.
+    pub steps: Vec<OAuthFlowStep>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OAuthFlowOutcome {
+    /// The user connected their account.
+    Completed,
+    /// The flow was cancelled, the account was disconnected, or another flow was begun.
+    Abandoned,
+    /// The flow waited too long for the user, and was given up with
+    /// [`FxaEvent::FlowTimedOut`](crate::FxaEvent::FlowTimedOut).
+    TimedOut,
+}
+
+/// An event processed by the state machine during an OAuth flow.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OAuthFlowStep {
+    /// The name of the event, eg "CompleteOAuthFlow".
+    pub event: String,
+    /// The name of the state before the event, eg "Authenticating".
+    pub from_state: String,
+    /// The name of the state after the event. It's the same as `from_state` if the event
+    /// failed.
+    pub to_state: String,
+    /// Milliseconds since the previous step, or 0 for the first step.
+    pub duration_ms: i64,
+    /// Whether processing the event returned an error.
+    pub failed: bool,
+}
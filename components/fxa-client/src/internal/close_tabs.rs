@@ -55,7 +55,7 @@ impl FirefoxAccount {
                 Ok(IncomingDeviceCommand::TabsClosed { sender, payload })
             }
             Err(e) => {
-                log::warn!("Could not decrypt Close Remote Tabs payload. Diagnosing then resetting the Close Tabs keys.");
+                warn!("Could not decrypt Close Remote Tabs payload. Diagnosing then resetting the Close Tabs keys.");
                 self.clear_close_tabs_keys();
                 self.reregister_current_capabilities()?;
                 Err(e)
@@ -39,7 +39,27 @@ impl From<SendTabPayload> for crate::SendTabPayload {
 
 impl SendTabPayload {
     pub fn single_tab(title: &str, url: &str) -> (Self, telemetry::SentCommand) {
-        let sent_telemetry: telemetry::SentCommand = telemetry::SentCommand::for_send_tab();
+        Self::single_tab_with_telemetry(title, url, telemetry::SentCommand::for_send_tab())
+    }
+
+    /// Like `single_tab`, but the payload is part of the existing flow `flow_id`.
+    pub fn single_tab_in_flow(
+        title: &str,
+        url: &str,
+        flow_id: &str,
+    ) -> (Self, telemetry::SentCommand) {
+        Self::single_tab_with_telemetry(
+            title,
+            url,
+            telemetry::SentCommand::for_send_tab_in_flow(flow_id),
+        )
+    }
+
+    fn single_tab_with_telemetry(
+        title: &str,
+        url: &str,
+        sent_telemetry: telemetry::SentCommand,
+    ) -> (Self, telemetry::SentCommand) {
         (
             SendTabPayload {
                 entries: vec![TabHistoryEntry {
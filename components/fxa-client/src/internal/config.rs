@@ -5,8 +5,9 @@
 use super::http_client;
 use crate::{FxaConfig, Result};
 use serde_derive::{Deserialize, Serialize};
-use std::{cell::RefCell, sync::Arc};
+use std::{cell::RefCell, collections::HashMap, sync::Arc};
 use url::Url;
+use viaduct::{Header, HeaderName};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
@@ -14,6 +15,12 @@ pub struct Config {
     token_server_url_override: Option<String>,
     pub client_id: String,
     pub redirect_uri: String,
+    // Sent with every request. Only headers which passed
+    // `validate_extra_headers()` are stored here. They aren't persisted, since
+    // they can hold credentials for a gateway, so the application supplies them
+    // again when it restores the account.
+    #[serde(skip)]
+    extra_headers: HashMap<String, String>,
     // RemoteConfig is lazily fetched from the server.
     #[serde(skip)]
     remote_config: RefCell<Option<Arc<RemoteConfig>>>,
@@ -42,14 +49,56 @@ pub struct RemoteConfig {
 pub(crate) const CONTENT_URL_RELEASE: &str = "https://accounts.firefox.com";
 pub(crate) const CONTENT_URL_CHINA: &str = "https://accounts.firefox.com.cn";
 
+// Headers which `FxaConfig::extra_headers` can't set, either because we set
+// them ourselves, or because they're needed to get the request to the right
+// server intact.
+const RESERVED_HEADERS: &[&str] = &[
+    "authorization",
+    "connection",
+    "content-length",
+    "content-type",
+    "host",
+    "transfer-encoding",
+];
+
+// Drops, with a warning, any of the app's extra headers which we can't send.
+// `FirefoxAccount::new()` can't fail, so dropping them is the best we can do;
+// the gateway they were meant for will reject the requests, which is more
+// obvious than it sounds.
+fn validate_extra_headers(headers: HashMap<String, String>) -> HashMap<String, String> {
+    headers
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let name = match HeaderName::new(name) {
+                Ok(name) => name,
+                Err(e) => {
+                    warn!("Ignoring extra header: {}", e);
+                    return None;
+                }
+            };
+            if RESERVED_HEADERS.contains(&name.as_str()) {
+                warn!("Ignoring extra header {}: it can't be overridden", name);
+                return None;
+            }
+            match Header::new(name.clone(), value) {
+                Ok(header) => Some((name.to_string(), header.value().to_string())),
+                Err(_) => {
+                    warn!("Ignoring extra header {}: its value is invalid", name);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
 impl Config {
     fn remote_config(&self) -> Result<Arc<RemoteConfig>> {
         if let Some(remote_config) = self.remote_config.borrow().clone() {
             return Ok(remote_config);
         }
 
-        let client_config = http_client::fxa_client_configuration(self.client_config_url()?)?;
-        let openid_config = http_client::openid_configuration(self.openid_config_url()?)?;
+        let client_config = http_client::fxa_client_configuration(self)?;
+        let openid_config = http_client::openid_configuration(self)?;
 
         let remote_config = self.set_remote_config(RemoteConfig {
             auth_url: format!("{}/", client_config.auth_server_base_url),
@@ -160,6 +209,20 @@ impl Config {
         Url::parse(&self.remote_config()?.userinfo_endpoint).map_err(Into::into)
     }
 
+    pub fn set_extra_headers(&mut self, extra_headers: HashMap<String, String>) {
+        self.extra_headers = validate_extra_headers(extra_headers);
+    }
+
+    /// The headers the application asked for us to send with every request.
+    pub fn extra_headers(&self) -> Vec<Header> {
+        self.extra_headers
+            .iter()
+            .map(|(name, value)| Header::new(name.clone(), value.clone()))
+            .collect::<std::result::Result<_, _>>()
+            // These were validated before they were stored.
+            .expect("extra headers should be valid")
+    }
+
     fn normalize_token_server_url(token_server_url_override: &str) -> String {
         // In self-hosting setups it is common to specify the `/1.0/sync/1.5` suffix on the
         // tokenserver URL. Accept and strip this form as a convenience for users.
@@ -185,6 +248,7 @@ impl From<FxaConfig> for Config {
             client_id: fxa_config.client_id,
             redirect_uri: fxa_config.redirect_uri,
             token_server_url_override,
+            extra_headers: validate_extra_headers(fxa_config.extra_headers.unwrap_or_default()),
             remote_config: RefCell::new(None),
         }
     }
@@ -212,6 +276,7 @@ impl Config {
             redirect_uri: redirect_uri.to_string(),
             remote_config: RefCell::new(None),
             token_server_url_override: None,
+            extra_headers: HashMap::new(),
         }
     }
 
@@ -256,6 +321,7 @@ mod tests {
             client_id: "263ceaa5546dce83".to_string(),
             redirect_uri: "https://127.0.0.1:8080".to_string(),
             token_server_url_override: None,
+            extra_headers: HashMap::new(),
         };
         assert_eq!(
             config.auth_url_path("v1/account/keys").unwrap().to_string(),
@@ -308,6 +374,7 @@ mod tests {
             client_id: "263ceaa5546dce83".to_string(),
             redirect_uri: "https://127.0.0.1:8080".to_string(),
             token_server_url_override: None,
+            extra_headers: HashMap::new(),
         };
 
         config.override_token_server_url("https://foo.bar");
@@ -340,6 +407,7 @@ mod tests {
             client_id: "263ceaa5546dce83".to_string(),
             redirect_uri: "https://127.0.0.1:8080".to_string(),
             token_server_url_override: None,
+            extra_headers: HashMap::new(),
         };
 
         config.override_token_server_url("https://foo.bar/prefix/1.0/sync/1.5");
@@ -360,4 +428,39 @@ mod tests {
             "https://foo.bar/1.0/sync/1.5/foobar"
         );
     }
+
+    #[test]
+    fn test_extra_headers() {
+        let mut fxa_config = FxaConfig::release("12345678", "https://foo.bar");
+        fxa_config.extra_headers = Some(
+            [
+                ("X-Gateway-Auth", "secret"),
+                ("Authorization", "Bearer nope"),
+                ("host", "example.com"),
+                ("bad header", "value"),
+                ("x-bad-value", "line\nbreak"),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        );
+        let config = Config::from(fxa_config);
+        let headers = config.extra_headers();
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[0].name(), "x-gateway-auth");
+        assert_eq!(headers[0].value(), "secret");
+
+        // They aren't persisted with the rest of the config...
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(!json.contains("secret"));
+        let mut restored: Config = serde_json::from_str(&json).unwrap();
+        assert!(restored.extra_headers().is_empty());
+
+        // ...so they're set again after restoring it.
+        restored.set_extra_headers(HashMap::from([(
+            "X-Gateway-Auth".to_string(),
+            "secret".to_string(),
+        )]));
+        assert_eq!(restored.extra_headers(), headers);
+    }
 }
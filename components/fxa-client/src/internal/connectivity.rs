@@ -0,0 +1,154 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::{http_client::ClientConfigurationResponse, FirefoxAccount};
+use crate::{Error, FxaConnectivity, FxaConnectivityStatus, Result};
+use std::time::Instant;
+use url::Url;
+use viaduct::{status_codes, Response};
+
+impl FirefoxAccount {
+    pub fn check_connectivity(&self) -> Result<FxaConnectivity> {
+        let config = self.state.config();
+        let probe_url = config.client_config_url()?;
+        let started = Instant::now();
+        let result = self.client.probe_connectivity(config);
+        let latency_ms = started.elapsed().as_millis() as i64;
+        let status = match result {
+            Ok(resp) => classify_response(&probe_url, &resp),
+            Err(Error::RequestError(
+                viaduct::Error::NetworkError(_) | viaduct::Error::BackendError(_),
+            )) => FxaConnectivityStatus::Offline,
+            Err(e) => return Err(e),
+        };
+        Ok(FxaConnectivity { status, latency_ms })
+    }
+}
+
+fn classify_response(probe_url: &Url, resp: &Response) -> FxaConnectivityStatus {
+    // Captive portals answer every request themselves, usually by redirecting to their
+    // login page.
+    if resp.url.origin() != probe_url.origin()
+        || resp.status == status_codes::NETWORK_AUTHENTICATION_REQUIRED
+        || (300..400).contains(&resp.status)
+    {
+        return FxaConnectivityStatus::CaptivePortalSuspected;
+    }
+    if !resp.is_success() {
+        return FxaConnectivityStatus::ServerDown;
+    }
+    // ...but some serve their login page in place of whatever was asked for.
+    match resp.json::<ClientConfigurationResponse>() {
+        Ok(_) => FxaConnectivityStatus::Ok,
+        Err(_) => FxaConnectivityStatus::CaptivePortalSuspected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::{config::Config, http_client::MockFxAClient};
+    use mockall::{predicate::always, Sequence};
+    use std::sync::Arc;
+    use viaduct::{Headers, Method};
+
+    const CONFIG_BODY: &str = r#"{
+        "auth_server_base_url": "https://api.accounts.firefox.com",
+        "oauth_server_base_url": "https://oauth.accounts.firefox.com",
+        "profile_server_base_url": "https://profile.accounts.firefox.com",
+        "sync_tokenserver_base_url": "https://token.services.mozilla.com"
+    }"#;
+
+    fn probe_url() -> Url {
+        Url::parse("https://accounts.firefox.com/.well-known/fxa-client-configuration").unwrap()
+    }
+
+    fn response(url: Url, status: u16, body: &str) -> Response {
+        Response {
+            request_method: Method::Get,
+            url,
+            status,
+            headers: Headers::new(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_classify_response() {
+        let classify = |url: Url, status: u16, body: &str| {
+            classify_response(&probe_url(), &response(url, status, body))
+        };
+        assert_eq!(
+            classify(probe_url(), 200, CONFIG_BODY),
+            FxaConnectivityStatus::Ok
+        );
+        assert_eq!(
+            classify(probe_url(), 503, "Service Unavailable"),
+            FxaConnectivityStatus::ServerDown
+        );
+        assert_eq!(
+            classify(probe_url(), 404, "{}"),
+            FxaConnectivityStatus::ServerDown
+        );
+        assert_eq!(
+            classify(probe_url(), 511, "<html>Log in</html>"),
+            FxaConnectivityStatus::CaptivePortalSuspected
+        );
+        assert_eq!(
+            classify(probe_url(), 302, ""),
+            FxaConnectivityStatus::CaptivePortalSuspected
+        );
+        assert_eq!(
+            classify(
+                Url::parse("http://portal.example.com/login").unwrap(),
+                200,
+                "<html>Log in</html>"
+            ),
+            FxaConnectivityStatus::CaptivePortalSuspected
+        );
+        assert_eq!(
+            classify(probe_url(), 200, "<html>Log in</html>"),
+            FxaConnectivityStatus::CaptivePortalSuspected
+        );
+    }
+
+    #[test]
+    fn test_check_connectivity() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        let mut client = MockFxAClient::new();
+        let mut seq = Sequence::new();
+        client
+            .expect_probe_connectivity()
+            .with(always())
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| {
+                Err(Error::RequestError(viaduct::Error::NetworkError(
+                    "offline".to_string(),
+                )))
+            });
+        client
+            .expect_probe_connectivity()
+            .with(always())
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|config| {
+                Ok(response(
+                    config.client_config_url().unwrap(),
+                    200,
+                    CONFIG_BODY,
+                ))
+            });
+        fxa.set_client(Arc::new(client));
+        assert_eq!(
+            fxa.check_connectivity().unwrap().status,
+            FxaConnectivityStatus::Offline
+        );
+        assert_eq!(
+            fxa.check_connectivity().unwrap().status,
+            FxaConnectivityStatus::Ok
+        );
+    }
+}
@@ -12,7 +12,10 @@ use super::{
     },
     scopes, telemetry, util, CachedResponse, FirefoxAccount,
 };
-use crate::{DeviceCapability, Error, LocalDevice, Result};
+use crate::{
+    AccountEvent, CommandKeyFreshness, DeviceCapability, DeviceConfig, DeviceConfigDiff, Error,
+    LocalDevice, LocalDeviceCapability, Result,
+};
 use sync15::DeviceType;
 
 // An devices response is considered fresh for `DEVICES_FRESHNESS_THRESHOLD` ms.
@@ -150,6 +153,68 @@ impl FirefoxAccount {
         Ok(())
     }
 
+    /// Checks that our device record still exists on the server, and still advertises the
+    /// commands for our capabilities.
+    ///
+    /// FxA garbage-collects device records which haven't been used for a while, and we'd
+    /// otherwise only find out when commands sent to us go missing. If our record has gone, we
+    /// register it again with the name, type, push subscription and capabilities we last
+    /// registered, and return an [`AccountEvent::DeviceReregistered`] describing it.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    pub fn ensure_device_registration(&mut self) -> Result<Option<AccountEvent>> {
+        let previous_device_id = match self.state.current_device_id() {
+            Some(device_id) => device_id.to_string(),
+            None => return Err(Error::NoCurrentDeviceId),
+        };
+        let capabilities: Vec<_> = self.state.device_capabilities().iter().cloned().collect();
+
+        if let Some(device) = self
+            .get_devices(true)?
+            .into_iter()
+            .find(|d| d.is_current_device)
+        {
+            let registered: HashSet<DeviceCapability> = device
+                .common
+                .available_commands
+                .into_keys()
+                .filter_map(|command| command.try_into().ok())
+                .collect();
+            if registered != *self.state.device_capabilities() {
+                info!("Our device record is missing some commands, registering them again");
+                self.reregister_current_capabilities()?;
+            }
+            return Ok(None);
+        }
+
+        // We need to know what we registered last time to be able to do it again. If a
+        // failed update meant we forgot, the app has to call `initialize_device()`.
+        let last_registered = self
+            .state
+            .server_local_device_info()
+            .cloned()
+            .ok_or(Error::NoCurrentDeviceId)?;
+        warn!("Our device record has been removed from the server, registering it again");
+        let commands = self.register_capabilities(&capabilities)?;
+        let push_subscription = last_registered
+            .push_subscription
+            .map(PushSubscription::from);
+        let mut builder = DeviceUpdateRequestBuilder::new()
+            .display_name(&last_registered.display_name)
+            .device_type(&last_registered.device_type)
+            .available_commands(&commands);
+        if let Some(push_subscription) = &push_subscription {
+            builder = builder.push_subscription(push_subscription);
+        }
+        let device = self.update_device(builder.build())?;
+        // The cached list doesn't have our new record.
+        self.devices_cache = None;
+        Ok(Some(AccountEvent::DeviceReregistered {
+            previous_device_id,
+            device,
+        }))
+    }
+
     pub(crate) fn invoke_command(
         &self,
         command: &str,
@@ -181,7 +246,25 @@ impl FirefoxAccount {
     ) -> Result<Vec<IncomingDeviceCommand>> {
         let last_command_index = self.state.last_handled_command_index().unwrap_or(0);
         // We increment last_command_index by 1 because the server response includes the current index.
-        self.fetch_and_parse_commands(last_command_index + 1, None, reason)
+        let commands = self.fetch_and_parse_commands(last_command_index + 1, None, reason)?;
+        self.state.set_last_commands_poll(util::now());
+        Ok(commands)
+    }
+
+    /// Poll for pending commands, unless we already polled in the last `max_age_ms`
+    /// milliseconds, in which case this returns an empty list without hitting the server.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    pub fn poll_device_commands_if_stale(
+        &mut self,
+        max_age_ms: u64,
+    ) -> Result<Vec<IncomingDeviceCommand>> {
+        if let Some(last_poll) = self.state.last_commands_poll() {
+            if util::now() < last_poll.saturating_add(max_age_ms) {
+                return Ok(Vec::new());
+            }
+        }
+        self.poll_device_commands(CommandFetchReason::Poll)
     }
 
     pub fn get_command_for_index(&mut self, index: u64) -> Result<IncomingDeviceCommand> {
@@ -208,7 +291,7 @@ impl FirefoxAccount {
         if pending_commands.messages.is_empty() {
             return Ok(Vec::new());
         }
-        log::info!("Handling {} messages", pending_commands.messages.len());
+        info!("Handling {} messages", pending_commands.messages.len());
         let device_commands = self.parse_commands_messages(pending_commands.messages, reason)?;
         self.state
             .set_last_handled_command_index(pending_commands.index);
@@ -330,6 +413,73 @@ impl FirefoxAccount {
         }
     }
 
+    /// Returns the commands our device record advertises, and those of our capabilities it
+    /// doesn't, along with whether the keys registered for each are the ones we'd use to
+    /// decrypt commands sent to us.
+    pub fn get_local_device_capabilities(&mut self) -> Result<Vec<LocalDeviceCapability>> {
+        let device = self.get_current_device()?;
+        let oldsync_key = self.get_scoped_key(scopes::OLD_SYNC)?;
+        let mut capabilities = Vec::new();
+        if let Some(device) = &device {
+            for command in device.available_commands.keys() {
+                let capability = match DeviceCapability::try_from(command.clone()) {
+                    Ok(capability) => capability,
+                    Err(e) => {
+                        warn!("While checking our device commands: {e}");
+                        continue;
+                    }
+                };
+                let command: &'static str = capability.command_name();
+                // The keys are stale if they were encrypted with an old sync key, or if we've
+                // since lost or replaced our private keys.
+                let registered = commands::get_public_keys(oldsync_key, device, command).ok();
+                let own = self
+                    .state
+                    .get_commands_data(command)
+                    .and_then(|s| PrivateCommandKeys::deserialize(s).ok())
+                    .map(PublicCommandKeys::from);
+                let key_freshness = match (registered, own) {
+                    (Some(registered), Some(own))
+                        if registered.public_key() == own.public_key()
+                            && registered.auth_secret() == own.auth_secret() =>
+                    {
+                        CommandKeyFreshness::Current
+                    }
+                    _ => CommandKeyFreshness::Stale,
+                };
+                capabilities.push(LocalDeviceCapability {
+                    capability,
+                    command: command.to_string(),
+                    key_freshness,
+                });
+            }
+        }
+        let mut missing: Vec<_> = self
+            .state
+            .device_capabilities()
+            .iter()
+            .filter(|wanted| !capabilities.iter().any(|c| &c.capability == *wanted))
+            .cloned()
+            .collect();
+        missing.sort_by_key(|capability| capability.command_name());
+        capabilities.sort_by(|a, b| a.command.cmp(&b.command));
+        capabilities.extend(missing.into_iter().map(|capability| LocalDeviceCapability {
+            command: capability.command_name().to_string(),
+            capability,
+            key_freshness: CommandKeyFreshness::Missing,
+        }));
+        Ok(capabilities)
+    }
+
+    pub fn diff_device_config(&mut self, config: &DeviceConfig) -> Result<DeviceConfigDiff> {
+        let capabilities = self.get_local_device_capabilities()?;
+        let device = self
+            .state
+            .server_local_device_info()
+            .ok_or(Error::NoCurrentDeviceId)?;
+        Ok(config.diff(device, &capabilities))
+    }
+
     /// Retrieve the current device id from state
     pub fn get_current_device_id(&mut self) -> Result<String> {
         match self.state.current_device_id() {
@@ -360,6 +510,16 @@ impl FirefoxAccount {
     }
 }
 
+impl DeviceCapability {
+    /// The name of the FxA device command for this capability.
+    pub(crate) fn command_name(&self) -> &'static str {
+        match self {
+            DeviceCapability::SendTab => commands::send_tab::COMMAND_NAME,
+            DeviceCapability::CloseTabs => commands::close_tabs::COMMAND_NAME,
+        }
+    }
+}
+
 impl TryFrom<String> for DeviceCapability {
     type Error = Error;
 
@@ -384,7 +544,7 @@ impl From<UpdateDeviceResponse> for LocalDevice {
                 .filter_map(|command| match command.try_into() {
                     Ok(capability) => Some(capability),
                     Err(e) => {
-                        log::warn!("While parsing UpdateDeviceResponse: {e}");
+                        warn!("While parsing UpdateDeviceResponse: {e}");
                         None
                     }
                 })
@@ -801,6 +961,247 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn test_ensure_device_registration() {
+        let mut fxa = setup();
+        fxa.ensure_device_registration().unwrap_err();
+
+        // Register the device.
+        let mut client = MockFxAClient::new();
+        client
+            .expect_update_device_record()
+            .with(always(), eq("refreshtok"), always())
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(UpdateDeviceResponse {
+                    id: "device1".to_string(),
+                    display_name: "My Phone".to_string(),
+                    device_type: DeviceType::Mobile,
+                    push_subscription: None,
+                    available_commands: HashMap::from([(
+                        commands::send_tab::COMMAND_NAME.to_owned(),
+                        "fake-command-data".to_owned(),
+                    )]),
+                    push_endpoint_expired: false,
+                })
+            });
+        fxa.set_client(Arc::new(client));
+        fxa.initialize_device("My Phone", DeviceType::Mobile, &[DeviceCapability::SendTab])
+            .unwrap();
+
+        let current_device = |id: &str, is_current_device: bool| Device {
+            common: DeviceResponseCommon {
+                id: id.into(),
+                display_name: "My Phone".to_string(),
+                device_type: DeviceType::Mobile,
+                push_subscription: None,
+                available_commands: HashMap::from([(
+                    commands::send_tab::COMMAND_NAME.to_owned(),
+                    "fake-command-data".to_owned(),
+                )]),
+                push_endpoint_expired: false,
+            },
+            is_current_device,
+            location: DeviceLocation {
+                city: None,
+                country: None,
+                state: None,
+                state_code: None,
+            },
+            last_access_time: None,
+        };
+
+        // While the record exists with our commands, there's nothing to do.
+        // The MockFxAClient will panic if we try to update the device.
+        let mut client = MockFxAClient::new();
+        client
+            .expect_get_devices()
+            .with(always(), always())
+            .times(1)
+            .returning(move |_, _| Ok(vec![current_device("device1", true)]));
+        fxa.set_client(Arc::new(client));
+        assert!(fxa.ensure_device_registration().unwrap().is_none());
+
+        // Once the server has removed it, we register it again, as it was.
+        let mut client = MockFxAClient::new();
+        client
+            .expect_get_devices()
+            .with(always(), always())
+            .times(1)
+            .returning(move |_, _| Ok(vec![current_device("other", false)]));
+        client
+            .expect_update_device_record()
+            .withf(|_, _, update| {
+                let update = serde_json::to_value(update).unwrap();
+                update["name"] == "My Phone"
+                    && update["type"] == "mobile"
+                    && update["availableCommands"]
+                        .get(commands::send_tab::COMMAND_NAME)
+                        .is_some()
+            })
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(UpdateDeviceResponse {
+                    id: "device2".to_string(),
+                    display_name: "My Phone".to_string(),
+                    device_type: DeviceType::Mobile,
+                    push_subscription: None,
+                    available_commands: HashMap::from([(
+                        commands::send_tab::COMMAND_NAME.to_owned(),
+                        "fake-command-data".to_owned(),
+                    )]),
+                    push_endpoint_expired: false,
+                })
+            });
+        fxa.set_client(Arc::new(client));
+        match fxa.ensure_device_registration().unwrap() {
+            Some(AccountEvent::DeviceReregistered {
+                previous_device_id,
+                device,
+            }) => {
+                assert_eq!(previous_device_id, "device1");
+                assert_eq!(device.id, "device2");
+                assert_eq!(device.capabilities, vec![DeviceCapability::SendTab]);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+        assert_eq!(fxa.get_current_device_id().unwrap(), "device2");
+        assert!(fxa.devices_cache.is_none());
+    }
+
+    #[test]
+    fn test_get_local_device_capabilities() {
+        let mut fxa = setup();
+        let mut client = MockFxAClient::new();
+        client
+            .expect_update_device_record()
+            .with(always(), eq("refreshtok"), always())
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(UpdateDeviceResponse {
+                    id: "device1".to_string(),
+                    display_name: "My Phone".to_string(),
+                    device_type: DeviceType::Mobile,
+                    push_subscription: None,
+                    available_commands: HashMap::from([(
+                        commands::send_tab::COMMAND_NAME.to_owned(),
+                        "fake-command-data".to_owned(),
+                    )]),
+                    push_endpoint_expired: false,
+                })
+            });
+        fxa.set_client(Arc::new(client));
+        fxa.initialize_device(
+            "My Phone",
+            DeviceType::Mobile,
+            &[DeviceCapability::SendTab, DeviceCapability::CloseTabs],
+        )
+        .unwrap();
+
+        let check = |fxa: &mut FirefoxAccount, send_tab_data: String| {
+            let mut client = MockFxAClient::new();
+            client
+                .expect_get_devices()
+                .with(always(), always())
+                .times(1)
+                .returning(move |_, _| {
+                    Ok(vec![Device {
+                        common: DeviceResponseCommon {
+                            id: "device1".into(),
+                            display_name: "My Phone".to_string(),
+                            device_type: DeviceType::Mobile,
+                            push_subscription: None,
+                            available_commands: HashMap::from([(
+                                commands::send_tab::COMMAND_NAME.to_owned(),
+                                send_tab_data.clone(),
+                            )]),
+                            push_endpoint_expired: false,
+                        },
+                        is_current_device: true,
+                        location: DeviceLocation {
+                            city: None,
+                            country: None,
+                            state: None,
+                            state_code: None,
+                        },
+                        last_access_time: None,
+                    }])
+                });
+            fxa.set_client(Arc::new(client));
+            fxa.devices_cache = None;
+            fxa.get_local_device_capabilities().unwrap()
+        };
+
+        // The keys we registered for Send Tab are current, and Close Tabs is missing.
+        let data = fxa
+            .generate_command_data(DeviceCapability::SendTab)
+            .unwrap();
+        let capabilities = check(&mut fxa, data);
+        assert_eq!(
+            capabilities,
+            vec![
+                LocalDeviceCapability {
+                    capability: DeviceCapability::SendTab,
+                    command: commands::send_tab::COMMAND_NAME.to_string(),
+                    key_freshness: CommandKeyFreshness::Current,
+                },
+                LocalDeviceCapability {
+                    capability: DeviceCapability::CloseTabs,
+                    command: commands::close_tabs::COMMAND_NAME.to_string(),
+                    key_freshness: CommandKeyFreshness::Missing,
+                },
+            ]
+        );
+        let device = fxa.state.server_local_device_info().unwrap().clone();
+        let config = DeviceConfig {
+            name: "My Phone".to_string(),
+            device_type: DeviceType::Mobile,
+            capabilities: vec![DeviceCapability::SendTab],
+        };
+        assert_eq!(
+            config.diff(&device, &capabilities),
+            DeviceConfigDiff::default()
+        );
+        let config = DeviceConfig {
+            name: "My Tablet".to_string(),
+            device_type: DeviceType::Mobile,
+            capabilities: vec![DeviceCapability::CloseTabs],
+        };
+        assert_eq!(
+            config.diff(&device, &capabilities),
+            DeviceConfigDiff {
+                name_changed: true,
+                added_capabilities: vec![DeviceCapability::CloseTabs],
+                removed_capabilities: vec![DeviceCapability::SendTab],
+                needs_reregistration: true,
+                ..Default::default()
+            }
+        );
+
+        // Keys registered by someone else, or with a different sync key, are stale.
+        let other_keys: PublicCommandKeys = PrivateCommandKeys::from_random().unwrap().into();
+        let data = other_keys
+            .as_command_data(fxa.get_scoped_key(scopes::OLD_SYNC).unwrap())
+            .unwrap();
+        let capabilities = check(&mut fxa, data);
+        assert_eq!(capabilities[0].key_freshness, CommandKeyFreshness::Stale);
+        let capabilities = check(&mut fxa, "fake-command-data".to_string());
+        assert_eq!(capabilities[0].key_freshness, CommandKeyFreshness::Stale);
+        let config = DeviceConfig {
+            name: "My Phone".to_string(),
+            device_type: DeviceType::Mobile,
+            capabilities: vec![DeviceCapability::SendTab],
+        };
+        assert_eq!(
+            config.diff(&device, &capabilities),
+            DeviceConfigDiff {
+                stale_capabilities: vec![DeviceCapability::SendTab],
+                needs_reregistration: true,
+                ..Default::default()
+            }
+        );
+    }
+
     #[test]
     fn test_get_devices() {
         let mut fxa = setup();
@@ -881,4 +1282,40 @@ mod tests {
         assert!(res.is_err());
         assert!(fxa.devices_cache.is_none());
     }
+
+    #[test]
+    fn test_poll_device_commands_if_stale() {
+        let mut fxa = setup();
+        let mut client = MockFxAClient::new();
+        client
+            .expect_get_pending_commands()
+            .with(always(), eq("refreshtok"), eq(1), eq(None))
+            .times(2)
+            .returning(|_, _, _, _| {
+                Ok(PendingCommandsResponse {
+                    index: 0,
+                    last: Some(true),
+                    messages: Vec::new(),
+                })
+            });
+        fxa.set_client(Arc::new(client));
+
+        // We've never polled, so this hits the server.
+        assert!(fxa
+            .poll_device_commands_if_stale(60_000)
+            .unwrap()
+            .is_empty());
+        assert!(fxa.state.last_commands_poll().is_some());
+        // We just polled, so this doesn't.
+        assert!(fxa
+            .poll_device_commands_if_stale(60_000)
+            .unwrap()
+            .is_empty());
+        // But it does once the last poll is old enough.
+        fxa.state.set_last_commands_poll(util::now() - 120_000);
+        assert!(fxa
+            .poll_device_commands_if_stale(60_000)
+            .unwrap()
+            .is_empty());
+    }
 }
@@ -0,0 +1,107 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::{flow_metrics::state_name, util, FirefoxAccount};
+use crate::diagnostics::{recent_errors, AccessTokenDiagnostics, FxaDiagnostics};
+
+impl FirefoxAccount {
+    /// Collect a sanitized report on the health of the account. Nothing here may include
+    /// tokens, keys or profile data.
+    pub fn collect_diagnostics(&self) -> FxaDiagnostics {
+        let now = util::now_secs() as i64;
+        let mut access_tokens: Vec<_> = self
+            .state
+            .cached_access_tokens()
+            .map(|(scope, token_info)| AccessTokenDiagnostics {
+                scope: scope.to_string(),
+                expires_in: token_info.expires_at as i64 - now,
+                has_scoped_key: token_info.key.is_some(),
+            })
+            .collect();
+        access_tokens.sort_by(|a, b| a.scope.cmp(&b.scope));
+        let mut scoped_key_scopes: Vec<_> =
+            self.state.scoped_key_scopes().map(str::to_string).collect();
+        scoped_key_scopes.sort();
+        FxaDiagnostics {
+            state: state_name(&self.auth_state).to_string(),
+            auth_state: self.get_auth_state(),
+            has_refresh_token: self.state.refresh_token().is_some(),
+            has_session_token: self.state.session_token().is_some(),
+            scoped_key_scopes,
+            access_tokens,
+            device_registered: self.state.current_device_id().is_some(),
+            device_capabilities_registered: self.state.server_local_device_info().is_some(),
+            recent_errors: recent_errors(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::{config::Config, oauth::AccessTokenInfo, oauth::RefreshToken};
+    use crate::{diagnostics::record_error, Error, FxaError, FxaRustAuthState};
+    use error_support::convert_log_report_error;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_collect_diagnostics() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        let diagnostics = fxa.collect_diagnostics();
+        assert_eq!(diagnostics.state, "Uninitialized");
+        assert_eq!(diagnostics.auth_state, FxaRustAuthState::Disconnected);
+        assert!(!diagnostics.has_refresh_token);
+        assert!(diagnostics.access_tokens.is_empty());
+        assert!(!diagnostics.device_registered);
+
+        fxa.state.force_refresh_token(RefreshToken {
+            token: "refreshtok".to_string(),
+            scopes: HashSet::from(["profile".to_string()]),
+        });
+        fxa.state.force_current_device_id("device-id");
+        fxa.add_cached_token(
+            "profile",
+            AccessTokenInfo {
+                scope: "profile".to_string(),
+                token: "profiletok".to_string(),
+                key: None,
+                expires_at: util::now_secs() + 3600,
+            },
+        );
+        // Errors are recorded as they're converted for the application.
+        let _: FxaError =
+            convert_log_report_error(Error::NoCachedToken("diagnostics-test".to_string()));
+        record_error(&Error::RemoteError {
+            code: 400,
+            errno: 999,
+            error: "Bad Request".to_string(),
+            message: "diagnostics-test".to_string(),
+            info: "".to_string(),
+        });
+
+        let diagnostics = fxa.collect_diagnostics();
+        assert_eq!(diagnostics.auth_state, FxaRustAuthState::Connected);
+        assert!(diagnostics.has_refresh_token);
+        assert!(diagnostics.device_registered);
+        assert_eq!(diagnostics.access_tokens.len(), 1);
+        let token = &diagnostics.access_tokens[0];
+        assert_eq!(token.scope, "profile");
+        assert!(token.expires_in > 3500 && token.expires_in <= 3600);
+        assert!(!token.has_scoped_key);
+        // Other tests may record errors concurrently, so just check ours are there.
+        assert!(diagnostics
+            .recent_errors
+            .iter()
+            .any(|e| e.kind == "NoCachedToken" && e.status.is_none()));
+        assert!(diagnostics
+            .recent_errors
+            .iter()
+            .any(|e| e.kind == "RemoteError" && e.status == Some(400) && e.errno == Some(999)));
+        let debug = format!("{diagnostics:?}");
+        assert!(!debug.contains("profiletok") && !debug.contains("refreshtok"));
+        // Error messages aren't kept, since they might contain anything.
+        assert!(!debug.contains("diagnostics-test"));
+    }
+}
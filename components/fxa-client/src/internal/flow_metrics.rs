@@ -0,0 +1,368 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::FirefoxAccount;
+use crate::{FxaEvent, FxaState, OAuthFlowMetrics, OAuthFlowOutcome, OAuthFlowStep};
+use std::collections::VecDeque;
+
+/// The number of finished flows kept until the application takes them.
+const MAX_FINISHED_FLOWS: usize = 20;
+
+impl FirefoxAccount {
+    pub fn take_flow_metrics(&mut self) -> Vec<OAuthFlowMetrics> {
+        self.flow_metrics.take_finished()
+    }
+}
+
+// Not the `Display` impls, which are mangled to get past Sentry's filters.
+pub(crate) fn state_name(state: &FxaState) -> &'static str {
+    match state {
+        FxaState::Uninitialized => "Uninitialized",
+        FxaState::Disconnected => "Disconnected",
+        FxaState::Authenticating { .. } => "Authenticating",
+        FxaState::TwoFactorRequired => "TwoFactorRequired",
+        FxaState::Connected => "Connected",
+        FxaState::AuthIssues => "AuthIssues",
+    }
+}
+
+fn event_name(event: &FxaEvent) -> &'static str {
+    match event {
+        FxaEvent::Initialize { .. } => "Initialize",
+        FxaEvent::BeginOAuthFlow { .. } => "BeginOAuthFlow",
+        FxaEvent::BeginPairingFlow { .. } => "BeginPairingFlow",
+        FxaEvent::CompleteOAuthFlow { .. } => "CompleteOAuthFlow",
+        FxaEvent::SubmitTotpCode { .. } => "SubmitTotpCode",
+        FxaEvent::CancelOAuthFlow => "CancelOAuthFlow",
+        FxaEvent::CheckAuthorizationStatus => "CheckAuthorizationStatus",
+        FxaEvent::Disconnect => "Disconnect",
+        FxaEvent::CallGetProfile => "CallGetProfile",
+        FxaEvent::FlowTimedOut => "FlowTimedOut",
+    }
+}
+
+struct FlowInProgress {
+    entrypoint: String,
+    is_pairing: bool,
+    started_at: u64,
+    last_step_at: u64,
+    steps: Vec<OAuthFlowStep>,
+}
+
+impl FlowInProgress {
+    fn add_step(
+        &mut self,
+        event: &FxaEvent,
+        from: &FxaState,
+        to: &FxaState,
+        failed: bool,
+        now: u64,
+    ) {
+        self.steps.push(OAuthFlowStep {
+            event: event_name(event).to_string(),
+            from_state: state_name(from).to_string(),
+            to_state: state_name(to).to_string(),
+            duration_ms: now.saturating_sub(self.last_step_at) as i64,
+            failed,
+        });
+        self.last_step_at = now;
+    }
+
+    fn finish(self, outcome: OAuthFlowOutcome, now: u64) -> OAuthFlowMetrics {
+        OAuthFlowMetrics {
+            entrypoint: self.entrypoint,
+            is_pairing: self.is_pairing,
+            outcome,
+            duration_ms: now.saturating_sub(self.started_at) as i64,
+            steps: self.steps,
+        }
+    }
+}
+
+/// Times OAuth flows as events are processed by the state machine.
+#[derive(Default)]
+pub(crate) struct FlowMetricsRecorder {
+    current: Option<FlowInProgress>,
+    finished: VecDeque<OAuthFlowMetrics>,
+}
+
+impl FlowMetricsRecorder {
+    /// Record that the state machine processed `event`, moving from `from` to `to`.
+    pub(crate) fn record(
+        &mut self,
+        event: &FxaEvent,
+        from: &FxaState,
+        to: &FxaState,
+        failed: bool,
+        now: u64,
+    ) {
+        let begun = match event {
+            FxaEvent::BeginOAuthFlow { entrypoint, .. } => Some((entrypoint, false)),
+            FxaEvent::BeginPairingFlow { entrypoint, .. } => Some((entrypoint, true)),
+            _ => None,
+        };
+        if let (Some((entrypoint, is_pairing)), false) = (begun, failed) {
+            if let Some(mut flow) = self.current.take() {
+                flow.add_step(event, from, to, failed, now);
+                self.push_finished(flow.finish(OAuthFlowOutcome::Abandoned, now));
+            }
+            let mut flow = FlowInProgress {
+                entrypoint: entrypoint.clone(),
+                is_pairing,
+                started_at: now,
+                last_step_at: now,
+                steps: Vec::new(),
+            };
+            flow.add_step(event, from, to, failed, now);
+            self.current = Some(flow);
+            return;
+        }
+        let Some(flow) = &mut self.current else {
+            return;
+        };
+        flow.add_step(event, from, to, failed, now);
+        let outcome = match to {
+            FxaState::Authenticating { .. } | FxaState::TwoFactorRequired => return,
+            FxaState::Connected => OAuthFlowOutcome::Completed,
+            _ if *event == FxaEvent::FlowTimedOut => OAuthFlowOutcome::TimedOut,
+            _ => OAuthFlowOutcome::Abandoned,
+        };
+        if let Some(flow) = self.current.take() {
+            self.push_finished(flow.finish(outcome, now));
+        }
+    }
+
+    fn push_finished(&mut self, metrics: OAuthFlowMetrics) {
+        if self.finished.len() == MAX_FINISHED_FLOWS {
+            self.finished.pop_front();
+        }
+        self.finished.push_back(metrics);
+    }
+
+    pub(crate) fn take_finished(&mut self) -> Vec<OAuthFlowMetrics> {
+        self.finished.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authenticating() -> FxaState {
+        FxaState::Authenticating {
+            oauth_url: "https://example.com/oauth".to_string(),
+        }
+    }
+
+    fn begin(entrypoint: &str) -> FxaEvent {
+        FxaEvent::BeginOAuthFlow {
+            scopes: vec!["profile".to_string()],
+            entrypoint: entrypoint.to_string(),
+        }
+    }
+
+    fn complete() -> FxaEvent {
+        FxaEvent::CompleteOAuthFlow {
+            code: "code".to_string(),
+            state: "state".to_string(),
+        }
+    }
+
+    fn step(event: &str, from: &str, to: &str, duration_ms: i64, failed: bool) -> OAuthFlowStep {
+        OAuthFlowStep {
+            event: event.to_string(),
+            from_state: from.to_string(),
+            to_state: to.to_string(),
+            duration_ms,
+            failed,
+        }
+    }
+
+    #[test]
+    fn test_completed_flow() {
+        let mut recorder = FlowMetricsRecorder::default();
+        // Events outside of a flow aren't recorded.
+        recorder.record(
+            &FxaEvent::CallGetProfile,
+            &FxaState::Disconnected,
+            &FxaState::Disconnected,
+            false,
+            500,
+        );
+        // Neither does a flow which fails to begin.
+        recorder.record(
+            &begin("toolbar"),
+            &FxaState::Disconnected,
+            &FxaState::Disconnected,
+            true,
+            800,
+        );
+        recorder.record(
+            &begin("menu"),
+            &FxaState::Disconnected,
+            &authenticating(),
+            false,
+            1000,
+        );
+        assert!(recorder.take_finished().is_empty());
+        recorder.record(
+            &complete(),
+            &authenticating(),
+            &authenticating(),
+            true,
+            4000,
+        );
+        recorder.record(
+            &complete(),
+            &authenticating(),
+            &FxaState::Connected,
+            false,
+            4500,
+        );
+        assert_eq!(
+            recorder.take_finished(),
+            vec![OAuthFlowMetrics {
+                entrypoint: "menu".to_string(),
+                is_pairing: false,
+                outcome: OAuthFlowOutcome::Completed,
+                duration_ms: 3500,
+                steps: vec![
+                    step("BeginOAuthFlow", "Disconnected", "Authenticating", 0, false),
+                    step(
+                        "CompleteOAuthFlow",
+                        "Authenticating",
+                        "Authenticating",
+                        3000,
+                        true
+                    ),
+                    step(
+                        "CompleteOAuthFlow",
+                        "Authenticating",
+                        "Connected",
+                        500,
+                        false
+                    ),
+                ],
+            }]
+        );
+        assert!(recorder.take_finished().is_empty());
+    }
+
+    #[test]
+    fn test_abandoned_flows() {
+        let mut recorder = FlowMetricsRecorder::default();
+        recorder.record(
+            &begin("menu"),
+            &FxaState::AuthIssues,
+            &authenticating(),
+            false,
+            1000,
+        );
+        // Beginning a new flow abandons the first.
+        recorder.record(
+            &FxaEvent::BeginPairingFlow {
+                pairing_url: "https://example.com/pair".to_string(),
+                scopes: vec![],
+                entrypoint: "pairing".to_string(),
+            },
+            &authenticating(),
+            &authenticating(),
+            false,
+            2000,
+        );
+        recorder.record(
+            &FxaEvent::CancelOAuthFlow,
+            &authenticating(),
+            &FxaState::AuthIssues,
+            false,
+            5000,
+        );
+        let finished = recorder.take_finished();
+        assert_eq!(finished.len(), 2);
+        assert_eq!(finished[0].entrypoint, "menu");
+        assert_eq!(finished[0].outcome, OAuthFlowOutcome::Abandoned);
+        assert_eq!(finished[0].duration_ms, 1000);
+        assert_eq!(
+            finished[0].steps[1],
+            step(
+                "BeginPairingFlow",
+                "Authenticating",
+                "Authenticating",
+                1000,
+                false
+            )
+        );
+        assert_eq!(finished[1].entrypoint, "pairing");
+        assert!(finished[1].is_pairing);
+        assert_eq!(finished[1].outcome, OAuthFlowOutcome::Abandoned);
+        assert_eq!(finished[1].duration_ms, 3000);
+        assert_eq!(
+            finished[1].steps.last().unwrap(),
+            &step(
+                "CancelOAuthFlow",
+                "Authenticating",
+                "AuthIssues",
+                3000,
+                false
+            )
+        );
+    }
+
+    #[test]
+    fn test_timed_out_flow() {
+        let mut recorder = FlowMetricsRecorder::default();
+        recorder.record(
+            &begin("menu"),
+            &FxaState::Disconnected,
+            &authenticating(),
+            false,
+            1000,
+        );
+        recorder.record(
+            &FxaEvent::FlowTimedOut,
+            &authenticating(),
+            &FxaState::Disconnected,
+            false,
+            3601000,
+        );
+        let finished = recorder.take_finished();
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].outcome, OAuthFlowOutcome::TimedOut);
+        assert_eq!(finished[0].duration_ms, 3600000);
+        assert_eq!(
+            finished[0].steps.last().unwrap(),
+            &step(
+                "FlowTimedOut",
+                "Authenticating",
+                "Disconnected",
+                3600000,
+                false
+            )
+        );
+    }
+
+    #[test]
+    fn test_finished_flows_are_bounded() {
+        let mut recorder = FlowMetricsRecorder::default();
+        for i in 0..(MAX_FINISHED_FLOWS as u64 + 5) {
+            recorder.record(
+                &begin(&i.to_string()),
+                &FxaState::Disconnected,
+                &authenticating(),
+                false,
+                i,
+            );
+            recorder.record(
+                &FxaEvent::Disconnect,
+                &authenticating(),
+                &FxaState::Disconnected,
+                false,
+                i,
+            );
+        }
+        let finished = recorder.take_finished();
+        assert_eq!(finished.len(), MAX_FINISHED_FLOWS);
+        assert_eq!(finished[0].entrypoint, "5");
+    }
+}
@@ -26,13 +26,16 @@ use std::{
 };
 use sync15::DeviceType;
 use url::Url;
-use viaduct::{header_names, status_codes, Method, Request, Response};
+use viaduct::{header_names, status_codes, Header, Method, Request, Response};
 
 const HAWK_HKDF_SALT: [u8; 32] = [0b0; 32];
 const HAWK_KEY_LENGTH: usize = 32;
 const RETRY_AFTER_DEFAULT_SECONDS: u64 = 10;
 // Devices older than this many days will not appear in the devices list
 const DEVICES_FILTER_DAYS: u64 = 21;
+// The errno the auth server responds with when the email a password was stretched with isn't
+// the one the account was created with. The response includes the original email.
+const ERRNO_INCORRECT_EMAIL_CASE: u64 = 120;
 
 /// Trait defining the low-level API for talking to the FxA server.
 ///
@@ -90,6 +93,12 @@ pub(crate) trait FxAClient {
         session_token: &str,
         scopes: &[&'a str],
     ) -> Result<OAuthTokenResponse>;
+    fn exchange_token_for_service(
+        &self,
+        config: &Config,
+        refresh_token: &str,
+        service: &str,
+    ) -> Result<OAuthTokenResponse>;
     fn create_authorization_code_using_session_token(
         &self,
         config: &Config,
@@ -138,6 +147,11 @@ pub(crate) trait FxAClient {
         config: &Config,
         session_token: &str,
     ) -> Result<Vec<GetAttachedClientResponse>>;
+    fn get_session_status(
+        &self,
+        config: &Config,
+        session_token: &str,
+    ) -> Result<SessionStatusResponse>;
     fn get_scoped_key_data(
         &self,
         config: &Config,
@@ -145,8 +159,41 @@ pub(crate) trait FxAClient {
         client_id: &str,
         scope: &str,
     ) -> Result<HashMap<String, ScopedKeyDataResponse>>;
+    fn reauth_session_token(
+        &self,
+        config: &Config,
+        session_token: &str,
+        email: &str,
+        auth_pw: &str,
+    ) -> Result<ReauthResponse>;
+    fn verify_session_totp(
+        &self,
+        config: &Config,
+        session_token: &str,
+        code: &str,
+    ) -> Result<VerifyTotpResponse>;
+    fn get_totp_status(&self, config: &Config, session_token: &str) -> Result<TotpStatusResponse>;
+    fn get_account_keys(
+        &self,
+        config: &Config,
+        key_fetch_token: &str,
+    ) -> Result<AccountKeysResponse>;
+    fn create_recovery_key(
+        &self,
+        config: &Config,
+        session_token: &str,
+        recovery_key_id: &str,
+        recovery_data: &str,
+    ) -> Result<()>;
+    fn check_recovery_key_exists(
+        &self,
+        config: &Config,
+        session_token: &str,
+    ) -> Result<RecoveryKeyExistsResponse>;
+    fn destroy_recovery_key(&self, config: &Config, session_token: &str) -> Result<()>;
     fn get_fxa_client_configuration(&self, config: &Config) -> Result<ClientConfigurationResponse>;
     fn get_openid_configuration(&self, config: &Config) -> Result<OpenIdConfigurationResponse>;
+    fn probe_connectivity(&self, config: &Config) -> Result<Response>;
     fn simulate_network_error(&self) {}
 }
 
@@ -161,16 +208,26 @@ enum HttpClientState {
 pub struct Client {
     state: Mutex<HashMap<String, HttpClientState>>,
     simulate_network_error: AtomicBool,
+    // Added to every request, from `Config::extra_headers()`.
+    extra_headers: Vec<Header>,
 }
 impl FxAClient for Client {
     fn get_fxa_client_configuration(&self, config: &Config) -> Result<ClientConfigurationResponse> {
         // Why go through two-levels of indirection? It looks kinda dumb.
         // Well, `config:Config` also needs to fetch the config, but does not have access
         // to an instance of `http_client`, so it calls the helper function directly.
-        fxa_client_configuration(config.client_config_url()?)
+        fxa_client_configuration(config)
     }
     fn get_openid_configuration(&self, config: &Config) -> Result<OpenIdConfigurationResponse> {
-        openid_configuration(config.openid_config_url()?)
+        openid_configuration(config)
+    }
+
+    /// Fetch the client configuration document, which is small and unauthenticated, and
+    /// return the response whatever its status, for the caller to classify.
+    fn probe_connectivity(&self, config: &Config) -> Result<Response> {
+        Ok(Request::get(config.client_config_url()?)
+            .headers(config.extra_headers())
+            .send()?)
     }
 
     fn get_profile(
@@ -276,6 +333,24 @@ impl FxAClient for Client {
         self.make_request(request)?.json().map_err(Into::into)
     }
 
+    // Exchange our refresh token for an access token whose audience is another service, such as
+    // a VPN or Relay backend, following RFC 8693.
+    fn exchange_token_for_service(
+        &self,
+        config: &Config,
+        refresh_token: &str,
+        service: &str,
+    ) -> Result<OAuthTokenResponse> {
+        let req = OAauthTokenRequest::TokenExchange {
+            client_id: config.client_id.clone(),
+            subject_token: refresh_token.to_string(),
+            subject_token_type: TOKEN_TYPE_REFRESH_TOKEN,
+            requested_token_type: TOKEN_TYPE_JWT,
+            audience: service.to_string(),
+        };
+        self.make_oauth_token_request(config, None, serde_json::to_value(req).unwrap())
+    }
+
     fn create_authorization_code_using_session_token(
         &self,
         config: &Config,
@@ -429,6 +504,17 @@ impl FxAClient for Client {
         Ok(self.make_request(request)?.json()?)
     }
 
+    fn get_session_status(
+        &self,
+        config: &Config,
+        session_token: &str,
+    ) -> Result<SessionStatusResponse> {
+        let url = config.auth_url_path("v1/session/status")?;
+        let key = derive_auth_key_from_session_token(session_token)?;
+        let request = HawkRequestBuilder::new(Method::Get, url, &key).build()?;
+        Ok(self.make_request(request)?.json()?)
+    }
+
     fn get_scoped_key_data(
         &self,
         config: &Config,
@@ -448,14 +534,112 @@ impl FxAClient for Client {
         self.make_request(request)?.json().map_err(|e| e.into())
     }
 
+    fn reauth_session_token(
+        &self,
+        config: &Config,
+        session_token: &str,
+        email: &str,
+        auth_pw: &str,
+    ) -> Result<ReauthResponse> {
+        let body = json!({
+            "email": email,
+            "authPW": auth_pw,
+        });
+        let mut url = config.auth_url_path("v1/session/reauth")?;
+        url.query_pairs_mut().append_pair("keys", "true");
+        let key = derive_auth_key_from_session_token(session_token)?;
+        let request = HawkRequestBuilder::new(Method::Post, url, &key)
+            .body(body)
+            .build()?;
+        Ok(self.make_request(request)?.json()?)
+    }
+
+    fn verify_session_totp(
+        &self,
+        config: &Config,
+        session_token: &str,
+        code: &str,
+    ) -> Result<VerifyTotpResponse> {
+        let body = json!({
+            "code": code,
+        });
+        let url = config.auth_url_path("v1/session/verify/totp")?;
+        let key = derive_auth_key_from_session_token(session_token)?;
+        let request = HawkRequestBuilder::new(Method::Post, url, &key)
+            .body(body)
+            .build()?;
+        Ok(self.make_request(request)?.json()?)
+    }
+
+    fn get_totp_status(&self, config: &Config, session_token: &str) -> Result<TotpStatusResponse> {
+        let url = config.auth_url_path("v1/totp/exists")?;
+        let key = derive_auth_key_from_session_token(session_token)?;
+        let request = HawkRequestBuilder::new(Method::Get, url, &key).build()?;
+        Ok(self.make_request(request)?.json()?)
+    }
+
+    fn get_account_keys(
+        &self,
+        config: &Config,
+        key_fetch_token: &str,
+    ) -> Result<AccountKeysResponse> {
+        let url = config.auth_url_path("v1/account/keys")?;
+        let key = derive_hkdf_sha256_key(&hex::decode(key_fetch_token)?, &kw("keyFetchToken"), 3)?;
+        let request = HawkRequestBuilder::new(Method::Get, url, &key).build()?;
+        Ok(self.make_request(request)?.json()?)
+    }
+
+    fn create_recovery_key(
+        &self,
+        config: &Config,
+        session_token: &str,
+        recovery_key_id: &str,
+        recovery_data: &str,
+    ) -> Result<()> {
+        let body = json!({
+            "recoveryKeyId": recovery_key_id,
+            "recoveryData": recovery_data,
+            "enabled": true,
+        });
+        let url = config.auth_url_path("v1/recoveryKey")?;
+        let key = derive_auth_key_from_session_token(session_token)?;
+        let request = HawkRequestBuilder::new(Method::Post, url, &key)
+            .body(body)
+            .build()?;
+        self.make_request(request)?;
+        Ok(())
+    }
+
+    fn check_recovery_key_exists(
+        &self,
+        config: &Config,
+        session_token: &str,
+    ) -> Result<RecoveryKeyExistsResponse> {
+        let url = config.auth_url_path("v1/recoveryKey/exists")?;
+        let key = derive_auth_key_from_session_token(session_token)?;
+        let request = HawkRequestBuilder::new(Method::Post, url, &key)
+            .body(json!({}))
+            .build()?;
+        Ok(self.make_request(request)?.json()?)
+    }
+
+    fn destroy_recovery_key(&self, config: &Config, session_token: &str) -> Result<()> {
+        let url = config.auth_url_path("v1/recoveryKey")?;
+        let key = derive_auth_key_from_session_token(session_token)?;
+        let request = HawkRequestBuilder::new(Method::Delete, url, &key).build()?;
+        self.make_request(request)?;
+        Ok(())
+    }
+
     fn simulate_network_error(&self) {
         self.simulate_network_error.store(true, Ordering::Relaxed);
     }
 }
 
 macro_rules! fetch {
-    ($url:expr) => {
+    ($url:expr, $config:expr) => {
         viaduct::Request::get($url)
+            .headers($config.extra_headers())
             .send()?
             .require_success()?
             .json()?
@@ -463,19 +647,25 @@ macro_rules! fetch {
 }
 
 #[inline]
-pub(crate) fn fxa_client_configuration(url: Url) -> Result<ClientConfigurationResponse> {
-    Ok(fetch!(url))
+pub(crate) fn fxa_client_configuration(config: &Config) -> Result<ClientConfigurationResponse> {
+    Ok(fetch!(config.client_config_url()?, config))
 }
 #[inline]
-pub(crate) fn openid_configuration(url: Url) -> Result<OpenIdConfigurationResponse> {
-    Ok(fetch!(url))
+pub(crate) fn openid_configuration(config: &Config) -> Result<OpenIdConfigurationResponse> {
+    Ok(fetch!(config.openid_config_url()?, config))
 }
 
 impl Client {
+    #[cfg(test)]
     pub fn new() -> Self {
+        Self::with_extra_headers(Vec::new())
+    }
+
+    pub fn with_extra_headers(extra_headers: Vec<Header>) -> Self {
         Self {
             state: Mutex::new(HashMap::new()),
             simulate_network_error: AtomicBool::new(false),
+            extra_headers,
         }
     }
 
@@ -521,18 +711,23 @@ impl Client {
     fn default_handle_response_error(resp: Response) -> Result<Response> {
         let json: std::result::Result<serde_json::Value, _> = resp.json();
         match json {
-            Ok(json) => Err(Error::RemoteError {
-                code: json["code"].as_u64().unwrap_or(0),
-                errno: json["errno"].as_u64().unwrap_or(0),
-                error: json["error"].as_str().unwrap_or("").to_string(),
-                message: json["message"].as_str().unwrap_or("").to_string(),
-                info: json["info"].as_str().unwrap_or("").to_string(),
-            }),
+            Ok(json) => match (json["errno"].as_u64(), json["email"].as_str()) {
+                (Some(ERRNO_INCORRECT_EMAIL_CASE), Some(email)) => {
+                    Err(Error::IncorrectEmailCase(email.to_string()))
+                }
+                _ => Err(Error::RemoteError {
+                    code: json["code"].as_u64().unwrap_or(0),
+                    errno: json["errno"].as_u64().unwrap_or(0),
+                    error: json["error"].as_str().unwrap_or("").to_string(),
+                    message: json["message"].as_str().unwrap_or("").to_string(),
+                    info: json["info"].as_str().unwrap_or("").to_string(),
+                }),
+            },
             Err(_) => Err(resp.require_success().unwrap_err().into()),
         }
     }
 
-    fn make_request(&self, request: Request) -> Result<Response> {
+    fn make_request(&self, mut request: Request) -> Result<Response> {
         if self.simulate_network_error.swap(false, Ordering::Relaxed) {
             return Err(Error::RequestError(viaduct::Error::NetworkError(
                 "Simulated error".to_owned(),
@@ -552,6 +747,12 @@ impl Client {
             }
         }
         self.state.lock().insert(url, HttpClientState::Ok);
+        // The extra headers can't replace any which were set for this request.
+        for header in &self.extra_headers {
+            request
+                .headers
+                .insert_if_missing(header.name().clone(), header.value())?;
+        }
         let resp = request.send()?;
         if resp.is_success() || resp.status == status_codes::NOT_MODIFIED {
             Ok(resp)
@@ -568,18 +769,26 @@ fn bearer_token(token: &str) -> String {
     format!("Bearer {}", token)
 }
 
-fn kw(name: &str) -> Vec<u8> {
+pub(crate) fn kw(name: &str) -> Vec<u8> {
     format!("identity.mozilla.com/picl/v1/{}", name)
         .as_bytes()
         .to_vec()
 }
 
 pub fn derive_auth_key_from_session_token(session_token: &str) -> Result<Vec<u8>> {
-    let session_token_bytes = hex::decode(session_token)?;
-    let context_info = kw("sessionToken");
+    derive_hkdf_sha256_key(&hex::decode(session_token)?, &kw("sessionToken"), 2)
+}
+
+/// Derive `num_keys` 32-byte keys from a token, as the onepw protocol does. The first two are
+/// always the Hawk id and key for requests made with the token.
+pub(crate) fn derive_hkdf_sha256_key(
+    token: &[u8],
+    context_info: &[u8],
+    num_keys: usize,
+) -> Result<Vec<u8>> {
     let salt = hmac::SigningKey::new(&digest::SHA256, &HAWK_HKDF_SALT);
-    let mut out = vec![0u8; HAWK_KEY_LENGTH * 2];
-    hkdf::extract_and_expand(&salt, &session_token_bytes, &context_info, &mut out)?;
+    let mut out = vec![0u8; HAWK_KEY_LENGTH * num_keys];
+    hkdf::extract_and_expand(&salt, token, context_info, &mut out)?;
     Ok(out)
 }
 
@@ -868,6 +1077,9 @@ pub struct GetAttachedClientResponse {
 // definition on
 // https://github.com/mozilla/fxa/blob/8ae0e6876a50c7f386a9ec5b6df9ebb54ccdf1b5/packages/fxa-auth-server/lib/oauth/routes/token.js#L70-L152
 
+const TOKEN_TYPE_REFRESH_TOKEN: &str = "urn:ietf:params:oauth:token-type:refresh_token";
+const TOKEN_TYPE_JWT: &str = "urn:ietf:params:oauth:token-type:jwt";
+
 #[derive(Serialize)]
 #[serde(tag = "grant_type")]
 enum OAauthTokenRequest {
@@ -888,6 +1100,14 @@ enum OAauthTokenRequest {
         #[serde(skip_serializing_if = "Option::is_none")]
         ttl: Option<u64>,
     },
+    #[serde(rename = "urn:ietf:params:oauth:grant-type:token-exchange")]
+    TokenExchange {
+        client_id: String,
+        subject_token: String,
+        subject_token_type: &'static str,
+        requested_token_type: &'static str,
+        audience: String,
+    },
 }
 
 #[derive(Deserialize)]
@@ -945,6 +1165,41 @@ pub struct ScopedKeyDataResponse {
     pub key_rotation_timestamp: u64,
 }
 
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReauthResponse {
+    // Only present if the request asked for keys.
+    #[serde(rename = "keyFetchToken")]
+    pub key_fetch_token: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct VerifyTotpResponse {
+    pub success: bool,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TotpStatusResponse {
+    // Only true once the user has finished setting up two-step authentication.
+    pub exists: bool,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AccountKeysResponse {
+    pub bundle: String,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RecoveryKeyExistsResponse {
+    pub exists: bool,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SessionStatusResponse {
+    pub uid: String,
+    // "verified" or "unverified". We only care that the server accepted the token.
+    pub state: String,
+}
+
 #[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct DuplicateTokenResponse {
     pub uid: String,
@@ -1079,6 +1334,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_extra_headers() {
+        viaduct_reqwest::use_reqwest_backend();
+        let m = mock("GET", "/v1/extra_headers")
+            .match_header("x-gateway-auth", "secret")
+            .match_header("x-custom", "request")
+            .with_status(200)
+            .create();
+        let client = Client::with_extra_headers(vec![
+            Header::new("x-gateway-auth", "secret").unwrap(),
+            Header::new("x-custom", "extra").unwrap(),
+        ]);
+        let url = Url::parse(&format!("{}/v1/extra_headers", mockito::server_url())).unwrap();
+        // Headers set for the request itself take precedence.
+        let request = Request::get(url).header("x-custom", "request").unwrap();
+        client.make_request(request).unwrap();
+        m.expect(1).assert();
+    }
+
     #[test]
     fn test_backoff_then_ok() {
         viaduct_reqwest::use_reqwest_backend();
@@ -8,10 +8,13 @@ use self::{
     config::Config,
     oauth::{AuthCircuitBreaker, OAuthFlow, OAUTH_WEBCHANNEL_REDIRECT},
     state_manager::StateManager,
-    state_persistence::PersistedState,
+    state_persistence::{PendingDisconnect, PersistedState},
     telemetry::FxaTelemetry,
 };
-use crate::{DeviceConfig, Error, FxaConfig, FxaRustAuthState, FxaState, Result};
+use crate::{
+    AccountEvent, DeviceConfig, Error, FxaConfig, FxaRustAuthState, FxaState, FxaStateTimeouts,
+    Result, StateFreshness, StateFreshnessStatus,
+};
 use serde_derive::*;
 use std::{
     collections::{HashMap, HashSet},
@@ -24,18 +27,23 @@ pub mod auth;
 mod close_tabs;
 mod commands;
 pub mod config;
+mod connectivity;
 pub mod device;
+mod diagnostics;
+mod flow_metrics;
 mod http_client;
 mod oauth;
 mod profile;
 mod push;
+mod recovery_key;
 mod scoped_keys;
-mod scopes;
+pub(crate) mod scopes;
 mod send_tab;
+mod state_file;
 mod state_manager;
 mod state_persistence;
 mod telemetry;
-mod util;
+pub mod util;
 
 type FxAClient = dyn http_client::FxAClient + Sync + Send;
 
@@ -53,6 +61,9 @@ pub struct FirefoxAccount {
     state: StateManager,
     attached_clients_cache: Option<CachedResponse<Vec<http_client::GetAttachedClientResponse>>>,
     devices_cache: Option<CachedResponse<Vec<http_client::GetDeviceResponse>>>,
+    // Tokens for other services, by service. These aren't persisted, since they're cheap to
+    // get again.
+    service_token_cache: HashMap<String, oauth::ServiceToken>,
     auth_circuit_breaker: AuthCircuitBreaker,
     telemetry: FxaTelemetry,
     // TODO: Cleanup our usage of the word "state" and change this field name to `state`
@@ -60,19 +71,48 @@ pub struct FirefoxAccount {
     pub(crate) auth_state: FxaState,
     // Set via `FxaEvent::Initialize`
     pub(crate) device_config: Option<DeviceConfig>,
+    pub(crate) flow_metrics: flow_metrics::FlowMetricsRecorder,
+    // When `auth_state` was last changed by the state machine, so that flows which wait too
+    // long can be timed out.
+    pub(crate) auth_state_changed_at: u64,
+    pub(crate) state_timeouts: FxaStateTimeouts,
+    // Set when the state was read from or written to a state file with a lock.
+    state_file_base: Option<state_file::StateFileBase>,
+    // How old the state was when it was restored.
+    state_freshness: StateFreshness,
+    // Events we noticed outside of a push message, waiting for the application to take them.
+    pending_account_events: Vec<AccountEvent>,
+    // The OAuth flow waiting for the user to pass a two-step authentication challenge. Kept
+    // here rather than in `FxaState`, so that the authorization code isn't handed to the app.
+    pub(crate) two_factor_oauth_flow: Option<oauth::PendingOAuthFlow>,
 }
 
 impl FirefoxAccount {
     fn from_state(state: PersistedState) -> Self {
         Self {
-            client: Arc::new(http_client::Client::new()),
+            client: Arc::new(http_client::Client::with_extra_headers(
+                state.config.extra_headers(),
+            )),
             state: StateManager::new(state),
             attached_clients_cache: None,
             devices_cache: None,
+            service_token_cache: HashMap::new(),
             auth_circuit_breaker: Default::default(),
             telemetry: FxaTelemetry::new(),
             auth_state: FxaState::Uninitialized,
             device_config: None,
+            flow_metrics: Default::default(),
+            auth_state_changed_at: 0,
+            state_timeouts: Default::default(),
+            state_file_base: None,
+            state_freshness: StateFreshness {
+                status: StateFreshnessStatus::Fresh,
+                persisted_at: None,
+                persisted_by_version: None,
+                schema_version: state_persistence::CURRENT_SCHEMA_VERSION,
+            },
+            pending_account_events: Vec::new(),
+            two_factor_oauth_flow: None,
         }
     }
 
@@ -91,6 +131,12 @@ impl FirefoxAccount {
             last_seen_profile: None,
             access_token_cache: HashMap::new(),
             logged_out_from_auth_issues: false,
+            pending_disconnects: Vec::new(),
+            last_commands_poll: None,
+            persisted_at: None,
+            persisted_by_version: None,
+            seen_scoped_keys: HashMap::new(),
+            seen_scoped_keys_uid: None,
         })
     }
 
@@ -107,8 +153,19 @@ impl FirefoxAccount {
     /// Restore a `FirefoxAccount` instance from a serialized state
     /// created using `to_json`.
     pub fn from_json(data: &str) -> Result<Self> {
-        let state = state_persistence::state_from_json(data)?;
-        Ok(Self::from_state(state))
+        let (state, state_freshness) = state_persistence::state_and_freshness_from_json(data)?;
+        Ok(Self {
+            state_freshness,
+            ..Self::from_state(state)
+        })
+    }
+
+    /// Set the headers to send with every request, which aren't persisted with the state.
+    pub fn set_extra_headers(&mut self, extra_headers: HashMap<String, String>) {
+        self.state.set_extra_headers(extra_headers);
+        self.client = Arc::new(http_client::Client::with_extra_headers(
+            self.state.config().extra_headers(),
+        ));
     }
 
     /// Serialize a `FirefoxAccount` instance internal state
@@ -117,6 +174,23 @@ impl FirefoxAccount {
         self.state.serialize_persisted_state()
     }
 
+    /// Serialize the internal state like `to_json`, but with secrets replaced by
+    /// fingerprints, for attaching to bug reports.
+    pub fn export_sanitized_state(&self) -> Result<String> {
+        self.state.serialize_sanitized_state()
+    }
+
+    /// How old the state this account was restored from is.
+    pub fn get_state_freshness(&self) -> StateFreshness {
+        self.state_freshness.clone()
+    }
+
+    /// Take the events queued since this was last called, such as
+    /// [`AccountEvent::KeysMismatch`] from completing an OAuth flow.
+    pub fn take_pending_account_events(&mut self) -> Vec<AccountEvent> {
+        std::mem::take(&mut self.pending_account_events)
+    }
+
     /// Clear the attached clients and devices cache
     pub fn clear_devices_and_attached_clients_cache(&mut self) {
         self.attached_clients_cache = None;
@@ -217,27 +291,66 @@ impl FirefoxAccount {
         if let Some(refresh_token) = self.state.refresh_token() {
             // Delete the current device (which deletes the refresh token), or
             // the refresh token directly if we don't have a device.
-            let destroy_result = match current_device_result {
-                // If we get an error trying to fetch our device record we'll at least
-                // still try to delete the refresh token itself.
-                Ok(Some(device)) => self.client.destroy_device_record(
-                    self.state.config(),
-                    &refresh_token.token,
-                    &device.id,
-                ),
-                _ => self
-                    .client
-                    .destroy_refresh_token(self.state.config(), &refresh_token.token),
+            let pending = PendingDisconnect {
+                token: refresh_token.token.clone(),
+                device_id: match current_device_result {
+                    Ok(device) => device.map(|d| d.common.id),
+                    // If we get an error trying to fetch our device record we'll at least
+                    // remember the device we registered, if any, for when we retry.
+                    Err(_) => self.state.current_device_id().map(str::to_string),
+                },
             };
-            if let Err(e) = destroy_result {
-                log::warn!("Error while destroying the device: {}", e);
+            if let Err(e) = self.destroy_on_server(&pending) {
+                warn!("Error while destroying the device: {}", e);
+                if is_retryable(&e) {
+                    self.state.add_pending_disconnect(pending);
+                }
             }
         }
         self.state.disconnect();
+        self.service_token_cache.clear();
         self.clear_devices_and_attached_clients_cache();
         self.telemetry = FxaTelemetry::new();
     }
 
+    /// Try again to destroy the device records and refresh tokens which `disconnect()`
+    /// couldn't, for example because we were offline. Anything which fails with a
+    /// network or server error is kept for the next retry, and the first such error is
+    /// returned.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    pub fn retry_pending_disconnect(&mut self) -> Result<()> {
+        let mut first_error = None;
+        let pending_disconnects = self.state.pending_disconnects().to_vec();
+        for pending in pending_disconnects {
+            match self.destroy_on_server(&pending) {
+                Err(e) if is_retryable(&e) => {
+                    warn!("Error while retrying a disconnect: {}", e);
+                    first_error.get_or_insert(e);
+                }
+                // Any other error means the server has already forgotten the token.
+                _ => self.state.remove_pending_disconnect(&pending),
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    // Destroying the device record destroys its refresh token too.
+    fn destroy_on_server(&self, pending: &PendingDisconnect) -> Result<()> {
+        match &pending.device_id {
+            Some(device_id) => {
+                self.client
+                    .destroy_device_record(self.state.config(), &pending.token, device_id)
+            }
+            None => self
+                .client
+                .destroy_refresh_token(self.state.config(), &pending.token),
+        }
+    }
+
     /// Update the state based on authentication issues.
     ///
     /// **💾 This method alters the persisted account state.**
@@ -270,6 +383,12 @@ pub(crate) struct CachedResponse<T> {
     etag: String,
 }
 
+// Whether it's worth trying again to destroy a token on the server. Network and server errors
+// may go away, but a client error means the server doesn't know the token anymore.
+fn is_retryable(e: &Error) -> bool {
+    !matches!(e, Error::RemoteError { code, .. } if (400..500).contains(code))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -293,7 +412,13 @@ mod tests {
         drop(fxa1);
         let fxa2 = FirefoxAccount::from_json(&fxa1_json).unwrap();
         let fxa2_json = fxa2.to_json().unwrap();
-        assert_eq!(fxa1_json, fxa2_json);
+        // Everything but the time it was saved round-trips.
+        let without_save_time = |json: &str| {
+            let mut value: serde_json::Value = serde_json::from_str(json).unwrap();
+            value.as_object_mut().unwrap().remove("persisted_at");
+            value
+        };
+        assert_eq!(without_save_time(&fxa1_json), without_save_time(&fxa2_json));
     }
 
     #[test]
@@ -534,6 +659,101 @@ mod tests {
         assert!(fxa.state.refresh_token().is_some());
         fxa.disconnect();
         assert!(fxa.state.refresh_token().is_none());
+        assert_eq!(
+            fxa.state.pending_disconnects(),
+            &[PendingDisconnect {
+                token: "refreshtok".to_string(),
+                device_id: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_retry_pending_disconnect() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+
+        fxa.state.force_refresh_token(RefreshToken {
+            token: "refreshtok".to_string(),
+            scopes: HashSet::default(),
+        });
+        fxa.state.force_current_device_id("1234a");
+
+        let mut client = MockFxAClient::new();
+        client.expect_get_devices().times(1).returning(|_, _| {
+            Err(Error::RequestError(viaduct::Error::NetworkError(
+                "offline".to_owned(),
+            )))
+        });
+        let mut seq = mockall::Sequence::new();
+        // While we're offline, the device record can't be destroyed...
+        client
+            .expect_destroy_device_record()
+            .with(always(), eq("refreshtok"), eq("1234a"))
+            .times(2)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _| {
+                Err(Error::RequestError(viaduct::Error::NetworkError(
+                    "offline".to_owned(),
+                )))
+            });
+        // ...but once we're back, it can.
+        client
+            .expect_destroy_device_record()
+            .with(always(), eq("refreshtok"), eq("1234a"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _| Ok(()));
+        fxa.set_client(Arc::new(client));
+
+        fxa.disconnect();
+        assert!(fxa.state.refresh_token().is_none());
+        assert_eq!(fxa.state.pending_disconnects().len(), 1);
+
+        // The pending disconnect is persisted.
+        let restored = FirefoxAccount::from_json(&fxa.to_json().unwrap()).unwrap();
+        assert_eq!(
+            restored.state.pending_disconnects(),
+            fxa.state.pending_disconnects()
+        );
+
+        assert!(fxa.retry_pending_disconnect().is_err());
+        assert_eq!(fxa.state.pending_disconnects().len(), 1);
+        fxa.retry_pending_disconnect().unwrap();
+        assert!(fxa.state.pending_disconnects().is_empty());
+
+        // There's nothing to do now.
+        fxa.retry_pending_disconnect().unwrap();
+    }
+
+    #[test]
+    fn test_retry_pending_disconnect_invalid_token() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.state.add_pending_disconnect(PendingDisconnect {
+            token: "refreshtok".to_string(),
+            device_id: None,
+        });
+
+        let mut client = MockFxAClient::new();
+        client
+            .expect_destroy_refresh_token()
+            .with(always(), eq("refreshtok"))
+            .times(1)
+            .returning(|_, _| {
+                Err(Error::RemoteError {
+                    code: 401,
+                    errno: 110,
+                    error: "Unauthorized".to_owned(),
+                    message: "Invalid authentication token in request signature".to_owned(),
+                    info: "".to_owned(),
+                })
+            });
+        fxa.set_client(Arc::new(client));
+
+        // The token's already gone, so there's nothing left to retry.
+        fxa.retry_pending_disconnect().unwrap();
+        assert!(fxa.state.pending_disconnects().is_empty());
     }
 
     #[test]
@@ -12,7 +12,9 @@ use super::{
     util, FirefoxAccount,
 };
 use crate::auth::UserData;
-use crate::{AuthorizationParameters, Error, FxaServer, Result, ScopedKey};
+use crate::{
+    AuthorizationParameters, Error, FxaServer, FxaState, OAuthFlowOptions, Result, ScopedKey,
+};
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use jwcrypto::{EncryptionAlgorithm, EncryptionParameters};
 use rate_limiter::RateLimiter;
@@ -30,6 +32,24 @@ const OAUTH_MIN_TIME_LEFT: u64 = 60;
 // Special redirect urn based on the OAuth native spec, signals that the
 // WebChannel flow is used
 pub const OAUTH_WEBCHANNEL_REDIRECT: &str = "urn:ietf:wg:oauth:2.0:oob:oauth-redirect-webchannel";
+// The query parameters `begin_oauth_flow_with_options` sets itself, which
+// can't be passed as extra parameters.
+const RESERVED_OAUTH_PARAMS: &[&str] = &[
+    "access_type",
+    "action",
+    "client_id",
+    "code_challenge",
+    "code_challenge_method",
+    "context",
+    "email",
+    "entrypoint",
+    "keys_jwk",
+    "prompt",
+    "redirect_uri",
+    "response_type",
+    "scope",
+    "state",
+];
 
 impl FirefoxAccount {
     /// Fetch a short-lived access token using the saved refresh token.
@@ -45,6 +65,7 @@ impl FirefoxAccount {
         if scope.contains(' ') {
             return Err(Error::MultipleScopesRequested);
         }
+        scopes::check(scope)?;
         if let Some(oauth_info) = self.state.get_cached_access_token(scope) {
             if oauth_info.expires_at > util::now_secs() + OAUTH_MIN_TIME_LEFT {
                 // If the cached key is missing the required sync scoped key, try to fetch it again
@@ -91,6 +112,41 @@ impl FirefoxAccount {
         Ok(token_info)
     }
 
+    /// Get a token for another service, such as a VPN or Relay backend, by exchanging our
+    /// refresh token for an access token whose audience is that service. Tokens are cached for
+    /// each service until they're about to expire.
+    pub fn get_service_token(&mut self, service: &str) -> Result<AccessTokenInfo> {
+        let refresh_token = match self.state.refresh_token() {
+            Some(refresh_token) => refresh_token.token.clone(),
+            None => return Err(Error::NoRefreshToken),
+        };
+        if let Some(cached) = self.service_token_cache.get(service) {
+            // A token exchanged from a previous refresh token might be for another account.
+            if cached.refresh_token == refresh_token
+                && cached.token_info.expires_at > util::now_secs() + OAUTH_MIN_TIME_LEFT
+            {
+                return Ok(cached.token_info.clone());
+            }
+        }
+        let resp =
+            self.client
+                .exchange_token_for_service(self.state.config(), &refresh_token, service)?;
+        let token_info = AccessTokenInfo {
+            scope: resp.scope,
+            token: resp.access_token,
+            key: None,
+            expires_at: util::now_secs() + resp.expires_in,
+        };
+        self.service_token_cache.insert(
+            service.to_string(),
+            ServiceToken {
+                refresh_token,
+                token_info: token_info.clone(),
+            },
+        );
+        Ok(token_info)
+    }
+
     /// Sets the user data (session token, email, uid)
     pub fn set_user_data(&mut self, user_data: UserData) {
         // for now, we only have use for the session token
@@ -122,6 +178,38 @@ impl FirefoxAccount {
         })
     }
 
+    /// Check whether the server still accepts our session token, which it stops doing when
+    /// the user changes their password or signs this device out from elsewhere.
+    ///
+    /// If it doesn't, this calls `on_auth_issues()` and, if we were connected, moves the state
+    /// machine to `AuthIssues`, then returns `false`. If we don't have a session token, the
+    /// refresh token is checked instead.
+    pub fn check_session_validity(&mut self) -> Result<bool> {
+        let valid = match self.state.session_token() {
+            Some(session_token) => {
+                self.auth_circuit_breaker.check()?;
+                match self
+                    .client
+                    .get_session_status(self.state.config(), session_token)
+                {
+                    Ok(_) => true,
+                    Err(Error::RemoteError { code: 401, .. }) => false,
+                    Err(e) => return Err(e),
+                }
+            }
+            None => self.check_authorization_status()?.active,
+        };
+        if !valid {
+            warn!("Session is no longer valid, moving to auth issues");
+            self.on_auth_issues();
+            if self.auth_state == FxaState::Connected {
+                self.auth_state = FxaState::AuthIssues;
+                self.auth_state_changed_at = util::now();
+            }
+        }
+        Ok(valid)
+    }
+
     /// Initiate a pairing flow and return a URL that should be navigated to.
     ///
     /// * `pairing_url` - A pairing URL obtained by scanning a QR code produced by
@@ -135,6 +223,9 @@ impl FirefoxAccount {
         scopes: &[&str],
         entrypoint: &str,
     ) -> Result<String> {
+        for scope in scopes {
+            scopes::check(scope)?;
+        }
         let mut url = self.state.config().pair_supp_url()?;
         url.query_pairs_mut().append_pair("entrypoint", entrypoint);
         let pairing_url = Url::parse(pairing_url)?;
@@ -155,8 +246,38 @@ impl FirefoxAccount {
     /// * `entrypoint` - The entrypoint to be used for metrics
     /// * `metrics` - Optional metrics parameters
     pub fn begin_oauth_flow(&mut self, scopes: &[&str], entrypoint: &str) -> Result<String> {
+        self.begin_oauth_flow_with_options(&OAuthFlowOptions {
+            scopes: scopes.iter().map(ToString::to_string).collect(),
+            entrypoint: entrypoint.to_string(),
+            ..Default::default()
+        })
+    }
+
+    /// Initiate an OAuth login flow with the given options, and return a URL
+    /// that should be navigated to.
+    pub fn begin_oauth_flow_with_options(&mut self, options: &OAuthFlowOptions) -> Result<String> {
+        // Check these before we change any state.
+        let mut extra_params: Vec<_> = options.extra_params.iter().collect();
+        extra_params.sort();
+        if let Some((name, _)) = extra_params
+            .iter()
+            .find(|(name, _)| RESERVED_OAUTH_PARAMS.contains(&name.as_str()))
+        {
+            return Err(Error::ReservedOAuthParameter(name.to_string()));
+        }
+        for scope in &options.scopes {
+            scopes::check(scope)?;
+        }
+
         self.state.on_begin_oauth();
-        let mut url = if self.state.last_seen_profile().is_some() {
+        // If we know who the user must sign in as, we use the force-auth page
+        // so they can't sign in to a different account.
+        let email = options.login_hint.clone().or_else(|| {
+            self.state
+                .last_seen_profile()
+                .map(|cached_profile| cached_profile.response.email.clone())
+        });
+        let mut url = if email.is_some() {
             self.state.config().oauth_force_auth_url()?
         } else {
             self.state.config().authorization_endpoint()?
@@ -165,13 +286,17 @@ impl FirefoxAccount {
         url.query_pairs_mut()
             .append_pair("action", "email")
             .append_pair("response_type", "code")
-            .append_pair("entrypoint", entrypoint);
+            .append_pair("entrypoint", &options.entrypoint);
 
-        if let Some(cached_profile) = self.state.last_seen_profile() {
-            url.query_pairs_mut()
-                .append_pair("email", &cached_profile.response.email);
+        if let Some(email) = email {
+            url.query_pairs_mut().append_pair("email", &email);
+        }
+        if let Some(prompt) = options.prompt {
+            url.query_pairs_mut().append_pair("prompt", prompt.as_str());
         }
+        url.query_pairs_mut().extend_pairs(extra_params);
 
+        let scopes = &options.scopes;
         let scopes: Vec<String> = match self.state.refresh_token() {
             Some(refresh_token) => {
                 // Union of the already held scopes and the one requested.
@@ -202,6 +327,7 @@ impl FirefoxAccount {
         &self,
         auth_params: AuthorizationParameters,
     ) -> Result<String> {
+        validate_authorization_parameters(&auth_params)?;
         let session_token = self.get_session_token()?;
 
         // Validate request to ensure that the client is actually allowed to request
@@ -317,15 +443,77 @@ impl FirefoxAccount {
             Some(oauth_flow) => oauth_flow,
             None => return Err(Error::UnknownOAuthState),
         };
-        let resp = self.client.create_refresh_token_using_authorization_code(
+        let resp = match self.client.create_refresh_token_using_authorization_code(
             self.state.config(),
             self.state.session_token(),
             code,
             &oauth_flow.code_verifier,
-        )?;
+        ) {
+            Ok(resp) => resp,
+            Err(e) => {
+                if e.is_unverified_session() && self.has_two_factor_enabled() {
+                    // Keep the flow around, so that it can be completed once the user
+                    // passes the challenge with `verify_totp_code()`.
+                    self.state.begin_oauth_flow(state, oauth_flow);
+                    self.two_factor_oauth_flow = Some(PendingOAuthFlow {
+                        code: code.to_string(),
+                        state: state.to_string(),
+                    });
+                    return Err(Error::TwoFactorRequired);
+                }
+                if matches!(e, Error::RequestError(_)) && self.two_factor_oauth_flow.is_some() {
+                    // Don't make the user start over because of a network error after they
+                    // entered their code.
+                    self.state.begin_oauth_flow(state, oauth_flow);
+                }
+                return Err(e);
+            }
+        };
+        self.two_factor_oauth_flow = None;
         self.handle_oauth_response(resp, oauth_flow.scoped_keys_flow)
     }
 
+    /// Complete the OAuth flow which was waiting for the user to pass a two-step
+    /// authentication challenge.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    pub(crate) fn complete_two_factor_oauth_flow(&mut self) -> Result<()> {
+        let PendingOAuthFlow { code, state } = self
+            .two_factor_oauth_flow
+            .clone()
+            .ok_or(Error::UnknownOAuthState)?;
+        self.complete_oauth_flow(&code, &state)
+    }
+
+    /// Is two-step authentication set up for the account?
+    ///
+    /// The server also refuses to complete the flow for sessions whose email isn't verified
+    /// yet, and a TOTP code won't help those, so check before asking the user for one.
+    fn has_two_factor_enabled(&self) -> bool {
+        let Some(session_token) = self.state.session_token() else {
+            return false;
+        };
+        match self
+            .client
+            .get_totp_status(self.state.config(), session_token)
+        {
+            Ok(status) => status.exists,
+            Err(e) => {
+                warn!("Couldn't check for two-step authentication: {e}");
+                false
+            }
+        }
+    }
+
+    /// Verify the session with a TOTP code, returning whether the code was right.
+    pub fn verify_totp_code(&mut self, code: &str) -> Result<bool> {
+        let session_token = self.get_session_token()?;
+        let resp = self
+            .client
+            .verify_session_totp(self.state.config(), &session_token, code)?;
+        Ok(resp.success)
+    }
+
     pub(crate) fn handle_oauth_response(
         &mut self,
         resp: OAuthTokenResponse,
@@ -371,7 +559,7 @@ impl FirefoxAccount {
             .client
             .destroy_access_token(self.state.config(), &resp.access_token)
         {
-            log::warn!("Access token destruction failure: {:?}", err);
+            warn!("Access token destruction failure: {:?}", err);
         }
         let old_refresh_token = self.state.refresh_token().cloned();
         let new_refresh_token = resp
@@ -383,7 +571,7 @@ impl FirefoxAccount {
             Some(_) => match self.get_current_device() {
                 Ok(maybe_device) => maybe_device,
                 Err(err) => {
-                    log::warn!("Error while getting previous device information: {:?}", err);
+                    warn!("Error while getting previous device information: {:?}", err);
                     None
                 }
             },
@@ -396,7 +584,7 @@ impl FirefoxAccount {
                 .client
                 .destroy_refresh_token(self.state.config(), &refresh_token.token)
             {
-                log::warn!("Refresh token destruction failure: {:?}", err);
+                warn!("Refresh token destruction failure: {:?}", err);
             }
         }
         if let Some(ref device_info) = old_device_info {
@@ -406,9 +594,15 @@ impl FirefoxAccount {
                 &device_info.push_subscription,
                 &device_info.available_commands,
             ) {
-                log::warn!("Device information restoration failed: {:?}", err);
+                warn!("Device information restoration failed: {:?}", err);
             }
         }
+        let uid = if scoped_keys.is_empty() {
+            None
+        } else {
+            self.scoped_keys_uid(resp.session_token.as_deref())
+        };
+        let scoped_keys = self.check_scoped_keys(uid.as_deref(), scoped_keys)?;
         self.state.complete_oauth_flow(
             scoped_keys,
             RefreshToken {
@@ -420,6 +614,22 @@ impl FirefoxAccount {
         Ok(())
     }
 
+    /// The uid of the account a new session token belongs to, so we know whose keys we were
+    /// just given.
+    fn scoped_keys_uid(&self, session_token: Option<&str>) -> Option<String> {
+        let session_token = session_token?;
+        match self
+            .client
+            .get_session_status(self.state.config(), session_token)
+        {
+            Ok(status) => Some(status.uid),
+            Err(err) => {
+                warn!("Error while getting the session status: {:?}", err);
+                None
+            }
+        }
+    }
+
     /// Typically called during a password change flow.
     /// Invalidates all tokens and fetches a new refresh token.
     /// Because the old refresh token is not valid anymore, we can't do like `handle_oauth_response`
@@ -449,9 +659,63 @@ impl FirefoxAccount {
         Ok(())
     }
 
+    /// The scopes granted to our refresh token, sorted.
+    pub fn get_granted_scopes(&self) -> Vec<String> {
+        let mut scopes: Vec<String> = match self.state.refresh_token() {
+            Some(refresh_token) => refresh_token.scopes.iter().cloned().collect(),
+            None => vec![],
+        };
+        scopes.sort();
+        scopes
+    }
+
+    /// Adds `scopes` to those granted to our refresh token, using the session token rather than
+    /// sending the user through another OAuth flow.
+    /// The server doesn't deliver scoped keys this way, so scopes which need them still have to be
+    /// requested with `begin_oauth_flow`.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    pub fn request_additional_scopes(&mut self, scopes: &[&str]) -> Result<()> {
+        for scope in scopes {
+            scopes::check(scope)?;
+        }
+        let old_refresh_token = self
+            .state
+            .refresh_token()
+            .ok_or(Error::NoRefreshToken)?
+            .clone();
+        if scopes
+            .iter()
+            .all(|scope| old_refresh_token.scopes.contains(*scope))
+        {
+            return Ok(());
+        }
+        let session_token = self.get_session_token()?;
+        let mut all_scopes: Vec<&str> = old_refresh_token
+            .scopes
+            .iter()
+            .map(AsRef::as_ref)
+            .chain(scopes.iter().copied())
+            .collect();
+        all_scopes.sort_unstable();
+        all_scopes.dedup();
+        let resp = self.client.create_refresh_token_using_session_token(
+            self.state.config(),
+            &session_token,
+            &all_scopes,
+        )?;
+        // This replaces our refresh token just like the end of an OAuth flow does, except that
+        // there are never any keys.
+        self.handle_oauth_response(resp, None)?;
+        self.clear_access_token_cache();
+        self.clear_devices_and_attached_clients_cache();
+        Ok(())
+    }
+
     /// **💾 This method may alter the persisted account state.**
     pub fn clear_access_token_cache(&mut self) {
         self.state.clear_access_token_cache();
+        self.service_token_cache.clear();
     }
 }
 
@@ -483,6 +747,39 @@ impl AuthCircuitBreaker {
     }
 }
 
+// Check the parameters of an incoming authorization request before we ask the server for a
+// code, so that a malformed request from another app fails here rather than with a code the
+// app can't redeem.
+fn validate_authorization_parameters(auth_params: &AuthorizationParameters) -> Result<()> {
+    if !matches!(auth_params.access_type.as_str(), "online" | "offline") {
+        return Err(Error::InvalidAuthorizationParameter("access_type"));
+    }
+    match (
+        &auth_params.code_challenge,
+        &auth_params.code_challenge_method,
+    ) {
+        (None, None) => {}
+        (Some(code_challenge), Some(code_challenge_method)) => {
+            // S256 is the only method FxA supports, where the challenge is the 32-byte SHA-256
+            // digest of the code verifier.
+            if code_challenge_method != "S256" {
+                return Err(Error::InvalidAuthorizationParameter(
+                    "code_challenge_method",
+                ));
+            }
+            let digest_len = URL_SAFE_NO_PAD
+                .decode(code_challenge)
+                .map_or(0, |digest| digest.len());
+            if digest_len != 32 {
+                return Err(Error::InvalidAuthorizationParameter("code_challenge"));
+            }
+        }
+        (None, Some(_)) => return Err(Error::MissingUrlParameter("code_challenge")),
+        (Some(_), None) => return Err(Error::MissingUrlParameter("code_challenge_method")),
+    }
+    Ok(())
+}
+
 impl TryFrom<Url> for AuthorizationParameters {
     type Error = Error;
 
@@ -538,6 +835,20 @@ pub struct OAuthFlow {
     pub code_verifier: String,
 }
 
+/// The `code` and `state` of an OAuth flow that's waiting for a two-step authentication
+/// challenge.
+#[derive(Clone)]
+pub(crate) struct PendingOAuthFlow {
+    pub code: String,
+    pub state: String,
+}
+
+/// A token for another service, and the refresh token it was exchanged from.
+pub(crate) struct ServiceToken {
+    refresh_token: String,
+    token_info: AccessTokenInfo,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct AccessTokenInfo {
     pub scope: String,
@@ -586,8 +897,11 @@ impl From<IntrospectInfo> for crate::AuthorizationInfo {
 
 #[cfg(test)]
 mod tests {
-    use super::super::{http_client::*, Config};
+    use super::super::{
+        http_client::*, scoped_keys::TEST_KEYS_JWE, state_persistence::SeenScopedKey, Config,
+    };
     use super::*;
+    use crate::logging::capture_logs;
     use mockall::predicate::always;
     use mockall::predicate::eq;
     use std::borrow::Cow;
@@ -696,6 +1010,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_oauth_flow_with_options() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.add_cached_profile("123", "cached@example.com");
+        let url = fxa
+            .begin_oauth_flow_with_options(&OAuthFlowOptions {
+                scopes: vec!["profile".to_string()],
+                entrypoint: "test_oauth_flow_with_options".to_string(),
+                prompt: Some(crate::OAuthPrompt::Login),
+                login_hint: Some("test@example.com".to_string()),
+                extra_params: HashMap::from([
+                    ("utm_source".to_string(), "settings".to_string()),
+                    ("flow_id".to_string(), "abc".to_string()),
+                ]),
+            })
+            .unwrap();
+        let url = Url::parse(&url).unwrap();
+        // The login hint wins over the cached profile.
+        assert_eq!(url.path(), "/oauth/force_auth");
+        let pairs: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(pairs["email"], "test@example.com");
+        assert_eq!(pairs["prompt"], "login");
+        assert_eq!(pairs["utm_source"], "settings");
+        assert_eq!(pairs["flow_id"], "abc");
+        assert_eq!(pairs["code_challenge_method"], "S256");
+        assert_eq!(pairs["code_challenge"].len(), 43);
+
+        // Parameters we manage ourselves can't be overridden.
+        let err = fxa
+            .begin_oauth_flow_with_options(&OAuthFlowOptions {
+                scopes: vec!["profile".to_string()],
+                extra_params: HashMap::from([("code_challenge".to_string(), "nope".to_string())]),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert!(matches!(err, Error::ReservedOAuthParameter(name) if name == "code_challenge"));
+    }
+
     #[test]
     fn test_webchannel_context_url() {
         // FIXME: this test shouldn't make network requests.
@@ -859,6 +1212,79 @@ mod tests {
         assert!(auth_status.active);
     }
 
+    #[test]
+    fn test_check_session_validity() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.set_session_token("session");
+        fxa.state.force_refresh_token(RefreshToken {
+            token: "refresh_token".to_owned(),
+            scopes: std::collections::HashSet::new(),
+        });
+        fxa.auth_state = FxaState::Connected;
+
+        let mut client = MockFxAClient::new();
+        client
+            .expect_get_session_status()
+            .with(always(), eq("session"))
+            .times(1)
+            .returning(|_, _| {
+                Ok(SessionStatusResponse {
+                    uid: "uid".to_owned(),
+                    state: "verified".to_owned(),
+                })
+            });
+        client
+            .expect_get_session_status()
+            .with(always(), eq("session"))
+            .times(1)
+            .returning(|_, _| {
+                Err(Error::RemoteError {
+                    code: 401,
+                    errno: 110,
+                    error: "Unauthorized".to_owned(),
+                    message: "Invalid authentication token in request signature".to_owned(),
+                    info: "".to_owned(),
+                })
+            });
+        fxa.set_client(Arc::new(client));
+
+        assert!(fxa.check_session_validity().unwrap());
+        assert_eq!(fxa.get_state(), FxaState::Connected);
+        assert!(fxa.state.refresh_token().is_some());
+
+        // The user changed their password on another device.
+        assert!(!fxa.check_session_validity().unwrap());
+        assert_eq!(fxa.get_state(), FxaState::AuthIssues);
+        assert_ne!(fxa.auth_state_changed_at, 0);
+        assert!(fxa.state.refresh_token().is_none());
+        assert!(fxa.state.session_token().is_none());
+    }
+
+    #[test]
+    fn test_check_session_validity_network_error() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.set_session_token("session");
+        fxa.auth_state = FxaState::Connected;
+
+        let mut client = MockFxAClient::new();
+        client
+            .expect_get_session_status()
+            .times(1)
+            .returning(|_, _| {
+                Err(Error::RequestError(viaduct::Error::NetworkError(
+                    "offline".to_owned(),
+                )))
+            });
+        fxa.set_client(Arc::new(client));
+
+        // We can't tell whether the session is valid, so nothing changes.
+        assert!(fxa.check_session_validity().is_err());
+        assert_eq!(fxa.get_state(), FxaState::Connected);
+        assert!(fxa.state.session_token().is_some());
+    }
+
     #[test]
     fn test_check_authorization_status_circuit_breaker() {
         let config = Config::stable_dev("12345678", "https://foo.bar");
@@ -1057,6 +1483,126 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_auth_code_pair_with_keys() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.set_session_token("session");
+        fxa.state.insert_scoped_key(
+            OLD_SYNC,
+            ScopedKey {
+                kty: "oct".to_string(),
+                scope: OLD_SYNC.to_string(),
+                k: "kMtwpVC0ZaYFJymPza8rXK_0CgCp3KMwRStwGfBRBDtL6hXRDVJgQFaoOQ2dimw0Bko5WVv2gNTy7RX5zFYZHg".to_string(),
+                kid: "1542236016429-Ox1FbJfFfwTe5t-xq4v2hQ".to_string(),
+            },
+        );
+        let mut client = MockFxAClient::new();
+        client
+            .expect_get_scoped_key_data()
+            .with(always(), eq("session"), eq("12345678"), eq(OLD_SYNC))
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(HashMap::from([(
+                    OLD_SYNC.to_string(),
+                    ScopedKeyDataResponse {
+                        key_rotation_secret: "IamASecret".to_string(),
+                        key_rotation_timestamp: 100,
+                        identifier: "".to_string(),
+                    },
+                )]))
+            });
+        let code_challenge = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+        let sent_keys_jwe = Arc::new(std::sync::Mutex::new(None));
+        let sent_keys_jwe_clone = Arc::clone(&sent_keys_jwe);
+        client
+            .expect_create_authorization_code_using_session_token()
+            .withf(move |_, session_token, params| {
+                session_token == "session"
+                    && params.scope == OLD_SYNC
+                    && params.code_challenge.as_deref() == Some(code_challenge)
+                    && params.code_challenge_method.as_deref() == Some("S256")
+            })
+            .times(1)
+            .returning(move |_, _, params| {
+                *sent_keys_jwe_clone.lock().unwrap() = params.keys_jwe;
+                Ok(OAuthAuthResponse {
+                    redirect: "https://example.com/".to_string(),
+                    code: "the_code".to_string(),
+                    state: params.state,
+                })
+            });
+        fxa.set_client(Arc::new(client));
+
+        let flow = ScopedKeysFlow::with_random_key().unwrap();
+        let jwk = serde_json::to_vec(&flow.get_public_key_jwk().unwrap()).unwrap();
+        let auth_params = AuthorizationParameters {
+            client_id: "12345678".to_string(),
+            scope: vec![OLD_SYNC.to_string()],
+            state: "somestate".to_string(),
+            access_type: "offline".to_string(),
+            code_challenge: Some(code_challenge.to_string()),
+            code_challenge_method: Some("S256".to_string()),
+            keys_jwk: Some(URL_SAFE_NO_PAD.encode(jwk)),
+        };
+        let code = fxa.authorize_code_using_session_token(auth_params).unwrap();
+        assert_eq!(code, "the_code");
+
+        // The keys are encrypted to the requesting app's key.
+        let keys_jwe = sent_keys_jwe.lock().unwrap().take().unwrap();
+        let keys: HashMap<String, ScopedKey> =
+            serde_json::from_str(&flow.decrypt_keys_jwe(&keys_jwe).unwrap()).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[OLD_SYNC].kid, "1542236016429-Ox1FbJfFfwTe5t-xq4v2hQ");
+    }
+
+    #[test]
+    fn test_auth_code_pair_invalid_parameters() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.set_session_token("session");
+        // We don't get as far as asking the server.
+        fxa.set_client(Arc::new(MockFxAClient::new()));
+        let auth_params = |access_type: &str, challenge: Option<&str>, method: Option<&str>| {
+            AuthorizationParameters {
+                client_id: "12345678".to_string(),
+                scope: vec!["profile".to_string()],
+                state: "somestate".to_string(),
+                access_type: access_type.to_string(),
+                code_challenge: challenge.map(ToString::to_string),
+                code_challenge_method: method.map(ToString::to_string),
+                keys_jwk: None,
+            }
+        };
+        let challenge = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+        assert!(matches!(
+            fxa.authorize_code_using_session_token(auth_params("forever", None, None)),
+            Err(Error::InvalidAuthorizationParameter("access_type"))
+        ));
+        assert!(matches!(
+            fxa.authorize_code_using_session_token(auth_params(
+                "online",
+                Some(challenge),
+                Some("plain")
+            )),
+            Err(Error::InvalidAuthorizationParameter(
+                "code_challenge_method"
+            ))
+        ));
+        assert!(matches!(
+            fxa.authorize_code_using_session_token(auth_params(
+                "online",
+                Some("tooshort"),
+                Some("S256")
+            )),
+            Err(Error::InvalidAuthorizationParameter("code_challenge"))
+        ));
+        assert!(matches!(
+            fxa.authorize_code_using_session_token(auth_params("online", Some(challenge), None)),
+            Err(Error::MissingUrlParameter("code_challenge_method"))
+        ));
+    }
+
     #[test]
     fn test_set_user_data_sets_session_token() {
         let config = Config::stable_dev("12345678", "https://foo.bar");
@@ -1120,4 +1666,390 @@ mod tests {
         fxa.complete_oauth_flow("mock_code", state.1.as_ref())
             .unwrap();
     }
+
+    #[test]
+    fn test_complete_oauth_flow_two_factor_required() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.set_session_token("session");
+        fxa.state.begin_oauth_flow(
+            "state",
+            OAuthFlow {
+                scoped_keys_flow: None,
+                code_verifier: "verifier".to_string(),
+            },
+        );
+
+        let mut client = MockFxAClient::new();
+        client
+            .expect_create_refresh_token_using_authorization_code()
+            .times(1)
+            .returning(|_, _, _, _| {
+                Err(Error::RemoteError {
+                    code: 400,
+                    errno: 138,
+                    error: "Bad Request".to_string(),
+                    message: "Unverified session".to_string(),
+                    info: "".to_string(),
+                })
+            });
+        client
+            .expect_get_totp_status()
+            .with(always(), eq("session"))
+            .times(1)
+            .returning(|_, _| Ok(TotpStatusResponse { exists: true }));
+        client
+            .expect_verify_session_totp()
+            .with(always(), eq("session"), eq("000000"))
+            .times(1)
+            .returning(|_, _, _| Ok(VerifyTotpResponse { success: false }));
+        client
+            .expect_verify_session_totp()
+            .with(always(), eq("session"), eq("123456"))
+            .times(1)
+            .returning(|_, _, _| Ok(VerifyTotpResponse { success: true }));
+        client
+            .expect_create_refresh_token_using_authorization_code()
+            .withf(|_, session_token, code, code_verifier| {
+                matches!(session_token, Some("session"))
+                    && code == "code"
+                    && code_verifier == "verifier"
+            })
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(OAuthTokenResponse {
+                    keys_jwe: None,
+                    refresh_token: Some("refresh_token".to_string()),
+                    session_token: None,
+                    expires_in: 1,
+                    scope: "profile".to_string(),
+                    access_token: "access_token".to_string(),
+                })
+            });
+        client
+            .expect_destroy_access_token()
+            .with(always(), eq("access_token"))
+            .times(1)
+            .returning(|_, _| Ok(()));
+        fxa.set_client(Arc::new(client));
+
+        assert!(matches!(
+            fxa.complete_oauth_flow("code", "state"),
+            Err(Error::TwoFactorRequired)
+        ));
+        assert!(!fxa.verify_totp_code("000000").unwrap());
+        assert!(fxa.verify_totp_code("123456").unwrap());
+        // The flow was kept, so it can be completed now that the session is verified.
+        fxa.complete_two_factor_oauth_flow().unwrap();
+        assert_eq!(fxa.state.refresh_token().unwrap().token, "refresh_token");
+        assert!(fxa.two_factor_oauth_flow.is_none());
+        assert!(matches!(
+            fxa.complete_oauth_flow("code", "state"),
+            Err(Error::UnknownOAuthState)
+        ));
+    }
+
+    #[test]
+    fn test_complete_oauth_flow_unverified_email() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.set_session_token("session");
+        fxa.state.begin_oauth_flow(
+            "state",
+            OAuthFlow {
+                scoped_keys_flow: None,
+                code_verifier: "verifier".to_string(),
+            },
+        );
+
+        let mut client = MockFxAClient::new();
+        client
+            .expect_create_refresh_token_using_authorization_code()
+            .times(1)
+            .returning(|_, _, _, _| {
+                Err(Error::RemoteError {
+                    code: 400,
+                    errno: 138,
+                    error: "Bad Request".to_string(),
+                    message: "Unverified session".to_string(),
+                    info: "".to_string(),
+                })
+            });
+        // The session is unverified because the email is, which a TOTP code won't fix.
+        client
+            .expect_get_totp_status()
+            .times(1)
+            .returning(|_, _| Ok(TotpStatusResponse { exists: false }));
+        fxa.set_client(Arc::new(client));
+
+        let err = fxa.complete_oauth_flow("code", "state").unwrap_err();
+        assert!(err.is_unverified_session());
+        assert!(fxa.two_factor_oauth_flow.is_none());
+        assert!(matches!(
+            fxa.complete_oauth_flow("code", "state"),
+            Err(Error::UnknownOAuthState)
+        ));
+    }
+
+    #[test]
+    fn test_complete_oauth_flow_keys_mismatch() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        // The user signed in before, and was given a different sync key then.
+        fxa.state.reset_seen_scoped_keys("uid");
+        fxa.state.set_seen_scoped_key(
+            scopes::OLD_SYNC,
+            SeenScopedKey {
+                kid: "1526414944666-zgTjf5oXmPmBjxwXWFsDWg".to_string(),
+                fingerprint: "0123456789abcdef0123456789abcdef".to_string(),
+            },
+        );
+        fxa.state.begin_oauth_flow(
+            "state",
+            OAuthFlow {
+                scoped_keys_flow: Some(ScopedKeysFlow::with_test_key()),
+                code_verifier: "verifier".to_string(),
+            },
+        );
+
+        let mut client = MockFxAClient::new();
+        client
+            .expect_create_refresh_token_using_authorization_code()
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(OAuthTokenResponse {
+                    keys_jwe: Some(TEST_KEYS_JWE.to_string()),
+                    refresh_token: Some("refresh_token".to_string()),
+                    session_token: Some("session".to_string()),
+                    expires_in: 1,
+                    scope: scopes::OLD_SYNC.to_string(),
+                    access_token: "access_token".to_string(),
+                })
+            });
+        client
+            .expect_destroy_access_token()
+            .times(1)
+            .returning(|_, _| Ok(()));
+        client
+            .expect_get_session_status()
+            .with(always(), eq("session"))
+            .times(1)
+            .returning(|_, _| {
+                Ok(SessionStatusResponse {
+                    uid: "uid".to_string(),
+                    state: "verified".to_string(),
+                })
+            });
+        fxa.set_client(Arc::new(client));
+
+        fxa.complete_oauth_flow("code", "state").unwrap();
+        assert!(fxa.get_scoped_key(scopes::OLD_SYNC).is_ok());
+        let events = fxa.take_pending_account_events();
+        assert!(matches!(
+            events.as_slice(),
+            [crate::AccountEvent::KeysMismatch { scopes }] if scopes == &[scopes::OLD_SYNC]
+        ));
+    }
+
+    #[test]
+    fn test_request_additional_scopes() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.set_session_token("session");
+        fxa.state.force_refresh_token(RefreshToken {
+            token: "refreshtok".to_string(),
+            scopes: HashSet::from(["profile".to_string()]),
+        });
+        let relay_scope = "https://identity.mozilla.com/apps/relay";
+
+        let mut client = MockFxAClient::new();
+        client
+            .expect_create_refresh_token_using_session_token()
+            .withf(move |_, session_token, scopes| {
+                session_token == "session" && scopes == [relay_scope, "profile"]
+            })
+            .times(1)
+            .returning(move |_, _, _| {
+                Ok(OAuthTokenResponse {
+                    keys_jwe: None,
+                    refresh_token: Some("new_refreshtok".to_string()),
+                    session_token: None,
+                    expires_in: 1,
+                    scope: format!("profile {relay_scope}"),
+                    access_token: "access_token".to_string(),
+                })
+            });
+        client
+            .expect_destroy_access_token()
+            .with(always(), eq("access_token"))
+            .times(1)
+            .returning(|_, _| Ok(()));
+        client
+            .expect_get_devices()
+            .with(always(), eq("refreshtok"))
+            .times(1)
+            .returning(|_, _| Ok(vec![]));
+        client
+            .expect_destroy_refresh_token()
+            .with(always(), eq("refreshtok"))
+            .times(1)
+            .returning(|_, _| Ok(()));
+        fxa.set_client(Arc::new(client));
+
+        assert_eq!(fxa.get_granted_scopes(), vec!["profile"]);
+        fxa.request_additional_scopes(&[relay_scope]).unwrap();
+        assert_eq!(fxa.get_granted_scopes(), vec![relay_scope, "profile"]);
+        assert_eq!(fxa.state.refresh_token().unwrap().token, "new_refreshtok");
+        assert_eq!(fxa.get_session_token().unwrap(), "session");
+
+        // Asking for scopes we already have doesn't hit the network.
+        fxa.request_additional_scopes(&["profile"]).unwrap();
+    }
+
+    #[test]
+    fn test_oauth_logs_are_redacted() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.set_session_token("session");
+        fxa.state.force_refresh_token(RefreshToken {
+            token: "refreshtok".to_string(),
+            scopes: HashSet::from(["profile".to_string()]),
+        });
+        let relay_scope = "https://identity.mozilla.com/apps/relay";
+        let secret_token = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+        let remote_error = move || Error::RemoteError {
+            code: 400,
+            errno: 110,
+            error: "Invalid token".to_owned(),
+            message: format!("Invalid token {secret_token} for foo@example.com"),
+            info: "".to_owned(),
+        };
+
+        let mut client = MockFxAClient::new();
+        client
+            .expect_create_refresh_token_using_session_token()
+            .times(1)
+            .returning(move |_, _, _| {
+                Ok(OAuthTokenResponse {
+                    keys_jwe: None,
+                    refresh_token: Some("new_refreshtok".to_string()),
+                    session_token: None,
+                    expires_in: 1,
+                    scope: format!("profile {relay_scope}"),
+                    access_token: "access_token".to_string(),
+                })
+            });
+        client
+            .expect_destroy_access_token()
+            .times(1)
+            .returning(move |_, _| Err(remote_error()));
+        client
+            .expect_get_devices()
+            .times(1)
+            .returning(move |_, _| Err(remote_error()));
+        client
+            .expect_destroy_refresh_token()
+            .times(1)
+            .returning(move |_, _| Err(remote_error()));
+        fxa.set_client(Arc::new(client));
+
+        let (result, logs) = capture_logs(|| fxa.request_additional_scopes(&[relay_scope]));
+        result.unwrap();
+        assert_eq!(logs.len(), 3, "{logs:?}");
+        for log in logs {
+            assert!(!log.contains(secret_token), "{log}");
+            assert!(!log.contains("foo@example.com"), "{log}");
+        }
+    }
+
+    #[test]
+    fn test_request_additional_scopes_signed_out() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        assert!(fxa.get_granted_scopes().is_empty());
+        assert!(matches!(
+            fxa.request_additional_scopes(&["profile"]),
+            Err(Error::NoRefreshToken)
+        ));
+    }
+
+    #[test]
+    fn test_get_service_token() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        assert!(matches!(
+            fxa.get_service_token("vpn"),
+            Err(Error::NoRefreshToken)
+        ));
+        fxa.state.force_refresh_token(RefreshToken {
+            token: "refreshtok".to_string(),
+            scopes: HashSet::from(["profile".to_string()]),
+        });
+
+        let mut client = MockFxAClient::new();
+        client
+            .expect_exchange_token_for_service()
+            .withf(|_, refresh_token, service| refresh_token == "refreshtok" && service == "vpn")
+            .times(2)
+            .returning(|_, _, _| {
+                Ok(OAuthTokenResponse {
+                    keys_jwe: None,
+                    refresh_token: None,
+                    session_token: None,
+                    expires_in: 3600,
+                    scope: "profile".to_string(),
+                    access_token: "vpn_token".to_string(),
+                })
+            });
+        client
+            .expect_exchange_token_for_service()
+            .withf(|_, refresh_token, service| refresh_token == "refreshtok" && service == "relay")
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(OAuthTokenResponse {
+                    keys_jwe: None,
+                    refresh_token: None,
+                    session_token: None,
+                    expires_in: 3600,
+                    scope: "profile".to_string(),
+                    access_token: "relay_token".to_string(),
+                })
+            });
+        client
+            .expect_exchange_token_for_service()
+            .withf(|_, refresh_token, service| {
+                refresh_token == "new_refreshtok" && service == "vpn"
+            })
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(OAuthTokenResponse {
+                    keys_jwe: None,
+                    refresh_token: None,
+                    session_token: None,
+                    expires_in: 3600,
+                    scope: "profile".to_string(),
+                    access_token: "new_vpn_token".to_string(),
+                })
+            });
+        fxa.set_client(Arc::new(client));
+
+        let token = fxa.get_service_token("vpn").unwrap();
+        assert_eq!(token.token, "vpn_token");
+        assert!(token.key.is_none());
+        assert!(token.expires_at > util::now_secs());
+        // Cached tokens are keyed by service.
+        assert_eq!(fxa.get_service_token("vpn").unwrap().token, "vpn_token");
+        assert_eq!(fxa.get_service_token("relay").unwrap().token, "relay_token");
+
+        // Clearing the cache means fetching a new token.
+        fxa.clear_access_token_cache();
+        assert_eq!(fxa.get_service_token("vpn").unwrap().token, "vpn_token");
+
+        // As does a new refresh token.
+        fxa.state.force_refresh_token(RefreshToken {
+            token: "new_refreshtok".to_string(),
+            scopes: HashSet::from(["profile".to_string()]),
+        });
+        assert_eq!(fxa.get_service_token("vpn").unwrap().token, "new_vpn_token");
+    }
 }
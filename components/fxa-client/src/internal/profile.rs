@@ -4,11 +4,28 @@
 
 pub use super::http_client::ProfileResponse as Profile;
 use super::{scopes, util, CachedResponse, FirefoxAccount};
-use crate::{Error, Result};
+use crate::{Error, ProfileField, Result};
 
 // A cached profile response is considered fresh for `PROFILE_FRESHNESS_THRESHOLD` ms.
 const PROFILE_FRESHNESS_THRESHOLD: u64 = 120_000; // 2 minutes
 
+impl Profile {
+    /// Returns the fields which differ between this profile and `other`.
+    pub(crate) fn changed_fields(&self, other: &Profile) -> Vec<ProfileField> {
+        let mut changed = Vec::new();
+        if self.email != other.email {
+            changed.push(ProfileField::Email);
+        }
+        if self.display_name != other.display_name {
+            changed.push(ProfileField::DisplayName);
+        }
+        if self.avatar != other.avatar || self.avatar_default != other.avatar_default {
+            changed.push(ProfileField::Avatar);
+        }
+        changed
+    }
+}
+
 impl FirefoxAccount {
     /// Fetch the profile for the user.
     /// This method will error-out if the `profile` scope is not
@@ -24,9 +41,7 @@ impl FirefoxAccount {
             Ok(res) => Ok(res),
             Err(e) => match e {
                 Error::RemoteError { code: 401, .. } => {
-                    log::warn!(
-                        "Access token rejected, clearing the tokens cache and trying again."
-                    );
+                    warn!("Access token rejected, clearing the tokens cache and trying again.");
                     self.clear_access_token_cache();
                     self.clear_devices_and_attached_clients_cache();
                     self.get_profile_helper(ignore_cache)
@@ -5,7 +5,7 @@
 use std::convert::TryInto;
 
 use super::FirefoxAccount;
-use crate::{AccountEvent, Error, Result};
+use crate::{AccountEvent, Error, ProfileField, Result};
 use serde_derive::Deserialize;
 
 impl FirefoxAccount {
@@ -33,8 +33,20 @@ impl FirefoxAccount {
                 })
             }
             PushPayload::ProfileUpdated => {
+                let previous = self
+                    .state
+                    .last_seen_profile()
+                    .map(|cached| cached.response.clone());
                 self.state.clear_last_seen_profile();
-                Ok(AccountEvent::ProfileUpdated)
+                let changed_fields = match (previous, self.get_profile(true)) {
+                    (Some(previous), Ok(current)) => previous.changed_fields(&current),
+                    (None, Ok(_)) => ProfileField::ALL.to_vec(),
+                    (_, Err(e)) => {
+                        warn!("Failed to fetch the updated profile: {}", e);
+                        ProfileField::ALL.to_vec()
+                    }
+                };
+                Ok(AccountEvent::ProfileUpdated { changed_fields })
             }
             PushPayload::DeviceConnected(DeviceConnectedPushPayload { device_name }) => {
                 self.clear_devices_and_attached_clients_cache();
@@ -73,12 +85,12 @@ impl FirefoxAccount {
                 Ok(if !status.active {
                     AccountEvent::AccountAuthStateChanged
                 } else {
-                    log::info!("Password change event, but no action required");
+                    info!("Password change event, but no action required");
                     AccountEvent::Unknown
                 })
             }
             PushPayload::Unknown => {
-                log::info!("Unknown Push command.");
+                info!("Unknown Push command.");
                 Ok(AccountEvent::Unknown)
             }
         }
@@ -140,6 +152,7 @@ mod tests {
     use super::*;
     use crate::internal::http_client::IntrospectResponse;
     use crate::internal::http_client::MockFxAClient;
+    use crate::internal::http_client::{ProfileResponse, ResponseAndETag};
     use crate::internal::oauth::RefreshToken;
     use crate::internal::CachedResponse;
     use crate::internal::Config;
@@ -163,7 +176,60 @@ mod tests {
         let json = "{\"version\":1,\"command\":\"fxaccounts:profile_updated\"}";
         let event = fxa.handle_push_message(json).unwrap();
         assert!(fxa.state.last_seen_profile().is_none());
-        assert!(matches!(event, AccountEvent::ProfileUpdated));
+        // We couldn't fetch the new profile, so any field might have changed.
+        match event {
+            AccountEvent::ProfileUpdated { changed_fields } => {
+                assert_eq!(changed_fields, ProfileField::ALL)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_push_profile_updated_changed_fields() {
+        let mut fxa = FirefoxAccount::with_config(crate::internal::Config::stable_dev(
+            "12345678",
+            "https://foo.bar",
+        ));
+        fxa.add_cached_profile("123", "test@example.com");
+        fxa.add_cached_token(
+            "profile",
+            crate::internal::oauth::AccessTokenInfo {
+                scope: "profile".to_string(),
+                token: "profiletok".to_string(),
+                key: None,
+                expires_at: u64::max_value(),
+            },
+        );
+        let mut client = MockFxAClient::new();
+        client
+            .expect_get_profile()
+            .with(always(), eq("profiletok"), eq(None))
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(Some(ResponseAndETag {
+                    response: ProfileResponse {
+                        uid: "123".to_string(),
+                        email: "test@example.com".to_string(),
+                        display_name: Some("Test".to_string()),
+                        avatar: "".to_string(),
+                        avatar_default: true,
+                    },
+                    etag: Some("new etag".to_string()),
+                }))
+            });
+        fxa.set_client(Arc::new(client));
+
+        let json = "{\"version\":1,\"command\":\"fxaccounts:profile_updated\"}";
+        let event = fxa.handle_push_message(json).unwrap();
+        match event {
+            AccountEvent::ProfileUpdated { changed_fields } => {
+                assert_eq!(changed_fields, vec![ProfileField::DisplayName])
+            }
+            _ => unreachable!(),
+        }
+        let cached = fxa.state.last_seen_profile().unwrap();
+        assert_eq!(cached.response.display_name.as_deref(), Some("Test"));
     }
 
     #[test]
@@ -0,0 +1,479 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Account recovery keys.
+//!
+//! A recovery key lets the user keep their data when they reset a forgotten password. The
+//! server stores kB encrypted with a key derived from the recovery key, which only the user
+//! has. We never see kB when signing in, so creating a recovery key needs the password: we
+//! reauthenticate the session to get a `keyFetchToken`, and unwrap kB from `/account/keys` as
+//! described in the onepw protocol.
+
+use super::{
+    http_client::{derive_hkdf_sha256_key, kw},
+    util::Xorable,
+    FirefoxAccount,
+};
+use crate::{Error, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jwcrypto::{EncryptionAlgorithm, EncryptionParameters, Jwk, JwkKeyParameters};
+use rc_crypto::{digest, hkdf, hmac, pbkdf2, rand};
+use serde_derive::*;
+
+/// 20 random bytes, which is 32 characters of base32.
+const RECOVERY_KEY_LENGTH: usize = 20;
+const RECOVERY_KEY_ID_LENGTH: usize = 16;
+const KEY_LENGTH: usize = 32;
+const QUICK_STRETCH_ROUNDS: u32 = 1000;
+/// Crockford's base32 alphabet, which leaves out letters that are easily confused with digits.
+const BASE32_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// What the server stores for a recovery key, encrypted.
+#[derive(Serialize, Deserialize)]
+struct RecoveryData {
+    #[serde(rename = "kB")]
+    k_b: String,
+}
+
+impl FirefoxAccount {
+    /// Create a new recovery key, replacing any the account already has, and return it
+    /// formatted for the user to write down.
+    pub fn create_recovery_key(&mut self, password: &str) -> Result<String> {
+        let session_token = self.get_session_token()?;
+        let profile = self.get_profile(false)?;
+        let k_b = self.fetch_k_b(&session_token, &profile.email, password)?;
+
+        let mut recovery_key = [0u8; RECOVERY_KEY_LENGTH];
+        rand::fill(&mut recovery_key)?;
+        let uid = hex::decode(&profile.uid)?;
+        let (recovery_key_id, encryption_key) = derive_recovery_keys(&recovery_key, &uid)?;
+        let recovery_data = jwcrypto::encrypt_to_jwe(
+            &serde_json::to_vec(&RecoveryData {
+                k_b: hex::encode(k_b),
+            })?,
+            EncryptionParameters::Direct {
+                enc: EncryptionAlgorithm::A256GCM,
+                jwk: &direct_jwk(&encryption_key),
+            },
+        )?;
+        self.client.create_recovery_key(
+            self.state.config(),
+            &session_token,
+            &recovery_key_id,
+            &recovery_data,
+        )?;
+        Ok(format_recovery_key(&recovery_key))
+    }
+
+    /// Whether the account has a recovery key.
+    pub fn get_recovery_key_status(&self) -> Result<bool> {
+        let session_token = self.get_session_token()?;
+        Ok(self
+            .client
+            .check_recovery_key_exists(self.state.config(), &session_token)?
+            .exists)
+    }
+
+    pub fn delete_recovery_key(&self) -> Result<()> {
+        let session_token = self.get_session_token()?;
+        self.client
+            .destroy_recovery_key(self.state.config(), &session_token)
+    }
+
+    fn fetch_k_b(&self, session_token: &str, email: &str, password: &str) -> Result<Vec<u8>> {
+        // The password is stretched with the email the account was created with, which may
+        // not be its current one. If so, the server tells us the original email to retry with.
+        let (key_fetch_token, unwrap_b_key) =
+            match self.reauth_with_password(session_token, email, password) {
+                Err(Error::IncorrectEmailCase(original_email)) => {
+                    self.reauth_with_password(session_token, &original_email, password)?
+                }
+                result => result?,
+            };
+        let bundle = self
+            .client
+            .get_account_keys(self.state.config(), &key_fetch_token)?
+            .bundle;
+        let key_request_key =
+            &derive_hkdf_sha256_key(&hex::decode(key_fetch_token)?, &kw("keyFetchToken"), 3)?
+                [2 * KEY_LENGTH..];
+        let wrap_k_b = unbundle_wrap_k_b(key_request_key, &hex::decode(bundle)?)?;
+        wrap_k_b.xored_with(&unwrap_b_key)
+    }
+
+    /// Reauthenticate the session with the password, returning a `keyFetchToken` and the key
+    /// which unwraps kB.
+    fn reauth_with_password(
+        &self,
+        session_token: &str,
+        email: &str,
+        password: &str,
+    ) -> Result<(String, Vec<u8>)> {
+        let stretched = quick_stretch_password(email, password)?;
+        let auth_pw = derive_hkdf_sha256_key(&stretched, &kw("authPW"), 1)?;
+        let unwrap_b_key = derive_hkdf_sha256_key(&stretched, &kw("unwrapBkey"), 1)?;
+        let key_fetch_token = self
+            .client
+            .reauth_session_token(
+                self.state.config(),
+                session_token,
+                email,
+                &hex::encode(auth_pw),
+            )?
+            .key_fetch_token
+            .ok_or(Error::ApiClientError(
+                "Missing keyFetchToken in reauth response",
+            ))?;
+        Ok((key_fetch_token, unwrap_b_key))
+    }
+}
+
+fn quick_stretch_password(email: &str, password: &str) -> Result<Vec<u8>> {
+    let mut salt = kw("quickStretch");
+    salt.push(b':');
+    salt.extend_from_slice(email.as_bytes());
+    let mut out = vec![0u8; KEY_LENGTH];
+    pbkdf2::derive(
+        password.as_bytes(),
+        &salt,
+        QUICK_STRETCH_ROUNDS,
+        pbkdf2::HashAlgorithm::SHA256,
+        &mut out,
+    )?;
+    Ok(out)
+}
+
+/// The `/account/keys` bundle is kA and wrap(kB), XORed with a key derived from the
+/// `keyFetchToken` and followed by an HMAC.
+fn unbundle_wrap_k_b(key_request_key: &[u8], bundle: &[u8]) -> Result<Vec<u8>> {
+    if bundle.len() != 3 * KEY_LENGTH {
+        return Err(Error::ApiClientError(
+            "Unexpected account keys bundle length",
+        ));
+    }
+    let keys = derive_hkdf_sha256_key(key_request_key, &kw("account/keys"), 3)?;
+    let (resp_hmac_key, resp_xor_key) = keys.split_at(KEY_LENGTH);
+    let (ciphertext, mac) = bundle.split_at(2 * KEY_LENGTH);
+    hmac::verify(
+        &hmac::VerificationKey::new(&digest::SHA256, resp_hmac_key),
+        ciphertext,
+        mac,
+    )?;
+    let plaintext = ciphertext.xored_with(resp_xor_key)?;
+    Ok(plaintext[KEY_LENGTH..].to_vec())
+}
+
+/// Returns the recovery key's id, which the server uses to look it up, and the key which
+/// encrypts the recovery data.
+fn derive_recovery_keys(recovery_key: &[u8], uid: &[u8]) -> Result<(String, Vec<u8>)> {
+    let salt = hmac::SigningKey::new(&digest::SHA256, uid);
+    let mut recovery_key_id = vec![0u8; RECOVERY_KEY_ID_LENGTH];
+    hkdf::extract_and_expand(
+        &salt,
+        recovery_key,
+        b"fxa recovery fingerprint",
+        &mut recovery_key_id,
+    )?;
+    let mut encryption_key = vec![0u8; KEY_LENGTH];
+    hkdf::extract_and_expand(
+        &salt,
+        recovery_key,
+        b"fxa recovery encrypt key",
+        &mut encryption_key,
+    )?;
+    Ok((hex::encode(recovery_key_id), encryption_key))
+}
+
+fn direct_jwk(key: &[u8]) -> Jwk {
+    Jwk {
+        kid: None,
+        key_parameters: JwkKeyParameters::Direct {
+            k: URL_SAFE_NO_PAD.encode(key),
+        },
+    }
+}
+
+/// Base32-encode the recovery key, in groups of four characters.
+fn format_recovery_key(recovery_key: &[u8]) -> String {
+    let mut chars = Vec::with_capacity(recovery_key.len() * 8 / 5 + 1);
+    let (mut buffer, mut bits) = (0u16, 0);
+    for &byte in recovery_key {
+        buffer = (buffer << 8) | u16::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            chars.push(BASE32_ALPHABET[usize::from((buffer >> bits) & 0x1f)]);
+        }
+    }
+    if bits > 0 {
+        chars.push(BASE32_ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)]);
+    }
+    chars
+        .chunks(4)
+        .map(|group| std::str::from_utf8(group).expect("base32 is ASCII"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::{
+        config::Config,
+        http_client::{
+            AccountKeysResponse, MockFxAClient, ProfileResponse, ReauthResponse,
+            RecoveryKeyExistsResponse,
+        },
+        util, CachedResponse,
+    };
+    use jwcrypto::DecryptionParameters;
+    use mockall::predicate::{always, eq};
+    use std::sync::{Arc, Mutex};
+
+    const UID: &str = "0123456789abcdef0123456789abcdef";
+    const EMAIL: &str = "andr\u{e9}@example.org";
+    const PASSWORD: &str = "p\u{e4}ssw\u{f6}rd";
+    const KEY_FETCH_TOKEN: &str =
+        "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f";
+
+    fn parse_recovery_key(formatted: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let (mut buffer, mut bits) = (0u16, 0);
+        for c in formatted.bytes().filter(|&c| c != b' ') {
+            let value = BASE32_ALPHABET.iter().position(|&a| a == c).unwrap() as u16;
+            buffer = (buffer << 5) | value;
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                bytes.push((buffer >> bits) as u8);
+            }
+        }
+        bytes
+    }
+
+    // Builds the bundle the server would send for these keys, so we can check that we
+    // unwrap kB from it.
+    fn make_bundle(k_a: &[u8], wrap_k_b: &[u8]) -> String {
+        let key_request_key = &derive_hkdf_sha256_key(
+            &hex::decode(KEY_FETCH_TOKEN).unwrap(),
+            &kw("keyFetchToken"),
+            3,
+        )
+        .unwrap()[2 * KEY_LENGTH..];
+        let keys = derive_hkdf_sha256_key(key_request_key, &kw("account/keys"), 3).unwrap();
+        let (resp_hmac_key, resp_xor_key) = keys.split_at(KEY_LENGTH);
+        let mut ciphertext = [k_a, wrap_k_b].concat().xored_with(resp_xor_key).unwrap();
+        let mac = hmac::sign(
+            &hmac::SigningKey::new(&digest::SHA256, resp_hmac_key),
+            &ciphertext,
+        )
+        .unwrap();
+        ciphertext.extend_from_slice(mac.as_ref());
+        hex::encode(ciphertext)
+    }
+
+    fn signed_in_account() -> FirefoxAccount {
+        let mut fxa =
+            FirefoxAccount::with_config(Config::stable_dev("12345678", "https://foo.bar"));
+        fxa.set_session_token("session");
+        fxa.state.set_last_seen_profile(CachedResponse {
+            response: ProfileResponse {
+                uid: UID.into(),
+                email: EMAIL.into(),
+                display_name: None,
+                avatar: "".into(),
+                avatar_default: true,
+            },
+            cached_at: util::now(),
+            etag: "".into(),
+        });
+        fxa
+    }
+
+    #[test]
+    fn test_quick_stretch_password() {
+        // From the onepw protocol's test vectors.
+        let stretched = quick_stretch_password(EMAIL, PASSWORD).unwrap();
+        assert_eq!(
+            hex::encode(&stretched),
+            "e4e8889bd8bd61ad6de6b95c059d56e7b50dacdaf62bd84644af7e2add84345d"
+        );
+        assert_eq!(
+            hex::encode(derive_hkdf_sha256_key(&stretched, &kw("authPW"), 1).unwrap()),
+            "247b675ffb4c46310bc87e26d712153abe5e1c90ef00a4784594f97ef54f2375"
+        );
+        assert_eq!(
+            hex::encode(derive_hkdf_sha256_key(&stretched, &kw("unwrapBkey"), 1).unwrap()),
+            "de6a2648b78284fcb9ffa81ba95803309cfba7af583c01a8a1a63e567234dd28"
+        );
+    }
+
+    #[test]
+    fn test_format_recovery_key() {
+        let formatted = format_recovery_key(&[0xff; RECOVERY_KEY_LENGTH]);
+        assert_eq!(formatted, "ZZZZ ZZZZ ZZZZ ZZZZ ZZZZ ZZZZ ZZZZ ZZZZ");
+        let key: Vec<u8> = (0..RECOVERY_KEY_LENGTH as u8).collect();
+        assert_eq!(parse_recovery_key(&format_recovery_key(&key)), key);
+    }
+
+    #[test]
+    fn test_unbundle_rejects_bad_hmac() {
+        let key_request_key = [0u8; KEY_LENGTH];
+        assert!(unbundle_wrap_k_b(&key_request_key, &[0u8; 3 * KEY_LENGTH]).is_err());
+        assert!(unbundle_wrap_k_b(&key_request_key, &[0u8; 2 * KEY_LENGTH]).is_err());
+    }
+
+    #[test]
+    fn test_create_recovery_key() {
+        let mut fxa = signed_in_account();
+        let stretched = quick_stretch_password(EMAIL, PASSWORD).unwrap();
+        let auth_pw = hex::encode(derive_hkdf_sha256_key(&stretched, &kw("authPW"), 1).unwrap());
+        let unwrap_b_key = derive_hkdf_sha256_key(&stretched, &kw("unwrapBkey"), 1).unwrap();
+        let k_b = [0x42u8; KEY_LENGTH];
+        let bundle = make_bundle(&[0x11; KEY_LENGTH], &k_b.xored_with(&unwrap_b_key).unwrap());
+
+        let mut client = MockFxAClient::new();
+        client
+            .expect_reauth_session_token()
+            .with(always(), eq("session"), eq(EMAIL), eq(auth_pw))
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(ReauthResponse {
+                    key_fetch_token: Some(KEY_FETCH_TOKEN.into()),
+                })
+            });
+        client
+            .expect_get_account_keys()
+            .with(always(), eq(KEY_FETCH_TOKEN))
+            .times(1)
+            .returning(move |_, _| {
+                Ok(AccountKeysResponse {
+                    bundle: bundle.clone(),
+                })
+            });
+        let uploaded = Arc::new(Mutex::new(None));
+        let uploaded_clone = uploaded.clone();
+        client
+            .expect_create_recovery_key()
+            .with(always(), eq("session"), always(), always())
+            .times(1)
+            .returning(move |_, _, id, data| {
+                *uploaded_clone.lock().unwrap() = Some((id.to_string(), data.to_string()));
+                Ok(())
+            });
+        fxa.set_client(Arc::new(client));
+
+        let recovery_key = fxa.create_recovery_key(PASSWORD).unwrap();
+        assert_eq!(recovery_key.len(), 39);
+        let (recovery_key_id, recovery_data) = uploaded.lock().unwrap().take().unwrap();
+        let (expected_id, encryption_key) = derive_recovery_keys(
+            &parse_recovery_key(&recovery_key),
+            &hex::decode(UID).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(recovery_key_id, expected_id);
+        // Someone with the recovery key can get kB back from what we uploaded.
+        let decrypted = jwcrypto::decrypt_jwe(
+            &recovery_data,
+            DecryptionParameters::Direct {
+                jwk: direct_jwk(&encryption_key),
+            },
+        )
+        .unwrap();
+        let data: RecoveryData = serde_json::from_str(&decrypted).unwrap();
+        assert_eq!(data.k_b, hex::encode(k_b));
+    }
+
+    #[test]
+    fn test_create_recovery_key_after_email_change() {
+        const ORIGINAL_EMAIL: &str = "original@example.org";
+        let mut fxa = signed_in_account();
+        let stretched = quick_stretch_password(ORIGINAL_EMAIL, PASSWORD).unwrap();
+        let auth_pw = hex::encode(derive_hkdf_sha256_key(&stretched, &kw("authPW"), 1).unwrap());
+        let unwrap_b_key = derive_hkdf_sha256_key(&stretched, &kw("unwrapBkey"), 1).unwrap();
+        let k_b = [0x42u8; KEY_LENGTH];
+        let bundle = make_bundle(&[0x11; KEY_LENGTH], &k_b.xored_with(&unwrap_b_key).unwrap());
+
+        let mut client = MockFxAClient::new();
+        // The profile has the account's current email, but the password was stretched
+        // with the one it was created with.
+        client
+            .expect_reauth_session_token()
+            .with(always(), eq("session"), eq(EMAIL), always())
+            .times(1)
+            .returning(|_, _, _, _| Err(Error::IncorrectEmailCase(ORIGINAL_EMAIL.into())));
+        client
+            .expect_reauth_session_token()
+            .with(always(), eq("session"), eq(ORIGINAL_EMAIL), eq(auth_pw))
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(ReauthResponse {
+                    key_fetch_token: Some(KEY_FETCH_TOKEN.into()),
+                })
+            });
+        client
+            .expect_get_account_keys()
+            .with(always(), eq(KEY_FETCH_TOKEN))
+            .times(1)
+            .returning(move |_, _| {
+                Ok(AccountKeysResponse {
+                    bundle: bundle.clone(),
+                })
+            });
+        let uploaded = Arc::new(Mutex::new(None));
+        let uploaded_clone = uploaded.clone();
+        client
+            .expect_create_recovery_key()
+            .times(1)
+            .returning(move |_, _, _, data| {
+                *uploaded_clone.lock().unwrap() = Some(data.to_string());
+                Ok(())
+            });
+        fxa.set_client(Arc::new(client));
+
+        let recovery_key = fxa.create_recovery_key(PASSWORD).unwrap();
+        let (_, encryption_key) = derive_recovery_keys(
+            &parse_recovery_key(&recovery_key),
+            &hex::decode(UID).unwrap(),
+        )
+        .unwrap();
+        let decrypted = jwcrypto::decrypt_jwe(
+            &uploaded.lock().unwrap().take().unwrap(),
+            DecryptionParameters::Direct {
+                jwk: direct_jwk(&encryption_key),
+            },
+        )
+        .unwrap();
+        let data: RecoveryData = serde_json::from_str(&decrypted).unwrap();
+        assert_eq!(data.k_b, hex::encode(k_b));
+    }
+
+    #[test]
+    fn test_recovery_key_status_and_delete() {
+        let mut fxa = signed_in_account();
+        let mut client = MockFxAClient::new();
+        client
+            .expect_check_recovery_key_exists()
+            .with(always(), eq("session"))
+            .times(1)
+            .returning(|_, _| Ok(RecoveryKeyExistsResponse { exists: true }));
+        client
+            .expect_destroy_recovery_key()
+            .with(always(), eq("session"))
+            .times(1)
+            .returning(|_, _| Ok(()));
+        fxa.set_client(Arc::new(client));
+        assert!(fxa.get_recovery_key_status().unwrap());
+        fxa.delete_recovery_key().unwrap();
+    }
+
+    #[test]
+    fn test_recovery_key_needs_session_token() {
+        let fxa = FirefoxAccount::with_config(Config::stable_dev("12345678", "https://foo.bar"));
+        assert!(matches!(
+            fxa.get_recovery_key_status(),
+            Err(Error::NoSessionToken)
+        ));
+    }
+}
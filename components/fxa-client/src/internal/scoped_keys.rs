@@ -4,10 +4,10 @@
 
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use jwcrypto::{self, DecryptionParameters, Jwk};
-use rc_crypto::{agreement, agreement::EphemeralKeyPair};
+use rc_crypto::{agreement, agreement::EphemeralKeyPair, digest};
 
-use super::FirefoxAccount;
-use crate::{Error, Result, ScopedKey};
+use super::{state_persistence::SeenScopedKey, FirefoxAccount};
+use crate::{AccountEvent, Error, Result, ScopedKey};
 
 impl FirefoxAccount {
     pub(crate) fn get_scoped_key(&self, scope: &str) -> Result<&ScopedKey> {
@@ -15,14 +15,106 @@ impl FirefoxAccount {
             .get_scoped_key(scope)
             .ok_or_else(|| Error::NoScopedKey(scope.to_string()))
     }
+
+    /// Compare the scoped keys we just got from the server for the account `uid` with the ones
+    /// we've seen before for that account, and remember the new ones.
+    ///
+    /// Keys we already have are kept as they are. A key that's different from the one we saw
+    /// before is expected if it was rotated since, because the user reset their password, but
+    /// otherwise the server shouldn't have changed it, so we queue an
+    /// [`AccountEvent::KeysMismatch`] for the application.
+    ///
+    /// If the user signed in to a different account, the keys we saw for the previous one are
+    /// forgotten. If we don't know which account the keys are for, we can't compare them, so
+    /// they're taken as they are.
+    pub(crate) fn check_scoped_keys(
+        &mut self,
+        uid: Option<&str>,
+        scoped_keys: Vec<(String, ScopedKey)>,
+    ) -> Result<Vec<(String, ScopedKey)>> {
+        let Some(uid) = uid else {
+            if !scoped_keys.is_empty() {
+                warn!("Unknown account uid, not checking the scoped keys");
+            }
+            return Ok(scoped_keys);
+        };
+        if self.state.seen_scoped_keys_uid() != Some(uid) {
+            self.state.reset_seen_scoped_keys(uid);
+        }
+        let mut mismatched_scopes = Vec::new();
+        let mut checked_keys = Vec::with_capacity(scoped_keys.len());
+        for (scope, key) in scoped_keys {
+            let fingerprint = key.fingerprint()?;
+            if let Some(existing) = self.state.get_scoped_key(&scope) {
+                if existing.fingerprint()? == fingerprint {
+                    checked_keys.push((scope, existing.clone()));
+                    continue;
+                }
+            }
+            if let Some(seen) = self.state.seen_scoped_key(&scope) {
+                let rotated = match (key.rotated_at(), seen.rotated_at()) {
+                    (Some(new), Some(old)) => new > old,
+                    _ => false,
+                };
+                if seen.fingerprint != fingerprint && !rotated {
+                    warn!("Scoped key for {} changed unexpectedly", scope);
+                    mismatched_scopes.push(scope.clone());
+                }
+            }
+            self.state.set_seen_scoped_key(
+                scope.clone(),
+                SeenScopedKey {
+                    kid: key.kid.clone(),
+                    fingerprint,
+                },
+            );
+            checked_keys.push((scope, key));
+        }
+        if !mismatched_scopes.is_empty() {
+            mismatched_scopes.sort();
+            self.pending_account_events
+                .push(AccountEvent::KeysMismatch {
+                    scopes: mismatched_scopes,
+                });
+        }
+        Ok(checked_keys)
+    }
 }
 
 impl ScopedKey {
     pub fn key_bytes(&self) -> Result<Vec<u8>> {
         Ok(URL_SAFE_NO_PAD.decode(&self.k)?)
     }
+
+    /// A truncated SHA-256 hash of the key, so we can tell whether the server sent the same
+    /// key again without keeping the key itself.
+    pub(crate) fn fingerprint(&self) -> Result<String> {
+        let hash = digest::digest(&digest::SHA256, &self.key_bytes()?)?;
+        Ok(hex::encode(&hash.as_ref()[..16]))
+    }
+
+    /// The time the key was last rotated, which is the first part of its `kid`. Rotating kB,
+    /// which happens when the user resets their password, changes every scoped key.
+    pub(crate) fn rotated_at(&self) -> Option<u64> {
+        rotated_at(&self.kid)
+    }
 }
 
+impl SeenScopedKey {
+    pub(crate) fn rotated_at(&self) -> Option<u64> {
+        rotated_at(&self.kid)
+    }
+}
+
+fn rotated_at(kid: &str) -> Option<u64> {
+    kid.split_once('-')?.0.parse().ok()
+}
+
+/// The old sync key, with the kid `1526414944666-zgTjf5oXmPmBjxwXWFsDWg`, encrypted for
+/// [`ScopedKeysFlow::with_test_key`].
+#[cfg(test)]
+pub(crate) const TEST_KEYS_JWE: &str = "eyJhbGciOiJFQ0RILUVTIiwia2lkIjoiNFBKTTl5dGVGeUtsb21ILWd2UUtyWGZ0a0N3ak9HNHRfTmpYVXhLM1VqSSIsImVwayI6eyJrdHkiOiJFQyIsImNydiI6IlAtMjU2IiwieCI6IlB3eG9Na1RjSVZ2TFlKWU4wM2R0Y3o2TEJrR0FHaU1hZWlNQ3lTZXEzb2MiLCJ5IjoiLUYtTllRRDZwNUdSQ2ZoYm1hN3NvNkhxdExhVlNub012S0pFcjFBeWlaSSJ9LCJlbmMiOiJBMjU2R0NNIn0..b9FPhjjpmAmo_rP8.ur9jTry21Y2trvtcanSFmAtiRfF6s6qqyg6ruRal7PCwa7PxDzAuMN6DZW5BiK8UREOH08-FyRcIgdDOm5Zq8KwVAn56PGfcH30aNDGQNkA_mpfjx5Tj2z8kI6ryLWew4PGZb-PsL1g-_eyXhktq7dAhetjNYttKwSREWQFokv7N3nJGpukBqnwL1ost-MjDXlINZLVJKAiMHDcu-q7Epitwid2c2JVGOSCJjbZ4-zbxVmZ4o9xhFb2lbvdiaMygH6bPlrjEK99uT6XKtaIZmyDwftbD6G3x4On-CqA2TNL6ILRaJMtmyX--ctL0IrngUIHg_F0Wz94v.zBD8NACkUcZTPLH0tceGnA";
+
 impl std::fmt::Debug for ScopedKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ScopedKey")
@@ -51,6 +143,25 @@ impl ScopedKeysFlow {
         Ok(Self { key_pair })
     }
 
+    /// A flow with a fixed key pair, which [`TEST_KEYS_JWE`] was encrypted with.
+    #[cfg(test)]
+    pub fn with_test_key() -> Self {
+        let x = URL_SAFE_NO_PAD
+            .decode("ARvGIPJ5eIFdp6YTM-INVDqwfun2R9FfCUvXbH7QCIU")
+            .unwrap();
+        let y = URL_SAFE_NO_PAD
+            .decode("hk8gP0Po8nBh-WSiTsvsyesC5c1L6fGOEVuX8FHsvTs")
+            .unwrap();
+        let d = URL_SAFE_NO_PAD
+            .decode("UayD4kn_4QHvLvLLSSaANfDUp9AcQndQu_TohQKoyn8")
+            .unwrap();
+        let ec_key =
+            agreement::EcKey::from_coordinates(agreement::Curve::P256, &d, &x, &y).unwrap();
+        let private_key = agreement::PrivateKey::<agreement::Static>::import(&ec_key).unwrap();
+        let key_pair = agreement::KeyPair::from(private_key).unwrap();
+        Self::from_static_key_pair(key_pair).unwrap()
+    }
+
     pub fn get_public_key_jwk(&self) -> Result<Jwk> {
         Ok(jwcrypto::ec::extract_pub_key_jwk(&self.key_pair)?)
     }
@@ -66,25 +177,88 @@ impl ScopedKeysFlow {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::internal::{config::Config, scopes};
     use jwcrypto::JwkKeyParameters;
-    use rc_crypto::agreement::{KeyPair, PrivateKey};
+
+    fn sync_key(kid: &str, k: &str) -> (String, ScopedKey) {
+        (
+            scopes::OLD_SYNC.to_string(),
+            ScopedKey {
+                kty: "oct".to_string(),
+                scope: scopes::OLD_SYNC.to_string(),
+                k: k.to_string(),
+                kid: kid.to_string(),
+            },
+        )
+    }
 
     #[test]
-    fn test_flow() {
-        let x = URL_SAFE_NO_PAD
-            .decode("ARvGIPJ5eIFdp6YTM-INVDqwfun2R9FfCUvXbH7QCIU")
+    fn test_check_scoped_keys() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+
+        // The first key we see for a scope is taken as it is.
+        let keys = fxa
+            .check_scoped_keys(Some("uid"), vec![sync_key("1526414944666-a", "AAAA")])
             .unwrap();
-        let y = URL_SAFE_NO_PAD
-            .decode("hk8gP0Po8nBh-WSiTsvsyesC5c1L6fGOEVuX8FHsvTs")
+        assert_eq!(keys.len(), 1);
+        assert!(fxa.take_pending_account_events().is_empty());
+
+        // Signing in again, and getting the same key back, is fine.
+        fxa.state.on_begin_oauth();
+        fxa.check_scoped_keys(Some("uid"), vec![sync_key("1526414944666-a", "AAAA")])
             .unwrap();
-        let d = URL_SAFE_NO_PAD
-            .decode("UayD4kn_4QHvLvLLSSaANfDUp9AcQndQu_TohQKoyn8")
+        assert!(fxa.take_pending_account_events().is_empty());
+
+        // So is getting a key that's been rotated since.
+        fxa.check_scoped_keys(Some("uid"), vec![sync_key("1526414955555-b", "BBBB")])
             .unwrap();
-        let ec_key =
-            agreement::EcKey::from_coordinates(agreement::Curve::P256, &d, &x, &y).unwrap();
-        let private_key = PrivateKey::<rc_crypto::agreement::Static>::import(&ec_key).unwrap();
-        let key_pair = KeyPair::from(private_key).unwrap();
-        let flow = ScopedKeysFlow::from_static_key_pair(key_pair).unwrap();
+        assert!(fxa.take_pending_account_events().is_empty());
+
+        // But a different key that wasn't rotated is a mismatch.
+        let keys = fxa
+            .check_scoped_keys(Some("uid"), vec![sync_key("1526414955555-c", "CCCC")])
+            .unwrap();
+        assert_eq!(keys[0].1.k, "CCCC");
+        let events = fxa.take_pending_account_events();
+        assert!(matches!(
+            events.as_slice(),
+            [AccountEvent::KeysMismatch { scopes }] if scopes == &[scopes::OLD_SYNC]
+        ));
+        assert!(fxa.take_pending_account_events().is_empty());
+
+        // The new key is what we compare with from now on.
+        fxa.check_scoped_keys(Some("uid"), vec![sync_key("1526414955555-c", "CCCC")])
+            .unwrap();
+        assert!(fxa.take_pending_account_events().is_empty());
+
+        // A different account has different keys.
+        fxa.check_scoped_keys(Some("other_uid"), vec![sync_key("1526414944666-d", "DDDD")])
+            .unwrap();
+        assert!(fxa.take_pending_account_events().is_empty());
+        // And we've forgotten the keys of the first one.
+        fxa.check_scoped_keys(Some("uid"), vec![sync_key("1526414944666-e", "EEEE")])
+            .unwrap();
+        assert!(fxa.take_pending_account_events().is_empty());
+
+        // Without knowing the account, we can't tell whether the keys changed.
+        fxa.check_scoped_keys(None, vec![sync_key("1526414944666-f", "FFFF")])
+            .unwrap();
+        assert!(fxa.take_pending_account_events().is_empty());
+        fxa.check_scoped_keys(Some("uid"), vec![sync_key("1526414944666-e", "EEEE")])
+            .unwrap();
+        assert!(fxa.take_pending_account_events().is_empty());
+
+        // Once the user disconnects, any key is fine.
+        fxa.state.disconnect();
+        fxa.check_scoped_keys(Some("uid"), vec![sync_key("1526414944666-g", "GGGG")])
+            .unwrap();
+        assert!(fxa.take_pending_account_events().is_empty());
+    }
+
+    #[test]
+    fn test_flow() {
+        let flow = ScopedKeysFlow::with_test_key();
         let jwk = flow.get_public_key_jwk().unwrap();
         let ec_key_params = match jwk.key_parameters {
             JwkKeyParameters::EC(ref ec_key_params) => ec_key_params,
@@ -100,8 +274,7 @@ mod tests {
             "hk8gP0Po8nBh-WSiTsvsyesC5c1L6fGOEVuX8FHsvTs"
         );
 
-        let jwe = "eyJhbGciOiJFQ0RILUVTIiwia2lkIjoiNFBKTTl5dGVGeUtsb21ILWd2UUtyWGZ0a0N3ak9HNHRfTmpYVXhLM1VqSSIsImVwayI6eyJrdHkiOiJFQyIsImNydiI6IlAtMjU2IiwieCI6IlB3eG9Na1RjSVZ2TFlKWU4wM2R0Y3o2TEJrR0FHaU1hZWlNQ3lTZXEzb2MiLCJ5IjoiLUYtTllRRDZwNUdSQ2ZoYm1hN3NvNkhxdExhVlNub012S0pFcjFBeWlaSSJ9LCJlbmMiOiJBMjU2R0NNIn0..b9FPhjjpmAmo_rP8.ur9jTry21Y2trvtcanSFmAtiRfF6s6qqyg6ruRal7PCwa7PxDzAuMN6DZW5BiK8UREOH08-FyRcIgdDOm5Zq8KwVAn56PGfcH30aNDGQNkA_mpfjx5Tj2z8kI6ryLWew4PGZb-PsL1g-_eyXhktq7dAhetjNYttKwSREWQFokv7N3nJGpukBqnwL1ost-MjDXlINZLVJKAiMHDcu-q7Epitwid2c2JVGOSCJjbZ4-zbxVmZ4o9xhFb2lbvdiaMygH6bPlrjEK99uT6XKtaIZmyDwftbD6G3x4On-CqA2TNL6ILRaJMtmyX--ctL0IrngUIHg_F0Wz94v.zBD8NACkUcZTPLH0tceGnA";
-        let keys = flow.decrypt_keys_jwe(jwe).unwrap();
+        let keys = flow.decrypt_keys_jwe(TEST_KEYS_JWE).unwrap();
         assert_eq!(keys, "{\"https://identity.mozilla.com/apps/oldsync\":{\"kty\":\"oct\",\"scope\":\"https://identity.mozilla.com/apps/oldsync\",\"k\":\"8ek1VNk4sjrNP0DhGC4crzQtwmpoR64zHuFMHb4Tw-exR70Z2SSIfMSrJDTLEZid9lD05-hbA3n2Q4Esjlu1tA\",\"kid\":\"1526414944666-zgTjf5oXmPmBjxwXWFsDWg\"}}");
     }
 }
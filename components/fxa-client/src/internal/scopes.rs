@@ -2,5 +2,53 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use crate::{Error, Result};
+use url::Url;
+
 pub const PROFILE: &str = "profile";
 pub const OLD_SYNC: &str = "https://identity.mozilla.com/apps/oldsync";
+pub const SESSION: &str = "https://identity.mozilla.com/tokens/session";
+
+/// Check that `scope` is a single, well-formed OAuth scope, so that typos are caught before
+/// they're sent to the server.
+///
+/// Scopes are a run of printable ASCII characters other than spaces, quotes and backslashes
+/// (RFC 6749 section 3.3). FxA's URL scopes must also be https URLs.
+pub fn check(scope: &str) -> Result<()> {
+    let valid_chars = !scope.is_empty()
+        && scope
+            .bytes()
+            .all(|b| matches!(b, 0x21 | 0x23..=0x5B | 0x5D..=0x7E));
+    let valid_url =
+        !scope.contains("://") || Url::parse(scope).map_or(false, |url| url.scheme() == "https");
+    if valid_chars && valid_url {
+        Ok(())
+    } else {
+        Err(Error::InvalidScope(scope.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        for scope in [PROFILE, OLD_SYNC, SESSION, "profile:email", "clients:write"] {
+            assert!(check(scope).is_ok(), "{scope} should be valid");
+        }
+        for scope in [
+            "",
+            "profile email",
+            "profile\n",
+            "\"profile\"",
+            "http://identity.mozilla.com/apps/oldsync",
+            "https:// identity.mozilla.com",
+        ] {
+            assert!(
+                matches!(check(scope), Err(Error::InvalidScope(_))),
+                "{scope:?} should be invalid"
+            );
+        }
+    }
+}
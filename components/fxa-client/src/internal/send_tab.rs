@@ -12,7 +12,8 @@ use super::{
     http_client::GetDeviceResponse,
     scopes, telemetry, FirefoxAccount,
 };
-use crate::{Error, Result};
+use crate::{Error, Result, SendTabFailure, SendTabResult};
+use sync_guid::Guid;
 
 impl FirefoxAccount {
     pub(crate) fn load_or_generate_send_tab_keys(&mut self) -> Result<PrivateSendTabKeys> {
@@ -58,6 +59,42 @@ impl FirefoxAccount {
         Ok(())
     }
 
+    /// Send a tab to every other device on the account which can receive
+    /// tabs. Failing to send to one device doesn't stop us sending to the
+    /// others; those failures are reported in the result instead.
+    pub fn send_tab_to_self(&mut self, title: &str, url: &str) -> Result<SendTabResult> {
+        let devices = self.get_devices(false)?;
+        let oldsync_key = self.get_scoped_key(scopes::OLD_SYNC)?.clone();
+        // All the commands share a flow ID, so that they can be tied together
+        // in telemetry.
+        let flow_id = Guid::random().to_string();
+        let mut result = SendTabResult::default();
+        for target in devices.iter().filter(|d| {
+            !d.is_current_device && d.available_commands.contains_key(send_tab::COMMAND_NAME)
+        }) {
+            let (payload, sent_telemetry) =
+                SendTabPayload::single_tab_in_flow(title, url, &flow_id);
+            let sent = encrypt_command(&oldsync_key, target, send_tab::COMMAND_NAME, &payload)
+                .and_then(|command_payload| {
+                    self.invoke_command(send_tab::COMMAND_NAME, target, &command_payload, None)
+                });
+            match sent {
+                Ok(()) => {
+                    self.telemetry.record_command_sent(sent_telemetry);
+                    result.sent_to.push(target.id.clone());
+                }
+                Err(e) => {
+                    warn!("Failed to send tab to device: {}", e);
+                    result.failures.push(SendTabFailure {
+                        device_id: target.id.clone(),
+                        reason: e.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(result)
+    }
+
     pub(crate) fn handle_send_tab_command(
         &mut self,
         sender: Option<GetDeviceResponse>,
@@ -85,7 +122,7 @@ impl FirefoxAccount {
                 // It also seems like it might be possible to recover - ie, one
                 // of the reasons is that there are key mismatches. Doesn't that
                 // mean the "other" key might work?
-                log::warn!("Could not decrypt Send Tab payload. Diagnosing then resetting the Send Tab keys.");
+                warn!("Could not decrypt Send Tab payload. Diagnosing then resetting the Send Tab keys.");
                 match self.diagnose_remote_keys(send_tab_key) {
                     Ok(_) => {
                         error_support::report_error!(
@@ -140,3 +177,132 @@ impl FirefoxAccount {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::{
+        http_client::{DeviceLocation, DeviceResponseCommon, MockFxAClient},
+        oauth::RefreshToken,
+        Config,
+    };
+    use crate::logging::capture_logs;
+    use crate::{DeviceType, ScopedKey};
+    use mockall::predicate::{always, eq};
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Arc;
+
+    fn oldsync_key() -> ScopedKey {
+        ScopedKey {
+            kty: "oct".to_string(),
+            scope: scopes::OLD_SYNC.to_string(),
+            k: "kMtwpVC0ZaYFJymPza8rXK_0CgCp3KMwRStwGfBRBDtL6hXRDVJgQFaoOQ2dimw0Bko5WVv2gNTy7RX5zFYZHg".to_string(),
+            kid: "1542236016429-Ox1FbJfFfwTe5t-xq4v2hQ".to_string(),
+        }
+    }
+
+    fn device(
+        id: &str,
+        is_current_device: bool,
+        send_tab_data: Option<String>,
+    ) -> GetDeviceResponse {
+        GetDeviceResponse {
+            common: DeviceResponseCommon {
+                id: id.to_string(),
+                display_name: id.to_string(),
+                device_type: DeviceType::Mobile,
+                push_subscription: None,
+                available_commands: send_tab_data
+                    .map(|data| HashMap::from([(send_tab::COMMAND_NAME.to_owned(), data)]))
+                    .unwrap_or_default(),
+                push_endpoint_expired: false,
+            },
+            is_current_device,
+            location: DeviceLocation {
+                city: None,
+                country: None,
+                state: None,
+                state_code: None,
+            },
+            last_access_time: None,
+        }
+    }
+
+    #[test]
+    fn test_send_tab_to_self() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.state.force_refresh_token(RefreshToken {
+            token: "refreshtok".to_string(),
+            scopes: HashSet::default(),
+        });
+        fxa.state.insert_scoped_key(scopes::OLD_SYNC, oldsync_key());
+
+        let command_data = PublicSendTabKeys::from(PrivateSendTabKeys::from_random().unwrap())
+            .as_command_data(&oldsync_key())
+            .unwrap();
+        let devices = vec![
+            device("current", true, Some(command_data.clone())),
+            device("phone", false, Some(command_data.clone())),
+            device("tablet", false, Some(command_data)),
+            device("no-send-tab", false, None),
+            device("bad-keys", false, Some("not-command-data".to_owned())),
+        ];
+
+        let mut client = MockFxAClient::new();
+        client
+            .expect_get_devices()
+            .with(always(), always())
+            .times(1)
+            .returning(move |_, _| Ok(devices.clone()));
+        client
+            .expect_invoke_command()
+            .with(
+                always(),
+                eq("refreshtok"),
+                eq(send_tab::COMMAND_NAME),
+                eq("phone"),
+                always(),
+                eq(None),
+            )
+            .times(1)
+            .returning(|_, _, _, _, _, _| Ok(()));
+        client
+            .expect_invoke_command()
+            .with(
+                always(),
+                eq("refreshtok"),
+                eq(send_tab::COMMAND_NAME),
+                eq("tablet"),
+                always(),
+                eq(None),
+            )
+            .times(1)
+            .returning(|_, _, _, _, _, _| {
+                Err(Error::RemoteError {
+                    code: 500,
+                    errno: 101,
+                    error: "Did not work!".to_owned(),
+                    message: "Did not work for foo@example.com!".to_owned(),
+                    info: "Did not work!".to_owned(),
+                })
+            });
+        fxa.set_client(Arc::new(client));
+
+        let (result, logs) =
+            capture_logs(|| fxa.send_tab_to_self("Example", "https://example.com/"));
+        let result = result.unwrap();
+        assert_eq!(result.sent_to, vec!["phone".to_string()]);
+        // The failure is logged, but without the account's email.
+        assert!(logs.iter().any(|log| log.contains("Failed to send tab")));
+        assert!(logs.iter().all(|log| !log.contains("foo@example.com")));
+        assert_eq!(
+            result
+                .failures
+                .iter()
+                .map(|f| f.device_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["tablet", "bad-keys"]
+        );
+    }
+}
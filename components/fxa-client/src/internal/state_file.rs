@@ -0,0 +1,404 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Persisting the state to a file that several processes share.
+//!
+//! The file holds the serialized state along with a generation counter, which is bumped on
+//! every write. Each account remembers the generation and state it last read or wrote, so when
+//! it comes to write again it can tell whether another process wrote in the meantime, and
+//! which fields each of them changed.
+//!
+//! Reads and writes hold a lock, which is a `.lock` file next to the state file. We don't use
+//! OS file locks, so that this works the same way everywhere. A lock file left behind by a
+//! process which crashed is ignored once it's old enough.
+
+use super::{state_persistence::state_from_json, FirefoxAccount};
+use crate::{Error, Result, StateConflictPolicy};
+use serde_derive::*;
+use serde_json::{Map, Value};
+use std::{
+    fs::{self, OpenOptions},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How long to wait for another process to release the lock.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait between attempts to take the lock.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+/// A lock file older than this was left behind by a process which crashed while holding it,
+/// since no process holds the lock for more than a read or a write.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize)]
+struct StateFile {
+    generation: u64,
+    state: Value,
+}
+
+/// The generation and state this account last read from or wrote to a state file.
+pub(crate) struct StateFileBase {
+    path: PathBuf,
+    generation: u64,
+    state: Value,
+}
+
+impl FirefoxAccount {
+    pub fn from_json_with_lock(path: &str) -> Result<Self> {
+        let path = PathBuf::from(path);
+        let file = {
+            let _lock = StateFileLock::acquire(&path)?;
+            read_state_file(&path)?
+        }
+        .ok_or(Error::IllegalState("The state file doesn't exist"))?;
+        let mut account = Self::from_json(&file.state.to_string())?;
+        account.state_file_base = Some(StateFileBase {
+            path,
+            generation: file.generation,
+            state: file.state,
+        });
+        Ok(account)
+    }
+
+    pub fn save_with_lock(&mut self, path: &str, policy: StateConflictPolicy) -> Result<()> {
+        let path = PathBuf::from(path);
+        let _lock = StateFileLock::acquire(&path)?;
+        let ours: Value = serde_json::from_str(&self.to_json()?)?;
+        let (base_generation, base_state) = match &self.state_file_base {
+            Some(base) if base.path == path => (base.generation, Some(&base.state)),
+            _ => (0, None),
+        };
+        let (generation, state) = match read_state_file(&path)? {
+            Some(theirs) if theirs.generation != base_generation => match policy {
+                StateConflictPolicy::FailFast => {
+                    return Err(Error::StateConflict(theirs.generation))
+                }
+                StateConflictPolicy::Merge => {
+                    let merged = merge_states(base_state, ours, theirs.state)
+                        .ok_or(Error::StateConflict(theirs.generation))?;
+                    self.state
+                        .replace_persisted_state(state_from_json(&merged.to_string())?);
+                    (theirs.generation.max(base_generation) + 1, merged)
+                }
+            },
+            _ => (base_generation + 1, ours),
+        };
+        write_state_file(&path, generation, &state)?;
+        self.state_file_base = Some(StateFileBase {
+            path,
+            generation,
+            state,
+        });
+        Ok(())
+    }
+}
+
+/// The fields which make up a sign-in: a refresh token only goes with the session, keys and
+/// device record it was issued with. These are merged as a group, so that a state never ends up
+/// with credentials from two different sign-ins.
+const CREDENTIAL_FIELDS: &[&str] = &[
+    "refresh_token",
+    "session_token",
+    "scoped_keys",
+    "current_device_id",
+    "server_local_device_info",
+    "device_capabilities",
+    "commands_data",
+];
+/// Things fetched with the credentials, which are taken from the same process as them.
+const CREDENTIAL_CACHES: &[&str] = &["access_token_cache", "last_seen_profile"];
+/// Progress markers, where the furthest along of the two is kept.
+const PROGRESS_FIELDS: &[&str] = &["last_handled_command", "last_commands_poll"];
+/// Set on every write, so these always come from the process that's writing.
+const WRITE_STAMPS: &[&str] = &["persisted_at", "persisted_by_version"];
+
+/// Merge the changes two processes made to the state since `base`. Changes only one of them
+/// made are kept. Returns `None` if they both changed something, either a field or the
+/// credentials, to different values, since there's no telling which of them is right.
+fn merge_states(base: Option<&Value>, ours: Value, theirs: Value) -> Option<Value> {
+    let (Value::Object(ours), Value::Object(mut merged)) = (ours, theirs) else {
+        return None;
+    };
+    let empty = Map::new();
+    let base = base.and_then(Value::as_object).unwrap_or(&empty);
+    let group_changed = |state: &Map<String, Value>| {
+        CREDENTIAL_FIELDS
+            .iter()
+            .any(|field| state.get(*field) != base.get(*field))
+    };
+    let use_our_credentials = match (group_changed(&ours), group_changed(&merged)) {
+        (true, false) => true,
+        (true, true)
+            if CREDENTIAL_FIELDS
+                .iter()
+                .any(|field| ours.get(*field) != merged.get(*field)) =>
+        {
+            return None
+        }
+        _ => false,
+    };
+    for (field, value) in ours {
+        let name = field.as_str();
+        if CREDENTIAL_FIELDS.contains(&name) || CREDENTIAL_CACHES.contains(&name) {
+            if use_our_credentials {
+                merged.insert(field, value);
+            }
+        } else if WRITE_STAMPS.contains(&name) {
+            merged.insert(field, value);
+        } else if PROGRESS_FIELDS.contains(&name) {
+            if value.as_u64() > merged.get(&field).and_then(Value::as_u64) {
+                merged.insert(field, value);
+            }
+        } else if base.get(&field) != Some(&value) {
+            let theirs = merged.get(&field);
+            if theirs != base.get(&field) && theirs != Some(&value) {
+                return None;
+            }
+            merged.insert(field, value);
+        }
+    }
+    Some(Value::Object(merged))
+}
+
+fn read_state_file(path: &Path) -> Result<Option<StateFile>> {
+    match fs::read_to_string(path) {
+        Ok(data) => Ok(Some(serde_json::from_str(&data)?)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_state_file(path: &Path, generation: u64, state: &Value) -> Result<()> {
+    // Write to a temporary file and rename it over the old one, so that a crash halfway
+    // through doesn't leave a truncated file behind.
+    let tmp_path = with_suffix(path, ".tmp");
+    fs::write(
+        &tmp_path,
+        serde_json::to_string(&StateFile {
+            generation,
+            state: state.clone(),
+        })?,
+    )?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// Holds the lock on a state file until it's dropped.
+struct StateFileLock {
+    path: PathBuf,
+}
+
+impl StateFileLock {
+    fn acquire(state_path: &Path) -> Result<Self> {
+        let path = with_suffix(state_path, ".lock");
+        let started = Instant::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(Self { path }),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    if is_stale(&path) && take_over_stale_lock(&path)? {
+                        return Ok(Self { path });
+                    }
+                    if started.elapsed() > LOCK_TIMEOUT {
+                        return Err(Error::StateFileLocked);
+                    }
+                    thread::sleep(LOCK_RETRY_INTERVAL);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Take the lock from the process which left `lock_path` behind, returning whether we got it.
+///
+/// Removing the stale file and creating our own isn't safe: another process could take the
+/// lock in between, and a third one, which also saw the stale file, would then remove it. So
+/// we atomically move our own lock file over the stale one instead, and only if that's still
+/// the file we looked at. The move is guarded by a second lock file, created exclusively, so
+/// only one process at a time can take over.
+fn take_over_stale_lock(lock_path: &Path) -> Result<bool> {
+    let takeover_path = with_suffix(lock_path, ".takeover");
+    match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&takeover_path)
+    {
+        Ok(_) => (),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            // If a process crashed while taking over, its takeover file is as stale as the
+            // lock it was taking over. It's never held for long, so it's safe to remove.
+            if is_stale(&takeover_path) {
+                warn!("Removing a stale takeover of the lock on the account state file");
+                let _ = fs::remove_file(&takeover_path);
+            }
+            return Ok(false);
+        }
+        Err(e) => return Err(e.into()),
+    }
+    let result = (|| {
+        // The lock could have been released, and taken again, since we looked at it.
+        if !is_stale(lock_path) {
+            return Ok(false);
+        }
+        warn!("Taking over a stale lock on the account state file");
+        let new_lock_path = with_suffix(lock_path, &format!(".{}", std::process::id()));
+        fs::write(&new_lock_path, "")?;
+        fs::rename(&new_lock_path, lock_path)?;
+        Ok(true)
+    })();
+    let _ = fs::remove_file(&takeover_path);
+    result
+}
+
+fn is_stale(lock_path: &Path) -> bool {
+    fs::metadata(lock_path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .map_or(false, |age| age > STALE_LOCK_AGE)
+}
+
+impl Drop for StateFileLock {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Failed to release the lock on the account state file: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::{config::Config, oauth::RefreshToken};
+    use std::collections::HashSet;
+
+    fn new_account() -> FirefoxAccount {
+        FirefoxAccount::with_config(Config::stable_dev("12345678", "https://foo.bar"))
+    }
+
+    fn set_refresh_token(fxa: &mut FirefoxAccount, token: &str) {
+        fxa.state.force_refresh_token(RefreshToken {
+            token: token.to_string(),
+            scopes: HashSet::new(),
+        });
+    }
+
+    #[test]
+    fn test_fail_fast() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("account.json");
+        let path = path.to_str().unwrap();
+        new_account()
+            .save_with_lock(path, StateConflictPolicy::FailFast)
+            .unwrap();
+
+        let mut first = FirefoxAccount::from_json_with_lock(path).unwrap();
+        let mut second = FirefoxAccount::from_json_with_lock(path).unwrap();
+        set_refresh_token(&mut first, "first");
+        first
+            .save_with_lock(path, StateConflictPolicy::FailFast)
+            .unwrap();
+        // The second process hasn't seen the first one's write.
+        set_refresh_token(&mut second, "second");
+        assert!(matches!(
+            second.save_with_lock(path, StateConflictPolicy::FailFast),
+            Err(Error::StateConflict(2))
+        ));
+        let reloaded = FirefoxAccount::from_json_with_lock(path).unwrap();
+        assert_eq!(reloaded.state.refresh_token().unwrap().token, "first");
+        // Nothing's left locked.
+        assert!(!with_suffix(Path::new(path), ".lock").exists());
+    }
+
+    #[test]
+    fn test_merge() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("account.json");
+        let path = path.to_str().unwrap();
+        new_account()
+            .save_with_lock(path, StateConflictPolicy::FailFast)
+            .unwrap();
+
+        let mut first = FirefoxAccount::from_json_with_lock(path).unwrap();
+        let mut second = FirefoxAccount::from_json_with_lock(path).unwrap();
+        set_refresh_token(&mut first, "first");
+        first
+            .save_with_lock(path, StateConflictPolicy::Merge)
+            .unwrap();
+        second.state.set_last_handled_command_index(5);
+        second
+            .save_with_lock(path, StateConflictPolicy::Merge)
+            .unwrap();
+        // The second process picks up the first one's changes, and keeps its own.
+        assert_eq!(second.state.refresh_token().unwrap().token, "first");
+        let reloaded = FirefoxAccount::from_json_with_lock(path).unwrap();
+        assert_eq!(reloaded.state.refresh_token().unwrap().token, "first");
+        assert_eq!(reloaded.state.last_handled_command_index(), Some(5));
+
+        // When both change the same field, neither is dropped: the second write fails, so
+        // that process can reload the state and redo its operation.
+        set_refresh_token(&mut first, "first again");
+        set_refresh_token(&mut second, "second");
+        second
+            .save_with_lock(path, StateConflictPolicy::Merge)
+            .unwrap();
+        assert!(matches!(
+            first.save_with_lock(path, StateConflictPolicy::Merge),
+            Err(Error::StateConflict(_))
+        ));
+        let reloaded = FirefoxAccount::from_json_with_lock(path).unwrap();
+        assert_eq!(reloaded.state.refresh_token().unwrap().token, "second");
+    }
+
+    #[test]
+    fn test_merge_credentials_together() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("account.json");
+        let path = path.to_str().unwrap();
+        new_account()
+            .save_with_lock(path, StateConflictPolicy::FailFast)
+            .unwrap();
+
+        let mut first = FirefoxAccount::from_json_with_lock(path).unwrap();
+        let mut second = FirefoxAccount::from_json_with_lock(path).unwrap();
+        // Each process signs in, and ends up with a different refresh token and session.
+        set_refresh_token(&mut first, "first");
+        first
+            .save_with_lock(path, StateConflictPolicy::Merge)
+            .unwrap();
+        second.state.set_session_token("second-session".to_string());
+        assert!(matches!(
+            second.save_with_lock(path, StateConflictPolicy::Merge),
+            Err(Error::StateConflict(_))
+        ));
+        let reloaded = FirefoxAccount::from_json_with_lock(path).unwrap();
+        assert_eq!(reloaded.state.refresh_token().unwrap().token, "first");
+        assert_eq!(reloaded.state.session_token(), None);
+    }
+
+    #[test]
+    fn test_stale_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("account.json");
+        let lock_path = with_suffix(&path, ".lock");
+        fs::write(&lock_path, "").unwrap();
+        let lock_file = fs::File::options().write(true).open(&lock_path).unwrap();
+        lock_file
+            .set_modified(SystemTime::now() - STALE_LOCK_AGE * 2)
+            .unwrap();
+        new_account()
+            .save_with_lock(path.to_str().unwrap(), StateConflictPolicy::FailFast)
+            .unwrap();
+        assert!(!lock_path.exists());
+    }
+}
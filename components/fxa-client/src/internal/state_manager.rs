@@ -8,7 +8,9 @@ use crate::{
     internal::{
         oauth::{AccessTokenInfo, RefreshToken},
         profile::Profile,
-        state_persistence::state_to_json,
+        state_persistence::{
+            state_to_json, state_to_sanitized_json, PendingDisconnect, SeenScopedKey,
+        },
         CachedResponse, Config, OAuthFlow, PersistedState,
     },
     DeviceCapability, FxaRustAuthState, LocalDevice, Result, ScopedKey,
@@ -33,14 +35,28 @@ impl StateManager {
         }
     }
 
+    /// Replace the persisted state with one read back from storage, keeping any in-progress
+    /// OAuth flows.
+    pub(crate) fn replace_persisted_state(&mut self, persisted_state: PersistedState) {
+        self.persisted_state = persisted_state;
+    }
+
     pub fn serialize_persisted_state(&self) -> Result<String> {
         state_to_json(&self.persisted_state)
     }
 
+    pub fn serialize_sanitized_state(&self) -> Result<String> {
+        state_to_sanitized_json(&self.persisted_state)
+    }
+
     pub fn config(&self) -> &Config {
         &self.persisted_state.config
     }
 
+    pub fn set_extra_headers(&mut self, extra_headers: HashMap<String, String>) {
+        self.persisted_state.config.set_extra_headers(extra_headers);
+    }
+
     pub fn refresh_token(&self) -> Option<&RefreshToken> {
         self.persisted_state.refresh_token.as_ref()
     }
@@ -108,6 +124,14 @@ impl StateManager {
         self.persisted_state.last_handled_command = Some(idx)
     }
 
+    pub fn last_commands_poll(&self) -> Option<u64> {
+        self.persisted_state.last_commands_poll
+    }
+
+    pub fn set_last_commands_poll(&mut self, timestamp: u64) {
+        self.persisted_state.last_commands_poll = Some(timestamp)
+    }
+
     pub fn current_device_id(&self) -> Option<&str> {
         self.persisted_state.current_device_id.as_deref()
     }
@@ -120,6 +144,27 @@ impl StateManager {
         self.persisted_state.scoped_keys.get(scope)
     }
 
+    pub(crate) fn seen_scoped_key(&self, scope: &str) -> Option<&SeenScopedKey> {
+        self.persisted_state.seen_scoped_keys.get(scope)
+    }
+
+    pub(crate) fn set_seen_scoped_key(&mut self, scope: impl Into<String>, seen: SeenScopedKey) {
+        self.persisted_state
+            .seen_scoped_keys
+            .insert(scope.into(), seen);
+    }
+
+    pub(crate) fn seen_scoped_keys_uid(&self) -> Option<&str> {
+        self.persisted_state.seen_scoped_keys_uid.as_deref()
+    }
+
+    /// Start remembering scoped keys for another account, forgetting the ones we saw for the
+    /// previous one.
+    pub(crate) fn reset_seen_scoped_keys(&mut self, uid: &str) {
+        self.persisted_state.seen_scoped_keys = HashMap::new();
+        self.persisted_state.seen_scoped_keys_uid = Some(uid.to_owned());
+    }
+
     pub(crate) fn last_seen_profile(&self) -> Option<&CachedResponse<Profile>> {
         self.persisted_state.last_seen_profile.as_ref()
     }
@@ -146,6 +191,17 @@ impl StateManager {
         self.persisted_state.access_token_cache.clear()
     }
 
+    pub fn cached_access_tokens(&self) -> impl Iterator<Item = (&str, &AccessTokenInfo)> {
+        self.persisted_state
+            .access_token_cache
+            .iter()
+            .map(|(scope, token_info)| (scope.as_str(), token_info))
+    }
+
+    pub fn scoped_key_scopes(&self) -> impl Iterator<Item = &str> {
+        self.persisted_state.scoped_keys.keys().map(String::as_str)
+    }
+
     /// Begin an OAuth flow.  This saves the OAuthFlow for later.  `state` must be unique to this
     /// oauth flow process.
     pub fn begin_oauth_flow(&mut self, state: impl Into<String>, flow: OAuthFlow) {
@@ -184,19 +240,41 @@ impl StateManager {
         self.flow_store.clear();
     }
 
+    /// Device records and refresh tokens which still need to be destroyed on the server.
+    pub(crate) fn pending_disconnects(&self) -> &[PendingDisconnect] {
+        &self.persisted_state.pending_disconnects
+    }
+
+    pub(crate) fn add_pending_disconnect(&mut self, pending: PendingDisconnect) {
+        if !self.persisted_state.pending_disconnects.contains(&pending) {
+            self.persisted_state.pending_disconnects.push(pending);
+        }
+    }
+
+    pub(crate) fn remove_pending_disconnect(&mut self, pending: &PendingDisconnect) {
+        self.persisted_state
+            .pending_disconnects
+            .retain(|p| p != pending);
+    }
+
     /// Called when the account is disconnected.  This clears most of the auth state, but keeps
     /// some information in order to eventually reconnect to the same user account later.
+    ///
+    /// Pending disconnects are kept too, since they're for tokens which are no longer ours.
     pub fn disconnect(&mut self) {
         self.persisted_state.current_device_id = None;
         self.persisted_state.refresh_token = None;
         self.persisted_state.scoped_keys = HashMap::new();
         self.persisted_state.last_handled_command = None;
+        self.persisted_state.last_commands_poll = None;
         self.persisted_state.commands_data = HashMap::new();
         self.persisted_state.access_token_cache = HashMap::new();
         self.persisted_state.device_capabilities = HashSet::new();
         self.persisted_state.server_local_device_info = None;
         self.persisted_state.session_token = None;
         self.persisted_state.logged_out_from_auth_issues = false;
+        self.persisted_state.seen_scoped_keys = HashMap::new();
+        self.persisted_state.seen_scoped_keys_uid = None;
         self.flow_store.clear();
     }
 
@@ -29,6 +29,7 @@
 //! For an example how the conversion works, [we can look at `StateV1` which was deliberately removed](https://github.com/mozilla/application-services/issues/3912)
 //! The code that was deleted demonstrates how we can implement the migration
 
+use rc_crypto::digest;
 use serde_derive::*;
 use std::collections::{HashMap, HashSet};
 
@@ -36,28 +37,141 @@ use super::{
     config::Config,
     oauth::{AccessTokenInfo, RefreshToken},
     profile::Profile,
-    CachedResponse, Result,
+    util, CachedResponse, Result,
 };
-use crate::{DeviceCapability, LocalDevice, ScopedKey};
+use crate::{DeviceCapability, LocalDevice, ScopedKey, StateFreshness, StateFreshnessStatus};
 
 // These are the public API for working with the persisted state.
 
 pub(crate) type PersistedState = StateV2;
 
+pub(crate) const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+/// State saved longer ago than this is reported as stale.
+const STALE_STATE_AGE_MS: u64 = 180 * 24 * 60 * 60 * 1000;
+/// How far in the future a state can have been saved before it's reported as future-dated, to
+/// allow for small clock adjustments.
+const MAX_CLOCK_SKEW_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// The application-services version saving the state.
+fn writer_version() -> &'static str {
+    include_str!("../../../../version.txt").trim()
+}
+
 /// Parse a `State` from a JSON string, performing migrations if necessary.
 ///
+/// Any persisted access tokens which can no longer be used are dropped here, so that the
+/// rest of the crate can trust the contents of the token cache.
+///
 pub(crate) fn state_from_json(data: &str) -> Result<PersistedState> {
+    Ok(state_and_freshness_from_json(data)?.0)
+}
+
+/// Like [`state_from_json`], but also returns how old the state is.
+pub(crate) fn state_and_freshness_from_json(
+    data: &str,
+) -> Result<(PersistedState, StateFreshness)> {
     let stored_state: PersistedStateTagged = serde_json::from_str(data)?;
-    upgrade_state(stored_state)
+    let schema_version = stored_state.schema_version();
+    let mut state = upgrade_state(stored_state)?;
+    state.prune_access_token_cache();
+    let freshness = state.freshness(schema_version, util::now());
+    Ok((state, freshness))
 }
 
 /// Serialize a `State` to a JSON string.
 ///
+/// Access tokens which carry a scoped key are left out - we don't want to write key material
+/// anywhere other than `scoped_keys`, so those tokens are only ever cached in memory and get
+/// re-fetched after a restart.
+///
 pub(crate) fn state_to_json(state: &PersistedState) -> Result<String> {
-    let state = PersistedStateTagged::V2(state.clone());
+    let mut state = state.clone();
+    state.persisted_at = Some(util::now());
+    state.persisted_by_version = Some(writer_version().to_string());
+    state_to_json_unstamped(state)
+}
+
+/// Serialize a `State` without recording when and by which version it was saved.
+fn state_to_json_unstamped(mut state: PersistedState) -> Result<String> {
+    state
+        .access_token_cache
+        .retain(|_, token_info| token_info.key.is_none());
+    let state = PersistedStateTagged::V2(state);
     serde_json::to_string(&state).map_err(Into::into)
 }
 
+// Fields whose values are secret (tokens, key material, push subscription credentials) or
+// personal (the account's uid, and the email, name and avatar from the user's profile), and so
+// are replaced by fingerprints in the sanitized state.
+const SANITIZED_FIELDS: &[&str] = &[
+    "token",
+    "session_token",
+    "k",
+    "auth_key",
+    "endpoint",
+    "email",
+    "uid",
+    "seen_scoped_keys_uid",
+    "displayName",
+    "avatar",
+];
+// Fields holding a map whose values all need sanitizing. `commands_data` holds the private keys
+// for device commands.
+const SANITIZED_MAPS: &[&str] = &["commands_data"];
+
+/// Serialize a `State` to the same JSON as [`state_to_json`], but with all secrets replaced
+/// by fingerprints, so that it can be attached to bug reports.
+///
+/// A fingerprint is a truncated SHA-256 hash, so engineers can still tell whether two values
+/// are the same. Everything else, including timestamps, is left as it is. The output is
+/// deterministic: object keys are sorted, so the same state always produces the same string.
+/// The save time and version are those of the state as it was loaded.
+///
+pub(crate) fn state_to_sanitized_json(state: &PersistedState) -> Result<String> {
+    let mut value: serde_json::Value =
+        serde_json::from_str(&state_to_json_unstamped(state.clone())?)?;
+    sanitize_value(&mut value)?;
+    serde_json::to_string(&value).map_err(Into::into)
+}
+
+fn sanitize_value(value: &mut serde_json::Value) -> Result<()> {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SANITIZED_FIELDS.contains(&key.as_str()) {
+                    *value = fingerprint(value)?;
+                } else if SANITIZED_MAPS.contains(&key.as_str()) {
+                    if let serde_json::Value::Object(map) = value {
+                        for value in map.values_mut() {
+                            *value = fingerprint(value)?;
+                        }
+                    }
+                } else {
+                    sanitize_value(value)?;
+                }
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                sanitize_value(value)?;
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+fn fingerprint(value: &serde_json::Value) -> Result<serde_json::Value> {
+    let data = match value {
+        serde_json::Value::Null => return Ok(serde_json::Value::Null),
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let hash = digest::digest(&digest::SHA256, data.as_bytes())?;
+    Ok(format!("sha256:{}", hex::encode(&hash.as_ref()[..8])).into())
+}
+
 fn upgrade_state(in_state: PersistedStateTagged) -> Result<PersistedState> {
     match in_state {
         PersistedStateTagged::V2(state) => Ok(state),
@@ -74,6 +188,14 @@ enum PersistedStateTagged {
     V2(StateV2),
 }
 
+impl PersistedStateTagged {
+    fn schema_version(&self) -> u32 {
+        match self {
+            PersistedStateTagged::V2(_) => 2,
+        }
+    }
+}
+
 /// `StateV2` is the current state schema. It and its fields all need to be public
 /// so that they can be used directly elsewhere in the crate.
 ///
@@ -110,6 +232,80 @@ pub(crate) struct StateV2 {
     pub(crate) server_local_device_info: Option<LocalDevice>,
     #[serde(default)]
     pub(crate) logged_out_from_auth_issues: bool,
+    // Device records and refresh tokens we failed to destroy when disconnecting.
+    #[serde(default)]
+    pub(crate) pending_disconnects: Vec<PendingDisconnect>,
+    // When we last polled for device commands, in milliseconds since the epoch.
+    #[serde(default)]
+    pub(crate) last_commands_poll: Option<u64>,
+    // When the state was serialized, in milliseconds since the epoch, and by which version of
+    // application-services. These are only set in the serialized state.
+    #[serde(default)]
+    pub(crate) persisted_at: Option<u64>,
+    #[serde(default)]
+    pub(crate) persisted_by_version: Option<String>,
+    // The scoped keys we've been given for this account, by scope. Unlike `scoped_keys`, these
+    // are kept when the user has to sign in again, so we can tell if the keys we get then are
+    // unexpectedly different.
+    #[serde(default)]
+    pub(crate) seen_scoped_keys: HashMap<String, SeenScopedKey>,
+    // The uid of the account `seen_scoped_keys` belong to.
+    #[serde(default)]
+    pub(crate) seen_scoped_keys_uid: Option<String>,
+}
+
+/// What we remember about a scoped key after it's been cleared from the state.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SeenScopedKey {
+    pub(crate) kid: String,
+    pub(crate) fingerprint: String,
+}
+
+/// A refresh token, and the device record registered with it, which still need to be
+/// destroyed on the server because the account was disconnected while offline.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PendingDisconnect {
+    pub(crate) token: String,
+    pub(crate) device_id: Option<String>,
+}
+
+impl StateV2 {
+    /// Drop cached access tokens that we shouldn't hand out after loading the state: tokens
+    /// which have expired, tokens for scopes we're no longer authorized for, and tokens carrying
+    /// scoped keys (which older versions persisted).
+    fn prune_access_token_cache(&mut self) {
+        let now = util::now_secs();
+        let refresh_token = self.refresh_token.as_ref();
+        let has_session_token = self.session_token.is_some();
+        self.access_token_cache.retain(|scope, token_info| {
+            let authorized = match refresh_token {
+                Some(refresh_token) => refresh_token.scopes.contains(scope),
+                None => has_session_token,
+            };
+            authorized && token_info.key.is_none() && token_info.expires_at > now
+        });
+    }
+
+    fn freshness(&self, schema_version: u32, now: u64) -> StateFreshness {
+        let status = match self.persisted_at {
+            None => StateFreshnessStatus::Unknown,
+            Some(persisted_at) if persisted_at > now.saturating_add(MAX_CLOCK_SKEW_MS) => {
+                StateFreshnessStatus::FutureDated
+            }
+            Some(persisted_at) if now.saturating_sub(persisted_at) > STALE_STATE_AGE_MS => {
+                StateFreshnessStatus::Stale
+            }
+            Some(_) => StateFreshnessStatus::Fresh,
+        };
+        StateFreshness {
+            status,
+            persisted_at: self
+                .persisted_at
+                .map(|persisted_at| i64::try_from(persisted_at).unwrap_or(i64::MAX)),
+            persisted_by_version: self.persisted_by_version.clone(),
+            schema_version,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -148,4 +344,168 @@ mod tests {
         );
         assert_eq!(state.access_token_cache.len(), 0);
     }
+
+    fn state_with_cached_tokens(tokens: Vec<AccessTokenInfo>) -> PersistedState {
+        let mut state = state_from_json("{\"schema_version\":\"V2\",\"config\":{\"client_id\":\"98adfa37698f255b\",\"redirect_uri\":\"https://lockbox.firefox.com/fxa/ios-redirect.html\",\"content_url\":\"https://accounts.firefox.com\"},\"refresh_token\":{\"token\":\"bed5532f4fea7e39c5c4f609f53603ee7518fd1c103cc4034da3618f786ed188\",\"scopes\":[\"profile\",\"https://identity.mozilla.com/apps/oldsync\"]},\"scoped_keys\":{}}").unwrap();
+        for token_info in tokens {
+            state
+                .access_token_cache
+                .insert(token_info.scope.clone(), token_info);
+        }
+        state
+    }
+
+    #[test]
+    fn test_access_tokens_persisted_without_keys() {
+        let key = ScopedKey {
+            kty: "oct".to_string(),
+            scope: "https://identity.mozilla.com/apps/oldsync".to_string(),
+            k: "kMtwpVC0ZaYFJymPza8rXK_0CgCp3KMwRStwGfBRBDtL6hXRDVJgQFaoOQ2dimw0Bko5WVv2gNTy7RX5zFYZHg".to_string(),
+            kid: "1542236016429-Ox1FbJfFfwTe5t-xq4v2hQ".to_string(),
+        };
+        let state = state_with_cached_tokens(vec![
+            AccessTokenInfo {
+                scope: "profile".to_string(),
+                token: "profiletok".to_string(),
+                key: None,
+                expires_at: u64::MAX,
+            },
+            AccessTokenInfo {
+                scope: "https://identity.mozilla.com/apps/oldsync".to_string(),
+                token: "synctok".to_string(),
+                key: Some(key),
+                expires_at: u64::MAX,
+            },
+        ]);
+        let json = state_to_json(&state).unwrap();
+        assert!(!json.contains("synctok"));
+        let restored = state_from_json(&json).unwrap();
+        assert_eq!(restored.access_token_cache.len(), 1);
+        assert_eq!(restored.access_token_cache["profile"].token, "profiletok");
+        assert_eq!(restored.access_token_cache["profile"].expires_at, u64::MAX);
+    }
+
+    #[test]
+    fn test_unusable_access_tokens_dropped_on_load() {
+        let mut state = state_with_cached_tokens(vec![
+            AccessTokenInfo {
+                scope: "profile".to_string(),
+                token: "expiredtok".to_string(),
+                key: None,
+                expires_at: util::now_secs() - 1,
+            },
+            AccessTokenInfo {
+                scope: "https://identity.mozilla.com/apps/send-tab".to_string(),
+                token: "unauthorizedtok".to_string(),
+                key: None,
+                expires_at: u64::MAX,
+            },
+        ]);
+        let restored = state_from_json(&state_to_json(&state).unwrap()).unwrap();
+        assert!(restored.access_token_cache.is_empty());
+
+        // Without a refresh token, tokens are only useful if we still have a session token.
+        state.refresh_token = None;
+        state.access_token_cache.clear();
+        state.access_token_cache.insert(
+            "profile".to_string(),
+            AccessTokenInfo {
+                scope: "profile".to_string(),
+                token: "profiletok".to_string(),
+                key: None,
+                expires_at: u64::MAX,
+            },
+        );
+        let restored = state_from_json(&state_to_json(&state).unwrap()).unwrap();
+        assert!(restored.access_token_cache.is_empty());
+        state.session_token = Some("sessiontok".to_string());
+        let restored = state_from_json(&state_to_json(&state).unwrap()).unwrap();
+        assert_eq!(restored.access_token_cache.len(), 1);
+    }
+
+    #[test]
+    fn test_state_to_sanitized_json() {
+        let state_v2_json = "{\"schema_version\":\"V2\",\"config\":{\"client_id\":\"98adfa37698f255b\",\"redirect_uri\":\"https://lockbox.firefox.com/fxa/ios-redirect.html\",\"content_url\":\"https://accounts.firefox.com\"},\"refresh_token\":{\"token\":\"bed5532f4fea7e39c5c4f609f53603ee7518fd1c103cc4034da3618f786ed188\",\"scopes\":[\"https://identity.mozilla.com/apps/oldsync\"]},\"scoped_keys\":{\"https://identity.mozilla.com/apps/oldsync\":{\"kty\":\"oct\",\"scope\":\"https://identity.mozilla.com/apps/oldsync\",\"k\":\"kMtwpVC0ZaYFJymPza8rXK_0CgCp3KMwRStwGfBRBDtL6hXRDVJgQFaoOQ2dimw0Bko5WVv2gNTy7RX5zFYZHg\",\"kid\":\"1542236016429-Ox1FbJfFfwTe5t-xq4v2hQ\"}},\"last_handled_command\":1542236016,\"commands_data\":{\"https://identity.mozilla.com/cmd/open-uri\":\"{\\\"private_key\\\":\\\"secret\\\"}\"},\"session_token\":\"a3c1fd6d1cd1bd2a5bd29ec91ea1fe6e2a1bde2e51d8b5fd3ac1ef3c4bc6a35e\",\"last_seen_profile\":{\"response\":{\"uid\":\"0123456789abcdef0123456789abcdef\",\"email\":\"foo@example.com\",\"displayName\":\"Foo Bar\",\"avatar\":\"https://profile.accounts.firefox.com/v1/avatar/f\",\"avatarDefault\":false},\"cached_at\":1542236016,\"etag\":\"\"}}";
+        let state = state_from_json(state_v2_json).unwrap();
+        let sanitized = state_to_sanitized_json(&state).unwrap();
+
+        for secret in [
+            "bed5532f4fea7e39c5c4f609f53603ee7518fd1c103cc4034da3618f786ed188",
+            "kMtwpVC0ZaYFJymPza8rXK_0CgCp3KMwRStwGfBRBDtL6hXRDVJgQFaoOQ2dimw0Bko5WVv2gNTy7RX5zFYZHg",
+            "a3c1fd6d1cd1bd2a5bd29ec91ea1fe6e2a1bde2e51d8b5fd3ac1ef3c4bc6a35e",
+            "private_key",
+            "0123456789abcdef0123456789abcdef",
+            "foo@example.com",
+            "Foo Bar",
+            "https://profile.accounts.firefox.com/v1/avatar/f",
+        ] {
+            assert!(!sanitized.contains(secret), "{secret} leaked");
+        }
+        let value: serde_json::Value = serde_json::from_str(&sanitized).unwrap();
+        let fingerprint = value["refresh_token"]["token"].as_str().unwrap();
+        assert!(fingerprint.starts_with("sha256:"));
+        assert_eq!(fingerprint.len(), "sha256:".len() + 16);
+        // Everything else is left alone.
+        assert_eq!(value["last_handled_command"], 1542236016);
+        assert_eq!(
+            value["scoped_keys"]["https://identity.mozilla.com/apps/oldsync"]["kid"],
+            "1542236016429-Ox1FbJfFfwTe5t-xq4v2hQ"
+        );
+        assert_eq!(
+            value["refresh_token"]["scopes"][0],
+            "https://identity.mozilla.com/apps/oldsync"
+        );
+        assert_eq!(
+            value["last_seen_profile"]["response"]["avatarDefault"],
+            false
+        );
+
+        // The same state always gives the same output.
+        assert_eq!(state_to_sanitized_json(&state).unwrap(), sanitized);
+    }
+
+    #[test]
+    fn test_freshness() {
+        let state_v2_json = "{\"schema_version\":\"V2\",\"config\":{\"client_id\":\"98adfa37698f255b\",\"redirect_uri\":\"https://lockbox.firefox.com/fxa/ios-redirect.html\",\"content_url\":\"https://accounts.firefox.com\"},\"refresh_token\":null,\"scoped_keys\":{},\"login_state\":{\"Unknown\":null}}";
+        // State saved before we recorded when.
+        let (state, freshness) = state_and_freshness_from_json(state_v2_json).unwrap();
+        assert_eq!(
+            freshness,
+            StateFreshness {
+                status: StateFreshnessStatus::Unknown,
+                persisted_at: None,
+                persisted_by_version: None,
+                schema_version: 2,
+            }
+        );
+
+        let (state, freshness) =
+            state_and_freshness_from_json(&state_to_json(&state).unwrap()).unwrap();
+        assert_eq!(freshness.status, StateFreshnessStatus::Fresh);
+        assert!(freshness.persisted_at.is_some());
+        assert_eq!(
+            freshness.persisted_by_version.as_deref(),
+            Some(writer_version())
+        );
+
+        let persisted_at = state.persisted_at.unwrap();
+        assert_eq!(
+            state
+                .freshness(2, persisted_at + STALE_STATE_AGE_MS - 1)
+                .status,
+            StateFreshnessStatus::Fresh
+        );
+        assert_eq!(
+            state
+                .freshness(2, persisted_at + STALE_STATE_AGE_MS + 1)
+                .status,
+            StateFreshnessStatus::Stale
+        );
+        assert_eq!(
+            state
+                .freshness(2, persisted_at - MAX_CLOCK_SKEW_MS - 1)
+                .status,
+            StateFreshnessStatus::FutureDated
+        );
+    }
 }
@@ -64,6 +64,17 @@ impl SentCommand {
         Self::new(Command::SendTab)
     }
 
+    /// A Send Tab command which is part of an existing flow, such as when the
+    /// same tab is sent to several devices. Each command still gets its own
+    /// stream ID.
+    pub fn for_send_tab_in_flow(flow_id: &str) -> Self {
+        Self {
+            command: Command::SendTab,
+            flow_id: flow_id.to_owned(),
+            stream_id: Guid::random().to_string(),
+        }
+    }
+
     pub fn for_close_tabs() -> Self {
         Self::new(Command::CloseTabs)
     }
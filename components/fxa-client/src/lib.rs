@@ -36,33 +36,59 @@
 //! * If the user opts to sign out of the application, calling [`disconnect`](FirefoxAccount::disconnect)
 //!   and then discarding any persisted account data.
 
+// This must come first, so that its macros can be used in the other modules.
+#[macro_use]
+mod logging;
+
 mod account;
 mod auth;
+mod connectivity;
 mod device;
+mod diagnostics;
 mod error;
+mod flow_metrics;
 mod internal;
 mod profile;
 mod push;
+mod recovery_key;
 mod state_machine;
 mod storage;
 mod telemetry;
 mod token;
+mod web_channel;
 
+use std::collections::HashMap;
 use std::fmt;
 
 pub use sync15::DeviceType;
 use url::Url;
 
-pub use auth::{AuthorizationInfo, FxaEvent, FxaRustAuthState, FxaState, UserData};
-pub use device::{AttachedClient, Device, DeviceCapability, DeviceConfig, LocalDevice};
-pub use error::{Error, FxaError};
+pub use auth::{
+    AuthorizationInfo, FxaEvent, FxaRustAuthState, FxaState, FxaStateTimeouts, OAuthFlowOptions,
+    OAuthPrompt, UserData,
+};
+pub use connectivity::{FxaConnectivity, FxaConnectivityStatus};
+pub use device::{
+    AttachedClient, CommandKeyFreshness, Device, DeviceCapability, DeviceConfig, DeviceConfigDiff,
+    LocalDevice, LocalDeviceCapability,
+};
+pub use diagnostics::{AccessTokenDiagnostics, FxaDiagnostics, FxaDiagnosticsError};
+pub use error::{
+    fxa_error_code_value, fxa_error_hint, Error, FxaError, FxaErrorCode, FxaErrorHint,
+};
+pub use flow_metrics::{OAuthFlowMetrics, OAuthFlowOutcome, OAuthFlowStep};
 use parking_lot::Mutex;
-pub use profile::Profile;
+pub use profile::{Profile, ProfileField};
 pub use push::{
-    AccountEvent, CloseTabsPayload, DevicePushSubscription, IncomingDeviceCommand, SendTabPayload,
-    TabHistoryEntry,
+    AccountEvent, CloseTabsPayload, DevicePushSubscription, IncomingDeviceCommand, SendTabFailure,
+    SendTabPayload, SendTabResult, TabHistoryEntry,
+};
+pub use storage::{StateConflictPolicy, StateFreshness, StateFreshnessStatus};
+pub use token::{parse_scope, AccessTokenInfo, AuthorizationParameters, Scope, ScopedKey};
+pub use web_channel::{
+    parse_web_channel_message, web_channel_message_to_event, web_channel_response,
+    WebChannelCommand, WebChannelMessage,
 };
-pub use token::{AccessTokenInfo, AuthorizationParameters, ScopedKey};
 
 // Used for auth state checking.  Remove this once firefox-android and firefox-ios are migrated to
 // using FxaAuthStateMachine
@@ -123,6 +149,12 @@ pub struct FxaConfig {
     ///  cut out `fxa-client` out of the middle and have applications send the overridden URL
     ///  directly to `SyncManager`.
     pub token_server_url_override: Option<String>,
+    /// Headers to send with every request to the FxA servers, for deployments which go through
+    /// a gateway that needs them, such as an authentication cookie. Headers which we set
+    /// ourselves, like `Authorization` and `Host`, can't be overridden, and are ignored with a
+    /// warning, as are invalid headers. They aren't saved with the state, so pass them to
+    /// [`FirefoxAccount::set_extra_headers`] again after restoring the account.
+    pub extra_headers: Option<HashMap<String, String>>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -196,6 +228,7 @@ impl FxaConfig {
             client_id: client_id.to_string(),
             redirect_uri: redirect_uri.to_string(),
             token_server_url_override: None,
+            extra_headers: None,
         }
     }
 
@@ -205,6 +238,7 @@ impl FxaConfig {
             client_id: client_id.to_string(),
             redirect_uri: redirect_uri.to_string(),
             token_server_url_override: None,
+            extra_headers: None,
         }
     }
 
@@ -214,6 +248,7 @@ impl FxaConfig {
             client_id: client_id.to_string(),
             redirect_uri: redirect_uri.to_string(),
             token_server_url_override: None,
+            extra_headers: None,
         }
     }
 
@@ -223,6 +258,7 @@ impl FxaConfig {
             client_id: client_id.to_string(),
             redirect_uri: redirect_uri.to_string(),
             token_server_url_override: None,
+            extra_headers: None,
         }
     }
 
@@ -232,6 +268,7 @@ impl FxaConfig {
             client_id: client_id.to_string(),
             redirect_uri: redirect_uri.to_string(),
             token_server_url_override: None,
+            extra_headers: None,
         }
     }
 }
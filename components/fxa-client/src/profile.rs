@@ -60,3 +60,21 @@ pub struct Profile {
     /// Whether the `avatar` URL represents the default avatar image.
     pub is_default_avatar: bool,
 }
+
+/// A part of the user's [`Profile`] which can change, as reported by
+/// [`AccountEvent::ProfileUpdated`](crate::AccountEvent::ProfileUpdated).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfileField {
+    Email,
+    DisplayName,
+    /// The `avatar` URL, or whether it's the default avatar.
+    Avatar,
+}
+
+impl ProfileField {
+    pub(crate) const ALL: [ProfileField; 3] = [
+        ProfileField::Email,
+        ProfileField::DisplayName,
+        ProfileField::Avatar,
+    ];
+}
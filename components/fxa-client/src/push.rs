@@ -5,7 +5,7 @@
 use error_support::handle_error;
 use serde::{Deserialize, Serialize};
 
-use crate::{internal, ApiResult, Device, Error, FirefoxAccount, LocalDevice};
+use crate::{internal, ApiResult, Device, Error, FirefoxAccount, LocalDevice, ProfileField};
 
 impl FirefoxAccount {
     /// Set or update a push subscription endpoint for this device.
@@ -78,6 +78,28 @@ impl FirefoxAccount {
             .collect::<Result<_, _>>()
     }
 
+    /// Poll the server for any pending device commands, unless it was polled recently.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    ///
+    /// This works like [`poll_device_commands`](FirefoxAccount::poll_device_commands), but
+    /// returns an empty list without hitting the server if the last successful poll was less
+    /// than `max_age_ms` milliseconds ago. That makes it cheap enough to call every time the
+    /// application comes to the foreground, as a fallback for devices where push messages are
+    /// unreliable.
+    #[handle_error(Error)]
+    pub fn poll_device_commands_if_stale(
+        &self,
+        max_age_ms: u64,
+    ) -> ApiResult<Vec<IncomingDeviceCommand>> {
+        self.internal
+            .lock()
+            .poll_device_commands_if_stale(max_age_ms)?
+            .into_iter()
+            .map(TryFrom::try_from)
+            .collect::<Result<_, _>>()
+    }
+
     /// Use device commands to send a single tab to another device.
     ///
     /// **💾 This method alters the persisted account state.**
@@ -101,6 +123,26 @@ impl FirefoxAccount {
             .send_single_tab(target_device_id, title, url)
     }
 
+    /// Use device commands to send a tab to all of the user's other devices.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    ///
+    /// The tab is sent to every device on the account, other than this one, which has
+    /// registered the [`SendTab`](DeviceCapability::SendTab) capability. Failing to send
+    /// to one device doesn't stop the tab being sent to the others; the returned
+    /// [`SendTabResult`] says which devices it was sent to, and which it couldn't be.
+    ///
+    /// # Notes
+    ///
+    ///    - This method only throws for errors which stop the tab being sent at all, for example
+    ///      because the device list couldn't be fetched.
+    ///    - Device commands functionality is only available to applications that have been
+    ///      granted the `https://identity.mozilla.com/apps/oldsync` scope.
+    #[handle_error(Error)]
+    pub fn send_tab_to_self(&self, title: &str, url: &str) -> ApiResult<SendTabResult> {
+        self.internal.lock().send_tab_to_self(title, url)
+    }
+
     /// Use device commands to close one or more tabs on another device.
     ///
     /// **💾 This method alters the persisted account state.**
@@ -149,12 +191,13 @@ pub enum AccountEvent {
     CommandReceived { command: IncomingDeviceCommand },
     /// Sent when the user has modified their account profile information.
     ///
-    /// When receiving this event, the application should request fresh profile
-    /// information by calling [`get_profile`](FirefoxAccount::get_profile) with
-    /// `ignore_cache` set to true, and update any profile information displayed
-    /// in its UI.
+    /// The new profile has already been fetched, so [`get_profile`](FirefoxAccount::get_profile)
+    /// will return it from the cache. `changed_fields` lists what's different from the
+    /// profile we had before, so the application only needs to update that part of its UI.
+    /// If we didn't have a profile before, or couldn't fetch the new one, it lists every
+    /// field.
     ///
-    ProfileUpdated,
+    ProfileUpdated { changed_fields: Vec<ProfileField> },
     /// Sent when when there has been a change in authorization status.
     ///
     /// When receiving this event, the application should check whether it is
@@ -181,6 +224,23 @@ pub enum AccountEvent {
         device_id: String,
         is_local_device: bool,
     },
+    /// Sent by [`ensure_device_registration`](FirefoxAccount::ensure_device_registration)
+    /// when the server had removed this device's record, and it was registered again.
+    ///
+    /// The device now has a new ID. The application may want to update any UI that shows
+    /// the list of connected devices, and record that this happened.
+    DeviceReregistered {
+        previous_device_id: String,
+        device: LocalDevice,
+    },
+    /// Queued by [`complete_oauth_flow`](FirefoxAccount::complete_oauth_flow) when the
+    /// server sent different keys for `scopes` than it did the last time the user signed in,
+    /// without them having been rotated by a password reset since.
+    ///
+    /// The keys from the server have been stored, and will be used from now on. This shouldn't
+    /// happen, so the application should record it, and may want to suggest that the user
+    /// review the security of their account.
+    KeysMismatch { scopes: Vec<String> },
 
     /// An unknown event, most likely an event the client doesn't support yet.
     ///
@@ -225,6 +285,23 @@ pub struct SendTabPayload {
     pub stream_id: String,
 }
 
+/// The outcome of [`FirefoxAccount::send_tab_to_self`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SendTabResult {
+    /// The IDs of the devices the tab was sent to.
+    pub sent_to: Vec<String>,
+    /// The devices the tab couldn't be sent to.
+    pub failures: Vec<SendTabFailure>,
+}
+
+/// A device which a tab couldn't be sent to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendTabFailure {
+    pub device_id: String,
+    /// A description of the error, for logging.
+    pub reason: String,
+}
+
 /// The payload sent when invoking a "close tabs" command.
 #[derive(Debug)]
 pub struct CloseTabsPayload {
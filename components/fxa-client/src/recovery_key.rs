@@ -0,0 +1,45 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! # Account Recovery Keys
+//!
+//! A recovery key lets the user keep their synced data when they reset a forgotten password.
+//! These methods let applications offer to create one, and nudge users who don't have one,
+//! in native UI.
+
+use crate::{ApiResult, Error, FirefoxAccount};
+use error_support::handle_error;
+
+impl FirefoxAccount {
+    /// Create a recovery key for the account, replacing any it already has.
+    ///
+    /// Returns the key, formatted for the user to write down. It isn't stored anywhere
+    /// else, so it can't be shown again.
+    ///
+    /// # Arguments
+    ///
+    ///    - `password` - the user's password, which is needed to fetch the account keys
+    ///      that the recovery key protects.
+    ///
+    /// # Notes
+    ///
+    ///    - Throws [`IncorrectPassword`](crate::FxaError::IncorrectPassword) if the password
+    ///      is wrong.
+    #[handle_error(Error)]
+    pub fn create_recovery_key(&self, password: &str) -> ApiResult<String> {
+        self.internal.lock().create_recovery_key(password)
+    }
+
+    /// Check whether the account has a recovery key.
+    #[handle_error(Error)]
+    pub fn get_recovery_key_status(&self) -> ApiResult<bool> {
+        self.internal.lock().get_recovery_key_status()
+    }
+
+    /// Delete the account's recovery key.
+    #[handle_error(Error)]
+    pub fn delete_recovery_key(&self) -> ApiResult<()> {
+        self.internal.lock().delete_recovery_key()
+    }
+}
@@ -33,6 +33,10 @@ pub enum FxaStateCheckerState {
         code: String,
         state: String,
     },
+    VerifyTotpCode {
+        code: String,
+    },
+    CompleteTwoFactorOAuthFlow,
     InitializeDevice,
     EnsureDeviceCapabilities,
     CheckAuthorizationStatus,
@@ -208,6 +212,7 @@ fn make_state_machine(public_state: &FxaState) -> Box<dyn InternalStateMachine +
         FxaState::Uninitialized => Box::new(UninitializedStateMachine),
         FxaState::Disconnected => Box::new(DisconnectedStateMachine),
         FxaState::Authenticating { .. } => Box::new(AuthenticatingStateMachine),
+        FxaState::TwoFactorRequired => Box::new(TwoFactorRequiredStateMachine),
         FxaState::Connected => Box::new(ConnectedStateMachine),
         FxaState::AuthIssues => Box::new(AuthIssuesStateMachine),
     }
@@ -232,6 +237,8 @@ impl From<InternalState> for FxaStateCheckerState {
             InternalState::CompleteOAuthFlow { code, state } => {
                 Self::CompleteOAuthFlow { code, state }
             }
+            InternalState::VerifyTotpCode { code } => Self::VerifyTotpCode { code },
+            InternalState::CompleteTwoFactorOAuthFlow => Self::CompleteTwoFactorOAuthFlow,
             InternalState::InitializeDevice => Self::InitializeDevice,
             InternalState::EnsureDeviceCapabilities => Self::EnsureDeviceCapabilities,
             InternalState::CheckAuthorizationStatus => Self::CheckAuthorizationStatus,
@@ -262,6 +269,8 @@ impl From<FxaStateCheckerState> for InternalState {
             FxaStateCheckerState::CompleteOAuthFlow { code, state } => {
                 Self::CompleteOAuthFlow { code, state }
             }
+            FxaStateCheckerState::VerifyTotpCode { code } => Self::VerifyTotpCode { code },
+            FxaStateCheckerState::CompleteTwoFactorOAuthFlow => Self::CompleteTwoFactorOAuthFlow,
             FxaStateCheckerState::InitializeDevice => Self::InitializeDevice,
             FxaStateCheckerState::EnsureDeviceCapabilities => Self::EnsureDeviceCapabilities,
             FxaStateCheckerState::CheckAuthorizationStatus => Self::CheckAuthorizationStatus,
@@ -19,6 +19,7 @@ impl fmt::Display for FxaState {
             Self::Uninitialized => "Uninitialized",
             Self::Disconnected => "Disconnected",
             Self::Authenticating { .. } => "Athenticating",
+            Self::TwoFactorRequired => "TwoFactorRequired",
             Self::Connected => "Connected",
             Self::AuthIssues => "AthIssues",
         };
@@ -33,10 +34,12 @@ impl fmt::Display for FxaEvent {
             Self::BeginOAuthFlow { .. } => "BeginOAthFlow",
             Self::BeginPairingFlow { .. } => "BeginPairingFlow",
             Self::CompleteOAuthFlow { .. } => "CompleteOAthFlow",
+            Self::SubmitTotpCode { .. } => "SubmitTotpCode",
             Self::CancelOAuthFlow => "CancelOAthFlow",
             Self::CheckAuthorizationStatus => "CheckAuthorizationStatus",
             Self::Disconnect => "Disconnect",
             Self::CallGetProfile => "CallGetProfile",
+            Self::FlowTimedOut => "FlowTimedOut",
         };
         write!(f, "{name}")
     }
@@ -49,6 +52,8 @@ impl fmt::Display for internal_machines::State {
             Self::BeginOAuthFlow { .. } => write!(f, "BeginOAthFlow"),
             Self::BeginPairingFlow { .. } => write!(f, "BeginPairingFlow"),
             Self::CompleteOAuthFlow { .. } => write!(f, "CompleteOAthFlow"),
+            Self::VerifyTotpCode { .. } => write!(f, "VerifyTotpCode"),
+            Self::CompleteTwoFactorOAuthFlow => write!(f, "CompleteTwoFactorOAuthFlow"),
             Self::InitializeDevice => write!(f, "InitializeDevice"),
             Self::EnsureDeviceCapabilities => write!(f, "EnsureDeviceCapabilities"),
             Self::CheckAuthorizationStatus => write!(f, "CheckAuthorizationStatus"),
@@ -67,6 +72,7 @@ impl fmt::Display for internal_machines::Event {
             Self::BeginOAuthFlowSuccess { .. } => "BeginOAthFlowSuccess",
             Self::BeginPairingFlowSuccess { .. } => "BeginPairingFlowSuccess",
             Self::CompleteOAuthFlowSuccess => "CompleteOAthFlowSuccess",
+            Self::VerifyTotpCodeSuccess { .. } => "VerifyTotpCodeSuccess",
             Self::InitializeDeviceSuccess => "InitializeDeviceSuccess",
             Self::EnsureDeviceCapabilitiesSuccess => "EnsureDeviceCapabilitiesSuccess",
             Self::CheckAuthorizationStatusSuccess { .. } => "CheckAuthorizationStatusSuccess",
@@ -74,6 +80,8 @@ impl fmt::Display for internal_machines::Event {
             Self::GetProfileSuccess => "GetProfileSuccess",
             Self::CallError => "CallError",
             Self::EnsureCapabilitiesAuthError => "EnsureCapabilitiesAthError",
+            Self::TwoFactorRequired => "TwoFactorRequired",
+            Self::NetworkError => "NetworkError",
         };
         write!(f, "{name}")
     }
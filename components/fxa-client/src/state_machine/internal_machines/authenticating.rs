@@ -18,7 +18,9 @@ impl InternalStateMachine for AuthenticatingStateMachine {
                 code: code.clone(),
                 state: state.clone(),
             }),
-            FxaEvent::CancelOAuthFlow => Ok(Complete(FxaState::Disconnected)),
+            FxaEvent::CancelOAuthFlow | FxaEvent::FlowTimedOut => {
+                Ok(Complete(FxaState::Disconnected))
+            }
             e => Err(Error::InvalidStateTransition(format!(
                 "Authenticating -> {e}"
             ))),
@@ -28,6 +30,7 @@ impl InternalStateMachine for AuthenticatingStateMachine {
     fn next_state(&self, state: State, event: Event) -> Result<State> {
         Ok(match (state, event) {
             (CompleteOAuthFlow { .. }, CompleteOAuthFlowSuccess) => InitializeDevice,
+            (CompleteOAuthFlow { .. }, TwoFactorRequired) => Complete(FxaState::TwoFactorRequired),
             (CompleteOAuthFlow { .. }, CallError) => Complete(FxaState::Disconnected),
             (InitializeDevice, InitializeDeviceSuccess) => Complete(FxaState::Connected),
             (InitializeDevice, CallError) => Complete(FxaState::Disconnected),
@@ -61,6 +64,10 @@ mod test {
             tester.peek_next_state(CallError),
             Complete(FxaState::Disconnected)
         );
+        assert_eq!(
+            tester.peek_next_state(TwoFactorRequired),
+            Complete(FxaState::TwoFactorRequired)
+        );
 
         tester.next_state(CompleteOAuthFlowSuccess);
         assert_eq!(tester.state, InitializeDevice);
@@ -79,4 +86,10 @@ mod test {
         let tester = StateMachineTester::new(AuthenticatingStateMachine, FxaEvent::CancelOAuthFlow);
         assert_eq!(tester.state, Complete(FxaState::Disconnected));
     }
+
+    #[test]
+    fn test_flow_timed_out() {
+        let tester = StateMachineTester::new(AuthenticatingStateMachine, FxaEvent::FlowTimedOut);
+        assert_eq!(tester.state, Complete(FxaState::Disconnected));
+    }
 }
@@ -8,6 +8,7 @@ mod auth_issues;
 mod authenticating;
 mod connected;
 mod disconnected;
+mod two_factor_required;
 mod uninitialized;
 
 use crate::{
@@ -19,6 +20,7 @@ pub use authenticating::AuthenticatingStateMachine;
 pub use connected::ConnectedStateMachine;
 pub use disconnected::DisconnectedStateMachine;
 use error_support::convert_log_report_error;
+pub use two_factor_required::TwoFactorRequiredStateMachine;
 pub use uninitialized::UninitializedStateMachine;
 
 pub trait InternalStateMachine {
@@ -51,6 +53,10 @@ pub enum State {
         code: String,
         state: String,
     },
+    VerifyTotpCode {
+        code: String,
+    },
+    CompleteTwoFactorOAuthFlow,
     InitializeDevice,
     EnsureDeviceCapabilities,
     CheckAuthorizationStatus,
@@ -78,6 +84,9 @@ pub enum Event {
         oauth_url: String,
     },
     CompleteOAuthFlowSuccess,
+    VerifyTotpCodeSuccess {
+        valid: bool,
+    },
     InitializeDeviceSuccess,
     EnsureDeviceCapabilitiesSuccess,
     CheckAuthorizationStatusSuccess {
@@ -89,6 +98,13 @@ pub enum Event {
     /// Auth error for the `ensure_capabilities` call that we do on startup.
     /// This should likely go away when we do https://bugzilla.mozilla.org/show_bug.cgi?id=1868418
     EnsureCapabilitiesAuthError,
+    /// The server wants the user to pass a two-step authentication challenge before it
+    /// completes the OAuth flow.
+    TwoFactorRequired,
+    /// Network error, after retrying, for the calls made while the user is entering a
+    /// two-step authentication code.  Those stay in the current state so the user can try
+    /// again, rather than having to sign in from scratch.
+    NetworkError,
 }
 
 impl State {
@@ -142,6 +158,14 @@ impl State {
                 account.complete_oauth_flow(code, state)?;
                 Event::CompleteOAuthFlowSuccess
             }
+            State::VerifyTotpCode { code } => {
+                let valid = account.verify_totp_code(code)?;
+                Event::VerifyTotpCodeSuccess { valid }
+            }
+            State::CompleteTwoFactorOAuthFlow => {
+                account.complete_two_factor_oauth_flow()?;
+                Event::CompleteOAuthFlowSuccess
+            }
             State::InitializeDevice => {
                 account.initialize_device(
                     &device_config.name,
@@ -196,14 +220,14 @@ impl<'a> CallErrorHandler<'a> {
         }
         // Report the error and convert it to `FxaError` which makes it easier to handle.
         // For example, multiple `Error` variants map to `FxaError::Authentication`.
-        log::warn!("handling error: {e}");
+        warn!("handling error: {e}");
         match convert_log_report_error(e) {
             FxaError::Network => {
                 if self.network_retries < NETWORK_RETRY_LIMIT {
                     self.network_retries += 1;
                     CallResult::Retry
                 } else {
-                    CallResult::Finished(Event::CallError)
+                    CallResult::Finished(self.event_for_network_error())
                 }
             }
             FxaError::Authentication => {
@@ -225,10 +249,29 @@ impl<'a> CallErrorHandler<'a> {
                     CallResult::Finished(self.event_for_auth_error())
                 }
             }
+            FxaError::TwoFactorRequired
+                if matches!(
+                    self.state,
+                    State::CompleteOAuthFlow { .. } | State::CompleteTwoFactorOAuthFlow
+                ) =>
+            {
+                CallResult::Finished(Event::TwoFactorRequired)
+            }
             _ => CallResult::Finished(Event::CallError),
         }
     }
 
+    fn event_for_network_error(&self) -> Event {
+        if matches!(
+            self.state,
+            State::VerifyTotpCode { .. } | State::CompleteTwoFactorOAuthFlow
+        ) {
+            Event::NetworkError
+        } else {
+            Event::CallError
+        }
+    }
+
     fn event_for_auth_error(&self) -> Event {
         if matches!(self.state, State::EnsureDeviceCapabilities) {
             Event::EnsureCapabilitiesAuthError
@@ -0,0 +1,109 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::{invalid_transition, Event, InternalStateMachine, State};
+use crate::{Error, FxaEvent, FxaState, Result};
+
+pub struct TwoFactorRequiredStateMachine;
+
+// Save some typing
+use Event::*;
+use State::*;
+
+impl InternalStateMachine for TwoFactorRequiredStateMachine {
+    fn initial_state(&self, event: FxaEvent) -> Result<State> {
+        match event {
+            FxaEvent::SubmitTotpCode { code } => Ok(VerifyTotpCode { code }),
+            FxaEvent::CancelOAuthFlow | FxaEvent::FlowTimedOut => {
+                Ok(Complete(FxaState::Disconnected))
+            }
+            e => Err(Error::InvalidStateTransition(format!(
+                "TwoFactorRequired -> {e}"
+            ))),
+        }
+    }
+
+    fn next_state(&self, state: State, event: Event) -> Result<State> {
+        Ok(match (state, event) {
+            (VerifyTotpCode { .. }, VerifyTotpCodeSuccess { valid: true }) => {
+                CompleteTwoFactorOAuthFlow
+            }
+            // Stay in `TwoFactorRequired`, so the user can try another code
+            (VerifyTotpCode { .. }, VerifyTotpCodeSuccess { valid: false }) => Cancel,
+            (VerifyTotpCode { .. }, NetworkError) => Cancel,
+            (VerifyTotpCode { .. }, CallError) => Complete(FxaState::Disconnected),
+            (CompleteTwoFactorOAuthFlow, CompleteOAuthFlowSuccess) => InitializeDevice,
+            (CompleteTwoFactorOAuthFlow, NetworkError) => Cancel,
+            (CompleteTwoFactorOAuthFlow, CallError) => Complete(FxaState::Disconnected),
+            (CompleteTwoFactorOAuthFlow, TwoFactorRequired) => Cancel,
+            (InitializeDevice, InitializeDeviceSuccess) => Complete(FxaState::Connected),
+            (InitializeDevice, CallError) => Complete(FxaState::Disconnected),
+            (state, event) => return invalid_transition(state, event),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::StateMachineTester;
+    use super::*;
+
+    #[test]
+    fn test_submit_totp_code() {
+        let mut tester = StateMachineTester::new(
+            TwoFactorRequiredStateMachine,
+            FxaEvent::SubmitTotpCode {
+                code: "123456".to_owned(),
+            },
+        );
+        assert_eq!(
+            tester.state,
+            VerifyTotpCode {
+                code: "123456".to_owned(),
+            }
+        );
+        assert_eq!(
+            tester.peek_next_state(CallError),
+            Complete(FxaState::Disconnected)
+        );
+        assert_eq!(tester.peek_next_state(NetworkError), Cancel);
+        assert_eq!(
+            tester.peek_next_state(VerifyTotpCodeSuccess { valid: false }),
+            Cancel
+        );
+
+        tester.next_state(VerifyTotpCodeSuccess { valid: true });
+        assert_eq!(tester.state, CompleteTwoFactorOAuthFlow);
+        assert_eq!(
+            tester.peek_next_state(CallError),
+            Complete(FxaState::Disconnected)
+        );
+        assert_eq!(tester.peek_next_state(NetworkError), Cancel);
+        assert_eq!(tester.peek_next_state(TwoFactorRequired), Cancel);
+
+        tester.next_state(CompleteOAuthFlowSuccess);
+        assert_eq!(tester.state, InitializeDevice);
+        assert_eq!(
+            tester.peek_next_state(CallError),
+            Complete(FxaState::Disconnected)
+        );
+        assert_eq!(
+            tester.peek_next_state(InitializeDeviceSuccess),
+            Complete(FxaState::Connected)
+        );
+    }
+
+    #[test]
+    fn test_cancel_oauth_flow() {
+        let tester =
+            StateMachineTester::new(TwoFactorRequiredStateMachine, FxaEvent::CancelOAuthFlow);
+        assert_eq!(tester.state, Complete(FxaState::Disconnected));
+    }
+
+    #[test]
+    fn test_flow_timed_out() {
+        let tester = StateMachineTester::new(TwoFactorRequiredStateMachine, FxaEvent::FlowTimedOut);
+        assert_eq!(tester.state, Complete(FxaState::Disconnected));
+    }
+}
@@ -8,7 +8,10 @@
 
 use error_support::breadcrumb;
 
-use crate::{internal::FirefoxAccount, DeviceConfig, Error, FxaEvent, FxaState, Result};
+use crate::{
+    internal::{util, FirefoxAccount},
+    DeviceConfig, Error, FxaEvent, FxaState, FxaStateTimeouts, Result,
+};
 
 pub mod checker;
 mod display;
@@ -30,8 +33,56 @@ impl FirefoxAccount {
     /// Process an event (login, logout, etc).
     ///
     /// On success, returns the new state.
-    /// On error, the state will remain the same.
+    /// On error, the state will remain the same, unless the current flow timed out before the
+    /// event was handled.
     pub fn process_event(&mut self, event: FxaEvent) -> Result<FxaState> {
+        // Time out a flow that's been waiting too long before handling the event, so that it's
+        // handled from the state the user would expect. For example, `BeginOAuthFlow` starts a
+        // new flow rather than failing because the old one is still in progress.
+        if !matches!(event, FxaEvent::FlowTimedOut | FxaEvent::CancelOAuthFlow) {
+            self.check_state_timeout()?;
+        }
+        self.process_event_and_record_metrics(event)
+    }
+
+    pub fn set_state_timeouts(&mut self, timeouts: FxaStateTimeouts) {
+        self.state_timeouts = timeouts;
+    }
+
+    /// Send `FxaEvent::FlowTimedOut` if the current state has been waiting for longer than its
+    /// timeout.
+    pub fn check_state_timeout(&mut self) -> Result<FxaState> {
+        let timeout_ms = match self.auth_state {
+            FxaState::Authenticating { .. } => self.state_timeouts.authenticating_ms,
+            FxaState::TwoFactorRequired => self.state_timeouts.two_factor_required_ms,
+            _ => return Ok(self.auth_state.clone()),
+        };
+        let waited_ms = util::now().saturating_sub(self.auth_state_changed_at);
+        if waited_ms < u64::try_from(timeout_ms).unwrap_or(0) {
+            return Ok(self.auth_state.clone());
+        }
+        breadcrumb!(
+            "FxaStateMachine: {} timed out after {waited_ms}ms",
+            self.auth_state
+        );
+        self.process_event_and_record_metrics(FxaEvent::FlowTimedOut)
+    }
+
+    fn process_event_and_record_metrics(&mut self, event: FxaEvent) -> Result<FxaState> {
+        let from_state = self.auth_state.clone();
+        let metrics_event = event.clone();
+        let result = self.process_event_for_current_state(event);
+        self.flow_metrics.record(
+            &metrics_event,
+            &from_state,
+            &self.auth_state,
+            result.is_err(),
+            util::now(),
+        );
+        result
+    }
+
+    fn process_event_for_current_state(&mut self, event: FxaEvent) -> Result<FxaState> {
         match &self.auth_state {
             FxaState::Uninitialized => self.process_event_with_internal_state_machine(
                 internal_machines::UninitializedStateMachine,
@@ -45,6 +96,10 @@ impl FirefoxAccount {
                 internal_machines::AuthenticatingStateMachine,
                 event,
             ),
+            FxaState::TwoFactorRequired => self.process_event_with_internal_state_machine(
+                internal_machines::TwoFactorRequiredStateMachine,
+                event,
+            ),
             FxaState::Connected => self.process_event_with_internal_state_machine(
                 internal_machines::ConnectedStateMachine,
                 event,
@@ -81,6 +136,7 @@ impl FirefoxAccount {
                 InternalState::Complete(new_state) => {
                     breadcrumb!("FxaStateMachine.process_event finished (Complete({new_state}))");
                     self.auth_state = new_state.clone();
+                    self.auth_state_changed_at = util::now();
                     return Ok(new_state);
                 }
                 InternalState::Cancel => {
@@ -121,3 +177,47 @@ impl FirefoxAccount {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::config::Config;
+    use sync15::DeviceType;
+
+    #[test]
+    fn test_state_timeout() {
+        let mut fxa =
+            FirefoxAccount::with_config(Config::stable_dev("12345678", "https://foo.bar"));
+        fxa.device_config = Some(DeviceConfig {
+            name: "My Phone".to_owned(),
+            device_type: DeviceType::Mobile,
+            capabilities: vec![],
+        });
+        let authenticating = FxaState::Authenticating {
+            oauth_url: "https://example.com/oauth".to_owned(),
+        };
+        fxa.auth_state = authenticating.clone();
+        fxa.auth_state_changed_at = util::now();
+        assert_eq!(fxa.check_state_timeout().unwrap(), authenticating);
+
+        fxa.set_state_timeouts(FxaStateTimeouts {
+            authenticating_ms: 1000,
+            two_factor_required_ms: 500,
+        });
+        fxa.auth_state_changed_at = util::now() - 2000;
+        assert_eq!(fxa.check_state_timeout().unwrap(), FxaState::Disconnected);
+        // Other states don't time out.
+        assert_eq!(fxa.check_state_timeout().unwrap(), FxaState::Disconnected);
+
+        // Events are handled from the state the flow timed out to.
+        fxa.auth_state = FxaState::TwoFactorRequired;
+        fxa.auth_state_changed_at = util::now() - 1000;
+        assert!(matches!(
+            fxa.process_event(FxaEvent::SubmitTotpCode {
+                code: "123456".to_owned()
+            }),
+            Err(Error::InvalidStateTransition(_))
+        ));
+        assert_eq!(fxa.get_state(), FxaState::Disconnected);
+    }
+}
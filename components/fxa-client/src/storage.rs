@@ -16,10 +16,16 @@
 //! After calling any such method, use [`FirefoxAccount::to_json`] to serialize
 //! the modified account state and persist the resulting string in application
 //! settings.
+//!
+//! Applications which share the state between processes, such as an app and its extensions,
+//! should instead use [`FirefoxAccount::from_json_with_lock`] and
+//! [`FirefoxAccount::to_json_with_lock`], which keep one process from silently overwriting
+//! another's changes.
 
 use crate::{internal, ApiResult, Error, FirefoxAccount};
 use error_support::handle_error;
 use parking_lot::Mutex;
+use std::collections::HashMap;
 
 impl FirefoxAccount {
     /// Restore a [`FirefoxAccount`] instance from serialized state.
@@ -53,4 +59,115 @@ impl FirefoxAccount {
     pub fn to_json(&self) -> ApiResult<String> {
         self.internal.lock().to_json()
     }
+
+    /// Restore a [`FirefoxAccount`] instance from a state file shared with other processes.
+    ///
+    /// The file must have been written by [`FirefoxAccount::to_json_with_lock`]. It's read
+    /// while holding a lock on it, and the account remembers what it read, so that it can
+    /// detect changes other processes make to the file before it's next saved.
+    #[handle_error(Error)]
+    pub fn from_json_with_lock(path: &str) -> ApiResult<FirefoxAccount> {
+        Ok(FirefoxAccount {
+            internal: Mutex::new(internal::FirefoxAccount::from_json_with_lock(path)?),
+        })
+    }
+
+    /// Save current state to a file shared with other processes.
+    ///
+    /// This holds a lock on the file while writing it, and checks whether another process
+    /// wrote to it since this account last read or wrote it. If so, `conflict_policy` decides
+    /// what happens:
+    ///
+    ///   - [`StateConflictPolicy::FailFast`] leaves the file alone and throws
+    ///     [`StateConflict`](FxaError::StateConflict), so the application can reload the state
+    ///     and redo its operation.
+    ///   - [`StateConflictPolicy::Merge`] combines the two states, keeping the changes each
+    ///     process made. The merged state is written to the file and replaces this account's
+    ///     state. If both processes changed the same thing, or signed in separately, nothing is
+    ///     written and [`StateConflict`](FxaError::StateConflict) is thrown.
+    ///
+    /// `StateConflict` is also thrown if another process holds the lock for more than a few
+    /// seconds.
+    ///
+    /// **⚠️ Warning:** see [`FirefoxAccount::to_json`] about storing the state securely.
+    #[handle_error(Error)]
+    pub fn to_json_with_lock(
+        &self,
+        path: &str,
+        conflict_policy: StateConflictPolicy,
+    ) -> ApiResult<()> {
+        self.internal.lock().save_with_lock(path, conflict_policy)
+    }
+
+    /// Export the current state for debugging.
+    ///
+    /// This returns the same JSON as [`FirefoxAccount::to_json`], but with every access token,
+    /// refresh token, session token and encryption key, the account's uid, and the email address,
+    /// display name and avatar URL from the user's profile replaced by a fingerprint (a truncated
+    /// SHA-256 hash), so that users can attach it to bug reports. Timestamps and
+    /// the rest of the state are left as they are, and the output is deterministic, so engineers
+    /// can still reason about the state of the account.
+    ///
+    /// The result can't be passed to [`FirefoxAccount::from_json`].
+    #[handle_error(Error)]
+    pub fn export_sanitized_state(&self) -> ApiResult<String> {
+        self.internal.lock().export_sanitized_state()
+    }
+
+    /// Set the headers to send with every request to the FxA servers.
+    ///
+    /// These are the [`FxaConfig::extra_headers`](crate::FxaConfig::extra_headers). They
+    /// aren't saved with the rest of the state, since they may hold credentials, so
+    /// applications which use them should call this after restoring the account with
+    /// [`FirefoxAccount::from_json`] or [`FirefoxAccount::from_json_with_lock`].
+    pub fn set_extra_headers(&self, extra_headers: HashMap<String, String>) {
+        self.internal.lock().set_extra_headers(extra_headers)
+    }
+
+    /// How old the state this account was restored from is.
+    ///
+    /// [`FirefoxAccount::from_json`] accepts state however old it is, since it may still be
+    /// perfectly usable. Applications can check this after restoring the account, and ask the
+    /// user to sign in again, or show a migration prompt, if the state is
+    /// [`Stale`](StateFreshnessStatus::Stale) or otherwise implausible.
+    pub fn get_state_freshness(&self) -> StateFreshness {
+        self.internal.lock().get_state_freshness()
+    }
+}
+
+/// How old the state an account was restored from is, from
+/// [`FirefoxAccount::get_state_freshness`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateFreshness {
+    pub status: StateFreshnessStatus,
+    /// When the state was saved, in milliseconds since the epoch.
+    pub persisted_at: Option<i64>,
+    /// The application-services version which saved the state.
+    pub persisted_by_version: Option<String>,
+    /// The schema version the state was saved with. Older schemas are migrated to the current
+    /// one when the state is loaded.
+    pub schema_version: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateFreshnessStatus {
+    /// The state was saved recently, or the account wasn't restored from saved state.
+    Fresh,
+    /// The state was saved more than 180 days ago.
+    Stale,
+    /// The state was saved by a version which didn't record when, so it's at least as old as
+    /// that version.
+    Unknown,
+    /// The state was saved in the future, which means the device's clock has changed, or the
+    /// state was copied from another device.
+    FutureDated,
+}
+
+/// What [`FirefoxAccount::to_json_with_lock`] does when another process changed the state file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateConflictPolicy {
+    /// Leave the file alone and throw an error.
+    FailFast,
+    /// Merge the two states and write the result.
+    Merge,
 }
@@ -15,10 +15,11 @@
 //!      typically managed on behalf of web content that runs within the context
 //!      of the application.
 
-use crate::{ApiResult, Error, FirefoxAccount};
+use crate::{internal::scopes, ApiResult, Error, FirefoxAccount};
 use error_support::handle_error;
 use serde_derive::*;
 use std::convert::{TryFrom, TryInto};
+use std::fmt;
 
 impl FirefoxAccount {
     /// Get a short-lived OAuth access token for the user's account.
@@ -37,6 +38,8 @@ impl FirefoxAccount {
     ///    - `scope` - the OAuth scope to be granted by the token.
     ///        - This must be one of the scopes requested during the signin flow.
     ///        - Only a single scope is supported; for multiple scopes request multiple tokens.
+    ///        - [`Scope::Other`] scopes are checked to be well-formed before the server is
+    ///          asked for a token.
     ///    - `ttl` - optionally, the time for which the token should be valid, in seconds.
     ///
     /// # Notes
@@ -45,12 +48,12 @@ impl FirefoxAccount {
     ///      token, it should call [`clear_access_token_cache`](FirefoxAccount::clear_access_token_cache)
     ///      before requesting a fresh token.
     #[handle_error(Error)]
-    pub fn get_access_token(&self, scope: &str, ttl: Option<i64>) -> ApiResult<AccessTokenInfo> {
+    pub fn get_access_token(&self, scope: &Scope, ttl: Option<i64>) -> ApiResult<AccessTokenInfo> {
         // Signedness converstion for Kotlin compatibility :-/
         let ttl = ttl.map(|ttl| u64::try_from(ttl).unwrap_or_default());
         self.internal
             .lock()
-            .get_access_token(scope, ttl)?
+            .get_access_token(scope.as_str(), ttl)?
             .try_into()
     }
 
@@ -104,6 +107,11 @@ impl FirefoxAccount {
     /// # Arguments
     ///
     ///    - `params` - the OAuth parameters from the incoming authorization request
+    ///
+    /// # Notes
+    ///
+    ///    - The request's `access_type` and PKCE parameters are checked before the server is
+    ///      asked for a code. Only the `S256` PKCE method is supported.
     #[handle_error(Error)]
     pub fn authorize_code_using_session_token(
         &self,
@@ -114,6 +122,63 @@ impl FirefoxAccount {
             .authorize_code_using_session_token(params)
     }
 
+    /// Get the OAuth scopes the user has granted to this application.
+    ///
+    /// Access tokens can only be obtained for these scopes. The list is empty when the
+    /// user isn't signed in.
+    pub fn get_granted_scopes(&self) -> Vec<String> {
+        self.internal.lock().get_granted_scopes()
+    }
+
+    /// Ask for additional OAuth scopes, without sending the user through another signin flow.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    ///
+    /// This lets applications ask for scopes when they're first needed (for example, the
+    /// Relay scope when the user first tries to generate an email mask) rather than all at
+    /// signin. The stored session token is used to authorize a new refresh token covering
+    /// both the existing and the additional scopes, and the current device record is carried
+    /// over to it.
+    ///
+    /// # Arguments
+    ///
+    ///    - `scopes` - the OAuth scopes to add. Scopes already granted are ignored.
+    ///
+    /// # Notes
+    ///
+    ///    - A session token is required, so this is only available to applications that have
+    ///      requested the `https://identity.mozilla.com/tokens/session` scope.
+    ///    - Scoped keys aren't delivered this way, so scopes which need them (such as sync)
+    ///      must still be requested using [`begin_oauth_flow`](FirefoxAccount::begin_oauth_flow).
+    ///    - The server may grant fewer scopes than were asked for; check
+    ///      [`get_granted_scopes`](FirefoxAccount::get_granted_scopes) afterwards.
+    #[handle_error(Error)]
+    pub fn request_additional_scopes<T: AsRef<str>>(&self, scopes: &[T]) -> ApiResult<()> {
+        let scopes = scopes.iter().map(T::as_ref).collect::<Vec<_>>();
+        self.internal.lock().request_additional_scopes(&scopes)
+    }
+
+    /// Get a token for another service, such as a VPN or Relay backend.
+    ///
+    /// The user's refresh token is exchanged for a short-lived token whose audience is the
+    /// given service, so that the service can verify it without accepting tokens meant for
+    /// anyone else. Tokens are cached in memory for each service, and a new one is only
+    /// fetched when the cached one is about to expire.
+    ///
+    /// # Arguments
+    ///
+    ///    - `service` - the identifier of the service the token is for.
+    ///
+    /// # Notes
+    ///
+    ///    - If the service rejects the token, the application should call
+    ///      [`clear_access_token_cache`](FirefoxAccount::clear_access_token_cache) before
+    ///      requesting a fresh one.
+    #[handle_error(Error)]
+    pub fn get_service_token(&self, service: &str) -> ApiResult<AccessTokenInfo> {
+        self.internal.lock().get_service_token(service)?.try_into()
+    }
+
     /// Clear the access token cache in response to an auth failure.
     ///
     /// **💾 This method alters the persisted account state.**
@@ -126,6 +191,65 @@ impl FirefoxAccount {
     }
 }
 
+/// An OAuth scope.
+///
+/// The scopes used by Firefox applications have their own variants, so that typos in them
+/// are caught at compile time rather than by the server. Any other scope can be given as
+/// [`Scope::Other`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// `profile`: read the user's profile.
+    Profile,
+    /// `https://identity.mozilla.com/apps/oldsync`: access the user's Firefox Sync data.
+    OldSync,
+    /// `https://identity.mozilla.com/tokens/session`: hold a session token on behalf of
+    /// web content.
+    Session,
+    /// Any other scope. Prefer [`parse_scope`], which checks that the scope is well-formed
+    /// and picks the named variant for known scopes.
+    Other { scope: String },
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Scope::Profile => scopes::PROFILE,
+            Scope::OldSync => scopes::OLD_SYNC,
+            Scope::Session => scopes::SESSION,
+            Scope::Other { scope } => scope,
+        }
+    }
+}
+
+impl AsRef<str> for Scope {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Parse an OAuth scope, such as one returned by
+/// [`get_granted_scopes`](FirefoxAccount::get_granted_scopes).
+///
+/// This fails if `scope` isn't a single, well-formed scope.
+#[handle_error(Error)]
+pub fn parse_scope(scope: &str) -> ApiResult<Scope> {
+    scopes::check(scope)?;
+    Ok(match scope {
+        scopes::PROFILE => Scope::Profile,
+        scopes::OLD_SYNC => Scope::OldSync,
+        scopes::SESSION => Scope::Session,
+        _ => Scope::Other {
+            scope: scope.to_string(),
+        },
+    })
+}
+
 /// An OAuth access token, with its associated keys and metadata.
 ///
 /// This struct represents an FxA OAuth access token, which can be used to access a resource
@@ -0,0 +1,354 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! # WebChannel messages
+//!
+//! When an application shows the FxA web content in an embedded browser, that content
+//! talks to the application by sending JSON messages over a
+//! [WebChannel](https://mozilla.github.io/ecosystem-platform/relying-parties/reference/integration-with-fxa#webchannel-integrations)
+//! called `account_updates`. These functions parse those messages, turn them into
+//! [`FxaEvent`]s for the state machine, and build the replies the web content expects.
+//!
+//! A typical integration looks like:
+//!
+//!   - Pass each incoming message to [`parse_web_channel_message`]. Messages for commands
+//!     this library doesn't handle give `None`, and can be handled by the application or
+//!     ignored.
+//!   - For [`WebChannelCommand::CanLinkAccount`], decide whether the user may sign in with
+//!     that email (for example, by asking them if it's different from the last account),
+//!     and send back the result of [`web_channel_response`].
+//!   - Pass any event from [`web_channel_message_to_event`] to
+//!     [`process_event`](crate::FirefoxAccount::process_event).
+
+use crate::{ApiResult, Error, FxaEvent, Result};
+use error_support::handle_error;
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use serde_json::json;
+
+const WEB_CHANNEL_ID: &str = "account_updates";
+
+const COMMAND_CAN_LINK_ACCOUNT: &str = "fxaccounts:can_link_account";
+const COMMAND_LOGIN: &str = "fxaccounts:login";
+const COMMAND_OAUTH_LOGIN: &str = "fxaccounts:oauth_login";
+const COMMAND_LOGOUT: &str = "fxaccounts:logout";
+const COMMAND_DELETE_ACCOUNT: &str = "fxaccounts:delete";
+
+/// A message sent by the FxA web content.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WebChannelMessage {
+    /// Identifies the message, so the reply can be matched up with it.
+    pub message_id: String,
+    pub command: WebChannelCommand,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WebChannelCommand {
+    /// The user is about to sign in with `email`. The web content waits for a reply
+    /// saying whether that's allowed.
+    CanLinkAccount { email: String },
+    /// The user signed in. Applications using OAuth don't need to do anything with this:
+    /// the sign-in is finished by the `OAuthLogin` command which follows it.
+    Login {
+        email: String,
+        uid: String,
+        verified: bool,
+    },
+    /// The user finished an OAuth flow, which should be completed with `code` and `state`.
+    OAuthLogin { code: String, state: String },
+    /// The user signed out from the web content.
+    Logout { uid: Option<String> },
+    /// The user deleted their account.
+    DeleteAccount { uid: Option<String> },
+}
+
+impl WebChannelCommand {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::CanLinkAccount { .. } => COMMAND_CAN_LINK_ACCOUNT,
+            Self::Login { .. } => COMMAND_LOGIN,
+            Self::OAuthLogin { .. } => COMMAND_OAUTH_LOGIN,
+            Self::Logout { .. } => COMMAND_LOGOUT,
+            Self::DeleteAccount { .. } => COMMAND_DELETE_ACCOUNT,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawMessage {
+    command: String,
+    #[serde(default)]
+    message_id: String,
+    #[serde(default)]
+    data: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct CanLinkAccountData {
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct LoginData {
+    email: String,
+    uid: String,
+    #[serde(default)]
+    verified: bool,
+}
+
+#[derive(Deserialize)]
+struct OAuthLoginData {
+    code: String,
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct AccountData {
+    uid: Option<String>,
+}
+
+/// Parse a message sent by the FxA web content.
+///
+/// `message` can either be the whole WebChannel message, with the `id` and `message`
+/// fields, or only its `message` field, which is what some embedded browsers pass on.
+///
+/// Returns `None` for commands this library doesn't handle, and throws if the message
+/// isn't valid JSON, or is missing data its command requires.
+#[handle_error(Error)]
+pub fn parse_web_channel_message(message: &str) -> ApiResult<Option<WebChannelMessage>> {
+    let mut json: serde_json::Value = serde_json::from_str(message)?;
+    if let Some(inner) = json.get_mut("message").filter(|m| m.is_object()) {
+        json = inner.take();
+    }
+    let raw: RawMessage = serde_json::from_value(json)?;
+    let command = match raw.command.as_str() {
+        COMMAND_CAN_LINK_ACCOUNT => {
+            let data: CanLinkAccountData = parse_data(raw.data)?;
+            WebChannelCommand::CanLinkAccount { email: data.email }
+        }
+        COMMAND_LOGIN => {
+            let data: LoginData = parse_data(raw.data)?;
+            WebChannelCommand::Login {
+                email: data.email,
+                uid: data.uid,
+                verified: data.verified,
+            }
+        }
+        COMMAND_OAUTH_LOGIN => {
+            let data: OAuthLoginData = parse_data(raw.data)?;
+            WebChannelCommand::OAuthLogin {
+                code: data.code,
+                state: data.state,
+            }
+        }
+        COMMAND_LOGOUT => {
+            let data: AccountData = parse_data(raw.data)?;
+            WebChannelCommand::Logout { uid: data.uid }
+        }
+        COMMAND_DELETE_ACCOUNT => {
+            let data: AccountData = parse_data(raw.data)?;
+            WebChannelCommand::DeleteAccount { uid: data.uid }
+        }
+        other => {
+            debug!("Ignoring WebChannel command {}", other);
+            return Ok(None);
+        }
+    };
+    Ok(Some(WebChannelMessage {
+        message_id: raw.message_id,
+        command,
+    }))
+}
+
+// Commands without any data sometimes leave out the `data` field altogether.
+fn parse_data<T: DeserializeOwned>(data: serde_json::Value) -> Result<T> {
+    Ok(match data {
+        serde_json::Value::Null => serde_json::from_value(json!({}))?,
+        data => serde_json::from_value(data)?,
+    })
+}
+
+/// Get the [`FxaEvent`] to send to the state machine for a message, if any.
+///
+/// Note that `Logout` and `DeleteAccount` become [`FxaEvent::Disconnect`] whichever
+/// account they're for; applications which can have the web content signed in to a
+/// different account should check the `uid` first.
+pub fn web_channel_message_to_event(message: WebChannelMessage) -> Option<FxaEvent> {
+    match message.command {
+        WebChannelCommand::OAuthLogin { code, state } => {
+            Some(FxaEvent::CompleteOAuthFlow { code, state })
+        }
+        WebChannelCommand::Logout { .. } | WebChannelCommand::DeleteAccount { .. } => {
+            Some(FxaEvent::Disconnect)
+        }
+        WebChannelCommand::CanLinkAccount { .. } | WebChannelCommand::Login { .. } => None,
+    }
+}
+
+/// Build the reply to a message, to send back to the web content.
+///
+/// Only `CanLinkAccount` needs a reply, where `ok` says whether the user may sign in.
+pub fn web_channel_response(message: WebChannelMessage, ok: bool) -> String {
+    json!({
+        "id": WEB_CHANNEL_ID,
+        "message": {
+            "command": message.command.name(),
+            "messageId": message.message_id,
+            "data": { "ok": ok },
+        },
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FxaError;
+
+    fn parse(message: serde_json::Value) -> Option<WebChannelMessage> {
+        parse_web_channel_message(&message.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let message = parse(json!({
+            "id": "account_updates",
+            "message": {
+                "command": "fxaccounts:can_link_account",
+                "messageId": "1",
+                "data": { "email": "foo@example.com" },
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            message,
+            WebChannelMessage {
+                message_id: "1".to_string(),
+                command: WebChannelCommand::CanLinkAccount {
+                    email: "foo@example.com".to_string()
+                },
+            }
+        );
+        assert_eq!(web_channel_message_to_event(message), None);
+
+        // The `message` field on its own works too.
+        let message = parse(json!({
+            "command": "fxaccounts:login",
+            "messageId": "2",
+            "data": {
+                "email": "foo@example.com",
+                "uid": "123",
+                "verified": true,
+                "sessionToken": "secret",
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            message.command,
+            WebChannelCommand::Login {
+                email: "foo@example.com".to_string(),
+                uid: "123".to_string(),
+                verified: true,
+            }
+        );
+        assert_eq!(web_channel_message_to_event(message), None);
+
+        let message = parse(json!({
+            "command": "fxaccounts:oauth_login",
+            "messageId": "3",
+            "data": { "code": "the-code", "state": "the-state", "redirect": "urn:foo" },
+        }))
+        .unwrap();
+        assert_eq!(
+            web_channel_message_to_event(message),
+            Some(FxaEvent::CompleteOAuthFlow {
+                code: "the-code".to_string(),
+                state: "the-state".to_string(),
+            })
+        );
+
+        let message = parse(json!({
+            "command": "fxaccounts:logout",
+            "messageId": "4",
+            "data": { "uid": "123" },
+        }))
+        .unwrap();
+        assert_eq!(
+            message.command,
+            WebChannelCommand::Logout {
+                uid: Some("123".to_string())
+            }
+        );
+        assert_eq!(
+            web_channel_message_to_event(message),
+            Some(FxaEvent::Disconnect)
+        );
+
+        let message = parse(json!({
+            "command": "fxaccounts:delete",
+            "messageId": "5",
+        }))
+        .unwrap();
+        assert_eq!(
+            message.command,
+            WebChannelCommand::DeleteAccount { uid: None }
+        );
+        assert_eq!(
+            web_channel_message_to_event(message),
+            Some(FxaEvent::Disconnect)
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown_and_invalid() {
+        assert_eq!(
+            parse(json!({
+                "command": "fxaccounts:fxa_status",
+                "messageId": "1",
+                "data": { "service": "sync" },
+            })),
+            None
+        );
+        assert!(matches!(
+            parse_web_channel_message("not json"),
+            Err(FxaError::Other(_))
+        ));
+        // An OAuth login needs a code.
+        assert!(matches!(
+            parse_web_channel_message(
+                &json!({
+                    "command": "fxaccounts:oauth_login",
+                    "messageId": "1",
+                    "data": { "state": "the-state" },
+                })
+                .to_string()
+            ),
+            Err(FxaError::Other(_))
+        ));
+    }
+
+    #[test]
+    fn test_response() {
+        let message = WebChannelMessage {
+            message_id: "1".to_string(),
+            command: WebChannelCommand::CanLinkAccount {
+                email: "foo@example.com".to_string(),
+            },
+        };
+        let response: serde_json::Value =
+            serde_json::from_str(&web_channel_response(message, true)).unwrap();
+        assert_eq!(
+            response,
+            json!({
+                "id": "account_updates",
+                "message": {
+                    "command": "fxaccounts:can_link_account",
+                    "messageId": "1",
+                    "data": { "ok": true },
+                },
+            })
+        );
+    }
+}
@@ -101,6 +101,35 @@ class DatabaseLoginsStorage(dbPath: String) : AutoCloseable {
         }
     }
 
+    @Throws(LoginsApiException::class)
+    fun getKeyStatus(encryptionKey: String): KeyStatus {
+        return readQueryCounters.measure {
+            store.getKeyStatus(encryptionKey)
+        }
+    }
+
+    /**
+     * Deletes the stored logins after the key was lost, and records how many
+     * were thrown away.
+     *
+     * Confirm first that [getKeyStatus] returns [KeyStatus.Lost] for
+     * [encryptionKey]; this throws without deleting anything if the key can
+     * decrypt any of the logins.
+     */
+    @Throws(LoginsApiException::class)
+    fun recoverByDiscardingUndecryptable(encryptionKey: String): KeyRecoveryResult {
+        val result = writeQueryCounters.measure {
+            store.recoverByDiscardingUndecryptable(encryptionKey)
+        }
+        LoginsStoreMetrics.undecryptableLoginsDiscarded.record(
+            LoginsStoreMetrics.UndecryptableLoginsDiscardedExtra(
+                localDiscarded = result.localDiscarded.toInt(),
+                mirrorDiscarded = result.mirrorDiscarded.toInt(),
+            ),
+        )
+        return result
+    }
+
     fun registerWithSyncManager() {
         return store.registerWithSyncManager()
     }
@@ -82,6 +82,25 @@ open class LoginsStorage {
         }
     }
 
+    /// Check whether the stored logins can be decrypted with `encryptionKey`.
+    open func getKeyStatus(encryptionKey: String) throws -> KeyStatus {
+        return try queue.sync {
+            try self.store.getKeyStatus(encryptionKey: encryptionKey)
+        }
+    }
+
+    /// Delete the stored logins, so the store can be used again after the key
+    /// was lost.
+    ///
+    /// Confirm first that `getKeyStatus()` returns `.lost` for `encryptionKey`;
+    /// this throws without deleting anything if the key can decrypt any of the
+    /// logins.
+    open func recoverByDiscardingUndecryptable(encryptionKey: String) throws -> KeyRecoveryResult {
+        return try queue.sync {
+            try self.store.recoverByDiscardingUndecryptable(encryptionKey: encryptionKey)
+        }
+    }
+
     /// Register with the sync manager
     open func registerWithSyncManager() {
         return queue.sync {
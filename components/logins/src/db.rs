@@ -22,9 +22,10 @@
 ///     server.
 ///   - After we sync, we move all records from loginsL to loginsM, overwriting any previous data.
 ///     loginsL will be an empty table after this.  See mark_as_synchronized() for the details.
-use crate::encryption::EncryptorDecryptor;
+use crate::encryption::{EncryptorDecryptor, KeyRecoveryResult, KeyStatus};
 use crate::error::*;
 use crate::login::*;
+use crate::passkey::{Passkey, PasskeyEntry, SecurePasskeyFields, PASSKEY_COLS};
 use crate::schema;
 use crate::sync::SyncStatus;
 use crate::util;
@@ -257,6 +258,209 @@ impl LoginDb {
         Ok(())
     }
 
+    /// Returns the logins which haven't been used since `since`, in milliseconds
+    /// since the epoch, least recently used first.
+    pub fn list_unused_since(&self, since: i64) -> Result<Vec<EncryptedLogin>> {
+        let mut stmt = self.db.prepare_cached(&GET_UNUSED_SINCE_SQL)?;
+        let rows =
+            stmt.query_and_then(named_params! { ":since": since }, EncryptedLogin::from_row)?;
+        rows.collect::<Result<_>>()
+    }
+
+    pub fn get_usage_stats(&self) -> Result<LoginUsageStats> {
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        Ok(self.db.query_row(
+            &USAGE_STATS_SQL,
+            named_params! { ":stale_before": now_ms - STALE_LOGIN_AGE_MS },
+            |row| {
+                Ok(LoginUsageStats {
+                    total_logins: row.get(0)?,
+                    total_times_used: row.get(1)?,
+                    stale_logins: row.get(2)?,
+                    oldest_last_used: row.get(3)?,
+                    newest_last_used: row.get(4)?,
+                })
+            },
+        )?)
+    }
+
+    pub fn get_key_status(&self, encdec: &EncryptorDecryptor) -> Result<KeyStatus> {
+        let (undecryptable_local, local_count) =
+            self.find_undecryptable(LOCAL_SEC_FIELDS_SQL, encdec)?;
+        let (undecryptable_mirror, mirror_count) =
+            self.find_undecryptable(MIRROR_SEC_FIELDS_SQL, encdec)?;
+        let undecryptable = (undecryptable_local.len() + undecryptable_mirror.len()) as i64;
+        Ok(if undecryptable == 0 {
+            KeyStatus::Valid
+        } else if undecryptable == local_count + mirror_count {
+            KeyStatus::Lost { undecryptable }
+        } else {
+            KeyStatus::Corrupt { undecryptable }
+        })
+    }
+
+    /// Deletes the logins which can't be decrypted with `encdec`, but only if
+    /// that's all of them, as it is when `get_key_status()` returns
+    /// `KeyStatus::Lost`. A key which decrypts any of the logins isn't lost,
+    /// so we refuse rather than throw away the ones it can't. If any synced
+    /// logins were deleted from the mirror, the next sync starts from scratch
+    /// so that they're downloaded again.
+    pub fn discard_undecryptable(&self, encdec: &EncryptorDecryptor) -> Result<KeyRecoveryResult> {
+        let tx = self.unchecked_transaction()?;
+        let (local, local_count) = self.find_undecryptable(LOCAL_SEC_FIELDS_SQL, encdec)?;
+        let (mirror, mirror_count) = self.find_undecryptable(MIRROR_SEC_FIELDS_SQL, encdec)?;
+        let undecryptable = (local.len() + mirror.len()) as i64;
+        if undecryptable == 0 || undecryptable != local_count + mirror_count {
+            return Err(Error::KeyNotLost);
+        }
+        for guid in &local {
+            self.execute_cached("DELETE FROM loginsL WHERE guid = :guid", &[(":guid", guid)])?;
+            // Any mirror copy of the login is no longer overridden.
+            self.execute_cached(
+                "UPDATE loginsM SET is_overridden = 0 WHERE guid = :guid",
+                &[(":guid", guid)],
+            )?;
+        }
+        for guid in &mirror {
+            self.execute_cached("DELETE FROM loginsM WHERE guid = :guid", &[(":guid", guid)])?;
+        }
+        if !mirror.is_empty() {
+            // This is what resetting the engine does, but we keep the sync IDs.
+            self.execute_all(&[
+                &*CLONE_ENTIRE_MIRROR_SQL,
+                "DELETE FROM loginsM",
+                &format!("UPDATE loginsL SET sync_status = {}", SyncStatus::New as u8),
+            ])?;
+            self.put_meta(schema::LAST_SYNC_META_KEY, &0i64)?;
+        }
+        tx.commit()?;
+        log::warn!(
+            "Discarded {} local and {} mirror logins which couldn't be decrypted",
+            local.len(),
+            mirror.len()
+        );
+        Ok(KeyRecoveryResult {
+            local_discarded: local.len() as i64,
+            mirror_discarded: mirror.len() as i64,
+        })
+    }
+
+    /// Returns the guids of the logins selected by `sql` whose secure fields
+    /// can't be decrypted, and how many logins were checked.
+    fn find_undecryptable(
+        &self,
+        sql: &str,
+        encdec: &EncryptorDecryptor,
+    ) -> Result<(Vec<String>, i64)> {
+        let mut stmt = self.db.prepare_cached(sql)?;
+        let mut rows = stmt.query([])?;
+        let mut undecryptable = Vec::new();
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            count += 1;
+            let sec_fields: String = row.get("secFields")?;
+            if SecureLoginFields::decrypt(&sec_fields, encdec).is_err() {
+                undecryptable.push(row.get("guid")?);
+            }
+        }
+        Ok((undecryptable, count))
+    }
+
+    /// Adds a passkey. There can only be one passkey with each credential ID for a
+    /// relying party.
+    pub fn add_passkey(&self, entry: PasskeyEntry, encdec: &EncryptorDecryptor) -> Result<Passkey> {
+        entry.check_valid()?;
+        let passkey = Passkey {
+            id: Guid::random().to_string(),
+            credential_id: entry.credential_id,
+            rp_id: entry.rp_id,
+            user_handle: entry.user_handle,
+            user_name: entry.user_name,
+            private_key_ref: entry.private_key_ref,
+            time_created: util::system_time_ms_i64(SystemTime::now()),
+            time_last_used: None,
+        };
+        let sec_fields = SecurePasskeyFields {
+            user_handle: passkey.user_handle.clone(),
+            user_name: passkey.user_name.clone(),
+        }
+        .encrypt(encdec)?;
+        let tx = self.unchecked_transaction()?;
+        let exists: bool = self.db.query_row(
+            "SELECT EXISTS(
+                 SELECT 1 FROM passkeys
+                 WHERE rpId = :rp_id AND credentialId = :credential_id
+             )",
+            named_params! {
+                ":rp_id": passkey.rp_id,
+                ":credential_id": passkey.credential_id,
+            },
+            |row| row.get(0),
+        )?;
+        if exists {
+            return Err(InvalidLogin::DuplicatePasskey.into());
+        }
+        self.execute_cached(
+            "INSERT INTO passkeys (
+                guid,
+                credentialId,
+                rpId,
+                secFields,
+                privateKeyRef,
+                timeCreated
+            ) VALUES (
+                :guid,
+                :credential_id,
+                :rp_id,
+                :sec_fields,
+                :private_key_ref,
+                :time_created
+            )",
+            named_params! {
+                ":guid": passkey.id,
+                ":credential_id": passkey.credential_id,
+                ":rp_id": passkey.rp_id,
+                ":sec_fields": sec_fields,
+                ":private_key_ref": passkey.private_key_ref,
+                ":time_created": passkey.time_created,
+            },
+        )?;
+        tx.commit()?;
+        Ok(passkey)
+    }
+
+    /// Returns all passkeys, ordered by relying party and then by when they were created.
+    pub fn list_passkeys(&self, encdec: &EncryptorDecryptor) -> Result<Vec<Passkey>> {
+        let mut stmt = self.db.prepare_cached(&format!(
+            "SELECT {PASSKEY_COLS} FROM passkeys ORDER BY rpId, timeCreated"
+        ))?;
+        let rows = stmt.query_and_then([], |row| Passkey::from_row(row, encdec))?;
+        rows.collect::<Result<_>>()
+    }
+
+    /// Sets when the passkey with the provided id was last used, in milliseconds since the
+    /// unix epoch. Returns true if it exists.
+    pub fn set_passkey_last_used(&self, id: &str, time_last_used: i64) -> Result<bool> {
+        let num_updated = self.execute_cached(
+            "UPDATE passkeys SET timeLastUsed = :time_last_used WHERE guid = :guid",
+            named_params! {
+                ":time_last_used": time_last_used,
+                ":guid": id,
+            },
+        )?;
+        Ok(num_updated > 0)
+    }
+
+    /// Deletes the passkey with the provided id. Returns true if it existed. Passkeys
+    /// aren't synced, so there's no tombstone.
+    pub fn delete_passkey(&self, id: &str) -> Result<bool> {
+        let num_deleted = self.execute_cached(
+            "DELETE FROM passkeys WHERE guid = :guid",
+            named_params! { ":guid": id },
+        )?;
+        Ok(num_deleted > 0)
+    }
+
     // The single place we insert new rows or update existing local rows.
     // just the SQL - no validation or anything.
     fn insert_new_login(&self, login: &EncryptedLogin) -> Result<()> {
@@ -677,6 +881,16 @@ impl LoginDb {
     }
 }
 
+const LOCAL_SEC_FIELDS_SQL: &str = "SELECT guid, secFields FROM loginsL WHERE is_deleted = 0";
+const MIRROR_SEC_FIELDS_SQL: &str = "SELECT guid, secFields FROM loginsM";
+
+/// How long a login can go unused before `get_usage_stats()` counts it as stale.
+const STALE_LOGIN_AGE_MS: i64 = 365 * 24 * 60 * 60 * 1000;
+
+// When a login was last used. Logins from other clients may not have
+// `timeLastUsed` set, in which case we use the time it was created.
+const LAST_USED_SQL: &str = "MAX(timeLastUsed, timeCreated)";
+
 lazy_static! {
     static ref GET_ALL_SQL: String = format!(
         "SELECT {common_cols} FROM loginsL WHERE is_deleted = 0
@@ -707,6 +921,22 @@ lazy_static! {
          FROM loginsM",
         common_cols = schema::COMMON_COLS,
     );
+    static ref GET_UNUSED_SINCE_SQL: String = format!(
+        "SELECT * FROM ({get_all}) WHERE {last_used} < :since ORDER BY {last_used}",
+        get_all = &*GET_ALL_SQL,
+        last_used = LAST_USED_SQL,
+    );
+    static ref USAGE_STATS_SQL: String = format!(
+        "SELECT COUNT(*),
+                IFNULL(SUM(timesUsed), 0),
+                IFNULL(SUM(last_used < :stale_before), 0),
+                MIN(last_used),
+                MAX(last_used)
+         FROM (SELECT timesUsed, {last_used} AS last_used FROM loginsL WHERE is_deleted = 0
+               UNION ALL
+               SELECT timesUsed, {last_used} AS last_used FROM loginsM WHERE is_overridden = 0)",
+        last_used = LAST_USED_SQL,
+    );
     static ref CLONE_SINGLE_MIRROR_SQL: String =
         format!("{} WHERE guid = :guid", &*CLONE_ENTIRE_MIRROR_SQL,);
 }
@@ -1197,6 +1427,152 @@ mod tests {
         assert_eq!(login2.record.times_used, login.record.times_used + 1);
     }
 
+    #[test]
+    fn test_usage_stats() {
+        let db = LoginDb::open_in_memory().unwrap();
+        assert_eq!(db.get_usage_stats().unwrap(), LoginUsageStats::default());
+
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let two_years_ago = now_ms - 2 * STALE_LOGIN_AGE_MS;
+        let with_usage = |id: &str, time_created: i64, time_last_used: i64, times_used: i64| {
+            let mut login = crate::login::test_utils::enc_login(id, "password");
+            login.record = RecordFields {
+                id: id.to_string(),
+                time_created,
+                time_password_changed: time_created,
+                time_last_used,
+                times_used,
+            };
+            login
+        };
+        // Used recently, locally.
+        db.insert_new_login(&with_usage("recent", two_years_ago, now_ms, 10))
+            .unwrap();
+        // Not used in two years.
+        db.insert_new_login(&with_usage("stale", two_years_ago, two_years_ago, 2))
+            .unwrap();
+        // Synced from a client which doesn't record use, so we fall back to
+        // when it was created.
+        test_utils::add_mirror(
+            &db,
+            &with_usage("synced", two_years_ago + 1, 0, 0),
+            &sync15::ServerTimestamp(now_ms),
+            false,
+        )
+        .unwrap();
+
+        let unused: Vec<_> = db
+            .list_unused_since(now_ms - STALE_LOGIN_AGE_MS)
+            .unwrap()
+            .into_iter()
+            .map(|login| login.record.id)
+            .collect();
+        assert_eq!(unused, vec!["stale", "synced"]);
+
+        assert_eq!(
+            db.get_usage_stats().unwrap(),
+            LoginUsageStats {
+                total_logins: 3,
+                total_times_used: 12,
+                stale_logins: 2,
+                oldest_last_used: Some(two_years_ago),
+                newest_last_used: Some(now_ms),
+            }
+        );
+
+        // Using the stale login means it's no longer stale.
+        db.touch("stale").unwrap();
+        assert_eq!(db.get_usage_stats().unwrap().stale_logins, 1);
+    }
+
+    #[test]
+    fn test_key_status_and_recovery() {
+        let db = LoginDb::open_in_memory().unwrap();
+        assert_eq!(
+            db.get_key_status(&TEST_ENCRYPTOR).unwrap(),
+            KeyStatus::Valid
+        );
+
+        test_utils::insert_login(&db, "local", Some("password"), None);
+        test_utils::insert_login(&db, "changed", Some("new-password"), Some("password"));
+        test_utils::insert_login(&db, "synced", None, Some("password"));
+        assert_eq!(
+            db.get_key_status(&TEST_ENCRYPTOR).unwrap(),
+            KeyStatus::Valid
+        );
+
+        // The current key can't be used to throw the logins away.
+        assert!(matches!(
+            db.discard_undecryptable(&TEST_ENCRYPTOR),
+            Err(Error::KeyNotLost)
+        ));
+
+        let other_key = crate::encryption::create_key().unwrap();
+        let other_encdec = EncryptorDecryptor::new(&other_key).unwrap();
+        assert_eq!(
+            db.get_key_status(&other_encdec).unwrap(),
+            KeyStatus::Lost { undecryptable: 4 }
+        );
+
+        // A login written with the new key after the old one was lost.
+        let login = db
+            .add(
+                LoginEntry {
+                    fields: LoginFields {
+                        origin: "https://www.example.com".into(),
+                        http_realm: Some("https://www.example.com".into()),
+                        ..Default::default()
+                    },
+                    sec_fields: SecureLoginFields {
+                        username: "test_user".into(),
+                        password: "test_password".into(),
+                    },
+                },
+                &other_encdec,
+            )
+            .unwrap();
+        assert_eq!(
+            db.get_key_status(&other_encdec).unwrap(),
+            KeyStatus::Corrupt { undecryptable: 4 }
+        );
+        // Since the new key decrypts one of the logins, it isn't lost, and
+        // nothing is discarded.
+        assert!(matches!(
+            db.discard_undecryptable(&other_encdec),
+            Err(Error::KeyNotLost)
+        ));
+        assert_eq!(test_utils::get_local_guids(&db).len(), 3);
+
+        // Once it's deleted, the new key can't decrypt any of them.
+        db.delete(login.guid_str()).unwrap();
+        assert_eq!(
+            db.get_key_status(&other_encdec).unwrap(),
+            KeyStatus::Lost { undecryptable: 4 }
+        );
+        db.put_meta(schema::LAST_SYNC_META_KEY, &1000i64).unwrap();
+
+        assert_eq!(
+            db.discard_undecryptable(&other_encdec).unwrap(),
+            KeyRecoveryResult {
+                local_discarded: 2,
+                mirror_discarded: 2,
+            }
+        );
+        assert_eq!(db.get_key_status(&other_encdec).unwrap(), KeyStatus::Valid);
+        // Only the tombstone for the deleted login is left.
+        assert_eq!(test_utils::get_local_guids(&db), vec![login.guid_str()]);
+        assert!(test_utils::get_mirror_guids(&db).is_empty());
+        // The next sync downloads the discarded mirror logins again.
+        assert_eq!(
+            db.get_meta::<i64>(schema::LAST_SYNC_META_KEY).unwrap(),
+            Some(0)
+        );
+        assert!(matches!(
+            db.discard_undecryptable(&other_encdec),
+            Err(Error::KeyNotLost)
+        ));
+    }
+
     #[test]
     fn test_delete() {
         let db = LoginDb::open_in_memory().unwrap();
@@ -1232,6 +1608,85 @@ mod tests {
         assert!(!db.exists(login.guid_str()).unwrap());
     }
 
+    #[test]
+    fn test_passkeys() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let encdec = &*TEST_ENCRYPTOR;
+        let entry = |rp_id: &str, credential_id: &str| PasskeyEntry {
+            credential_id: credential_id.into(),
+            rp_id: rp_id.into(),
+            user_handle: "dXNlcg".into(),
+            user_name: "test_user".into(),
+            private_key_ref: format!("keystore-{rp_id}-{credential_id}"),
+        };
+        let b = db
+            .add_passkey(entry("www.example2.com", "Y3JlZDE"), encdec)
+            .unwrap();
+        let mut a = db
+            .add_passkey(entry("www.example.com", "Y3JlZDE"), encdec)
+            .unwrap();
+        assert_eq!(a.rp_id, "www.example.com");
+        assert_eq!(a.user_name, "test_user");
+        assert_eq!(a.private_key_ref, "keystore-www.example.com-Y3JlZDE");
+        assert!(a.time_created > 0);
+        assert_eq!(a.time_last_used, None);
+        assert_eq!(
+            db.list_passkeys(encdec).unwrap(),
+            vec![a.clone(), b.clone()]
+        );
+
+        // The user handle and name are encrypted.
+        let sec_fields: String = db
+            .query_row(
+                "SELECT secFields FROM passkeys WHERE guid = ?",
+                [&a.id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(!sec_fields.contains("test_user"));
+        assert!(!sec_fields.contains("dXNlcg"));
+        let other_key = EncryptorDecryptor::new_with_random_key().unwrap();
+        assert!(db.list_passkeys(&other_key).is_err());
+
+        // The same credential can't be added twice for one relying party.
+        assert!(matches!(
+            db.add_passkey(entry("www.example.com", "Y3JlZDE"), encdec),
+            Err(Error::InvalidLogin(InvalidLogin::DuplicatePasskey))
+        ));
+        assert!(matches!(
+            db.add_passkey(
+                PasskeyEntry {
+                    private_key_ref: "".into(),
+                    ..entry("www.example.com", "Y3JlZDI")
+                },
+                encdec
+            ),
+            Err(Error::InvalidLogin(InvalidLogin::IllegalFieldValue { .. }))
+        ));
+
+        assert!(db.set_passkey_last_used(&a.id, 1_700_000_000_000).unwrap());
+        assert!(!db.set_passkey_last_used("missing", 1).unwrap());
+        a.time_last_used = Some(1_700_000_000_000);
+        assert_eq!(
+            db.list_passkeys(encdec).unwrap(),
+            vec![a.clone(), b.clone()]
+        );
+
+        // Passkeys aren't logins.
+        assert!(db.get_all().unwrap().is_empty());
+
+        // They're local-only, so wiping the synced data keeps them.
+        db.wipe_local().unwrap();
+        assert_eq!(
+            db.list_passkeys(encdec).unwrap(),
+            vec![a.clone(), b.clone()]
+        );
+
+        assert!(db.delete_passkey(&a.id).unwrap());
+        assert!(!db.delete_passkey(&a.id).unwrap());
+        assert_eq!(db.list_passkeys(encdec).unwrap(), vec![b]);
+    }
+
     mod test_find_login_to_update {
         use super::*;
 
@@ -46,6 +46,30 @@ pub fn create_key() -> ApiResult<String> {
     EncryptorDecryptor::create_key()
 }
 
+/// Whether the stored logins can be decrypted with a key, as returned by
+/// `LoginStore::get_key_status()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyStatus {
+    /// Every stored login can be decrypted, or there aren't any.
+    Valid,
+    /// Some of the stored logins can't be decrypted, so they were probably
+    /// corrupted, or written with another key.
+    Corrupt { undecryptable: i64 },
+    /// None of the stored logins can be decrypted. This usually means the key
+    /// was lost, for example when the keystore was wiped, and a new one made.
+    Lost { undecryptable: i64 },
+}
+
+/// The logins thrown away by `LoginStore::recover_by_discarding_undecryptable()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyRecoveryResult {
+    /// Logins from the local table. Local changes which weren't synced yet
+    /// are gone for good.
+    pub local_discarded: i64,
+    /// Logins from the mirror. These are downloaded again on the next sync.
+    pub mirror_discarded: i64,
+}
+
 #[cfg(test)]
 pub mod test_utils {
     use super::*;
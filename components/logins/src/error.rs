@@ -58,6 +58,9 @@ pub enum Error {
     #[error("local encryption key not set")]
     EncryptionKeyMissing,
 
+    #[error("Some logins can be decrypted with the encryption key, so it isn't lost")]
+    KeyNotLost,
+
     #[error("Error synchronizing: {0}")]
     SyncAdapterError(#[from] sync15::Error),
 
@@ -96,6 +99,8 @@ pub enum InvalidLogin {
     EmptyPassword,
     #[error("Login already exists")]
     DuplicateLogin,
+    #[error("Passkey already exists")]
+    DuplicatePasskey,
     #[error("Both `formActionOrigin` and `httpRealm` are present")]
     BothTargets,
     #[error("Neither `formActionOrigin` or `httpRealm` are present")]
@@ -138,6 +143,12 @@ impl GetErrorHandling for Error {
             }
             Self::CryptoError { .. } => ErrorHandling::convert(LoginsApiError::IncorrectKey)
                 .report_error("logins-crypto-error"),
+            // The app asked to recover without checking the key status first, so there's
+            // nothing to report.
+            Self::KeyNotLost => ErrorHandling::convert(LoginsApiError::UnexpectedLoginsApiError {
+                reason: self.to_string(),
+            })
+            .log_warning(),
             Self::Interrupted(_) => ErrorHandling::convert(LoginsApiError::Interrupted {
                 reason: self.to_string(),
             }),
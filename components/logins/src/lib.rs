@@ -11,6 +11,7 @@ mod login;
 
 mod db;
 pub mod encryption;
+mod passkey;
 mod schema;
 mod store;
 mod sync;
@@ -20,8 +21,10 @@ uniffi::include_scaffolding!("logins");
 
 pub use crate::db::LoginDb;
 use crate::encryption::{check_canary, create_canary, create_key};
+pub use crate::encryption::{KeyRecoveryResult, KeyStatus};
 pub use crate::error::*;
 pub use crate::login::*;
+pub use crate::passkey::{Passkey, PasskeyEntry};
 pub use crate::store::*;
 pub use crate::sync::LoginsSyncEngine;
 
@@ -385,6 +385,23 @@ pub struct RecordFields {
     pub times_used: i64,
 }
 
+/// Aggregate usage information about all the logins in the store, as returned
+/// by `get_usage_stats()`. Times are integer milliseconds from the unix epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LoginUsageStats {
+    pub total_logins: i64,
+    /// The sum of `times_used` over all logins.
+    pub total_times_used: i64,
+    /// The number of logins which haven't been used in over a year.
+    pub stale_logins: i64,
+    /// When the least recently used login was last used, or `None` if there
+    /// are no logins.
+    pub oldest_last_used: Option<i64>,
+    /// When the most recently used login was last used, or `None` if there
+    /// are no logins.
+    pub newest_last_used: Option<i64>,
+}
+
 /// A login entered by the user
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Default)]
 pub struct LoginEntry {
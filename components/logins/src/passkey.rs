@@ -0,0 +1,118 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! # Passkey Structs
+//!
+//! Passkeys are WebAuthn credentials. We only store what's needed to list them alongside
+//! logins, and to find the key for a site: the private key itself stays with the platform
+//! authenticator (for example, the Android Keystore or the iOS Keychain), and we only keep
+//! a reference to it.
+//!
+//! Passkeys are stored in their own table, which the sync engine never reads, so they're
+//! never synced. Like the secure fields of a login, the user handle and name are stored
+//! encrypted with the store's key.
+//!
+//! * [`PasskeyEntry`] - A passkey from the authenticator, not yet stored.
+//! * [`Passkey`] - A [`PasskeyEntry`] plus DB record information.
+
+use crate::encryption::EncryptorDecryptor;
+use crate::error::*;
+use rusqlite::Row;
+use serde_derive::*;
+
+/// The columns of the `passkeys` table, in the order [`Passkey::from_row`] expects.
+pub(crate) const PASSKEY_COLS: &str = "
+    guid,
+    credentialId,
+    rpId,
+    secFields,
+    privateKeyRef,
+    timeCreated,
+    timeLastUsed
+";
+
+/// A passkey to add.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Default)]
+pub struct PasskeyEntry {
+    /// The credential ID, base64url encoded.
+    pub credential_id: String,
+    /// The relying party ID, usually the site's domain.
+    pub rp_id: String,
+    /// The user handle the relying party gave the credential, base64url encoded.
+    pub user_handle: String,
+    /// The user name the relying party gave the credential, for display.
+    pub user_name: String,
+    /// How to find the private key in the platform authenticator, such as a keystore alias.
+    pub private_key_ref: String,
+}
+
+/// A passkey stored in the database.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Default)]
+pub struct Passkey {
+    pub id: String,
+    pub credential_id: String,
+    pub rp_id: String,
+    pub user_handle: String,
+    pub user_name: String,
+    pub private_key_ref: String,
+    /// Milliseconds since the unix epoch.
+    pub time_created: i64,
+    /// Milliseconds since the unix epoch, or null if the passkey hasn't been used.
+    pub time_last_used: Option<i64>,
+}
+
+/// Passkey fields that are stored encrypted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SecurePasskeyFields {
+    // Short names, as for `SecureLoginFields`, to reduce the overhead in the DB.
+    #[serde(rename = "h")]
+    pub user_handle: String,
+    #[serde(rename = "n")]
+    pub user_name: String,
+}
+
+impl SecurePasskeyFields {
+    pub fn encrypt(&self, encdec: &EncryptorDecryptor) -> Result<String> {
+        encdec.encrypt_struct(&self, "encrypt SecurePasskeyFields")
+    }
+
+    pub fn decrypt(ciphertext: &str, encdec: &EncryptorDecryptor) -> Result<Self> {
+        encdec.decrypt_struct(ciphertext, "decrypt SecurePasskeyFields")
+    }
+}
+
+impl Passkey {
+    pub(crate) fn from_row(row: &Row<'_>, encdec: &EncryptorDecryptor) -> Result<Self> {
+        let sec_fields = SecurePasskeyFields::decrypt(&row.get::<_, String>("secFields")?, encdec)?;
+        Ok(Self {
+            id: row.get("guid")?,
+            credential_id: row.get("credentialId")?,
+            rp_id: row.get("rpId")?,
+            user_handle: sec_fields.user_handle,
+            user_name: sec_fields.user_name,
+            private_key_ref: row.get("privateKeyRef")?,
+            time_created: row.get("timeCreated")?,
+            time_last_used: row.get("timeLastUsed")?,
+        })
+    }
+}
+
+impl PasskeyEntry {
+    pub(crate) fn check_valid(&self) -> Result<()> {
+        for (name, value) in [
+            ("credential_id", &self.credential_id),
+            ("rp_id", &self.rp_id),
+            ("user_handle", &self.user_handle),
+            ("private_key_ref", &self.private_key_ref),
+        ] {
+            if value.is_empty() {
+                return Err(InvalidLogin::IllegalFieldValue {
+                    field_info: format!("`{name}` is empty"),
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
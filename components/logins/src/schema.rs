@@ -6,11 +6,12 @@
 //! ================
 //!
 //! The schema we use is a evolution of the firefox-ios logins database format.
-//! There are three tables:
+//! There are four tables:
 //!
 //! - `loginsL`: The local table.
 //! - `loginsM`: The mirror table.
 //! - `loginsSyncMeta`: The table used to to store various sync metadata.
+//! - `passkeys`: Passkey metadata, which is never synced.
 //!
 //! ## `loginsL`
 //!
@@ -87,6 +88,17 @@
 //!    [GLOBAL_STATE_META_KEY]. This is a `sync15::GlobalState` stored as
 //!    JSON.
 //!
+//! ## `passkeys`
+//!
+//! This stores WebAuthn credentials, added in version 3. Unlike logins,
+//! passkeys are local-only: there's no mirror table, and the sync engine never
+//! reads this table.
+//!
+//! We never store the private key, only `privateKeyRef`, which tells the
+//! platform authenticator where to find it. `secFields` is the ciphertext of
+//! the user handle and name, encrypted like the secure fields of a login.
+//! `credentialId` is base64url encoded, and the timestamps are in milliseconds.
+//!
 
 use crate::error::*;
 use lazy_static::lazy_static;
@@ -95,7 +107,8 @@ use sql_support::ConnExt;
 
 /// Version 1: SQLCipher -> plaintext migration.
 /// Version 2: addition of `loginsM.enc_unknown_fields`.
-pub(super) const VERSION: i64 = 2;
+/// Version 3: addition of the `passkeys` table.
+pub(super) const VERSION: i64 = 3;
 
 /// Every column shared by both tables except for `id`
 ///
@@ -190,6 +203,20 @@ const CREATE_DELETED_ORIGIN_INDEX_SQL: &str = "
     ON loginsL (is_deleted, origin)
 ";
 
+const CREATE_PASSKEYS_TABLE_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS passkeys (
+        id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        guid          TEXT NOT NULL UNIQUE,
+        credentialId  TEXT NOT NULL,
+        rpId          TEXT NOT NULL,
+        secFields     TEXT NOT NULL,
+        privateKeyRef TEXT NOT NULL,
+        timeCreated   INTEGER NOT NULL,
+        timeLastUsed  INTEGER,
+        UNIQUE(rpId, credentialId)
+    )
+";
+
 pub(crate) static LAST_SYNC_META_KEY: &str = "last_sync_time";
 pub(crate) static GLOBAL_STATE_META_KEY: &str = "global_state_v2";
 pub(crate) static GLOBAL_SYNCID_META_KEY: &str = "global_sync_id";
@@ -218,7 +245,7 @@ pub(crate) fn init(db: &Connection) -> Result<()> {
 
 // Allow the redundant Ok() here.  It will make more sense once we have an actual upgrade function.
 #[allow(clippy::unnecessary_wraps)]
-fn upgrade(db: &Connection, from: i64) -> Result<()> {
+fn upgrade(db: &Connection, mut from: i64) -> Result<()> {
     log::debug!("Upgrading schema from {} to {}", from, VERSION);
     if from == VERSION {
         return Ok(());
@@ -232,10 +259,14 @@ fn upgrade(db: &Connection, from: i64) -> Result<()> {
     if from == 1 {
         // Just one new nullable column makes this fairly easy
         db.execute_batch("ALTER TABLE loginsM ADD enc_unknown_fields TEXT;")?;
+        from = 2;
+    }
+    if from == 2 {
+        db.execute_batch(CREATE_PASSKEYS_TABLE_SQL)?;
     }
     // XXX - next migration, be sure to:
-    // from = 2;
-    // if from == 2 ...
+    // from = 3;
+    // if from == 3 ...
     db.execute_batch(&SET_VERSION_SQL)?;
     Ok(())
 }
@@ -248,6 +279,7 @@ pub(crate) fn create(db: &Connection) -> Result<()> {
         CREATE_OVERRIDE_ORIGIN_INDEX_SQL,
         CREATE_DELETED_ORIGIN_INDEX_SQL,
         CREATE_META_TABLE_SQL,
+        CREATE_PASSKEYS_TABLE_SQL,
         &*SET_VERSION_SQL,
     ])?;
     Ok(())
@@ -312,5 +344,24 @@ mod tests {
         // and ensure sql selecting the new column works.
         db.execute_batch("SELECT enc_unknown_fields FROM loginsM")
             .unwrap();
+        // and the v3 table exists.
+        db.execute_batch("SELECT credentialId FROM passkeys")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_upgrade_v2() {
+        let connection = Connection::open_in_memory().unwrap();
+        create(&connection).unwrap();
+        // Make it look like a v2 database, which didn't have the passkeys table.
+        connection
+            .execute_batch("DROP TABLE passkeys; PRAGMA user_version = 2;")
+            .unwrap();
+
+        let db = LoginDb::with_connection(connection).unwrap();
+        let version = db.query_one::<i64>("PRAGMA user_version").unwrap();
+        assert_eq!(version, VERSION);
+        db.execute_batch("SELECT credentialId, privateKeyRef FROM passkeys")
+            .unwrap();
     }
 }
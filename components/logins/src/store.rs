@@ -2,9 +2,10 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 use crate::db::LoginDb;
-use crate::encryption::EncryptorDecryptor;
+use crate::encryption::{EncryptorDecryptor, KeyRecoveryResult, KeyStatus};
 use crate::error::*;
-use crate::login::{EncryptedLogin, Login, LoginEntry};
+use crate::login::{EncryptedLogin, Login, LoginEntry, LoginUsageStats};
+use crate::passkey::{Passkey, PasskeyEntry};
 use crate::LoginsSyncEngine;
 use parking_lot::Mutex;
 use std::path::Path;
@@ -97,11 +98,67 @@ impl LoginStore {
         self.db.lock().touch(id)
     }
 
+    #[handle_error(Error)]
+    pub fn list_unused_since(&self, since: i64) -> ApiResult<Vec<EncryptedLogin>> {
+        self.db.lock().list_unused_since(since)
+    }
+
+    #[handle_error(Error)]
+    pub fn get_usage_stats(&self) -> ApiResult<LoginUsageStats> {
+        self.db.lock().get_usage_stats()
+    }
+
+    /// Checks whether the stored logins can be decrypted with `enc_key`.
+    #[handle_error(Error)]
+    pub fn get_key_status(&self, enc_key: &str) -> ApiResult<KeyStatus> {
+        let encdec = EncryptorDecryptor::new(enc_key)?;
+        self.db.lock().get_key_status(&encdec)
+    }
+
+    /// Deletes the stored logins, so the store can be used again after the
+    /// key was lost.
+    ///
+    /// Callers should first confirm with `get_key_status()` that `enc_key`
+    /// is `KeyStatus::Lost`. This fails without deleting anything if `enc_key`
+    /// can decrypt any of the stored logins, since the key isn't lost then.
+    /// It can't tell a lost key from a wrong one, though, so the app must be
+    /// sure `enc_key` is the key it uses now.
+    #[handle_error(Error)]
+    pub fn recover_by_discarding_undecryptable(
+        &self,
+        enc_key: &str,
+    ) -> ApiResult<KeyRecoveryResult> {
+        let encdec = EncryptorDecryptor::new(enc_key)?;
+        self.db.lock().discard_undecryptable(&encdec)
+    }
+
     #[handle_error(Error)]
     pub fn delete(&self, id: &str) -> ApiResult<bool> {
         self.db.lock().delete(id)
     }
 
+    #[handle_error(Error)]
+    pub fn add_passkey(&self, entry: PasskeyEntry, enc_key: &str) -> ApiResult<Passkey> {
+        let encdec = EncryptorDecryptor::new(enc_key)?;
+        self.db.lock().add_passkey(entry, &encdec)
+    }
+
+    #[handle_error(Error)]
+    pub fn list_passkeys(&self, enc_key: &str) -> ApiResult<Vec<Passkey>> {
+        let encdec = EncryptorDecryptor::new(enc_key)?;
+        self.db.lock().list_passkeys(&encdec)
+    }
+
+    #[handle_error(Error)]
+    pub fn set_passkey_last_used(&self, id: &str, time_last_used: i64) -> ApiResult<bool> {
+        self.db.lock().set_passkey_last_used(id, time_last_used)
+    }
+
+    #[handle_error(Error)]
+    pub fn delete_passkey(&self, id: &str) -> ApiResult<bool> {
+        self.db.lock().delete_passkey(id)
+    }
+
     #[handle_error(Error)]
     pub fn wipe_local(&self) -> ApiResult<()> {
         self.db.lock().wipe_local()?;
@@ -297,6 +354,44 @@ mod test {
         assert_eq!(b_after_update.record.times_used, 2);
     }
 
+    #[test]
+    fn test_recover_requires_lost_key() {
+        let store = LoginStore::new_in_memory().unwrap();
+        store
+            .add(
+                LoginEntry {
+                    fields: LoginFields {
+                        origin: "https://www.example.com".into(),
+                        http_realm: Some("https://www.example.com".into()),
+                        ..Default::default()
+                    },
+                    sec_fields: SecureLoginFields {
+                        username: "coolperson21".into(),
+                        password: "p4ssw0rd".into(),
+                    },
+                },
+                &TEST_ENCRYPTION_KEY,
+            )
+            .unwrap();
+
+        // The key which decrypts the logins can't discard them.
+        assert!(matches!(
+            store.recover_by_discarding_undecryptable(&TEST_ENCRYPTION_KEY),
+            Err(LoginsApiError::UnexpectedLoginsApiError { .. })
+        ));
+        assert_eq!(store.list().unwrap().len(), 1);
+
+        // Once the app has replaced its lost key, it can.
+        let new_key = crate::create_key().unwrap();
+        assert_eq!(
+            store.get_key_status(&new_key).unwrap(),
+            KeyStatus::Lost { undecryptable: 1 }
+        );
+        let result = store.recover_by_discarding_undecryptable(&new_key).unwrap();
+        assert_eq!(result.local_discarded, 1);
+        assert!(store.list().unwrap().is_empty());
+    }
+
     #[test]
     fn test_sync_manager_registration() {
         let store = Arc::new(LoginStore::new_in_memory().unwrap());
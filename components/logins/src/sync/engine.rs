@@ -502,6 +502,11 @@ impl SyncEngine for LoginsSyncEngine {
         self.do_reset(assoc)?;
         Ok(())
     }
+
+    fn wipe(&self) -> anyhow::Result<()> {
+        self.store.db.lock().wipe_local()?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -674,6 +679,33 @@ mod tests {
         assert!(changes["changed"].get("deleted").is_none());
     }
 
+    #[test]
+    fn test_wipe() {
+        let store = LoginStore::new_in_memory().unwrap();
+        insert_login(&store.db.lock(), "synced", None, Some("password"));
+        insert_login(&store.db.lock(), "deleted", None, Some("password"));
+        insert_login(&store.db.lock(), "added", Some("password"), None);
+        store.db.lock().delete("deleted").unwrap();
+
+        let mut engine = LoginsSyncEngine::new(Arc::new(store)).unwrap();
+        engine.wipe().unwrap();
+        engine
+            .set_local_encryption_key(&TEST_ENCRYPTION_KEY)
+            .unwrap();
+        // The wipe mustn't leave tombstones behind, or the next sync would
+        // delete the logins on other devices.
+        assert!(engine.fetch_outgoing().unwrap().is_empty());
+        let db = engine.store.db.lock();
+        assert_eq!(
+            db.query_one::<i64>("SELECT COUNT(*) FROM loginsL").unwrap(),
+            0
+        );
+        assert_eq!(
+            db.query_one::<i64>("SELECT COUNT(*) FROM loginsM").unwrap(),
+            0
+        );
+    }
+
     #[test]
     fn test_bad_record() {
         let store = LoginStore::new_in_memory().unwrap();
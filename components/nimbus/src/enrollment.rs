@@ -9,8 +9,9 @@ use crate::{
     SLUG_REPLACEMENT_PATTERN,
 };
 use serde_derive::*;
+use serde_json::{Map, Value};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::{Display, Formatter, Result as FmtResult},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
@@ -1025,6 +1026,96 @@ pub fn map_features_by_feature_id(
         .unwrap()
 }
 
+/// A feature's configuration, merged from every experiment and rollout it's enrolled in,
+/// along with which of them each top-level variable came from. This is a debugging aid for
+/// features which allow coenrollment, where several rollouts or experiments might try to
+/// configure the same variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergedFeatureConfig {
+    pub feature_id: String,
+    /// The merged variables, as a JSON string. These are the same as the ones
+    /// `get_feature_config_variables` returns.
+    pub variables: String,
+    /// Where each of the top-level variables came from, sorted by variable name.
+    pub sources: Vec<FeatureVariableSource>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeatureVariableSource {
+    pub variable: String,
+    /// The slug of the experiment or rollout whose value for the variable was used.
+    pub slug: String,
+    pub is_rollout: bool,
+}
+
+/// Merge the configurations for `feature_id`, and note where each variable came from.
+///
+/// The configurations are merged as they are by [`map_features_by_feature_id`]:
+///
+///  1. The experiments' configurations are patched over the rollouts', so a variable an
+///     experiment sets always beats one from a rollout.
+///  2. If the feature allows coenrollment, the rollouts (and then the experiments) are
+///     patched over each other in the order of `enrollments`, so later ones win. The client
+///     stores enrollments by slug, so this is the order of their slugs.
+///  3. If it doesn't, only the last enrolled rollout and experiment are used.
+///
+/// Variables whose value is `null` don't override the value from an earlier configuration.
+#[cfg_attr(not(feature = "stateful"), allow(unused))]
+pub fn merge_feature_config_with_sources(
+    enrollments: &[ExperimentEnrollment],
+    experiments: &[Experiment],
+    coenrolling_ids: &HashSet<&str>,
+    feature_id: &str,
+) -> Result<Option<MergedFeatureConfig>> {
+    let rollouts = filter_experiments_and_enrollments(
+        experiments,
+        enrollments,
+        ExperimentMetadata::is_rollout,
+    );
+    let experiments =
+        filter_experiments_and_enrollments(experiments, enrollments, |exp| !exp.is_rollout());
+
+    let mut layers = Vec::new();
+    for (experiments, enrollments) in [rollouts, experiments] {
+        let experiments = map_experiments(&experiments);
+        let mut configs: Vec<_> = enrollments
+            .iter()
+            .flat_map(|e| get_enrolled_feature_configs(e, &experiments))
+            .filter(|f| f.feature_id == feature_id)
+            .collect();
+        if !coenrolling_ids.contains(feature_id) {
+            configs = configs.pop().into_iter().collect();
+        }
+        layers.extend(configs);
+    }
+    if layers.is_empty() {
+        return Ok(None);
+    }
+
+    let mut variables = Map::new();
+    let mut sources = BTreeMap::new();
+    for layer in &layers {
+        variables = layer.feature.value.defaults(&variables)?;
+        for (variable, value) in &layer.feature.value {
+            if !value.is_null() {
+                sources.insert(variable.clone(), layer);
+            }
+        }
+    }
+    Ok(Some(MergedFeatureConfig {
+        feature_id: feature_id.to_string(),
+        variables: Value::Object(variables).to_string(),
+        sources: sources
+            .into_iter()
+            .map(|(variable, layer)| FeatureVariableSource {
+                variable,
+                slug: layer.slug.clone(),
+                is_rollout: layer.is_rollout(),
+            })
+            .collect(),
+    }))
+}
+
 pub(crate) fn populate_feature_maps(
     enrolled_feature: EnrolledFeatureConfig,
     coenrolling_feature_ids: &HashSet<&str>,
@@ -17,12 +17,14 @@ pub mod metrics;
 pub mod schema;
 pub mod versioning;
 
-pub use enrollment::{EnrolledFeature, EnrollmentStatus};
+pub use enrollment::{
+    EnrolledFeature, EnrollmentStatus, FeatureVariableSource, MergedFeatureConfig,
+};
 pub use error::{NimbusError, Result};
 #[cfg(debug_assertions)]
 pub use evaluator::evaluate_enrollment;
 pub use schema::*;
-pub use targeting::NimbusTargetingHelper;
+pub use targeting::{EvaluatedExpression, NimbusTargetingHelper, TargetingExplanation};
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "stateful")] {
@@ -30,6 +32,7 @@ cfg_if::cfg_if! {
         pub mod stateful;
 
         pub use stateful::nimbus_client::*;
+        pub use stateful::enrollment::EnrollmentSimulation;
         pub use stateful::matcher::AppContext;
         pub use remote_settings::{RemoteSettingsConfig, RemoteSettingsServer};
     } else {
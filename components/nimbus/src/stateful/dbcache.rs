@@ -4,7 +4,8 @@
 
 use crate::{
     enrollment::{
-        map_features_by_feature_id, EnrolledFeature, EnrolledFeatureConfig, ExperimentEnrollment,
+        map_features_by_feature_id, merge_feature_config_with_sources, EnrolledFeature,
+        EnrolledFeatureConfig, ExperimentEnrollment, MergedFeatureConfig,
     },
     error::{NimbusError, Result},
     stateful::{
@@ -121,6 +122,12 @@ impl DatabaseCache {
         }
     }
 
+    // True once `commit_and_update()` has been called, and the getters below
+    // can return results.
+    pub fn is_ready(&self) -> bool {
+        self.data.read().unwrap().is_some()
+    }
+
     pub fn get_experiment_branch(&self, id: &str) -> Result<Option<String>> {
         self.get_data(|data| -> Option<String> {
             data.experiments_by_slug
@@ -139,6 +146,21 @@ impl DatabaseCache {
         })
     }
 
+    pub fn get_feature_config_variables_merged(
+        &self,
+        feature_id: &str,
+        coenrolling_ids: &HashSet<&str>,
+    ) -> Result<Option<MergedFeatureConfig>> {
+        self.get_data(|data| {
+            merge_feature_config_with_sources(
+                &data.enrollments,
+                &data.experiments,
+                coenrolling_ids,
+                feature_id,
+            )
+        })?
+    }
+
     pub fn get_enrollment_by_feature(&self, feature_id: &str) -> Result<Option<EnrolledFeature>> {
         self.get_data(|data| {
             data.features_by_feature_id
@@ -6,9 +6,9 @@ use crate::{
         map_enrollments, EnrollmentChangeEvent, EnrollmentChangeEventType, EnrollmentsEvolver,
         ExperimentEnrollment,
     },
-    error::Result,
+    error::{NimbusError, Result},
     stateful::persistence::{Database, Readable, StoreId, Writer},
-    EnrolledExperiment, EnrollmentStatus, Experiment,
+    EnrolledExperiment, EnrollmentStatus, Experiment, TargetingExplanation,
 };
 
 const DB_KEY_GLOBAL_USER_PARTICIPATION: &str = "user-opt-in";
@@ -53,6 +53,108 @@ impl<'a> EnrollmentsEvolver<'a> {
         }
         Ok(enrollments_change_events)
     }
+
+    /// Works out what the enrollment for `recipe` would be if it were published, or replaced
+    /// the recipe with the same slug, in the same way as `evolve_enrollments_in_db`, but
+    /// without writing anything. Returns the current and next enrollments for the recipe, and
+    /// the change event for it, if any.
+    pub(crate) fn simulate_enrollment_in_db<'r>(
+        &mut self,
+        db: &Database,
+        reader: &'r impl Readable<'r>,
+        recipe: &Experiment,
+    ) -> Result<(
+        Option<ExperimentEnrollment>,
+        ExperimentEnrollment,
+        Option<EnrollmentChangeEvent>,
+    )> {
+        let is_user_participating = get_global_user_participation(db, reader)?;
+        let experiments_store = db.get_store(StoreId::Experiments);
+        let enrollments_store = db.get_store(StoreId::Enrollments);
+        let prev_experiments: Vec<Experiment> = experiments_store.collect_all(reader)?;
+        let prev_enrollments: Vec<ExperimentEnrollment> = enrollments_store.collect_all(reader)?;
+        let next_experiments: Vec<Experiment> = prev_experiments
+            .iter()
+            .filter(|experiment| experiment.slug != recipe.slug)
+            .cloned()
+            .chain(std::iter::once(recipe.clone()))
+            .collect();
+        let (next_enrollments, events) = self.evolve_enrollments(
+            is_user_participating,
+            &prev_experiments,
+            &next_experiments,
+            &prev_enrollments,
+        )?;
+        let prev_enrollment = prev_enrollments
+            .into_iter()
+            .find(|enrollment| enrollment.slug == recipe.slug);
+        let next_enrollment = next_enrollments
+            .into_iter()
+            .find(|enrollment| enrollment.slug == recipe.slug)
+            .ok_or(NimbusError::InternalError(
+                "Simulated recipe has no enrollment",
+            ))?;
+        let event = events
+            .into_iter()
+            .find(|event| event.experiment_slug == recipe.slug);
+        Ok((prev_enrollment, next_enrollment, event))
+    }
+}
+
+/// What would happen to this client's enrollment in a recipe if it were published, as worked
+/// out by `NimbusClient::simulate_enrollment()`.
+#[derive(Debug, Clone)]
+pub struct EnrollmentSimulation {
+    /// The client's current enrollment status for the recipe, or `None` if it hasn't seen it.
+    pub previous_status: Option<String>,
+    /// The enrollment status the client would have, e.g. `Enrolled` or `NotEnrolled`.
+    pub status: String,
+    /// Why the client would have that status, e.g. `Qualified`, `NotSelected` or
+    /// `EnrollmentsPaused`, or the error for `Error`.
+    pub reason: Option<String>,
+    /// The branch the client would be, or was, enrolled in.
+    pub branch: Option<String>,
+    /// The enrollment change event which would be recorded, if any.
+    pub change: Option<EnrollmentChangeEvent>,
+    /// Whether the client falls in the recipe's buckets, or `None` if the client doesn't have
+    /// the randomization unit the recipe uses.
+    pub in_bucket: Option<bool>,
+    /// The recipe's targeting expression, evaluated with an explanation, or `None` if it
+    /// doesn't have one.
+    pub targeting: Option<TargetingExplanation>,
+}
+
+impl EnrollmentSimulation {
+    pub(crate) fn new(
+        prev_enrollment: Option<ExperimentEnrollment>,
+        next_enrollment: ExperimentEnrollment,
+        change: Option<EnrollmentChangeEvent>,
+        in_bucket: Option<bool>,
+        targeting: Option<TargetingExplanation>,
+    ) -> Self {
+        let (reason, branch) = match next_enrollment.status {
+            EnrollmentStatus::Enrolled {
+                ref reason,
+                ref branch,
+            } => (Some(reason.to_string()), Some(branch.clone())),
+            EnrollmentStatus::NotEnrolled { ref reason } => (Some(reason.to_string()), None),
+            EnrollmentStatus::Disqualified {
+                ref reason,
+                ref branch,
+            } => (Some(reason.to_string()), Some(branch.clone())),
+            EnrollmentStatus::WasEnrolled { ref branch, .. } => (None, Some(branch.clone())),
+            EnrollmentStatus::Error { ref reason } => (Some(reason.clone()), None),
+        };
+        Self {
+            previous_status: prev_enrollment.map(|enrollment| enrollment.status.name()),
+            status: next_enrollment.status.name(),
+            reason,
+            branch,
+            change,
+            in_bucket,
+            targeting,
+        }
+    }
 }
 
 /// Return information about all enrolled experiments.
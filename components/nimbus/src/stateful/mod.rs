@@ -9,6 +9,7 @@ pub mod enrollment;
 pub mod evaluator;
 pub mod matcher;
 pub mod nimbus_client;
+pub(crate) mod pending_events;
 pub mod persistence;
 pub mod targeting;
 pub mod updating;
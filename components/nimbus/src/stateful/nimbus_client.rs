@@ -6,7 +6,7 @@ use crate::{
     defaults::Defaults,
     enrollment::{
         EnrolledFeature, EnrollmentChangeEvent, EnrollmentChangeEventType, EnrollmentsEvolver,
-        ExperimentEnrollment,
+        ExperimentEnrollment, FeatureVariableSource, MergedFeatureConfig,
     },
     error::BehaviorError,
     evaluator::{is_experiment_available, TargetingAttributes},
@@ -15,6 +15,7 @@ use crate::{
         EnrollmentStatusExtraDef, FeatureExposureExtraDef, MalformedFeatureConfigExtraDef,
         MetricsHandler,
     },
+    sampling,
     schema::parse_experiments,
     stateful::{
         behavior::EventStore,
@@ -22,16 +23,17 @@ use crate::{
         dbcache::DatabaseCache,
         enrollment::{
             get_global_user_participation, opt_in_with_branch, opt_out,
-            reset_telemetry_identifiers, set_global_user_participation,
+            reset_telemetry_identifiers, set_global_user_participation, EnrollmentSimulation,
         },
         matcher::AppContext,
+        pending_events::{PendingEvent, PendingEventQueue},
         persistence::{Database, StoreId, Writer},
         updating::{read_and_remove_pending_experiments, write_pending_experiments},
     },
     strings::fmt_with_map,
     targeting::RecordedContext,
-    AvailableExperiment, AvailableRandomizationUnits, EnrolledExperiment, Experiment,
-    ExperimentBranch, NimbusError, NimbusTargetingHelper, Result,
+    AvailableExperiment, AvailableRandomizationUnits, EnrolledExperiment, EvaluatedExpression,
+    Experiment, ExperimentBranch, NimbusError, NimbusTargetingHelper, Result, TargetingExplanation,
 };
 use chrono::{DateTime, NaiveDateTime, Utc};
 use once_cell::sync::OnceCell;
@@ -51,6 +53,7 @@ pub const DB_KEY_INSTALLATION_DATE: &str = "installation-date";
 pub const DB_KEY_UPDATE_DATE: &str = "update-date";
 pub const DB_KEY_APP_VERSION: &str = "app-version";
 pub const DB_KEY_FETCH_ENABLED: &str = "fetch-enabled";
+const DB_KEY_PENDING_EVENTS: &str = "pending-events";
 
 // The main `NimbusClient` struct must not expose any methods that make an `&mut self`,
 // in order to be compatible with the uniffi's requirements on objects. This is a helper
@@ -88,6 +91,8 @@ pub struct NimbusClient {
     event_store: Arc<Mutex<EventStore>>,
     recorded_context: Option<Arc<dyn RecordedContext>>,
     metrics_handler: Arc<Box<dyn MetricsHandler>>,
+    // Events recorded before the database cache is ready.
+    pending_events: Mutex<PendingEventQueue>,
 }
 
 impl NimbusClient {
@@ -125,6 +130,7 @@ impl NimbusClient {
             event_store: Arc::default(),
             recorded_context,
             metrics_handler: Arc::new(metrics_handler),
+            pending_events: Default::default(),
         })
     }
 
@@ -141,6 +147,7 @@ impl NimbusClient {
 
     pub fn initialize(&self) -> Result<()> {
         let db = self.db()?;
+        self.persist_pending_events(db)?;
         // We're not actually going to write, we just want to exclude concurrent writers.
         let mut writer = db.write()?;
 
@@ -182,6 +189,59 @@ impl NimbusClient {
         self.database_cache
             .commit_and_update(db, writer, &coenrolling_ids)?;
         self.record_enrollment_status_telemetry(state)?;
+        self.flush_pending_events(db)?;
+        Ok(())
+    }
+
+    // Picks up any events left queued by a previous session, and writes the
+    // ones queued in this session to the database, so they aren't lost if
+    // this initialization doesn't finish.
+    pub(crate) fn persist_pending_events(&self, db: &Database) -> Result<()> {
+        let store = db.get_store(StoreId::Meta);
+        let persisted: Option<Vec<PendingEvent>> = store.get(&db.read()?, DB_KEY_PENDING_EVENTS)?;
+        let events = {
+            let mut queue = self.pending_events.lock().unwrap();
+            if let Some(persisted) = persisted {
+                queue.merge_persisted(persisted);
+            }
+            if !queue.needs_persisting() {
+                return Ok(());
+            }
+            queue.mark_persisted();
+            queue.events().to_vec()
+        };
+        let mut writer = db.write()?;
+        store.put(&mut writer, DB_KEY_PENDING_EVENTS, &events)?;
+        writer.commit()?;
+        Ok(())
+    }
+
+    // Records the events queued before the database cache was ready. This
+    // must be called after the cache has been updated.
+    fn flush_pending_events(&self, db: &Database) -> Result<()> {
+        let (events, persisted) = self.pending_events.lock().unwrap().take();
+        for event in events {
+            match event {
+                PendingEvent::Exposure { feature_id, slug } => {
+                    self.record_feature_exposure(feature_id, slug)
+                }
+                PendingEvent::MalformedFeatureConfig {
+                    feature_id,
+                    part_id,
+                } => self.record_malformed_feature_config(feature_id, part_id),
+            }
+        }
+        if persisted {
+            let store = db.get_store(StoreId::Meta);
+            let mut writer = db.write()?;
+            if store
+                .get::<Vec<PendingEvent>, _>(&writer, DB_KEY_PENDING_EVENTS)?
+                .is_some()
+            {
+                store.delete(&mut writer, DB_KEY_PENDING_EVENTS)?;
+                writer.commit()?;
+            }
+        }
         Ok(())
     }
 
@@ -208,6 +268,22 @@ impl NimbusClient {
         )
     }
 
+    /// Like `get_feature_config_variables`, but also returns which experiment or rollout
+    /// each variable came from. This is for debugging, so it doesn't count as using the
+    /// feature.
+    pub fn get_feature_config_variables_merged(
+        &self,
+        feature_id: String,
+    ) -> Result<Option<MergedFeatureConfig>> {
+        let coenrolling_ids = self
+            .coenrolling_feature_ids
+            .iter()
+            .map(|s| s.as_str())
+            .collect();
+        self.database_cache
+            .get_feature_config_variables_merged(&feature_id, &coenrolling_ids)
+    }
+
     pub fn get_experiment_branches(&self, slug: String) -> Result<Vec<ExperimentBranch>> {
         self.get_all_experiments()?
             .into_iter()
@@ -391,6 +467,7 @@ impl NimbusClient {
     pub fn apply_pending_experiments(&self) -> Result<Vec<EnrollmentChangeEvent>> {
         log::info!("updating experiment list");
         let db = self.db()?;
+        self.persist_pending_events(db)?;
         let mut writer = db.write()?;
 
         // We'll get the pending experiments which were stored for us, either by fetch_experiments
@@ -638,6 +715,82 @@ impl NimbusClient {
         Ok(Arc::new(helper))
     }
 
+    /// Evaluates a targeting expression in the same way as enrollment does, along with each of
+    /// its sub-expressions, so that experiment owners can see why a client was or wasn't
+    /// targeted.
+    pub fn evaluate_targeting_with_explanation(
+        &self,
+        expression: String,
+        additional_context: Option<JsonObject>,
+    ) -> Result<TargetingExplanation> {
+        self.create_targeting_helper(additional_context)?
+            .eval_jexl_with_explanation(expression)
+    }
+
+    /// Works out what would happen to this client's enrollment in `recipe_json`, a single
+    /// experiment or rollout, if it were published, using the same bucketing, targeting and
+    /// feature conflict rules as `apply_pending_experiments()`. If the client has already seen
+    /// a recipe with the same slug, this is treated as an update to it, so pausing or resuming
+    /// enrollment, or changing a rollout's buckets, can be checked too. Nothing is written.
+    pub fn simulate_enrollment(
+        &self,
+        recipe_json: String,
+        additional_context: Option<JsonObject>,
+    ) -> Result<EnrollmentSimulation> {
+        let recipe: Experiment = serde_json::from_str(&recipe_json)?;
+        let targeting_helper = self.create_targeting_helper(additional_context)?;
+        let db = self.db()?;
+        let reader = db.read()?;
+        let state = self.mutable_state.lock().unwrap();
+        let coenrolling_feature_ids = self
+            .coenrolling_feature_ids
+            .iter()
+            .map(|s| s.as_str())
+            .collect();
+        let mut evolver_targeting_helper = (*targeting_helper).clone();
+        let mut evolver = EnrollmentsEvolver::new(
+            &state.available_randomization_units,
+            &mut evolver_targeting_helper,
+            &coenrolling_feature_ids,
+        );
+        let (prev_enrollment, next_enrollment, change) =
+            evolver.simulate_enrollment_in_db(db, &reader, &recipe)?;
+
+        let bucket_config = &recipe.bucket_config;
+        let in_bucket = match state
+            .available_randomization_units
+            .get_value(&bucket_config.randomization_unit)
+        {
+            Some(id) => Some(sampling::bucket_sample(
+                vec![id.to_owned(), bucket_config.namespace.clone()],
+                bucket_config.start,
+                bucket_config.count,
+                bucket_config.total,
+            )?),
+            None => None,
+        };
+        let targeting = match &recipe.targeting {
+            Some(expression) => {
+                let is_already_enrolled = prev_enrollment
+                    .as_ref()
+                    .map_or(false, |enrollment| enrollment.status.is_enrolled());
+                Some(
+                    targeting_helper
+                        .put("is_already_enrolled", is_already_enrolled)
+                        .eval_jexl_with_explanation(expression.clone())?,
+                )
+            }
+            None => None,
+        };
+        Ok(EnrollmentSimulation::new(
+            prev_enrollment,
+            next_enrollment,
+            change,
+            in_bucket,
+            targeting,
+        ))
+    }
+
     pub fn create_string_helper(
         &self,
         additional_context: Option<JsonObject>,
@@ -772,7 +925,25 @@ impl NimbusClient {
         }
     }
 
+    // Queues the event if the database cache isn't ready yet, returning true
+    // if it did. The queue stays locked while we check, so that the event
+    // can't miss a flush which is happening at the same time.
+    fn queue_if_not_ready(&self, event: impl FnOnce() -> PendingEvent) -> bool {
+        let mut queue = self.pending_events.lock().unwrap();
+        if self.database_cache.is_ready() {
+            return false;
+        }
+        queue.push(event());
+        true
+    }
+
     pub fn record_feature_exposure(&self, feature_id: String, slug: Option<String>) {
+        if self.queue_if_not_ready(|| PendingEvent::Exposure {
+            feature_id: feature_id.clone(),
+            slug: slug.clone(),
+        }) {
+            return;
+        }
         let event = if let Some(slug) = slug {
             if let Ok(Some(branch)) = self.database_cache.get_experiment_branch(&slug) {
                 Some(FeatureExposureExtraDef {
@@ -799,6 +970,12 @@ impl NimbusClient {
     }
 
     pub fn record_malformed_feature_config(&self, feature_id: String, part_id: String) {
+        if self.queue_if_not_ready(|| PendingEvent::MalformedFeatureConfig {
+            feature_id: feature_id.clone(),
+            part_id: part_id.clone(),
+        }) {
+            return;
+        }
         let event = if let Ok(Some(f)) = self.database_cache.get_enrollment_by_feature(&feature_id)
         {
             MalformedFeatureConfigExtraDef::from(f, part_id)
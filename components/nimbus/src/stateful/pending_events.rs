@@ -0,0 +1,100 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+// Exposure and malformed feature config events need the enrollments to say
+// which experiment and branch they are about, and those aren't known until
+// the database cache has been filled by `initialize()`. Events recorded
+// before then are held here, and recorded once initialization is done.
+//
+// Dropping them instead would bias experiment analysis: a feature used early
+// in startup would be under-reported, and more so on slower devices.
+//
+// The queue is also written to the database at the start of initialization,
+// so that if initialization fails, or the app is killed before it finishes,
+// the events are recorded by the next successful initialization.
+
+use serde_derive::{Deserialize, Serialize};
+
+// The most events we hold on to. Events are coalesced, so this is the number
+// of distinct (feature, slug) or (feature, part) pairs, which is small in
+// practice; this is only here to stop a misbehaving caller growing the queue
+// without bound.
+pub(crate) const MAX_PENDING_EVENTS: usize = 100;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum PendingEvent {
+    Exposure {
+        feature_id: String,
+        slug: Option<String>,
+    },
+    MalformedFeatureConfig {
+        feature_id: String,
+        part_id: String,
+    },
+}
+
+#[derive(Default, Debug)]
+pub(crate) struct PendingEventQueue {
+    events: Vec<PendingEvent>,
+    // True if there are events which haven't yet been written to the database.
+    dirty: bool,
+    // True if the database may have a copy of the queue which needs removing
+    // once the events have been recorded.
+    persisted: bool,
+}
+
+impl PendingEventQueue {
+    // Adds an event to the queue. An event which is already queued is
+    // coalesced with it: the telemetry only needs to know that a feature was
+    // exposed at least once, not how many times it was checked.
+    pub(crate) fn push(&mut self, event: PendingEvent) {
+        if self.events.contains(&event) {
+            return;
+        }
+        if self.events.len() >= MAX_PENDING_EVENTS {
+            log::warn!(
+                "Dropping {:?}: there are already {} events waiting for initialization",
+                event,
+                MAX_PENDING_EVENTS
+            );
+            return;
+        }
+        self.events.push(event);
+        self.dirty = true;
+    }
+
+    // Merges in the events read from the database. Those events were queued
+    // first, so they go before any events queued in this session.
+    pub(crate) fn merge_persisted(&mut self, persisted: Vec<PendingEvent>) {
+        let queued = std::mem::take(&mut self.events);
+        let dirty = self.dirty;
+        for event in persisted.into_iter().chain(queued) {
+            self.push(event);
+        }
+        self.dirty = dirty;
+        self.persisted = true;
+    }
+
+    pub(crate) fn needs_persisting(&self) -> bool {
+        self.dirty
+    }
+
+    pub(crate) fn mark_persisted(&mut self) {
+        self.dirty = false;
+        self.persisted = true;
+    }
+
+    pub(crate) fn events(&self) -> &[PendingEvent] {
+        &self.events
+    }
+
+    // Empties the queue, returning the events, and whether the database
+    // may hold a copy of them which now needs deleting.
+    pub(crate) fn take(&mut self) -> (Vec<PendingEvent>, bool) {
+        let events = std::mem::take(&mut self.events);
+        let persisted = std::mem::take(&mut self.persisted);
+        self.dirty = false;
+        (events, persisted)
+    }
+}
@@ -14,7 +14,7 @@ use crate::enrollment::ExperimentEnrollment;
 use crate::Experiment;
 use core::iter::Iterator;
 use rkv::{StoreError, StoreOptions};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 
@@ -25,6 +25,19 @@ use std::path::Path;
 // ⚠️ Warning : Altering the type of `DB_VERSION` would itself require a DB migration. ⚠️
 pub(crate) const DB_KEY_DB_VERSION: &str = "db_version";
 pub(crate) const DB_VERSION: u16 = 2;
+// Alongside the version, we record the oldest `DB_VERSION` able to read each store, so that
+// when an app is downgraded an older SDK can keep the stores it understands and reset only
+// those it doesn't. When making a change to a store that older versions can't read, set its
+// entry here to the new `DB_VERSION`; additive changes which older versions can safely ignore
+// don't need to touch it.
+pub(crate) const DB_KEY_MIN_READER_VERSIONS: &str = "db-min-reader-versions";
+pub(crate) const DB_MIN_READER_VERSIONS: &[(&str, u16)] = &[
+    ("meta", 2),
+    ("experiments", 2),
+    ("enrollments", 2),
+    ("updates", 2),
+    ("event_counts", 2),
+];
 const RKV_MAX_DBS: u32 = 6;
 
 // Inspired by Glean - use a feature to choose between the backends.
@@ -291,6 +304,15 @@ impl Database {
             Some(DB_VERSION) => {
                 // Already at the current version, no migration required.
                 log::info!("Already at version {}, no upgrade needed", DB_VERSION);
+                // Databases created before we tracked reader versions need them added.
+                if self
+                    .meta_store
+                    .get::<HashMap<String, u16>, _>(&writer, DB_KEY_MIN_READER_VERSIONS)?
+                    .is_none()
+                {
+                    self.put_min_reader_versions(&mut writer)?;
+                    writer.commit()?;
+                }
                 return Ok(());
             }
             Some(1) => {
@@ -321,6 +343,9 @@ impl Database {
                 // since nimbus wasn't really shipped to production at the time anyway.
                 self.clear_experiments_and_enrollments(&mut writer)?;
             }
+            Some(version) if version > DB_VERSION => {
+                self.downgrade(&mut writer, version)?;
+            }
             _ => {
                 error_support::report_error!(
                     "nimbus-unknown-database-version",
@@ -337,11 +362,79 @@ impl Database {
         self.updates_store.clear(&mut writer)?;
         self.meta_store
             .put(&mut writer, DB_KEY_DB_VERSION, &DB_VERSION)?;
+        self.put_min_reader_versions(&mut writer)?;
         writer.commit()?;
         log::debug!("maybe_upgrade: transaction committed");
         Ok(())
     }
 
+    fn put_min_reader_versions(&self, writer: &mut Writer) -> Result<()> {
+        let versions: HashMap<String, u16> = DB_MIN_READER_VERSIONS
+            .iter()
+            .map(|(name, version)| (name.to_string(), *version))
+            .collect();
+        self.meta_store
+            .put(writer, DB_KEY_MIN_READER_VERSIONS, &versions)
+    }
+
+    /// Handles a database written by a newer version of the SDK, typically because the app
+    /// was downgraded.
+    ///
+    /// Stores which the newer version says we can still read are kept as they are, so the
+    /// user stays enrolled in their experiments where possible; the others are reset. If the
+    /// newer version didn't say, we have to assume we can't read any of them.
+    fn downgrade(&self, writer: &mut Writer, version: u16) -> Result<()> {
+        log::info!("Downgrading database from v{} to v{}", version, DB_VERSION);
+        let min_reader_versions = self
+            .meta_store
+            .get::<HashMap<String, u16>, _>(writer, DB_KEY_MIN_READER_VERSIONS)
+            .ok()
+            .flatten();
+        let Some(min_reader_versions) = min_reader_versions else {
+            error_support::report_error!(
+                "nimbus-unknown-database-version",
+                "Unknown database version {}. Wiping all stores.",
+                version
+            );
+            self.clear_experiments_and_enrollments(writer)?;
+            self.meta_store.clear(writer)?;
+            return Ok(());
+        };
+        let mut incompatible = Vec::new();
+        for (name, min_version) in &min_reader_versions {
+            if *min_version <= DB_VERSION {
+                continue;
+            }
+            // Stores we've never heard of were added after us, so there's nothing to reset.
+            if let Some(store) = self.get_store_by_name(name) {
+                store.clear(writer)?;
+                incompatible.push(name.as_str());
+            }
+        }
+        incompatible.sort();
+        if !incompatible.is_empty() {
+            error_support::report_error!(
+                "nimbus-database-downgrade",
+                "Database version {} is newer than {}. Reset incompatible stores: {:?}",
+                version,
+                DB_VERSION,
+                incompatible
+            );
+        }
+        Ok(())
+    }
+
+    fn get_store_by_name(&self, name: &str) -> Option<&SingleStore> {
+        Some(self.get_store(match name {
+            "meta" => StoreId::Meta,
+            "experiments" => StoreId::Experiments,
+            "enrollments" => StoreId::Enrollments,
+            "updates" => StoreId::Updates,
+            "event_counts" => StoreId::EventCounts,
+            _ => return None,
+        }))
+    }
+
     pub(crate) fn clear_experiments_and_enrollments(
         &self,
         writer: &mut Writer,
@@ -4,8 +4,13 @@
 
 use crate::{versioning::Version, NimbusError, Result};
 use jexl_eval::Evaluator;
+use jexl_parser::{
+    ast::{Expression, OpCode},
+    Parser,
+};
 use serde::Serialize;
 use serde_json::{json, Value};
+use std::collections::HashSet;
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "stateful")] {
@@ -61,6 +66,16 @@ impl NimbusTargetingHelper {
         }
     }
 
+    pub fn eval_jexl_with_explanation(&self, expr: String) -> Result<TargetingExplanation> {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "stateful")] {
+                jexl_explain(&expr, &self.context, self.event_store.clone())
+            } else {
+                jexl_explain(&expr, &self.context)
+            }
+        }
+    }
+
     pub(crate) fn put(&self, key: &str, value: bool) -> Self {
         let context = if let Value::Object(map) = &self.context {
             let mut map = map.clone();
@@ -89,6 +104,22 @@ pub fn jexl_eval<Context: serde::Serialize>(
     context: &Context,
     #[cfg(feature = "stateful")] event_store: Arc<Mutex<EventStore>>,
 ) -> Result<bool> {
+    let evaluator = create_evaluator(
+        #[cfg(feature = "stateful")]
+        &event_store,
+    );
+    let res = evaluator.eval_in_context(expression_statement, context)?;
+    match res.as_bool() {
+        Some(v) => Ok(v),
+        None => Err(NimbusError::InvalidExpression),
+    }
+}
+
+// Without the `stateful` feature there's no argument to elide the lifetime from.
+#[allow(clippy::needless_lifetimes)]
+fn create_evaluator<'a>(
+    #[cfg(feature = "stateful")] event_store: &'a Arc<Mutex<EventStore>>,
+) -> Evaluator<'a> {
     let evaluator =
         Evaluator::new().with_transform("versionCompare", |args| Ok(version_compare(args)?));
 
@@ -131,10 +162,261 @@ pub fn jexl_eval<Context: serde::Serialize>(
         })
         .with_transform("bucketSample", bucket_sample);
 
-    let res = evaluator.eval_in_context(expression_statement, context)?;
-    match res.as_bool() {
-        Some(v) => Ok(v),
-        None => Err(NimbusError::InvalidExpression),
+    evaluator
+}
+
+/// The result of evaluating a targeting expression, along with the values of the expressions
+/// it's made of, so that experiment owners can see why a client was or wasn't targeted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetingExplanation {
+    /// The result of the whole expression, or `None` if it failed to evaluate or didn't
+    /// evaluate to a boolean.
+    pub result: Option<bool>,
+    /// Why the whole expression failed to evaluate, if it did.
+    pub error: Option<String>,
+    /// The sub-expressions, innermost first, in the order they appear in the expression.
+    /// Literals aren't included.
+    pub sub_expressions: Vec<EvaluatedExpression>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvaluatedExpression {
+    /// The sub-expression, as JEXL.
+    pub expression: String,
+    /// The sub-expression's value as JSON, or `None` if it failed to evaluate.
+    pub value: Option<String>,
+    /// Why the sub-expression failed to evaluate, if it did.
+    pub error: Option<String>,
+}
+
+/// Like `jexl_eval`, but also evaluates each sub-expression on its own. This is only meant for
+/// debugging targeting, since it evaluates the expression many times over.
+pub fn jexl_explain<Context: serde::Serialize>(
+    expression_statement: &str,
+    context: &Context,
+    #[cfg(feature = "stateful")] event_store: Arc<Mutex<EventStore>>,
+) -> Result<TargetingExplanation> {
+    let ast = Parser::parse(expression_statement)
+        .map_err(|e| NimbusError::EvaluationError(e.to_string()))?;
+    let evaluator = create_evaluator(
+        #[cfg(feature = "stateful")]
+        &event_store,
+    );
+    let context = serde_json::to_value(context)?;
+
+    let mut sub_expressions = Vec::new();
+    collect_sub_expressions(&ast, &mut sub_expressions);
+    let mut seen = HashSet::new();
+    let sub_expressions = sub_expressions
+        .into_iter()
+        .filter(|expression| seen.insert(expression.clone()))
+        .map(|expression| {
+            let (value, error) = match evaluator.eval_in_context(&expression, &context) {
+                Ok(value) => (Some(value.to_string()), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            EvaluatedExpression {
+                expression,
+                value,
+                error,
+            }
+        })
+        .collect();
+
+    let (result, error) = match evaluator.eval_in_context(expression_statement, &context) {
+        Ok(value) => match value.as_bool() {
+            Some(v) => (Some(v), None),
+            None => (None, Some(NimbusError::InvalidExpression.to_string())),
+        },
+        Err(e) => (None, Some(NimbusError::from(e).to_string())),
+    };
+    Ok(TargetingExplanation {
+        result,
+        error,
+        sub_expressions,
+    })
+}
+
+/// Collects the JEXL source of each non-literal sub-expression of `expression`, innermost
+/// first, including `expression` itself.
+fn collect_sub_expressions(expression: &Expression, into: &mut Vec<String>) {
+    let start = into.len();
+    match expression {
+        Expression::Number(_) | Expression::String(_) | Expression::Boolean(_) => return,
+        // Filters can only be evaluated as part of the index operation they're in.
+        Expression::Filter { .. } => return,
+        Expression::Array(items) => {
+            for item in items {
+                collect_sub_expressions(item, into);
+            }
+        }
+        Expression::Object(items) => {
+            for (_, value) in items {
+                collect_sub_expressions(value, into);
+            }
+        }
+        Expression::Identifier(_) => {}
+        Expression::BinaryOperation { left, right, .. } => {
+            collect_sub_expressions(left, into);
+            collect_sub_expressions(right, into);
+        }
+        Expression::Transform { subject, args, .. } => {
+            collect_sub_expressions(subject, into);
+            for arg in args.iter().flatten() {
+                collect_sub_expressions(arg, into);
+            }
+        }
+        Expression::DotOperation { subject, .. } => collect_sub_expressions(subject, into),
+        Expression::IndexOperation { subject, index } => {
+            collect_sub_expressions(subject, into);
+            collect_sub_expressions(index, into);
+        }
+        Expression::Conditional {
+            left,
+            truthy,
+            falsy,
+        } => {
+            collect_sub_expressions(left, into);
+            collect_sub_expressions(truthy, into);
+            collect_sub_expressions(falsy, into);
+        }
+    }
+    // Array and object literals are only worth explaining if something in them isn't literal.
+    if matches!(expression, Expression::Array(_) | Expression::Object(_)) && into.len() == start {
+        return;
+    }
+    into.push(to_jexl(expression));
+}
+
+/// Turns an expression back into JEXL, adding parentheses wherever precedence requires them.
+fn to_jexl(expression: &Expression) -> String {
+    // Binary operations are left associative, so a right operand with the same precedence
+    // needs parentheses too.
+    fn binary_operand(expression: &Expression, parent: OpCode, is_right: bool) -> String {
+        match expression {
+            Expression::BinaryOperation { operation, .. }
+                if precedence(*operation) < precedence(parent)
+                    || (is_right && precedence(*operation) == precedence(parent)) =>
+            {
+                format!("({})", to_jexl(expression))
+            }
+            Expression::Conditional { .. } => format!("({})", to_jexl(expression)),
+            _ => to_jexl(expression),
+        }
+    }
+    fn operand(expression: &Expression) -> String {
+        match expression {
+            Expression::BinaryOperation { .. } | Expression::Conditional { .. } => {
+                format!("({})", to_jexl(expression))
+            }
+            _ => to_jexl(expression),
+        }
+    }
+    fn list(items: &[Box<Expression>]) -> String {
+        items
+            .iter()
+            .map(|item| to_jexl(item))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+    match expression {
+        Expression::Number(n) => n.to_string(),
+        Expression::String(s) => format!("\"{}\"", s.replace('"', "\\\"")),
+        Expression::Boolean(b) => b.to_string(),
+        Expression::Array(items) => format!("[{}]", list(items)),
+        Expression::Object(items) => format!(
+            "{{{}}}",
+            items
+                .iter()
+                .map(|(key, value)| format!("\"{}\": {}", key, to_jexl(value)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Expression::Identifier(ident) => ident.clone(),
+        Expression::BinaryOperation {
+            operation,
+            left,
+            right,
+        } => format!(
+            "{} {} {}",
+            binary_operand(left, *operation, false),
+            op_to_jexl(*operation),
+            binary_operand(right, *operation, true)
+        ),
+        Expression::Transform {
+            name,
+            subject,
+            args,
+        } => match args {
+            Some(args) => format!("{}|{}({})", operand(subject), name, list(args)),
+            None => format!("{}|{}", operand(subject), name),
+        },
+        Expression::DotOperation { subject, ident } => {
+            format!("{}.{}", subject_to_jexl(subject), ident)
+        }
+        Expression::IndexOperation { subject, index } => {
+            format!("{}[{}]", subject_to_jexl(subject), to_jexl(index))
+        }
+        Expression::Conditional {
+            left,
+            truthy,
+            falsy,
+        } => format!(
+            "{} ? {} : {}",
+            operand(left),
+            operand(truthy),
+            operand(falsy)
+        ),
+        Expression::Filter { ident, op, right } => {
+            format!(".{} {} {}", ident, op_to_jexl(*op), subject_to_jexl(right))
+        }
+    }
+}
+
+/// Dot and index operations, and filters, bind tighter than transforms too.
+fn subject_to_jexl(subject: &Expression) -> String {
+    match subject {
+        Expression::BinaryOperation { .. }
+        | Expression::Conditional { .. }
+        | Expression::Transform { .. } => format!("({})", to_jexl(subject)),
+        _ => to_jexl(subject),
+    }
+}
+
+fn precedence(op: OpCode) -> u8 {
+    match op {
+        OpCode::And | OpCode::Or => 1,
+        OpCode::Equal
+        | OpCode::NotEqual
+        | OpCode::Less
+        | OpCode::LessEqual
+        | OpCode::Greater
+        | OpCode::GreaterEqual
+        | OpCode::In => 2,
+        OpCode::Add | OpCode::Subtract => 3,
+        OpCode::Multiply | OpCode::Divide | OpCode::FloorDivide => 4,
+        OpCode::Modulus | OpCode::Exponent => 5,
+    }
+}
+
+fn op_to_jexl(op: OpCode) -> &'static str {
+    match op {
+        OpCode::Add => "+",
+        OpCode::Subtract => "-",
+        OpCode::Multiply => "*",
+        OpCode::Divide => "/",
+        OpCode::FloorDivide => "//",
+        OpCode::Less => "<",
+        OpCode::LessEqual => "<=",
+        OpCode::Greater => ">",
+        OpCode::GreaterEqual => ">=",
+        OpCode::Equal => "==",
+        OpCode::NotEqual => "!=",
+        OpCode::And => "&&",
+        OpCode::Or => "||",
+        OpCode::Modulus => "%",
+        OpCode::Exponent => "^",
+        OpCode::In => "in",
     }
 }
 
@@ -4,7 +4,10 @@
 
 use crate::tests::helpers::TestRecordedContext;
 use crate::{
-    enrollment::{DisqualifiedReason, EnrolledReason, EnrollmentStatus, ExperimentEnrollment},
+    enrollment::{
+        DisqualifiedReason, EnrolledReason, EnrollmentChangeEventType, EnrollmentStatus,
+        ExperimentEnrollment,
+    },
     error::Result,
     metrics::MalformedFeatureConfigExtraDef,
     stateful::{
@@ -987,6 +990,190 @@ fn test_fetch_enabled() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_evaluate_targeting_with_explanation() -> Result<()> {
+    let metrics = TestMetrics::new();
+    let temp_dir = tempfile::tempdir()?;
+    let app_context = AppContext {
+        app_name: "fenix".to_string(),
+        app_id: "org.mozilla.fenix".to_string(),
+        channel: "nightly".to_string(),
+        app_version: Some("124.1.0".to_string()),
+        ..Default::default()
+    };
+    let client = NimbusClient::new(
+        app_context,
+        Default::default(),
+        Default::default(),
+        temp_dir.path(),
+        None,
+        Box::new(metrics),
+    )?;
+    client.initialize()?;
+
+    let explanation = client.evaluate_targeting_with_explanation(
+        "app_version|versionCompare('125.!') >= 0 && (channel == 'nightly' || extra.debug)"
+            .to_string(),
+        Some(
+            json!({ "extra": { "debug": true } })
+                .as_object()
+                .unwrap()
+                .clone(),
+        ),
+    )?;
+    assert_eq!(explanation.result, Some(false));
+    assert_eq!(explanation.error, None);
+    let sub_expressions: Vec<_> = explanation
+        .sub_expressions
+        .iter()
+        .map(|e| (e.expression.as_str(), e.value.as_deref()))
+        .collect();
+    assert_eq!(
+        sub_expressions,
+        [
+            ("app_version", Some("\"124.1.0\"")),
+            ("app_version|versionCompare(\"125.!\")", Some("-1")),
+            ("app_version|versionCompare(\"125.!\") >= 0", Some("false")),
+            ("channel", Some("\"nightly\"")),
+            ("channel == \"nightly\"", Some("true")),
+            ("extra", Some("{\"debug\":true}")),
+            ("extra.debug", Some("true")),
+            ("channel == \"nightly\" || extra.debug", Some("true")),
+            (
+                "app_version|versionCompare(\"125.!\") >= 0 && (channel == \"nightly\" || extra.debug)",
+                Some("false")
+            ),
+        ]
+    );
+
+    // Sub-expressions which fail don't stop the others from being explained.
+    let explanation = client.evaluate_targeting_with_explanation(
+        "missing_attribute || is_already_enrolled".to_string(),
+        None,
+    )?;
+    assert_eq!(explanation.result, Some(false));
+    assert_eq!(
+        explanation.sub_expressions[0].expression,
+        "missing_attribute"
+    );
+    assert_eq!(explanation.sub_expressions[0].value, None);
+    assert!(explanation.sub_expressions[0].error.is_some());
+    assert_eq!(
+        explanation.sub_expressions[1].value.as_deref(),
+        Some("false")
+    );
+
+    // Expressions which aren't booleans have no result.
+    let explanation = client.evaluate_targeting_with_explanation("channel".to_string(), None)?;
+    assert_eq!(explanation.result, None);
+    assert!(explanation.error.is_some());
+
+    // But expressions which don't parse are errors.
+    assert!(client
+        .evaluate_targeting_with_explanation("channel ==".to_string(), None)
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_simulate_enrollment() -> Result<()> {
+    let metrics = TestMetrics::new();
+    let temp_dir = tempfile::tempdir()?;
+    let app_context = AppContext {
+        app_name: "fenix".to_string(),
+        app_id: "org.mozilla.fenix".to_string(),
+        channel: "nightly".to_string(),
+        ..Default::default()
+    };
+    let client = NimbusClient::new(
+        app_context,
+        Default::default(),
+        Default::default(),
+        temp_dir.path(),
+        None,
+        Box::new(metrics),
+    )?;
+    client.initialize()?;
+
+    let rollout = get_bucketed_rollout("rollout", 10_000);
+    let simulation = client.simulate_enrollment(serde_json::to_string(&rollout)?, None)?;
+    assert_eq!(simulation.previous_status, None);
+    assert_eq!(simulation.status, "Enrolled");
+    assert_eq!(simulation.reason.as_deref(), Some("Qualified"));
+    assert_eq!(simulation.branch.as_deref(), Some("control"));
+    assert_eq!(
+        simulation.change.map(|event| event.change),
+        Some(EnrollmentChangeEventType::Enrollment)
+    );
+    assert_eq!(simulation.in_bucket, Some(true));
+    assert!(simulation.targeting.is_none());
+    // Simulating doesn't enroll us.
+    assert!(client
+        .get_enrollment_by_feature("a-feature".into())?
+        .is_none());
+
+    client.set_experiments_locally(to_local_experiments_string(&[&rollout])?)?;
+    client.apply_pending_experiments()?;
+
+    // Pausing enrollment keeps us enrolled.
+    let mut paused = rollout.clone();
+    paused.is_enrollment_paused = true;
+    let simulation = client.simulate_enrollment(serde_json::to_string(&paused)?, None)?;
+    assert_eq!(simulation.previous_status.as_deref(), Some("Enrolled"));
+    assert_eq!(simulation.status, "Enrolled");
+    assert!(simulation.change.is_none());
+
+    // A second rollout of the same feature conflicts with the first.
+    paused.slug = "paused-rollout".to_string();
+    let simulation = client.simulate_enrollment(serde_json::to_string(&paused)?, None)?;
+    assert_eq!(simulation.status, "NotEnrolled");
+    assert_eq!(simulation.reason.as_deref(), Some("FeatureConflict"));
+
+    // But a recipe we haven't seen doesn't enroll us while it's paused.
+    let mut paused = get_single_feature_rollout("paused-rollout", "b-feature", json!({}));
+    paused.is_enrollment_paused = true;
+    let simulation = client.simulate_enrollment(serde_json::to_string(&paused)?, None)?;
+    assert_eq!(simulation.previous_status, None);
+    assert_eq!(simulation.status, "NotEnrolled");
+    assert_eq!(simulation.reason.as_deref(), Some("EnrollmentsPaused"));
+
+    // Scaling the rollout back to nobody disqualifies us.
+    let scaled_back = get_bucketed_rollout("rollout", 0);
+    let simulation = client.simulate_enrollment(serde_json::to_string(&scaled_back)?, None)?;
+    assert_eq!(simulation.status, "Disqualified");
+    assert_eq!(simulation.reason.as_deref(), Some("NotSelected"));
+    assert_eq!(simulation.branch.as_deref(), Some("control"));
+    assert_eq!(simulation.in_bucket, Some(false));
+    assert_eq!(
+        simulation.change.map(|event| event.change),
+        Some(EnrollmentChangeEventType::Disqualification)
+    );
+    assert!(client
+        .get_enrollment_by_feature("a-feature".into())?
+        .is_some());
+
+    // Targeting is explained, including with any additional context.
+    let experiment = get_targeted_experiment("experiment", "extra.debug || channel == 'release'");
+    let simulation = client.simulate_enrollment(experiment.to_string(), None)?;
+    assert_eq!(simulation.status, "NotEnrolled");
+    assert_eq!(simulation.reason.as_deref(), Some("NotTargeted"));
+    assert_eq!(simulation.targeting.unwrap().result, Some(false));
+    let simulation = client.simulate_enrollment(
+        experiment.to_string(),
+        Some(
+            json!({ "extra": { "debug": true } })
+                .as_object()
+                .unwrap()
+                .clone(),
+        ),
+    )?;
+    assert_eq!(simulation.status, "Enrolled");
+    assert_eq!(simulation.targeting.unwrap().result, Some(true));
+
+    assert!(client.simulate_enrollment("{}".to_string(), None).is_err());
+    Ok(())
+}
+
 #[test]
 fn test_active_enrollment_in_targeting() -> Result<()> {
     let metrics = TestMetrics::new();
@@ -1594,6 +1781,84 @@ fn test_malformed_feature_events() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_events_recorded_before_initialize_are_queued() -> Result<()> {
+    let slug_exp = "my-experiment";
+    let feature_exp = "experimental-feature";
+    let rec_exp = get_single_feature_experiment(slug_exp, feature_exp, json!({}));
+    let part = "my-part";
+
+    let temp_dir = tempfile::tempdir()?;
+    let new_client = |metrics: &TestMetrics| {
+        NimbusClient::new(
+            AppContext {
+                app_name: "fenix".to_string(),
+                app_id: "org.mozilla.fenix".to_string(),
+                channel: "nightly".to_string(),
+                ..Default::default()
+            },
+            Default::default(),
+            Default::default(),
+            temp_dir.path(),
+            None,
+            Box::new(metrics.clone()),
+        )
+    };
+
+    // The first session records some events, but doesn't get as far as
+    // finishing initialization.
+    let metrics = TestMetrics::new();
+    let client = new_client(&metrics)?;
+    client.record_feature_exposure(feature_exp.to_string(), None);
+    client.record_feature_exposure(feature_exp.to_string(), None);
+    client.record_malformed_feature_config(feature_exp.to_string(), part.to_string());
+    assert!(metrics.get_exposures().is_empty());
+    assert!(metrics.get_malformeds().is_empty());
+    client.persist_pending_events(client.db()?)?;
+    drop(client);
+
+    // The next session records another event before it's initialized.
+    let metrics = TestMetrics::new();
+    let client = new_client(&metrics)?;
+    client.record_feature_exposure(feature_exp.to_string(), Some(slug_exp.to_string()));
+    client.set_experiments_locally(to_local_experiments_string(&[rec_exp])?)?;
+    client.apply_pending_experiments()?;
+
+    // The duplicate exposure was coalesced, and the events now know which
+    // experiment they are about.
+    let exposures = metrics.get_exposures();
+    assert_eq!(2, exposures.len());
+    for ev in &exposures {
+        assert_eq!(slug_exp, ev.slug);
+        assert_eq!(Some("control"), ev.branch.as_deref());
+        assert_eq!(feature_exp, ev.feature_id);
+    }
+    assert_eq!(
+        vec![MalformedFeatureConfigExtraDef {
+            slug: Some(slug_exp.to_string()),
+            branch: Some("control".to_string()),
+            feature_id: feature_exp.to_string(),
+            part: part.to_string()
+        }],
+        metrics.get_malformeds()
+    );
+
+    // The persisted queue has been removed, so the events aren't recorded again.
+    let db = client.db()?;
+    assert!(db
+        .get::<serde_json::Value>(StoreId::Meta, "pending-events")?
+        .is_none());
+    metrics.clear();
+    client.initialize()?;
+    assert!(metrics.get_exposures().is_empty());
+
+    // Now that the client is initialized, events are recorded straight away.
+    client.record_feature_exposure(feature_exp.to_string(), None);
+    assert_eq!(1, metrics.get_exposures().len());
+
+    Ok(())
+}
+
 #[test]
 fn test_new_enrollment_in_targeting_mid_run() -> Result<()> {
     let metrics = TestMetrics::new();
@@ -7,6 +7,7 @@ use crate::{
 };
 use rkv::StoreOptions;
 use serde_json::json;
+use std::collections::HashMap;
 use std::fs;
 
 #[test]
@@ -55,6 +56,84 @@ fn test_db_upgrade_unknown_version() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_db_downgrade_resets_only_incompatible_stores() -> Result<()> {
+    let tmp_dir = tempfile::tempdir()?;
+
+    let rkv = Database::open_rkv(&tmp_dir)?;
+    let meta_store = SingleStore::new(rkv.open_single("meta", StoreOptions::create())?);
+    let experiment_store =
+        SingleStore::new(rkv.open_single("experiments", StoreOptions::create())?);
+    let enrollment_store =
+        SingleStore::new(rkv.open_single("enrollments", StoreOptions::create())?);
+    let event_count_store =
+        SingleStore::new(rkv.open_single("event_counts", StoreOptions::create())?);
+    let mut writer = rkv.write()?;
+    let newer_version = DB_VERSION + 1;
+    meta_store.put(&mut writer, DB_KEY_DB_VERSION, &newer_version)?;
+    meta_store.put(&mut writer, "nimbus-id", &"some-id".to_owned())?;
+    meta_store.put(
+        &mut writer,
+        DB_KEY_MIN_READER_VERSIONS,
+        &json!({
+            "meta": DB_VERSION,
+            "experiments": DB_VERSION,
+            "enrollments": DB_VERSION,
+            "updates": DB_VERSION,
+            "event_counts": newer_version,
+            "some_new_store": newer_version,
+        }),
+    )?;
+    enrollment_store.put(&mut writer, "foo", &"bar".to_owned())?;
+    experiment_store.put(&mut writer, "bobo", &"tron".to_owned())?;
+    event_count_store.put(&mut writer, "event", &"counts".to_owned())?;
+    writer.commit()?;
+
+    let db = Database::new(&tmp_dir)?;
+    assert_eq!(db.get(StoreId::Meta, DB_KEY_DB_VERSION)?, Some(DB_VERSION));
+    assert_eq!(
+        db.get::<String>(StoreId::Meta, "nimbus-id")?,
+        Some("some-id".to_owned())
+    );
+    assert_eq!(
+        db.collect_all::<String>(StoreId::Enrollments)?,
+        vec!["bar".to_owned()]
+    );
+    assert_eq!(
+        db.collect_all::<String>(StoreId::Experiments)?,
+        vec!["tron".to_owned()]
+    );
+    assert!(db.collect_all::<String>(StoreId::EventCounts)?.is_empty());
+    // Our own reader versions replace the newer ones.
+    let min_reader_versions: HashMap<String, u16> =
+        db.get(StoreId::Meta, DB_KEY_MIN_READER_VERSIONS)?.unwrap();
+    assert_eq!(min_reader_versions.len(), DB_MIN_READER_VERSIONS.len());
+    assert_eq!(min_reader_versions["event_counts"], DB_VERSION);
+
+    Ok(())
+}
+
+#[test]
+fn test_db_min_reader_versions_added_to_current_version() -> Result<()> {
+    let tmp_dir = tempfile::tempdir()?;
+
+    let rkv = Database::open_rkv(&tmp_dir)?;
+    let meta_store = SingleStore::new(rkv.open_single("meta", StoreOptions::create())?);
+    let mut writer = rkv.write()?;
+    meta_store.put(&mut writer, DB_KEY_DB_VERSION, &DB_VERSION)?;
+    writer.commit()?;
+
+    let db = Database::new(&tmp_dir)?;
+    let min_reader_versions: Option<HashMap<String, u16>> =
+        db.get(StoreId::Meta, DB_KEY_MIN_READER_VERSIONS)?;
+    assert_eq!(
+        min_reader_versions.unwrap().len(),
+        DB_MIN_READER_VERSIONS.len()
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_corrupt_db() -> Result<()> {
     let tmp_dir = tempfile::tempdir()?;
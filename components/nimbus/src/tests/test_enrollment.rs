@@ -11,7 +11,7 @@ use crate::{
     error::Result,
     tests::helpers::{
         get_ios_rollout_experiment, get_multi_feature_experiment, get_single_feature_experiment,
-        get_test_experiments, no_coenrolling_features,
+        get_single_feature_rollout, get_test_experiments, no_coenrolling_features,
     },
     AppContext, AvailableRandomizationUnits, Branch, BucketConfig, Experiment, FeatureConfig,
     NimbusTargetingHelper, TargetingAttributes,
@@ -1889,6 +1889,70 @@ fn test_map_features_by_feature_id_with_coenrollment() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_merge_feature_config_with_sources() -> Result<()> {
+    let ro1 = get_single_feature_rollout("ro1", "coenrolling", json!({ "a": 1, "b": 1 }));
+    let ro2 = get_single_feature_rollout("ro2", "coenrolling", json!({ "b": 2, "c": 2 }));
+    let exp1 = get_single_feature_experiment("exp1", "coenrolling", json!({ "c": 3, "d": null }));
+    let exp2 = get_single_feature_experiment("exp2", "colliding", json!({ "x": 1 }));
+    let ro3 = get_single_feature_rollout("ro3", "colliding", json!({ "x": 0, "y": 0 }));
+    let exps = [ro1, ro2, exp1, exp2, ro3];
+    let enrollments: Vec<_> = ["exp1", "exp2", "ro1", "ro2", "ro3"]
+        .into_iter()
+        .map(ExperimentEnrollment::enrolled)
+        .collect();
+    let ids = HashSet::from(["coenrolling"]);
+
+    let source = |variable: &str, slug: &str, is_rollout| FeatureVariableSource {
+        variable: variable.to_string(),
+        slug: slug.to_string(),
+        is_rollout,
+    };
+    let merged =
+        merge_feature_config_with_sources(&enrollments, &exps, &ids, "coenrolling")?.unwrap();
+    assert_eq!(
+        serde_json::from_str::<Value>(&merged.variables)?,
+        // `d` is null, so has no source.
+        json!({ "a": 1, "b": 2, "c": 3, "d": null })
+    );
+    assert_eq!(
+        merged.sources,
+        vec![
+            source("a", "ro1", true),
+            source("b", "ro2", true),
+            source("c", "exp1", false),
+        ]
+    );
+
+    let merged =
+        merge_feature_config_with_sources(&enrollments, &exps, &ids, "colliding")?.unwrap();
+    assert_eq!(
+        serde_json::from_str::<Value>(&merged.variables)?,
+        json!({ "x": 1, "y": 0 })
+    );
+    assert_eq!(
+        merged.sources,
+        vec![source("x", "exp2", false), source("y", "ro3", true)]
+    );
+
+    // The variables are the same as the ones we'd normally use.
+    let features = map_features_by_feature_id(&enrollments, &exps, &ids);
+    for feature_id in ["coenrolling", "colliding"] {
+        let merged =
+            merge_feature_config_with_sources(&enrollments, &exps, &ids, feature_id)?.unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&merged.variables)?,
+            Value::Object(features[feature_id].feature.value.clone())
+        );
+    }
+
+    assert_eq!(
+        merge_feature_config_with_sources(&enrollments, &exps, &ids, "unknown")?,
+        None
+    );
+    Ok(())
+}
+
 #[test]
 fn test_map_features_by_feature_id_with_coenrolling_multifeature() -> Result<()> {
     let exp1 = get_multi_feature_experiment(
@@ -46,9 +46,21 @@ CREATE INDEX IF NOT EXISTS originidindex ON moz_places(origin_id);
 
 
 CREATE TABLE IF NOT EXISTS moz_places_tombstones (
-    guid TEXT PRIMARY KEY
+    guid TEXT PRIMARY KEY,
+    -- When the page was deleted, in milliseconds. Used to expire tombstones
+    -- that will never be uploaded because history sync isn't connected.
+    deleted_at INTEGER NOT NULL DEFAULT 0
 ) WITHOUT ROWID;
 
+-- Pages the user recently closed. These aren't synced, and aren't tied to moz_places,
+-- so that they're kept when the page's history is removed.
+CREATE TABLE IF NOT EXISTS moz_recently_closed (
+    id INTEGER PRIMARY KEY,
+    url TEXT NOT NULL,
+    title TEXT,
+    closed_at INTEGER NOT NULL
+);
+
 
 -- This table stores Place IDs with stale frecencies, along with the time they
 -- were marked as stale. Maintenance and Sync periodically recalculate
@@ -69,6 +81,8 @@ CREATE TABLE IF NOT EXISTS moz_historyvisits (
     visit_type INTEGER NOT NULL,
     -- session INTEGER, -- XXX - what is 'session'? Appears unused.
     unknown_fields TEXT,
+    -- The container or private context the visit was made in, if any. Local-only, never synced.
+    container_id TEXT,
 
     FOREIGN KEY(place_id) REFERENCES moz_places(id) ON DELETE CASCADE,
     FOREIGN KEY(from_visit) REFERENCES moz_historyvisits(id)
@@ -85,6 +99,7 @@ CREATE INDEX IF NOT EXISTS visits_from_type_idx ON moz_historyvisits(from_visit,
 CREATE TABLE IF NOT EXISTS moz_historyvisit_tombstones (
     place_id INTEGER NOT NULL,
     visit_date INTEGER NOT NULL,
+    deleted_at INTEGER NOT NULL DEFAULT 0, -- In milliseconds, as above.
     FOREIGN KEY(place_id) REFERENCES moz_places(id) ON DELETE CASCADE,
     PRIMARY KEY(place_id, visit_date)
 );
@@ -129,6 +144,19 @@ CREATE INDEX IF NOT EXISTS itemlastmodifiedindex ON moz_bookmarks(fk, lastModifi
 -- CREATE INDEX IF NOT EXISTS dateaddedindex ON moz_bookmarks(dateAdded);
 CREATE UNIQUE INDEX IF NOT EXISTS guid_uniqueindex ON moz_bookmarks(guid);
 
+-- The URLs a bookmark had before it was edited, written by
+-- `moz_bookmarks_url_history_afterupdate_trigger`. We store the URL rather than the place
+-- ID, so that the old URL is kept even if its page is removed.
+CREATE TABLE IF NOT EXISTS moz_bookmarks_url_history (
+    id INTEGER PRIMARY KEY,
+    bookmark_id INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    changed_at INTEGER NOT NULL,
+
+    FOREIGN KEY(bookmark_id) REFERENCES moz_bookmarks(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS bookmarkurlhistoryindex ON moz_bookmarks_url_history(bookmark_id);
+
 
 CREATE TABLE IF NOT EXISTS moz_bookmarks_deleted (
     guid TEXT PRIMARY KEY,
@@ -149,6 +177,16 @@ CREATE TABLE IF NOT EXISTS moz_origins (
 
 CREATE INDEX IF NOT EXISTS hostindex ON moz_origins(rev_host);
 
+-- Origins whose history has been merged into another, such as after a site moved
+-- from http to https. New visits to pages on `from` are recorded on `to` instead.
+CREATE TABLE IF NOT EXISTS moz_origin_merges (
+    from_prefix TEXT NOT NULL,
+    from_host TEXT NOT NULL,
+    to_prefix TEXT NOT NULL,
+    to_host TEXT NOT NULL,
+    PRIMARY KEY (from_prefix, from_host)
+) WITHOUT ROWID;
+
 
 -- This table holds key-value metadata for Places and its consumers. Sync stores
 -- the sync IDs for the bookmarks and history collections in this table, and the
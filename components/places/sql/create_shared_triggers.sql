@@ -78,6 +78,24 @@ BEGIN
         WHERE OLD.fk <> NEW.fk AND id = OLD.fk;
 END;
 
+-- Remember the old URL when a bookmark's URL changes, whether it was edited locally or
+-- by Sync, keeping the most recent few for each bookmark.
+-- NOTE: the value "10" below is MAX_BOOKMARK_URL_HISTORY.
+CREATE TEMP TRIGGER moz_bookmarks_url_history_afterupdate_trigger
+AFTER UPDATE OF fk ON moz_bookmarks FOR EACH ROW
+WHEN OLD.fk <> NEW.fk
+BEGIN
+    INSERT INTO moz_bookmarks_url_history(bookmark_id, url, changed_at)
+    SELECT NEW.id, url, NEW.lastModified FROM moz_places WHERE id = OLD.fk;
+    DELETE FROM moz_bookmarks_url_history
+    WHERE bookmark_id = NEW.id AND id NOT IN (
+        SELECT id FROM moz_bookmarks_url_history
+        WHERE bookmark_id = NEW.id
+        ORDER BY id DESC
+        LIMIT 10
+    );
+END;
+
 -- The next several triggers are a workaround for the lack of FOR EACH STATEMENT
 -- in Sqlite, (see bug 871908).
 --
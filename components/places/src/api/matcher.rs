@@ -5,7 +5,8 @@
 use crate::db::PlacesDb;
 use crate::error::Result;
 use crate::ffi::SearchResult as FfiSearchResult;
-pub use crate::match_impl::{MatchBehavior, SearchBehavior};
+use crate::match_impl::{find_match_ranges, find_url_match_ranges};
+pub use crate::match_impl::{MatchBehavior, MatchRange, SearchBehavior};
 use rusqlite::Row;
 use serde_derive::*;
 use sql_support::ConnExt;
@@ -82,6 +83,11 @@ pub fn search_frecent(conn: &PlacesDb, params: SearchParams) -> Result<Vec<Searc
     matches.sort_unstable_by(|a, b| a.url.cmp(&b.url));
     matches.dedup_by(|a, b| a.url == b.url);
 
+    for m in &mut matches {
+        m.title_match_ranges = find_match_ranges(&params.search_string, &m.title);
+        m.url_match_ranges = find_url_match_ranges(&params.search_string, m.url.as_str());
+    }
+
     Ok(matches)
 }
 
@@ -198,6 +204,12 @@ pub struct SearchResult {
 
     /// A frecency score for this match.
     pub frecency: i64,
+
+    /// The parts of `title` which matched the search string.
+    pub title_match_ranges: Vec<MatchRange>,
+
+    /// The parts of `url` which matched the search string.
+    pub url_match_ranges: Vec<MatchRange>,
 }
 
 impl SearchResult {
@@ -219,6 +231,8 @@ impl SearchResult {
             title,
             icon_url: None,
             frecency,
+            title_match_ranges: Vec::new(),
+            url_match_ranges: Vec::new(),
         })
     }
 
@@ -240,6 +254,8 @@ impl SearchResult {
             title,
             icon_url: None,
             frecency,
+            title_match_ranges: Vec::new(),
+            url_match_ranges: Vec::new(),
         })
     }
 
@@ -257,6 +273,8 @@ impl SearchResult {
             title: display_url,
             icon_url: None,
             frecency,
+            title_match_ranges: Vec::new(),
+            url_match_ranges: Vec::new(),
         })
     }
 
@@ -291,6 +309,8 @@ impl SearchResult {
             title: display_url,
             icon_url: None,
             frecency,
+            title_match_ranges: Vec::new(),
+            url_match_ranges: Vec::new(),
         })
     }
 }
@@ -301,6 +321,8 @@ impl From<SearchResult> for FfiSearchResult {
             url: res.url,
             title: res.title,
             frecency: res.frecency,
+            title_match_ranges: res.title_match_ranges,
+            url_match_ranges: res.url_match_ranges,
         }
     }
 }
@@ -683,6 +705,8 @@ mod tests {
                 title: "example.com/".into(),
                 icon_url: None,
                 frecency: 2000,
+                title_match_ranges: vec![MatchRange { start: 0, end: 7 }],
+                url_match_ranges: vec![MatchRange { start: 7, end: 14 }],
             }]
         );
     }
@@ -4,10 +4,12 @@
 
 use crate::bookmark_sync::BookmarksSyncEngine;
 use crate::db::db::{PlacesDb, SharedPlacesDb};
+use crate::db::WriteQueue;
 use crate::error::*;
 use crate::history_sync::HistorySyncEngine;
 use crate::storage::{
     self, bookmarks::bookmark_sync, delete_meta, get_meta, history::history_sync, put_meta,
+    PlacesLimits,
 };
 use crate::util::normalize_path;
 use error_support::handle_error;
@@ -134,6 +136,14 @@ pub fn places_api_new(db_name: impl AsRef<Path>) -> ApiResult<Arc<PlacesApi>> {
     PlacesApi::new(db_name)
 }
 
+#[handle_error(crate::Error)]
+pub fn places_api_new_with_limits(
+    db_name: impl AsRef<Path>,
+    limits: PlacesLimits,
+) -> ApiResult<Arc<PlacesApi>> {
+    PlacesApi::new_with_limits(db_name, limits)
+}
+
 /// The entry-point to the places API. This object gives access to database
 /// connections and other helpers. It enforces that only 1 write connection
 /// can exist to the database at once.
@@ -141,7 +151,8 @@ pub struct PlacesApi {
     db_name: PathBuf,
     write_connection: Mutex<Option<PlacesDb>>,
     sync_state: Mutex<Option<SyncState>>,
-    coop_tx_lock: Arc<Mutex<()>>,
+    // Decides which of the write and sync connections starts its next transaction first.
+    write_queue: Arc<WriteQueue>,
     // Used for get_sync_connection()
     // - The inner mutex synchronizes sync operation (for example one of the [SyncEngine] methods).
     //   This avoids issues like #867
@@ -152,46 +163,65 @@ pub struct PlacesApi {
     //   ran that at the same time there would be issues.
     sync_connection: Mutex<Weak<SharedPlacesDb>>,
     id: usize,
+    limits: PlacesLimits,
 }
 
 impl PlacesApi {
     /// Create a new, or fetch an already open, PlacesApi backed by a file on disk.
     pub fn new(db_name: impl AsRef<Path>) -> Result<Arc<Self>> {
+        Self::new_with_limits(db_name, PlacesLimits::default())
+    }
+
+    /// Like [`PlacesApi::new`], but stores URLs and titles up to the lengths in `limits`, rather
+    /// than the defaults. Fails if a PlacesApi for the same file is already open with different
+    /// limits.
+    pub fn new_with_limits(db_name: impl AsRef<Path>, limits: PlacesLimits) -> Result<Arc<Self>> {
         let db_name = normalize_path(db_name)?;
-        Self::new_or_existing(db_name)
+        Self::new_or_existing(db_name, limits)
     }
 
     /// Create a new, or fetch an already open, memory-based PlacesApi. You must
     /// provide a name, but you are still able to have a single writer and many
     ///  reader connections to the same memory DB open.
     pub fn new_memory(db_name: &str) -> Result<Arc<Self>> {
+        Self::new_memory_with_limits(db_name, PlacesLimits::default())
+    }
+
+    pub fn new_memory_with_limits(db_name: &str, limits: PlacesLimits) -> Result<Arc<Self>> {
         let name = PathBuf::from(format!("file:{}?mode=memory&cache=shared", db_name));
-        Self::new_or_existing(name)
+        Self::new_or_existing(name, limits)
     }
     fn new_or_existing_into(
         target: &mut HashMap<PathBuf, Weak<PlacesApi>>,
         db_name: PathBuf,
+        limits: PlacesLimits,
     ) -> Result<Arc<Self>> {
         let id = ID_COUNTER.fetch_add(1, Ordering::SeqCst);
         match target.get(&db_name).and_then(Weak::upgrade) {
+            Some(existing) if existing.limits != limits => Err(Error::InvalidPlacesLimits(
+                "the database is already open with different limits".to_string(),
+            )),
             Some(existing) => Ok(existing),
             None => {
                 // We always create a new read-write connection for an initial open so
                 // we can create the schema and/or do version upgrades.
-                let coop_tx_lock = Arc::new(Mutex::new(()));
+                let write_queue = Arc::new(WriteQueue::new());
                 let connection = PlacesDb::open(
                     &db_name,
                     ConnectionType::ReadWrite,
                     id,
-                    coop_tx_lock.clone(),
+                    write_queue.clone(),
+                    limits,
                 )?;
+                limits.validate(&connection)?;
                 let new = PlacesApi {
                     db_name: db_name.clone(),
                     write_connection: Mutex::new(Some(connection)),
                     sync_state: Mutex::new(None),
                     sync_connection: Mutex::new(Weak::new()),
                     id,
-                    coop_tx_lock,
+                    write_queue,
+                    limits,
                 };
                 let arc = Arc::new(new);
                 target.insert(db_name, Arc::downgrade(&arc));
@@ -200,9 +230,14 @@ impl PlacesApi {
         }
     }
 
-    fn new_or_existing(db_name: PathBuf) -> Result<Arc<Self>> {
+    fn new_or_existing(db_name: PathBuf, limits: PlacesLimits) -> Result<Arc<Self>> {
         let mut guard = APIS.lock();
-        Self::new_or_existing_into(&mut guard, db_name)
+        Self::new_or_existing_into(&mut guard, db_name, limits)
+    }
+
+    /// The URL and title length limits this API was created with.
+    pub fn limits(&self) -> PlacesLimits {
+        self.limits
     }
 
     /// Open a connection to the database.
@@ -214,7 +249,8 @@ impl PlacesApi {
                     self.db_name.clone(),
                     ConnectionType::ReadOnly,
                     self.id,
-                    self.coop_tx_lock.clone(),
+                    self.write_queue.clone(),
+                    self.limits,
                 )
             }
             ConnectionType::ReadWrite => {
@@ -250,7 +286,8 @@ impl PlacesApi {
                     self.db_name.clone(),
                     ConnectionType::Sync,
                     self.id,
-                    self.coop_tx_lock.clone(),
+                    self.write_queue.clone(),
+                    self.limits,
                 )?));
                 register_interrupt(Arc::<SharedPlacesDb>::downgrade(&db));
                 // Store a weakref for next time
@@ -603,4 +640,84 @@ mod tests {
         // Make sure we can open it again.
         assert!(api.open_connection(ConnectionType::ReadWrite).is_ok());
     }
+
+    #[test]
+    fn test_limits() {
+        use crate::storage::history::apply_observation;
+        use crate::{VisitObservation, VisitType};
+        use url::Url;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("places.sqlite");
+        let title = |conn: &PlacesDb, url: &str| -> String {
+            conn.query_row("SELECT title FROM moz_places WHERE url = ?", [url], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        let long_title = "x".repeat(100);
+
+        let api = PlacesApi::new(&path).unwrap();
+        assert_eq!(api.limits(), PlacesLimits::default());
+        let conn = api.open_connection(ConnectionType::ReadWrite).unwrap();
+        apply_observation(
+            &conn,
+            VisitObservation::new(Url::parse("https://example.com/long").unwrap())
+                .with_title(long_title.clone())
+                .with_visit_type(VisitType::Link),
+        )
+        .unwrap();
+        drop(conn);
+        drop(api);
+
+        let limits = PlacesLimits {
+            max_url_length: 30,
+            max_title_length: 10,
+        };
+        let api = PlacesApi::new_with_limits(&path, limits).unwrap();
+        assert!(matches!(
+            PlacesApi::new(&path),
+            Err(Error::InvalidPlacesLimits(_))
+        ));
+        let conn = api.open_connection(ConnectionType::ReadWrite).unwrap();
+        assert_eq!(conn.limits(), limits);
+        // Lowering the limits doesn't truncate what's already stored.
+        assert_eq!(title(&conn, "https://example.com/long"), long_title);
+
+        apply_observation(
+            &conn,
+            VisitObservation::new(Url::parse("https://example.com/short").unwrap())
+                .with_title(long_title.clone())
+                .with_visit_type(VisitType::Link),
+        )
+        .unwrap();
+        assert_eq!(title(&conn, "https://example.com/short"), "xxxxxxxxxx");
+        let row = apply_observation(
+            &conn,
+            VisitObservation::new(
+                Url::parse("https://example.com/too/long/for/the/limit").unwrap(),
+            )
+            .with_visit_type(VisitType::Link),
+        )
+        .unwrap();
+        assert!(row.is_none(), "Shouldn't insert a URL over the limit");
+        drop(conn);
+        drop(api);
+
+        for invalid in [
+            PlacesLimits {
+                max_url_length: 0,
+                ..PlacesLimits::default()
+            },
+            PlacesLimits {
+                max_title_length: u32::MAX,
+                ..PlacesLimits::default()
+            },
+        ] {
+            assert!(matches!(
+                PlacesApi::new_memory_with_limits("test-invalid-limits", invalid),
+                Err(Error::InvalidPlacesLimits(_))
+            ));
+        }
+    }
 }
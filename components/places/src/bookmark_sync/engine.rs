@@ -7,7 +7,7 @@ use super::record::{
     BookmarkItemRecord, BookmarkRecord, BookmarkRecordId, FolderRecord, QueryRecord,
     SeparatorRecord,
 };
-use super::{SyncedBookmarkKind, SyncedBookmarkValidity};
+use super::{BookmarkConflictPolicy, SyncedBookmarkKind, SyncedBookmarkValidity};
 use crate::db::{GlobalChangeCounterTracker, PlacesDb, SharedPlacesDb};
 use crate::error::*;
 use crate::frecency::{calculate_frecency, DEFAULT_FRECENCY_SETTINGS};
@@ -20,16 +20,18 @@ use crate::storage::{
 };
 use crate::types::{BookmarkType, SyncStatus, UnknownFields};
 use dogear::{
-    self, AbortSignal, CompletionOps, Content, Item, MergedRoot, TelemetryEvent, Tree, UploadItem,
-    UploadTombstone,
+    self, AbortSignal, CompletionOps, Content, Item, MergeState, MergedNode, MergedRoot,
+    TelemetryEvent, Tree, UploadItem, UploadTombstone,
 };
 use interrupt_support::SqlInterruptScope;
 use rusqlite::Row;
+use serde_derive::*;
 use sql_support::ConnExt;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
+use std::time::Duration;
 use sync15::bso::{IncomingBso, OutgoingBso};
 use sync15::engine::{CollSyncIds, CollectionRequest, EngineSyncAssociation, SyncEngine};
 use sync15::{telemetry, CollectionName, ServerTimestamp};
@@ -41,6 +43,13 @@ pub const LAST_SYNC_META_KEY: &str = "bookmarks_last_sync_time";
 pub const GLOBAL_SYNCID_META_KEY: &str = "bookmarks_global_sync_id";
 pub const COLLECTION_SYNCID_META_KEY: &str = "bookmarks_sync_id";
 pub const COLLECTION_NAME: &str = "bookmarks";
+pub const CONFLICT_POLICY_META_KEY: &str = "bookmarks_conflict_policy";
+pub const LAST_CONFLICT_REPORT_META_KEY: &str = "bookmarks_last_conflict_report";
+
+/// Added to the ages of the side that the conflict policy doesn't prefer, so
+/// that its changes always look older. This is far larger than any real age,
+/// but leaves room to keep the relative ages of items on that side.
+const CONFLICT_POLICY_AGE_OFFSET: i64 = i64::MAX / 2;
 
 /// The maximum number of URLs for which to recalculate frecencies at once.
 /// This is a trade-off between write efficiency and transaction time: higher
@@ -1104,6 +1113,10 @@ pub(crate) struct Merger<'a> {
     // Allows us to abort applying the result of the merge if the local tree
     // changed since we fetched it.
     global_change_tracker: GlobalChangeCounterTracker,
+    // Read from `moz_meta` at the start of each merge.
+    conflict_policy: BookmarkConflictPolicy,
+    // Set once the merged tree has been applied.
+    conflict_report: Option<ConflictReport>,
 }
 
 impl<'a> Merger<'a> {
@@ -1121,6 +1134,8 @@ impl<'a> Merger<'a> {
             external_transaction: false,
             telem: None,
             global_change_tracker: db.global_bookmark_change_tracker(),
+            conflict_policy: BookmarkConflictPolicy::default(),
+            conflict_report: None,
         }
     }
 
@@ -1138,6 +1153,8 @@ impl<'a> Merger<'a> {
             external_transaction: false,
             telem: Some(telem),
             global_change_tracker: db.global_bookmark_change_tracker(),
+            conflict_policy: BookmarkConflictPolicy::default(),
+            conflict_report: None,
         }
     }
 
@@ -1156,6 +1173,8 @@ impl<'a> Merger<'a> {
             external_transaction: false,
             telem: None,
             global_change_tracker: db.global_bookmark_change_tracker(),
+            conflict_policy: BookmarkConflictPolicy::default(),
+            conflict_report: None,
         }
     }
 
@@ -1166,10 +1185,17 @@ impl<'a> Merger<'a> {
         }
         // Merge and stage outgoing items via dogear.
         let driver = Driver::default();
+        self.conflict_policy = get_meta(self.db, CONFLICT_POLICY_META_KEY)?.unwrap_or_default();
         self.prepare()?;
         let result = self.merge_with_driver(&driver, &MergeInterruptee(self.scope));
         log::debug!("merge completed: {:?}", result);
 
+        if let (Ok(()), Some(report)) = (&result, &self.conflict_report) {
+            log::debug!("conflicts resolved: {:?}", report);
+            let report = serde_json::to_string(report)?;
+            put_meta(self.db, LAST_CONFLICT_REPORT_META_KEY, &report)?;
+        }
+
         // Record telemetry in all cases, even if the merge fails.
         if let Some(ref mut telem) = self.telem {
             telem.validation(driver.validation.into_inner());
@@ -1300,7 +1326,7 @@ impl<'a> Merger<'a> {
             .local_time
             .duration_since(row.get::<_, Timestamp>("localModified")?)
             .unwrap_or_default();
-        item.age = age.as_secs() as i64 * 1000 + i64::from(age.subsec_millis());
+        item.age = self.item_age(age, BookmarkConflictPolicy::PreferRemote);
         item.needs_merge = row.get::<_, u32>("syncChangeCounter")? > 0;
 
         let content = if item.guid == dogear::ROOT_GUID {
@@ -1337,7 +1363,7 @@ impl<'a> Merger<'a> {
             .remote_time
             .duration_since(ServerTimestamp(row.get::<_, i64>("serverModified")?))
             .unwrap_or_default();
-        item.age = age.as_secs() as i64 * 1000 + i64::from(age.subsec_millis());
+        item.age = self.item_age(age, BookmarkConflictPolicy::PreferLocal);
         item.needs_merge = row.get("needsMerge")?;
         item.validity = SyncedBookmarkValidity::from_u8(row.get("validity")?)?.into();
 
@@ -1360,6 +1386,102 @@ impl<'a> Merger<'a> {
 
         Ok((item, content))
     }
+
+    /// Converts the time since an item was modified into an age, in
+    /// milliseconds, for the merger. If the conflict policy is
+    /// `loses_under`, the item's age is offset so that the other side always
+    /// looks newer.
+    fn item_age(&self, since_modified: Duration, loses_under: BookmarkConflictPolicy) -> i64 {
+        let age =
+            since_modified.as_secs() as i64 * 1000 + i64::from(since_modified.subsec_millis());
+        if self.conflict_policy == loses_under {
+            age.saturating_add(CONFLICT_POLICY_AGE_OFFSET)
+        } else {
+            age
+        }
+    }
+}
+
+/// A summary of the conflicts between local and remote changes resolved by a
+/// bookmark merge, so that apps can tell which changes were kept. This is
+/// saved after each sync that merged changes; see `PlacesApi`'s
+/// `bookmarks_get_last_conflict_report`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ConflictReport {
+    /// Items changed on both sides, where we kept the local changes.
+    pub local_wins: u32,
+    /// Items changed on both sides, where we kept the remote changes.
+    pub remote_wins: u32,
+    /// Items deleted remotely, but kept because they changed locally.
+    pub local_revives: u32,
+    /// Items deleted locally, but kept because they changed remotely.
+    pub remote_revives: u32,
+    /// Folders deleted locally, even though they changed remotely.
+    pub local_deletes: u32,
+    /// Folders deleted remotely, even though they changed locally.
+    pub remote_deletes: u32,
+    /// New local items which matched, and were replaced by, remote items.
+    pub dupes: u32,
+}
+
+impl ConflictReport {
+    fn new(root: &MergedRoot<'_>) -> Self {
+        fn count(n: usize) -> u32 {
+            u32::try_from(n).unwrap_or(u32::MAX)
+        }
+        let counts = root.counts();
+        let mut report = ConflictReport {
+            local_revives: count(counts.local_revives),
+            remote_revives: count(counts.remote_revives),
+            local_deletes: count(counts.local_deletes),
+            remote_deletes: count(counts.remote_deletes),
+            dupes: count(counts.dupes),
+            ..ConflictReport::default()
+        };
+        report.count_value_conflicts(root.node());
+        report
+    }
+
+    fn count_value_conflicts(&mut self, node: &MergedNode<'_>) {
+        let resolved = match node.merge_state {
+            MergeState::Local {
+                local_node,
+                remote_node,
+            }
+            | MergeState::LocalWithNewLocalStructure {
+                local_node,
+                remote_node,
+            } => Some((local_node, remote_node, true)),
+            MergeState::Remote {
+                local_node,
+                remote_node,
+            }
+            | MergeState::RemoteWithNewRemoteStructure {
+                local_node,
+                remote_node,
+            } => Some((local_node, remote_node, false)),
+            _ => None,
+        };
+        if let Some((local_node, remote_node, local_won)) = resolved {
+            // Items that only changed on one side aren't conflicts. We also
+            // skip the roots, since the merger always keeps their local
+            // values, and they change whenever their children do.
+            if !local_node.is_root()
+                && !local_node.is_built_in_root()
+                && local_node.needs_merge
+                && remote_node.needs_merge
+            {
+                if local_won {
+                    self.local_wins += 1;
+                } else {
+                    self.remote_wins += 1;
+                }
+            }
+        }
+        for child in &node.merged_children {
+            self.count_value_conflicts(child);
+        }
+    }
 }
 
 impl<'a> dogear::Store for Merger<'a> {
@@ -1538,6 +1660,7 @@ impl<'a> dogear::Store for Merger<'a> {
         if ops.is_empty() {
             // If we don't have any items to apply, upload, or delete,
             // no need to open a transaction at all.
+            self.conflict_report = Some(ConflictReport::new(&root));
             return Ok(());
         }
 
@@ -1556,6 +1679,7 @@ impl<'a> dogear::Store for Merger<'a> {
             }
             return Ok(());
         }
+        self.conflict_report = Some(ConflictReport::new(&root));
 
         log::debug!("Updating local items in Places");
         update_local_items_in_places(self.db, self.scope, self.local_time, &ops)?;
@@ -2186,6 +2310,93 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_conflict_policy() {
+        let api = new_mem_api();
+        let writer = api
+            .open_connection(ConnectionType::ReadWrite)
+            .expect("Should open read-write connection");
+        assert_eq!(
+            api.bookmarks_get_conflict_policy().unwrap(),
+            BookmarkConflictPolicy::NewestWins
+        );
+        assert_eq!(api.bookmarks_get_last_conflict_report().unwrap(), None);
+
+        // Each record conflicts with a new local bookmark with the same GUID.
+        // The remote records are always newer, since `apply_incoming` merges
+        // as if the server time was 0.
+        let remote_bookmark = |guid: &str| {
+            json!([{
+                "id": "menu",
+                "type": "folder",
+                "parentid": "places",
+                "parentName": "",
+                "dateAdded": 0,
+                "title": "menu",
+                "children": [guid],
+            }, {
+                "id": guid,
+                "type": "bookmark",
+                "parentid": "menu",
+                "parentName": "menu",
+                "dateAdded": 1_381_542_355_843u64,
+                "title": "remote",
+                "bmkUri": "http://example.com/remote",
+            }])
+        };
+        let local_bookmark = |guid: &str| {
+            json!({
+                "guid": &BookmarkRootGuid::Menu.as_guid(),
+                "children": [{
+                    "guid": guid,
+                    "title": "local",
+                    "url": "http://example.com/local",
+                }],
+            })
+        };
+
+        insert_local_json_tree(&writer, local_bookmark("bookmarkAAAA"));
+        assert_incoming_creates_local_tree(
+            &api,
+            remote_bookmark("bookmarkAAAA"),
+            &BookmarkRootGuid::Menu.as_guid(),
+            json!({"children" : [
+                {"guid": "bookmarkAAAA", "url": "http://example.com/remote"},
+            ]}),
+        );
+        assert_eq!(
+            api.bookmarks_get_last_conflict_report().unwrap(),
+            Some(ConflictReport {
+                remote_wins: 1,
+                ..ConflictReport::default()
+            })
+        );
+
+        api.bookmarks_set_conflict_policy(BookmarkConflictPolicy::PreferLocal)
+            .unwrap();
+        assert_eq!(
+            api.bookmarks_get_conflict_policy().unwrap(),
+            BookmarkConflictPolicy::PreferLocal
+        );
+        insert_local_json_tree(&writer, local_bookmark("bookmarkBBBB"));
+        assert_incoming_creates_local_tree(
+            &api,
+            remote_bookmark("bookmarkBBBB"),
+            &BookmarkRootGuid::Menu.as_guid(),
+            json!({"children" : [
+                {"guid": "bookmarkAAAA", "url": "http://example.com/remote"},
+                {"guid": "bookmarkBBBB", "url": "http://example.com/local"},
+            ]}),
+        );
+        assert_eq!(
+            api.bookmarks_get_last_conflict_report().unwrap(),
+            Some(ConflictReport {
+                local_wins: 1,
+                ..ConflictReport::default()
+            })
+        );
+    }
+
     #[test]
     fn test_apply_complex_bookmark_tags() -> Result<()> {
         let api = new_mem_api();
@@ -7,14 +7,13 @@ use super::record::{
     QueryRecord, SeparatorRecord,
 };
 use super::{SyncedBookmarkKind, SyncedBookmarkValidity};
+use crate::db::PlacesDb;
 use crate::error::*;
 use crate::storage::{
     bookmarks::maybe_truncate_title,
     tags::{validate_tag, ValidatedTag},
-    URL_LENGTH_MAX,
 };
 use crate::types::serialize_unknown_fields;
-use rusqlite::Connection;
 use serde_json::Value as JsonValue;
 use sql_support::{self, ConnExt};
 use std::{collections::HashSet, iter};
@@ -30,13 +29,13 @@ const RESULTS_AS_TAG_CONTENTS: &str = "7";
 /// Manages the application of incoming records into the moz_bookmarks_synced
 /// and related tables.
 pub struct IncomingApplicator<'a> {
-    db: &'a Connection,
+    db: &'a PlacesDb,
     // For tests to override chunk sizes so they can finish quicker!
     default_max_variable_number: Option<usize>,
 }
 
 impl<'a> IncomingApplicator<'a> {
-    pub fn new(db: &'a Connection) -> Self {
+    pub fn new(db: &'a PlacesDb) -> Self {
         Self {
             db,
             default_max_variable_number: None,
@@ -127,7 +126,7 @@ impl<'a> IncomingApplicator<'a> {
                 (":serverModified", &modified.as_millis()),
                 (":kind", &SyncedBookmarkKind::Bookmark),
                 (":dateAdded", &b.date_added),
-                (":title", &maybe_truncate_title(&b.title.as_deref())),
+                (":title", &maybe_truncate_title(self.db, &b.title.as_deref())),
                 (":keyword", &b.keyword),
                 (":validity", &validity),
                 (":url", &url),
@@ -175,7 +174,10 @@ impl<'a> IncomingApplicator<'a> {
                 (":serverModified", &modified.as_millis()),
                 (":kind", &SyncedBookmarkKind::Folder),
                 (":dateAdded", &f.date_added),
-                (":title", &maybe_truncate_title(&f.title.as_deref())),
+                (
+                    ":title",
+                    &maybe_truncate_title(self.db, &f.title.as_deref()),
+                ),
                 (":validity", &validity),
                 (
                     ":unknownFields",
@@ -347,7 +349,10 @@ impl<'a> IncomingApplicator<'a> {
                 (":serverModified", &modified.as_millis()),
                 (":kind", &SyncedBookmarkKind::Query),
                 (":dateAdded", &q.date_added),
-                (":title", &maybe_truncate_title(&q.title.as_deref())),
+                (
+                    ":title",
+                    &maybe_truncate_title(self.db, &q.title.as_deref()),
+                ),
                 (":validity", &validity),
                 (
                     ":unknownFields",
@@ -366,12 +371,13 @@ impl<'a> IncomingApplicator<'a> {
         mut validity: SyncedBookmarkValidity,
     ) -> Result<()> {
         // livemarks don't store a reference to the place, so we validate it manually.
-        fn validate_href(h: &Option<String>, guid: &SyncGuid, what: &str) -> Option<String> {
+        let max_url_length = self.db.limits().max_url_length();
+        let validate_href = |h: &Option<String>, guid: &SyncGuid, what: &str| -> Option<String> {
             match h {
                 Some(h) => match Url::parse(h) {
                     Ok(url) => {
                         let s = url.to_string();
-                        if s.len() > URL_LENGTH_MAX {
+                        if s.len() > max_url_length {
                             log::warn!("Livemark {} has a {} URL which is too long", &guid, what);
                             None
                         } else {
@@ -388,7 +394,7 @@ impl<'a> IncomingApplicator<'a> {
                     None
                 }
             }
-        }
+        };
         let feed_url = validate_href(&l.feed_url, l.record_id.as_guid(), "feed");
         let site_url = validate_href(&l.site_url, l.record_id.as_guid(), "site");
 
@@ -465,7 +471,7 @@ impl<'a> IncomingApplicator<'a> {
 
     fn maybe_store_url(&self, url: Option<Url>) -> Result<Url> {
         if let Some(url) = url {
-            if url.as_str().len() > URL_LENGTH_MAX {
+            if url.as_str().len() > self.db.limits().max_url_length() {
                 return Err(Error::InvalidPlaceInfo(InvalidPlaceInfo::UrlTooLong));
             }
             self.db.execute_cached(
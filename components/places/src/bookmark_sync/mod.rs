@@ -10,8 +10,8 @@ pub mod record;
 mod tests;
 
 use crate::error::*;
-pub use engine::BookmarksSyncEngine;
-use rusqlite::types::{ToSql, ToSqlOutput};
+pub use engine::{BookmarksSyncEngine, ConflictReport};
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 use rusqlite::Result as RusqliteResult;
 
 /// Synced item kinds. These are stored in `moz_bookmarks_synced.kind` and match
@@ -117,3 +117,49 @@ impl ToSql for SyncedBookmarkValidity {
         Ok(ToSqlOutput::from(*self as u8))
     }
 }
+
+/// How to resolve a bookmark that was changed both locally and remotely
+/// since the last sync. This is stored in `moz_meta`, so it applies to every
+/// sync until it's changed.
+///
+/// The merger resolves conflicts by comparing modification times, so the
+/// non-default policies work by making one side's changes always look
+/// newer. That applies to structure conflicts, like an item moved to
+/// different folders on each side, as well as value conflicts; deletions and
+/// invalid items are still handled as usual.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[repr(u8)]
+pub enum BookmarkConflictPolicy {
+    /// Take whichever side changed most recently.
+    #[default]
+    NewestWins = 1,
+    /// Always take the local changes.
+    PreferLocal = 2,
+    /// Always take the remote changes.
+    PreferRemote = 3,
+}
+
+impl BookmarkConflictPolicy {
+    #[inline]
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            1 => Some(BookmarkConflictPolicy::NewestWins),
+            2 => Some(BookmarkConflictPolicy::PreferLocal),
+            3 => Some(BookmarkConflictPolicy::PreferRemote),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for BookmarkConflictPolicy {
+    fn to_sql(&self) -> RusqliteResult<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(*self as u8))
+    }
+}
+
+impl FromSql for BookmarkConflictPolicy {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        // Fall back to the default for values written by a newer version.
+        Ok(Self::from_u8(u8::column_result(value)?).unwrap_or_default())
+    }
+}
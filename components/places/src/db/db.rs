@@ -2,17 +2,21 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use super::query_stats::{QueryStats, QueryStatsCollector};
 use super::schema;
+use super::tx::{WritePriority, WriteQueue};
 use crate::api::places_api::ConnectionType;
 use crate::error::*;
+use crate::storage::PlacesLimits;
 use interrupt_support::{SqlInterruptHandle, SqlInterruptScope};
 use lazy_static::lazy_static;
 use parking_lot::Mutex;
 use rusqlite::{self, Connection, Transaction};
 use sql_support::{
     open_database::{self, open_database_with_flags, ConnectionInitializer},
-    ConnExt,
+    ConnExt, StatementCacheStats, StatementCacheTracker,
 };
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::path::Path;
@@ -24,6 +28,23 @@ use std::sync::{
 
 pub const MAX_VARIABLE_NUMBER: usize = 999;
 
+/// How many pages can be written to the WAL before a passive checkpoint copies them back to
+/// the DB: 2048000 (our max desired WAL size) / 32768 (page size).
+pub const WAL_AUTOCHECKPOINT_PAGES: u32 = 62;
+
+/// The size, in bytes, the WAL is truncated to once a checkpoint has copied all of it back
+/// to the DB. Passive checkpoints can't finish while there are readers, so a busy connection's
+/// WAL can grow well past `WAL_AUTOCHECKPOINT_PAGES`; without a limit, SQLite keeps the file
+/// at its largest size forever.
+pub const WAL_SIZE_LIMIT: i64 = 2048000;
+
+/// How many prepared statements each connection caches. Places has well over a hundred
+/// distinct cached statements, and a sync interleaved with awesomebar queries can run enough
+/// of them to push the awesomebar's out of a smaller cache. `PlacesDb::get_statement_cache_stats`
+/// shows whether this is enough, and the places-bench "awesomebar + sync" benchmark shows what
+/// a thrashing cache costs.
+pub const STATEMENT_CACHE_CAPACITY: usize = 256;
+
 lazy_static! {
     // Each API has a single bookmark change counter shared across all connections.
     // This hashmap indexes them by the "api id" of the API.
@@ -50,11 +71,11 @@ impl ConnectionInitializer for PlacesInitializer {
     const END_VERSION: u32 = schema::VERSION;
 
     fn init(&self, tx: &Transaction<'_>) -> open_database::Result<()> {
-        Ok(schema::init(tx)?)
+        schema::init(tx)
     }
 
     fn upgrade_from(&self, tx: &Transaction<'_>, version: u32) -> open_database::Result<()> {
-        Ok(schema::upgrade_from(tx, version)?)
+        schema::upgrade_from(tx, version)
     }
 
     fn prepare(&self, conn: &Connection, db_empty: bool) -> open_database::Result<()> {
@@ -65,7 +86,8 @@ impl ConnectionInitializer for PlacesInitializer {
             conn.execute_one("VACUUM")?;
         }
 
-        let initial_pragmas = "
+        let initial_pragmas = format!(
+            "
             -- The value we use was taken from Desktop Firefox, and seems necessary to
             -- help ensure good performance on autocomplete-style queries.
             -- Modern default value is 4096, but as reported in
@@ -99,17 +121,19 @@ impl ConnectionInitializer for PlacesInitializer {
             PRAGMA journal_mode=WAL;
 
             -- How often to autocheckpoint (in units of pages).
-            -- 2048000 (our max desired WAL size) / 32760 (page size).
-            PRAGMA wal_autocheckpoint=62;
+            PRAGMA wal_autocheckpoint={WAL_AUTOCHECKPOINT_PAGES};
+
+            -- How large the WAL may stay after it's been checkpointed (in bytes).
+            PRAGMA journal_size_limit={WAL_SIZE_LIMIT};
 
             -- How long to wait for a lock before returning SQLITE_BUSY (in ms)
             -- See `doc/sql_concurrency.md` for details.
             PRAGMA busy_timeout = 5000;
-        ";
-        conn.execute_batch(initial_pragmas)?;
+        "
+        );
+        conn.execute_batch(&initial_pragmas)?;
         define_functions(conn, self.api_id)?;
         sql_support::debug_tools::define_debug_functions(conn)?;
-        conn.set_prepared_statement_cache_capacity(128);
         Ok(())
     }
 
@@ -124,7 +148,13 @@ pub struct PlacesDb {
     conn_type: ConnectionType,
     interrupt_handle: Arc<SqlInterruptHandle>,
     api_id: usize,
-    pub(super) coop_tx_lock: Arc<Mutex<()>>,
+    pub(super) write_queue: Arc<WriteQueue>,
+    write_priority: Cell<WritePriority>,
+    // Boxed so its address doesn't change when we're moved, since sqlite holds a pointer to it
+    // while stats are enabled. Fields are dropped in order, so `db` is closed before this is.
+    query_stats: Box<QueryStatsCollector>,
+    statement_cache: StatementCacheTracker,
+    limits: PlacesLimits,
 }
 
 impl PlacesDb {
@@ -132,15 +162,22 @@ impl PlacesDb {
         db: Connection,
         conn_type: ConnectionType,
         api_id: usize,
-        coop_tx_lock: Arc<Mutex<()>>,
+        write_queue: Arc<WriteQueue>,
+        limits: PlacesLimits,
     ) -> Self {
+        let statement_cache = StatementCacheTracker::default();
+        statement_cache.set_capacity(&db, STATEMENT_CACHE_CAPACITY);
         Self {
             interrupt_handle: Arc::new(SqlInterruptHandle::new(&db)),
             db,
             conn_type,
             // The API sets this explicitly.
             api_id,
-            coop_tx_lock,
+            write_queue,
+            write_priority: Cell::new(WritePriority::Interactive),
+            query_stats: Box::default(),
+            statement_cache,
+            limits,
         }
     }
 
@@ -148,11 +185,18 @@ impl PlacesDb {
         path: impl AsRef<Path>,
         conn_type: ConnectionType,
         api_id: usize,
-        coop_tx_lock: Arc<Mutex<()>>,
+        write_queue: Arc<WriteQueue>,
+        limits: PlacesLimits,
     ) -> Result<Self> {
         let initializer = PlacesInitializer { api_id, conn_type };
         let conn = open_database_with_flags(path, conn_type.rusqlite_flags(), &initializer)?;
-        Ok(Self::with_connection(conn, conn_type, api_id, coop_tx_lock))
+        Ok(Self::with_connection(
+            conn,
+            conn_type,
+            api_id,
+            write_queue,
+            limits,
+        ))
     }
 
     #[cfg(test)]
@@ -171,7 +215,8 @@ impl PlacesDb {
             conn,
             conn_type,
             0,
-            Arc::new(Mutex::new(())),
+            Arc::new(WriteQueue::new()),
+            PlacesLimits::default(),
         ))
     }
 
@@ -201,6 +246,55 @@ impl PlacesDb {
     pub fn api_id(&self) -> usize {
         self.api_id
     }
+
+    /// The URL and title length limits the API was created with.
+    #[inline]
+    pub fn limits(&self) -> PlacesLimits {
+        self.limits
+    }
+
+    /// The priority with which transactions on this connection wait for the database. This is
+    /// only used by the ReadWrite connection; the sync connection always uses
+    /// `WritePriority::Sync`.
+    #[inline]
+    pub fn write_priority(&self) -> WritePriority {
+        self.write_priority.get()
+    }
+
+    /// Runs `f` with transactions on this connection queued at `priority`, rather than as
+    /// interactive writes.
+    pub fn with_write_priority<T>(&self, priority: WritePriority, f: impl FnOnce() -> T) -> T {
+        let previous = self.write_priority.replace(priority);
+        let result = f();
+        self.write_priority.set(previous);
+        result
+    }
+
+    /// Starts or stops timing the statements run on this connection. Stopping also discards the
+    /// stats collected so far.
+    pub fn set_query_stats_enabled(&self, enabled: bool) {
+        self.query_stats.set_enabled(&self.db, enabled);
+        if !enabled {
+            self.query_stats.reset();
+        }
+    }
+
+    /// Returns the timings collected since `set_query_stats_enabled(true)` was called, slowest
+    /// statements first.
+    pub fn get_query_stats(&self) -> Vec<QueryStats> {
+        self.query_stats.get()
+    }
+
+    /// Changes how many prepared statements this connection caches, from the default of
+    /// `STATEMENT_CACHE_CAPACITY`.
+    pub fn set_statement_cache_capacity(&self, capacity: usize) {
+        self.statement_cache.set_capacity(&self.db, capacity);
+    }
+
+    /// Returns how often the statements run on this connection were found in its cache.
+    pub fn get_statement_cache_stats(&self) -> StatementCacheStats {
+        self.statement_cache.stats()
+    }
 }
 
 impl Drop for PlacesDb {
@@ -223,6 +317,11 @@ impl ConnExt for PlacesDb {
     fn conn(&self) -> &Connection {
         &self.db
     }
+
+    #[inline]
+    fn statement_cache_tracker(&self) -> Option<&StatementCacheTracker> {
+        Some(&self.statement_cache)
+    }
 }
 
 impl Deref for PlacesDb {
@@ -339,6 +438,18 @@ fn define_functions(c: &Connection, api_id: usize) -> rusqlite::Result<()> {
         FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
         sql_fns::hash,
     )?;
+    c.create_scalar_function(
+        "fold_for_search",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        sql_fns::fold_for_search,
+    )?;
+    c.create_scalar_function(
+        "normalize_search_term",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        sql_fns::normalize_search_term,
+    )?;
     c.create_scalar_function("now", 0, FunctionFlags::SQLITE_UTF8, sql_fns::now)?;
     c.create_scalar_function(
         "generate_guid",
@@ -458,6 +569,18 @@ pub(crate) mod sql_fns {
         Ok(matcher.invoke())
     }
 
+    #[inline(never)]
+    pub fn fold_for_search(ctx: &Context<'_>) -> Result<Option<String>> {
+        Ok(get_raw_opt_str(ctx, "fold_for_search", 0)?
+            .map(|s| crate::match_impl::fold_for_search(s).into_owned()))
+    }
+
+    #[inline(never)]
+    pub fn normalize_search_term(ctx: &Context<'_>) -> Result<Option<String>> {
+        Ok(get_raw_opt_str(ctx, "normalize_search_term", 0)?
+            .map(crate::storage::history_metadata::normalize_search_term))
+    }
+
     #[inline(never)]
     pub fn reverse_host(ctx: &Context<'_>) -> Result<String> {
         // We reuse this memory so no need for get_raw.
@@ -557,4 +680,21 @@ mod tests {
             .unwrap();
         assert_eq!(rev_host, ".");
     }
+
+    #[test]
+    fn test_statement_cache_stats() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("no memory db");
+        let stats = conn.get_statement_cache_stats();
+        assert_eq!(stats.capacity as usize, STATEMENT_CACHE_CAPACITY);
+        let sql = "SELECT COUNT(*) FROM moz_places";
+        for _ in 0..3 {
+            conn.try_query_one::<i64, _>(sql, [], true).unwrap();
+        }
+        let after = conn.get_statement_cache_stats();
+        assert_eq!(after.misses - stats.misses, 1);
+        assert_eq!(after.hits - stats.hits, 2);
+
+        conn.set_statement_cache_capacity(32);
+        assert_eq!(conn.get_statement_cache_stats().capacity, 32);
+    }
 }
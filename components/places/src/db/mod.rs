@@ -5,8 +5,9 @@
 // We don't want 'db.rs' as a sub-module. We could move the contents here? Or something else?
 #[allow(clippy::module_inception)] // FIXME
 pub mod db;
+pub mod query_stats;
 mod schema;
 mod tx;
-pub use self::tx::PlacesTransaction;
+pub use self::tx::{PlacesTransaction, WritePriority, WriteQueue};
 
 pub use crate::db::db::{GlobalChangeCounterTracker, PlacesDb, SharedPlacesDb};
@@ -0,0 +1,146 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Opt-in timing of the statements run on a connection, so that slow history
+//! queries can be spotted in the wild.
+//!
+//! We use sqlite's `SQLITE_TRACE_PROFILE` hook directly rather than
+//! `rusqlite::Connection::profile`, because the latter takes a plain function
+//! pointer and so can't tell which connection a statement ran on.
+//!
+//! Statements are keyed by their SQL text, before any parameters are bound, so
+//! the stats never contain URLs, titles or anything else from the user's data.
+
+use parking_lot::Mutex;
+use rusqlite::{ffi, Connection};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_int, c_uint, c_void};
+use std::time::Duration;
+
+/// Aggregate timings for a single SQL statement.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    pub sql: String,
+    /// How many times the statement was run.
+    pub count: u64,
+    /// The total time spent running it, in microseconds.
+    pub total_time_us: u64,
+    /// The longest single run, in microseconds.
+    pub max_time_us: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct QueryStatsCollector {
+    stats: Mutex<HashMap<String, QueryStats>>,
+}
+
+impl QueryStatsCollector {
+    /// Starts or stops collecting stats for statements run on `conn`. The
+    /// caller must make sure `self` outlives `conn`, or that collection is
+    /// stopped before `self` is dropped.
+    pub(crate) fn set_enabled(&self, conn: &Connection, enabled: bool) {
+        // Safety: while enabled, the callback only dereferences `self`, which
+        // the caller has promised outlives the connection.
+        let rc = unsafe {
+            if enabled {
+                ffi::sqlite3_trace_v2(
+                    conn.handle(),
+                    ffi::SQLITE_TRACE_PROFILE as c_uint,
+                    Some(profile_callback),
+                    self as *const Self as *mut c_void,
+                )
+            } else {
+                ffi::sqlite3_trace_v2(conn.handle(), 0, None, std::ptr::null_mut())
+            }
+        };
+        if rc != ffi::SQLITE_OK {
+            log::warn!("Failed to configure query stats: {}", rc);
+        }
+    }
+
+    fn record(&self, sql: &str, elapsed: Duration) {
+        let elapsed_us = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        let mut stats = self.stats.lock();
+        let entry = stats.entry(sql.to_string()).or_insert_with(|| QueryStats {
+            sql: sql.to_string(),
+            ..QueryStats::default()
+        });
+        entry.count += 1;
+        entry.total_time_us = entry.total_time_us.saturating_add(elapsed_us);
+        entry.max_time_us = entry.max_time_us.max(elapsed_us);
+    }
+
+    /// Returns the stats collected so far, slowest statements (by total time)
+    /// first.
+    pub(crate) fn get(&self) -> Vec<QueryStats> {
+        let mut stats: Vec<_> = self.stats.lock().values().cloned().collect();
+        stats.sort_by(|a, b| b.total_time_us.cmp(&a.total_time_us));
+        stats
+    }
+
+    pub(crate) fn reset(&self) {
+        self.stats.lock().clear();
+    }
+}
+
+unsafe extern "C" fn profile_callback(
+    event: c_uint,
+    ctx: *mut c_void,
+    stmt: *mut c_void,
+    elapsed_ns: *mut c_void,
+) -> c_int {
+    if event != ffi::SQLITE_TRACE_PROFILE as c_uint || ctx.is_null() {
+        return 0;
+    }
+    // For `SQLITE_TRACE_PROFILE`, the 3rd argument is the statement and the
+    // 4th points to the time it took to run, in nanoseconds.
+    let sql = ffi::sqlite3_sql(stmt as *mut ffi::sqlite3_stmt);
+    if sql.is_null() {
+        return 0;
+    }
+    let collector = &*(ctx as *const QueryStatsCollector);
+    let elapsed = Duration::from_nanos(*(elapsed_ns as *const i64) as u64);
+    collector.record(&CStr::from_ptr(sql).to_string_lossy(), elapsed);
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_stats() {
+        let conn = Connection::open_in_memory().unwrap();
+        let collector = QueryStatsCollector::default();
+        conn.execute_batch("CREATE TABLE t(x)").unwrap();
+
+        collector.set_enabled(&conn, true);
+        for x in 0..3 {
+            conn.execute("INSERT INTO t(x) VALUES(?)", [x]).unwrap();
+        }
+        conn.query_row("SELECT COUNT(*) FROM t", [], |row| row.get::<_, i64>(0))
+            .unwrap();
+
+        let mut stats = collector.get();
+        stats.sort_by(|a, b| a.sql.cmp(&b.sql));
+        assert_eq!(
+            stats
+                .iter()
+                .map(|s| (s.sql.as_str(), s.count))
+                .collect::<Vec<_>>(),
+            vec![
+                ("INSERT INTO t(x) VALUES(?)", 3),
+                ("SELECT COUNT(*) FROM t", 1)
+            ]
+        );
+        assert!(stats.iter().all(|s| s.max_time_us <= s.total_time_us));
+
+        // Nothing's recorded once it's disabled.
+        collector.set_enabled(&conn, false);
+        collector.reset();
+        conn.execute("INSERT INTO t(x) VALUES(?)", [4]).unwrap();
+        assert!(collector.get().is_empty());
+    }
+}
@@ -9,9 +9,10 @@ use crate::storage::bookmarks::{
 };
 use crate::types::SyncStatus;
 use rusqlite::Connection;
-use sql_support::ConnExt;
+use sql_support::migration_runner::{Migration, MigrationRunner};
+use sql_support::{open_database, ConnExt};
 
-pub const VERSION: u32 = 17;
+pub const VERSION: u32 = 22;
 
 // Shared schema and temp tables for the read-write and Sync connections.
 const CREATE_SHARED_SCHEMA_SQL: &str = include_str!("../../sql/create_shared_schema.sql");
@@ -25,6 +26,8 @@ const CREATE_SYNC_TRIGGERS_SQL: &str = include_str!("../../sql/create_sync_trigg
 const CREATE_MAIN_TRIGGERS_SQL: &str = include_str!("../../sql/create_main_triggers.sql");
 
 lazy_static::lazy_static! {
+    static ref MIGRATIONS: MigrationRunner = migrations();
+
     // Triggers for the read-write and Sync connections.
     static ref CREATE_SHARED_TRIGGERS_SQL: String = {
         format!(
@@ -69,10 +72,11 @@ fn update_origin_frecency_stats(op: &str) -> String {
     )
 }
 
-pub fn init(conn: &Connection) -> rusqlite::Result<()> {
+pub fn init(conn: &Connection) -> open_database::Result<()> {
     log::debug!("Initializing schema");
     conn.execute_batch(CREATE_SHARED_SCHEMA_SQL)?;
     create_bookmark_roots(conn)?;
+    MIGRATIONS.init(conn)?;
     Ok(())
 }
 
@@ -137,11 +141,89 @@ where
     Ok(())
 }
 
-pub fn upgrade_from(db: &Connection, from: u32) -> rusqlite::Result<()> {
-    log::debug!("Upgrading schema from {} to {}", from, VERSION);
+pub fn upgrade_from(db: &Connection, from: u32) -> open_database::Result<()> {
+    MIGRATIONS.upgrade_from(db, from)
+}
 
-    // Old-style migrations
+fn migrations() -> MigrationRunner {
+    // Old-style migrations, which all go through `legacy_upgrade_from`.
+    let mut migrations: Vec<Migration> = (1..15)
+        .map(|from| {
+            Migration::new(from, format!("legacy migration from v{from}"), move |db| {
+                Ok(legacy_upgrade_from(db, from)?)
+            })
+        })
+        .collect();
+    migrations.extend([
+        Migration::new(15, "add moz_bookmarks_synced.unknownFields", |db| {
+            // This migration was rolled out incorrectly and we need to check if it was already
+            // applied (https://github.com/mozilla/application-services/issues/5464)
+            let exists_sql = "SELECT 1 FROM pragma_table_info('moz_bookmarks_synced') WHERE name = 'unknownFields'";
+            let add_column_sql = "ALTER TABLE moz_bookmarks_synced ADD COLUMN unknownFields TEXT";
+            if !db.exists(exists_sql, [])? {
+                db.execute(add_column_sql, [])?;
+            }
+            Ok(())
+        }),
+        Migration::sql(
+            16,
+            "add unknown_fields to moz_places and moz_historyvisits",
+            "ALTER TABLE moz_places ADD COLUMN unknown_fields TEXT;
+             ALTER TABLE moz_historyvisits ADD COLUMN unknown_fields TEXT;",
+        ),
+        // For visits made in containers or private contexts.
+        Migration::sql(
+            17,
+            "add moz_historyvisits.container_id",
+            "ALTER TABLE moz_historyvisits ADD COLUMN container_id TEXT",
+        ),
+        // Existing tombstones get a `deleted_at` of 0, so they're the first to be expired.
+        Migration::sql(
+            18,
+            "add deleted_at to the history tombstone tables",
+            "ALTER TABLE moz_places_tombstones ADD COLUMN deleted_at INTEGER NOT NULL DEFAULT 0;
+             ALTER TABLE moz_historyvisit_tombstones ADD COLUMN deleted_at INTEGER NOT NULL DEFAULT 0;",
+        ),
+        Migration::sql(
+            19,
+            "add moz_origin_merges",
+            "CREATE TABLE moz_origin_merges (
+                from_prefix TEXT NOT NULL,
+                from_host TEXT NOT NULL,
+                to_prefix TEXT NOT NULL,
+                to_host TEXT NOT NULL,
+                PRIMARY KEY (from_prefix, from_host)
+            ) WITHOUT ROWID;",
+        ),
+        Migration::sql(
+            20,
+            "add moz_recently_closed",
+            "CREATE TABLE moz_recently_closed (
+                id INTEGER PRIMARY KEY,
+                url TEXT NOT NULL,
+                title TEXT,
+                closed_at INTEGER NOT NULL
+            );",
+        ),
+        Migration::sql(
+            21,
+            "add moz_bookmarks_url_history",
+            "CREATE TABLE moz_bookmarks_url_history (
+                id INTEGER PRIMARY KEY,
+                bookmark_id INTEGER NOT NULL,
+                url TEXT NOT NULL,
+                changed_at INTEGER NOT NULL,
+
+                FOREIGN KEY(bookmark_id) REFERENCES moz_bookmarks(id) ON DELETE CASCADE
+            );
+            CREATE INDEX bookmarkurlhistoryindex ON moz_bookmarks_url_history(bookmark_id);",
+        ),
+        // Add more migrations here...
+    ]);
+    MigrationRunner::new("places", migrations)
+}
 
+fn legacy_upgrade_from(db: &Connection, from: u32) -> rusqlite::Result<()> {
     migration(db, from, 2, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?;
     migration(
         db,
@@ -262,42 +344,6 @@ pub fn upgrade_from(db: &Connection, from: u32) -> rusqlite::Result<()> {
         || Ok(()),
     )?;
 
-    // End of old style migrations, starting with the 15 -> 16 migration, we just use match
-    // statements
-
-    match from {
-        // Skip the old style migrations
-        n if n < 15 => (),
-        // New-style migrations start here
-        15 => {
-            // Add the `unknownFields` column
-            //
-            // This migration was rolled out incorrectly and we need to check if it was already
-            // applied (https://github.com/mozilla/application-services/issues/5464)
-            let exists_sql = "SELECT 1 FROM pragma_table_info('moz_bookmarks_synced') WHERE name = 'unknownFields'";
-            let add_column_sql = "ALTER TABLE moz_bookmarks_synced ADD COLUMN unknownFields TEXT";
-            if !db.exists(exists_sql, [])? {
-                db.execute(add_column_sql, [])?;
-            }
-        }
-        16 => {
-            // Add the `unknownFields` column for history
-            db.execute("ALTER TABLE moz_places ADD COLUMN unknown_fields TEXT", ())?;
-            db.execute(
-                "ALTER TABLE moz_historyvisits ADD COLUMN unknown_fields TEXT",
-                (),
-            )?;
-        }
-        // Add more migrations here...
-
-        // Any other from value indicates that something very wrong happened
-        _ => panic!(
-            "Places does not have a v{} -> v{} migration",
-            from,
-            from + 1
-        ),
-    }
-
     Ok(())
 }
 
@@ -306,6 +352,7 @@ mod tests {
     use super::*;
     use crate::db::{db::PlacesInitializer, PlacesDb};
     use crate::error::Result;
+    use sql_support::migration_runner::migration_history;
     use sql_support::open_database::test_utils::MigratedDatabaseFile;
     use std::collections::BTreeSet;
     use sync_guid::Guid as SyncGuid;
@@ -363,7 +410,7 @@ mod tests {
         let guid = SyncGuid::random();
 
         conn.execute_cached(
-            "INSERT INTO moz_places_tombstones VALUES (:guid)",
+            "INSERT INTO moz_places_tombstones (guid) VALUES (:guid)",
             &[(":guid", &guid)],
         )
         .expect("should work");
@@ -893,6 +940,29 @@ mod tests {
         db_file.upgrade_to(16);
     }
 
+    #[test]
+    fn test_migration_history() {
+        let db_file = MigratedDatabaseFile::new(PlacesInitializer::new_for_test(), CREATE_V15_DB);
+        let planned = MIGRATIONS.plan(15, VERSION).unwrap();
+        db_file.run_all_upgrades();
+        let history = migration_history(&db_file.open()).unwrap();
+        assert_eq!(
+            history
+                .iter()
+                .map(|r| (r.from_version, r.to_version, r.description.clone()))
+                .collect::<Vec<_>>(),
+            planned
+                .into_iter()
+                .map(|p| (p.from_version, p.to_version, p.description))
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(history.last().unwrap().to_version, VERSION);
+
+        // A new database starts at the current version, so has nothing to record.
+        let fresh_db = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        assert!(migration_history(&fresh_db).unwrap().is_empty());
+    }
+
     #[test]
     fn test_all_upgrades() {
         // Test the migration process in general: open a fresh DB and a DB that's gone through the migration
@@ -916,7 +986,10 @@ mod tests {
             "moz_inputhistory",
             "moz_bookmarks",
             "moz_bookmarks_deleted",
+            "moz_bookmarks_url_history",
             "moz_origins",
+            "moz_origin_merges",
+            "moz_recently_closed",
             "moz_meta",
             "moz_tags",
             "moz_tags_relation",
@@ -33,6 +33,9 @@
 //! In other words, the lock is held only while obtaining the DB lock, then
 //! immediately released.
 //!
+//! The shared "mutex" is actually a `WriteQueue`, which hands the lock to the
+//! highest priority waiter first - see the `write_queue` module.
+//!
 //! The end result here is that if either connection is waiting for the
 //! database lock because the other already holds it, the waiting one is
 //! guaranteed to get the database lock next.
@@ -41,10 +44,10 @@
 //! there's still a possibility of SQLITE_BUSY if the database is being
 //! checkpointed. So we handle that case and perform exactly 1 retry.
 
+use super::write_queue::{WritePriority, WriteQueue};
 use crate::api::places_api::ConnectionType;
 use crate::db::PlacesDb;
 use crate::error::*;
-use parking_lot::Mutex;
 use rusqlite::{Connection, TransactionBehavior};
 use sql_support::{ConnExt, UncheckedTransaction};
 use std::ops::Deref;
@@ -66,7 +69,7 @@ impl PlacesDb {
         // is closely related to the timeouts configured on the database
         // itself.
         let commit_after = Duration::from_millis(1000);
-        ChunkedCoopTransaction::new(self.conn(), commit_after, &self.coop_tx_lock)
+        ChunkedCoopTransaction::new(self.conn(), commit_after, &self.write_queue)
     }
 
     /// Begin a "coop" transaction. Must be called from the write connection, see
@@ -78,7 +81,7 @@ impl PlacesDb {
             ConnectionType::ReadWrite,
             "coop_transaction must only be called on the ReadWrite connection"
         );
-        let _lock = self.coop_tx_lock.lock();
+        let _lock = self.write_queue.lock(self.write_priority());
         get_tx_with_retry_on_locked(self.conn())
     }
 }
@@ -108,7 +111,7 @@ impl PlacesDb {
 pub struct ChunkedCoopTransaction<'conn> {
     tx: UncheckedTransaction<'conn>,
     commit_after: Duration,
-    coop: &'conn Mutex<()>,
+    coop: &'conn WriteQueue,
 }
 
 impl<'conn> ChunkedCoopTransaction<'conn> {
@@ -119,9 +122,9 @@ impl<'conn> ChunkedCoopTransaction<'conn> {
     pub fn new(
         conn: &'conn Connection,
         commit_after: Duration,
-        coop: &'conn Mutex<()>,
+        coop: &'conn WriteQueue,
     ) -> Result<Self> {
-        let _lock = coop.lock();
+        let _lock = coop.lock(WritePriority::Sync);
         let tx = get_tx_with_retry_on_locked(conn)?;
         Ok(Self {
             tx,
@@ -168,7 +171,7 @@ impl<'conn> ChunkedCoopTransaction<'conn> {
         // database is being checkpointed - so we still perform exactly 1 retry,
         // which we do while we have the lock, because we don't want our other
         // write connection to win this race either.
-        let _lock = self.coop.lock();
+        let _lock = self.coop.lock(WritePriority::Sync);
         self.tx = get_tx_with_retry_on_locked(self.tx.conn)?;
         Ok(())
     }
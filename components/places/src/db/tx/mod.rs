@@ -3,12 +3,14 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 mod coop_transaction;
+mod write_queue;
 
 use crate::api::places_api::ConnectionType;
 use crate::error::*;
 use coop_transaction::ChunkedCoopTransaction;
 use rusqlite::Connection;
 use sql_support::{ConnExt, UncheckedTransaction};
+pub use write_queue::{WritePriority, WriteQueue};
 
 /// High level transaction type which "does the right thing" for you.
 /// Construct one with `PlacesDb::begin_transaction()`.
@@ -0,0 +1,139 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The write queue decides which of our write connections gets to start its
+//! next transaction.
+//!
+//! It's a generalization of the shared mutex described in the
+//! `coop_transaction` module docs: each connection takes it before starting
+//! an immediate transaction and releases it once sqlite holds the database
+//! lock on its behalf. The difference is that when several writers are
+//! waiting, the one with the highest `WritePriority` goes first, so that
+//! history observations from page loads aren't stuck behind a sync or a big
+//! maintenance job.
+
+use parking_lot::{Condvar, Mutex};
+
+/// How urgent a write is. Higher priorities are granted the database first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WritePriority {
+    /// Housekeeping, such as pruning old history, which can always wait.
+    Maintenance = 0,
+    /// Writes made by the sync engines.
+    Sync = 1,
+    /// Writes the user is waiting on, such as observing a page load.
+    Interactive = 2,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    locked: bool,
+    // The number of waiters at each priority, indexed by `WritePriority as usize`.
+    waiting: [usize; 3],
+}
+
+impl QueueState {
+    fn has_waiter_above(&self, priority: WritePriority) -> bool {
+        self.waiting[priority as usize + 1..]
+            .iter()
+            .any(|count| *count > 0)
+    }
+}
+
+/// A lock which is granted in priority order. See the module docs.
+#[derive(Debug, Default)]
+pub struct WriteQueue {
+    state: Mutex<QueueState>,
+    available: Condvar,
+}
+
+impl WriteQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait our turn, then take the lock. It's released when the guard is
+    /// dropped.
+    pub fn lock(&self, priority: WritePriority) -> WriteQueueGuard<'_> {
+        let mut state = self.state.lock();
+        state.waiting[priority as usize] += 1;
+        while state.locked || state.has_waiter_above(priority) {
+            self.available.wait(&mut state);
+        }
+        state.waiting[priority as usize] -= 1;
+        state.locked = true;
+        WriteQueueGuard { queue: self }
+    }
+}
+
+pub struct WriteQueueGuard<'queue> {
+    queue: &'queue WriteQueue,
+}
+
+impl<'queue> Drop for WriteQueueGuard<'queue> {
+    fn drop(&mut self) {
+        self.queue.state.lock().locked = false;
+        self.queue.available.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn wait_for_waiters(queue: &WriteQueue, count: usize) {
+        while queue.state.lock().waiting.iter().sum::<usize>() < count {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_granted_in_priority_order() {
+        let queue = Arc::new(WriteQueue::new());
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let guard = queue.lock(WritePriority::Interactive);
+
+        let mut threads = Vec::new();
+        for (i, priority) in [
+            WritePriority::Maintenance,
+            WritePriority::Sync,
+            WritePriority::Interactive,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let thread_queue = Arc::clone(&queue);
+            let order = Arc::clone(&order);
+            threads.push(thread::spawn(move || {
+                let _guard = thread_queue.lock(priority);
+                order.lock().push(priority);
+            }));
+            // Make sure they're queued in this order, lowest priority first.
+            wait_for_waiters(&queue, i + 1);
+        }
+        drop(guard);
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(
+            *order.lock(),
+            vec![
+                WritePriority::Interactive,
+                WritePriority::Sync,
+                WritePriority::Maintenance
+            ]
+        );
+    }
+
+    #[test]
+    fn test_uncontended() {
+        let queue = WriteQueue::new();
+        drop(queue.lock(WritePriority::Maintenance));
+        drop(queue.lock(WritePriority::Sync));
+        let _guard = queue.lock(WritePriority::Interactive);
+    }
+}
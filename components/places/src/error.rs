@@ -113,6 +113,12 @@ pub enum Error {
 
     #[error("Invalid metadata observation: {0}")]
     InvalidMetadataObservation(#[from] InvalidMetadataObservation),
+
+    #[error("Frecency decay rate must be between 0 and 1, got {0}")]
+    InvalidFrecencyDecayRate(f64),
+
+    #[error("Invalid places limits: {0}")]
+    InvalidPlacesLimits(String),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -152,6 +158,10 @@ pub enum InvalidPlaceInfo {
 
     #[error("Cannot update the bookmark root {0:?}")]
     CannotUpdateRoot(BookmarkRootGuid),
+
+    // Like Urls, an origin is considered private info, so the value isn't in the error.
+    #[error("The origin is invalid")]
+    InvalidOrigin,
 }
 
 // Error types used when we can't continue due to corruption.
@@ -192,7 +202,7 @@ impl GetErrorHandling for Error {
                     InvalidPlaceInfo::InvalidParent(..) => {
                         PlacesApiError::InvalidBookmarkOperation { reason: label }
                     }
-                    InvalidPlaceInfo::UrlTooLong => {
+                    InvalidPlaceInfo::UrlTooLong | InvalidPlaceInfo::InvalidOrigin => {
                         PlacesApiError::UrlParseFailed { reason: label }
                     }
                     InvalidPlaceInfo::NoSuchGuid(..) => {
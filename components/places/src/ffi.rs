@@ -5,22 +5,38 @@
 // This module implement the traits that make the FFI code easier to manage.
 
 use crate::api::matcher::{self, search_frecent, SearchParams};
-pub use crate::api::places_api::places_api_new;
+pub use crate::api::places_api::{places_api_new, places_api_new_with_limits};
+use crate::bookmark_sync::engine::{CONFLICT_POLICY_META_KEY, LAST_CONFLICT_REPORT_META_KEY};
+pub use crate::bookmark_sync::{BookmarkConflictPolicy, ConflictReport};
+pub use crate::db::query_stats::QueryStats;
+use crate::db::WritePriority;
 pub use crate::error::Result;
 pub use crate::error::{ApiResult, PlacesApiError};
-pub use crate::import::common::HistoryMigrationResult;
-use crate::import::import_ios_history;
+use crate::history_sync::engine::SYNC_WINDOW_DAYS_META_KEY;
+pub use crate::import::common::{HistoryImportProgressListener, HistoryMigrationResult};
+use crate::import::{import_ios_history, import_safari_history};
+pub use crate::match_impl::MatchRange;
 use crate::storage;
+use crate::storage::batch::BatchOperation;
 use crate::storage::bookmarks;
+pub use crate::storage::bookmarks::BookmarkCountByType;
 pub use crate::storage::bookmarks::BookmarkPosition;
+pub use crate::storage::bookmarks::BookmarkUrlChange;
+pub use crate::storage::history::{OriginStats, OriginStatsOrder, VisitCountByStatus};
 pub use crate::storage::history_metadata::{
-    DocumentType, HistoryHighlight, HistoryHighlightWeights, HistoryMetadata,
-    HistoryMetadataObservation,
+    DocumentType, EngagedPage, HistoryHighlight, HistoryHighlightWeights, HistoryMetadata,
+    HistoryMetadataObservation, OriginViewTime,
+};
+pub use crate::storage::recently_closed::RecentlyClosedPage;
+use crate::storage::{delete_meta, get_meta, history, history_metadata, put_meta, recently_closed};
+pub use crate::storage::{
+    DbStats, ExpirationReport, FrecencyDecayMetrics, PlacesLimits, RunMaintenanceMetrics,
+    TombstoneCompactionMetrics,
 };
-pub use crate::storage::RunMaintenanceMetrics;
-use crate::storage::{history, history_metadata};
 use crate::types::VisitTransitionSet;
+pub use crate::url_fixup::{UrlFixup, UrlFixupResult};
 use crate::ConnectionType;
+use crate::HiddenVisitPolicy;
 use crate::UniffiCustomTypeConverter;
 use crate::VisitObservation;
 use crate::VisitType;
@@ -28,7 +44,9 @@ use crate::{PlacesApi, PlacesDb};
 use error_support::handle_error;
 use interrupt_support::register_interrupt;
 pub use interrupt_support::SqlInterruptHandle;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, MutexGuard};
+pub use sql_support::StatementCacheStats;
+use std::collections::HashMap;
 use std::sync::{Arc, Weak};
 use sync15::client::Sync15StorageClientInit;
 pub use sync_guid::Guid;
@@ -52,6 +70,14 @@ pub type BookmarkItem = crate::storage::bookmarks::fetch::Item;
 pub type BookmarkFolder = crate::storage::bookmarks::fetch::Folder;
 pub type BookmarkSeparator = crate::storage::bookmarks::fetch::Separator;
 pub use crate::storage::bookmarks::fetch::BookmarkData;
+pub use crate::storage::bookmarks::fetch::BookmarkSearchResult;
+
+/// Fixes up a nearly-valid URL so it can be used in a `VisitObservation`. Exposed
+/// at the top level of the namespace, like `places_api_new`.
+#[handle_error(crate::Error)]
+pub fn places_fixup_url(url: String) -> ApiResult<UrlFixupResult> {
+    crate::url_fixup::fixup_url(&url)
+}
 
 impl UniffiCustomTypeConverter for Url {
     type Builtin = String;
@@ -168,16 +194,87 @@ impl PlacesApi {
         Ok(serde_json::to_string(&ping).unwrap())
     }
 
+    /// Limits history sync to visits from the last `days` days, or syncs all
+    /// history if `days` is `None`. Older incoming visits aren't applied, and
+    /// older local visits aren't uploaded. This makes the first sync much
+    /// faster for accounts with a lot of history. Removing the limit doesn't
+    /// download older visits until the history engine is reset.
+    #[handle_error(crate::Error)]
+    pub fn set_history_sync_window_days(&self, days: Option<u32>) -> ApiResult<()> {
+        let conn = self.get_sync_connection()?;
+        let conn = conn.lock();
+        match days {
+            Some(days) => put_meta(&conn, SYNC_WINDOW_DAYS_META_KEY, &days)?,
+            None => delete_meta(&conn, SYNC_WINDOW_DAYS_META_KEY)?,
+        }
+        Ok(())
+    }
+
     #[handle_error(crate::Error)]
     pub fn bookmarks_reset(&self) -> ApiResult<()> {
         self.reset_bookmarks()?;
         Ok(())
     }
+
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_set_conflict_policy(&self, policy: BookmarkConflictPolicy) -> ApiResult<()> {
+        let conn = self.get_sync_connection()?;
+        put_meta(&conn.lock(), CONFLICT_POLICY_META_KEY, &policy)?;
+        Ok(())
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_get_conflict_policy(&self) -> ApiResult<BookmarkConflictPolicy> {
+        let conn = self.get_sync_connection()?;
+        let policy = get_meta(&conn.lock(), CONFLICT_POLICY_META_KEY)?;
+        Ok(policy.unwrap_or_default())
+    }
+
+    /// Returns a summary of the conflicts resolved by the last bookmark sync
+    /// that merged any changes, or `None` if there hasn't been one yet.
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_get_last_conflict_report(&self) -> ApiResult<Option<ConflictReport>> {
+        let conn = self.get_sync_connection()?;
+        let report = get_meta::<String>(&conn.lock(), LAST_CONFLICT_REPORT_META_KEY)?;
+        Ok(report
+            .map(|report| serde_json::from_str(&report))
+            .transpose()?)
+    }
 }
 
 pub struct PlacesConnection {
     db: Mutex<PlacesDb>,
     interrupt_handle: Arc<SqlInterruptHandle>,
+    title_updates: PendingTitleUpdates,
+}
+
+/// Tracks title-only observations waiting for the connection, so that when a page changes its
+/// title several times while the connection is busy, only the latest title gets written.
+#[derive(Default)]
+struct PendingTitleUpdates {
+    // The latest pending update for each URL, as `(next_id, url -> id)`.
+    state: Mutex<(u64, HashMap<Url, u64>)>,
+}
+
+impl PendingTitleUpdates {
+    fn push(&self, url: &Url) -> u64 {
+        let mut state = self.state.lock();
+        state.0 += 1;
+        let id = state.0;
+        state.1.insert(url.clone(), id);
+        id
+    }
+
+    fn is_latest(&self, url: &Url, id: u64) -> bool {
+        self.state.lock().1.get(url) == Some(&id)
+    }
+
+    fn finish(&self, url: &Url, id: u64) {
+        let mut state = self.state.lock();
+        if state.1.get(url) == Some(&id) {
+            state.1.remove(url);
+        }
+    }
 }
 
 impl PlacesConnection {
@@ -185,6 +282,7 @@ impl PlacesConnection {
         Self {
             interrupt_handle: db.new_interrupt_handle(),
             db: Mutex::new(db),
+            title_updates: PendingTitleUpdates::default(),
         }
     }
 
@@ -197,6 +295,21 @@ impl PlacesConnection {
         f(&conn)
     }
 
+    /// Runs a maintenance job a chunk at a time, with the chunks' transactions queued at
+    /// `WritePriority::Maintenance`. The connection is released between chunks, and any
+    /// thread waiting for it goes first, so a page load doesn't wait for the whole job.
+    fn run_maintenance_job<T>(
+        &self,
+        job: impl FnOnce(&mut dyn FnMut(storage::MaintenanceChunk<'_>) -> Result<()>) -> Result<T>,
+    ) -> Result<T> {
+        job(&mut |chunk| {
+            let conn = self.db.lock();
+            let result = conn.with_write_priority(WritePriority::Maintenance, || chunk(&conn));
+            MutexGuard::unlock_fair(conn);
+            result
+        })
+    }
+
     // pass the SqlInterruptHandle as an object through Uniffi
     pub fn new_interrupt_handle(&self) -> Arc<SqlInterruptHandle> {
         Arc::clone(&self.interrupt_handle)
@@ -238,6 +351,15 @@ impl PlacesConnection {
         self.with_conn(|conn| history_metadata::query(conn, query.as_str(), limit))
     }
 
+    #[handle_error(crate::Error)]
+    pub fn suggest_queries_from_history(
+        &self,
+        prefix: String,
+        limit: i32,
+    ) -> ApiResult<Vec<String>> {
+        self.with_conn(|conn| history_metadata::suggest_search_queries(conn, &prefix, limit))
+    }
+
     #[handle_error(crate::Error)]
     pub fn get_history_highlights(
         &self,
@@ -247,6 +369,40 @@ impl PlacesConnection {
         self.with_conn(|conn| history_metadata::get_highlights(conn, weights, limit))
     }
 
+    #[handle_error(crate::Error)]
+    pub fn get_total_view_time_by_origin(
+        &self,
+        start: PlacesTimestamp,
+        end: PlacesTimestamp,
+        limit: i32,
+    ) -> ApiResult<Vec<OriginViewTime>> {
+        self.with_conn(|conn| {
+            history_metadata::get_total_view_time_by_origin(
+                conn,
+                start.as_millis_i64(),
+                end.as_millis_i64(),
+                limit,
+            )
+        })
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn get_most_engaged_pages(
+        &self,
+        start: PlacesTimestamp,
+        end: PlacesTimestamp,
+        limit: i32,
+    ) -> ApiResult<Vec<EngagedPage>> {
+        self.with_conn(|conn| {
+            history_metadata::get_most_engaged_pages(
+                conn,
+                start.as_millis_i64(),
+                end.as_millis_i64(),
+                limit,
+            )
+        })
+    }
+
     #[handle_error(crate::Error)]
     pub fn note_history_metadata_observation(
         &self,
@@ -278,13 +434,71 @@ impl PlacesConnection {
         })
     }
 
+    #[handle_error(crate::Error)]
+    pub fn record_closed_page(
+        &self,
+        url: Url,
+        title: Option<String>,
+        closed_at: PlacesTimestamp,
+    ) -> ApiResult<()> {
+        self.with_conn(|conn| {
+            recently_closed::record_closed_page(conn, &url, title.as_deref(), closed_at)
+        })
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn get_recently_closed(&self, limit: u32) -> ApiResult<Vec<RecentlyClosedPage>> {
+        self.with_conn(|conn| recently_closed::get_recently_closed(conn, limit))
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn clear_recently_closed(&self) -> ApiResult<()> {
+        self.with_conn(recently_closed::clear_recently_closed)
+    }
+
     /// Add an observation to the database.
     #[handle_error(crate::Error)]
     pub fn apply_observation(&self, visit: VisitObservation) -> ApiResult<()> {
-        self.with_conn(|conn| history::apply_observation(conn, visit))?;
+        if !visit.is_title_only() {
+            self.with_conn(|conn| history::apply_observation(conn, visit))?;
+            return Ok(());
+        }
+        let url = visit.url.clone();
+        let id = self.title_updates.push(&url);
+        let conn = self.db.lock();
+        // If a newer title for this page arrived while we were waiting for the connection,
+        // it makes this one redundant.
+        let result = if self.title_updates.is_latest(&url, id) {
+            history::apply_observation(&conn, visit).map(|_| ())
+        } else {
+            Ok(())
+        };
+        self.title_updates.finish(&url, id);
+        result?;
         Ok(())
     }
 
+    /// Stops local reloads of a page within `secs` seconds of a local visit to it being
+    /// recorded as new visits, or records them all again if `secs` is `None`.
+    #[handle_error(crate::Error)]
+    pub fn set_reload_coalescing_window(&self, secs: Option<u32>) -> ApiResult<()> {
+        self.with_conn(|conn| history::set_reload_coalescing_window(conn, secs))
+    }
+
+    /// Sets the rules which decide whether new visits mark their page as hidden.
+    #[handle_error(crate::Error)]
+    pub fn set_hidden_visit_policy(&self, policy: HiddenVisitPolicy) -> ApiResult<()> {
+        self.with_conn(|conn| history::set_hidden_visit_policy(conn, policy))
+    }
+
+    /// Treats incoming synced visits within `ms` milliseconds of an existing visit to the
+    /// same page, with the same transition, as that visit, or only those at exactly the
+    /// same time if `ms` is `None`.
+    #[handle_error(crate::Error)]
+    pub fn set_synced_visit_dedup_window(&self, ms: Option<u32>) -> ApiResult<()> {
+        self.with_conn(|conn| history::set_synced_visit_dedup_window(conn, ms))
+    }
+
     #[handle_error(crate::Error)]
     pub fn get_visited_urls_in_range(
         &self,
@@ -312,11 +526,55 @@ impl PlacesConnection {
         self.with_conn(|conn| history::get_visit_infos(conn, start_date, end_date, exclude_types))
     }
 
+    #[handle_error(crate::Error)]
+    pub fn get_visit_infos_in_container(
+        &self,
+        start_date: PlacesTimestamp,
+        end_date: PlacesTimestamp,
+        exclude_types: VisitTransitionSet,
+        container_id: Option<String>,
+    ) -> ApiResult<Vec<HistoryVisitInfo>> {
+        self.with_conn(|conn| {
+            history::get_visit_infos_in_container(
+                conn,
+                start_date,
+                end_date,
+                exclude_types,
+                container_id.as_deref(),
+            )
+        })
+    }
+
+    pub fn open_visit_cursor(
+        self: Arc<Self>,
+        start_date: PlacesTimestamp,
+        end_date: PlacesTimestamp,
+        exclude_types: VisitTransitionSet,
+    ) -> Arc<HistoryVisitCursor> {
+        Arc::new(HistoryVisitCursor {
+            conn: self,
+            start_date,
+            end_date,
+            exclude_types,
+            position: Mutex::new(CursorPosition::Start),
+        })
+    }
+
     #[handle_error(crate::Error)]
     pub fn get_visit_count(&self, exclude_types: VisitTransitionSet) -> ApiResult<i64> {
         self.with_conn(|conn| history::get_visit_count(conn, exclude_types))
     }
 
+    #[handle_error(crate::Error)]
+    pub fn get_history_page_count(&self) -> ApiResult<i64> {
+        self.with_conn(history::get_history_page_count)
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn get_history_visit_count_by_status(&self) -> ApiResult<VisitCountByStatus> {
+        self.with_conn(history::get_history_visit_count_by_status)
+    }
+
     #[handle_error(crate::Error)]
     pub fn get_visit_page(
         &self,
@@ -327,6 +585,25 @@ impl PlacesConnection {
         self.with_conn(|conn| history::get_visit_page(conn, offset, count, exclude_types))
     }
 
+    #[handle_error(crate::Error)]
+    pub fn get_visit_page_in_container(
+        &self,
+        offset: i64,
+        count: i64,
+        exclude_types: VisitTransitionSet,
+        container_id: Option<String>,
+    ) -> ApiResult<Vec<HistoryVisitInfo>> {
+        self.with_conn(|conn| {
+            history::get_visit_page_in_container(
+                conn,
+                offset,
+                count,
+                exclude_types,
+                container_id.as_deref(),
+            )
+        })
+    }
+
     #[handle_error(crate::Error)]
     pub fn get_visit_page_with_bound(
         &self,
@@ -340,6 +617,11 @@ impl PlacesConnection {
         })
     }
 
+    #[handle_error(crate::Error)]
+    pub fn get_visited_for_origin_prefix(&self, prefix: Url) -> ApiResult<Vec<Url>> {
+        self.with_conn(|conn| history::get_visited_for_origin_prefix(conn, &prefix))
+    }
+
     // This is identical to get_visited in history.rs but takes a list of strings instead of urls
     // This is necessary b/c we still need to return 'false' for bad URLs which prevents us from
     // parsing/filtering them before reaching the history layer
@@ -381,6 +663,16 @@ impl PlacesConnection {
         self.with_conn(|conn| history::delete_visits_between(conn, start, end))
     }
 
+    #[handle_error(crate::Error)]
+    pub fn merge_origins(&self, from_origin: Url, to_origin: Url) -> ApiResult<()> {
+        self.with_conn(|conn| history::merge_origins(conn, &from_origin, &to_origin))
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn unmerge_origin(&self, from_origin: Url) -> ApiResult<bool> {
+        self.with_conn(|conn| history::unmerge_origin(conn, &from_origin))
+    }
+
     #[handle_error(crate::Error)]
     pub fn delete_visit(&self, url: String, timestamp: PlacesTimestamp) -> ApiResult<()> {
         self.with_conn(|conn| {
@@ -411,6 +703,16 @@ impl PlacesConnection {
             )
         })
     }
+
+    #[handle_error(crate::Error)]
+    pub fn get_origin_stats(
+        &self,
+        limit: i32,
+        order_by: OriginStatsOrder,
+    ) -> ApiResult<Vec<OriginStats>> {
+        self.with_conn(|conn| history::get_origin_stats(conn, limit, order_by))
+    }
+
     // deletes all history and updates the sync metadata to only sync after
     // most recent visit to prevent further syncing of older data
     #[handle_error(crate::Error)]
@@ -424,7 +726,9 @@ impl PlacesConnection {
         db_size_limit: u32,
         prune_limit: u32,
     ) -> ApiResult<RunMaintenanceMetrics> {
-        self.with_conn(|conn| storage::run_maintenance_prune(conn, db_size_limit, prune_limit))
+        self.run_maintenance_job(|run_chunk| {
+            storage::run_maintenance_prune_in_chunks(run_chunk, db_size_limit, prune_limit)
+        })
     }
 
     #[handle_error(crate::Error)]
@@ -442,6 +746,45 @@ impl PlacesConnection {
         self.with_conn(storage::run_maintenance_checkpoint)
     }
 
+    #[handle_error(crate::Error)]
+    pub fn get_db_stats(&self) -> ApiResult<DbStats> {
+        self.with_conn(storage::get_db_stats)
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn run_maintenance_compact_tombstones(
+        &self,
+        retention_days: u32,
+    ) -> ApiResult<TombstoneCompactionMetrics> {
+        self.run_maintenance_job(|run_chunk| {
+            let mut metrics = TombstoneCompactionMetrics::default();
+            run_chunk(&mut |conn| {
+                metrics = storage::run_maintenance_compact_tombstones(conn, retention_days)?;
+                Ok(())
+            })?;
+            Ok(metrics)
+        })
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn run_maintenance_frecency_decay(&self, rate: f64) -> ApiResult<FrecencyDecayMetrics> {
+        self.run_maintenance_job(|run_chunk| {
+            let mut metrics = FrecencyDecayMetrics::default();
+            run_chunk(&mut |conn| {
+                metrics = storage::run_maintenance_frecency_decay(conn, rate)?;
+                Ok(())
+            })?;
+            Ok(metrics)
+        })
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn expire_to_target_size(&self, target_bytes: u32) -> ApiResult<ExpirationReport> {
+        self.run_maintenance_job(|run_chunk| {
+            storage::expire_to_target_size_in_chunks(run_chunk, target_bytes)
+        })
+    }
+
     #[handle_error(crate::Error)]
     pub fn query_autocomplete(&self, search: String, limit: i32) -> ApiResult<Vec<SearchResult>> {
         self.with_conn(|conn| {
@@ -525,6 +868,15 @@ impl PlacesConnection {
         })
     }
 
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_search_with_paths(
+        &self,
+        query: String,
+        limit: i32,
+    ) -> ApiResult<Vec<BookmarkSearchResult>> {
+        self.with_conn(|conn| bookmarks::search_with_paths(conn, query.as_str(), limit as u32))
+    }
+
     #[handle_error(crate::Error)]
     pub fn bookmarks_get_recent(&self, limit: i32) -> ApiResult<Vec<BookmarkItem>> {
         self.with_conn(|conn| {
@@ -551,11 +903,21 @@ impl PlacesConnection {
         self.with_conn(|conn| bookmarks::bookmarks_get_url_for_keyword(conn, keyword.as_str()))
     }
 
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_get_url_history(&self, guid: Guid) -> ApiResult<Vec<BookmarkUrlChange>> {
+        self.with_conn(|conn| bookmarks::get_bookmark_url_history(conn, &guid))
+    }
+
     #[handle_error(crate::Error)]
     pub fn bookmarks_insert(&self, data: InsertableBookmarkItem) -> ApiResult<Guid> {
         self.with_conn(|conn| bookmarks::insert_bookmark(conn, data))
     }
 
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_insert_tree(&self, folder: InsertableBookmarkFolder) -> ApiResult<Guid> {
+        self.with_conn(|conn| bookmarks::insert_bookmark_tree(conn, folder))
+    }
+
     #[handle_error(crate::Error)]
     pub fn bookmarks_update(&self, item: BookmarkUpdateInfo) -> ApiResult<()> {
         self.with_conn(|conn| bookmarks::update_bookmark_from_info(conn, item))
@@ -566,6 +928,20 @@ impl PlacesConnection {
         self.with_conn(|conn| bookmarks::count_bookmarks_in_trees(conn, guids))
     }
 
+    #[handle_error(crate::Error)]
+    pub fn get_bookmark_count_by_type(&self) -> ApiResult<BookmarkCountByType> {
+        self.with_conn(bookmarks::get_bookmark_count_by_type)
+    }
+
+    /// Applies the operations queued in `batch` in a single transaction, and
+    /// empties it. Returns the GUIDs of the bookmarks which were inserted, in
+    /// the order they were queued.
+    #[handle_error(crate::Error)]
+    pub fn apply_write_batch(&self, batch: Arc<PlacesWriteBatch>) -> ApiResult<Vec<Guid>> {
+        let operations = batch.take();
+        self.with_conn(|conn| storage::batch::apply_batch(conn, operations))
+    }
+
     #[handle_error(crate::Error)]
     pub fn places_history_import_from_ios(
         &self,
@@ -574,6 +950,119 @@ impl PlacesConnection {
     ) -> ApiResult<HistoryMigrationResult> {
         self.with_conn(|conn| import_ios_history(conn, &db_path, last_sync_timestamp))
     }
+
+    #[handle_error(crate::Error)]
+    pub fn places_history_import_from_safari(
+        &self,
+        db_path: String,
+        progress: Option<Box<dyn HistoryImportProgressListener>>,
+    ) -> ApiResult<HistoryMigrationResult> {
+        self.with_conn(|conn| import_safari_history(conn, &db_path, progress.as_deref()))
+    }
+
+    pub fn set_query_stats_enabled(&self, enabled: bool) {
+        self.db.lock().set_query_stats_enabled(enabled)
+    }
+
+    pub fn get_query_stats(&self) -> Vec<QueryStats> {
+        self.db.lock().get_query_stats()
+    }
+
+    pub fn set_statement_cache_capacity(&self, capacity: u32) {
+        self.db
+            .lock()
+            .set_statement_cache_capacity(capacity as usize)
+    }
+
+    pub fn get_statement_cache_stats(&self) -> StatementCacheStats {
+        self.db.lock().get_statement_cache_stats()
+    }
+}
+
+/// Queues writes to make in a single transaction, with
+/// `PlacesConnection::apply_write_batch()`.
+#[derive(Default)]
+pub struct PlacesWriteBatch {
+    operations: Mutex<Vec<BatchOperation>>,
+}
+
+impl PlacesWriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply_observation(&self, visit: VisitObservation) {
+        self.push(BatchOperation::ApplyObservation(Box::new(visit)));
+    }
+
+    pub fn insert_bookmark(&self, bookmark: InsertableBookmarkItem) {
+        self.push(BatchOperation::InsertBookmark(bookmark));
+    }
+
+    pub fn delete_bookmark(&self, guid: Guid) {
+        self.push(BatchOperation::DeleteBookmark(guid));
+    }
+
+    pub fn delete_visits_for(&self, url: String) {
+        self.push(BatchOperation::DeleteVisitsFor(url));
+    }
+
+    fn push(&self, operation: BatchOperation) {
+        self.operations.lock().push(operation);
+    }
+
+    fn take(&self) -> Vec<BatchOperation> {
+        std::mem::take(&mut *self.operations.lock())
+    }
+}
+
+/// Pages through the visits `PlacesConnection::get_visit_infos()` would return, oldest first, so
+/// that callers don't need to hold all of them in memory. Opened with
+/// `PlacesConnection::open_visit_cursor()`.
+pub struct HistoryVisitCursor {
+    conn: Arc<PlacesConnection>,
+    start_date: PlacesTimestamp,
+    end_date: PlacesTimestamp,
+    exclude_types: VisitTransitionSet,
+    position: Mutex<CursorPosition>,
+}
+
+enum CursorPosition {
+    Start,
+    After(history::VisitPagePosition),
+    Done,
+}
+
+impl HistoryVisitCursor {
+    /// Returns up to `count` visits following the ones returned by the last call, or an empty
+    /// list once there are no more.
+    #[handle_error(crate::Error)]
+    pub fn next_page(&self, count: i64) -> ApiResult<Vec<HistoryVisitInfo>> {
+        let mut position = self.position.lock();
+        let after = match *position {
+            CursorPosition::Start => None,
+            CursorPosition::After(after) => Some(after),
+            CursorPosition::Done => return Ok(Vec::new()),
+        };
+        if count <= 0 {
+            return Ok(Vec::new());
+        }
+        let (infos, last) = self.conn.with_conn(|conn| {
+            history::get_visit_infos_after(
+                conn,
+                self.start_date,
+                self.end_date,
+                after,
+                count,
+                self.exclude_types,
+            )
+        })?;
+        *position = match last {
+            Some(last) if infos.len() as i64 == count => CursorPosition::After(last),
+            _ => CursorPosition::Done,
+        };
+        Ok(infos)
+    }
 }
 
 impl AsRef<SqlInterruptHandle> for PlacesConnection {
@@ -622,6 +1111,8 @@ pub struct SearchResult {
     pub url: Url,
     pub title: String,
     pub frecency: i64,
+    pub title_match_ranges: Vec<MatchRange>,
+    pub url_match_ranges: Vec<MatchRange>,
 }
 
 // Exists just to convince uniffi to generate `liftSequence*` helpers!
@@ -647,4 +1138,71 @@ mod tests {
         let invalid_url = "http://1234.56.78.90".to_string();
         assert!(PlacesConnection::bookmarks_get_all_with_url(&conn, invalid_url).is_ok());
     }
+
+    #[test]
+    fn test_maintenance_yields_between_chunks() {
+        let conn = Arc::new(PlacesConnection::new(new_mem_connection()));
+        let url = Url::parse("https://example.com/").unwrap();
+        let mut writer = None;
+        let mut chunks = 0;
+        conn.run_maintenance_job(|run_chunk| {
+            run_chunk(&mut |_| {
+                // A page load comes in while the first chunk holds the connection.
+                let thread_conn = Arc::clone(&conn);
+                let visit = VisitObservation::new(url.clone()).with_visit_type(VisitType::Link);
+                let (started, waiting) = std::sync::mpsc::channel();
+                writer = Some(std::thread::spawn(move || {
+                    started.send(()).unwrap();
+                    thread_conn.apply_observation(visit)
+                }));
+                waiting.recv().unwrap();
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                chunks += 1;
+                Ok(())
+            })?;
+            // It was written before the next chunk got the connection back.
+            run_chunk(&mut |conn| {
+                assert!(storage::fetch_page_info(conn, &url)?.is_some());
+                chunks += 1;
+                Ok(())
+            })
+        })
+        .unwrap();
+        writer.unwrap().join().unwrap().unwrap();
+        assert_eq!(chunks, 2);
+    }
+
+    #[test]
+    fn test_title_updates_coalesced() {
+        let conn = Arc::new(PlacesConnection::new(new_mem_connection()));
+        let url = Url::parse("https://example.com/").unwrap();
+        conn.apply_observation(VisitObservation::new(url.clone()).with_visit_type(VisitType::Link))
+            .unwrap();
+
+        // Queue two title updates behind a busy connection.
+        let guard = conn.db.lock();
+        let mut threads = Vec::new();
+        for (i, title) in ["Loading…", "Example"].into_iter().enumerate() {
+            let thread_conn = Arc::clone(&conn);
+            let visit = VisitObservation::new(url.clone()).with_title(title.to_string());
+            threads.push(std::thread::spawn(move || {
+                thread_conn.apply_observation(visit)
+            }));
+            while conn.title_updates.state.lock().0 < i as u64 + 1 {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        }
+        drop(guard);
+        for thread in threads {
+            thread.join().unwrap().unwrap();
+        }
+
+        // Whichever order they got the connection in, the stale title wasn't written.
+        let page = storage::fetch_page_info(&conn.db.lock(), &url)
+            .unwrap()
+            .unwrap()
+            .page;
+        assert_eq!(page.title, "Example");
+        assert!(conn.title_updates.state.lock().1.is_empty());
+    }
 }
@@ -4,6 +4,7 @@
 
 use crate::error::*;
 use crate::types::VisitType;
+use crate::RowId;
 use error_support::trace_error;
 use rusqlite::Connection;
 use types::Timestamp;
@@ -106,6 +107,22 @@ impl FrecencySettings {
         }
     }
 
+    fn get_unvisited_bookmark_frecency(&self, typed: bool) -> i32 {
+        // Make it so something bookmarked and typed will have a higher frecency
+        // than something just typed or just bookmarked.
+        let mut bonus = self.get_transition_bonus(Some(VisitType::Bookmark), false, false);
+        if typed {
+            bonus += self.get_transition_bonus(Some(VisitType::Typed), false, false);
+        }
+
+        // Assume "now" as our age_in_days, so use the first bucket.
+        let score = (self.first_bucket_weight as f32) * (bonus as f32 / 100.0f32);
+
+        // use ceil() so that we don't round down to 0, which
+        // would cause us to completely ignore the place during autocomplete
+        score.ceil() as i32
+    }
+
     fn get_frecency_aged_weight(&self, age_in_days: i32) -> i32 {
         if age_in_days <= self.first_bucket_cutoff_days {
             self.first_bucket_weight
@@ -274,23 +291,8 @@ impl<'db, 's> FrecencyComputation<'db, 's> {
     }
 
     fn compute_unvisited_bookmark_frecency(&self) -> i32 {
-        // Make it so something bookmarked and typed will have a higher frecency
-        // than something just typed or just bookmarked.
-        let mut bonus = self
-            .settings
-            .get_transition_bonus(Some(VisitType::Bookmark), false, false);
-        if self.typed != 0 {
-            bonus += self
-                .settings
-                .get_transition_bonus(Some(VisitType::Typed), false, false);
-        }
-
-        // Assume "now" as our age_in_days, so use the first bucket.
-        let score = (self.settings.first_bucket_weight as f32) * (bonus as f32 / 100.0f32);
-
-        // use ceil() so that we don't round down to 0, which
-        // would cause us to completely ignore the place during autocomplete
-        score.ceil() as i32
+        self.settings
+            .get_unvisited_bookmark_frecency(self.typed != 0)
     }
 }
 
@@ -328,3 +330,131 @@ pub fn calculate_frecency(
         fc.compute_unvisited_bookmark_frecency()
     })
 }
+
+/// Recalculate and store the frecencies of many pages with a single statement per chunk of
+/// pages, instead of the handful of statements per page `calculate_frecency` needs. This is
+/// what we use after removing visits, where there can be thousands of pages to update.
+///
+/// The result is the same as `calculate_frecency` with `is_redirect: None`. When adding a
+/// visit we know whether it was a redirect, so that still goes through `calculate_frecency`.
+pub fn update_frecencies(
+    db: &Connection,
+    settings: &FrecencySettings,
+    page_ids: &[RowId],
+) -> Result<()> {
+    let visit_bonus = (0..=u8::MAX)
+        .filter_map(VisitType::from_primitive)
+        .map(|visit_type| {
+            format!(
+                "WHEN {} THEN {}",
+                visit_type as u8,
+                settings.get_transition_bonus(Some(visit_type), true, false)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    sql_support::each_chunk_mapped(
+        page_ids,
+        |id| id.0,
+        |chunk, _| -> Result<()> {
+            // This mirrors `FrecencyComputation`: we sample the most recent visits to each
+            // page, score them by their type and age, and scale the sample up to the page's
+            // visit count. Pages without visits only get a frecency if they're bookmarked.
+            let sql = format!(
+                "WITH
+                 pages AS (
+                     SELECT id, typed, foreign_count,
+                            (visit_count_local + visit_count_remote) AS visit_count,
+                            (substr(url, 0, 7) = 'place:') AS is_query
+                     FROM moz_places
+                     WHERE id IN ({ids})
+                 ),
+                 visits AS (
+                     SELECT v.place_id,
+                            IFNULL(origin.visit_type, v.visit_type) AS visit_type,
+                            target.visit_type AS target_visit_type,
+                            round((now() - v.visit_date) / 86400000.0) AS age_in_days,
+                            (p.foreign_count > 0) AS has_bookmark,
+                            ROW_NUMBER() OVER (
+                                PARTITION BY v.place_id ORDER BY v.visit_date DESC
+                            ) AS sample_index
+                     FROM pages p
+                     JOIN moz_historyvisits v ON v.place_id = p.id
+                     LEFT JOIN moz_historyvisits origin ON origin.id = v.from_visit
+                         AND v.visit_type IN ({redirect_permanent}, {redirect_temporary})
+                     LEFT JOIN moz_historyvisits target ON v.id = target.from_visit
+                         AND target.visit_type IN ({redirect_permanent}, {redirect_temporary})
+                     WHERE p.visit_count > 0
+                 ),
+                 samples AS (
+                     SELECT place_id,
+                            count(*) AS num_sampled,
+                            sum(
+                                (CASE WHEN age_in_days <= {first_cutoff} THEN {first_weight}
+                                      WHEN age_in_days <= {second_cutoff} THEN {second_weight}
+                                      WHEN age_in_days <= {third_cutoff} THEN {third_weight}
+                                      WHEN age_in_days <= {fourth_cutoff} THEN {fourth_weight}
+                                      ELSE {default_weight}
+                                 END) *
+                                ((CASE WHEN target_visit_type = {redirect_permanent}
+                                            OR (target_visit_type = {redirect_temporary}
+                                                AND visit_type != {typed})
+                                       THEN {redirect_source_bonus}
+                                       ELSE CASE visit_type {visit_bonus}
+                                                ELSE {default_bonus}
+                                            END
+                                  END) +
+                                 (CASE WHEN has_bookmark THEN {bookmark_bonus} ELSE 0 END)) /
+                                100.0
+                            ) AS points
+                     FROM visits
+                     WHERE sample_index <= {num_visits}
+                     GROUP BY place_id
+                 ),
+                 frecencies AS (
+                     SELECT p.id,
+                            CASE WHEN s.num_sampled IS NULL THEN
+                                     CASE WHEN p.foreign_count <= 0 OR p.is_query THEN 0
+                                          WHEN p.typed != 0 THEN {unvisited_typed_bookmark}
+                                          ELSE {unvisited_bookmark}
+                                     END
+                                 WHEN s.points = 0 THEN -1
+                                 -- ceil(visit_count * ceil(points) / num_sampled), in
+                                 -- integer arithmetic.
+                                 ELSE (p.visit_count *
+                                           (CAST(s.points AS INTEGER) +
+                                            (s.points > CAST(s.points AS INTEGER))) +
+                                       s.num_sampled - 1) / s.num_sampled
+                            END AS frecency
+                     FROM pages p
+                     LEFT JOIN samples s ON s.place_id = p.id
+                 )
+                 UPDATE moz_places
+                 SET frecency = (SELECT frecency FROM frecencies f WHERE f.id = moz_places.id)
+                 WHERE id IN (SELECT id FROM frecencies)",
+                ids = sql_support::repeat_sql_vars(chunk.len()),
+                redirect_permanent = VisitType::RedirectPermanent as u8,
+                redirect_temporary = VisitType::RedirectTemporary as u8,
+                typed = VisitType::Typed as u8,
+                first_cutoff = settings.first_bucket_cutoff_days,
+                second_cutoff = settings.second_bucket_cutoff_days,
+                third_cutoff = settings.third_bucket_cutoff_days,
+                fourth_cutoff = settings.fourth_bucket_cutoff_days,
+                first_weight = settings.first_bucket_weight,
+                second_weight = settings.second_bucket_weight,
+                third_weight = settings.third_bucket_weight,
+                fourth_weight = settings.fourth_bucket_weight,
+                default_weight = settings.default_bucket_weight,
+                redirect_source_bonus = settings.redirect_source_visit_bonus,
+                default_bonus = settings.default_visit_bonus,
+                bookmark_bonus =
+                    settings.get_transition_bonus(Some(VisitType::Bookmark), true, false),
+                num_visits = settings.num_visits,
+                unvisited_typed_bookmark = settings.get_unvisited_bookmark_frecency(true),
+                unvisited_bookmark = settings.get_unvisited_bookmark_frecency(false),
+            );
+            db.execute(&sql, rusqlite::params_from_iter(chunk))?;
+            Ok(())
+        },
+    )
+}
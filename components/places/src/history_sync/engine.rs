@@ -8,11 +8,13 @@ use crate::storage::history::{delete_everything, history_sync::reset};
 use crate::storage::{get_meta, put_meta};
 use interrupt_support::SqlInterruptScope;
 use std::sync::Arc;
+use std::time::Duration;
 use sync15::bso::{IncomingBso, OutgoingBso};
 use sync15::engine::{
     CollSyncIds, CollectionRequest, EngineSyncAssociation, RequestOrder, SyncEngine,
 };
 use sync15::{telemetry, Guid, ServerTimestamp};
+use types::Timestamp;
 
 use super::plan::{apply_plan, finish_plan, get_planned_outgoing};
 use super::MAX_INCOMING_PLACES;
@@ -22,6 +24,18 @@ pub const LAST_SYNC_META_KEY: &str = "history_last_sync_time";
 // for the global sync ID, because engines are reset individually.
 pub const GLOBAL_SYNCID_META_KEY: &str = "history_global_sync_id";
 pub const COLLECTION_SYNCID_META_KEY: &str = "history_sync_id";
+// If set, we only sync visits from the last this many days.
+pub const SYNC_WINDOW_DAYS_META_KEY: &str = "history_sync_window_days";
+
+/// Returns the earliest visit date we should sync, if a partial sync window
+/// has been set with `PlacesApi::set_history_sync_window_days`.
+pub(crate) fn sync_window_start(db: &PlacesDb) -> Result<Option<Timestamp>> {
+    Ok(get_meta::<u32>(db, SYNC_WINDOW_DAYS_META_KEY)?.map(|days| {
+        Timestamp::now()
+            .checked_sub(Duration::from_secs(u64::from(days) * 24 * 60 * 60))
+            .unwrap_or(Timestamp(0))
+    }))
+}
 
 fn do_apply_incoming(
     db: &PlacesDb,
@@ -118,9 +132,18 @@ impl SyncEngine for HistorySyncEngine {
         server_timestamp: ServerTimestamp,
     ) -> anyhow::Result<Option<CollectionRequest>> {
         let conn = self.db.lock();
-        let since =
+        let mut since =
             ServerTimestamp(get_meta::<i64>(&conn, LAST_SYNC_META_KEY)?.unwrap_or_default());
-        Ok(if since == server_timestamp {
+        // With a partial sync window, there's no point downloading records
+        // that haven't changed since before the window started, since all
+        // their visits would be too old to apply.
+        if let Some(window_start) = sync_window_start(&conn)? {
+            let window_start = ServerTimestamp(window_start.as_millis_i64());
+            if window_start > since {
+                since = window_start;
+            }
+        }
+        Ok(if since >= server_timestamp {
             None
         } else {
             Some(
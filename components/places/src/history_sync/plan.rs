@@ -2,6 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use super::engine::sync_window_start;
 use super::record::{HistoryRecord, HistoryRecordVisit};
 use super::{MAX_OUTGOING_PLACES, MAX_VISITS};
 use crate::api::history::can_add_url;
@@ -182,6 +183,7 @@ pub fn apply_plan(
     telem: &mut telemetry::EngineIncoming,
     interruptee: &impl Interruptee,
 ) -> Result<()> {
+    let window_start = sync_window_start(db)?;
     // for a first-cut, let's do this in the most naive way possible...
     let mut plans: Vec<(SyncGuid, IncomingPlan)> = Vec::with_capacity(inbound.len());
     for incoming in inbound {
@@ -189,7 +191,20 @@ pub fn apply_plan(
         let content = incoming.into_content::<HistoryRecord>();
         let plan = match content.kind {
             IncomingKind::Tombstone => IncomingPlan::Delete,
-            IncomingKind::Content(record) => plan_incoming_record(db, record, MAX_VISITS),
+            IncomingKind::Content(mut record) => {
+                // With a partial sync window, we drop visits from before it,
+                // and skip pages which only have visits from before it.
+                if let Some(window_start) = window_start {
+                    record
+                        .visits
+                        .retain(|visit| Timestamp::from(visit.date) >= window_start);
+                }
+                if window_start.is_some() && record.visits.is_empty() {
+                    IncomingPlan::Skip
+                } else {
+                    plan_incoming_record(db, record, MAX_VISITS)
+                }
+            }
             IncomingKind::Malformed => {
                 // We could push IncomingPlan::Invalid here, but the code before the IncomingKind
                 // refactor didn't know what `id` to use, so skipped it - so we do too.
@@ -275,7 +290,8 @@ pub fn get_planned_outgoing(db: &PlacesDb) -> Result<Vec<OutgoingBso>> {
     // at this time, the fact we hold a single transaction for the entire call
     // really is used only for performance, so it's certainly a candidate.
     let tx = db.begin_transaction()?;
-    let outgoing = fetch_outgoing(db, MAX_OUTGOING_PLACES, MAX_VISITS)?;
+    let window_start = sync_window_start(db)?;
+    let outgoing = fetch_outgoing(db, MAX_OUTGOING_PLACES, MAX_VISITS, window_start)?;
     tx.commit()?;
     Ok(outgoing)
 }
@@ -294,10 +310,12 @@ mod tests {
     use crate::api::matcher::{search_frecent, SearchParams};
     use crate::api::places_api::ConnectionType;
     use crate::db::PlacesDb;
+    use crate::history_sync::engine::SYNC_WINDOW_DAYS_META_KEY;
     use crate::history_sync::ServerVisitTimestamp;
     use crate::observation::VisitObservation;
     use crate::storage::history::history_sync::fetch_visits;
     use crate::storage::history::{apply_observation, delete_visits_for, url_to_guid};
+    use crate::storage::put_meta;
     use crate::types::SyncStatus;
     use interrupt_support::NeverInterrupts;
     use serde_json::json;
@@ -769,6 +787,60 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_sync_window() -> Result<()> {
+        let _ = env_logger::try_init();
+        let db = PlacesDb::open_in_memory(ConnectionType::Sync)?;
+        put_meta(&db, SYNC_WINDOW_DAYS_META_KEY, &7)?;
+        let now: Timestamp = SystemTime::now().into();
+        let old: Timestamp = (SystemTime::now() - Duration::from_secs(30 * 24 * 60 * 60)).into();
+
+        // An old local visit isn't uploaded, but a recent one is.
+        for (url, at) in [
+            ("https://old.example.com", old),
+            ("https://new.example.com", now),
+        ] {
+            apply_observation(
+                &db,
+                VisitObservation::new(Url::parse(url)?)
+                    .with_visit_type(VisitType::Link)
+                    .with_at(Some(at)),
+            )?;
+        }
+        let incoming = vec![
+            // Only the recent visit of this one should be applied...
+            IncomingBso::from_test_content(json!({
+                "id": "aaaaaaaaaaaa",
+                "title": "title",
+                "histUri": "http://example.com/mixed",
+                "visits": [
+                    {"date": ServerVisitTimestamp::from(now), "type": 1},
+                    {"date": ServerVisitTimestamp::from(old), "type": 1},
+                ]
+            })),
+            // ...and this one shouldn't be applied at all.
+            IncomingBso::from_test_content(json!({
+                "id": "bbbbbbbbbbbb",
+                "title": "title",
+                "histUri": "http://example.com/old",
+                "visits": [ {"date": ServerVisitTimestamp::from(old), "type": 1}]
+            })),
+        ];
+        let outgoing = apply_and_get_outgoing(&db, incoming);
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(
+            outgoing[0].envelope.id,
+            get_existing_guid(&db, &Url::parse("https://new.example.com")?)
+        );
+
+        let (_, visits) =
+            fetch_visits(&db, &Url::parse("http://example.com/mixed")?, 10)?.expect("page exists");
+        assert_eq!(visits.len(), 1);
+        assert_eq!(visits[0].visit_date, now);
+        assert!(fetch_visits(&db, &Url::parse("http://example.com/old")?, 10)?.is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_simple_visit_reconciliation() -> Result<()> {
         let _ = env_logger::try_init();
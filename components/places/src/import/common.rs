@@ -4,7 +4,7 @@
 
 use crate::db::PlacesDb;
 use crate::error::*;
-use rusqlite::{named_params, Connection};
+use rusqlite::named_params;
 use serde::Serialize;
 use sql_support::ConnExt;
 use types::Timestamp;
@@ -24,7 +24,6 @@ lazy_static::lazy_static! {
 
 pub mod sql_fns {
     use crate::import::common::NOW;
-    use crate::storage::URL_LENGTH_MAX;
     use rusqlite::{functions::Context, types::ValueRef, Result};
     use types::Timestamp;
     use url::Url;
@@ -72,14 +71,14 @@ pub mod sql_fns {
     // Possibly better named as "normalize URL" - even in non-error cases, the
     // result string may not be the same href used passed as input.
     #[inline(never)]
-    pub fn validate_url(ctx: &Context<'_>) -> Result<Option<String>> {
+    pub fn validate_url(ctx: &Context<'_>, max_url_length: usize) -> Result<Option<String>> {
         let val = ctx.get_raw(0);
         let href = if let ValueRef::Text(s) = val {
             String::from_utf8_lossy(s).to_string()
         } else {
             return Ok(None);
         };
-        if href.len() > URL_LENGTH_MAX {
+        if href.len() > max_url_length {
             return Ok(None);
         }
         if let Ok(url) = Url::parse(&href) {
@@ -171,13 +170,21 @@ pub struct HistoryMigrationResult {
     pub total_duration: u64,
 }
 
-pub fn define_history_migration_functions(c: &Connection) -> Result<()> {
+/// Receives progress updates from history imports that support them.
+pub trait HistoryImportProgressListener: Send + Sync {
+    /// Called after each batch of visits is imported. `num_processed` counts the visits read
+    /// from the source database so far, including any that were skipped.
+    fn on_progress(&self, num_processed: u32, num_total: u32);
+}
+
+pub fn define_history_migration_functions(c: &PlacesDb) -> Result<()> {
     use rusqlite::functions::FunctionFlags;
+    let max_url_length = c.limits().max_url_length();
     c.create_scalar_function(
         "validate_url",
         1,
         FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
-        crate::import::common::sql_fns::validate_url,
+        move |ctx| crate::import::common::sql_fns::validate_url(ctx, max_url_length),
     )?;
     c.create_scalar_function(
         "sanitize_timestamp",
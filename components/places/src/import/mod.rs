@@ -4,4 +4,6 @@
 
 pub mod common;
 pub mod ios;
+pub mod safari;
 pub use ios::import_history as import_ios_history;
+pub use safari::import_history as import_safari_history;
@@ -0,0 +1,244 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::time::Instant;
+
+use crate::error::Result;
+use crate::import::common::{
+    attached_database, define_history_migration_functions, select_count,
+    HistoryImportProgressListener, HistoryMigrationResult,
+};
+use crate::storage::update_all_frecencies_at_once;
+use crate::types::VisitType;
+use crate::PlacesDb;
+use rusqlite::named_params;
+use types::Timestamp;
+use url::Url;
+
+/// The number of Safari visits we import between progress updates.
+const VISIT_BATCH_SIZE: u32 = 5000;
+
+/// This import is used for iOS users moving their browsing history over from
+/// Safari's `History.db`.
+///
+/// ### Basic process
+///
+/// - Attach the Safari database, read-only.
+/// - Slurp the history items into a temp table "safariHistoryStaging", to
+///   punycode the URLs and pick a title for each from its latest visit.
+/// - Add any entries to moz_places that are needed, hiding those which were
+///   only ever visited as the source of a redirect.
+/// - Migrate the visits in batches, reporting progress after each, skipping
+///   any which are already in places so that re-running an import is harmless.
+/// - Update frecency for new items.
+/// - Cleanup (detach the Safari database, etc).
+pub fn import(
+    conn: &PlacesDb,
+    path: impl AsRef<std::path::Path>,
+    progress: Option<&dyn HistoryImportProgressListener>,
+) -> Result<HistoryMigrationResult> {
+    let mut url = crate::util::ensure_url_path(path)?;
+    url.query_pairs_mut().append_pair("mode", "ro");
+    do_import(conn, url, progress)
+}
+
+fn do_import(
+    conn: &PlacesDb,
+    safari_db_file_url: Url,
+    progress: Option<&dyn HistoryImportProgressListener>,
+) -> Result<HistoryMigrationResult> {
+    let scope = conn.begin_interrupt_scope()?;
+    define_history_migration_functions(conn)?;
+    let import_start = Instant::now();
+    log::info!("Attaching database {}", safari_db_file_url);
+    let auto_detach = attached_database(conn, &safari_db_file_url, "safari")?;
+    let tx = conn.begin_transaction()?;
+    let num_total = select_count(conn, &COUNT_SAFARI_HISTORY_VISITS)?;
+    let num_visits_before = select_count(conn, &COUNT_PLACES_HISTORY_VISITS)?;
+    log::info!("The number of visits is: {:?}", num_total);
+
+    log::info!("Creating and populating staging table");
+    tx.execute_batch(&CREATE_STAGING_TABLE)?;
+    tx.execute_batch(&FILL_STAGING)?;
+    scope.err_if_interrupted()?;
+
+    log::info!("Filling in missing titles");
+    tx.execute_batch(&UPDATE_PLACES_TITLES)?;
+    scope.err_if_interrupted()?;
+
+    log::info!("Populating missing entries in moz_places");
+    tx.execute_batch(&FILL_MOZ_PLACES)?;
+    scope.err_if_interrupted()?;
+
+    log::info!("Inserting the history visits");
+    let mut last_visit_id = 0i64;
+    let mut num_processed = 0u32;
+    loop {
+        let (batch_end, batch_len) = conn.query_row(
+            &NEXT_VISIT_BATCH,
+            named_params! {
+                ":last_visit_id": last_visit_id,
+                ":batch_size": VISIT_BATCH_SIZE,
+            },
+            |row| Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, u32>(1)?)),
+        )?;
+        let Some(batch_end) = batch_end else {
+            break;
+        };
+        tx.execute(
+            &INSERT_HISTORY_VISITS,
+            named_params! {
+                ":last_visit_id": last_visit_id,
+                ":batch_end": batch_end,
+            },
+        )?;
+        scope.err_if_interrupted()?;
+        last_visit_id = batch_end;
+        num_processed += batch_len;
+        if let Some(progress) = progress {
+            progress.on_progress(num_processed, num_total);
+        }
+    }
+
+    log::info!("Insert all new entries into stale frecencies");
+    let now = Timestamp::now().as_millis();
+    tx.execute(&ADD_TO_STALE_FRECENCIES, &[(":now", &now)])?;
+    tx.execute_batch("DROP TABLE temp.safariHistoryStaging")?;
+    scope.err_if_interrupted()?;
+
+    tx.commit()?;
+    log::info!("Successfully imported history visits!");
+
+    let num_succeeded =
+        select_count(conn, &COUNT_PLACES_HISTORY_VISITS)?.saturating_sub(num_visits_before);
+    let num_failed = num_total.saturating_sub(num_succeeded);
+
+    // As for the iOS import, frecencies are updated in their own transaction
+    // so readers can see the imported history without waiting for them.
+    log::info!("Updating all frecencies");
+    update_all_frecencies_at_once(conn, &scope)?;
+    log::info!("Frecencies updated!");
+    auto_detach.execute_now()?;
+
+    Ok(HistoryMigrationResult {
+        num_total,
+        num_succeeded,
+        num_failed,
+        total_duration: import_start.elapsed().as_millis() as u64,
+    })
+}
+
+lazy_static::lazy_static! {
+   static ref COUNT_SAFARI_HISTORY_VISITS: &'static str =
+       "SELECT COUNT(*) FROM safari.history_visits"
+   ;
+
+   static ref COUNT_PLACES_HISTORY_VISITS: &'static str =
+       "SELECT COUNT(*) FROM main.moz_historyvisits"
+   ;
+
+   // We use a staging table so that we can normalize URLs (and specifically,
+   // punycode them). Safari stores titles per visit, so we use the latest one.
+   // Items which were only ever visited as the source of a redirect are hidden,
+   // like they would have been if we'd seen the visits ourselves.
+   static ref CREATE_STAGING_TABLE: &'static str = "
+        CREATE TEMP TABLE IF NOT EXISTS temp.safariHistoryStaging(
+            id INTEGER PRIMARY KEY,
+            url TEXT,
+            url_hash INTEGER NOT NULL,
+            title TEXT,
+            hidden INTEGER NOT NULL
+        ) WITHOUT ROWID;";
+
+   static ref FILL_STAGING: &'static str = "
+    INSERT OR IGNORE INTO temp.safariHistoryStaging(id, url, url_hash, title, hidden)
+        SELECT
+            i.id,
+            validate_url(i.url),
+            hash(validate_url(i.url)),
+            sanitize_utf8((SELECT v.title FROM safari.history_visits v
+                           WHERE v.history_item = i.id AND v.title IS NOT NULL
+                           ORDER BY v.visit_time DESC
+                           LIMIT 1)),
+            NOT EXISTS(SELECT 1 FROM safari.history_visits v
+                       WHERE v.history_item = i.id AND v.redirect_destination IS NULL)
+        FROM safari.history_items i
+        WHERE validate_url(i.url) IS NOT NULL
+        "
+   ;
+
+   // Only fill in titles we don't already have - ours are likely to be newer.
+   static ref UPDATE_PLACES_TITLES: &'static str =
+   "UPDATE main.moz_places
+        SET title = (SELECT t.title
+                     FROM temp.safariHistoryStaging t
+                     WHERE t.url_hash = main.moz_places.url_hash AND t.url = main.moz_places.url)
+        WHERE title IS NULL
+          AND url_hash IN (SELECT url_hash FROM temp.safariHistoryStaging)"
+    ;
+
+   static ref FILL_MOZ_PLACES: &'static str =
+   "INSERT OR IGNORE INTO main.moz_places(guid, url, url_hash, title, hidden, frecency, sync_change_counter)
+        SELECT
+            IFNULL(
+                (SELECT p.guid FROM main.moz_places p WHERE p.url_hash = t.url_hash AND p.url = t.url),
+                generate_guid()
+            ),
+            t.url,
+            t.url_hash,
+            t.title,
+            t.hidden,
+            -1,
+            1
+        FROM temp.safariHistoryStaging t
+   "
+   ;
+
+   // Finds the last visit id and the number of visits in the next batch.
+   static ref NEXT_VISIT_BATCH: &'static str =
+   "SELECT MAX(id), COUNT(*) FROM (
+        SELECT id FROM safari.history_visits
+        WHERE id > :last_visit_id
+        ORDER BY id
+        LIMIT :batch_size
+    )"
+   ;
+
+   // Safari's `visit_time` is in seconds since 2001-01-01 (978307200 seconds
+   // after the Unix epoch). It records which visits were the destination of a
+   // redirect, but not whether the redirect was permanent, so those become
+   // temporary redirects. Visits which arrived via iCloud have a non-zero
+   // `origin`, so we treat those as remote.
+   static ref INSERT_HISTORY_VISITS: String = format!(
+   "INSERT INTO main.moz_historyvisits(from_visit, place_id, visit_date, visit_type, is_local)
+        SELECT NULL, p.id, t.visit_date, t.visit_type, t.is_local
+        FROM (
+            SELECT
+                s.url,
+                s.url_hash,
+                sanitize_float_timestamp((v.visit_time + 978307200) * 1000) AS visit_date,
+                CASE WHEN v.redirect_source IS NULL THEN {link} ELSE {redirect} END AS visit_type,
+                v.origin = 0 AS is_local
+            FROM safari.history_visits v
+            JOIN temp.safariHistoryStaging s ON v.history_item = s.id
+            WHERE v.id > :last_visit_id AND v.id <= :batch_end
+        ) t
+        JOIN main.moz_places p ON p.url_hash = t.url_hash AND p.url = t.url
+        WHERE NOT EXISTS(SELECT 1 FROM main.moz_historyvisits e
+                         WHERE e.place_id = p.id AND e.visit_date = t.visit_date)
+    ",
+        link = VisitType::Link as u8,
+        redirect = VisitType::RedirectTemporary as u8,
+   );
+
+   // Adds newly modified places entries into the stale frecencies table
+   static ref ADD_TO_STALE_FRECENCIES: &'static str =
+   "INSERT OR IGNORE INTO main.moz_places_stale_frecencies(place_id, stale_at)
+    SELECT
+        p.id,
+        :now
+    FROM main.moz_places p
+    WHERE p.frecency = -1"
+    ;
+}
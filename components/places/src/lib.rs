@@ -22,6 +22,7 @@ pub mod observation;
 pub mod storage;
 #[cfg(test)]
 mod tests;
+pub mod url_fixup;
 mod util;
 
 pub use crate::api::apply_observation;
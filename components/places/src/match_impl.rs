@@ -9,7 +9,9 @@ use rusqlite::{
     self,
     types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef},
 };
+use serde_derive::*;
 use std::borrow::Cow;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
 
 const MAX_CHARS_TO_SEARCH_THROUGH: usize = 255;
 
@@ -304,6 +306,115 @@ pub fn find_in_string(token: &str, src: &str, only_boundary: bool) -> bool {
     false
 }
 
+/// Fold `s` for matching: full Unicode case folding, then stripping diacritics, so that
+/// searching for `cafe` finds `Café`, and `STRASSE` finds `Straße`. ASCII strings are
+/// returned as they are, since the search functions already ignore ASCII case.
+pub fn fold_for_search(s: &str) -> Cow<'_, str> {
+    if s.is_ascii() {
+        return Cow::Borrowed(s);
+    }
+    Cow::Owned(
+        s.chars()
+            .default_case_fold()
+            .nfkd()
+            .filter(|&c| !is_combining_mark(c))
+            .collect(),
+    )
+}
+
+/// A part of a title or URL which matched the search string, as a range of UTF-8 byte offsets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct MatchRange {
+    pub start: u32,
+    /// The offset just past the end of the match.
+    pub end: u32,
+}
+
+/// Folds a single character the way `fold_for_search` folds a string, except that ASCII is
+/// lower-cased too.
+fn fold_char(c: char) -> impl Iterator<Item = char> {
+    std::iter::once(c)
+        .default_case_fold()
+        .nfkd()
+        .filter(|&c| !is_combining_mark(c))
+}
+
+/// If `text` starts with the folded `token`, returns how many bytes of `text` matched,
+/// including any combining marks right after the match, so that highlighting covers whole
+/// characters.
+fn folded_prefix_len(token: &[char], text: &str) -> Option<usize> {
+    let mut remaining = token;
+    let mut chars = text.char_indices().peekable();
+    while !remaining.is_empty() {
+        let (_, c) = chars.next()?;
+        for folded in fold_char(c) {
+            match remaining.split_first() {
+                Some((&t, rest)) if t == folded => remaining = rest,
+                // The token ended partway through this character's folding, like "s" in "ß".
+                None => break,
+                Some(_) => return None,
+            }
+        }
+    }
+    while let Some(&(_, c)) = chars.peek() {
+        if fold_char(c).next().is_some() {
+            break;
+        }
+        chars.next();
+    }
+    Some(chars.peek().map_or(text.len(), |&(index, _)| index))
+}
+
+/// Finds everywhere each word of `search_str` appears in `text`, ignoring case and diacritics
+/// like `AutocompleteMatch` does, so that applications can highlight the parts of a result that
+/// matched. The ranges are sorted, and overlapping or adjacent ones are merged. Like the
+/// matcher, this only looks at the start of long strings.
+pub fn find_match_ranges(search_str: &str, text: &str) -> Vec<MatchRange> {
+    let text = util::slice_up_to(text, MAX_CHARS_TO_SEARCH_THROUGH);
+    let mut ranges = Vec::new();
+    for token in search_str.split_whitespace() {
+        let token: Vec<char> = token.chars().flat_map(fold_char).collect();
+        if token.is_empty() {
+            continue;
+        }
+        for (start, _) in text.char_indices() {
+            if let Some(len) = folded_prefix_len(&token, &text[start..]) {
+                ranges.push((start, start + len));
+            }
+        }
+    }
+    ranges.sort_unstable();
+    let mut merged: Vec<MatchRange> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        let (start, end) = (start as u32, end as u32);
+        match merged.last_mut() {
+            Some(last) if start <= last.end => last.end = last.end.max(end),
+            _ => merged.push(MatchRange { start, end }),
+        }
+    }
+    merged
+}
+
+/// Like `find_match_ranges`, but skips the `http://`, `https://` or `ftp://` prefix which
+/// the matcher ignores. The ranges are still offsets into the whole URL.
+pub fn find_url_match_ranges(search_str: &str, url: &str) -> Vec<MatchRange> {
+    let prefix_len = url_prefix_len(url);
+    let mut ranges = find_match_ranges(search_str, &url[prefix_len..]);
+    for range in &mut ranges {
+        range.start += prefix_len as u32;
+        range.end += prefix_len as u32;
+    }
+    ranges
+}
+
+/// The length of the scheme prefix that the matcher strips from URLs.
+fn url_prefix_len(url: &str) -> usize {
+    ["http://", "https://", "ftp://"]
+        .iter()
+        .find(|prefix| url.starts_with(*prefix))
+        .map_or(0, |prefix| prefix.len())
+}
+
 // Search functions used as function pointers by AutocompleteMatch::Invoke
 
 fn find_anywhere(token: &str, source: &str) -> bool {
@@ -352,13 +463,7 @@ impl<'search, 'url, 'title, 'tags> AutocompleteMatch<'search, 'url, 'title, 'tag
 
     fn fixup_url_str<'a>(&self, mut s: &'a str) -> Cow<'a, str> {
         if self.match_behavior != MatchBehavior::AnywhereUnmodified {
-            if s.starts_with("http://") {
-                s = &s[7..];
-            } else if s.starts_with("https://") {
-                s = &s[8..];
-            } else if s.starts_with("ftp://") {
-                s = &s[6..];
-            }
+            s = &s[url_prefix_len(s)..];
         }
         // Bail out early if we don't need to percent decode. It's a
         // little weird that it's measurably faster to check this
@@ -374,6 +479,14 @@ impl<'search, 'url, 'title, 'tags> AutocompleteMatch<'search, 'url, 'title, 'tag
         }
     }
 
+    fn fold<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        if self.match_behavior == MatchBehavior::BeginningCaseSensitive {
+            Cow::Borrowed(s)
+        } else {
+            fold_for_search(s)
+        }
+    }
+
     #[inline]
     fn has_behavior(&self, behavior: SearchBehavior) -> bool {
         self.search_behavior.intersects(behavior)
@@ -408,23 +521,31 @@ impl<'search, 'url, 'title, 'tags> AutocompleteMatch<'search, 'url, 'title, 'tag
         let fixed_url = self.fixup_url_str(self.url_str);
         let search_fn = self.get_search_fn();
 
-        let trimmed_url = util::slice_up_to(fixed_url.as_ref(), MAX_CHARS_TO_SEARCH_THROUGH);
-        let trimmed_title = util::slice_up_to(self.title_str, MAX_CHARS_TO_SEARCH_THROUGH);
-        for token in self.search_str.split_ascii_whitespace() {
+        let trimmed_url = self.fold(util::slice_up_to(
+            fixed_url.as_ref(),
+            MAX_CHARS_TO_SEARCH_THROUGH,
+        ));
+        let trimmed_title = self.fold(util::slice_up_to(
+            self.title_str,
+            MAX_CHARS_TO_SEARCH_THROUGH,
+        ));
+        let tags = self.fold(self.tags);
+        let (trimmed_url, trimmed_title, tags) = (&*trimmed_url, &*trimmed_title, &*tags);
+        for token in self.fold(self.search_str).split_ascii_whitespace() {
             let matches = match (
                 self.has_behavior(SearchBehavior::TITLE),
                 self.has_behavior(SearchBehavior::URL),
             ) {
                 (true, true) => {
-                    (search_fn(token, trimmed_title) || search_fn(token, self.tags))
+                    (search_fn(token, trimmed_title) || search_fn(token, tags))
                         && search_fn(token, trimmed_url)
                 }
-                (true, false) => search_fn(token, trimmed_title) || search_fn(token, self.tags),
+                (true, false) => search_fn(token, trimmed_title) || search_fn(token, tags),
                 (false, true) => search_fn(token, trimmed_url),
                 (false, false) => {
                     search_fn(token, trimmed_url)
                         || search_fn(token, trimmed_title)
-                        || search_fn(token, self.tags)
+                        || search_fn(token, tags)
                 }
             };
             if !matches {
@@ -518,4 +639,80 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn test_fold_for_search() {
+        assert_eq!(fold_for_search("Cafe"), "Cafe");
+        assert!(matches!(fold_for_search("Cafe"), Cow::Borrowed(_)));
+        assert_eq!(fold_for_search("Café"), "cafe");
+        // Already-decomposed accents are stripped too.
+        assert_eq!(fold_for_search("Cafe\u{301}"), "cafe");
+        assert_eq!(fold_for_search("Straße ÅNGSTRÖM"), "strasse angstrom");
+        assert_eq!(fold_for_search("日本語"), "日本語");
+    }
+
+    #[test]
+    fn test_match_ignores_diacritics() {
+        let matches = |search_str, title_str, match_behavior| {
+            AutocompleteMatch {
+                search_str,
+                url_str: "https://example.com/",
+                title_str,
+                tags: "",
+                visit_count: 1,
+                typed: false,
+                bookmarked: false,
+                open_page_count: 0,
+                match_behavior,
+                search_behavior: SearchBehavior::default(),
+            }
+            .invoke()
+        };
+        for behavior in [
+            MatchBehavior::Anywhere,
+            MatchBehavior::BoundaryAnywhere,
+            MatchBehavior::Boundary,
+            MatchBehavior::Beginning,
+        ] {
+            assert!(matches("cafe", "Café du Monde", behavior));
+            assert!(matches("CAFÉ", "cafe du monde", behavior));
+            assert!(matches("café", "Cafe\u{301} du Monde", behavior));
+            assert!(!matches("cafes", "Café du Monde", behavior));
+        }
+        assert!(matches("monde", "Café du Monde", MatchBehavior::Boundary));
+        assert!(!matches(
+            "cafe",
+            "Café du Monde",
+            MatchBehavior::BeginningCaseSensitive
+        ));
+    }
+
+    #[test]
+    fn test_find_match_ranges() {
+        let ranges = |search_str, text| {
+            find_match_ranges(search_str, text)
+                .into_iter()
+                .map(|r| (r.start, r.end))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ranges("moz", "Mozilla"), [(0, 3)]);
+        // Every occurrence of every word, with overlapping matches merged.
+        assert_eq!(ranges("a b", "a bab"), [(0, 1), (2, 5)]);
+        assert_eq!(ranges("ab bc", "abc"), [(0, 3)]);
+        assert_eq!(ranges("xyz", "Mozilla"), []);
+        assert_eq!(ranges("", "Mozilla"), []);
+        // Offsets are in bytes of the original text, and matches ignore diacritics.
+        assert_eq!(ranges("cafe", "Le Café"), [(3, 8)]);
+        assert_eq!(ranges("café", "Le Cafe\u{301}!"), [(3, 9)]);
+        assert_eq!(ranges("strasse", "Hauptstraße"), [(5, 12)]);
+
+        let url = "https://example.com/https";
+        assert_eq!(
+            find_url_match_ranges("https", url)
+                .into_iter()
+                .map(|r| (r.start, r.end))
+                .collect::<Vec<_>>(),
+            [(20, 25)]
+        );
+    }
 }
@@ -3,6 +3,8 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::types::*;
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::Result as RusqliteResult;
 use types::Timestamp;
 use url::Url;
 
@@ -31,6 +33,9 @@ pub struct VisitObservation {
     pub referrer: Option<Url>,
     pub is_remote: Option<bool>,
     pub preview_image_url: Option<Url>,
+    /// The container (or other browsing context, such as a private window)
+    /// the visit was made in. This is stored with the visit but never synced.
+    pub container_id: Option<String>,
 }
 
 impl VisitObservation {
@@ -46,9 +51,25 @@ impl VisitObservation {
             referrer: None,
             is_remote: None,
             preview_image_url: None,
+            container_id: None,
         }
     }
 
+    /// Whether this observation does nothing but set the page title, as happens when a page
+    /// changes its title while loading.
+    pub(crate) fn is_title_only(&self) -> bool {
+        self.title.is_some()
+            && self.visit_type.is_none()
+            && self.is_error.is_none()
+            && self.is_redirect_source.is_none()
+            && self.is_permanent_redirect_source.is_none()
+            && self.at.is_none()
+            && self.referrer.is_none()
+            && self.is_remote.is_none()
+            && self.preview_image_url.is_none()
+            && self.container_id.is_none()
+    }
+
     // A "builder" API to sanely build an observation. Note that this can be
     // called with Option<String> (and if None will effectively be a no-op)
     // or directly with a string.
@@ -97,6 +118,11 @@ impl VisitObservation {
         self
     }
 
+    pub fn with_container_id(mut self, v: impl Into<Option<String>>) -> Self {
+        self.container_id = v.into();
+        self
+    }
+
     // Other helpers which can be derived.
     pub fn get_redirect_frecency_boost(&self) -> bool {
         self.is_redirect_source.is_some()
@@ -105,16 +131,63 @@ impl VisitObservation {
                 _ => true,
             }
     }
+}
+
+/// Decides which visits mark their page as hidden. Hidden pages are left out of
+/// history queries, autocomplete and sync, but are still stored, so a later visit
+/// which isn't hidden makes the page visible again.
+///
+/// The policy for a database is stored in it; see `set_hidden_visit_policy`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[repr(u8)]
+pub enum HiddenVisitPolicy {
+    /// The rules we've always used. A visit is hidden if it's a `FramedLink` or
+    /// `Embed` visit, or if the observation says anything at all about it being a
+    /// redirect source, even `with_is_redirect_source(false)`.
+    #[default]
+    Legacy = 1,
+    /// Desktop's rules (`GetHiddenState()` in `History.cpp`). A visit is hidden if
+    /// it's a `FramedLink` or `Embed` visit, or if it's the source of a redirect,
+    /// except that `Typed` visits are never hidden, since the user asked for the page.
+    Desktop = 2,
+}
+
+impl HiddenVisitPolicy {
+    #[inline]
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            1 => Some(HiddenVisitPolicy::Legacy),
+            2 => Some(HiddenVisitPolicy::Desktop),
+            _ => None,
+        }
+    }
 
-    // nsHistory::GetHiddenState()
-    pub fn get_is_hidden(&self) -> bool {
-        match self.visit_type {
-            Some(visit_type) => {
-                self.is_redirect_source.is_some()
-                    || visit_type == VisitType::FramedLink
-                    || visit_type == VisitType::Embed
+    /// Whether the visit in `visit` should leave its page hidden. Observations
+    /// without a visit never change whether the page is hidden.
+    pub fn is_hidden(self, visit: &VisitObservation) -> bool {
+        let Some(visit_type) = visit.visit_type else {
+            return false;
+        };
+        let is_framed = matches!(visit_type, VisitType::FramedLink | VisitType::Embed);
+        match self {
+            HiddenVisitPolicy::Legacy => visit.is_redirect_source.is_some() || is_framed,
+            HiddenVisitPolicy::Desktop => {
+                visit_type != VisitType::Typed
+                    && (visit.is_redirect_source.unwrap_or(false) || is_framed)
             }
-            None => false,
         }
     }
 }
+
+impl ToSql for HiddenVisitPolicy {
+    fn to_sql(&self) -> RusqliteResult<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(*self as u8))
+    }
+}
+
+impl FromSql for HiddenVisitPolicy {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        // Fall back to the default for values written by a newer version.
+        Ok(Self::from_u8(u8::column_result(value)?).unwrap_or_default())
+    }
+}
@@ -0,0 +1,161 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Applies several writes in a single transaction, so that either all of them
+//! are made or none are. This is mainly for imports, which shouldn't leave
+//! half of what they imported behind if they fail, and it also saves the cost
+//! of a transaction for each write.
+
+use super::bookmarks::{self, InsertableItem};
+use super::{delete_pending_temp_tables, history};
+use crate::db::PlacesDb;
+use crate::error::Result;
+use crate::observation::VisitObservation;
+use sync_guid::Guid as SyncGuid;
+use url::Url;
+
+/// A write to make as part of a batch.
+#[derive(Debug, Clone)]
+pub enum BatchOperation {
+    ApplyObservation(Box<VisitObservation>),
+    InsertBookmark(InsertableItem),
+    DeleteBookmark(SyncGuid),
+    /// Deletes all visits to a URL. Like `delete_visits_for`, this takes a
+    /// string, so that pages with URLs we can't parse can still be deleted.
+    DeleteVisitsFor(String),
+}
+
+/// Applies `operations` in order, in a single transaction. Returns the GUIDs
+/// of the bookmarks which were inserted, in the order they were inserted. If
+/// any operation fails, the transaction is rolled back and none are applied.
+pub fn apply_batch(db: &PlacesDb, operations: Vec<BatchOperation>) -> Result<Vec<SyncGuid>> {
+    let tx = db.begin_transaction()?;
+    let result = apply_batch_in_tx(db, operations);
+    match result {
+        Ok(_) => {
+            delete_pending_temp_tables(db)?;
+            tx.commit()?
+        }
+        Err(_) => tx.rollback()?,
+    }
+    result
+}
+
+fn apply_batch_in_tx(db: &PlacesDb, operations: Vec<BatchOperation>) -> Result<Vec<SyncGuid>> {
+    let mut inserted = Vec::new();
+    let hidden_policy = history::get_hidden_visit_policy(db)?;
+    for operation in operations {
+        match operation {
+            BatchOperation::ApplyObservation(visit) => {
+                history::apply_observation_direct(db, *visit, hidden_policy)?;
+            }
+            BatchOperation::InsertBookmark(item) => {
+                inserted.push(bookmarks::insert_bookmark_in_tx(db, item)?);
+            }
+            BatchOperation::DeleteBookmark(guid) => {
+                bookmarks::delete_bookmark_in_tx(db, &guid)?;
+            }
+            BatchOperation::DeleteVisitsFor(url) => {
+                let guid = match Url::parse(&url) {
+                    Ok(url) => history::url_to_guid(db, &url)?,
+                    Err(_) => history::href_to_guid(db, &url)?,
+                };
+                if let Some(guid) = guid {
+                    history::delete_visits_for_in_tx(db, &guid)?;
+                }
+            }
+        }
+    }
+    Ok(inserted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::bookmarks::{
+        BookmarkPosition, BookmarkRootGuid, InsertableBookmark, InsertableFolder,
+    };
+    use crate::storage::history::get_visit_count;
+    use crate::types::VisitTransitionSet;
+    use crate::VisitType;
+    use types::Timestamp;
+
+    fn bookmark(parent: &SyncGuid, url: &str) -> InsertableItem {
+        InsertableBookmark {
+            parent_guid: parent.clone(),
+            position: BookmarkPosition::Append,
+            date_added: None,
+            last_modified: None,
+            guid: None,
+            url: Url::parse(url).unwrap(),
+            title: None,
+        }
+        .into()
+    }
+
+    fn visit(url: &str) -> BatchOperation {
+        BatchOperation::ApplyObservation(Box::new(
+            VisitObservation::new(Url::parse(url).unwrap())
+                .with_at(Timestamp::now())
+                .with_visit_type(VisitType::Link),
+        ))
+    }
+
+    #[test]
+    fn test_apply_batch() {
+        let conn = new_mem_connection();
+        let folder_guid = SyncGuid::random();
+        let folder: InsertableItem = InsertableFolder {
+            parent_guid: BookmarkRootGuid::Unfiled.into(),
+            position: BookmarkPosition::Append,
+            date_added: None,
+            last_modified: None,
+            guid: Some(folder_guid.clone()),
+            title: Some("Imported".into()),
+            children: vec![],
+        }
+        .into();
+
+        let inserted = apply_batch(
+            &conn,
+            vec![
+                visit("https://example.com/1"),
+                visit("https://example.com/2"),
+                BatchOperation::InsertBookmark(folder),
+                BatchOperation::InsertBookmark(bookmark(&folder_guid, "https://example.com/1")),
+                BatchOperation::DeleteVisitsFor("https://example.com/2".into()),
+            ],
+        )
+        .expect("should apply");
+        assert_eq!(inserted.len(), 2);
+        assert_eq!(inserted[0], folder_guid);
+        assert_eq!(
+            get_visit_count(&conn, VisitTransitionSet::empty()).unwrap(),
+            1
+        );
+        let bm = bookmarks::get_raw_bookmark(&conn, &inserted[1])
+            .unwrap()
+            .expect("should exist");
+        assert_eq!(bm.parent_guid, Some(folder_guid.clone()));
+
+        // If an operation fails, none of them are applied.
+        apply_batch(
+            &conn,
+            vec![
+                visit("https://example.com/3"),
+                BatchOperation::DeleteBookmark(folder_guid.clone()),
+                BatchOperation::DeleteBookmark(BookmarkRootGuid::Root.into()),
+            ],
+        )
+        .expect_err("can't delete the root");
+        assert_eq!(
+            get_visit_count(&conn, VisitTransitionSet::empty()).unwrap(),
+            1
+        );
+        assert!(bookmarks::get_raw_bookmark(&conn, &folder_guid)
+            .unwrap()
+            .is_some());
+    }
+}
@@ -21,6 +21,7 @@ use sync_guid::Guid as SyncGuid;
 use types::Timestamp;
 use url::Url;
 
+pub use fetch::search_with_paths;
 pub use root_guid::{BookmarkRootGuid, USER_CONTENT_ROOTS};
 
 mod conversions;
@@ -278,13 +279,12 @@ pub fn insert_bookmark(db: &PlacesDb, bm: InsertableItem) -> Result<SyncGuid> {
     result
 }
 
-pub fn maybe_truncate_title<'a>(t: &Option<&'a str>) -> Option<&'a str> {
-    use super::TITLE_LENGTH_MAX;
+pub fn maybe_truncate_title<'a>(db: &PlacesDb, t: &Option<&'a str>) -> Option<&'a str> {
     use crate::util::slice_up_to;
-    t.map(|title| slice_up_to(title, TITLE_LENGTH_MAX))
+    t.map(|title| slice_up_to(title, db.limits().max_title_length()))
 }
 
-fn insert_bookmark_in_tx(db: &PlacesDb, bm: InsertableItem) -> Result<SyncGuid> {
+pub(crate) fn insert_bookmark_in_tx(db: &PlacesDb, bm: InsertableItem) -> Result<SyncGuid> {
     // find the row ID of the parent.
     if bm.parent_guid() == BookmarkRootGuid::Root {
         return Err(InvalidPlaceInfo::CannotUpdateRoot(BookmarkRootGuid::Root).into());
@@ -298,25 +298,6 @@ fn insert_bookmark_in_tx(db: &PlacesDb, bm: InsertableItem) -> Result<SyncGuid>
     // Do the "position" dance.
     let position = resolve_pos_for_insert(db, *bm.position(), &parent)?;
 
-    // Note that we could probably do this 'fk' work as a sub-query (although
-    // markh isn't clear how we could perform the insert) - it probably doesn't
-    // matter in practice though...
-    let fk = match bm {
-        InsertableItem::Bookmark { ref b } => {
-            let page_info = match fetch_page_info(db, &b.url)? {
-                Some(info) => info.page,
-                None => new_page_info(db, &b.url, None)?,
-            };
-            Some(page_info.row_id)
-        }
-        _ => None,
-    };
-    let sql = "INSERT INTO moz_bookmarks
-              (fk, type, parent, position, title, dateAdded, lastModified,
-               guid, syncStatus, syncChangeCounter) VALUES
-              (:fk, :type, :parent, :position, :title, :dateAdded, :lastModified,
-               :guid, :syncStatus, :syncChangeCounter)";
-
     let guid = bm.guid().clone().unwrap_or_else(SyncGuid::random);
     if !guid.is_valid_for_places() || !guid.is_valid_for_sync_server() {
         return Err(InvalidPlaceInfo::InvalidGuid.into());
@@ -328,57 +309,45 @@ fn insert_bookmark_in_tx(db: &PlacesDb, bm: InsertableItem) -> Result<SyncGuid>
         date_added,
     );
 
-    let bookmark_type = bm.bookmark_type();
     match bm {
         InsertableItem::Bookmark { ref b } => {
-            let title = maybe_truncate_title(&b.title.as_deref());
-            db.execute_cached(
-                sql,
-                &[
-                    (":fk", &fk as &dyn rusqlite::ToSql),
-                    (":type", &bookmark_type),
-                    (":parent", &parent.row_id),
-                    (":position", &position),
-                    (":title", &title),
-                    (":dateAdded", &date_added),
-                    (":lastModified", &last_modified),
-                    (":guid", &guid),
-                    (":syncStatus", &SyncStatus::New),
-                    (":syncChangeCounter", &1),
-                ],
-            )?;
+            NewBookmarkRow {
+                fk: Some(get_or_create_page_id(db, &b.url)?),
+                bookmark_type: BookmarkType::Bookmark,
+                parent_id: parent.row_id,
+                position,
+                title: b.title.as_deref(),
+                date_added,
+                last_modified,
+                guid: &guid,
+            }
+            .insert(db)?;
         }
         InsertableItem::Separator { .. } => {
-            db.execute_cached(
-                sql,
-                &[
-                    (":type", &bookmark_type as &dyn rusqlite::ToSql),
-                    (":parent", &parent.row_id),
-                    (":position", &position),
-                    (":dateAdded", &date_added),
-                    (":lastModified", &last_modified),
-                    (":guid", &guid),
-                    (":syncStatus", &SyncStatus::New),
-                    (":syncChangeCounter", &1),
-                ],
-            )?;
+            NewBookmarkRow {
+                fk: None,
+                bookmark_type: BookmarkType::Separator,
+                parent_id: parent.row_id,
+                position,
+                title: None,
+                date_added,
+                last_modified,
+                guid: &guid,
+            }
+            .insert(db)?;
         }
         InsertableItem::Folder { f } => {
-            let title = maybe_truncate_title(&f.title.as_deref());
-            db.execute_cached(
-                sql,
-                &[
-                    (":type", &bookmark_type as &dyn rusqlite::ToSql),
-                    (":parent", &parent.row_id),
-                    (":title", &title),
-                    (":position", &position),
-                    (":dateAdded", &date_added),
-                    (":lastModified", &last_modified),
-                    (":guid", &guid),
-                    (":syncStatus", &SyncStatus::New),
-                    (":syncChangeCounter", &1),
-                ],
-            )?;
+            NewBookmarkRow {
+                fk: None,
+                bookmark_type: BookmarkType::Folder,
+                parent_id: parent.row_id,
+                position,
+                title: f.title.as_deref(),
+                date_added,
+                last_modified,
+                guid: &guid,
+            }
+            .insert(db)?;
             // now recurse for children
             for mut child in f.children.into_iter() {
                 // As a special case for trees, each child in a folder can specify
@@ -412,6 +381,144 @@ fn insert_bookmark_in_tx(db: &PlacesDb, bm: InsertableItem) -> Result<SyncGuid>
     Ok(guid)
 }
 
+/// Insert a folder and everything in it. This is the same as inserting the folder with
+/// `insert_bookmark`, but much faster for large trees, like those restored from a backup:
+/// the position of every child is worked out up front, rather than by querying and updating
+/// its siblings as it's inserted, and only the folder's parent has its change counter bumped.
+pub fn insert_bookmark_tree(db: &PlacesDb, folder: InsertableFolder) -> Result<SyncGuid> {
+    let tx = db.begin_transaction()?;
+    let result = insert_bookmark_tree_in_tx(db, folder);
+    super::delete_pending_temp_tables(db)?;
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result
+}
+
+fn insert_bookmark_tree_in_tx(db: &PlacesDb, mut folder: InsertableFolder) -> Result<SyncGuid> {
+    // The folder itself goes in the usual way, since it might need to make room for
+    // itself among existing items.
+    let children = std::mem::take(&mut folder.children);
+    let guid = insert_bookmark_in_tx(db, folder.into())?;
+    let inserted = get_raw_bookmark(db, &guid)?
+        .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(guid.to_string()))?;
+    insert_tree_children(
+        db,
+        inserted.row_id,
+        &guid,
+        inserted.date_added,
+        inserted.date_modified,
+        children,
+    )?;
+    Ok(guid)
+}
+
+// Insert the children of a folder we just inserted, and their children, in the positions
+// they'd get if they were inserted one at a time.
+fn insert_tree_children(
+    db: &PlacesDb,
+    parent_id: RowId,
+    parent_guid: &SyncGuid,
+    parent_date_added: Timestamp,
+    parent_last_modified: Timestamp,
+    children: Vec<InsertableItem>,
+) -> Result<()> {
+    let mut ordered: Vec<InsertableItem> = Vec::with_capacity(children.len());
+    for child in children {
+        let index = match *child.position() {
+            BookmarkPosition::Specific { pos } => min(pos as usize, ordered.len()),
+            BookmarkPosition::Append => ordered.len(),
+        };
+        ordered.insert(index, child);
+    }
+    for (position, child) in ordered.into_iter().enumerate() {
+        let specified_parent_guid = child.parent_guid();
+        if !specified_parent_guid.is_empty() && specified_parent_guid != parent_guid {
+            return Err(InvalidPlaceInfo::InvalidParent(specified_parent_guid.to_string()).into());
+        }
+        let guid = child.guid().clone().unwrap_or_else(SyncGuid::random);
+        if !guid.is_valid_for_places() || !guid.is_valid_for_sync_server() {
+            return Err(InvalidPlaceInfo::InvalidGuid.into());
+        }
+        // Children without timestamps get their parent's.
+        let date_added = child.date_added().unwrap_or(parent_date_added);
+        let last_modified = max(
+            child.last_modified().unwrap_or(parent_last_modified),
+            date_added,
+        );
+        let mut row = NewBookmarkRow {
+            fk: None,
+            bookmark_type: child.bookmark_type(),
+            parent_id,
+            position: position as u32,
+            title: None,
+            date_added,
+            last_modified,
+            guid: &guid,
+        };
+        match child {
+            InsertableItem::Bookmark { b } => {
+                row.fk = Some(get_or_create_page_id(db, &b.url)?);
+                row.title = b.title.as_deref();
+                row.insert(db)?;
+            }
+            InsertableItem::Separator { .. } => {
+                row.insert(db)?;
+            }
+            InsertableItem::Folder { f } => {
+                row.title = f.title.as_deref();
+                let row_id = row.insert(db)?;
+                insert_tree_children(db, row_id, &guid, date_added, last_modified, f.children)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn get_or_create_page_id(db: &PlacesDb, url: &Url) -> Result<RowId> {
+    Ok(match fetch_page_info(db, url)? {
+        Some(info) => info.page.row_id,
+        None => new_page_info(db, url, None)?.row_id,
+    })
+}
+
+/// A new row in `moz_bookmarks`. New items always start with a change counter of 1.
+struct NewBookmarkRow<'a> {
+    fk: Option<RowId>,
+    bookmark_type: BookmarkType,
+    parent_id: RowId,
+    position: u32,
+    title: Option<&'a str>,
+    date_added: Timestamp,
+    last_modified: Timestamp,
+    guid: &'a SyncGuid,
+}
+
+impl NewBookmarkRow<'_> {
+    fn insert(&self, db: &PlacesDb) -> Result<RowId> {
+        db.execute_cached(
+            "INSERT INTO moz_bookmarks
+             (fk, type, parent, position, title, dateAdded, lastModified,
+              guid, syncStatus, syncChangeCounter) VALUES
+             (:fk, :type, :parent, :position, :title, :dateAdded, :lastModified,
+              :guid, :syncStatus, 1)",
+            rusqlite::named_params! {
+                ":fk": self.fk,
+                ":type": self.bookmark_type,
+                ":parent": self.parent_id,
+                ":position": self.position,
+                ":title": maybe_truncate_title(db, &self.title),
+                ":dateAdded": self.date_added,
+                ":lastModified": self.last_modified,
+                ":guid": self.guid,
+                ":syncStatus": SyncStatus::New,
+            },
+        )?;
+        Ok(RowId(db.last_insert_rowid()))
+    }
+}
+
 /// Delete the specified bookmark. Returns true if a bookmark with the guid
 /// existed and was deleted, false otherwise.
 pub fn delete_bookmark(db: &PlacesDb, guid: &SyncGuid) -> Result<bool> {
@@ -424,7 +531,7 @@ pub fn delete_bookmark(db: &PlacesDb, guid: &SyncGuid) -> Result<bool> {
     result
 }
 
-fn delete_bookmark_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<bool> {
+pub(crate) fn delete_bookmark_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<bool> {
     // Can't delete a root.
     if let Some(root) = BookmarkRootGuid::well_known(guid.as_str()) {
         return Err(InvalidPlaceInfo::CannotUpdateRoot(root).into());
@@ -713,7 +820,7 @@ fn update_bookmark_in_tx(
             (":fk", &place_id as &dyn rusqlite::ToSql),
             (":parent", &parent_id),
             (":position", &position),
-            (":title", &maybe_truncate_title(&title.as_deref())),
+            (":title", &maybe_truncate_title(db, &title.as_deref())),
             (":now", &now),
             (":change_incr", &(change_incr as u32)),
             (":id", &raw.row_id),
@@ -812,6 +919,78 @@ pub fn count_bookmarks_in_trees(db: &PlacesDb, item_guids: &[SyncGuid]) -> Resul
     Ok(db.try_query_one(&sql, params, true)?.unwrap_or_default())
 }
 
+/// The number of each type of item in the bookmark tree, from `get_bookmark_count_by_type()`.
+/// The roots aren't counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BookmarkCountByType {
+    pub bookmarks: i64,
+    pub folders: i64,
+    pub separators: i64,
+}
+
+pub fn get_bookmark_count_by_type(db: &PlacesDb) -> Result<BookmarkCountByType> {
+    let sql = format!(
+        "SELECT
+            IFNULL(SUM(type = {bookmark}), 0),
+            IFNULL(SUM(type = {folder}), 0),
+            IFNULL(SUM(type = {separator}), 0)
+         FROM moz_bookmarks
+         WHERE guid NOT IN ('{}', '{}', '{}', '{}', '{}')",
+        BookmarkRootGuid::Root.as_str(),
+        BookmarkRootGuid::Menu.as_str(),
+        BookmarkRootGuid::Mobile.as_str(),
+        BookmarkRootGuid::Toolbar.as_str(),
+        BookmarkRootGuid::Unfiled.as_str(),
+        bookmark = BookmarkType::Bookmark as u8,
+        folder = BookmarkType::Folder as u8,
+        separator = BookmarkType::Separator as u8,
+    );
+    Ok(db.query_row_and_then_cachable(
+        &sql,
+        [],
+        |row| -> rusqlite::Result<_> {
+            Ok(BookmarkCountByType {
+                bookmarks: row.get(0)?,
+                folders: row.get(1)?,
+                separators: row.get(2)?,
+            })
+        },
+        true,
+    )?)
+}
+
+/// The number of previous URLs kept for each bookmark.
+pub const MAX_BOOKMARK_URL_HISTORY: u32 = 10;
+
+/// A URL a bookmark had before it was changed, from `get_bookmark_url_history()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BookmarkUrlChange {
+    /// The URL before the change.
+    pub url: Url,
+    /// When the bookmark was changed to its next URL.
+    pub changed_at: Timestamp,
+}
+
+/// Get the URLs the bookmark with the given guid had before its URL was changed, most recent
+/// first, so that an accidental edit can be undone. Only the last `MAX_BOOKMARK_URL_HISTORY`
+/// are kept, and they're removed along with the bookmark.
+pub fn get_bookmark_url_history(db: &PlacesDb, guid: &SyncGuid) -> Result<Vec<BookmarkUrlChange>> {
+    db.query_rows_and_then_cached(
+        "SELECT h.url, h.changed_at
+         FROM moz_bookmarks_url_history h
+         JOIN moz_bookmarks b ON b.id = h.bookmark_id
+         WHERE b.guid = :guid
+         ORDER BY h.id DESC",
+        &[(":guid", guid)],
+        |row| -> Result<_> {
+            Ok(BookmarkUrlChange {
+                url: Url::parse(&row.get::<_, String>(0)?)?,
+                changed_at: row.get(1)?,
+            })
+        },
+    )
+}
+
 /// Erases all bookmarks and resets all Sync metadata.
 pub fn delete_everything(db: &PlacesDb) -> Result<()> {
     let tx = db.begin_transaction()?;
@@ -1159,6 +1338,171 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_insert_tree() -> Result<()> {
+        let conn = new_mem_connection();
+        conn.execute("UPDATE moz_bookmarks SET syncChangeCounter = 0", [])?;
+        let bookmark = |guid: &str, url: &str, position| InsertableItem::Bookmark {
+            b: InsertableBookmark {
+                parent_guid: SyncGuid::empty(),
+                position,
+                date_added: None,
+                last_modified: None,
+                guid: Some(guid.into()),
+                url: Url::parse(url).unwrap(),
+                title: Some(guid.into()),
+            },
+        };
+        let folder = |guid: &str, position, children| InsertableFolder {
+            parent_guid: SyncGuid::empty(),
+            position,
+            date_added: None,
+            last_modified: None,
+            guid: Some(guid.into()),
+            title: Some(guid.into()),
+            children,
+        };
+        let tree = InsertableFolder {
+            parent_guid: BookmarkRootGuid::Toolbar.into(),
+            ..folder(
+                "folder1_____",
+                BookmarkPosition::Append,
+                vec![
+                    bookmark(
+                        "bookmark1___",
+                        "https://www.example1.com/",
+                        BookmarkPosition::Append,
+                    ),
+                    bookmark(
+                        "bookmark2___",
+                        "https://www.example2.com/",
+                        BookmarkPosition::Specific { pos: 0 },
+                    ),
+                    InsertableItem::Separator {
+                        s: InsertableSeparator {
+                            parent_guid: "folder1_____".into(),
+                            position: BookmarkPosition::Append,
+                            date_added: None,
+                            last_modified: None,
+                            guid: Some("separator1__".into()),
+                        },
+                    },
+                    folder(
+                        "folder2_____",
+                        BookmarkPosition::Specific { pos: 100 },
+                        vec![bookmark(
+                            "bookmark3___",
+                            "https://www.example1.com/",
+                            BookmarkPosition::Append,
+                        )],
+                    )
+                    .into(),
+                ],
+            )
+        };
+        let guid = insert_bookmark_tree(&conn, tree)?;
+        assert_eq!(guid, "folder1_____");
+
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Toolbar.into(),
+            json!({
+                "guid": &BookmarkRootGuid::Toolbar.as_guid(),
+                "children": [
+                    {
+                        "guid": "folder1_____",
+                        "title": "folder1_____",
+                        "children": [
+                            {
+                                "guid": "bookmark2___",
+                                "title": "bookmark2___",
+                                "url": "https://www.example2.com/"
+                            },
+                            {
+                                "guid": "bookmark1___",
+                                "title": "bookmark1___",
+                                "url": "https://www.example1.com/"
+                            },
+                            {
+                                "guid": "separator1__",
+                                "type": 3
+                            },
+                            {
+                                "guid": "folder2_____",
+                                "title": "folder2_____",
+                                "children": [
+                                    {
+                                        "guid": "bookmark3___",
+                                        "title": "bookmark3___",
+                                        "url": "https://www.example1.com/"
+                                    }
+                                ]
+                            }
+                        ]
+                    }
+                ]
+            }),
+        );
+        // The toolbar's change counter is bumped once, and every new item starts at 1.
+        let counters = conn.query_rows_and_then(
+            "SELECT guid, syncChangeCounter, syncStatus FROM moz_bookmarks
+             WHERE syncChangeCounter > 0 ORDER BY guid",
+            [],
+            |row| -> rusqlite::Result<(String, u32, u8)> {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            },
+        )?;
+        let new = SyncStatus::New as u8;
+        assert_eq!(
+            counters,
+            vec![
+                ("bookmark1___".to_string(), 1, new),
+                ("bookmark2___".to_string(), 1, new),
+                ("bookmark3___".to_string(), 1, new),
+                ("folder1_____".to_string(), 1, new),
+                ("folder2_____".to_string(), 1, new),
+                ("separator1__".to_string(), 1, new),
+                (BookmarkRootGuid::Toolbar.as_str().to_string(), 1, new),
+            ]
+        );
+        // Both bookmarks for the same URL share a page.
+        assert_eq!(
+            conn.query_one::<u32>(
+                "SELECT foreign_count FROM moz_places WHERE url = 'https://www.example1.com/'"
+            )?,
+            2
+        );
+
+        // A child which names the wrong parent fails the whole insert.
+        let bad_tree = InsertableFolder {
+            parent_guid: BookmarkRootGuid::Unfiled.into(),
+            ..folder(
+                "folder3_____",
+                BookmarkPosition::Append,
+                vec![
+                    bookmark(
+                        "bookmark4___",
+                        "https://www.example4.com/",
+                        BookmarkPosition::Append,
+                    ),
+                    InsertableItem::Separator {
+                        s: InsertableSeparator {
+                            parent_guid: "folder1_____".into(),
+                            position: BookmarkPosition::Append,
+                            date_added: None,
+                            last_modified: None,
+                            guid: None,
+                        },
+                    },
+                ],
+            )
+        };
+        assert!(insert_bookmark_tree(&conn, bad_tree).is_err());
+        assert!(get_raw_bookmark(&conn, &"folder3_____".into())?.is_none());
+        assert!(get_raw_bookmark(&conn, &"bookmark4___".into())?.is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_insert_titles() -> Result<()> {
         let conn = new_mem_connection();
@@ -1752,6 +2096,78 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_url_history() -> Result<()> {
+        let conn = new_mem_connection();
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": &BookmarkRootGuid::Unfiled.as_guid(),
+                "children": [
+                    {
+                        "guid": "bookmark1___",
+                        "title": "the bookmark",
+                        "url": "https://www.example.com/0"
+                    },
+                ]
+            }),
+        );
+        let guid = SyncGuid::from("bookmark1___");
+        assert!(get_bookmark_url_history(&conn, &guid)?.is_empty());
+
+        let set_url = |url: &str| {
+            update_bookmark(
+                &conn,
+                &guid,
+                &UpdatableBookmark {
+                    url: Some(Url::parse(url).unwrap()),
+                    ..Default::default()
+                }
+                .into(),
+            )
+        };
+        set_url("https://www.example.com/1")?;
+        let changed_at = get_raw_bookmark(&conn, &guid)?.unwrap().date_modified;
+        // Changing only the title isn't recorded.
+        update_bookmark(
+            &conn,
+            &guid,
+            &UpdatableBookmark {
+                title: Some("new title".into()),
+                ..Default::default()
+            }
+            .into(),
+        )?;
+        let history = get_bookmark_url_history(&conn, &guid)?;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].url.as_str(), "https://www.example.com/0");
+        assert_eq!(history[0].changed_at, changed_at);
+
+        // Only the most recent URLs are kept.
+        for i in 2..(MAX_BOOKMARK_URL_HISTORY + 5) {
+            set_url(&format!("https://www.example.com/{}", i))?;
+        }
+        let history = get_bookmark_url_history(&conn, &guid)?;
+        assert_eq!(history.len(), MAX_BOOKMARK_URL_HISTORY as usize);
+        assert_eq!(
+            history[0].url.as_str(),
+            format!("https://www.example.com/{}", MAX_BOOKMARK_URL_HISTORY + 3)
+        );
+        assert_eq!(
+            history.last().unwrap().url.as_str(),
+            "https://www.example.com/4"
+        );
+
+        // And they're removed with the bookmark.
+        delete_bookmark(&conn, &guid)?;
+        assert!(get_bookmark_url_history(&conn, &guid)?.is_empty());
+        assert_eq!(
+            conn.query_one::<u32>("SELECT COUNT(*) FROM moz_bookmarks_url_history")?,
+            0
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_update_errors() {
         let conn = new_mem_connection();
@@ -2004,6 +2420,14 @@ mod tests {
             }),
         );
         assert_eq!(count_bookmarks_in_trees(&conn, &[])?, 0);
+        assert_eq!(
+            get_bookmark_count_by_type(&conn)?,
+            BookmarkCountByType {
+                bookmarks: 4,
+                folders: 3,
+                separators: 2,
+            }
+        );
         // A folder with sub-folders
         assert_eq!(count_bookmarks_in_trees(&conn, &[unfiled])?, 4);
         // A folder with items but no folders.
@@ -61,6 +61,17 @@ impl PartialEq for BookmarkData {
     }
 }
 
+/// A bookmark matching a search, along with the path of folders it lives in.
+#[derive(Debug, Clone)]
+pub struct BookmarkSearchResult {
+    pub bookmark: BookmarkData,
+    /// The titles of the bookmark's ancestor folders, outermost first, joined
+    /// with " / " (eg, "toolbar / Recipes / Dinner"). The titles of the roots
+    /// are the ones we store (eg, "toolbar"), so consumers wanting localized
+    /// names will want to replace the first component.
+    pub folder_path: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct Separator {
     pub guid: SyncGuid,
@@ -343,6 +354,37 @@ pub fn search_bookmarks(db: &PlacesDb, search: &str, limit: u32) -> Result<Vec<B
         .collect())
 }
 
+/// Like `search_bookmarks`, but also returns the folder path of each match,
+/// which UIs can use to tell apart bookmarks with the same title.
+pub fn search_with_paths(
+    db: &PlacesDb,
+    search: &str,
+    limit: u32,
+) -> Result<Vec<BookmarkSearchResult>> {
+    let scope = db.begin_interrupt_scope()?;
+    Ok(db
+        .query_rows_into_cached::<Vec<Option<BookmarkSearchResult>>, _, _, _, _>(
+            &SEARCH_WITH_PATHS_QUERY,
+            &[
+                (":search", &search as &dyn rusqlite::ToSql),
+                (":limit", &limit),
+            ],
+            |row| -> Result<_> {
+                scope.err_if_interrupted()?;
+                Ok(match bookmark_from_row(row)? {
+                    Some(bookmark) => Some(BookmarkSearchResult {
+                        bookmark,
+                        folder_path: row.get("folderPath")?,
+                    }),
+                    None => None,
+                })
+            },
+        )?
+        .into_iter()
+        .flatten()
+        .collect())
+}
+
 pub fn recent_bookmarks(db: &PlacesDb, limit: u32) -> Result<Vec<BookmarkData>> {
     let scope = db.begin_interrupt_scope()?;
     Ok(db
@@ -396,6 +438,70 @@ lazy_static::lazy_static! {
         search_bhvr = crate::match_impl::SearchBehavior::BOOKMARK.bits(),
     );
 
+    // The same matching as `SEARCH_QUERY`, but we then walk up the tree from
+    // each match, building the path as we go. The walk stops at the children
+    // of the root, so the row we want for each match is the one whose parent
+    // is the root. Newer bookmarks come first, both when picking which
+    // matches to keep and in the results.
+    pub static ref SEARCH_WITH_PATHS_QUERY: String = format!(
+        "WITH RECURSIVE
+        matches(id, parent, guid, parentGuid, position, dateAdded, lastModified, title, url) AS (
+            SELECT
+                b.id,
+                b.parent,
+                b.guid,
+                p.guid,
+                b.position,
+                b.dateAdded,
+                b.lastModified,
+                NULLIF(b.title, ''),
+                h.url
+            FROM moz_bookmarks b
+            JOIN moz_bookmarks p ON p.id = b.parent
+            JOIN moz_places h ON h.id = b.fk
+            WHERE b.type = {bookmark_type}
+                AND AUTOCOMPLETE_MATCH(
+                    :search, h.url, IFNULL(b.title, h.title),
+                    NULL, -- tags
+                    0, -- visit_count
+                    0, -- typed
+                    1, -- bookmarked
+                    NULL, -- open page count
+                    {match_bhvr},
+                    {search_bhvr}
+                )
+            ORDER BY b.dateAdded DESC, b.id DESC
+            LIMIT :limit
+        ),
+        ancestors(matchId, parentId, path) AS (
+            SELECT m.id, f.parent, IFNULL(f.title, '')
+            FROM matches m
+            JOIN moz_bookmarks f ON f.id = m.parent
+            UNION ALL
+            SELECT a.matchId, f.parent, IFNULL(f.title, '') || ' / ' || a.path
+            FROM ancestors a
+            JOIN moz_bookmarks f ON f.id = a.parentId
+            WHERE f.guid <> '{root_guid}'
+        )
+        SELECT
+            m.guid,
+            m.parentGuid,
+            m.position,
+            m.dateAdded,
+            m.lastModified,
+            m.title,
+            m.url,
+            a.path AS folderPath
+        FROM matches m
+        JOIN ancestors a ON a.matchId = m.id
+        WHERE a.parentId = (SELECT id FROM moz_bookmarks WHERE guid = '{root_guid}')
+        ORDER BY m.dateAdded DESC, m.id DESC",
+        bookmark_type = BookmarkType::Bookmark as u8,
+        match_bhvr = crate::match_impl::MatchBehavior::Anywhere as u32,
+        search_bhvr = crate::match_impl::SearchBehavior::BOOKMARK.bits(),
+        root_guid = BookmarkRootGuid::Root.as_str(),
+    );
+
     pub static ref RECENT_BOOKMARKS_QUERY: String = format!(
         "SELECT
             b.guid,
@@ -581,6 +687,85 @@ mod test {
         Ok(())
     }
     #[test]
+    fn test_search_with_paths() -> Result<()> {
+        let conns = new_mem_connections();
+        insert_json_tree(
+            &conns.write,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Toolbar.as_str()),
+                "children": [
+                    {
+                        "guid": "folder1_____",
+                        "title": "Recipes",
+                        "children": [
+                            {
+                                "guid": "folder2_____",
+                                "title": "Dinner",
+                                "children": [
+                                    {
+                                        "guid": "bookmark1___",
+                                        "url": "https://www.example1.com/",
+                                        "title": "pasta",
+                                    },
+                                ]
+                            },
+                            {
+                                "guid": "bookmark2___",
+                                "url": "https://www.example2.com/",
+                                "title": "pasta",
+                            },
+                        ]
+                    },
+                    {
+                        "guid": "bookmark3___",
+                        "url": "https://www.example3.com/",
+                        "title": "not a match",
+                    },
+                ]
+            }),
+        );
+        append_invalid_bookmark(
+            &conns.write,
+            BookmarkRootGuid::Unfiled.guid(),
+            "pasta",
+            "badurl",
+        );
+        insert_json_tree(
+            &conns.write,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    {
+                        "guid": "bookmark4___",
+                        "url": "https://www.example4.com/",
+                        "title": "pasta",
+                    },
+                ]
+            }),
+        );
+        // Newest first.
+        let results = search_with_paths(&conns.read, "pasta", 10)?;
+        let got = results
+            .iter()
+            .map(|r| (r.bookmark.guid.as_str(), r.folder_path.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            got,
+            vec![
+                ("bookmark4___", "unfiled"),
+                ("bookmark2___", "toolbar / Recipes"),
+                ("bookmark1___", "toolbar / Recipes / Dinner"),
+            ]
+        );
+        assert_eq!(results[2].bookmark.parent_guid, "folder2_____");
+
+        assert!(search_with_paths(&conns.read, "nothing", 10)?.is_empty());
+        let newest = search_with_paths(&conns.read, "pasta", 1)?;
+        assert_eq!(newest.len(), 1);
+        assert_eq!(newest[0].bookmark.guid, "bookmark4___");
+        Ok(())
+    }
+    #[test]
     fn test_fetch_bookmark() -> Result<()> {
         let conns = new_mem_connections();
 
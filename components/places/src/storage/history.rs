@@ -5,17 +5,18 @@
 mod actions;
 
 use super::{fetch_page_info, new_page_info, PageInfo, RowId};
+use crate::api::matcher::{split_after_host_and_port, split_after_prefix};
 use crate::db::PlacesDb;
-use crate::error::Result;
+use crate::error::{InvalidPlaceInfo, Result};
 use crate::ffi::{HistoryVisitInfo, HistoryVisitInfosWithBound, TopFrecentSiteInfo};
 use crate::frecency;
 use crate::hash;
 use crate::history_sync::engine::{
     COLLECTION_SYNCID_META_KEY, GLOBAL_SYNCID_META_KEY, LAST_SYNC_META_KEY,
 };
-use crate::observation::VisitObservation;
+use crate::observation::{HiddenVisitPolicy, VisitObservation};
 use crate::storage::{
-    delete_meta, delete_pending_temp_tables, get_meta, history_metadata, put_meta,
+    delete_meta, delete_pending_temp_tables, get_meta, history_metadata, put_meta, recently_closed,
 };
 use crate::types::{
     serialize_unknown_fields, SyncStatus, UnknownFields, VisitTransitionSet, VisitType,
@@ -41,29 +42,124 @@ use url::Url;
 /// add visits to them remotely.
 static DELETION_HIGH_WATER_MARK_META_KEY: &str = "history_deleted_hwm";
 
+/// If set, a local reload of a page within this many seconds of a local
+/// visit to it isn't recorded as a new visit, so that bursts of reloads don't
+/// inflate the page's frecency. See `set_reload_coalescing_window`.
+pub(crate) const RELOAD_COALESCING_WINDOW_META_KEY: &str = "history_reload_coalescing_window_secs";
+
+/// Sets how many seconds after a local visit to a page local reloads of it
+/// are ignored, or stops ignoring them if `secs` is `None`. The page's title
+/// and preview image are still updated by the ignored observations.
+pub fn set_reload_coalescing_window(db: &PlacesDb, secs: Option<u32>) -> Result<()> {
+    match secs {
+        Some(secs) => put_meta(db, RELOAD_COALESCING_WINDOW_META_KEY, &secs),
+        None => delete_meta(db, RELOAD_COALESCING_WINDOW_META_KEY),
+    }
+}
+
+/// If set, an incoming synced visit within this many milliseconds of an existing visit
+/// to the same page, with the same transition, is treated as that visit. See
+/// `set_synced_visit_dedup_window`.
+pub(crate) const SYNCED_VISIT_DEDUP_WINDOW_META_KEY: &str = "history_synced_visit_dedup_window_ms";
+
+/// Sets how many milliseconds apart an incoming synced visit and an existing visit to the
+/// same page, with the same transition, can be while still being treated as the same
+/// visit. This stops visits which round-trip through the server with slightly different
+/// timestamps from being duplicated. If `ms` is `None`, only visits with exactly the same
+/// timestamp are treated as the same.
+pub fn set_synced_visit_dedup_window(db: &PlacesDb, ms: Option<u32>) -> Result<()> {
+    match ms {
+        Some(ms) => put_meta(db, SYNCED_VISIT_DEDUP_WINDOW_META_KEY, &ms),
+        None => delete_meta(db, SYNCED_VISIT_DEDUP_WINDOW_META_KEY),
+    }
+}
+
+// Whether the page already has a visit with `transition` within `window` ms of `at`.
+fn has_visit_near(
+    db: &PlacesDb,
+    page_id: RowId,
+    at: Timestamp,
+    transition: VisitType,
+    window: u32,
+) -> Result<bool> {
+    let window = Duration::from_millis(u64::from(window));
+    Ok(db.exists(
+        "SELECT 1 FROM moz_historyvisits
+         WHERE place_id = :page_id
+           AND visit_type = :transition
+           AND visit_date BETWEEN :since AND :until",
+        rusqlite::named_params! {
+            ":page_id": page_id,
+            ":transition": transition,
+            ":since": at.checked_sub(window).unwrap_or(Timestamp(0)),
+            ":until": at.checked_add(window).unwrap_or(at),
+        },
+    )?)
+}
+
+/// Which visits mark their page as hidden. See `set_hidden_visit_policy`.
+pub(crate) const HIDDEN_VISIT_POLICY_META_KEY: &str = "history_hidden_visit_policy";
+
+/// Sets the rules which decide whether new visits mark their page as hidden. Pages
+/// which are already hidden, or visible, stay that way until their next visit.
+pub fn set_hidden_visit_policy(db: &PlacesDb, policy: HiddenVisitPolicy) -> Result<()> {
+    put_meta(db, HIDDEN_VISIT_POLICY_META_KEY, &policy)
+}
+
+pub fn get_hidden_visit_policy(db: &PlacesDb) -> Result<HiddenVisitPolicy> {
+    Ok(get_meta(db, HIDDEN_VISIT_POLICY_META_KEY)?.unwrap_or_default())
+}
+
+// Whether a local reload of the page at `at` falls in the coalescing window.
+fn is_coalesced_reload(db: &PlacesDb, page_id: RowId, at: Timestamp) -> Result<bool> {
+    let window = match get_meta::<u32>(db, RELOAD_COALESCING_WINDOW_META_KEY)? {
+        Some(window) => window,
+        None => return Ok(false),
+    };
+    let since = at
+        .checked_sub(Duration::from_secs(u64::from(window)))
+        .unwrap_or(Timestamp(0));
+    Ok(db.exists(
+        "SELECT 1 FROM moz_historyvisits
+         WHERE place_id = :page_id AND is_local AND visit_date BETWEEN :since AND :at",
+        rusqlite::named_params! {
+            ":page_id": page_id,
+            ":since": since,
+            ":at": at,
+        },
+    )?)
+}
+
 /// Returns the RowId of a new visit in moz_historyvisits, or None if no new visit was added.
 pub fn apply_observation(db: &PlacesDb, visit_ob: VisitObservation) -> Result<Option<RowId>> {
     let tx = db.begin_transaction()?;
-    let result = apply_observation_direct(db, visit_ob)?;
+    let hidden_policy = get_hidden_visit_policy(db)?;
+    let result = apply_observation_direct(db, visit_ob, hidden_policy)?;
     delete_pending_temp_tables(db)?;
     tx.commit()?;
     Ok(result)
 }
 
 /// Returns the RowId of a new visit in moz_historyvisits, or None if no new visit was added.
+/// `hidden_policy` decides whether the visit leaves the page hidden.
 pub fn apply_observation_direct(
     db: &PlacesDb,
-    visit_ob: VisitObservation,
+    mut visit_ob: VisitObservation,
+    hidden_policy: HiddenVisitPolicy,
 ) -> Result<Option<RowId>> {
+    if let Some(url) = url_on_merged_origin(db, &visit_ob.url)? {
+        visit_ob.url = url;
+    }
     // Don't insert urls larger than our length max.
-    if visit_ob.url.as_str().len() > super::URL_LENGTH_MAX {
+    let limits = db.limits();
+    if visit_ob.url.as_str().len() > limits.max_url_length() {
         return Ok(None);
     }
     // Make sure we have a valid preview URL - it should parse, and not exceed max size.
     // In case the URL is too long, ignore it and proceed with the rest of the observation.
     // In case the URL is entirely invalid, let the caller know by failing.
     let preview_image_url = if let Some(ref piu) = visit_ob.preview_image_url {
-        if piu.as_str().len() > super::URL_LENGTH_MAX {
+        if piu.as_str().len() > limits.max_url_length() {
             None
         } else {
             Some(piu.clone())
@@ -71,16 +167,17 @@ pub fn apply_observation_direct(
     } else {
         None
     };
-    let mut page_info = match fetch_page_info(db, &visit_ob.url)? {
-        Some(info) => info.page,
-        None => new_page_info(db, &visit_ob.url, None)?,
+    let (mut page_info, is_new_page) = match fetch_page_info(db, &visit_ob.url)? {
+        Some(info) => (info.page, false),
+        None => (new_page_info(db, &visit_ob.url, None)?, true),
     };
+    let is_hidden = hidden_policy.is_hidden(&visit_ob);
     let mut update_change_counter = false;
     let mut update_frec = false;
     let mut updates: Vec<(&str, &str, &dyn ToSql)> = Vec::new();
 
     if let Some(ref title) = visit_ob.title {
-        page_info.title = crate::util::slice_up_to(title, super::TITLE_LENGTH_MAX).into();
+        page_info.title = crate::util::slice_up_to(title, limits.max_title_length()).into();
         updates.push(("title", ":title", &page_info.title));
         update_change_counter = true;
     }
@@ -93,22 +190,38 @@ pub fn apply_observation_direct(
             &preview_image_url_str,
         ));
     }
+    let at = visit_ob.at.unwrap_or_else(Timestamp::now);
+    let is_remote = visit_ob.is_remote.unwrap_or(false);
+    let visit_type = match visit_ob.visit_type {
+        Some(VisitType::Reload) if !is_remote && is_coalesced_reload(db, page_info.row_id, at)? => {
+            None
+        }
+        visit_type => visit_type,
+    };
     // There's a new visit, so update everything that implies. To help with
     // testing we return the rowid of the visit we added.
-    let visit_row_id = match visit_ob.visit_type {
+    let visit_row_id = match visit_type {
         Some(visit_type) => {
-            // A single non-hidden visit makes the place non-hidden.
-            if !visit_ob.get_is_hidden() {
-                updates.push(("hidden", ":hidden", &false));
+            // A page we've just added is only hidden if its first visit is, and a
+            // single non-hidden visit makes any page non-hidden.
+            if is_new_page || !is_hidden {
+                updates.push(("hidden", ":hidden", &is_hidden));
             }
             if visit_type == VisitType::Typed {
                 page_info.typed += 1;
                 updates.push(("typed", ":typed", &page_info.typed));
             }
 
-            let at = visit_ob.at.unwrap_or_else(Timestamp::now);
-            let is_remote = visit_ob.is_remote.unwrap_or(false);
-            let row_id = add_visit(db, page_info.row_id, None, at, visit_type, !is_remote, None)?;
+            let row_id = add_visit(
+                db,
+                page_info.row_id,
+                None,
+                at,
+                visit_type,
+                !is_remote,
+                None,
+                visit_ob.container_id.as_deref(),
+            )?;
             // a new visit implies new frecency except in error cases.
             if !visit_ob.is_error.unwrap_or(false) {
                 update_frec = true;
@@ -194,6 +307,7 @@ pub fn frecency_stale_at(db: &PlacesDb, url: &Url) -> Result<Option<Timestamp>>
 // Add a single visit - you must know the page rowid. Does not update the
 // page info - if you are calling this, you will also need to update the
 // parent page with an updated change counter etc.
+#[allow(clippy::too_many_arguments)]
 fn add_visit(
     db: &PlacesDb,
     page_id: RowId,
@@ -202,10 +316,12 @@ fn add_visit(
     visit_type: VisitType,
     is_local: bool,
     unknown_fields: Option<String>,
+    container_id: Option<&str>,
 ) -> Result<RowId> {
     let sql = "INSERT INTO moz_historyvisits
-            (from_visit, place_id, visit_date, visit_type, is_local, unknown_fields)
-        VALUES (:from_visit, :page_id, :visit_date, :visit_type, :is_local, :unknown_fields)";
+            (from_visit, place_id, visit_date, visit_type, is_local, unknown_fields, container_id)
+        VALUES (:from_visit, :page_id, :visit_date, :visit_type, :is_local, :unknown_fields,
+                :container_id)";
     db.execute_cached(
         sql,
         &[
@@ -215,6 +331,7 @@ fn add_visit(
             (":visit_type", &visit_type),
             (":is_local", &is_local),
             (":unknown_fields", &unknown_fields),
+            (":container_id", &container_id),
         ],
     )?;
     let rid = db.conn().last_insert_rowid();
@@ -252,7 +369,7 @@ pub fn href_to_guid(db: &PlacesDb, url: &str) -> Result<Option<SyncGuid>> {
 
 /// Internal function for deleting a page, creating a tombstone if necessary.
 /// Assumes a transaction is already set up by the caller.
-fn delete_visits_for_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
+pub(crate) fn delete_visits_for_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
     // We only create tombstones for history which exists and with sync_status
     // == SyncStatus::Normal
     let to_clean = db.conn().try_query_row(
@@ -322,14 +439,15 @@ fn delete_visits_for_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
         None => {}
     }
     delete_pending_temp_tables(db)?;
+    delete_orphaned_origin_merges(db)?;
     Ok(())
 }
 
 /// Inserts Sync tombstones for all of a page's visits.
 fn insert_tombstones_for_all_page_visits(db: &PlacesDb, page_id: RowId) -> Result<()> {
     db.execute_cached(
-        "INSERT OR IGNORE INTO moz_historyvisit_tombstones(place_id, visit_date)
-         SELECT place_id, visit_date
+        "INSERT OR IGNORE INTO moz_historyvisit_tombstones(place_id, visit_date, deleted_at)
+         SELECT place_id, visit_date, now()
          FROM moz_historyvisits
          WHERE place_id = :page_id",
         &[(":page_id", &page_id)],
@@ -351,8 +469,8 @@ fn delete_all_visits_for_page(db: &PlacesDb, page_id: RowId) -> Result<()> {
 /// Inserts a Sync tombstone for a page.
 fn insert_tombstone_for_page(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
     db.execute_cached(
-        "INSERT OR IGNORE INTO moz_places_tombstones (guid)
-         VALUES(:guid)",
+        "INSERT OR IGNORE INTO moz_places_tombstones (guid, deleted_at)
+         VALUES(:guid, now())",
         &[(":guid", guid)],
     )?;
     Ok(())
@@ -402,18 +520,37 @@ pub fn delete_place_visit_at_time_by_href(
 }
 
 pub fn prune_older_visits(db: &PlacesDb, limit: u32) -> Result<()> {
+    prune_older_visits_counted(db, limit).map(|_| ())
+}
+
+/// Like `prune_older_visits`, but returns the number of visits which were pruned.
+pub(crate) fn prune_older_visits_counted(db: &PlacesDb, limit: u32) -> Result<usize> {
     let tx = db.begin_transaction()?;
 
-    let result = DbAction::apply_all(
-        db,
-        db_actions_from_visits_to_delete(find_visits_to_prune(
-            db,
-            limit as usize,
-            Timestamp::now(),
-        )?),
-    );
+    let to_delete = find_visits_to_prune(db, limit as usize, Timestamp::now())?;
+    let num_pruned = to_delete.len();
+    let result = DbAction::apply_all(db, db_actions_from_visits_to_delete(to_delete));
     tx.commit()?;
-    result
+    result.map(|_| num_pruned)
+}
+
+/// Deletes page and visit tombstones for deletions made before `cutoff`. Returns the number of
+/// page tombstones and visit tombstones deleted.
+///
+/// Tombstones are how deletions are synced, so this should only be used when history sync
+/// isn't connected, or deletions made before `cutoff` won't reach other devices.
+pub fn delete_tombstones_older_than(db: &PlacesDb, cutoff: Timestamp) -> Result<(usize, usize)> {
+    let tx = db.begin_transaction()?;
+    let num_pages = db.execute_cached(
+        "DELETE FROM moz_places_tombstones WHERE deleted_at < :cutoff",
+        &[(":cutoff", &cutoff)],
+    )?;
+    let num_visits = db.execute_cached(
+        "DELETE FROM moz_historyvisit_tombstones WHERE deleted_at < :cutoff",
+        &[(":cutoff", &cutoff)],
+    )?;
+    tx.commit()?;
+    Ok((num_pages, num_visits))
 }
 
 fn find_visits_to_prune(db: &PlacesDb, limit: usize, now: Timestamp) -> Result<Vec<VisitToDelete>> {
@@ -505,6 +642,7 @@ fn wipe_local_in_tx(db: &PlacesDb) -> Result<()> {
         "DELETE FROM moz_historyvisit_tombstones",
         "DELETE FROM moz_origins
          WHERE id NOT IN (SELECT origin_id FROM moz_places)",
+        "DELETE FROM moz_origin_merges",
         &format!(
             r#"UPDATE moz_places SET
                 frecency = (CASE WHEN url_hash BETWEEN hash("place", "prefix_lo") AND
@@ -521,9 +659,7 @@ fn wipe_local_in_tx(db: &PlacesDb) -> Result<()> {
         db.query_rows_and_then("SELECT id FROM moz_places", [], |r| r.get::<_, RowId>(0))?;
     // Update the frecency for any remaining items, which basically means just
     // for the bookmarks.
-    for row_id in need_frecency_update {
-        update_frecency(db, row_id, None)?;
-    }
+    frecency::update_frecencies(db, &DEFAULT_FRECENCY_SETTINGS, &need_frecency_update)?;
     delete_pending_temp_tables(db)?;
     Ok(())
 }
@@ -547,6 +683,7 @@ pub fn delete_everything(db: &PlacesDb) -> Result<()> {
     put_meta(db, DELETION_HIGH_WATER_MARK_META_KEY, &new_mark)?;
 
     wipe_local_in_tx(db)?;
+    recently_closed::clear_recently_closed(db)?;
 
     // Remove Sync metadata, too.
     reset_in_tx(db, &EngineSyncAssociation::Disconnected)?;
@@ -618,10 +755,10 @@ pub fn delete_visits_between_in_tx(db: &PlacesDb, start: Timestamp, end: Timesta
     // Insert tombstones for the deleted visits.
     if !visits.is_empty() {
         let sql = format!(
-            "INSERT OR IGNORE INTO moz_historyvisit_tombstones(place_id, visit_date) VALUES {}",
+            "INSERT OR IGNORE INTO moz_historyvisit_tombstones(place_id, visit_date, deleted_at) VALUES {}",
             sql_support::repeat_display(visits.len(), ",", |i, f| {
                 let (_, place_id, visit_date) = visits[i];
-                write!(f, "({},{})", place_id.0, visit_date.0)
+                write!(f, "({},{},now())", place_id.0, visit_date.0)
             })
         );
         db.conn().execute(&sql, [])?;
@@ -653,6 +790,7 @@ pub fn delete_visits_between_in_tx(db: &PlacesDb, start: Timestamp, end: Timesta
     // Clean up history metadata between start and end
     history_metadata::delete_between(db, start.as_millis_i64(), end.as_millis_i64())?;
     delete_pending_temp_tables(db)?;
+    delete_orphaned_origin_merges(db)?;
     Ok(())
 }
 
@@ -681,16 +819,12 @@ impl PageToClean {
 /// are no more foreign keys such as bookmarks) or updating
 /// their frecency.
 fn cleanup_pages(db: &PlacesDb, pages: &[PageToClean]) -> Result<()> {
-    // desktop does this frecency work using a function in a single sql
-    // statement - we should see if we can do that too.
-    let frec_ids = pages
+    let frec_ids: Vec<RowId> = pages
         .iter()
         .filter(|&p| p.has_foreign || p.has_visits)
-        .map(|p| p.id);
-
-    for id in frec_ids {
-        update_frecency(db, id, None)?;
-    }
+        .map(|p| p.id)
+        .collect();
+    frecency::update_frecencies(db, &frecency::DEFAULT_FRECENCY_SETTINGS, &frec_ids)?;
 
     // Like desktop, we do "AND foreign_count = 0 AND last_visit_date ISNULL"
     // to creating orphans in case of async race conditions - in Desktop's
@@ -707,8 +841,8 @@ fn cleanup_pages(db: &PlacesDb, pages: &[PageToClean]) -> Result<()> {
         db.conn().execute(
             &format!(
                 "
-                INSERT OR IGNORE INTO moz_places_tombstones (guid)
-                SELECT guid FROM moz_places
+                INSERT OR IGNORE INTO moz_places_tombstones (guid, deleted_at)
+                SELECT guid, now() FROM moz_places
                 WHERE id in ({ids}) AND sync_status = {status}
                     AND foreign_count = 0
                     AND last_visit_date_local = 0
@@ -736,6 +870,189 @@ fn cleanup_pages(db: &PlacesDb, pages: &[PageToClean]) -> Result<()> {
     Ok(())
 }
 
+// Splits an http(s) origin, given as any URL on it, into its prefix and host, as they're
+// stored in `moz_origins`.
+fn split_origin(url: &Url) -> Result<(&str, &str)> {
+    if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+        return Err(InvalidPlaceInfo::InvalidOrigin.into());
+    }
+    let (prefix, _) = split_after_prefix(url.as_str());
+    let (host, _) = split_after_host_and_port(url.as_str());
+    Ok((prefix, host))
+}
+
+/// Moves the history for pages on the `from` origin to the same pages on the `to` origin,
+/// such as after a site moves from http to https, so that the history and frecency of the
+/// site aren't split between them. Both origins can be given as any URL on them.
+///
+/// Visits, history metadata and input history are moved, and the `from` pages are removed
+/// unless they're bookmarked. New visits to pages on `from` are recorded on `to` from now on.
+pub fn merge_origins(db: &PlacesDb, from: &Url, to: &Url) -> Result<()> {
+    let (from_prefix, from_host) = split_origin(from)?;
+    let (to_prefix, to_host) = split_origin(to)?;
+    if (from_prefix, from_host) == (to_prefix, to_host) {
+        return Err(InvalidPlaceInfo::InvalidOrigin.into());
+    }
+    let tx = db.begin_transaction()?;
+    let pages = db.query_rows_and_then(
+        "SELECT h.id, h.url FROM moz_places h
+         JOIN moz_origins o ON o.id = h.origin_id
+         WHERE o.prefix = :prefix AND o.host = :host",
+        &[(":prefix", &from_prefix), (":host", &from_host)],
+        |row| -> Result<_> { Ok((row.get::<_, RowId>(0)?, row.get::<_, String>(1)?)) },
+    )?;
+    let mut touched = Vec::with_capacity(pages.len() * 2);
+    for (page_id, href) in pages {
+        let (_, rest) = split_after_host_and_port(&href);
+        let url = Url::parse(&format!("{to_prefix}{to_host}{rest}"))?;
+        let target = match fetch_page_info(db, &url)? {
+            Some(info) => info.page,
+            None => new_page_info(db, &url, None)?,
+        };
+        move_page_history(db, page_id, target.row_id)?;
+        touched.push(page_id);
+        touched.push(target.row_id);
+    }
+    sql_support::each_chunk(&touched, |chunk, _| -> Result<()> {
+        let mut stmt = db.conn().prepare(&format!(
+            "SELECT id,
+                (foreign_count != 0) AS has_foreign,
+                ((last_visit_date_local + last_visit_date_remote) != 0) as has_visits,
+                sync_status
+            FROM moz_places
+            WHERE id IN ({})",
+            sql_support::repeat_sql_vars(chunk.len()),
+        ))?;
+        let pages = stmt
+            .query_and_then(rusqlite::params_from_iter(chunk), PageToClean::from_row)?
+            .collect::<Result<Vec<_>>>()?;
+        cleanup_pages(db, &pages)
+    })?;
+    // Origins which were merged into `from` are now merged into `to`, and if `to` was
+    // merged into `from`, it isn't any more.
+    db.execute_cached(
+        "DELETE FROM moz_origin_merges
+         WHERE from_prefix = :to_prefix AND from_host = :to_host",
+        rusqlite::named_params! { ":to_prefix": to_prefix, ":to_host": to_host },
+    )?;
+    db.execute_cached(
+        "UPDATE moz_origin_merges SET to_prefix = :to_prefix, to_host = :to_host
+         WHERE to_prefix = :from_prefix AND to_host = :from_host",
+        rusqlite::named_params! {
+            ":from_prefix": from_prefix,
+            ":from_host": from_host,
+            ":to_prefix": to_prefix,
+            ":to_host": to_host,
+        },
+    )?;
+    db.execute_cached(
+        "INSERT OR REPLACE INTO moz_origin_merges (from_prefix, from_host, to_prefix, to_host)
+         VALUES (:from_prefix, :from_host, :to_prefix, :to_host)",
+        rusqlite::named_params! {
+            ":from_prefix": from_prefix,
+            ":from_host": from_host,
+            ":to_prefix": to_prefix,
+            ":to_host": to_host,
+        },
+    )?;
+    delete_pending_temp_tables(db)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Undoes [`merge_origins`] for the `from` origin, which can be given as any URL on it, so
+/// that new visits to its pages are recorded on it again. The history which was already
+/// moved stays where it is. Returns whether the origin had been merged.
+pub fn unmerge_origin(db: &PlacesDb, from: &Url) -> Result<bool> {
+    let (from_prefix, from_host) = split_origin(from)?;
+    let deleted = db.execute_cached(
+        "DELETE FROM moz_origin_merges
+         WHERE from_prefix = :from_prefix AND from_host = :from_host",
+        rusqlite::named_params! { ":from_prefix": from_prefix, ":from_host": from_host },
+    )?;
+    Ok(deleted > 0)
+}
+
+// A merge says that the user visited both origins, so once all the history on the origin
+// it points to has been deleted, it's forgotten too.
+fn delete_orphaned_origin_merges(db: &PlacesDb) -> Result<()> {
+    db.execute_cached(
+        "DELETE FROM moz_origin_merges
+         WHERE NOT EXISTS (SELECT 1 FROM moz_origins
+                           WHERE prefix = to_prefix AND host = to_host)",
+        [],
+    )?;
+    Ok(())
+}
+
+// Moves the visits, history metadata and input history from one page to another. The
+// moved visits are tombstoned on the old page, so they aren't synced back to it.
+fn move_page_history(db: &PlacesDb, from_id: RowId, to_id: RowId) -> Result<()> {
+    let params = rusqlite::named_params! { ":from_id": from_id, ":to_id": to_id };
+    insert_tombstones_for_all_page_visits(db, from_id)?;
+    // The visits are re-inserted rather than updated, so that the triggers maintain both
+    // pages' visit counts. Links between visits are lost, because the visits they point
+    // to are deleted.
+    db.execute_cached(
+        "INSERT INTO moz_historyvisits
+            (place_id, visit_date, visit_type, is_local, unknown_fields, container_id)
+         SELECT :to_id, v.visit_date, v.visit_type, v.is_local, v.unknown_fields, v.container_id
+         FROM moz_historyvisits v
+         WHERE v.place_id = :from_id
+           AND NOT EXISTS (SELECT 1 FROM moz_historyvisits
+                           WHERE place_id = :to_id AND visit_date = v.visit_date)",
+        params,
+    )?;
+    db.execute_cached(
+        "DELETE FROM moz_historyvisit_tombstones
+         WHERE place_id = :to_id
+           AND visit_date IN (SELECT visit_date FROM moz_historyvisits WHERE place_id = :to_id)",
+        rusqlite::named_params! { ":to_id": to_id },
+    )?;
+    delete_all_visits_for_page(db, from_id)?;
+    // Anything which can't be moved because the new page already has it is deleted with
+    // the old page.
+    db.execute_cached(
+        "UPDATE OR IGNORE moz_places_metadata SET place_id = :to_id WHERE place_id = :from_id",
+        params,
+    )?;
+    db.execute_cached(
+        "UPDATE OR IGNORE moz_inputhistory SET place_id = :to_id WHERE place_id = :from_id",
+        params,
+    )?;
+    db.execute_cached(
+        "UPDATE moz_places SET
+            title = CASE WHEN IFNULL(title, '') = '' THEN
+                (SELECT title FROM moz_places WHERE id = :from_id) ELSE title END,
+            sync_change_counter = sync_change_counter + 1
+         WHERE id = :to_id",
+        params,
+    )?;
+    Ok(())
+}
+
+// If `url` is on an origin which has been merged into another, returns the same URL on that
+// origin.
+fn url_on_merged_origin(db: &PlacesDb, url: &Url) -> Result<Option<Url>> {
+    let Ok((prefix, host)) = split_origin(url) else {
+        return Ok(None);
+    };
+    let target = db.try_query_row(
+        "SELECT to_prefix, to_host FROM moz_origin_merges
+         WHERE from_prefix = :prefix AND from_host = :host",
+        &[(":prefix", &prefix), (":host", &host)],
+        |row| -> Result<_> { Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)) },
+        true,
+    )?;
+    Ok(match target {
+        Some((to_prefix, to_host)) => {
+            let (_, rest) = split_after_host_and_port(url.as_str());
+            Some(Url::parse(&format!("{to_prefix}{to_host}{rest}"))?)
+        }
+        None => None,
+    })
+}
+
 fn reset_in_tx(db: &PlacesDb, assoc: &EngineSyncAssociation) -> Result<()> {
     // Reset change counters and sync statuses for all URLs.
     db.execute_cached(
@@ -946,6 +1263,13 @@ pub mod history_sync {
 
             visits_to_skip.reserve(visits.len());
 
+            // Visits which round-trip through the server can come back with slightly
+            // different timestamps, so we may also skip visits close to one that's
+            // already present. Unlike the check above, this also requires the transition
+            // to match.
+            let dedup_window =
+                get_meta::<u32>(db, SYNCED_VISIT_DEDUP_WINDOW_META_KEY)?.filter(|ms| *ms > 0);
+
             for visit in visits {
                 let timestamp = Timestamp::from(visit.date);
                 // Don't insert visits that have been locally deleted.
@@ -954,6 +1278,11 @@ pub mod history_sync {
                 }
                 let transition = VisitType::from_primitive(visit.transition)
                     .expect("these should already be validated");
+                if let Some(window) = dedup_window {
+                    if has_visit_near(db, page_info.row_id, timestamp, transition, window)? {
+                        continue;
+                    }
+                }
                 add_visit(
                     db,
                     page_info.row_id,
@@ -962,6 +1291,8 @@ pub mod history_sync {
                     transition,
                     false,
                     serialize_unknown_fields(&visit.unknown_fields)?,
+                    // Containers are local to this device, so synced visits never have one.
+                    None,
                 )?;
                 // Make sure that even if a history entry weirdly has the same visit
                 // twice, we don't insert it twice. (This avoids us needing to
@@ -1038,10 +1369,13 @@ pub mod history_sync {
         Ok(())
     }
 
+    /// Fetches the pages and tombstones to upload. If `window_start` is set,
+    /// we only upload visits since then, and skip pages without any.
     pub fn fetch_outgoing(
         db: &PlacesDb,
         max_places: usize,
         max_visits: usize,
+        window_start: Option<Timestamp>,
     ) -> Result<Vec<OutgoingBso>> {
         // Note that we want *all* "new" regardless of change counter,
         // so that we do the right thing after a "reset". We also
@@ -1064,7 +1398,8 @@ pub mod history_sync {
         let visits_sql = "
             SELECT visit_date as date, visit_type as transition, unknown_fields
             FROM moz_historyvisits
-            WHERE place_id = :place_id
+            WHERE place_id = :place_id AND
+                  visit_date >= :window_start
             ORDER BY visit_date DESC
             LIMIT :max_visits";
         // tombstones
@@ -1127,6 +1462,7 @@ pub mod history_sync {
                 &[
                     (":max_visits", &(max_visits as u32) as &dyn rusqlite::ToSql),
                     (":place_id", &page.row_id),
+                    (":window_start", &window_start.unwrap_or_default()),
                 ],
                 |row| -> Result<_> {
                     Ok(HistoryRecordVisit {
@@ -1307,6 +1643,28 @@ pub fn get_visited_into(
     Ok(())
 }
 
+/// Get the visited urls which start with `prefix`, which is usually an origin (like
+/// `https://example.com/`) or a directory under one (like `https://example.com/docs/`).
+///
+/// The prefix is matched against the url as a string, so `https://example.com/doc` also
+/// matches `https://example.com/documents`. Only urls on the same origin as `prefix` are
+/// considered, which lets us find them through `moz_origins` rather than scanning every url.
+pub fn get_visited_for_origin_prefix(db: &PlacesDb, prefix: &Url) -> Result<Vec<Url>> {
+    let urls = db.query_rows_and_then_cached(
+        "SELECT h.url
+         FROM moz_places h
+         JOIN moz_origins o ON o.id = h.origin_id
+         WHERE o.prefix = get_prefix(:prefix)
+           AND o.host = get_host_and_port(:prefix)
+           AND substr(h.url, 1, length(:prefix)) = :prefix
+           AND (h.visit_count_local > 0 OR h.visit_count_remote > 0)
+         ORDER BY h.url",
+        &[(":prefix", &prefix.as_str())],
+        |row| -> RusqliteResult<_> { row.get::<_, String>(0) },
+    )?;
+    Ok(urls.iter().filter_map(|s| Url::parse(s).ok()).collect())
+}
+
 /// Get the set of urls that were visited between `start` and `end`. Only considers local visits
 /// unless you pass in `include_remote`.
 pub fn get_visited_urls(
@@ -1378,6 +1736,106 @@ pub fn get_top_frecent_site_infos(
     Ok(infos)
 }
 
+/// How `get_origin_stats()` orders origins. Each order is descending, so the
+/// busiest, most recent or largest origins come first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OriginStatsOrder {
+    Frecency,
+    VisitCount,
+    LastVisit,
+    EstimatedSize,
+}
+
+impl OriginStatsOrder {
+    fn column(self) -> &'static str {
+        match self {
+            OriginStatsOrder::Frecency => "frecency",
+            OriginStatsOrder::VisitCount => "visit_count",
+            OriginStatsOrder::LastVisit => "last_visit_date",
+            OriginStatsOrder::EstimatedSize => "estimated_size",
+        }
+    }
+}
+
+/// What's stored for an origin's history, from `get_origin_stats()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OriginStats {
+    /// The origin, such as `https://example.com`.
+    pub origin: String,
+    pub host: String,
+    pub frecency: i64,
+    /// How many of the origin's pages are stored, including ones which are
+    /// only bookmarked.
+    pub page_count: i64,
+    /// Local and remote visits to all of the origin's pages.
+    pub visit_count: i64,
+    pub last_visit_date: Timestamp,
+    /// Roughly how many bytes the origin's pages and visits take up in the
+    /// database. It's an estimate, and is only meant for comparing origins.
+    pub estimated_size: i64,
+}
+
+impl OriginStats {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            origin: row.get("origin")?,
+            host: row.get("host")?,
+            frecency: row.get("frecency")?,
+            page_count: row.get("page_count")?,
+            visit_count: row.get("visit_count")?,
+            last_visit_date: row.get("last_visit_date")?,
+            estimated_size: row.get("estimated_size")?,
+        })
+    }
+}
+
+// The size estimate adds up the lengths of each page's text columns, then
+// adds these for the rest of each row, which are mostly integers, and for the
+// indexes on them.
+const ESTIMATED_PAGE_ROW_BYTES: i64 = 120;
+const ESTIMATED_VISIT_ROW_BYTES: i64 = 40;
+
+/// Returns statistics about the history stored for each visited origin, for
+/// showing the user what's stored and letting them clear it by site.
+pub fn get_origin_stats(
+    db: &PlacesDb,
+    limit: i32,
+    order_by: OriginStatsOrder,
+) -> Result<Vec<OriginStats>> {
+    let sql = format!(
+        "SELECT origin, host, frecency, page_count, visit_count, last_visit_date,
+            text_bytes + page_count * :page_row_bytes + visit_rows * :visit_row_bytes
+                AS estimated_size
+        FROM (
+            SELECT o.prefix || o.host AS origin, o.host AS host, o.frecency AS frecency,
+                COUNT(*) AS page_count,
+                SUM(h.visit_count_local + h.visit_count_remote) AS visit_count,
+                MAX(MAX(h.last_visit_date_local, h.last_visit_date_remote)) AS last_visit_date,
+                SUM(LENGTH(h.url) + IFNULL(LENGTH(h.title), 0)
+                    + IFNULL(LENGTH(h.description), 0)
+                    + IFNULL(LENGTH(h.preview_image_url), 0)) AS text_bytes,
+                SUM((SELECT COUNT(*) FROM moz_historyvisits v WHERE v.place_id = h.id))
+                    AS visit_rows
+            FROM moz_origins o
+            JOIN moz_places h ON h.origin_id = o.id
+            GROUP BY o.id
+        )
+        WHERE last_visit_date > 0
+        ORDER BY {order} DESC, origin
+        LIMIT :limit",
+        order = order_by.column(),
+    );
+    db.query_rows_and_then(
+        &sql,
+        rusqlite::named_params! {
+            ":page_row_bytes": ESTIMATED_PAGE_ROW_BYTES,
+            ":visit_row_bytes": ESTIMATED_VISIT_ROW_BYTES,
+            ":limit": limit,
+        },
+        OriginStats::from_row,
+    )
+}
+
 pub fn get_visit_infos(
     db: &PlacesDb,
     start: Timestamp,
@@ -1405,6 +1863,93 @@ pub fn get_visit_infos(
     Ok(infos)
 }
 
+/// Like `get_visit_infos`, but only returns the visits made in `container_id`. Passing `None`
+/// returns only the visits which weren't made in any container.
+pub fn get_visit_infos_in_container(
+    db: &PlacesDb,
+    start: Timestamp,
+    end: Timestamp,
+    exclude_types: VisitTransitionSet,
+    container_id: Option<&str>,
+) -> Result<Vec<HistoryVisitInfo>> {
+    let allowed_types = exclude_types.complement();
+    let infos = db.query_rows_and_then_cached(
+        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden, h.preview_image_url,
+                v.is_local
+         FROM moz_places h
+         JOIN moz_historyvisits v
+           ON h.id = v.place_id
+         WHERE v.visit_date BETWEEN :start AND :end
+           AND ((1 << visit_type) & :allowed_types) != 0
+           AND v.container_id IS :container_id AND
+           NOT h.hidden
+         ORDER BY v.visit_date",
+        rusqlite::named_params! {
+            ":start": start,
+            ":end": end,
+            ":allowed_types": allowed_types,
+            ":container_id": container_id,
+        },
+        HistoryVisitInfo::from_row,
+    )?;
+    Ok(infos)
+}
+
+/// The last visit in a page returned by `get_visit_infos_after`. Visits are ordered by date, and
+/// by id for visits made at the same time, so the next page starts right after this one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VisitPagePosition {
+    visit_date: Timestamp,
+    visit_id: i64,
+}
+
+/// Returns up to `count` of the visits `get_visit_infos` would return for `start..=end`, starting
+/// after `after`, or from the oldest visit if it's `None`. Unlike `get_visit_infos`, this doesn't
+/// need to hold every visit in the range in memory, so callers can page through all of history.
+/// Returns the position of the last visit in the page, or `None` if the page is empty.
+pub fn get_visit_infos_after(
+    db: &PlacesDb,
+    start: Timestamp,
+    end: Timestamp,
+    after: Option<VisitPagePosition>,
+    count: i64,
+    exclude_types: VisitTransitionSet,
+) -> Result<(Vec<HistoryVisitInfo>, Option<VisitPagePosition>)> {
+    let allowed_types = exclude_types.complement();
+    let (after_date, after_id) = match after {
+        Some(position) => (position.visit_date, position.visit_id),
+        None => (start, -1),
+    };
+    let rows = db.query_rows_and_then_cached(
+        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden, h.preview_image_url,
+                v.is_local, v.id AS visit_id
+         FROM moz_places h
+         JOIN moz_historyvisits v
+           ON h.id = v.place_id
+         WHERE v.visit_date BETWEEN :start AND :end
+           AND (v.visit_date > :after_date OR
+                (v.visit_date = :after_date AND v.id > :after_id))
+           AND ((1 << visit_type) & :allowed_types) != 0 AND
+           NOT h.hidden
+         ORDER BY v.visit_date, v.id
+         LIMIT :count",
+        rusqlite::named_params! {
+            ":start": start,
+            ":end": end,
+            ":after_date": after_date,
+            ":after_id": after_id,
+            ":allowed_types": allowed_types,
+            ":count": count,
+        },
+        |row| -> Result<_> { Ok((HistoryVisitInfo::from_row(row)?, row.get("visit_id")?)) },
+    )?;
+    let position = rows.last().map(|(info, visit_id)| VisitPagePosition {
+        visit_date: info.timestamp,
+        visit_id: *visit_id,
+    });
+    Ok((rows.into_iter().map(|(info, _)| info).collect(), position))
+}
+
 pub fn get_visit_count(db: &PlacesDb, exclude_types: VisitTransitionSet) -> Result<i64> {
     let count = if exclude_types.is_empty() {
         db.query_one::<i64>("SELECT COUNT(*) FROM moz_historyvisits")?
@@ -1424,6 +1969,37 @@ pub fn get_visit_count(db: &PlacesDb, exclude_types: VisitTransitionSet) -> Resu
     Ok(count)
 }
 
+/// The number of pages in history, ie, pages with at least one visit. Pages which are
+/// only bookmarked aren't counted.
+pub fn get_history_page_count(db: &PlacesDb) -> Result<i64> {
+    Ok(db.query_one::<i64>(
+        "SELECT COUNT(*) FROM moz_places h
+         WHERE EXISTS(SELECT 1 FROM moz_historyvisits v WHERE v.place_id = h.id)",
+    )?)
+}
+
+/// How many visits were made on this device, and how many came from other devices via sync,
+/// from `get_history_visit_count_by_status()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VisitCountByStatus {
+    pub local: i64,
+    pub remote: i64,
+}
+
+pub fn get_history_visit_count_by_status(db: &PlacesDb) -> Result<VisitCountByStatus> {
+    Ok(db.query_row_and_then_cachable(
+        "SELECT IFNULL(SUM(is_local), 0), IFNULL(SUM(NOT is_local), 0) FROM moz_historyvisits",
+        [],
+        |row| -> rusqlite::Result<_> {
+            Ok(VisitCountByStatus {
+                local: row.get(0)?,
+                remote: row.get(1)?,
+            })
+        },
+        true,
+    )?)
+}
+
 pub fn get_visit_page(
     db: &PlacesDb,
     offset: i64,
@@ -1452,6 +2028,39 @@ pub fn get_visit_page(
     Ok(infos)
 }
 
+/// Like `get_visit_page`, but only returns the visits made in `container_id`. Passing `None`
+/// returns only the visits which weren't made in any container.
+pub fn get_visit_page_in_container(
+    db: &PlacesDb,
+    offset: i64,
+    count: i64,
+    exclude_types: VisitTransitionSet,
+    container_id: Option<&str>,
+) -> Result<Vec<HistoryVisitInfo>> {
+    let allowed_types = exclude_types.complement();
+    let infos = db.query_rows_and_then_cached(
+        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden, h.preview_image_url,
+                v.is_local
+         FROM moz_places h
+         JOIN moz_historyvisits v
+           ON h.id = v.place_id
+         WHERE ((1 << v.visit_type) & :allowed_types) != 0 AND
+               v.container_id IS :container_id AND
+               NOT h.hidden
+         ORDER BY v.visit_date DESC, v.id
+         LIMIT :count
+         OFFSET :offset",
+        rusqlite::named_params! {
+            ":count": count,
+            ":offset": offset,
+            ":allowed_types": allowed_types,
+            ":container_id": container_id,
+        },
+        HistoryVisitInfo::from_row,
+    )?;
+    Ok(infos)
+}
+
 pub fn get_visit_page_with_bound(
     db: &PlacesDb,
     bound: i64,
@@ -1567,81 +2176,516 @@ mod tests {
             ),
         ];
 
-        for &(url, when, remote, _) in &to_add {
-            apply_observation(
-                &conn,
-                VisitObservation::new(Url::parse(url).unwrap())
-                    .with_at(Timestamp(when))
-                    .with_is_remote(remote)
-                    .with_visit_type(VisitType::Link),
-            )
-            .expect("Should apply visit");
-        }
+        for &(url, when, remote, _) in &to_add {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_at(Timestamp(when))
+                    .with_is_remote(remote)
+                    .with_visit_type(VisitType::Link),
+            )
+            .expect("Should apply visit");
+        }
+
+        let visited_all = get_visited_urls(
+            &conn,
+            Timestamp(now_u64 - 200_000),
+            Timestamp(now_u64 - 1000),
+            true,
+        )
+        .unwrap()
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+        let visited_local = get_visited_urls(
+            &conn,
+            Timestamp(now_u64 - 200_000),
+            Timestamp(now_u64 - 1000),
+            false,
+        )
+        .unwrap()
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+        for &(url, ts, is_remote, (expected_in_all, expected_in_local)) in &to_add {
+            // Make sure we format stuff the same way (in practice, just trailing slashes)
+            let url = Url::parse(url).unwrap().to_string();
+            assert_eq!(
+                expected_in_local,
+                visited_local.contains(&url),
+                "Failed in local for {:?}",
+                (url, ts, is_remote)
+            );
+            assert_eq!(
+                expected_in_all,
+                visited_all.contains(&url),
+                "Failed in all for {:?}",
+                (url, ts, is_remote)
+            );
+        }
+    }
+
+    fn get_custom_observed_page<F>(conn: &mut PlacesDb, url: &str, custom: F) -> Result<PageInfo>
+    where
+        F: Fn(VisitObservation) -> VisitObservation,
+    {
+        let u = Url::parse(url)?;
+        let obs = VisitObservation::new(u.clone()).with_visit_type(VisitType::Link);
+        apply_observation(conn, custom(obs))?;
+        Ok(fetch_page_info(conn, &u)?
+            .expect("should have the page")
+            .page)
+    }
+
+    fn get_observed_page(conn: &mut PlacesDb, url: &str) -> Result<PageInfo> {
+        get_custom_observed_page(conn, url, |o| o)
+    }
+
+    fn get_tombstone_count(conn: &PlacesDb) -> u32 {
+        let result: Result<Option<u32>> = conn.try_query_row(
+            "SELECT COUNT(*) from moz_places_tombstones;",
+            [],
+            |row| Ok(row.get::<_, u32>(0)?),
+            true,
+        );
+        result
+            .expect("should have worked")
+            .expect("should have got a value")
+    }
+
+    #[test]
+    fn test_hidden_visit_policy_vectors() {
+        // (visit type, is_redirect_source, hidden with Legacy, hidden with Desktop). The
+        // Desktop column matches `GetHiddenState()` in desktop's History.cpp.
+        let vectors = [
+            (VisitType::Link, None, false, false),
+            (VisitType::Link, Some(true), true, true),
+            (VisitType::Link, Some(false), true, false),
+            (VisitType::Typed, None, false, false),
+            (VisitType::Typed, Some(true), true, false),
+            (VisitType::Bookmark, Some(true), true, true),
+            (VisitType::FramedLink, None, true, true),
+            (VisitType::Embed, None, true, true),
+            (VisitType::RedirectPermanent, None, false, false),
+            (VisitType::RedirectTemporary, Some(true), true, true),
+            (VisitType::Reload, None, false, false),
+        ];
+        let url = Url::parse("https://www.example.com/").unwrap();
+        for (visit_type, is_redirect_source, legacy, desktop) in vectors {
+            let visit = VisitObservation::new(url.clone())
+                .with_visit_type(visit_type)
+                .with_is_redirect_source(is_redirect_source);
+            assert_eq!(
+                HiddenVisitPolicy::Legacy.is_hidden(&visit),
+                legacy,
+                "{:?} {:?}",
+                visit_type,
+                is_redirect_source
+            );
+            assert_eq!(
+                HiddenVisitPolicy::Desktop.is_hidden(&visit),
+                desktop,
+                "{:?} {:?}",
+                visit_type,
+                is_redirect_source
+            );
+        }
+        // Observations without a visit are never hidden.
+        let title_only = VisitObservation::new(url).with_is_redirect_source(true);
+        assert!(!HiddenVisitPolicy::Legacy.is_hidden(&title_only));
+        assert!(!HiddenVisitPolicy::Desktop.is_hidden(&title_only));
+    }
+
+    #[test]
+    fn test_set_hidden_visit_policy() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let is_hidden = |url: &Url| -> Result<bool> {
+            Ok(fetch_page_info(&conn, url)?
+                .expect("should exist")
+                .page
+                .hidden)
+        };
+        let typed_redirect = |url: &Url| {
+            apply_observation(
+                &conn,
+                VisitObservation::new(url.clone())
+                    .with_visit_type(VisitType::Typed)
+                    .with_is_redirect_source(true),
+            )
+        };
+        assert_eq!(get_hidden_visit_policy(&conn)?, HiddenVisitPolicy::Legacy);
+        let url1 = Url::parse("https://www.example.com/1").unwrap();
+        typed_redirect(&url1)?;
+        assert!(is_hidden(&url1)?);
+
+        set_hidden_visit_policy(&conn, HiddenVisitPolicy::Desktop)?;
+        assert_eq!(get_hidden_visit_policy(&conn)?, HiddenVisitPolicy::Desktop);
+        let url2 = Url::parse("https://www.example.com/2").unwrap();
+        typed_redirect(&url2)?;
+        assert!(!is_hidden(&url2)?);
+        // The policy also applies to batches.
+        let url3 = Url::parse("https://www.example.com/3").unwrap();
+        crate::storage::batch::apply_batch(
+            &conn,
+            vec![crate::storage::batch::BatchOperation::ApplyObservation(
+                Box::new(
+                    VisitObservation::new(url3.clone())
+                        .with_visit_type(VisitType::Link)
+                        .with_is_redirect_source(false),
+                ),
+            )],
+        )?;
+        assert!(!is_hidden(&url3)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reload_coalescing() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let now = Timestamp::now();
+        let reload = |secs_after: u64, title: &str| {
+            apply_observation(
+                &conn,
+                VisitObservation::new(url.clone())
+                    .with_visit_type(VisitType::Reload)
+                    .with_title(title.to_string())
+                    .with_at(now.checked_add(Duration::from_secs(secs_after))),
+            )
+        };
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone())
+                .with_visit_type(VisitType::Link)
+                .with_at(now),
+        )?;
+
+        // Without a window, every reload is a visit.
+        assert!(reload(1, "one")?.is_some());
+
+        set_reload_coalescing_window(&conn, Some(10))?;
+        assert!(reload(5, "two")?.is_none());
+        // The title is still updated.
+        let page = fetch_page_info(&conn, &url)?.expect("should exist").page;
+        assert_eq!(page.title, "two");
+        assert_eq!(
+            conn.query_one::<i64>("SELECT COUNT(*) FROM moz_historyvisits")?,
+            2
+        );
+        // A remote reload is always a visit.
+        assert!(apply_observation(
+            &conn,
+            VisitObservation::new(url.clone())
+                .with_visit_type(VisitType::Reload)
+                .with_at(now.checked_add(Duration::from_secs(6)))
+                .with_is_remote(true),
+        )?
+        .is_some());
+        // So is a reload after the window.
+        assert!(reload(20, "three")?.is_some());
+
+        set_reload_coalescing_window(&conn, None)?;
+        assert!(reload(21, "four")?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_synced_visit_dedup_window() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let now = Timestamp::now();
+        let at = |ms: i64| Timestamp((now.0 as i64 + ms) as u64);
+        let incoming = |ms: i64, transition: VisitType| HistoryRecordVisit {
+            date: at(ms).into(),
+            transition: transition as u8,
+            unknown_fields: UnknownFields::new(),
+        };
+        let apply = |url: &Url, visits: &[HistoryRecordVisit]| -> Result<Vec<(u64, VisitType)>> {
+            let page = fetch_page_info(&conn, url)?.expect("should exist").page;
+            apply_synced_visits(&conn, &page.guid, url, &None, visits, &UnknownFields::new())?;
+            conn.query_rows_and_then(
+                "SELECT visit_date, visit_type FROM moz_historyvisits
+                 WHERE place_id = :page_id
+                 ORDER BY visit_date",
+                &[(":page_id", &page.row_id)],
+                |row| -> Result<_> {
+                    Ok((
+                        row.get::<_, Timestamp>(0)?.0 - now.0,
+                        VisitType::from_primitive(row.get(1)?).unwrap(),
+                    ))
+                },
+            )
+        };
+        let visit = |url: &Url| {
+            apply_observation(
+                &conn,
+                VisitObservation::new(url.clone())
+                    .with_visit_type(VisitType::Link)
+                    .with_at(now),
+            )
+        };
+
+        // Without a window, only visits at exactly the same time are the same.
+        let url = Url::parse("https://www.example.com/1").unwrap();
+        visit(&url)?;
+        assert_eq!(
+            apply(
+                &url,
+                &[incoming(0, VisitType::Typed), incoming(1, VisitType::Link)]
+            )?,
+            vec![(0, VisitType::Link), (1, VisitType::Link)]
+        );
+
+        set_synced_visit_dedup_window(&conn, Some(1))?;
+        let url = Url::parse("https://www.example.com/2").unwrap();
+        visit(&url)?;
+        assert_eq!(
+            apply(
+                &url,
+                &[
+                    // Within the window of the local visit.
+                    incoming(-1, VisitType::Link),
+                    // Within the window, but with a different transition.
+                    incoming(1, VisitType::Typed),
+                    // Outside the window.
+                    incoming(2, VisitType::Link),
+                    // Within the window of the previous incoming visit.
+                    incoming(3, VisitType::Link),
+                ]
+            )?,
+            vec![
+                (0, VisitType::Link),
+                (1, VisitType::Typed),
+                (2, VisitType::Link)
+            ]
+        );
+
+        set_synced_visit_dedup_window(&conn, None)?;
+        assert_eq!(
+            apply(&url, &[incoming(3, VisitType::Link)])?,
+            vec![
+                (0, VisitType::Link),
+                (1, VisitType::Typed),
+                (2, VisitType::Link),
+                (3, VisitType::Link)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_origins() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let now = Timestamp::now();
+        let visit = |url: &str, ms_ago: u64, title: Option<&str>| -> Result<()> {
+            let mut ob = VisitObservation::new(Url::parse(url)?)
+                .with_visit_type(VisitType::Link)
+                .with_at(now.checked_sub(Duration::from_millis(ms_ago)));
+            if let Some(title) = title {
+                ob = ob.with_title(title.to_string());
+            }
+            apply_observation(&conn, ob)?;
+            Ok(())
+        };
+        visit("http://example.com/a", 3000, Some("A"))?;
+        visit("http://example.com/a", 2000, None)?;
+        visit("https://example.com/a", 1000, None)?;
+        visit("http://example.com/b", 1000, None)?;
+        insert_bookmark(
+            &conn,
+            crate::storage::bookmarks::InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: crate::storage::bookmarks::BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse("http://example.com/b")?,
+                title: None,
+            }
+            .into(),
+        )?;
+        visit("http://other.example.com/c", 1000, None)?;
 
-        let visited_all = get_visited_urls(
+        merge_origins(
             &conn,
-            Timestamp(now_u64 - 200_000),
-            Timestamp(now_u64 - 1000),
-            true,
-        )
-        .unwrap()
-        .into_iter()
-        .collect::<HashSet<_>>();
+            &Url::parse("http://example.com/")?,
+            &Url::parse("https://example.com")?,
+        )?;
 
-        let visited_local = get_visited_urls(
+        let page = |url: &str| -> Result<Option<PageInfo>> {
+            Ok(fetch_page_info(&conn, &Url::parse(url)?)?.map(|info| info.page))
+        };
+        let a = page("https://example.com/a")?.expect("should exist");
+        assert_eq!(a.visit_count_local, 3);
+        assert_eq!(a.title, "A");
+        assert!(a.frecency > 0);
+        assert_eq!(page("https://example.com/b")?.unwrap().visit_count_local, 1);
+        assert!(page("http://example.com/a")?.is_none());
+        // Bookmarked pages are kept, but without their visits.
+        let b = page("http://example.com/b")?.expect("should exist");
+        assert_eq!(b.visit_count_local, 0);
+        // ...which are tombstoned, so they don't come back when we sync.
+        assert_eq!(
+            conn.query_one::<i64>(&format!(
+                "SELECT COUNT(*) FROM moz_historyvisit_tombstones WHERE place_id = {}",
+                b.row_id.0
+            ))?,
+            1
+        );
+        // Other origins aren't touched.
+        assert_eq!(
+            page("http://other.example.com/c")?
+                .unwrap()
+                .visit_count_local,
+            1
+        );
+        // The origin's frecency includes the moved pages.
+        assert_eq!(
+            conn.query_one::<i32>(
+                "SELECT frecency FROM moz_origins
+                 WHERE prefix = 'https://' AND host = 'example.com'"
+            )?,
+            a.frecency + page("https://example.com/b")?.unwrap().frecency
+        );
+
+        // New visits to the old origin are recorded on the new one.
+        visit("http://example.com/d?q=1#top", 0, None)?;
+        assert!(page("http://example.com/d?q=1#top")?.is_none());
+        assert_eq!(
+            page("https://example.com/d?q=1#top")?
+                .unwrap()
+                .visit_count_local,
+            1
+        );
+
+        // Merges are followed through.
+        merge_origins(
             &conn,
-            Timestamp(now_u64 - 200_000),
-            Timestamp(now_u64 - 1000),
-            false,
+            &Url::parse("https://example.com")?,
+            &Url::parse("https://www.example.com")?,
+        )?;
+        visit("http://example.com/e", 0, None)?;
+        assert!(page("https://www.example.com/e")?.is_some());
+        assert_eq!(
+            page("https://www.example.com/a")?
+                .unwrap()
+                .visit_count_local,
+            3
+        );
+
+        // Undoing a merge stops redirecting visits, but leaves the history where it is.
+        assert!(unmerge_origin(&conn, &Url::parse("http://example.com/a")?)?);
+        assert!(!unmerge_origin(&conn, &Url::parse("http://example.com/")?)?);
+        visit("http://example.com/f", 0, None)?;
+        assert!(page("http://example.com/f")?.is_some());
+        assert!(page("https://www.example.com/a")?.is_some());
+
+        // Merges are forgotten along with the history they point to.
+        let merges = || conn.query_one::<i64>("SELECT COUNT(*) FROM moz_origin_merges");
+        assert_eq!(merges()?, 1);
+        delete_visits_between(&conn, Timestamp(0), Timestamp::now())?;
+        assert_eq!(merges()?, 0);
+        merge_origins(
+            &conn,
+            &Url::parse("http://example.com/")?,
+            &Url::parse("https://example.com")?,
+        )?;
+        assert_eq!(merges()?, 1);
+        delete_everything(&conn)?;
+        assert_eq!(merges()?, 0);
+
+        assert!(merge_origins(
+            &conn,
+            &Url::parse("https://example.com/")?,
+            &Url::parse("https://example.com/x")?,
         )
-        .unwrap()
-        .into_iter()
-        .collect::<HashSet<_>>();
+        .is_err());
+        assert!(merge_origins(
+            &conn,
+            &Url::parse("file:///tmp")?,
+            &Url::parse("https://example.com")?,
+        )
+        .is_err());
+        Ok(())
+    }
 
-        for &(url, ts, is_remote, (expected_in_all, expected_in_local)) in &to_add {
-            // Make sure we format stuff the same way (in practice, just trailing slashes)
-            let url = Url::parse(url).unwrap().to_string();
-            assert_eq!(
-                expected_in_local,
-                visited_local.contains(&url),
-                "Failed in local for {:?}",
-                (url, ts, is_remote)
-            );
-            assert_eq!(
-                expected_in_all,
-                visited_all.contains(&url),
-                "Failed in all for {:?}",
-                (url, ts, is_remote)
-            );
+    #[test]
+    fn test_get_origin_stats() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let now = Timestamp::now();
+        for (url, at) in [
+            ("https://example.com/a", 3000),
+            ("https://example.com/a", 2000),
+            ("https://example.com/b", 1000),
+            ("https://www.mozilla.org/", 0),
+        ] {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_visit_type(VisitType::Link)
+                    .with_at(Timestamp(now.0 - at)),
+            )?;
         }
-    }
 
-    fn get_custom_observed_page<F>(conn: &mut PlacesDb, url: &str, custom: F) -> Result<PageInfo>
-    where
-        F: Fn(VisitObservation) -> VisitObservation,
-    {
-        let u = Url::parse(url)?;
-        let obs = VisitObservation::new(u.clone()).with_visit_type(VisitType::Link);
-        apply_observation(conn, custom(obs))?;
-        Ok(fetch_page_info(conn, &u)?
-            .expect("should have the page")
-            .page)
+        let stats = get_origin_stats(&conn, 10, OriginStatsOrder::VisitCount)?;
+        assert_eq!(
+            stats
+                .iter()
+                .map(|s| (s.origin.as_str(), s.page_count, s.visit_count))
+                .collect::<Vec<_>>(),
+            vec![
+                ("https://example.com", 2, 3),
+                ("https://www.mozilla.org", 1, 1)
+            ]
+        );
+        assert_eq!(stats[0].host, "example.com");
+        assert_eq!(stats[0].last_visit_date, Timestamp(now.0 - 1000));
+        assert!(stats[0].frecency > 0);
+        assert!(stats[0].estimated_size > stats[1].estimated_size);
+
+        let stats = get_origin_stats(&conn, 1, OriginStatsOrder::LastVisit)?;
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].origin, "https://www.mozilla.org");
+        assert_eq!(stats[0].last_visit_date, now);
+        Ok(())
     }
 
-    fn get_observed_page(conn: &mut PlacesDb, url: &str) -> Result<PageInfo> {
-        get_custom_observed_page(conn, url, |o| o)
-    }
+    #[test]
+    fn test_history_counts() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        assert_eq!(get_history_page_count(&conn)?, 0);
+        assert_eq!(
+            get_history_visit_count_by_status(&conn)?,
+            VisitCountByStatus::default()
+        );
 
-    fn get_tombstone_count(conn: &PlacesDb) -> u32 {
-        let result: Result<Option<u32>> = conn.try_query_row(
-            "SELECT COUNT(*) from moz_places_tombstones;",
+        for (url, is_remote) in [
+            ("https://example.com/a", false),
+            ("https://example.com/a", true),
+            ("https://example.com/b", false),
+            ("https://www.mozilla.org/", true),
+        ] {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_visit_type(VisitType::Link)
+                    .with_is_remote(is_remote),
+            )?;
+        }
+        // A page which is only bookmarked isn't in history.
+        conn.execute(
+            "INSERT INTO moz_places (guid, url, url_hash) VALUES ('fake_guid___', 'https://bookmarked.example/', hash('https://bookmarked.example/'))",
             [],
-            |row| Ok(row.get::<_, u32>(0)?),
-            true,
+        )?;
+
+        assert_eq!(get_history_page_count(&conn)?, 3);
+        assert_eq!(
+            get_history_visit_count_by_status(&conn)?,
+            VisitCountByStatus {
+                local: 2,
+                remote: 2
+            }
         );
-        result
-            .expect("should have worked")
-            .expect("should have got a value")
+        Ok(())
     }
 
     #[test]
@@ -1733,6 +2777,69 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_get_visited_for_origin_prefix() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        for url in [
+            "https://www.example.com/",
+            "https://www.example.com/docs/",
+            "https://www.example.com/docs/a",
+            "https://www.example.com/docs/b?q=1",
+            "https://www.example.com/documents",
+            "https://www.example.com/other",
+            "http://www.example.com/docs/c",
+            "https://example.com/docs/d",
+            "https://www.example.com:8080/docs/e",
+        ] {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url)?).with_visit_type(VisitType::Link),
+            )?;
+        }
+        // Bookmarked but never visited.
+        insert_bookmark(
+            &conn,
+            crate::storage::bookmarks::InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: crate::storage::bookmarks::BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse("https://www.example.com/docs/unvisited")?,
+                title: None,
+            }
+            .into(),
+        )?;
+
+        let visited = |prefix: &str| -> Result<Vec<String>> {
+            Ok(get_visited_for_origin_prefix(&conn, &Url::parse(prefix)?)?
+                .into_iter()
+                .map(String::from)
+                .collect())
+        };
+        assert_eq!(
+            visited("https://www.example.com/docs/")?,
+            [
+                "https://www.example.com/docs/",
+                "https://www.example.com/docs/a",
+                "https://www.example.com/docs/b?q=1",
+            ]
+        );
+        assert_eq!(
+            visited("https://www.example.com/doc")?,
+            [
+                "https://www.example.com/docs/",
+                "https://www.example.com/docs/a",
+                "https://www.example.com/docs/b?q=1",
+                "https://www.example.com/documents",
+            ]
+        );
+        assert_eq!(visited("https://www.example.com")?.len(), 6);
+        assert_eq!(visited("https://www.example.com:8080/")?.len(), 1);
+        assert!(visited("https://www.mozilla.org/")?.is_empty());
+        Ok(())
+    }
+
     #[test]
     fn test_get_visited() -> Result<()> {
         let _ = env_logger::try_init();
@@ -2033,7 +3140,7 @@ mod tests {
             ],
         )?;
 
-        let outgoing = fetch_outgoing(&conn, 2, 3)?;
+        let outgoing = fetch_outgoing(&conn, 2, 3, None)?;
         assert_eq!(outgoing.len(), 2, "should have restricted to the limit");
         // want pi or pi2 (but order is indeterminate) and this seems simpler than sorting.
         assert!(outgoing[0].envelope.id != outgoing[1].envelope.id);
@@ -2348,11 +3455,11 @@ mod tests {
         assert_eq!(pi.sync_change_counter, 0);
         assert_eq!(pi.sync_status, SyncStatus::New);
         // Ensure we are going to do a full re-upload after a reset.
-        let outgoing = fetch_outgoing(&conn, 100, 100)?;
+        let outgoing = fetch_outgoing(&conn, 100, 100, None)?;
         assert_eq!(outgoing.len(), 1);
 
         mark_all_as_synced(&conn)?;
-        assert!(fetch_outgoing(&conn, 100, 100)?.is_empty());
+        assert!(fetch_outgoing(&conn, 100, 100, None)?.is_empty());
         // ...
 
         // Now simulate a reset on disconnect, and verify we've removed all Sync
@@ -2709,6 +3816,140 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_update_frecencies_matches_calculate_frecency() -> Result<()> {
+        use crate::storage::bookmarks::InsertableBookmark;
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let now = Timestamp::now();
+        let days_ago = |days: u64| Timestamp(now.0 - days * 86_400_000 - 1000);
+        let visit = |url: &str, visit_type: VisitType, at: Timestamp| -> Result<()> {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url)?)
+                    .with_visit_type(visit_type)
+                    .with_at(at),
+            )?;
+            Ok(())
+        };
+        let bookmark = |url: &str| -> Result<()> {
+            insert_bookmark(
+                &conn,
+                InsertableBookmark {
+                    parent_guid: BookmarkRootGuid::Unfiled.into(),
+                    position: crate::storage::bookmarks::BookmarkPosition::Append,
+                    date_added: None,
+                    last_modified: None,
+                    guid: None,
+                    url: Url::parse(url)?,
+                    title: None,
+                }
+                .into(),
+            )?;
+            Ok(())
+        };
+
+        // Visits in each age bucket.
+        for (i, days) in [0, 7, 20, 60, 200].into_iter().enumerate() {
+            let visit_type = if i % 2 == 0 {
+                VisitType::Link
+            } else {
+                VisitType::Typed
+            };
+            visit("https://example.com/buckets", visit_type, days_ago(days))?;
+        }
+        // More visits than we sample.
+        for days in 0..15 {
+            visit("https://example.com/many", VisitType::Typed, days_ago(days))?;
+        }
+        // Visits with no bonus, one of which doesn't count towards the visit count.
+        visit("https://example.com/embed", VisitType::Embed, days_ago(1))?;
+        visit(
+            "https://example.com/redirected",
+            VisitType::RedirectPermanent,
+            days_ago(1),
+        )?;
+        // A visited bookmark.
+        visit(
+            "https://example.com/bookmarked",
+            VisitType::Link,
+            days_ago(3),
+        )?;
+        bookmark("https://example.com/bookmarked")?;
+        // Unvisited bookmarks, one of which was typed.
+        bookmark("https://example.com/unvisited")?;
+        bookmark("place:folder=BOOKMARKS_MENU")?;
+        visit("https://example.com/typed", VisitType::Typed, days_ago(1))?;
+        bookmark("https://example.com/typed")?;
+        conn.execute(
+            "DELETE FROM moz_historyvisits WHERE place_id =
+                 (SELECT id FROM moz_places WHERE url = 'https://example.com/typed')",
+            [],
+        )?;
+        // A typed visit to a page which temporarily redirected, and a link to one which
+        // permanently redirected.
+        for (source, target, visit_type, redirect_type) in [
+            (
+                "https://example.com/a",
+                "https://example.com/b",
+                VisitType::Typed,
+                VisitType::RedirectTemporary,
+            ),
+            (
+                "https://example.com/c",
+                "https://example.com/d",
+                VisitType::Link,
+                VisitType::RedirectPermanent,
+            ),
+        ] {
+            visit(source, visit_type, days_ago(2))?;
+            visit(target, VisitType::Link, days_ago(10))?;
+            conn.execute(
+                "INSERT INTO moz_historyvisits(from_visit, place_id, visit_date, visit_type, is_local)
+                 SELECT v.id, p.id, :date, :visit_type, 1
+                 FROM moz_historyvisits v, moz_places p
+                 WHERE v.place_id = (SELECT id FROM moz_places WHERE url = :source)
+                   AND p.url = :target",
+                rusqlite::named_params! {
+                    ":date": days_ago(2).0 + 1,
+                    ":visit_type": redirect_type,
+                    ":source": source,
+                    ":target": target,
+                },
+            )?;
+        }
+
+        let ids: Vec<RowId> =
+            conn.query_rows_and_then("SELECT id FROM moz_places ORDER BY id", [], |row| {
+                row.get(0)
+            })?;
+        let mut expected = Vec::new();
+        for id in &ids {
+            expected.push((
+                *id,
+                frecency::calculate_frecency(
+                    &conn,
+                    &frecency::DEFAULT_FRECENCY_SETTINGS,
+                    id.0,
+                    None,
+                )?,
+            ));
+        }
+        conn.execute("UPDATE moz_places SET frecency = -2", [])?;
+        frecency::update_frecencies(&conn, &frecency::DEFAULT_FRECENCY_SETTINGS, &ids)?;
+        let actual: Vec<(RowId, i32)> = conn.query_rows_and_then(
+            "SELECT id, frecency FROM moz_places ORDER BY id",
+            [],
+            |row| -> rusqlite::Result<_> { Ok((row.get(0)?, row.get(1)?)) },
+        )?;
+        assert_eq!(actual, expected);
+        // Make sure we covered all the cases.
+        for frecency in [-1, 0] {
+            assert!(expected.iter().any(|(_, f)| *f == frecency));
+        }
+        assert!(expected.iter().filter(|(_, f)| *f > 0).count() >= 8);
+        Ok(())
+    }
+
     #[test]
     fn test_delete_everything() {
         use crate::storage::bookmarks::{
@@ -3042,6 +4283,84 @@ mod tests {
         assert!(title.starts_with(&db_title));
     }
 
+    #[test]
+    fn test_get_visit_infos_after() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("no memory db");
+        let now: Timestamp = SystemTime::now().into();
+        let now_u64 = now.0;
+        // Several visits share a date, so pages need to split them by id.
+        let to_add = [
+            ("https://www.example.com/0", now_u64 - 300, VisitType::Link),
+            ("https://www.example.com/1", now_u64 - 200, VisitType::Link),
+            ("https://www.example.com/2", now_u64 - 200, VisitType::Link),
+            (
+                "https://www.example.com/3",
+                now_u64 - 200,
+                VisitType::Download,
+            ),
+            ("https://www.example.com/4", now_u64 - 200, VisitType::Link),
+            ("https://www.example.com/5", now_u64 - 100, VisitType::Link),
+            ("https://www.example.com/6", now_u64, VisitType::Link),
+        ];
+        for &(url, when, visit_type) in &to_add {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_at(Timestamp(when))
+                    .with_visit_type(visit_type),
+            )
+            .expect("Should apply visit");
+        }
+
+        let exclude_types = VisitTransitionSet::for_specific(&[VisitType::Download]);
+        let expected = get_visit_infos(
+            &conn,
+            Timestamp(now_u64 - 300),
+            Timestamp(now_u64 - 100),
+            exclude_types,
+        )
+        .unwrap();
+        assert_eq!(expected.len(), 5);
+
+        let mut paged = Vec::new();
+        let mut after = None;
+        loop {
+            let (infos, position) = get_visit_infos_after(
+                &conn,
+                Timestamp(now_u64 - 300),
+                Timestamp(now_u64 - 100),
+                after,
+                2,
+                exclude_types,
+            )
+            .unwrap();
+            assert!(infos.len() <= 2);
+            if infos.is_empty() {
+                assert_eq!(position, None);
+                break;
+            }
+            paged.extend(infos);
+            after = position;
+        }
+        let urls = |infos: &[HistoryVisitInfo]| {
+            infos
+                .iter()
+                .map(|info| info.url.as_str().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(urls(&paged), urls(&expected));
+        assert_eq!(
+            urls(&paged),
+            [
+                "https://www.example.com/0",
+                "https://www.example.com/1",
+                "https://www.example.com/2",
+                "https://www.example.com/4",
+                "https://www.example.com/5",
+            ]
+        );
+    }
+
     #[test]
     fn test_get_visit_page_with_bound() {
         use std::time::SystemTime;
@@ -3415,4 +4734,49 @@ mod tests {
                 .collect::<HashSet<_>>()
         );
     }
+
+    #[test]
+    fn test_visits_in_container() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("no memory db");
+        let now = Timestamp::now();
+        for (url, at, container_id) in [
+            ("https://www.example.com/default", 3, None),
+            ("https://www.example.com/work", 2, Some("work")),
+            ("https://www.example.com/private", 1, Some("private")),
+        ] {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_visit_type(VisitType::Link)
+                    .with_at(Timestamp(now.0 - at))
+                    .with_container_id(container_id.map(str::to_string)),
+            )
+            .unwrap();
+        }
+
+        let urls = |infos: Vec<HistoryVisitInfo>| {
+            infos
+                .into_iter()
+                .map(|info| info.url.path().to_string())
+                .collect::<Vec<_>>()
+        };
+        let all = get_visit_infos(&conn, Timestamp(0), now, VisitTransitionSet::empty()).unwrap();
+        assert_eq!(urls(all), ["/default", "/work", "/private"]);
+        let work = get_visit_infos_in_container(
+            &conn,
+            Timestamp(0),
+            now,
+            VisitTransitionSet::empty(),
+            Some("work"),
+        )
+        .unwrap();
+        assert_eq!(urls(work), ["/work"]);
+        let default =
+            get_visit_page_in_container(&conn, 0, 10, VisitTransitionSet::empty(), None).unwrap();
+        assert_eq!(urls(default), ["/default"]);
+        let private =
+            get_visit_page_in_container(&conn, 0, 10, VisitTransitionSet::empty(), Some("private"))
+                .unwrap();
+        assert_eq!(urls(private), ["/private"]);
+    }
 }
@@ -73,8 +73,8 @@ impl DbAction {
             db.execute_cached(
                 &format!(
                     "
-                    INSERT OR IGNORE INTO moz_historyvisit_tombstones(place_id, visit_date)
-                    SELECT place_id, visit_date
+                    INSERT OR IGNORE INTO moz_historyvisit_tombstones(place_id, visit_date, deleted_at)
+                    SELECT place_id, visit_date, now()
                     FROM moz_historyvisits
                     WHERE id IN ({})
                     ",
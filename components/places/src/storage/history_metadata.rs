@@ -4,6 +4,7 @@
 
 use crate::db::{PlacesDb, PlacesTransaction};
 use crate::error::*;
+use crate::match_impl::fold_for_search;
 use crate::RowId;
 use error_support::{breadcrumb, redact_url};
 use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
@@ -70,6 +71,51 @@ impl HistoryHighlight {
     }
 }
 
+/// The view time spent on an origin's pages, from `get_total_view_time_by_origin`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OriginViewTime {
+    /// The origin, such as `https://example.com`.
+    pub origin: String,
+    pub total_view_time: i64,
+    /// How many of the origin's pages were viewed.
+    pub page_count: i64,
+}
+
+impl OriginViewTime {
+    pub(crate) fn from_row(row: &rusqlite::Row<'_>) -> Result<Self> {
+        Ok(Self {
+            origin: row.get("origin")?,
+            total_view_time: row.get("total_view_time")?,
+            page_count: row.get("page_count")?,
+        })
+    }
+}
+
+/// A page and the view time spent on it, from `get_most_engaged_pages`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EngagedPage {
+    pub url: String,
+    pub title: Option<String>,
+    pub preview_image_url: Option<String>,
+    pub total_view_time: i64,
+    /// How many metadata entries were recorded for the page. Observations
+    /// made in quick succession are debounced into a single entry, so this
+    /// roughly counts distinct visits.
+    pub view_count: i64,
+}
+
+impl EngagedPage {
+    pub(crate) fn from_row(row: &rusqlite::Row<'_>) -> Result<Self> {
+        Ok(Self {
+            url: row.get("url")?,
+            title: row.get("title")?,
+            preview_image_url: row.get("preview_image_url")?,
+            total_view_time: row.get("total_view_time")?,
+            view_count: row.get("view_count")?,
+        })
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct HistoryMetadataObservation {
     pub url: String,
@@ -391,6 +437,58 @@ ON p.id = ranked.place_id
 ORDER BY ranked.score DESC
 LIMIT :limit";
 
+// Both of these only count metadata updated in the requested time window, so
+// a page which was viewed both before and during the window only has the view
+// time from the entries updated during it.
+const VIEW_TIME_BY_ORIGIN_QUERY: &str = "
+SELECT
+    o.prefix || o.host AS origin, SUM(m.total_view_time) AS total_view_time,
+    COUNT(DISTINCT m.place_id) AS page_count
+FROM moz_places_metadata m
+JOIN moz_places p ON p.id = m.place_id
+JOIN moz_origins o ON o.id = p.origin_id
+WHERE m.updated_at BETWEEN :start AND :end AND m.total_view_time > 0
+GROUP BY o.id
+ORDER BY total_view_time DESC, origin
+LIMIT :limit";
+
+const MOST_ENGAGED_PAGES_QUERY: &str = "
+SELECT
+    p.url AS url, p.title AS title, p.preview_image_url AS preview_image_url,
+    SUM(m.total_view_time) AS total_view_time, COUNT(*) AS view_count
+FROM moz_places_metadata m
+JOIN moz_places p ON p.id = m.place_id
+WHERE m.updated_at BETWEEN :start AND :end AND m.total_view_time > 0
+GROUP BY m.place_id
+ORDER BY total_view_time DESC, url
+LIMIT :limit";
+
+// Search terms come from two places: the terms recorded with metadata
+// observations, and what was typed into the awesomebar before accepting a
+// result. The latter are in `moz_inputhistory`, which doesn't record when an
+// input was used, so we use the last visit to the accepted page instead. Both
+// are ranked together by how often they were used, decaying by the number of
+// days since they were last used.
+const SEARCH_QUERY_SUGGESTIONS_QUERY: &str = "
+WITH candidates(term, used_at, uses) AS (
+    SELECT s.term, MAX(m.updated_at), COUNT(*)
+    FROM moz_places_metadata_search_queries s
+    JOIN moz_places_metadata m ON m.search_query_id = s.id
+    WHERE substr(s.term, 1, length(:prefix)) = :prefix
+    GROUP BY s.id
+    UNION ALL
+    SELECT normalize_search_term(i.input), MAX(p.last_visit_date_local, p.last_visit_date_remote), i.use_count
+    FROM moz_inputhistory i
+    JOIN moz_places p ON p.id = i.place_id
+    WHERE substr(normalize_search_term(i.input), 1, length(:prefix)) = :prefix
+)
+SELECT term
+FROM candidates
+WHERE term <> ''
+GROUP BY term
+ORDER BY SUM(uses) / (1.0 + MAX(0, :now - MAX(used_at)) / 86400000.0) DESC, MAX(used_at) DESC, term
+LIMIT :limit";
+
 lazy_static! {
     static ref GET_LATEST_SQL: String = format!(
         "{common_select_sql}
@@ -419,8 +517,8 @@ lazy_static! {
         "{common_select_sql}
         WHERE
             p.url LIKE :query OR
-            p.title LIKE :query OR
-            search_term LIKE :query
+            fold_for_search(p.title) LIKE :query OR
+            fold_for_search(search_term) LIKE :query
         ORDER BY total_view_time DESC
         LIMIT :limit",
         common_select_sql = COMMON_METADATA_SELECT
@@ -474,17 +572,77 @@ pub fn get_highlights(
     )
 }
 
+/// Returns the origins with the most view time recorded between `start` and
+/// `end`, most viewed first.
+pub fn get_total_view_time_by_origin(
+    db: &PlacesDb,
+    start: i64,
+    end: i64,
+    limit: i32,
+) -> Result<Vec<OriginViewTime>> {
+    db.query_rows_and_then_cached(
+        VIEW_TIME_BY_ORIGIN_QUERY,
+        rusqlite::named_params! {
+            ":start": start,
+            ":end": end,
+            ":limit": limit
+        },
+        OriginViewTime::from_row,
+    )
+}
+
+/// Returns the pages with the most view time recorded between `start` and
+/// `end`, most viewed first.
+pub fn get_most_engaged_pages(
+    db: &PlacesDb,
+    start: i64,
+    end: i64,
+    limit: i32,
+) -> Result<Vec<EngagedPage>> {
+    db.query_rows_and_then_cached(
+        MOST_ENGAGED_PAGES_QUERY,
+        rusqlite::named_params! {
+            ":start": start,
+            ":end": end,
+            ":limit": limit
+        },
+        EngagedPage::from_row,
+    )
+}
+
 pub fn query(db: &PlacesDb, query: &str, limit: i32) -> Result<Vec<HistoryMetadata>> {
     db.query_rows_and_then_cached(
         QUERY_SQL.as_str(),
         rusqlite::named_params! {
-            ":query": format!("%{}%", query),
+            ":query": format!("%{}%", fold_for_search(query)),
             ":limit": limit
         },
         HistoryMetadata::from_row,
     )
 }
 
+/// Returns previously-issued search terms starting with `prefix`, for the
+/// awesomebar's "recent searches" row. Terms used more often, and more
+/// recently, come first.
+pub fn suggest_search_queries(db: &PlacesDb, prefix: &str, limit: i32) -> Result<Vec<String>> {
+    db.query_rows_and_then_cached(
+        SEARCH_QUERY_SUGGESTIONS_QUERY,
+        rusqlite::named_params! {
+            ":prefix": normalize_search_term(prefix),
+            ":now": Timestamp::now(),
+            ":limit": limit
+        },
+        |row| -> Result<String> { Ok(row.get(0)?) },
+    )
+}
+
+/// Trims and lower-cases a search term. This is also the `normalize_search_term()`
+/// SQL function, so that inputs are folded the same way as the prefix; SQLite's
+/// `lower()` only handles ASCII.
+pub(crate) fn normalize_search_term(term: &str) -> String {
+    term.trim().to_lowercase()
+}
+
 pub fn delete_older_than(db: &PlacesDb, older_than: i64) -> Result<()> {
     db.execute_cached(
         "DELETE FROM moz_places_metadata
@@ -1399,6 +1557,107 @@ mod tests {
         assert_eq!(highlights[0].score, 0.0);
     }
 
+    #[test]
+    fn test_view_time_aggregation() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("memory db");
+
+        assert!(get_total_view_time_by_origin(&conn, 0, i64::MAX, 10)
+            .unwrap()
+            .is_empty());
+        assert!(get_most_engaged_pages(&conn, 0, i64::MAX, 10)
+            .unwrap()
+            .is_empty());
+
+        let beginning = Timestamp::now().as_millis() as i64;
+        note_observation!(&conn,
+            url "https://mozilla.com/1",
+            view_time Some(1000),
+            search_term None,
+            document_type Some(DocumentType::Regular),
+            referrer_url None,
+            title Some("One")
+        );
+        // A different key, so it isn't debounced into the first entry.
+        note_observation!(&conn,
+            url "https://mozilla.com/1",
+            view_time Some(500),
+            search_term Some("mozilla"),
+            document_type Some(DocumentType::Regular),
+            referrer_url None,
+            title None
+        );
+        note_observation!(&conn,
+            url "https://mozilla.com/2",
+            view_time Some(2000),
+            search_term None,
+            document_type Some(DocumentType::Regular),
+            referrer_url None,
+            title None
+        );
+        note_observation!(&conn,
+            url "https://example.com/",
+            view_time Some(3000),
+            search_term None,
+            document_type Some(DocumentType::Regular),
+            referrer_url None,
+            title None
+        );
+        // Pages without any view time are ignored.
+        note_observation!(&conn,
+            url "https://example.org/",
+            view_time Some(0),
+            search_term None,
+            document_type Some(DocumentType::Regular),
+            referrer_url None,
+            title None
+        );
+        let end = Timestamp::now().as_millis() as i64;
+
+        assert_eq!(
+            get_total_view_time_by_origin(&conn, beginning, end, 10).unwrap(),
+            vec![
+                OriginViewTime {
+                    origin: "https://mozilla.com".to_string(),
+                    total_view_time: 3500,
+                    page_count: 2,
+                },
+                OriginViewTime {
+                    origin: "https://example.com".to_string(),
+                    total_view_time: 3000,
+                    page_count: 1,
+                },
+            ]
+        );
+        assert_eq!(
+            get_total_view_time_by_origin(&conn, beginning, end, 1)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let pages = get_most_engaged_pages(&conn, beginning, end, 10).unwrap();
+        assert_eq!(
+            pages
+                .iter()
+                .map(|p| (p.url.as_str(), p.total_view_time, p.view_count))
+                .collect::<Vec<_>>(),
+            vec![
+                ("https://example.com/", 3000, 1),
+                ("https://mozilla.com/2", 2000, 1),
+                ("https://mozilla.com/1", 1500, 2),
+            ]
+        );
+        assert_eq!(pages[2].title.as_deref(), Some("One"));
+
+        // Nothing was recorded outside the window.
+        assert!(get_total_view_time_by_origin(&conn, 0, beginning - 1, 10)
+            .unwrap()
+            .is_empty());
+        assert!(get_most_engaged_pages(&conn, end + 1, end + 2, 10)
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_query() {
         let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("memory db");
@@ -1466,6 +1725,10 @@ mod tests {
             preview_image_url Some("https://i.cbc.ca/1.5993583.1618861792!/cpImage/httpImage/image.jpg_gen/derivatives/16x9_620/fedbudget-20210419.jpg")
         );
 
+        // query by title, ignoring accents
+        let meta = query(&conn, "chîld cAre", 10).expect("query should work");
+        assert_eq!(1, meta.len(), "expected exactly one result");
+
         // query by search term
         let meta = query(&conn, "string format", 10).expect("query should work");
         assert_eq!(1, meta.len(), "expected exactly one result");
@@ -1504,6 +1767,79 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_suggest_search_queries() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("memory db");
+
+        for (url, term) in [
+            ("https://www.rust-lang.org/", "Rust"),
+            ("https://doc.rust-lang.org/std/", "rust std"),
+            ("https://doc.rust-lang.org/book/", "rust std"),
+            ("https://www.sqlite.org/", "sqlite"),
+        ] {
+            note_observation!(
+                &conn,
+                url url,
+                view_time None,
+                search_term Some(term),
+                document_type None,
+                referrer_url None,
+                title None
+            );
+        }
+
+        // A term used more often comes first.
+        assert_eq!(
+            suggest_search_queries(&conn, "RUST", 10).unwrap(),
+            vec!["rust std", "rust"]
+        );
+        assert_eq!(
+            suggest_search_queries(&conn, "rust", 1).unwrap(),
+            vec!["rust std"]
+        );
+        assert!(suggest_search_queries(&conn, "python", 10)
+            .unwrap()
+            .is_empty());
+
+        // ...unless it hasn't been used for a while.
+        conn.execute(
+            "UPDATE moz_places_metadata SET updated_at = updated_at - 10 * 86400000
+             WHERE search_query_id = (
+                SELECT id FROM moz_places_metadata_search_queries WHERE term = 'rust std'
+             )",
+            [],
+        )
+        .unwrap();
+        assert_eq!(
+            suggest_search_queries(&conn, "rust", 10).unwrap(),
+            vec!["rust", "rust std"]
+        );
+
+        // What was typed before accepting a result is a search term too, and
+        // is combined with the same term from metadata.
+        let url = Url::parse("https://rustup.rs/").unwrap();
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone())
+                .with_at(Timestamp::now())
+                .with_visit_type(VisitType::Typed),
+        )
+        .unwrap();
+        crate::api::matcher::accept_result(&conn, "rust ", &url).unwrap();
+        crate::api::matcher::accept_result(&conn, "rustup", &url).unwrap();
+        assert_eq!(
+            suggest_search_queries(&conn, "rust", 10).unwrap(),
+            vec!["rust", "rustup", "rust std"]
+        );
+
+        // Inputs are lower-cased the same way as the prefix, not just for ASCII.
+        crate::api::matcher::accept_result(&conn, "ÜBER RUST", &url).unwrap();
+        assert_eq!(
+            suggest_search_queries(&conn, "Über", 10).unwrap(),
+            vec!["über rust"]
+        );
+    }
+
     #[test]
     fn test_delete_metadata() {
         let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("memory db");
@@ -5,9 +5,11 @@
 // A "storage" module - this module is intended to be the layer between the
 // API and the database.
 
+pub mod batch;
 pub mod bookmarks;
 pub mod history;
 pub mod history_metadata;
+pub mod recently_closed;
 pub mod tags;
 
 use crate::db::PlacesDb;
@@ -33,6 +35,55 @@ pub const TITLE_LENGTH_MAX: usize = 4096;
 pub const TAG_LENGTH_MAX: usize = 100;
 // pub const DESCRIPTION_LENGTH_MAX: usize = 256;
 
+/// The longest URLs and titles that a [`PlacesApi`](crate::PlacesApi) stores, which default to
+/// [`URL_LENGTH_MAX`] and [`TITLE_LENGTH_MAX`].
+///
+/// Longer URLs are rejected, and longer titles truncated, when they're written. The limits
+/// don't apply retroactively: lowering them leaves longer URLs and titles that are already in
+/// the database alone, and raising them doesn't restore titles that were truncated before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlacesLimits {
+    pub max_url_length: u32,
+    pub max_title_length: u32,
+}
+
+impl Default for PlacesLimits {
+    fn default() -> Self {
+        Self {
+            max_url_length: URL_LENGTH_MAX as u32,
+            max_title_length: TITLE_LENGTH_MAX as u32,
+        }
+    }
+}
+
+impl PlacesLimits {
+    #[inline]
+    pub fn max_url_length(&self) -> usize {
+        self.max_url_length as usize
+    }
+
+    #[inline]
+    pub fn max_title_length(&self) -> usize {
+        self.max_title_length as usize
+    }
+
+    /// Checks that the limits are non-zero, and that SQLite can store values that long.
+    pub(crate) fn validate(&self, conn: &Connection) -> Result<()> {
+        let sqlite_max = conn.limit(rusqlite::limits::Limit::SQLITE_LIMIT_LENGTH);
+        for (name, value) in [
+            ("max_url_length", self.max_url_length),
+            ("max_title_length", self.max_title_length),
+        ] {
+            if value == 0 || i64::from(value) > i64::from(sqlite_max) {
+                return Err(Error::InvalidPlacesLimits(format!(
+                    "{name} must be between 1 and {sqlite_max}, got {value}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
 // Typesafe way to manage RowIds. Does it make sense? A better way?
 #[derive(
     Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Deserialize, Serialize, Default, Hash,
@@ -168,7 +219,7 @@ fn new_page_info(db: &PlacesDb, url: &Url, new_guid: Option<SyncGuid>) -> Result
         None => SyncGuid::random(),
     };
     let url_str = url.as_str();
-    if url_str.len() > URL_LENGTH_MAX {
+    if url_str.len() > db.limits().max_url_length() {
         // Generally callers check this first (bookmarks don't, history does).
         return Err(Error::InvalidPlaceInfo(InvalidPlaceInfo::UrlTooLong));
     }
@@ -252,12 +303,48 @@ pub fn run_maintenance_prune(
     db_size_limit: u32,
     prune_limit: u32,
 ) -> Result<RunMaintenanceMetrics> {
-    let db_size_before = conn.get_db_size()?;
+    run_maintenance_prune_in_chunks(&mut |chunk| chunk(conn), db_size_limit, prune_limit)
+}
+
+/// The most visits a maintenance job prunes in one transaction.
+const MAINTENANCE_MAX_VISITS_PER_CHUNK: u32 = 500;
+
+/// One step of a maintenance job, which runs in its own transaction.
+pub type MaintenanceChunk<'a> = &'a mut dyn FnMut(&PlacesDb) -> Result<()>;
+
+/// Like `run_maintenance_prune()`, but runs each step of the job with `run_chunk`. Between
+/// steps, the caller can let other writers use the connection, so they don't wait for the
+/// whole job.
+pub fn run_maintenance_prune_in_chunks(
+    run_chunk: &mut dyn FnMut(MaintenanceChunk<'_>) -> Result<()>,
+    db_size_limit: u32,
+    prune_limit: u32,
+) -> Result<RunMaintenanceMetrics> {
+    let mut db_size_before = 0;
+    run_chunk(&mut |conn| {
+        db_size_before = conn.get_db_size()?;
+        Ok(())
+    })?;
     let should_prune = db_size_limit > 0 && db_size_before > db_size_limit;
-    if should_prune {
-        history::prune_older_visits(conn, prune_limit)?;
+    let mut remaining = if should_prune { prune_limit } else { 0 };
+    while remaining > 0 {
+        let limit = remaining.min(MAINTENANCE_MAX_VISITS_PER_CHUNK);
+        let mut num_pruned = 0;
+        run_chunk(&mut |conn| {
+            num_pruned = history::prune_older_visits_counted(conn, limit)?;
+            Ok(())
+        })?;
+        if num_pruned < limit as usize {
+            // Nothing old enough left to prune.
+            break;
+        }
+        remaining -= limit;
     }
-    let db_size_after = conn.get_db_size()?;
+    let mut db_size_after = 0;
+    run_chunk(&mut |conn| {
+        db_size_after = conn.get_db_size()?;
+        Ok(())
+    })?;
     Ok(RunMaintenanceMetrics {
         pruned_visits: should_prune,
         db_size_before,
@@ -304,11 +391,264 @@ pub fn run_maintenance_optimize(conn: &PlacesDb) -> Result<()> {
 /// to clean up / shrink the database.  They're split up so that we can time each one in the
 /// Kotlin wrapper code (This is needed because we only have access to the Glean API in Kotlin and
 /// it supports a stop-watch style API, not recording specific values).
+///
+/// This copies the whole WAL back to the DB and truncates it to 0 bytes, unlike the passive
+/// checkpoints run every `WAL_AUTOCHECKPOINT_PAGES`, which give up if there are readers. It
+/// waits for readers to finish, up to the busy timeout, and leaves the WAL as it was if they don't.
 pub fn run_maintenance_checkpoint(conn: &PlacesDb) -> Result<()> {
-    conn.execute_one("PRAGMA wal_checkpoint(PASSIVE)")?;
+    let (busy, wal_pages, checkpointed_pages): (bool, i64, i64) =
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+    if busy {
+        log::info!(
+            "run_maintenance_checkpoint: blocked after checkpointing {} of {} WAL pages",
+            checkpointed_pages,
+            wal_pages
+        );
+    }
     Ok(())
 }
 
+/// Size and write-ahead log metrics for the places DB, from `get_db_stats()`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DbStats {
+    /// The space used by the DB, not counting free pages or the WAL.
+    pub db_size_bytes: u32,
+    /// The size of the WAL file, or 0 if there isn't one.
+    pub wal_size_bytes: u64,
+    /// How many pages can be written to the WAL before a passive checkpoint.
+    pub wal_autocheckpoint_pages: u32,
+    /// The size the WAL is truncated to once it's been checkpointed.
+    pub wal_size_limit_bytes: i64,
+}
+
+pub fn get_db_stats(conn: &PlacesDb) -> Result<DbStats> {
+    // In-memory DBs don't have a path, or a WAL.
+    let wal_size_bytes = match conn.path() {
+        Some(path) if !path.is_empty() => std::fs::metadata(format!("{path}-wal"))
+            .map(|metadata| metadata.len())
+            .unwrap_or_default(),
+        _ => 0,
+    };
+    Ok(DbStats {
+        db_size_bytes: conn.get_db_size()?,
+        wal_size_bytes,
+        wal_autocheckpoint_pages: conn.query_one("PRAGMA wal_autocheckpoint")?,
+        wal_size_limit_bytes: conn.query_one("PRAGMA journal_size_limit")?,
+    })
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TombstoneCompactionMetrics {
+    pub page_tombstones_deleted: u32,
+    pub visit_tombstones_deleted: u32,
+}
+
+/// Run maintenance on the places DB (tombstone compaction step)
+///
+/// The `run_maintenance_*()` functions are intended to be run during idle time and will take steps
+/// to clean up / shrink the database.  They're split up so that we can time each one in the
+/// Kotlin wrapper code (This is needed because we only have access to the Glean API in Kotlin and
+/// it supports a stop-watch style API, not recording specific values).
+///
+/// History tombstones are only deleted once they've been uploaded, so for users who never sync
+/// they pile up forever. When history sync is disconnected, this deletes the tombstones for
+/// deletions made more than `retention_days` ago. Those deletions won't be synced if the user
+/// connects sync later, which is why we keep recent ones around. When history sync is connected,
+/// this does nothing.
+pub fn run_maintenance_compact_tombstones(
+    conn: &PlacesDb,
+    retention_days: u32,
+) -> Result<TombstoneCompactionMetrics> {
+    if is_history_sync_connected(conn)? {
+        return Ok(TombstoneCompactionMetrics::default());
+    }
+    let retention = std::time::Duration::from_secs(u64::from(retention_days) * 24 * 60 * 60);
+    let cutoff = Timestamp::now()
+        .checked_sub(retention)
+        .unwrap_or(Timestamp(0));
+    let (num_pages, num_visits) = history::delete_tombstones_older_than(conn, cutoff)?;
+    Ok(TombstoneCompactionMetrics {
+        page_tombstones_deleted: num_pages as u32,
+        visit_tombstones_deleted: num_visits as u32,
+    })
+}
+
+// The same check as `HistorySyncEngine::get_sync_assoc()`, without needing an engine.
+fn is_history_sync_connected(conn: &PlacesDb) -> Result<bool> {
+    use crate::history_sync::engine::{COLLECTION_SYNCID_META_KEY, GLOBAL_SYNCID_META_KEY};
+    Ok(get_meta::<String>(conn, GLOBAL_SYNCID_META_KEY)?.is_some()
+        && get_meta::<String>(conn, COLLECTION_SYNCID_META_KEY)?.is_some())
+}
+
+/// The rate desktop decays frecency by each day, from its `places.frecency.decayRate` pref.
+pub const DEFAULT_FRECENCY_DECAY_RATE: f64 = 0.975;
+/// How long to wait between decays. Desktop decays on its `idle-daily` notification.
+const FRECENCY_DECAY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+/// Input history with a lower use count than this after decaying is removed, like on desktop.
+const MIN_INPUT_HISTORY_USE_COUNT: f64 = 0.01;
+pub(crate) static LAST_FRECENCY_DECAY_META_KEY: &str = "last_frecency_decay";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FrecencyDecayMetrics {
+    /// False if frecency was already decayed in the last day, so nothing changed.
+    pub decayed: bool,
+    pub places_decayed: u32,
+    pub input_history_removed: u32,
+}
+
+/// Run maintenance on the places DB (frecency decay step)
+///
+/// The `run_maintenance_*()` functions are intended to be run during idle time and will take steps
+/// to clean up / shrink the database.  They're split up so that we can time each one in the
+/// Kotlin wrapper code (This is needed because we only have access to the Glean API in Kotlin and
+/// it supports a stop-watch style API, not recording specific values).
+///
+/// Frecency is only recalculated when a page is visited or bookmarked, so without this, sites
+/// which were visited often a long time ago outrank the ones the user visits now. This does what
+/// desktop does once a day:
+///
+/// * `frecency = ROUND(frecency * rate)` for every page with a positive frecency, which also
+///   updates the frecency of their origins.
+/// * `use_count = use_count * rate` for every input history entry, removing the ones with a
+///   use count below 0.01.
+///
+/// Like desktop, this decays at most once a day, using the time of the last decay stored in the
+/// DB, and doesn't make up for days it wasn't run. `rate` must be between 0 and 1;
+/// `DEFAULT_FRECENCY_DECAY_RATE` is desktop's default.
+pub fn run_maintenance_frecency_decay(conn: &PlacesDb, rate: f64) -> Result<FrecencyDecayMetrics> {
+    decay_frecency_at(conn, rate, Timestamp::now())
+}
+
+fn decay_frecency_at(conn: &PlacesDb, rate: f64, now: Timestamp) -> Result<FrecencyDecayMetrics> {
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(Error::InvalidFrecencyDecayRate(rate));
+    }
+    if let Some(last_decay) = get_meta::<Timestamp>(conn, LAST_FRECENCY_DECAY_META_KEY)? {
+        let decayed_recently = last_decay
+            .checked_add(FRECENCY_DECAY_INTERVAL)
+            .map_or(true, |next_decay| now < next_decay);
+        // If the clock went backwards, decay again rather than waiting for it to catch up.
+        if decayed_recently && now >= last_decay {
+            return Ok(FrecencyDecayMetrics::default());
+        }
+    }
+    let tx = conn.begin_transaction()?;
+    let places_decayed = conn.execute_cached(
+        "UPDATE moz_places SET frecency = ROUND(frecency * :rate) WHERE frecency > 0",
+        rusqlite::named_params! { ":rate": rate },
+    )?;
+    // Flush the frecency changes to the origins.
+    delete_pending_temp_tables(conn)?;
+    conn.execute_cached(
+        "UPDATE moz_inputhistory SET use_count = use_count * :rate",
+        rusqlite::named_params! { ":rate": rate },
+    )?;
+    let input_history_removed = conn.execute_cached(
+        "DELETE FROM moz_inputhistory WHERE use_count < :min_use_count",
+        rusqlite::named_params! { ":min_use_count": MIN_INPUT_HISTORY_USE_COUNT },
+    )?;
+    put_meta(conn, LAST_FRECENCY_DECAY_META_KEY, &now)?;
+    tx.commit()?;
+    Ok(FrecencyDecayMetrics {
+        decayed: true,
+        places_decayed: places_decayed as u32,
+        input_history_removed: input_history_removed as u32,
+    })
+}
+
+/// The most visits we'll prune in a single transaction when expiring to a target size.
+const EXPIRE_MAX_VISITS_PER_ITERATION: u32 = 2000;
+/// The most pruning rounds we'll do before giving up on reaching the target size.
+const EXPIRE_MAX_ITERATIONS: u32 = 10;
+
+#[derive(Debug, Default)]
+pub struct ExpirationReport {
+    pub db_size_before: u32,
+    pub db_size_after: u32,
+    pub visits_pruned: u32,
+    pub iterations: u32,
+    pub reached_target: bool,
+}
+
+/// Expire history until the database is using no more than `target_bytes` of storage.
+///
+/// Unlike `run_maintenance_prune()`, which prunes a fixed number of visits, this estimates how
+/// much space each visit is taking up and prunes enough of them (oldest and "exotic" visits first)
+/// to get under the target, re-estimating after each round since deleting a visit only frees
+/// space once whole pages are empty. Free pages are then returned to the file system with an
+/// incremental vacuum.
+///
+/// Visits from the last week are never pruned, so the target may not be reachable; check
+/// `reached_target` in the report.
+pub fn expire_to_target_size(conn: &PlacesDb, target_bytes: u32) -> Result<ExpirationReport> {
+    expire_to_target_size_in_chunks(&mut |chunk| chunk(conn), target_bytes)
+}
+
+/// Like `expire_to_target_size()`, but runs each round of pruning with `run_chunk`, as for
+/// `run_maintenance_prune_in_chunks()`.
+pub fn expire_to_target_size_in_chunks(
+    run_chunk: &mut dyn FnMut(MaintenanceChunk<'_>) -> Result<()>,
+    target_bytes: u32,
+) -> Result<ExpirationReport> {
+    let mut report = ExpirationReport::default();
+    let mut num_visits = 0;
+    run_chunk(&mut |conn| {
+        report.db_size_before = conn.get_db_size()?;
+        num_visits = conn.query_one("SELECT COUNT(*) FROM moz_historyvisits")?;
+        Ok(())
+    })?;
+    let db_size_before = report.db_size_before;
+    report.db_size_after = db_size_before;
+    report.reached_target = db_size_before <= target_bytes;
+    if report.reached_target {
+        return Ok(report);
+    }
+
+    // Visits and the pages they keep alive are most of the database, so to start with we
+    // pretend they're all of it. This overestimates the weight of each visit, which is
+    // the safe direction - we'll prune too few rather than too many, and go around again.
+    let mut bytes_per_visit = f64::from(db_size_before) / f64::from(num_visits.max(1));
+    let mut db_size = db_size_before;
+    while db_size > target_bytes && report.iterations < EXPIRE_MAX_ITERATIONS {
+        let excess = f64::from(db_size - target_bytes);
+        let limit =
+            ((excess / bytes_per_visit).ceil() as u32).clamp(1, EXPIRE_MAX_VISITS_PER_ITERATION);
+        let mut num_pruned = 0;
+        let mut new_db_size = db_size;
+        run_chunk(&mut |conn| {
+            num_pruned = history::prune_older_visits_counted(conn, limit)? as u32;
+            new_db_size = conn.get_db_size()?;
+            Ok(())
+        })?;
+        report.iterations += 1;
+        if num_pruned == 0 {
+            // Nothing old enough left to prune.
+            break;
+        }
+        report.visits_pruned += num_pruned;
+        let freed = db_size.saturating_sub(new_db_size);
+        if freed > 0 {
+            bytes_per_visit = f64::from(freed) / f64::from(num_pruned);
+        }
+        db_size = new_db_size;
+    }
+
+    run_chunk(&mut |conn| {
+        let auto_vacuum_setting: u32 = conn.query_one("PRAGMA auto_vacuum")?;
+        if auto_vacuum_setting == 2 {
+            conn.execute_one("PRAGMA incremental_vacuum")?;
+        } else {
+            log::warn!("expire_to_target_size: auto_vacuum=incremental isn't set, not vacuuming");
+        }
+        report.db_size_after = conn.get_db_size()?;
+        Ok(())
+    })?;
+    report.reached_target = report.db_size_after <= target_bytes;
+    Ok(report)
+}
+
 pub fn update_all_frecencies_at_once(db: &PlacesDb, scope: &SqlInterruptScope) -> Result<()> {
     let tx = db.begin_transaction()?;
 
@@ -428,6 +768,249 @@ mod tests {
         delete_meta(&conn, "foo").expect("delete non-existing should work");
     }
 
+    fn add_old_visits_with_long_titles(conn: &PlacesDb, count: u64) {
+        for i in 0..count {
+            apply_observation(
+                conn,
+                VisitObservation::new(Url::parse(&format!("https://example.com/{i}")).unwrap())
+                    .with_title(Some("x".repeat(1000)))
+                    .with_at(Timestamp::from(727_747_200_001 + i))
+                    .with_visit_type(VisitType::Link),
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_expire_to_target_size() {
+        let conn = new_mem_connection();
+        add_old_visits_with_long_titles(&conn, 500);
+        // A recent visit, which is never expired.
+        let recent_url = Url::parse("https://example.com/recent").unwrap();
+        apply_observation(
+            &conn,
+            VisitObservation::new(recent_url.clone()).with_visit_type(VisitType::Link),
+        )
+        .unwrap();
+
+        let db_size = conn.get_db_size().unwrap();
+        let target = db_size / 2;
+        let report = expire_to_target_size(&conn, target).unwrap();
+        assert_eq!(report.db_size_before, db_size);
+        assert!(report.reached_target);
+        assert!(report.db_size_after <= target);
+        assert!(report.visits_pruned > 0 && report.visits_pruned < 501);
+        assert!(report.iterations >= 1);
+        let remaining: u32 = conn
+            .query_one("SELECT COUNT(*) FROM moz_historyvisits")
+            .unwrap();
+        assert_eq!(remaining, 501 - report.visits_pruned);
+        // The oldest visits went first.
+        assert!(
+            fetch_page_info(&conn, &Url::parse("https://example.com/0").unwrap())
+                .unwrap()
+                .is_none()
+        );
+        assert!(fetch_page_info(&conn, &recent_url).unwrap().is_some());
+
+        // Already under the target, so there's nothing to do.
+        let report = expire_to_target_size(&conn, report.db_size_after).unwrap();
+        assert!(report.reached_target);
+        assert_eq!(report.visits_pruned, 0);
+        assert_eq!(report.iterations, 0);
+    }
+
+    #[test]
+    fn test_expire_to_target_size_unreachable() {
+        let conn = new_mem_connection();
+        apply_observation(
+            &conn,
+            VisitObservation::new(Url::parse("https://example.com/recent").unwrap())
+                .with_visit_type(VisitType::Link),
+        )
+        .unwrap();
+        let report = expire_to_target_size(&conn, 1).unwrap();
+        assert!(!report.reached_target);
+        assert_eq!(report.visits_pruned, 0);
+        assert_eq!(report.iterations, 1);
+    }
+
+    #[test]
+    fn test_db_stats_and_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let api = crate::PlacesApi::new(dir.path().join("places.sqlite")).unwrap();
+        let conn = api
+            .open_connection(crate::ConnectionType::ReadWrite)
+            .unwrap();
+        apply_observation(
+            &conn,
+            VisitObservation::new(Url::parse("https://example.com/").unwrap())
+                .with_visit_type(VisitType::Link),
+        )
+        .unwrap();
+        let stats = get_db_stats(&conn).unwrap();
+        assert!(stats.db_size_bytes > 0);
+        assert!(stats.wal_size_bytes > 0);
+        assert_eq!(
+            stats.wal_autocheckpoint_pages,
+            crate::db::db::WAL_AUTOCHECKPOINT_PAGES
+        );
+        assert_eq!(stats.wal_size_limit_bytes, crate::db::db::WAL_SIZE_LIMIT);
+
+        run_maintenance_checkpoint(&conn).unwrap();
+        assert_eq!(get_db_stats(&conn).unwrap().wal_size_bytes, 0);
+
+        // In-memory DBs don't have a WAL.
+        assert_eq!(
+            get_db_stats(&new_mem_connection()).unwrap().wal_size_bytes,
+            0
+        );
+    }
+
+    #[test]
+    fn test_compact_tombstones() {
+        let conn = new_mem_connection();
+        let url = Url::parse("https://example.com/").unwrap();
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone()).with_visit_type(VisitType::Link),
+        )
+        .unwrap();
+        let place_id = fetch_page_info(&conn, &url).unwrap().unwrap().page.row_id;
+        let old = Timestamp::now()
+            .checked_sub(std::time::Duration::from_secs(31 * 24 * 60 * 60))
+            .unwrap();
+        let add_tombstones = |suffix: &str, deleted_at: Timestamp| {
+            conn.execute(
+                "INSERT INTO moz_places_tombstones(guid, deleted_at) VALUES (?, ?)",
+                (format!("tombstone{suffix}"), deleted_at),
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO moz_historyvisit_tombstones(place_id, visit_date, deleted_at)
+                 VALUES (?, ?, ?)",
+                (place_id, deleted_at, deleted_at),
+            )
+            .unwrap();
+        };
+        add_tombstones("old", old);
+        add_tombstones("new", Timestamp::now());
+        let count_tombstones = || -> (u32, u32) {
+            (
+                conn.query_one("SELECT COUNT(*) FROM moz_places_tombstones")
+                    .unwrap(),
+                conn.query_one("SELECT COUNT(*) FROM moz_historyvisit_tombstones")
+                    .unwrap(),
+            )
+        };
+
+        // Only tombstones older than the retention period are deleted.
+        assert_eq!(
+            run_maintenance_compact_tombstones(&conn, 30).unwrap(),
+            TombstoneCompactionMetrics {
+                page_tombstones_deleted: 1,
+                visit_tombstones_deleted: 1,
+            }
+        );
+        assert_eq!(count_tombstones(), (1, 1));
+
+        // Nothing is deleted while history sync is connected, because the tombstones
+        // still need uploading.
+        add_tombstones("old2", Timestamp(old.0 - 1));
+        put_meta(
+            &conn,
+            crate::history_sync::engine::GLOBAL_SYNCID_META_KEY,
+            &"global",
+        )
+        .unwrap();
+        put_meta(
+            &conn,
+            crate::history_sync::engine::COLLECTION_SYNCID_META_KEY,
+            &"coll",
+        )
+        .unwrap();
+        assert_eq!(
+            run_maintenance_compact_tombstones(&conn, 30).unwrap(),
+            TombstoneCompactionMetrics::default()
+        );
+        assert_eq!(count_tombstones(), (2, 2));
+    }
+
+    #[test]
+    fn test_frecency_decay() {
+        let conn = new_mem_connection();
+        let url = Url::parse("https://example.com/").unwrap();
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone()).with_visit_type(VisitType::Link),
+        )
+        .unwrap();
+        let place_id = fetch_page_info(&conn, &url).unwrap().unwrap().page.row_id;
+        conn.execute_batch(&format!(
+            "UPDATE moz_places SET frecency = 1000 WHERE id = {place_id};
+             INSERT INTO moz_inputhistory(place_id, input, use_count)
+             VALUES ({place_id}, 'ex', 2), ({place_id}, 'e', 0.01);"
+        ))
+        .unwrap();
+        delete_pending_temp_tables(&conn).unwrap();
+        let frecencies = || -> (i64, i64) {
+            (
+                conn.query_one(
+                    "SELECT frecency FROM moz_places WHERE url = 'https://example.com/'",
+                )
+                .unwrap(),
+                conn.query_one("SELECT frecency FROM moz_origins WHERE host = 'example.com'")
+                    .unwrap(),
+            )
+        };
+        assert_eq!(frecencies(), (1000, 1000));
+
+        let day = std::time::Duration::from_secs(24 * 60 * 60);
+        let now = Timestamp::now();
+        assert_eq!(
+            decay_frecency_at(&conn, DEFAULT_FRECENCY_DECAY_RATE, now).unwrap(),
+            FrecencyDecayMetrics {
+                decayed: true,
+                places_decayed: 1,
+                input_history_removed: 1,
+            }
+        );
+        assert_eq!(frecencies(), (975, 975));
+        let use_count: f64 = conn
+            .query_one("SELECT use_count FROM moz_inputhistory")
+            .unwrap();
+        assert_eq!(use_count, 1.95);
+
+        // Frecency only decays once a day.
+        let later = now.checked_add(day / 2).unwrap();
+        assert!(
+            !decay_frecency_at(&conn, DEFAULT_FRECENCY_DECAY_RATE, later)
+                .unwrap()
+                .decayed
+        );
+        assert_eq!(frecencies(), (975, 975));
+
+        let tomorrow = now.checked_add(day).unwrap();
+        assert!(
+            decay_frecency_at(&conn, DEFAULT_FRECENCY_DECAY_RATE, tomorrow)
+                .unwrap()
+                .decayed
+        );
+        assert_eq!(frecencies(), (951, 951));
+
+        // The time of the last decay is kept in the DB, so it survives restarts.
+        assert_eq!(
+            get_meta::<Timestamp>(&conn, LAST_FRECENCY_DECAY_META_KEY).unwrap(),
+            Some(tomorrow)
+        );
+
+        assert!(matches!(
+            decay_frecency_at(&conn, 1.5, tomorrow.checked_add(day).unwrap()),
+            Err(Error::InvalidFrecencyDecayRate(_))
+        ));
+        assert!(run_maintenance_frecency_decay(&conn, f64::NAN).is_err());
+    }
+
     // Here we try and test that we replicate desktop behaviour, which isn't that obvious.
     // * create a bookmark
     // * remove the bookmark - this doesn't remove the place or origin - probably because in
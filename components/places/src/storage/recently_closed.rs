@@ -0,0 +1,181 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Pages the user recently closed, so the application can offer to reopen them after a
+//! restart. These aren't synced, and aren't part of history: closing a page doesn't add a
+//! visit, and a page stays here even if its history is removed, until it's pushed out by
+//! newer pages or everything is deleted.
+
+use crate::db::PlacesDb;
+use crate::error::Result;
+use rusqlite::Row;
+use sql_support::ConnExt;
+use types::Timestamp;
+use url::Url;
+
+/// The number of pages kept, matching desktop's default for recently closed tabs.
+pub const MAX_RECENTLY_CLOSED_PAGES: u32 = 25;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecentlyClosedPage {
+    pub url: Url,
+    pub title: Option<String>,
+    pub closed_at: Timestamp,
+}
+
+impl RecentlyClosedPage {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            url: Url::parse(&row.get::<_, String>("url")?)?,
+            title: row.get("title")?,
+            closed_at: row.get("closed_at")?,
+        })
+    }
+}
+
+/// Record that a page was closed. Closing a page that's already in the list moves it to the
+/// front, and the oldest pages are removed to keep the list bounded.
+pub fn record_closed_page(
+    db: &PlacesDb,
+    url: &Url,
+    title: Option<&str>,
+    closed_at: Timestamp,
+) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    db.execute_cached(
+        "DELETE FROM moz_recently_closed WHERE url = :url",
+        rusqlite::named_params! { ":url": url.as_str() },
+    )?;
+    db.execute_cached(
+        "INSERT INTO moz_recently_closed(url, title, closed_at)
+         VALUES(:url, :title, :closed_at)",
+        rusqlite::named_params! {
+            ":url": url.as_str(),
+            ":title": title,
+            ":closed_at": closed_at,
+        },
+    )?;
+    db.execute_cached(
+        "DELETE FROM moz_recently_closed
+         WHERE id NOT IN (
+             SELECT id FROM moz_recently_closed
+             ORDER BY closed_at DESC, id DESC
+             LIMIT :max_pages
+         )",
+        rusqlite::named_params! { ":max_pages": MAX_RECENTLY_CLOSED_PAGES },
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Get up to `limit` recently closed pages, most recently closed first.
+pub fn get_recently_closed(db: &PlacesDb, limit: u32) -> Result<Vec<RecentlyClosedPage>> {
+    db.query_rows_and_then_cached(
+        "SELECT url, title, closed_at FROM moz_recently_closed
+         ORDER BY closed_at DESC, id DESC
+         LIMIT :limit",
+        rusqlite::named_params! { ":limit": limit },
+        RecentlyClosedPage::from_row,
+    )
+}
+
+pub fn clear_recently_closed(db: &PlacesDb) -> Result<()> {
+    db.execute_cached("DELETE FROM moz_recently_closed", [])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::ConnectionType;
+    use crate::storage::history::delete_everything;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    fn urls(pages: &[RecentlyClosedPage]) -> Vec<&str> {
+        pages.iter().map(|p| p.url.as_str()).collect()
+    }
+
+    #[test]
+    fn test_recently_closed() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        record_closed_page(
+            &conn,
+            &url("https://example.com/a"),
+            Some("A"),
+            Timestamp(1000),
+        )
+        .unwrap();
+        record_closed_page(&conn, &url("https://example.com/b"), None, Timestamp(2000)).unwrap();
+        record_closed_page(
+            &conn,
+            &url("https://example.com/c"),
+            Some("C"),
+            Timestamp(3000),
+        )
+        .unwrap();
+        let pages = get_recently_closed(&conn, 10).unwrap();
+        assert_eq!(
+            urls(&pages),
+            [
+                "https://example.com/c",
+                "https://example.com/b",
+                "https://example.com/a"
+            ]
+        );
+        assert_eq!(pages[1].title, None);
+        assert_eq!(pages[2].title.as_deref(), Some("A"));
+        assert_eq!(pages[2].closed_at, Timestamp(1000));
+        assert_eq!(get_recently_closed(&conn, 1).unwrap().len(), 1);
+
+        // Closing a page again moves it to the front, with its new title.
+        record_closed_page(
+            &conn,
+            &url("https://example.com/a"),
+            Some("A again"),
+            Timestamp(4000),
+        )
+        .unwrap();
+        let pages = get_recently_closed(&conn, 10).unwrap();
+        assert_eq!(
+            urls(&pages),
+            [
+                "https://example.com/a",
+                "https://example.com/c",
+                "https://example.com/b"
+            ]
+        );
+        assert_eq!(pages[0].title.as_deref(), Some("A again"));
+
+        clear_recently_closed(&conn).unwrap();
+        assert!(get_recently_closed(&conn, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_recently_closed_is_bounded() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        for i in 0..(MAX_RECENTLY_CLOSED_PAGES + 5) {
+            record_closed_page(
+                &conn,
+                &url(&format!("https://example.com/{}", i)),
+                None,
+                Timestamp(u64::from(i) * 1000),
+            )
+            .unwrap();
+        }
+        let pages = get_recently_closed(&conn, 100).unwrap();
+        assert_eq!(pages.len(), MAX_RECENTLY_CLOSED_PAGES as usize);
+        assert_eq!(pages.last().unwrap().url.as_str(), "https://example.com/5");
+    }
+
+    #[test]
+    fn test_delete_everything_clears_recently_closed() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        record_closed_page(&conn, &url("https://example.com/"), None, Timestamp(1000)).unwrap();
+        delete_everything(&conn).unwrap();
+        assert!(get_recently_closed(&conn, 10).unwrap().is_empty());
+    }
+}
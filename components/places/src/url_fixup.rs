@@ -0,0 +1,204 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! An opt-in layer for turning nearly-valid URLs, like those we get from
+//! intent handlers and share sheets, into something we can record a visit to.
+//!
+//! `apply_observation` requires a valid `Url`, so apps which would otherwise
+//! drop these can call `fixup_url` first, and use `UrlFixupResult::fixups` to
+//! see what (if anything) was changed.
+
+use crate::error::Result;
+use url::{ParseError, Url};
+
+/// The scheme we assume when we're given something like `example.com/page`.
+const DEFAULT_SCHEME: &str = "https";
+
+/// Schemes which never have a host, so we shouldn't mistake `about:blank`
+/// for a host called `about` with a port of `blank`.
+const HOSTLESS_SCHEMES: &[&str] = &[
+    "about",
+    "blob",
+    "data",
+    "javascript",
+    "mailto",
+    "tel",
+    "view-source",
+];
+
+/// A change made to a URL by `fixup_url`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UrlFixup {
+    /// Leading or trailing whitespace was removed.
+    TrimmedWhitespace,
+    /// The URL had no scheme, so we added one.
+    AddedScheme,
+    /// The host was lowercased, or converted to punycode.
+    NormalizedHost,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlFixupResult {
+    pub url: Url,
+    /// What was changed to get `url`, in the order the changes were made.
+    /// Empty if the input was already a valid URL.
+    pub fixups: Vec<UrlFixup>,
+}
+
+/// Tries to turn `input` into a valid URL. Fails with the error from parsing
+/// `input` as-is if it can't be fixed up.
+pub fn fixup_url(input: &str) -> Result<UrlFixupResult> {
+    let mut fixups = Vec::new();
+    let trimmed = input.trim();
+    if trimmed.len() != input.len() {
+        fixups.push(UrlFixup::TrimmedWhitespace);
+    }
+    let (url, candidate) = match Url::parse(trimmed) {
+        Ok(url) if !looks_like_missing_scheme(&url) => (url, trimmed.to_string()),
+        result => {
+            let candidate = format!("{}://{}", DEFAULT_SCHEME, trimmed);
+            match Url::parse(&candidate) {
+                Ok(url) if url.host_str().map_or(false, is_plausible_host) => {
+                    fixups.push(UrlFixup::AddedScheme);
+                    (url, candidate)
+                }
+                _ => {
+                    return Err(result
+                        .err()
+                        .unwrap_or(ParseError::RelativeUrlWithoutBase)
+                        .into())
+                }
+            }
+        }
+    };
+    // The URL parser lowercases and punycodes hosts for us, so if the host
+    // isn't exactly what we were given, then it was normalized.
+    if let Some(host) = url.host_str() {
+        if written_host(&candidate) != Some(host) {
+            fixups.push(UrlFixup::NormalizedHost);
+        }
+    }
+    Ok(UrlFixupResult { url, fixups })
+}
+
+// `example.com:8080/page` parses as a URL with a scheme of `example.com` and
+// a path of `8080/page`, so that's treated as a host and port.
+fn looks_like_missing_scheme(url: &Url) -> bool {
+    !url.has_host()
+        && !HOSTLESS_SCHEMES.contains(&url.scheme())
+        && url.path().starts_with(|c: char| c.is_ascii_digit())
+}
+
+// The host as it appears in `input`, before any normalization. Only handles
+// URLs with an authority, like `https://user@host:port/path`.
+fn written_host(input: &str) -> Option<&str> {
+    let (_, rest) = input.split_once("://")?;
+    let authority = rest.split(['/', '\\', '?', '#']).next()?;
+    let host_and_port = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    Some(match host_and_port.find(']') {
+        Some(end) if host_and_port.starts_with('[') => &host_and_port[..=end],
+        _ => host_and_port.split(':').next()?,
+    })
+}
+
+fn is_plausible_host(host: &str) -> bool {
+    host == "localhost" || (host.contains('.') && !host.starts_with('.') && !host.ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    fn fixup(input: &str) -> (String, Vec<UrlFixup>) {
+        let result = fixup_url(input).unwrap();
+        (result.url.into(), result.fixups)
+    }
+
+    #[test]
+    fn test_valid_urls_unchanged() {
+        for url in [
+            "https://example.com/",
+            "http://example.com:8080/path?q=1#frag",
+            "about:blank",
+            "data:text/plain,hello",
+            "file:///etc/hosts",
+        ] {
+            assert_eq!(fixup(url), (url.to_string(), vec![]));
+        }
+    }
+
+    #[test]
+    fn test_fixups() {
+        assert_eq!(
+            fixup("  https://example.com/\n"),
+            (
+                "https://example.com/".to_string(),
+                vec![UrlFixup::TrimmedWhitespace]
+            )
+        );
+        assert_eq!(
+            fixup("example.com/page"),
+            (
+                "https://example.com/page".to_string(),
+                vec![UrlFixup::AddedScheme]
+            )
+        );
+        assert_eq!(
+            fixup("example.com:8080/page"),
+            (
+                "https://example.com:8080/page".to_string(),
+                vec![UrlFixup::AddedScheme]
+            )
+        );
+        assert_eq!(
+            fixup(" bücher.example/"),
+            (
+                "https://xn--bcher-kva.example/".to_string(),
+                vec![
+                    UrlFixup::TrimmedWhitespace,
+                    UrlFixup::AddedScheme,
+                    UrlFixup::NormalizedHost
+                ]
+            )
+        );
+        assert_eq!(
+            fixup("https://EXAMPLE.com/"),
+            (
+                "https://example.com/".to_string(),
+                vec![UrlFixup::NormalizedHost]
+            )
+        );
+        // The normalized host appearing elsewhere in the URL doesn't count.
+        assert_eq!(
+            fixup("https://user@EXAMPLE.com:8080/example.com"),
+            (
+                "https://user@example.com:8080/example.com".to_string(),
+                vec![UrlFixup::NormalizedHost]
+            )
+        );
+        assert_eq!(
+            fixup("http://[::1]:8080/"),
+            ("http://[::1]:8080/".to_string(), vec![])
+        );
+    }
+
+    #[test]
+    fn test_unfixable() {
+        for input in [
+            "",
+            "   ",
+            "not a url",
+            "foo",
+            "https://",
+            "http://exa mple.com/",
+        ] {
+            assert!(
+                matches!(fixup_url(input), Err(Error::UrlParseError(_))),
+                "{:?} should fail",
+                input
+            );
+        }
+    }
+}
@@ -25,7 +25,10 @@ pub use config::{SuggestGlobalConfig, SuggestProviderConfig};
 pub use error::SuggestApiError;
 pub use provider::SuggestionProvider;
 pub use query::SuggestionQuery;
-pub use store::{InterruptKind, SuggestIngestionConstraints, SuggestStore, SuggestStoreBuilder};
+pub use store::{
+    IngestProgressObserver, InterruptKind, SuggestIngestProgress, SuggestIngestionConstraints,
+    SuggestStore, SuggestStoreBuilder,
+};
 pub use suggestion::{raw_suggestion_url_matches, Suggestion};
 
 pub(crate) type Result<T> = std::result::Result<T, error::Error>;
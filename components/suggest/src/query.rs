@@ -2,7 +2,9 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::SuggestionProvider;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{Suggestion, SuggestionProvider};
 
 /// A query for suggestions to show in the address bar.
 #[derive(Clone, Debug, Default)]
@@ -10,6 +12,9 @@ pub struct SuggestionQuery {
     pub keyword: String,
     pub providers: Vec<SuggestionProvider>,
     pub limit: Option<i32>,
+    /// The maximum number of suggestions to return from each provider. Only
+    /// used by `SuggestStore::query_mixed()`.
+    pub provider_limits: Option<HashMap<SuggestionProvider, i32>>,
 }
 
 impl SuggestionQuery {
@@ -18,6 +23,7 @@ impl SuggestionQuery {
             keyword: keyword.to_string(),
             providers: Vec::from(SuggestionProvider::all()),
             limit: None,
+            provider_limits: None,
         }
     }
 
@@ -26,6 +32,7 @@ impl SuggestionQuery {
             keyword: keyword.to_string(),
             providers,
             limit: None,
+            provider_limits: None,
         }
     }
 
@@ -44,6 +51,7 @@ impl SuggestionQuery {
             keyword: keyword.into(),
             providers: vec![SuggestionProvider::Amp],
             limit: None,
+            provider_limits: None,
         }
     }
 
@@ -52,6 +60,7 @@ impl SuggestionQuery {
             keyword: keyword.into(),
             providers: vec![SuggestionProvider::Wikipedia],
             limit: None,
+            provider_limits: None,
         }
     }
 
@@ -60,6 +69,7 @@ impl SuggestionQuery {
             keyword: keyword.into(),
             providers: vec![SuggestionProvider::AmpMobile],
             limit: None,
+            provider_limits: None,
         }
     }
 
@@ -68,6 +78,7 @@ impl SuggestionQuery {
             keyword: keyword.into(),
             providers: vec![SuggestionProvider::Amo],
             limit: None,
+            provider_limits: None,
         }
     }
 
@@ -76,6 +87,7 @@ impl SuggestionQuery {
             keyword: keyword.into(),
             providers: vec![SuggestionProvider::Pocket],
             limit: None,
+            provider_limits: None,
         }
     }
 
@@ -84,6 +96,7 @@ impl SuggestionQuery {
             keyword: keyword.into(),
             providers: vec![SuggestionProvider::Yelp],
             limit: None,
+            provider_limits: None,
         }
     }
 
@@ -92,6 +105,7 @@ impl SuggestionQuery {
             keyword: keyword.into(),
             providers: vec![SuggestionProvider::Mdn],
             limit: None,
+            provider_limits: None,
         }
     }
 
@@ -101,6 +115,7 @@ impl SuggestionQuery {
             keyword: keyword.into(),
             providers: vec![SuggestionProvider::Fakespot],
             limit: None,
+            provider_limits: None,
         }
     }
 
@@ -109,6 +124,7 @@ impl SuggestionQuery {
             keyword: keyword.into(),
             providers: vec![SuggestionProvider::Weather],
             limit: None,
+            provider_limits: None,
         }
     }
 
@@ -118,4 +134,80 @@ impl SuggestionQuery {
             ..self
         }
     }
+
+    pub fn provider_limit(mut self, provider: SuggestionProvider, limit: i32) -> Self {
+        self.provider_limits
+            .get_or_insert_with(HashMap::new)
+            .insert(provider, limit);
+        self
+    }
+
+    /// Returns the providers to query, in order, without duplicates.
+    pub(crate) fn unique_providers(&self) -> Vec<SuggestionProvider> {
+        let mut seen = HashSet::new();
+        self.providers
+            .iter()
+            .copied()
+            .filter(|provider| seen.insert(*provider))
+            .collect()
+    }
+}
+
+/// Interleaves the suggestions from each provider into one list, for
+/// `SuggestStore::query_mixed()`.
+///
+/// `suggestions_by_provider` has each provider's suggestions, best first, in
+/// the order returned by `SuggestionQuery::unique_providers()`. We take the
+/// best remaining suggestion from each provider in turn, so earlier providers
+/// win ties. A suggestion with the same URL as one we've already taken is
+/// dropped, and doesn't count towards its provider's limit.
+pub(crate) fn mix_suggestions(
+    query: &SuggestionQuery,
+    suggestions_by_provider: Vec<(SuggestionProvider, Vec<Suggestion>)>,
+) -> Vec<Suggestion> {
+    let limit = query
+        .limit
+        .and_then(|limit| usize::try_from(limit).ok())
+        .unwrap_or(usize::MAX);
+    let mut queues = suggestions_by_provider
+        .into_iter()
+        .map(|(provider, suggestions)| {
+            let provider_limit = query
+                .provider_limits
+                .as_ref()
+                .and_then(|limits| limits.get(&provider))
+                .and_then(|limit| usize::try_from(*limit).ok())
+                .unwrap_or(usize::MAX);
+            (provider_limit, VecDeque::from(suggestions))
+        })
+        .collect::<Vec<_>>();
+
+    let mut seen_urls = HashSet::new();
+    let mut mixed = Vec::new();
+    loop {
+        let mut took_any = false;
+        for (remaining, queue) in queues.iter_mut() {
+            if mixed.len() >= limit {
+                return mixed;
+            }
+            if *remaining == 0 {
+                continue;
+            }
+            while let Some(suggestion) = queue.pop_front() {
+                // Suggestions without URLs, like weather, are never duplicates.
+                let is_new = suggestion
+                    .url()
+                    .map_or(true, |url| seen_urls.insert(url.to_string()));
+                if is_new {
+                    mixed.push(suggestion);
+                    *remaining -= 1;
+                    took_any = true;
+                    break;
+                }
+            }
+        }
+        if !took_any {
+            return mixed;
+        }
+    }
 }
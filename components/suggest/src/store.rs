@@ -18,9 +18,10 @@ use serde::de::DeserializeOwned;
 
 use crate::{
     config::{SuggestGlobalConfig, SuggestProviderConfig},
-    db::{ConnectionType, SuggestDao, SuggestDb},
+    db::{ConnectionType, SuggestDao, SuggestDb, WriteScope},
     error::Error,
     provider::SuggestionProvider,
+    query::mix_suggestions,
     rs::{
         Client, Record, RecordRequest, RemoteSettingsClient, SuggestAttachment, SuggestRecord,
         SuggestRecordId, SuggestRecordType, DEFAULT_RECORDS_TYPES,
@@ -166,6 +167,20 @@ impl SuggestStore {
         self.inner.query(query)
     }
 
+    /// Queries each of the query's providers, and mixes their suggestions
+    /// into one list.
+    ///
+    /// Unlike `query()`, which ranks all suggestions by score, this takes
+    /// the best suggestion from each provider in turn, in the order of
+    /// `query.providers`, skipping suggestions with the same URL as one
+    /// that's already in the list. `query.provider_limits` caps how many
+    /// suggestions each provider can contribute, and `query.limit` caps the
+    /// total.
+    #[handle_error(Error)]
+    pub fn query_mixed(&self, query: SuggestionQuery) -> SuggestApiResult<Vec<Suggestion>> {
+        self.inner.query_mixed(query)
+    }
+
     /// Dismiss a suggestion
     ///
     /// Dismissed suggestions will not be returned again
@@ -197,6 +212,18 @@ impl SuggestStore {
         self.inner.ingest(constraints)
     }
 
+    /// Like [`SuggestStore::ingest()`], but reports progress to `observer` as
+    /// records are ingested.
+    #[handle_error(Error)]
+    pub fn ingest_with_progress(
+        &self,
+        constraints: SuggestIngestionConstraints,
+        observer: Box<dyn IngestProgressObserver>,
+    ) -> SuggestApiResult<()> {
+        self.inner
+            .ingest_with_progress(constraints, Some(observer.as_ref()))
+    }
+
     /// Removes all content from the database.
     #[handle_error(Error)]
     pub fn clear(&self) -> SuggestApiResult<()> {
@@ -233,6 +260,29 @@ pub struct SuggestIngestionConstraints {
     pub empty_only: bool,
 }
 
+/// Progress reported to an [`IngestProgressObserver`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SuggestIngestProgress {
+    /// The providers whose records are being ingested.
+    pub providers: Vec<SuggestionProvider>,
+    /// The number of these records ingested so far.
+    pub records_ingested: u32,
+    /// The number of these records which will be ingested.
+    pub records_total: u32,
+}
+
+/// Implemented by consumers who want to show ingestion progress. See
+/// [`SuggestStore::ingest_with_progress()`].
+pub trait IngestProgressObserver: Send + Sync {
+    fn on_progress(&self, progress: SuggestIngestProgress);
+}
+
+/// The number of records we ingest in each transaction. After each chunk is
+/// committed we move the last ingest time for the record type forward, so if
+/// we're killed (or interrupted) part way through an ingest, the next one
+/// picks up where we stopped.
+const INGEST_CHUNK_SIZE: usize = 10;
+
 /// The implementation of the store. This is generic over the Remote Settings
 /// client, and is split out from the concrete [`SuggestStore`] for testing
 /// with a mock client.
@@ -270,6 +320,24 @@ impl<S> SuggestStoreInner<S> {
         self.dbs()?.reader.read(|dao| dao.fetch_suggestions(&query))
     }
 
+    fn query_mixed(&self, query: SuggestionQuery) -> Result<Vec<Suggestion>> {
+        if query.keyword.is_empty() || query.providers.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.dbs()?.reader.read(|dao| {
+            let suggestions_by_provider = query
+                .unique_providers()
+                .into_iter()
+                .map(|provider| {
+                    let provider_query =
+                        SuggestionQuery::with_providers(&query.keyword, vec![provider]);
+                    Ok((provider, dao.fetch_suggestions(&provider_query)?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(mix_suggestions(&query, suggestions_by_provider))
+        })
+    }
+
     fn dismiss_suggestion(&self, suggestion_url: String) -> Result<()> {
         self.dbs()?
             .writer
@@ -322,6 +390,14 @@ where
     S: Client,
 {
     pub fn ingest(&self, constraints: SuggestIngestionConstraints) -> Result<()> {
+        self.ingest_with_progress(constraints, None)
+    }
+
+    pub fn ingest_with_progress(
+        &self,
+        constraints: SuggestIngestionConstraints,
+        observer: Option<&dyn IngestProgressObserver>,
+    ) -> Result<()> {
         breadcrumb!("Ingestion starting");
         let writer = &self.dbs()?.writer;
         if constraints.empty_only && !writer.read(|dao| dao.suggestions_table_empty())? {
@@ -339,12 +415,34 @@ where
             DEFAULT_RECORDS_TYPES.to_vec()
         };
 
+        let requested_providers = match &constraints.providers {
+            Some(providers) => providers.clone(),
+            None => SuggestionProvider::all().to_vec(),
+        };
+
         // Handle ingestion inside single write scope
         let mut write_scope = writer.write_scope()?;
         for ingest_record_type in ingest_record_types {
             breadcrumb!("Ingesting {ingest_record_type}");
-            write_scope
-                .write(|dao| self.ingest_records_by_type(ingest_record_type, dao, &constraints))?;
+            let providers: Vec<_> = requested_providers
+                .iter()
+                .filter(|p| p.records_for_provider().contains(&ingest_record_type))
+                .copied()
+                .collect();
+            self.ingest_records_by_type(
+                ingest_record_type,
+                &mut write_scope,
+                &constraints,
+                |records_ingested, records_total| {
+                    if let Some(observer) = observer {
+                        observer.on_progress(SuggestIngestProgress {
+                            providers: providers.clone(),
+                            records_ingested,
+                            records_total,
+                        });
+                    }
+                },
+            )?;
             write_scope.err_if_interrupted()?;
         }
         breadcrumb!("Ingestion complete");
@@ -355,27 +453,40 @@ where
     fn ingest_records_by_type(
         &self,
         ingest_record_type: SuggestRecordType,
-        dao: &mut SuggestDao,
+        write_scope: &mut WriteScope,
         constraints: &SuggestIngestionConstraints,
+        mut on_progress: impl FnMut(u32, u32),
     ) -> Result<()> {
         let last_ingest_key = ingest_record_type.last_ingest_meta_key();
         let request = RecordRequest {
             record_type: ingest_record_type.to_string(),
-            last_modified: dao.get_meta::<u64>(&last_ingest_key)?,
+            last_modified: write_scope.write(|dao| dao.get_meta::<u64>(&last_ingest_key))?,
             limit: constraints.max_suggestions,
         };
 
+        // Records come back oldest first, so each chunk only moves the last
+        // ingest time forward.
         let records = self.settings_client.get_records(request)?;
-        for record in &records {
-            // Drop any data that we previously ingested from this record.
-            // Suggestions in particular don't have a stable identifier, and
-            // determining which suggestions in the record actually changed is
-            // more complicated than dropping and re-ingesting all of them.
-            dao.delete_record_data(record)?;
-        }
-        self.ingest_records(dao, &records)?;
-        if let Some(max_last_modified) = records.iter().map(|r| r.last_modified).max() {
-            dao.put_last_ingest_if_newer(&last_ingest_key, max_last_modified)?;
+        let records_total = records.len() as u32;
+        let mut records_ingested = 0;
+        for chunk in records.chunks(INGEST_CHUNK_SIZE) {
+            write_scope.write(|dao| {
+                for record in chunk {
+                    // Drop any data that we previously ingested from this record.
+                    // Suggestions in particular don't have a stable identifier, and
+                    // determining which suggestions in the record actually changed is
+                    // more complicated than dropping and re-ingesting all of them.
+                    dao.delete_record_data(record)?;
+                }
+                self.ingest_records(dao, chunk)?;
+                if let Some(max_last_modified) = chunk.iter().map(|r| r.last_modified).max() {
+                    dao.put_last_ingest_if_newer(&last_ingest_key, max_last_modified)?;
+                }
+                Ok(())
+            })?;
+            records_ingested += chunk.len() as u32;
+            on_progress(records_ingested, records_total);
+            write_scope.err_if_interrupted()?;
         }
         Ok(())
     }
@@ -510,15 +621,16 @@ where
     pub fn benchmark_ingest_records_by_type(&self, ingest_record_type: SuggestRecordType) {
         let writer = &self.dbs().unwrap().writer;
         writer
-            .write(|dao| {
-                dao.clear_meta(ingest_record_type.last_ingest_meta_key().as_str())?;
-                self.ingest_records_by_type(
-                    ingest_record_type,
-                    dao,
-                    &SuggestIngestionConstraints::default(),
-                )
-            })
-            .unwrap()
+            .write(|dao| dao.clear_meta(ingest_record_type.last_ingest_meta_key().as_str()))
+            .unwrap();
+        let mut write_scope = writer.write_scope().unwrap();
+        self.ingest_records_by_type(
+            ingest_record_type,
+            &mut write_scope,
+            &SuggestIngestionConstraints::default(),
+            |_, _| (),
+        )
+        .unwrap()
     }
 
     pub fn table_row_counts(&self) -> Vec<(String, u32)> {
@@ -727,6 +839,73 @@ mod tests {
         Ok(())
     }
 
+    /// Tests that an interrupted ingestion keeps the chunks it finished, and
+    /// that the next one completes it.
+    #[test]
+    fn ingest_resumes_after_interrupt() -> anyhow::Result<()> {
+        before_each();
+
+        struct InterruptingObserver<'a> {
+            store: &'a TestStore,
+            progress: Mutex<Vec<SuggestIngestProgress>>,
+        }
+        impl IngestProgressObserver for InterruptingObserver<'_> {
+            fn on_progress(&self, progress: SuggestIngestProgress) {
+                self.progress.lock().push(progress);
+                self.store.inner.interrupt(Some(InterruptKind::Write));
+            }
+        }
+
+        let mut client = MockRemoteSettingsClient::default();
+        for i in 0..=INGEST_CHUNK_SIZE {
+            client.last_modified_timestamp = 100 + i as u64;
+            client = client.with_record("data", &format!("data-{i}"), json!([los_pollos_amp()]));
+        }
+        let store = TestStore::new(client);
+        let last_ingest_key = SuggestRecordType::AmpWikipedia.last_ingest_meta_key();
+
+        let observer = InterruptingObserver {
+            store: &store,
+            progress: Mutex::new(Vec::new()),
+        };
+        let constraints = SuggestIngestionConstraints {
+            providers: Some(vec![SuggestionProvider::Amp]),
+            ..SuggestIngestionConstraints::default()
+        };
+        assert!(matches!(
+            store
+                .inner
+                .ingest_with_progress(constraints.clone(), Some(&observer)),
+            Err(Error::Interrupted(_))
+        ));
+        assert_eq!(
+            *observer.progress.lock(),
+            vec![SuggestIngestProgress {
+                providers: vec![SuggestionProvider::Amp],
+                records_ingested: INGEST_CHUNK_SIZE as u32,
+                records_total: INGEST_CHUNK_SIZE as u32 + 1,
+            }]
+        );
+        // The first chunk was committed, and the last ingest time moved up to it.
+        assert_eq!(store.count_rows("suggestions"), INGEST_CHUNK_SIZE as u64);
+        assert_eq!(
+            store.read(|dao| dao.get_meta::<u64>(&last_ingest_key))?,
+            Some(100 + INGEST_CHUNK_SIZE as u64 - 1)
+        );
+
+        store.ingest(constraints);
+        assert_eq!(
+            store.count_rows("suggestions"),
+            INGEST_CHUNK_SIZE as u64 + 1
+        );
+        assert_eq!(
+            store.read(|dao| dao.get_meta::<u64>(&last_ingest_key))?,
+            Some(100 + INGEST_CHUNK_SIZE as u64)
+        );
+
+        Ok(())
+    }
+
     /// Tests ingesting suggestions with icons.
     #[test]
     fn ingest_amp_icons() -> anyhow::Result<()> {
@@ -1586,6 +1765,114 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn query_mixed() -> anyhow::Result<()> {
+        before_each();
+
+        // The second AMP suggestion has the same URL as the Wikipedia one.
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(
+                    "data",
+                    "data-1",
+                    json!([
+                        los_pollos_amp().merge(json!({
+                            "keywords": ["mix"],
+                            "score": 0.3,
+                        })),
+                        good_place_eats_amp().merge(json!({
+                            "keywords": ["mix"],
+                            "score": 0.1,
+                            "url": "https://wikipedia.org/California",
+                        })),
+                        california_wiki().merge(json!({
+                            "keywords": ["mix"],
+                        })),
+                    ]),
+                )
+                .with_record(
+                    "mdn-suggestions",
+                    "data-2",
+                    json!([array_mdn().merge(json!({
+                        "keywords": ["mix"],
+                    }))]),
+                )
+                .with_icon(los_pollos_icon())
+                .with_icon(good_place_eats_icon())
+                .with_icon(california_icon()),
+        );
+        store.ingest(SuggestIngestionConstraints::default());
+
+        let mut good_place_eats = good_place_eats_suggestion("mix").with_score(0.1);
+        if let Suggestion::Amp { url, raw_url, .. } = &mut good_place_eats {
+            *url = "https://wikipedia.org/California".into();
+            *raw_url = url.clone();
+        }
+        let los_pollos = los_pollos_suggestion("mix").with_score(0.3);
+        let query_mixed = |query| store.inner.query_mixed(query).unwrap();
+
+        // Suggestions are interleaved in provider order, and duplicate URLs
+        // are dropped from later providers.
+        assert_eq!(
+            query_mixed(SuggestionQuery::with_providers(
+                "mix",
+                vec![
+                    SuggestionProvider::Amp,
+                    SuggestionProvider::Wikipedia,
+                    SuggestionProvider::Mdn,
+                    SuggestionProvider::Amp,
+                ],
+            )),
+            vec![
+                los_pollos.clone(),
+                california_suggestion("mix"),
+                array_suggestion()
+            ],
+        );
+        assert_eq!(
+            query_mixed(SuggestionQuery::with_providers(
+                "mix",
+                vec![SuggestionProvider::Mdn, SuggestionProvider::Amp],
+            )),
+            vec![
+                array_suggestion(),
+                los_pollos.clone(),
+                good_place_eats.clone()
+            ],
+        );
+        assert_eq!(
+            query_mixed(SuggestionQuery::with_providers(
+                "mix",
+                vec![SuggestionProvider::Amp, SuggestionProvider::Wikipedia],
+            )),
+            vec![los_pollos.clone(), california_suggestion("mix")],
+        );
+        // Per-provider limits.
+        assert_eq!(
+            query_mixed(
+                SuggestionQuery::with_providers(
+                    "mix",
+                    vec![SuggestionProvider::Amp, SuggestionProvider::Mdn],
+                )
+                .provider_limit(SuggestionProvider::Amp, 1)
+            ),
+            vec![los_pollos.clone(), array_suggestion()],
+        );
+        // The overall limit.
+        assert_eq!(
+            query_mixed(
+                SuggestionQuery::with_providers(
+                    "mix",
+                    vec![SuggestionProvider::Mdn, SuggestionProvider::Amp],
+                )
+                .limit(2)
+            ),
+            vec![array_suggestion(), los_pollos],
+        );
+
+        Ok(())
+    }
+
     // Tests querying multiple suggestions with multiple keywords with same prefix keyword
     #[test]
     fn query_with_amp_mobile_provider() -> anyhow::Result<()> {
@@ -86,6 +86,10 @@ pub trait GetErrorHandling {
 
     /// Return how to handle our internal errors
     fn get_error_handling(&self) -> ErrorHandling<Self::ExternalError>;
+
+    /// Called with each error `convert_log_report_error` converts, for components which
+    /// keep track of their own errors. Does nothing by default.
+    fn note_error(&self) {}
 }
 
 /// Handle the specified "internal" error, taking any logging or error
@@ -96,6 +100,7 @@ where
     IE: GetErrorHandling<ExternalError = EE> + std::error::Error,
     EE: std::error::Error,
 {
+    e.note_error();
     let handling = e.get_error_handling();
     let reporting = handling.reporting;
     if let Some(level) = reporting.log_level {
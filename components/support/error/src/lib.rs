@@ -30,7 +30,7 @@ pub use redact::*;
 mod reporting;
 pub use reporting::{
     report_breadcrumb, report_error_to_app, set_application_error_reporter,
-    unset_application_error_reporter, ApplicationErrorReporter,
+    set_error_report_rate_limit, unset_application_error_reporter, ApplicationErrorReporter,
 };
 
 pub use error_support_macros::handle_error;
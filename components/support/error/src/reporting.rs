@@ -2,8 +2,13 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+/// The window over which `set_error_report_rate_limit()` counts reports.
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60 * 60);
 
 /// Counter for breadcrumb messages
 ///
@@ -47,6 +52,69 @@ impl ApplicationErrorReporter for DefaultApplicationErrorReporter {
 lazy_static::lazy_static! {
     // RwLock rather than a Mutex, since we only expect to set this once.
     pub(crate) static ref APPLICATION_ERROR_REPORTER: RwLock<Box<dyn ApplicationErrorReporter>> = RwLock::new(Box::new(DefaultApplicationErrorReporter));
+    static ref ERROR_REPORT_LIMITER: Mutex<ReportLimiter> = Mutex::new(ReportLimiter::default());
+}
+
+/// Limits how many reports of each type get sent to the app, so that a broken hot path doesn't
+/// flood the error reporting system.
+///
+/// Each type can send `max_per_hour` reports per hour.  After that, only the 2nd, 4th, 8th, etc.
+/// report in the same hour goes through.  The number of reports suppressed in between is added to
+/// the next report of that type which does.
+#[derive(Default)]
+struct ReportLimiter {
+    // `None` means reports aren't limited, which is the default.
+    max_per_hour: Option<u32>,
+    types: HashMap<String, ReportTypeState>,
+}
+
+struct ReportTypeState {
+    window_start: Instant,
+    // Reports seen in the current window, including suppressed ones.
+    seen: u32,
+    // Suppressed reports which haven't been mentioned in a report yet.
+    suppressed: u32,
+}
+
+impl ReportLimiter {
+    /// Returns `None` if the report should be suppressed, otherwise the number of reports of this
+    /// type suppressed since the last one that went through.
+    fn check(&mut self, type_name: &str, now: Instant) -> Option<u32> {
+        let max_per_hour = match self.max_per_hour {
+            Some(max_per_hour) => max_per_hour,
+            None => return Some(0),
+        };
+        let state = self
+            .types
+            .entry(type_name.to_owned())
+            .or_insert_with(|| ReportTypeState {
+                window_start: now,
+                seen: 0,
+                suppressed: 0,
+            });
+        if now.saturating_duration_since(state.window_start) >= RATE_LIMIT_WINDOW {
+            // Start a new window, but keep the suppressed count so the next report includes it.
+            state.window_start = now;
+            state.seen = 0;
+        }
+        state.seen = state.seen.saturating_add(1);
+        let over_limit = state.seen.saturating_sub(max_per_hour);
+        if over_limit == 0 || (over_limit >= 2 && over_limit.is_power_of_two()) {
+            Some(std::mem::take(&mut state.suppressed))
+        } else {
+            state.suppressed = state.suppressed.saturating_add(1);
+            None
+        }
+    }
+}
+
+/// Limit each error report type to `max_per_hour` reports per hour, with reports past that
+/// limit sampled at exponentially decreasing rates.  Pass `None` to report everything, which is
+/// the default.
+pub fn set_error_report_rate_limit(max_per_hour: Option<u32>) {
+    let mut limiter = ERROR_REPORT_LIMITER.lock();
+    limiter.max_per_hour = max_per_hour;
+    limiter.types.clear();
 }
 
 pub fn set_application_error_reporter(reporter: Box<dyn ApplicationErrorReporter>) {
@@ -58,6 +126,18 @@ pub fn unset_application_error_reporter() {
 }
 
 pub fn report_error_to_app(type_name: String, message: String) {
+    let suppressed = match ERROR_REPORT_LIMITER
+        .lock()
+        .check(&type_name, Instant::now())
+    {
+        Some(suppressed) => suppressed,
+        None => return,
+    };
+    let message = if suppressed > 0 {
+        format!("{} ({} similar reports suppressed)", message, suppressed)
+    } else {
+        message
+    };
     APPLICATION_ERROR_REPORTER
         .read()
         .report_error(type_name, message);
@@ -69,3 +149,75 @@ pub fn report_breadcrumb(message: String, module: String, line: u32, column: u32
         .read()
         .report_breadcrumb(message, module, line, column);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn limiter(max_per_hour: u32) -> ReportLimiter {
+        ReportLimiter {
+            max_per_hour: Some(max_per_hour),
+            ..ReportLimiter::default()
+        }
+    }
+
+    #[test]
+    fn test_unlimited_by_default() {
+        let mut limiter = ReportLimiter::default();
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert_eq!(limiter.check("foo", now), Some(0));
+        }
+    }
+
+    #[test]
+    fn test_exponential_suppression() {
+        let mut limiter = limiter(2);
+        let now = Instant::now();
+        let results: Vec<_> = (0..20).map(|_| limiter.check("foo", now)).collect();
+        assert_eq!(
+            results,
+            [
+                // The first 2 go through.
+                Some(0),
+                Some(0),
+                // Then only the 2nd, 4th, 8th and 16th past the limit.
+                None,
+                Some(1),
+                None,
+                Some(1),
+                None,
+                None,
+                None,
+                Some(3),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(7),
+                None,
+                None,
+            ]
+        );
+        // Other types are counted separately.
+        assert_eq!(limiter.check("bar", now), Some(0));
+    }
+
+    #[test]
+    fn test_window_reset() {
+        let mut limiter = limiter(1);
+        let now = Instant::now();
+        assert_eq!(limiter.check("foo", now), Some(0));
+        assert_eq!(limiter.check("foo", now), None);
+        assert_eq!(limiter.check("foo", now), Some(1));
+        assert_eq!(limiter.check("foo", now + Duration::from_secs(60)), None);
+        // An hour later the type can report again, and that report includes the one suppressed
+        // in the previous hour.
+        let later = now + RATE_LIMIT_WINDOW;
+        assert_eq!(limiter.check("foo", later), Some(1));
+        assert_eq!(limiter.check("foo", later), None);
+    }
+}
@@ -6,6 +6,7 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use crate::{
     command_line::commands::GenerateExperimenterManifestCmd,
@@ -28,6 +29,7 @@ pub(crate) struct ExperimenterFeature {
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub(crate) struct ExperimenterFeatureProperty {
     #[serde(rename = "type")]
     property_type: String,
@@ -36,6 +38,21 @@ pub(crate) struct ExperimenterFeatureProperty {
     #[serde(rename = "enum")]
     #[serde(skip_serializing_if = "Option::is_none")]
     variants: Option<BTreeSet<String>>,
+
+    // The rest is documentation, so Experimenter can show more than a JSON editor.
+    /// The type as it's written in the FML, e.g. `Map<HomeScreenSection, Boolean>`,
+    /// where `type` would just be `json`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fml_type: Option<String>,
+
+    /// The default value, as an example of what the variable looks like.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default_value: Option<Value>,
+
+    /// The documentation for each of the enums used anywhere in the type, keyed by
+    /// enum name and then variant name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    enum_values: BTreeMap<String, BTreeMap<String, String>>,
 }
 
 impl TryFrom<FeatureManifest> for ExperimenterManifest {
@@ -71,34 +88,59 @@ impl FeatureManifest {
         props.iter().try_for_each(|prop| -> Result<()> {
             let typ = ExperimentManifestPropType::from(prop.typ()).to_string();
 
-            let yaml_prop = if let TypeRef::Enum(e) = prop.typ() {
-                let enum_def = self
-                    .find_enum(&e)
-                    .ok_or(FMLError::InternalError("Found enum with no definition"))?;
+            let mut enum_values = BTreeMap::new();
+            self.collect_enum_values(&prop.typ(), &mut enum_values)?;
 
-                let variants = enum_def
-                    .variants
-                    .iter()
-                    .map(|variant| variant.name())
-                    .collect::<BTreeSet<String>>();
-
-                ExperimenterFeatureProperty {
-                    variants: Some(variants),
-                    description: prop.doc(),
-                    property_type: typ,
-                }
+            let variants = if let TypeRef::Enum(e) = prop.typ() {
+                enum_values
+                    .get(&e)
+                    .map(|docs| docs.keys().cloned().collect::<BTreeSet<String>>())
             } else {
-                ExperimenterFeatureProperty {
-                    variants: None,
-                    description: prop.doc(),
-                    property_type: typ,
-                }
+                None
+            };
+
+            let yaml_prop = ExperimenterFeatureProperty {
+                variants,
+                description: prop.doc(),
+                property_type: typ,
+                fml_type: Some(prop.typ().to_string()),
+                default_value: Some(prop.default()),
+                enum_values,
             };
             map.insert(prop.name(), yaml_prop);
             Ok(())
         })?;
         Ok(map)
     }
+
+    fn collect_enum_values(
+        &self,
+        typ: &TypeRef,
+        enum_values: &mut BTreeMap<String, BTreeMap<String, String>>,
+    ) -> Result<()> {
+        match typ {
+            TypeRef::Enum(e) => {
+                let enum_def = self
+                    .find_enum(e)
+                    .ok_or(FMLError::InternalError("Found enum with no definition"))?;
+                let docs = enum_def
+                    .variants
+                    .iter()
+                    .map(|variant| (variant.name(), variant.doc()))
+                    .collect();
+                enum_values.insert(e.clone(), docs);
+            }
+            TypeRef::EnumMap(k, v) => {
+                self.collect_enum_values(k, enum_values)?;
+                self.collect_enum_values(v, enum_values)?;
+            }
+            TypeRef::StringMap(v) | TypeRef::List(v) | TypeRef::Option(v) => {
+                self.collect_enum_values(v, enum_values)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
 }
 
 enum ExperimentManifestPropType {
@@ -25,6 +25,7 @@ impl From<FeatureManifest> for ManifestFrontEnd {
             about: Some(about),
             version: "1.0.0".to_string(),
             channels,
+            locales: value.locales,
             includes: Default::default(),
             imports: Default::default(),
             features,
@@ -144,6 +145,8 @@ impl From<PropDef> for FeatureFieldBody {
         Self {
             pref_key: value.pref_key.clone(),
             string_alias: value.string_alias.as_ref().map(TypeRef::to_string),
+            localized: value.localized,
+            translations: value.translations.clone(),
             field: value.into(),
         }
     }
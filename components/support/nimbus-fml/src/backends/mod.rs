@@ -276,3 +276,4 @@ pub(crate) mod frontend_manifest;
 pub(crate) mod info;
 pub(crate) mod kotlin;
 pub(crate) mod swift;
+pub(crate) mod xliff;
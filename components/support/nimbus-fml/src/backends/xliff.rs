@@ -0,0 +1,126 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Extracts the variables marked `localized: true` into an XLIFF 1.2 file, so
+//! the copy used in experiments can be sent for translation.
+//!
+//! There is one `<file>` element for each of the manifest's `locales`, with the
+//! feature's default as the `<source>`, and the translation, if there is one,
+//! as the `<target>`. If the manifest doesn't declare any locales, there is a
+//! single `<file>` with only the source strings.
+
+use std::fmt::Write;
+
+use crate::{
+    command_line::commands::ExtractStringsCmd,
+    error::Result,
+    intermediate_representation::{FeatureManifest, PropDef},
+};
+
+/// The language the defaults in a manifest are written in.
+const SOURCE_LANGUAGE: &str = "en-US";
+
+pub(crate) fn generate_xliff(ir: &FeatureManifest, cmd: &ExtractStringsCmd) -> Result<()> {
+    std::fs::write(&cmd.output, to_xliff(ir))?;
+    Ok(())
+}
+
+pub(crate) fn to_xliff(ir: &FeatureManifest) -> String {
+    // Strings are keyed by `feature-id.variable-name`.
+    let mut strings: Vec<(String, &PropDef)> = ir
+        .iter_all_feature_defs()
+        .flat_map(|(_, f)| {
+            f.props
+                .iter()
+                .filter(|p| p.localized)
+                .map(move |p| (format!("{}.{}", f.name, p.name), p))
+        })
+        .collect();
+    strings.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let locales = if ir.locales.is_empty() {
+        vec![None]
+    } else {
+        ir.locales.iter().map(Some).collect()
+    };
+
+    let original = escape(&ir.id.to_string());
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<xliff xmlns=\"urn:oasis:names:tc:xliff:document:1.2\" version=\"1.2\">\n");
+    for locale in locales {
+        let _ = write!(
+            out,
+            "  <file original=\"{original}\" datatype=\"plaintext\" source-language=\"{SOURCE_LANGUAGE}\""
+        );
+        if let Some(locale) = locale {
+            let _ = write!(out, " target-language=\"{}\"", escape(locale));
+        }
+        out.push_str(">\n    <body>\n");
+        for (id, prop) in &strings {
+            let _ = writeln!(out, "      <trans-unit id=\"{}\">", escape(id));
+            let source = prop.default.as_str().unwrap_or_default();
+            let _ = writeln!(out, "        <source>{}</source>", escape(source));
+            if let Some(target) = locale.and_then(|l| prop.translations.get(l)) {
+                let _ = writeln!(out, "        <target>{}</target>", escape(target));
+            }
+            if !prop.doc.is_empty() {
+                let _ = writeln!(out, "        <note>{}</note>", escape(&prop.doc));
+            }
+            out.push_str("      </trans-unit>\n");
+        }
+        out.push_str("    </body>\n  </file>\n");
+    }
+    out.push_str("</xliff>\n");
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::intermediate_representation::{FeatureDef, TypeRef};
+
+    #[test]
+    fn test_to_xliff() {
+        let mut title = PropDef::with_doc(
+            "title",
+            "The <b>title</b>",
+            &TypeRef::String,
+            &json!("Hi & bye"),
+        );
+        title.localized = true;
+        title.translations = [("de".to_string(), "Hallo & tschüss".to_string())].into();
+        let count = PropDef::new("count", &TypeRef::Int, &json!(1));
+
+        let mut fm = FeatureManifest::default();
+        fm.add_feature(FeatureDef::new("onboarding", "", vec![title, count], false));
+        fm.locales = vec!["de".to_string(), "fr".to_string()];
+
+        let xliff = to_xliff(&fm);
+        assert_eq!(xliff.matches("<file ").count(), 2);
+        assert!(xliff.contains("target-language=\"de\""));
+        assert!(xliff.contains("<trans-unit id=\"onboarding.title\">"));
+        assert!(xliff.contains("<source>Hi &amp; bye</source>"));
+        assert!(xliff.contains("<target>Hallo &amp; tschüss</target>"));
+        assert_eq!(xliff.matches("<target>").count(), 1);
+        assert!(xliff.contains("<note>The &lt;b&gt;title&lt;/b&gt;</note>"));
+        assert!(!xliff.contains("count"));
+
+        // With no locales, there are only source strings.
+        fm.locales = vec![];
+        let xliff = to_xliff(&fm);
+        assert_eq!(xliff.matches("<file ").count(), 1);
+        assert!(!xliff.contains("target-language"));
+        assert!(!xliff.contains("<target>"));
+    }
+}
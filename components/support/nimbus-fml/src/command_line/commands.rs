@@ -12,6 +12,7 @@ pub(crate) enum CliCmd {
     Generate(GenerateStructCmd),
     GenerateExperimenter(GenerateExperimenterManifestCmd),
     GenerateSingleFileManifest(GenerateSingleFileManifestCmd),
+    ExtractStrings(ExtractStringsCmd),
     FetchFile(LoaderConfig, String),
     Validate(ValidateCmd),
     PrintChannels(PrintChannelsCmd),
@@ -43,6 +44,13 @@ pub(crate) struct GenerateSingleFileManifestCmd {
     pub(crate) loader: LoaderConfig,
 }
 
+pub(crate) struct ExtractStringsCmd {
+    pub(crate) manifest: String,
+    pub(crate) output: PathBuf,
+    pub(crate) channel: Option<String>,
+    pub(crate) loader: LoaderConfig,
+}
+
 pub(crate) struct ValidateCmd {
     pub(crate) manifest: String,
     pub(crate) loader: LoaderConfig,
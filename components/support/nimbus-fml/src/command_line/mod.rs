@@ -10,8 +10,8 @@ use crate::util::loaders::LoaderConfig;
 use anyhow::{bail, Result};
 use clap::{App, ArgMatches};
 use commands::{
-    CliCmd, GenerateExperimenterManifestCmd, GenerateSingleFileManifestCmd, GenerateStructCmd,
-    PrintChannelsCmd, ValidateCmd,
+    CliCmd, ExtractStringsCmd, GenerateExperimenterManifestCmd, GenerateSingleFileManifestCmd,
+    GenerateStructCmd, PrintChannelsCmd, ValidateCmd,
 };
 
 use std::{
@@ -40,6 +40,7 @@ fn process_command(cmd: &CliCmd) -> Result<()> {
         CliCmd::GenerateSingleFileManifest(params) => {
             workflows::generate_single_file_manifest(params)?
         }
+        CliCmd::ExtractStrings(params) => workflows::extract_strings(params)?,
         CliCmd::FetchFile(files, nm) => workflows::fetch_file(files, nm)?,
         CliCmd::Validate(params) => workflows::validate(params)?,
         CliCmd::PrintChannels(params) => workflows::print_channels(params)?,
@@ -63,6 +64,9 @@ where
         ("generate-experimenter", Some(matches)) => CliCmd::GenerateExperimenter(
             create_generate_command_experimenter_from_cli(matches, cwd)?,
         ),
+        ("extract-strings", Some(matches)) => {
+            CliCmd::ExtractStrings(create_extract_strings_from_cli(matches, cwd)?)
+        }
         ("fetch", Some(matches)) => {
             CliCmd::FetchFile(create_loader(matches, cwd)?, input_file(matches)?)
         }
@@ -100,6 +104,20 @@ fn create_single_file_from_cli(
     })
 }
 
+fn create_extract_strings_from_cli(matches: &ArgMatches, cwd: &Path) -> Result<ExtractStringsCmd> {
+    let manifest = input_file(matches)?;
+    let output =
+        file_path("output", matches, cwd).or_else(|_| file_path("OUTPUT", matches, cwd))?;
+    let channel = matches.value_of("channel").map(str::to_string);
+    let loader = create_loader(matches, cwd)?;
+    Ok(ExtractStringsCmd {
+        manifest,
+        output,
+        channel,
+        loader,
+    })
+}
+
 fn create_generate_command_experimenter_from_cli(
     matches: &ArgMatches,
     cwd: &Path,
@@ -163,6 +181,8 @@ fn create_loader(matches: &ArgMatches, cwd: &Path) -> Result<LoaderConfig> {
 
     let _ref = matches.value_of("ref").map(String::from);
 
+    let resolution_report = matches.value_of("resolution-report").map(|f| cwd.join(f));
+
     let mut refs: BTreeMap<_, _> = Default::default();
     match (LoaderConfig::repo_and_path(&manifest), _ref) {
         (Some((repo, _)), Some(ref_)) => refs.insert(repo, ref_),
@@ -174,6 +194,7 @@ fn create_loader(matches: &ArgMatches, cwd: &Path) -> Result<LoaderConfig> {
         repo_files,
         cwd,
         refs,
+        resolution_report,
     })
 }
 
@@ -563,6 +584,36 @@ mod cli_tests {
         Ok(())
     }
 
+    ///////////////////////////////////////////////////////////////////////////
+    #[test]
+    fn test_cli_extract_strings_command() -> Result<()> {
+        let cwd = package_dir()?;
+        let cmd = get_command_from_cli(
+            [FML_BIN, "extract-strings", TEST_FILE, "./strings.xlf"],
+            &cwd,
+        )?;
+
+        assert!(
+            matches!(&cmd, CliCmd::ExtractStrings(c) if c.manifest.ends_with(TEST_FILE) && c.output.ends_with("strings.xlf") && c.channel.is_none())
+        );
+
+        let cmd = get_command_from_cli(
+            [
+                FML_BIN,
+                "extract-strings",
+                "--channel",
+                "beta",
+                TEST_FILE,
+                "./strings.xlf",
+            ],
+            &cwd,
+        )?;
+        assert!(
+            matches!(&cmd, CliCmd::ExtractStrings(ExtractStringsCmd { channel: Some(channel), .. }) if channel.as_str() == "beta")
+        );
+        Ok(())
+    }
+
     ///////////////////////////////////////////////////////////////////////////
     #[test]
     fn test_cli_print_info_command() -> Result<()> {
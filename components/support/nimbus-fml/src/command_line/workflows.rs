@@ -6,8 +6,8 @@ use glob::MatchOptions;
 use std::collections::HashSet;
 
 use super::commands::{
-    GenerateExperimenterManifestCmd, GenerateSingleFileManifestCmd, GenerateStructCmd,
-    PrintChannelsCmd, PrintInfoCmd, ValidateCmd,
+    ExtractStringsCmd, GenerateExperimenterManifestCmd, GenerateSingleFileManifestCmd,
+    GenerateStructCmd, PrintChannelsCmd, PrintInfoCmd, ValidateCmd,
 };
 use crate::backends::info::ManifestInfo;
 use crate::error::FMLError::CliError;
@@ -39,7 +39,8 @@ pub(crate) fn generate_struct(cmd: &GenerateStructCmd) -> Result<()> {
         _ => Err(FMLError::CliError(
             "Cannot generate a single output file from an input directory".to_string(),
         )),
-    }
+    }?;
+    files.write_resolution_report()
 }
 
 fn generate_struct_from_dir(files: &FileLoader, cmd: &GenerateStructCmd, cwd: &Path) -> Result<()> {
@@ -107,18 +108,25 @@ fn generate_struct_from_ir(ir: &FeatureManifest, cmd: &GenerateStructCmd) -> Res
 pub(crate) fn generate_experimenter_manifest(cmd: &GenerateExperimenterManifestCmd) -> Result<()> {
     let files: FileLoader = TryFrom::try_from(&cmd.loader)?;
     let path = files.file_path(&cmd.manifest)?;
-    let ir = load_feature_manifest(files, path, cmd.load_from_ir, None)?;
+    let ir = load_feature_manifest(files.clone(), path, cmd.load_from_ir, None)?;
     backends::experimenter_manifest::generate_manifest(ir, cmd)?;
-    Ok(())
+    files.write_resolution_report()
 }
 
 pub(crate) fn generate_single_file_manifest(cmd: &GenerateSingleFileManifestCmd) -> Result<()> {
     let files: FileLoader = TryFrom::try_from(&cmd.loader)?;
     let path = files.file_path(&cmd.manifest)?;
-    let fm = load_feature_manifest(files, path, false, Some(&cmd.channel))?;
+    let fm = load_feature_manifest(files.clone(), path, false, Some(&cmd.channel))?;
     let frontend: ManifestFrontEnd = fm.into();
     std::fs::write(&cmd.output, serde_yaml::to_string(&frontend)?)?;
-    Ok(())
+    files.write_resolution_report()
+}
+
+pub(crate) fn extract_strings(cmd: &ExtractStringsCmd) -> Result<()> {
+    let files: FileLoader = TryFrom::try_from(&cmd.loader)?;
+    let path = files.file_path(&cmd.manifest)?;
+    let ir = load_feature_manifest(files, path, false, cmd.channel.as_deref())?;
+    backends::xliff::generate_xliff(&ir, cmd)
 }
 
 fn load_feature_manifest(
@@ -509,6 +517,34 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_experimenter_manifest_variable_docs() -> Result<()> {
+        let cmd = create_experimenter_manifest_cmd("fixtures/fe/importing/simple/app.yaml")?;
+        let files = FileLoader::default()?;
+        let path = files.file_path(&cmd.manifest)?;
+        let fm = load_feature_manifest(files, path, cmd.load_from_ir, None)?;
+        let m: ExperimenterManifest = fm.try_into()?;
+        let m = serde_json::to_value(m)?;
+
+        let variable = &m["homescreen"]["variables"]["sections-enabled"];
+        assert_eq!(variable["type"], "json");
+        assert_eq!(variable["fmlType"], "Map<HomeScreenSection, Boolean>");
+        assert_eq!(variable["defaultValue"]["top-sites"], true);
+        // Only variables which are a single enum can be validated with `enum`, but the
+        // documentation for the enums used in the map is still there.
+        assert!(variable.get("enum").is_none());
+        let variants = variable["enumValues"]["HomeScreenSection"]
+            .as_object()
+            .unwrap();
+        assert_eq!(variants.len(), 6);
+        assert_eq!(
+            variants["pocket"],
+            "The pocket section. This should only be available in the US."
+        );
+
+        Ok(())
+    }
+
     fn validate_against_experimenter_schema<P: AsRef<Path>>(
         schema_path: P,
         generated_yaml: &serde_yaml::Value,
@@ -673,6 +709,41 @@ mod test {
         test_single_merged_manifest_file("fixtures/fe/importing/diamond/02-sublib.yaml", "debug")?;
 
         test_single_merged_manifest_file("fixtures/fe/misc-features.yaml", "debug")?;
+        test_single_merged_manifest_file("fixtures/fe/localized-strings.fml.yaml", "release")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_strings_command() -> Result<()> {
+        fs::create_dir_all(generated_src_dir())?;
+        let output: PathBuf = join(generated_src_dir(), "localized-strings.xlf").into();
+        let cmd = ExtractStringsCmd {
+            manifest: join(pkg_dir(), "fixtures/fe/localized-strings.fml.yaml"),
+            output: output.clone(),
+            channel: None,
+            loader: Default::default(),
+        };
+        extract_strings(&cmd)?;
+
+        let xliff = fs::read_to_string(output)?;
+        assert_eq!(xliff.matches("<file ").count(), 2);
+        assert!(xliff.contains("<trans-unit id=\"onboarding.button-label\">"));
+        assert!(xliff.contains("<source>Welcome to Firefox</source>"));
+        assert!(xliff.contains("<target>Bienvenue dans Firefox</target>"));
+        assert!(!xliff.contains("card-count"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_translations_are_invalid() -> Result<()> {
+        let files = FileLoader::default()?;
+        let path = files.file_path(&join(pkg_dir(), "fixtures/fe/localized-strings.fml.yaml"))?;
+        let mut fm = load_feature_manifest(files, path, false, Some("release"))?;
+        fm.locales.push("es".to_string());
+        let err = fm.validate_manifest().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Missing translations for locales: es"));
         Ok(())
     }
 }
@@ -54,6 +54,8 @@ impl PropDef {
             doc: format!("{nm} property of type {typ}"),
             pref_key: None,
             string_alias: None,
+            localized: false,
+            translations: Default::default(),
         }
     }
 
@@ -65,6 +67,8 @@ impl PropDef {
             doc: nm.to_string(),
             pref_key: None,
             string_alias: Some(sa.clone()),
+            localized: false,
+            translations: Default::default(),
         }
     }
 
@@ -76,6 +80,8 @@ impl PropDef {
             default: default.clone(),
             pref_key: None,
             string_alias: None,
+            localized: false,
+            translations: Default::default(),
         }
     }
 }
@@ -46,6 +46,17 @@ pub(crate) struct FeatureFieldBody {
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) string_alias: Option<String>,
+
+    /// Marks a user-facing `String` or `Text` variable as needing to be
+    /// translated. Its default is the source string.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) localized: bool,
+
+    /// The translations of a localized variable's default, keyed by locale.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) translations: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -279,6 +290,11 @@ pub struct ManifestFrontEnd {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub(crate) channels: Vec<String>,
 
+    // The locales that every localized variable must be translated into.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) locales: Vec<String>,
+
     #[serde(default)]
     #[serde(alias = "include")]
     #[serde(skip_serializing_if = "Vec::is_empty")]
@@ -349,6 +365,8 @@ impl ManifestFrontEnd {
         if let Some(s) = &body.string_alias {
             prop.string_alias = Some(TypeRef::StringAlias(s.clone()));
         }
+        prop.localized = body.localized;
+        prop.translations = body.translations.clone();
         prop
     }
 
@@ -381,6 +399,8 @@ impl ManifestFrontEnd {
             default: json!(body.default),
             pref_key: None,
             string_alias: None,
+            localized: false,
+            translations: Default::default(),
         }
     }
 
@@ -478,14 +498,10 @@ impl ManifestFrontEnd {
             None => Default::default(),
         };
 
-        Ok(FeatureManifest::new(
-            id.clone(),
-            channel,
-            features,
-            enums,
-            objects,
-            about,
-        ))
+        let mut manifest =
+            FeatureManifest::new(id.clone(), channel, features, enums, objects, about);
+        manifest.locales = self.locales.clone();
+        Ok(manifest)
     }
 }
 
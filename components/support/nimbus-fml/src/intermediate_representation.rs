@@ -7,7 +7,7 @@ use crate::error::{FMLError, Result};
 use crate::frontend::{
     AboutBlock, ExampleBlock, FeatureExampleMetadata, FeatureMetadata, InlineExampleBlock,
 };
-use crate::schema::{SchemaHasher, SchemaValidator, TypeQuery};
+use crate::schema::{IdentifierValidator, SchemaHasher, SchemaValidator, TypeQuery};
 use crate::util::loaders::FilePath;
 use anyhow::{bail, Error, Result as AnyhowResult};
 use serde::{Deserialize, Serialize};
@@ -236,6 +236,10 @@ pub struct FeatureManifest {
     #[serde(default)]
     pub(crate) about: AboutBlock,
 
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) locales: Vec<String>,
+
     #[serde(default)]
     pub(crate) imported_features: HashMap<ModuleId, BTreeSet<String>>,
 
@@ -322,6 +326,19 @@ impl FeatureManifest {
         for object in self.iter_object_defs() {
             validator.validate_object_def(object)?;
         }
+        for feature_def in self.iter_feature_defs() {
+            validator.validate_feature_def(feature_def)?;
+            validator.validate_feature_localizations(feature_def, &self.locales)?;
+        }
+
+        // Check the names will make legal identifiers in the generated code.
+        let validator = IdentifierValidator;
+        for enum_def in self.iter_enum_defs() {
+            validator.validate_enum_def(enum_def)?;
+        }
+        for object in self.iter_object_defs() {
+            validator.validate_object_def(object)?;
+        }
         for feature_def in self.iter_feature_defs() {
             validator.validate_feature_def(feature_def)?;
         }
@@ -662,6 +679,12 @@ pub struct PropDef {
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) string_alias: Option<TypeRef>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) localized: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) translations: BTreeMap<String, String>,
 }
 
 impl PropDef {
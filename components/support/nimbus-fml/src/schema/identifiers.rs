@@ -0,0 +1,359 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Checks that the names in a manifest make legal identifiers in the generated Kotlin and Swift.
+//!
+//! Without this, a variable called `in`, or an enum variant called `default`, would only be
+//! caught when the app failed to compile the generated code.
+
+use crate::error::{FMLError, Result};
+use crate::intermediate_representation::{EnumDef, FeatureDef, ObjectDef, PropDef};
+use heck::{CamelCase, MixedCase, ShoutySnakeCase};
+use std::collections::HashMap;
+
+// Hard keywords, which can't be used as identifiers without backticks.
+// https://kotlinlang.org/docs/keyword-reference.html#hard-keywords
+const KOTLIN_KEYWORDS: &[&str] = &[
+    "as",
+    "break",
+    "class",
+    "continue",
+    "do",
+    "else",
+    "false",
+    "for",
+    "fun",
+    "if",
+    "in",
+    "interface",
+    "is",
+    "null",
+    "object",
+    "package",
+    "return",
+    "super",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typealias",
+    "typeof",
+    "val",
+    "var",
+    "when",
+    "while",
+];
+
+// https://docs.swift.org/swift-book/documentation/the-swift-programming-language/lexicalstructure/#Keywords-and-Punctuation
+const SWIFT_KEYWORDS: &[&str] = &[
+    "Any",
+    "Self",
+    "Type",
+    "as",
+    "associatedtype",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "continue",
+    "default",
+    "defer",
+    "deinit",
+    "do",
+    "else",
+    "enum",
+    "extension",
+    "fallthrough",
+    "false",
+    "fileprivate",
+    "for",
+    "func",
+    "guard",
+    "if",
+    "import",
+    "in",
+    "init",
+    "inout",
+    "internal",
+    "is",
+    "let",
+    "nil",
+    "open",
+    "operator",
+    "precedencegroup",
+    "private",
+    "protocol",
+    "public",
+    "repeat",
+    "rethrows",
+    "return",
+    "self",
+    "static",
+    "struct",
+    "subscript",
+    "super",
+    "switch",
+    "throw",
+    "throws",
+    "true",
+    "try",
+    "typealias",
+    "var",
+    "where",
+    "while",
+];
+
+#[derive(Clone, Copy)]
+enum Language {
+    Kotlin,
+    Swift,
+}
+
+impl Language {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Kotlin => "Kotlin",
+            Self::Swift => "Swift",
+        }
+    }
+
+    fn keywords(&self) -> &'static [&'static str] {
+        match self {
+            Self::Kotlin => KOTLIN_KEYWORDS,
+            Self::Swift => SWIFT_KEYWORDS,
+        }
+    }
+}
+
+const LANGUAGES: [Language; 2] = [Language::Kotlin, Language::Swift];
+
+// What a name is used for, which decides how it's rendered in each language. These need to be
+// kept in step with the `class_name`, `var_name` and `enum_variant_name` in each backend.
+#[derive(Clone, Copy)]
+enum IdentifierKind {
+    Type,
+    Variable,
+    EnumVariant,
+}
+
+impl IdentifierKind {
+    fn render(&self, lang: Language, nm: &str) -> String {
+        match (self, lang) {
+            (Self::Type, _) => nm.to_camel_case(),
+            (Self::Variable, _) => nm.to_mixed_case(),
+            (Self::EnumVariant, Language::Kotlin) => nm.to_shouty_snake_case(),
+            (Self::EnumVariant, Language::Swift) => nm.to_mixed_case(),
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Self::Type => "type",
+            Self::Variable => "variable",
+            Self::EnumVariant => "enum variant",
+        }
+    }
+}
+
+pub(crate) struct IdentifierValidator;
+
+impl IdentifierValidator {
+    pub(crate) fn validate_enum_def(&self, enum_def: &EnumDef) -> Result<()> {
+        let enum_nm = &enum_def.name;
+        let path = format!("enums/{enum_nm}");
+        self.validate_identifier(&path, enum_nm, IdentifierKind::Type)?;
+        self.validate_names(
+            &path,
+            enum_def.variants.iter().map(|v| v.name.as_str()),
+            IdentifierKind::EnumVariant,
+        )
+    }
+
+    pub(crate) fn validate_object_def(&self, object_def: &ObjectDef) -> Result<()> {
+        let obj_nm = &object_def.name;
+        let path = format!("objects/{obj_nm}");
+        self.validate_identifier(&path, obj_nm, IdentifierKind::Type)?;
+        self.validate_props(&path, &object_def.props)
+    }
+
+    pub(crate) fn validate_feature_def(&self, feature_def: &FeatureDef) -> Result<()> {
+        let feat_nm = &feature_def.name;
+        let path = format!("features/{feat_nm}");
+        self.validate_identifier(&path, feat_nm, IdentifierKind::Type)?;
+        self.validate_props(&path, &feature_def.props)
+    }
+
+    fn validate_props(&self, path: &str, props: &[PropDef]) -> Result<()> {
+        self.validate_names(
+            path,
+            props.iter().map(|p| p.name.as_str()),
+            IdentifierKind::Variable,
+        )
+    }
+
+    // Checks each of the names, and that no two of them end up as the same identifier.
+    fn validate_names<'a>(
+        &self,
+        path: &str,
+        names: impl Iterator<Item = &'a str>,
+        kind: IdentifierKind,
+    ) -> Result<()> {
+        let mut seen: HashMap<String, &str> = Default::default();
+        for nm in names {
+            self.validate_identifier(&format!("{path}/{nm}"), nm, kind)?;
+            for lang in LANGUAGES {
+                let rendered = kind.render(lang, nm);
+                if let Some(other) = seen.insert(format!("{}:{rendered}", lang.name()), nm) {
+                    if other != nm {
+                        return Err(FMLError::ValidationError(
+                            format!("{path}/{nm}"),
+                            format!(
+                                "The {kind} names `{other}` and `{nm}` would both become `{rendered}` in {lang}. Rename one of them, for example to `{suggestion}`",
+                                kind = kind.description(),
+                                lang = lang.name(),
+                                suggestion = suggest_rename(&format!("{nm}-2"), kind),
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_identifier(&self, path: &str, nm: &str, kind: IdentifierKind) -> Result<()> {
+        for lang in LANGUAGES {
+            let rendered = kind.render(lang, nm);
+            let problem = if !is_legal_identifier(&rendered) {
+                "which isn't a legal identifier"
+            } else if lang.keywords().contains(&rendered.as_str()) {
+                "which is a reserved word"
+            } else {
+                continue;
+            };
+            return Err(FMLError::ValidationError(
+                path.to_string(),
+                format!(
+                    "The {kind} name `{nm}` would become `{rendered}` in {lang}, {problem}. Try renaming it to `{suggestion}`",
+                    kind = kind.description(),
+                    lang = lang.name(),
+                    suggestion = suggest_rename(nm, kind),
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn is_legal_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => chars.all(|c| c.is_alphanumeric() || c == '_'),
+        _ => false,
+    }
+}
+
+// A name like `nm`, which is legal in every language.
+fn suggest_rename(nm: &str, kind: IdentifierKind) -> String {
+    let mut suggestion = nm
+        .split(|c: char| !c.is_alphanumeric() && c != '-' && c != '_')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if !suggestion.starts_with(char::is_alphabetic) {
+        suggestion = format!("{}-{suggestion}", kind.description().replace(' ', "-"));
+    }
+    let is_reserved = |s: &str| {
+        LANGUAGES
+            .iter()
+            .any(|lang| lang.keywords().contains(&kind.render(*lang, s).as_str()))
+    };
+    if is_reserved(&suggestion) {
+        suggestion = format!("{suggestion}-value");
+    }
+    suggestion.trim_end_matches('-').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate_representation::{TypeRef, VariantDef};
+    use serde_json::json;
+
+    fn feature(props: &[&str]) -> FeatureDef {
+        FeatureDef::new(
+            "my-feature",
+            "",
+            props
+                .iter()
+                .map(|nm| PropDef::new(nm, &TypeRef::Boolean, &json!(true)))
+                .collect(),
+            false,
+        )
+    }
+
+    fn error_message(result: Result<()>) -> String {
+        match result {
+            Err(FMLError::ValidationError(_, message)) => message,
+            _ => panic!("Expected a validation error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_valid_names() -> Result<()> {
+        let validator = IdentifierValidator;
+        validator.validate_feature_def(&feature(&["enabled", "sections-enabled", "title_text"]))?;
+        let enum_def = EnumDef {
+            name: "HomeScreenSection".to_string(),
+            doc: "".to_string(),
+            variants: vec![
+                VariantDef::new("top-sites", ""),
+                VariantDef::new("recently-saved", ""),
+            ],
+        };
+        validator.validate_enum_def(&enum_def)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_reserved_words() {
+        let validator = IdentifierValidator;
+        let message = error_message(validator.validate_feature_def(&feature(&["enabled", "in"])));
+        assert!(message.contains("would become `in` in Kotlin, which is a reserved word"));
+        assert!(message.contains("`in-value`"));
+
+        // Kotlin enum variants are upper case, but Swift's aren't.
+        let enum_def = EnumDef {
+            name: "Choice".to_string(),
+            doc: "".to_string(),
+            variants: vec![VariantDef::new("default", "")],
+        };
+        let message = error_message(validator.validate_enum_def(&enum_def));
+        assert!(message.contains("would become `default` in Swift"));
+        assert!(message.contains("`default-value`"));
+    }
+
+    #[test]
+    fn test_illegal_identifiers() {
+        let validator = IdentifierValidator;
+        let message = error_message(validator.validate_feature_def(&feature(&["1st-run"])));
+        assert!(message.contains("`1stRun` in Kotlin, which isn't a legal identifier"));
+        assert!(message.contains("`variable-1st-run`"));
+
+        let message = error_message(validator.validate_feature_def(&feature(&["---"])));
+        assert!(message.contains("isn't a legal identifier"));
+    }
+
+    #[test]
+    fn test_colliding_names() {
+        let validator = IdentifierValidator;
+        let message = error_message(
+            validator.validate_feature_def(&feature(&["sections-enabled", "sections_enabled"])),
+        );
+        assert!(message.contains(
+            "`sections-enabled` and `sections_enabled` would both become `sectionsEnabled`"
+        ));
+    }
+}
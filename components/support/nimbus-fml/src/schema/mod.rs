@@ -3,9 +3,11 @@
 * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 mod hasher;
+mod identifiers;
 mod types;
 mod validator;
 
 pub(crate) use hasher::{SchemaHasher, Sha256Hasher};
+pub(crate) use identifiers::IdentifierValidator;
 pub(crate) use types::TypeQuery;
 pub(crate) use validator::SchemaValidator;
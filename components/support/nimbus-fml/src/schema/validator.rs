@@ -106,6 +106,69 @@ impl<'a> SchemaValidator<'a> {
         Ok(())
     }
 
+    /// Checks that localized variables are user-facing strings, and that
+    /// they're translated into every one of the manifest's `locales`.
+    pub(crate) fn validate_feature_localizations(
+        &self,
+        feature_def: &FeatureDef,
+        locales: &[String],
+    ) -> Result<()> {
+        let feat_nm = &feature_def.name;
+        for prop in &feature_def.props {
+            let path = format!("features/{feat_nm}/{}", prop.name);
+            if !prop.localized {
+                if !prop.translations.is_empty() {
+                    return Err(FMLError::ValidationError(
+                        path,
+                        "Translations can only be given for variables marked `localized: true`"
+                            .to_string(),
+                    ));
+                }
+                continue;
+            }
+
+            if !matches!(prop.typ, TypeRef::String | TypeRef::BundleText) {
+                return Err(FMLError::ValidationError(
+                    path,
+                    "Only String and Text variables can be localized".to_string(),
+                ));
+            }
+            if !prop.default.is_string() {
+                return Err(FMLError::ValidationError(
+                    path,
+                    "A localized variable needs a string default to translate".to_string(),
+                ));
+            }
+
+            let missing: Vec<_> = locales
+                .iter()
+                .filter(|locale| {
+                    prop.translations
+                        .get(*locale)
+                        .map_or(true, |value| value.is_empty())
+                })
+                .map(String::as_str)
+                .collect();
+            if !missing.is_empty() {
+                return Err(FMLError::ValidationError(
+                    path,
+                    format!("Missing translations for locales: {}", missing.join(", ")),
+                ));
+            }
+            if let Some(locale) = prop
+                .translations
+                .keys()
+                .find(|locale| !locales.contains(locale))
+            {
+                return Err(FMLError::ValidationError(
+                    path,
+                    format!("Found a translation for {locale}, which isn't one of the manifest's locales"),
+                ));
+            }
+        }
+        Ok(())
+    }
+
     fn validate_string_alias_declarations(
         &self,
         path: &str,
@@ -483,3 +546,83 @@ mod string_aliases {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod localizations {
+    use serde_json::json;
+
+    use crate::intermediate_representation::PropDef;
+
+    use super::*;
+
+    fn localized(
+        typ: &TypeRef,
+        default: serde_json::Value,
+        translations: &[(&str, &str)],
+    ) -> PropDef {
+        let mut prop = PropDef::new("title", typ, &default);
+        prop.localized = true;
+        prop.translations = translations
+            .iter()
+            .map(|(locale, value)| (locale.to_string(), value.to_string()))
+            .collect();
+        prop
+    }
+
+    fn validate(prop: PropDef, locales: &[&str]) -> Result<()> {
+        let enums = Default::default();
+        let objects = Default::default();
+        let validator = SchemaValidator::new(&enums, &objects);
+        let feature = FeatureDef::new("test-feature", "", vec![prop], false);
+        let locales: Vec<_> = locales.iter().map(|l| l.to_string()).collect();
+        validator.validate_feature_localizations(&feature, &locales)
+    }
+
+    #[test]
+    fn test_validate_localizations() -> Result<()> {
+        let translated = &[("de", "Hallo"), ("fr", "Bonjour")];
+        validate(
+            localized(&TypeRef::String, json!("Hello"), translated),
+            &["de", "fr"],
+        )?;
+        validate(
+            localized(&TypeRef::BundleText, json!("Hello"), translated),
+            &["de", "fr"],
+        )?;
+        // With no locales declared, there's nothing to check yet.
+        validate(localized(&TypeRef::String, json!("Hello"), &[]), &[])?;
+
+        // A locale is missing, or is empty.
+        let err = validate(
+            localized(&TypeRef::String, json!("Hello"), translated),
+            &["de", "fr", "es"],
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Missing translations for locales: es"));
+        validate(
+            localized(&TypeRef::String, json!("Hello"), &[("de", "")]),
+            &["de"],
+        )
+        .unwrap_err();
+
+        // A translation for an undeclared locale.
+        validate(
+            localized(&TypeRef::String, json!("Hello"), translated),
+            &["de"],
+        )
+        .unwrap_err();
+
+        // Only strings can be localized.
+        validate(localized(&TypeRef::Int, json!(1), &[]), &[]).unwrap_err();
+        let optional = TypeRef::Option(Box::new(TypeRef::String));
+        validate(localized(&optional, json!(null), &[]), &[]).unwrap_err();
+
+        // Translations without `localized: true`.
+        let mut prop = localized(&TypeRef::String, json!("Hello"), translated);
+        prop.localized = false;
+        validate(prop, &["de", "fr"]).unwrap_err();
+        Ok(())
+    }
+}
@@ -8,12 +8,15 @@ use crate::{
 
 use anyhow::anyhow;
 use reqwest::blocking::{Client, ClientBuilder};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::{
-    collections::{hash_map::DefaultHasher, BTreeMap},
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
     env,
     fmt::Display,
     hash::{Hash, Hasher},
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 use url::Url;
 
@@ -26,6 +29,9 @@ pub struct LoaderConfig {
     pub repo_files: Vec<String>,
     pub cache_dir: Option<PathBuf>,
     pub refs: BTreeMap<String, String>,
+    /// If set, a report of every file read is written here by
+    /// [`FileLoader::write_resolution_report`].
+    pub resolution_report: Option<PathBuf>,
 }
 
 impl LoaderConfig {
@@ -49,6 +55,7 @@ impl Default for LoaderConfig {
             cache_dir: None,
             cwd: env::current_dir().expect("Current Working Directory is not set"),
             refs: Default::default(),
+            resolution_report: None,
         }
     }
 }
@@ -226,6 +233,42 @@ impl From<&Path> for FilePath {
     }
 }
 
+/// A record of a file read by the [`FileLoader`], for the resolution report.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FileResolution {
+    /// The path as it was written, e.g. `@mozilla/application-services/file.fml.yaml`, or the
+    /// resolved path if the file wasn't found by joining a path.
+    pub requested: String,
+    pub resolved: String,
+    /// Whether a remote file was read from the cache. This is `None` for local files.
+    pub cache_hit: Option<bool>,
+    pub bytes: usize,
+    /// The SHA-256 of the contents, in hex.
+    pub sha256: String,
+}
+
+// Shared between clones of a `FileLoader`, so every file read during generation is
+// reported, whichever clone read it.
+#[derive(Debug, Default)]
+struct ResolutionLog {
+    // Resolved paths to the paths they were first requested as.
+    requested: HashMap<String, String>,
+    files: Vec<FileResolution>,
+}
+
+// Local paths are canonicalized, because the parser canonicalizes the paths it imports
+// before loading them.
+fn resolution_key(file: &FilePath) -> String {
+    match file {
+        FilePath::Local(p) => p
+            .canonicalize()
+            .unwrap_or_else(|_| p.clone())
+            .display()
+            .to_string(),
+        _ => file.to_string(),
+    }
+}
+
 #[cfg(not(test))]
 fn is_dir(path_buf: &Path) -> bool {
     path_buf.is_dir()
@@ -243,6 +286,56 @@ fn is_dir(path_buf: &Path) -> bool {
 
 static USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 
+/// The environment variable which sets how many idle connections the shared
+/// HTTP client keeps open to each host, for reuse by later requests. It doesn't
+/// limit how many connections are open at once.
+const MAX_IDLE_CONNECTIONS_ENV_VAR: &str = "FML_HTTP_MAX_IDLE_CONNECTIONS";
+const DEFAULT_MAX_IDLE_CONNECTIONS_PER_HOST: usize = 8;
+
+fn max_idle_connections_per_host(value: Option<&str>) -> Result<usize> {
+    match value {
+        None => Ok(DEFAULT_MAX_IDLE_CONNECTIONS_PER_HOST),
+        Some(v) => match v.trim().parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(anyhow!(
+                "{MAX_IDLE_CONNECTIONS_ENV_VAR} must be a positive number, not {v:?}"
+            )
+            .into()),
+        },
+    }
+}
+
+/// Returns the HTTP client shared by every `FileLoader` in this process.
+///
+/// Building a client is expensive, and each one has its own connection pool
+/// (and, for the blocking client, its own runtime thread). CI jobs can create
+/// dozens of loaders, so sharing one client means that connections to GitHub
+/// are reused between them. `Client` is a handle to the shared state, so
+/// clones are cheap.
+fn shared_http_client() -> Result<Client> {
+    lazy_static::lazy_static! {
+        static ref SHARED_CLIENT: Mutex<Option<Client>> = Default::default();
+    }
+    let mut shared = SHARED_CLIENT
+        .lock()
+        .map_err(|_| FMLError::InternalError("HTTP client lock poisoned"))?;
+    if let Some(client) = shared.as_ref() {
+        return Ok(client.clone());
+    }
+    let max_idle_connections =
+        max_idle_connections_per_host(env::var(MAX_IDLE_CONNECTIONS_ENV_VAR).ok().as_deref())?;
+    // HTTP/2 is negotiated with ALPN, so requests to the same host are
+    // multiplexed over a single connection when the server supports it.
+    let client = ClientBuilder::new()
+        .https_only(true)
+        .user_agent(USER_AGENT)
+        .pool_max_idle_per_host(max_idle_connections)
+        .http2_adaptive_window(true)
+        .build()?;
+    *shared = Some(client.clone());
+    Ok(client)
+}
+
 /// Utility class to abstract away the differences between loading from file and network.
 ///
 /// With a nod to offline developer experience, files which come from the network
@@ -272,6 +365,9 @@ pub struct FileLoader {
     // This is used for resolving relative paths when no other path
     // information is available.
     cwd: PathBuf,
+
+    resolution_report: Option<PathBuf>,
+    resolutions: Arc<Mutex<ResolutionLog>>,
 }
 
 impl TryFrom<&LoaderConfig> for FileLoader {
@@ -282,6 +378,7 @@ impl TryFrom<&LoaderConfig> for FileLoader {
         let cwd = loader_config.cwd.clone();
 
         let mut file_loader = Self::new(cwd, cache_dir, Default::default())?;
+        file_loader.resolution_report = loader_config.resolution_report.clone();
 
         for (repo_id, git_ref) in &loader_config.refs {
             file_loader.add_repo(repo_id, git_ref)?;
@@ -302,16 +399,13 @@ impl FileLoader {
         cache_dir: Option<PathBuf>,
         repo_refs: BTreeMap<String, FilePath>,
     ) -> Result<Self> {
-        let http_client = ClientBuilder::new()
-            .https_only(true)
-            .user_agent(USER_AGENT)
-            .build()?;
-
         Ok(Self {
             cache_dir,
-            fetch_client: http_client,
+            fetch_client: shared_http_client()?,
             cwd,
             repo_refs,
+            resolution_report: None,
+            resolutions: Default::default(),
         })
     }
 
@@ -410,9 +504,12 @@ impl FileLoader {
     /// We don't worry about cache invalidation, because a clean build should blow the cache
     /// away.
     pub fn read_to_string(&self, file: &FilePath) -> Result<String> {
-        Ok(match file {
-            FilePath::Local(path) => std::fs::read_to_string(path)?,
-            FilePath::Remote(url) => self.fetch_and_cache(url)?,
+        let (text, cache_hit) = match file {
+            FilePath::Local(path) => (std::fs::read_to_string(path)?, None),
+            FilePath::Remote(url) => {
+                let (text, cache_hit) = self.fetch_and_cache(url)?;
+                (text, Some(cache_hit))
+            }
             FilePath::GitHub(p) => {
                 // If there is a GITHUB_BEARER_TOKEN environment variable
                 // present, we will use that to get the download URL from the
@@ -449,9 +546,51 @@ impl FileLoader {
                     p.default_download_url()?
                 };
 
-                self.fetch_and_cache(&download_url)?
+                let (text, cache_hit) = self.fetch_and_cache(&download_url)?;
+                (text, Some(cache_hit))
             }
-        })
+        };
+        self.record_resolution(file, &text, cache_hit);
+        Ok(text)
+    }
+
+    fn record_resolution(&self, file: &FilePath, text: &str, cache_hit: Option<bool>) {
+        let resolved = file.to_string();
+        let mut log = self.resolutions.lock().expect("resolution log is poisoned");
+        let requested = log
+            .requested
+            .get(&resolution_key(file))
+            .cloned()
+            .unwrap_or_else(|| resolved.clone());
+        log.files.push(FileResolution {
+            requested,
+            resolved,
+            cache_hit,
+            bytes: text.len(),
+            sha256: format!("{:x}", Sha256::digest(text.as_bytes())),
+        });
+    }
+
+    fn record_request(&self, requested: &str, file: &FilePath) {
+        let mut log = self.resolutions.lock().expect("resolution log is poisoned");
+        log.requested
+            .entry(resolution_key(file))
+            .or_insert_with(|| requested.to_string());
+    }
+
+    /// The files read so far by this loader and its clones, in the order they were read.
+    pub fn resolutions(&self) -> Vec<FileResolution> {
+        let log = self.resolutions.lock().expect("resolution log is poisoned");
+        log.files.clone()
+    }
+
+    /// If the loader was configured with a `resolution_report` path, writes the files read
+    /// so far to it as JSON.
+    pub fn write_resolution_report(&self) -> Result<()> {
+        if let Some(path) = &self.resolution_report {
+            std::fs::write(path, serde_json::to_string_pretty(&self.resolutions())?)?;
+        }
+        Ok(())
     }
 
     pub fn read<T: serde::de::DeserializeOwned>(&self, file: &FilePath) -> Result<T> {
@@ -462,13 +601,14 @@ impl FileLoader {
         Ok(serde_yaml::from_str(&string)?)
     }
 
-    fn fetch_and_cache(&self, url: &Url) -> Result<String> {
+    /// Returns the file, and whether it was read from the cache.
+    fn fetch_and_cache(&self, url: &Url) -> Result<(String, bool)> {
         if !SUPPORT_URL_LOADING {
             unimplemented!("Loading manifests from URLs is not yet supported ({})", url);
         }
         let path_buf = self.create_cache_path_buf(url);
         Ok(if path_buf.exists() {
-            std::fs::read_to_string(path_buf)?
+            (std::fs::read_to_string(path_buf)?, true)
         } else {
             let res = self.fetch_client.get(url.clone()).send()?;
             let text = res.text()?;
@@ -479,7 +619,7 @@ impl FileLoader {
             }
 
             std::fs::write(path_buf, &text)?;
-            text
+            (text, false)
         })
     }
 
@@ -530,11 +670,13 @@ impl FileLoader {
     ///
     /// If `f` is a relative path, the result is relative to `base`.
     pub fn join(&self, base: &FilePath, f: &str) -> Result<FilePath> {
-        Ok(if let Some(u) = self.resolve_url_shortcut(f)? {
+        let file = if let Some(u) = self.resolve_url_shortcut(f)? {
             u
         } else {
             base.join(f)?
-        })
+        };
+        self.record_request(f, &file);
+        Ok(file)
     }
 
     /// Make a new path.
@@ -546,11 +688,13 @@ impl FileLoader {
     ///
     /// If `f` is a relative path, the result is relative to `self.cwd`.
     pub fn file_path(&self, f: &str) -> Result<FilePath> {
-        Ok(if let Some(u) = self.resolve_url_shortcut(f)? {
+        let file = if let Some(u) = self.resolve_url_shortcut(f)? {
             u
         } else {
             FilePath::new(&self.cwd, f)?
-        })
+        };
+        self.record_request(f, &file);
+        Ok(file)
     }
 
     /// Checks that the given string has a @organization/repo/ prefix.
@@ -784,6 +928,7 @@ mod unit_tests {
                 "fixtures/loaders/config_files/local.yaml".to_string(),
             ],
             refs: Default::default(),
+            resolution_report: None,
         };
 
         let files: FileLoader = config.try_into()?;
@@ -843,6 +988,7 @@ mod unit_tests {
             cache_dir: None,
             repo_files: Default::default(),
             refs: BTreeMap::from([("@my-remote/repo".to_string(), "cli-branch".to_string())]),
+            resolution_report: None,
         };
 
         let files: FileLoader = config.try_into()?;
@@ -867,6 +1013,7 @@ mod unit_tests {
             cache_dir: None,
             repo_files: Default::default(),
             refs: Default::default(),
+            resolution_report: None,
         };
 
         let files: FileLoader = config.try_into()?;
@@ -963,4 +1110,75 @@ mod unit_tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_max_idle_connections_per_host() -> Result<()> {
+        assert_eq!(
+            max_idle_connections_per_host(None)?,
+            DEFAULT_MAX_IDLE_CONNECTIONS_PER_HOST
+        );
+        assert_eq!(max_idle_connections_per_host(Some("32"))?, 32);
+        assert_eq!(max_idle_connections_per_host(Some(" 4 "))?, 4);
+        assert!(max_idle_connections_per_host(Some("0")).is_err());
+        assert!(max_idle_connections_per_host(Some("lots")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolutions() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let config = LoaderConfig {
+            cwd: PathBuf::from(pkg_dir()),
+            cache_dir: Some(tmp.path().join("cache")),
+            repo_files: Default::default(),
+            refs: Default::default(),
+            resolution_report: Some(tmp.path().join("report.json")),
+        };
+        let files: FileLoader = (&config).try_into()?;
+
+        let config_file = files.file_path("fixtures/loaders/config_files/local.yaml")?;
+        let nested = files.join(&config_file, "./remote.json")?;
+        // Clones share the report.
+        let contents = files.clone().read_to_string(&nested)?;
+        files.read_to_string(&config_file)?;
+
+        // Put a remote file in the cache, so it's read without going to the network.
+        let url = Url::parse("https://example.com/cached.fml.yaml")?;
+        let cache_path = files.create_cache_path_buf(&url);
+        fs::create_dir_all(cache_path.parent().unwrap())?;
+        fs::write(&cache_path, "cached")?;
+        files.read_to_string(&files.file_path(url.as_str())?)?;
+
+        let resolutions = files.resolutions();
+        assert_eq!(resolutions.len(), 3);
+        assert_eq!(resolutions[0].requested, "./remote.json");
+        assert!(resolutions[0].resolved.ends_with("/remote.json"));
+        assert_eq!(resolutions[0].cache_hit, None);
+        assert_eq!(resolutions[0].bytes, contents.len());
+        assert_eq!(
+            resolutions[0].sha256,
+            format!("{:x}", Sha256::digest(contents.as_bytes()))
+        );
+        assert_eq!(
+            resolutions[1].requested,
+            "fixtures/loaders/config_files/local.yaml"
+        );
+        assert_eq!(
+            resolutions[2],
+            FileResolution {
+                requested: url.to_string(),
+                resolved: url.to_string(),
+                cache_hit: Some(true),
+                bytes: 6,
+                sha256: format!("{:x}", Sha256::digest(b"cached")),
+            }
+        );
+
+        files.write_resolution_report()?;
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(tmp.path().join("report.json"))?)?;
+        assert_eq!(report.as_array().map(Vec::len), Some(3));
+        assert_eq!(report[2]["cache_hit"], serde_json::json!(true));
+        Ok(())
+    }
 }
@@ -23,10 +23,24 @@ pub fn set_logger(logger: Option<Box<dyn AppServicesLogger>>) {
 
 /// Set the maximum log level filter.  Records below this level will not be sent to the logger.
 pub fn set_max_level(level: Level) {
-    log::set_max_level(level.to_level_filter());
+    rust_logger::set_max_level(level.to_level_filter());
     HAVE_SET_MAX_LEVEL.store(true, Ordering::Relaxed);
 }
 
+/// Set the log level for a single target, overriding the max level for it.
+///
+/// The target is usually a crate or module path, like `places` or `sync15::client`, and the level
+/// also applies to the modules under it. This can be used to get verbose logs from one component
+/// while keeping the others quiet, and takes effect immediately.
+pub fn set_level(target: String, level: Level) {
+    rust_logger::set_target_level(target, Some(level.to_level_filter()));
+}
+
+/// Remove a level set with `set_level`, so that the target uses the max level again.
+pub fn clear_level(target: String) {
+    rust_logger::set_target_level(target, None);
+}
+
 uniffi::include_scaffolding!("rust_log_forwarder");
 
 #[cfg(test)]
@@ -101,6 +115,45 @@ mod test {
         assert_eq!(log::max_level(), log::Level::Warn);
     }
 
+    #[test]
+    fn test_target_levels() {
+        let _lock = TEST_LOCK.lock().unwrap();
+        let logger = TestLogger::new();
+        set_logger(Some(Box::new(logger.clone())));
+        set_max_level(Level::Warn);
+        set_level("places".into(), Level::Debug);
+        set_level("places::db".into(), Level::Error);
+        // The global level has to let the debug records through to us.
+        assert_eq!(log::max_level(), log::Level::Debug);
+
+        log::debug!(target: "places", "places");
+        log::debug!(target: "places::storage::history", "history");
+        log::warn!(target: "places::db", "db warning");
+        log::error!(target: "places::db::schema", "db error");
+        log::debug!(target: "placesx", "placesx");
+        log::info!(target: "sync15", "sync15 info");
+        log::warn!(target: "sync15", "sync15 warning");
+        let record = |level, target: &str, message: &str| Record {
+            level,
+            target: target.into(),
+            message: message.into(),
+        };
+        logger.check_records(vec![
+            record(Level::Debug, "places", "places"),
+            record(Level::Debug, "places::storage::history", "history"),
+            record(Level::Error, "places::db::schema", "db error"),
+            record(Level::Warn, "sync15", "sync15 warning"),
+        ]);
+
+        logger.clear_records();
+        clear_level("places".into());
+        clear_level("places::db".into());
+        assert_eq!(log::max_level(), log::Level::Warn);
+        log::debug!(target: "places", "places");
+        logger.check_records(vec![]);
+        set_logger(None);
+    }
+
     #[test]
     fn test_max_level_default() {
         let _lock = TEST_LOCK.lock().unwrap();
@@ -8,7 +8,9 @@
 //! foreign_logger::Logger instance.
 
 use crate::foreign_logger::AppServicesLogger as ForeignLogger;
+use log::LevelFilter;
 use parking_lot::RwLock;
+use std::collections::BTreeMap;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Once,
@@ -22,6 +24,48 @@ static INIT: Once = Once::new();
 struct Logger {
     foreign_logger: RwLock<Option<Box<dyn ForeignLogger>>>,
     is_enabled: AtomicBool,
+    levels: RwLock<Levels>,
+}
+
+/// The max level, and the levels set for individual targets.
+struct Levels {
+    max_level: LevelFilter,
+    targets: BTreeMap<String, LevelFilter>,
+}
+
+impl Levels {
+    const fn new() -> Self {
+        Self {
+            max_level: LevelFilter::Off,
+            targets: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the level for `target`. A level set for a target also applies
+    /// to the modules under it, so `places` covers `places::storage::history`,
+    /// and the most specific match wins.
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .filter(|(prefix, _)| {
+                target
+                    .strip_prefix(prefix.as_str())
+                    .map_or(false, |rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.max_level, |(_, level)| *level)
+    }
+
+    fn update_global_max_level(&self) {
+        // The log macros drop records above the global max level before they
+        // get to us, so it has to let through the most verbose of our levels.
+        let max_level = self
+            .targets
+            .values()
+            .copied()
+            .fold(self.max_level, Ord::max);
+        log::set_max_level(max_level);
+    }
 }
 
 impl Logger {
@@ -29,6 +73,7 @@ impl Logger {
         Self {
             foreign_logger: RwLock::new(None),
             is_enabled: AtomicBool::new(false),
+            levels: RwLock::new(Levels::new()),
         }
     }
 
@@ -40,11 +85,15 @@ impl Logger {
 }
 
 impl log::Log for Logger {
-    fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
         self.is_enabled.load(Ordering::Relaxed)
+            && metadata.level() <= self.levels.read().level_for(metadata.target())
     }
 
     fn log(&self, record: &log::Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
         if let Some(foreign_logger) = &*self.foreign_logger.read() {
             foreign_logger.log(record.into())
         }
@@ -63,3 +112,19 @@ pub fn set_foreign_logger(foreign_logger: Option<Box<dyn ForeignLogger>>) {
     });
     RUST_LOGGER.set_foreign_logger(foreign_logger);
 }
+
+pub fn set_max_level(level: LevelFilter) {
+    let mut levels = RUST_LOGGER.levels.write();
+    levels.max_level = level;
+    levels.update_global_max_level();
+}
+
+/// Sets the level for `target`, or removes it if `level` is `None`.
+pub fn set_target_level(target: String, level: Option<LevelFilter>) {
+    let mut levels = RUST_LOGGER.levels.write();
+    match level {
+        Some(level) => levels.targets.insert(target, level),
+        None => levels.targets.remove(&target),
+    };
+    levels.update_global_max_level();
+}
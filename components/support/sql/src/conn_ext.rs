@@ -12,6 +12,7 @@ use std::ops::Deref;
 use std::time::Instant;
 
 use crate::maybe_cached::MaybeCached;
+use crate::statement_cache::StatementCacheTracker;
 
 pub struct Conn(rusqlite::Connection);
 
@@ -21,6 +22,13 @@ pub trait ConnExt {
     /// The method you need to implement to opt in to all of this.
     fn conn(&self) -> &Connection;
 
+    /// Implement this to count hits and misses for the statements the `_cached` helpers
+    /// prepare. The connection's statement cache should be sized with the tracker's
+    /// `set_capacity`, so that its stats report the right capacity.
+    fn statement_cache_tracker(&self) -> Option<&StatementCacheTracker> {
+        None
+    }
+
     /// Set the value of the pragma on the main database. Returns the same object, for chaining.
     fn set_pragma<T>(&self, pragma_name: &str, pragma_value: T) -> SqlResult<&Self>
     where
@@ -39,7 +47,12 @@ pub trait ConnExt {
         sql: &str,
         cache: bool,
     ) -> SqlResult<MaybeCached<'conn>> {
-        MaybeCached::prepare(self.conn(), sql, cache)
+        let stmt = MaybeCached::prepare(self.conn(), sql, cache)?;
+        if let (MaybeCached::Cached(stmt), Some(tracker)) = (&stmt, self.statement_cache_tracker())
+        {
+            tracker.record(stmt);
+        }
+        Ok(stmt)
     }
 
     /// Execute all the provided statements.
@@ -66,7 +79,7 @@ pub trait ConnExt {
     /// Equivalent to `Connection::execute` but caches the statement so that subsequent
     /// calls to `execute_cached` will have improved performance.
     fn execute_cached<P: Params>(&self, sql: &str, params: P) -> SqlResult<usize> {
-        let mut stmt = self.conn().prepare_cached(sql)?;
+        let mut stmt = self.prepare_maybe_cached(sql, true)?;
         stmt.execute(params)
     }
 
@@ -99,7 +112,6 @@ pub trait ConnExt {
         // The outer option is if we got rows, the inner option is
         // if the first row was null.
         let res: Option<Option<T>> = self
-            .conn()
             .query_row_and_then_cachable(sql, params, |row| row.get(0), cache)
             .optional()?;
         // go from Option<Option<T>> to Option<T>
@@ -136,7 +148,7 @@ pub trait ConnExt {
         E: From<rusqlite::Error>,
         F: FnMut(&Row<'_>) -> Result<T, E>,
     {
-        query_rows_and_then_cachable(self.conn(), sql, params, mapper, false)
+        query_rows_and_then_cachable(self, sql, params, mapper, false)
     }
 
     /// Helper for when you'd like to get a `Vec<T>` of all the rows returned by a
@@ -153,7 +165,7 @@ pub trait ConnExt {
         E: From<rusqlite::Error>,
         F: FnMut(&Row<'_>) -> Result<T, E>,
     {
-        query_rows_and_then_cachable(self.conn(), sql, params, mapper, true)
+        query_rows_and_then_cachable(self, sql, params, mapper, true)
     }
 
     /// Like `query_rows_and_then_cachable`, but works if you want a non-Vec as a result.
@@ -180,7 +192,7 @@ pub trait ConnExt {
         Coll: FromIterator<T>,
         P: Params,
     {
-        query_rows_and_then_cachable(self.conn(), sql, params, mapper, false)
+        query_rows_and_then_cachable(self, sql, params, mapper, false)
     }
 
     /// Same as `query_rows_into`, but caches the stmt if possible.
@@ -197,7 +209,7 @@ pub trait ConnExt {
         F: FnMut(&Row<'_>) -> Result<T, E>,
         Coll: FromIterator<T>,
     {
-        query_rows_and_then_cachable(self.conn(), sql, params, mapper, true)
+        query_rows_and_then_cachable(self, sql, params, mapper, true)
     }
 
     // This should probably have a longer name...
@@ -216,8 +228,7 @@ pub trait ConnExt {
         E: From<rusqlite::Error>,
         F: FnOnce(&Row<'_>) -> Result<T, E>,
     {
-        let conn = self.conn();
-        let mut stmt = MaybeCached::prepare(conn, sql, cache)?;
+        let mut stmt = self.prepare_maybe_cached(sql, cache)?;
         let mut rows = stmt.query(params)?;
         rows.next()?.map(mapper).transpose()
     }
@@ -391,14 +402,15 @@ impl<'conn> ConnExt for UncheckedTransaction<'conn> {
     }
 }
 
-fn query_rows_and_then_cachable<Coll, T, E, P, F>(
-    conn: &Connection,
+fn query_rows_and_then_cachable<C, Coll, T, E, P, F>(
+    conn: &C,
     sql: &str,
     params: P,
     mapper: F,
     cache: bool,
 ) -> Result<Coll, E>
 where
+    C: ConnExt + ?Sized,
     E: From<rusqlite::Error>,
     F: FnMut(&Row<'_>) -> Result<T, E>,
     Coll: FromIterator<T>,
@@ -12,14 +12,17 @@ pub mod debug_tools;
 mod each_chunk;
 mod lazy;
 mod maybe_cached;
+pub mod migration_runner;
 pub mod open_database;
 mod repeat;
+mod statement_cache;
 
 pub use conn_ext::*;
 pub use each_chunk::*;
 pub use lazy::*;
 pub use maybe_cached::*;
 pub use repeat::*;
+pub use statement_cache::*;
 
 /// In PRAGMA foo='bar', `'bar'` must be a constant string (it cannot be a
 /// bound parameter), so we need to escape manually. According to
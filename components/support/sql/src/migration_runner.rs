@@ -0,0 +1,306 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A shared implementation of schema upgrades, for use by a
+//! [`ConnectionInitializer`](crate::open_database::ConnectionInitializer).
+//!
+//! Usage:
+//!    - Build a [`MigrationRunner`] listing one [`Migration`] for each version
+//!      the schema can be upgraded from.
+//!    - Call [`MigrationRunner::init`] from `ConnectionInitializer::init()`, so that
+//!      new databases have the same history table as upgraded ones.
+//!    - Call [`MigrationRunner::upgrade_from`] from `ConnectionInitializer::upgrade_from()`.
+//!
+//! Each migration runs inside its own savepoint, so a failed migration leaves nothing
+//! half-applied behind it. When a migration fails we run `PRAGMA integrity_check`; if
+//! that finds problems we return [`Error::Corrupt`], which causes `open_database()`
+//! to replace the database, otherwise we return the migration's own error.
+//!
+//! Every migration that's applied is recorded in the `sql_support_migration_history`
+//! table, which can be read with [`migration_history()`].
+//!
+//! [`MigrationRunner::plan`] reports the migrations that would be run, without running
+//! them, which is useful for checking the list of migrations is complete.
+
+use crate::open_database::{Error, Result};
+use crate::ConnExt;
+use rusqlite::{Connection, Row};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CREATE_HISTORY_TABLE_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS sql_support_migration_history(
+        id INTEGER PRIMARY KEY,
+        from_version INTEGER NOT NULL,
+        to_version INTEGER NOT NULL,
+        description TEXT NOT NULL,
+        applied_at INTEGER NOT NULL
+    )";
+
+type MigrationFn = Box<dyn Fn(&Connection) -> Result<()> + Send + Sync>;
+
+/// A single step, which upgrades the schema from `from_version` to `from_version + 1`.
+pub struct Migration {
+    pub from_version: u32,
+    pub description: String,
+    apply: MigrationFn,
+}
+
+impl Migration {
+    pub fn new(
+        from_version: u32,
+        description: impl Into<String>,
+        apply: impl Fn(&Connection) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            from_version,
+            description: description.into(),
+            apply: Box::new(apply),
+        }
+    }
+
+    /// A migration which only executes SQL.
+    pub fn sql(from_version: u32, description: impl Into<String>, sql: &'static str) -> Self {
+        Self::new(from_version, description, move |conn| {
+            Ok(conn.execute_batch(sql)?)
+        })
+    }
+}
+
+/// A migration which would be run, as reported by [`MigrationRunner::plan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedMigration {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub description: String,
+}
+
+/// A migration which was run, as reported by [`migration_history()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationRecord {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub description: String,
+    /// Milliseconds since the epoch.
+    pub applied_at: i64,
+}
+
+impl MigrationRecord {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            from_version: row.get("from_version")?,
+            to_version: row.get("to_version")?,
+            description: row.get("description")?,
+            applied_at: row.get("applied_at")?,
+        })
+    }
+}
+
+pub struct MigrationRunner {
+    // Name to display in the logs
+    name: &'static str,
+    migrations: Vec<Migration>,
+}
+
+impl MigrationRunner {
+    pub fn new(name: &'static str, mut migrations: Vec<Migration>) -> Self {
+        migrations.sort_by_key(|m| m.from_version);
+        Self { name, migrations }
+    }
+
+    /// Set up a newly created database. This only creates the history table:
+    /// there's nothing to record, since a new database starts at the latest version.
+    pub fn init(&self, conn: &Connection) -> Result<()> {
+        conn.execute_batch(CREATE_HISTORY_TABLE_SQL)?;
+        Ok(())
+    }
+
+    /// Returns the migrations needed to upgrade from `from` to `to`, or
+    /// [`Error::IncompatibleVersion`] with the first version we can't upgrade from.
+    pub fn plan(&self, from: u32, to: u32) -> Result<Vec<PlannedMigration>> {
+        (from..to)
+            .map(|version| {
+                let migration = self.find(version)?;
+                Ok(PlannedMigration {
+                    from_version: version,
+                    to_version: version + 1,
+                    description: migration.description.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Upgrade the schema from `version` to `version + 1`. This doesn't update
+    /// `user_version`, which `open_database()` does once all upgrades are done.
+    pub fn upgrade_from(&self, conn: &Connection, version: u32) -> Result<()> {
+        let migration = self.find(version)?;
+        log::debug!(
+            "{}: migrating from {} to {}: {}",
+            self.name,
+            version,
+            version + 1,
+            migration.description
+        );
+        conn.execute_batch("SAVEPOINT sql_support_migration")?;
+        // Databases created before they adopted the runner won't have the table yet.
+        conn.execute_batch(CREATE_HISTORY_TABLE_SQL)?;
+        match (migration.apply)(conn).and_then(|_| self.record(conn, migration)) {
+            Ok(()) => {
+                conn.execute_batch("RELEASE sql_support_migration")?;
+                Ok(())
+            }
+            Err(e) => {
+                log::warn!("{}: migration from {} failed: {}", self.name, version, e);
+                conn.execute_batch(
+                    "ROLLBACK TO sql_support_migration; RELEASE sql_support_migration",
+                )?;
+                if !integrity_check_ok(conn)? {
+                    log::error!("{}: database failed its integrity check", self.name);
+                    return Err(Error::Corrupt);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn find(&self, version: u32) -> Result<&Migration> {
+        self.migrations
+            .iter()
+            .find(|m| m.from_version == version)
+            .ok_or(Error::IncompatibleVersion(version))
+    }
+
+    fn record(&self, conn: &Connection, migration: &Migration) -> Result<()> {
+        let applied_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        conn.execute(
+            "INSERT INTO sql_support_migration_history(from_version, to_version, description, applied_at)
+             VALUES (?, ?, ?, ?)",
+            (
+                migration.from_version,
+                migration.from_version + 1,
+                &migration.description,
+                applied_at,
+            ),
+        )?;
+        Ok(())
+    }
+}
+
+/// Returns the migrations which have been applied to this database, oldest first.
+pub fn migration_history(conn: &Connection) -> Result<Vec<MigrationRecord>> {
+    if !conn.exists(
+        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'sql_support_migration_history'",
+        [],
+    )? {
+        return Ok(Vec::new());
+    }
+    Ok(conn.query_rows_and_then(
+        "SELECT from_version, to_version, description, applied_at
+         FROM sql_support_migration_history
+         ORDER BY id",
+        [],
+        MigrationRecord::from_row,
+    )?)
+}
+
+fn integrity_check_ok(conn: &Connection) -> Result<bool> {
+    let results: Vec<String> =
+        conn.query_rows_and_then("PRAGMA integrity_check", [], |row| row.get(0))?;
+    for problem in results.iter().filter(|r| *r != "ok") {
+        log::warn!("integrity_check: {}", problem);
+    }
+    Ok(results.iter().all(|r| r == "ok"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn runner() -> MigrationRunner {
+        MigrationRunner::new(
+            "test db",
+            vec![
+                Migration::sql(2, "add col2", "ALTER TABLE t ADD COLUMN col2"),
+                Migration::sql(1, "create t", "CREATE TABLE t(col1)"),
+                Migration::new(3, "populate t", |conn| {
+                    conn.execute_batch("INSERT INTO t(col1, col2) VALUES (1, 2)")?;
+                    Ok(())
+                }),
+                Migration::new(4, "broken", |conn| {
+                    conn.execute_batch("INSERT INTO t(col1) VALUES (3); ILLEGAL_SQL_CODE")?;
+                    Ok(())
+                }),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_plan() {
+        let runner = runner();
+        assert_eq!(
+            runner.plan(1, 3).unwrap(),
+            vec![
+                PlannedMigration {
+                    from_version: 1,
+                    to_version: 2,
+                    description: "create t".to_string(),
+                },
+                PlannedMigration {
+                    from_version: 2,
+                    to_version: 3,
+                    description: "add col2".to_string(),
+                },
+            ]
+        );
+        assert!(runner.plan(3, 3).unwrap().is_empty());
+        assert!(matches!(
+            runner.plan(3, 6),
+            Err(Error::IncompatibleVersion(5))
+        ));
+    }
+
+    #[test]
+    fn test_upgrade_and_history() {
+        let conn = Connection::open_in_memory().unwrap();
+        let runner = runner();
+        assert!(migration_history(&conn).unwrap().is_empty());
+        for version in 1..4 {
+            runner.upgrade_from(&conn, version).unwrap();
+        }
+        assert_eq!(conn.query_one::<i64>("SELECT col2 FROM t").unwrap(), 2);
+        let history = migration_history(&conn).unwrap();
+        assert_eq!(
+            history
+                .iter()
+                .map(|r| (r.from_version, r.to_version, r.description.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(1, 2, "create t"), (2, 3, "add col2"), (3, 4, "populate t")]
+        );
+        assert!(history.iter().all(|r| r.applied_at > 0));
+    }
+
+    #[test]
+    fn test_failed_migration_is_rolled_back() {
+        let conn = Connection::open_in_memory().unwrap();
+        let runner = runner();
+        for version in 1..4 {
+            runner.upgrade_from(&conn, version).unwrap();
+        }
+        // The database is fine, so we get the migration's error rather than `Corrupt`.
+        assert!(matches!(
+            runner.upgrade_from(&conn, 4),
+            Err(Error::SqlError(_))
+        ));
+        // The insert that ran before the error was rolled back, and nothing was recorded.
+        assert_eq!(conn.query_one::<i64>("SELECT COUNT(*) FROM t").unwrap(), 1);
+        assert_eq!(migration_history(&conn).unwrap().len(), 3);
+
+        assert!(matches!(
+            runner.upgrade_from(&conn, 5),
+            Err(Error::IncompatibleVersion(5))
+        ));
+    }
+}
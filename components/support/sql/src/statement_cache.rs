@@ -0,0 +1,170 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Hit and miss counts for a connection's prepared statement cache, so that
+//! components can tell whether their cache is big enough for the statements
+//! they run.
+//!
+//! rusqlite doesn't tell us whether `prepare_cached` found the statement in
+//! its cache, so we ask sqlite how many times the statement has been run: a
+//! statement which was just prepared has never been run, while one we got
+//! from the cache has. This means a cached statement which was never stepped
+//! (for example, because binding its parameters failed) is counted as a miss
+//! the next time, which is close enough for tuning.
+
+use rusqlite::{Connection, Statement, StatementStatus};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// The capacity rusqlite gives a connection's statement cache if it isn't
+/// changed.
+pub const DEFAULT_STATEMENT_CACHE_CAPACITY: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatementCacheStats {
+    /// The number of statements the cache holds.
+    pub capacity: u32,
+    /// How many times a statement was found in the cache.
+    pub hits: u64,
+    /// How many times a statement had to be prepared, either because it was
+    /// never cached, or because it was evicted to make room for another.
+    pub misses: u64,
+}
+
+/// Counts hits and misses for the statements prepared through a
+/// [`ConnExt`](crate::ConnExt) whose `statement_cache_tracker()` returns this.
+#[derive(Debug)]
+pub struct StatementCacheTracker {
+    capacity: AtomicUsize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for StatementCacheTracker {
+    fn default() -> Self {
+        Self {
+            capacity: AtomicUsize::new(DEFAULT_STATEMENT_CACHE_CAPACITY),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+}
+
+impl StatementCacheTracker {
+    /// Sets the capacity of `conn`'s statement cache, which should be the
+    /// connection this tracker belongs to. Shrinking the cache evicts the
+    /// least recently used statements.
+    pub fn set_capacity(&self, conn: &Connection, capacity: usize) {
+        conn.set_prepared_statement_cache_capacity(capacity);
+        self.capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    /// Records whether `stmt`, which was just returned by `prepare_cached`,
+    /// came from the cache.
+    pub fn record(&self, stmt: &Statement<'_>) {
+        let counter = if stmt.get_status(StatementStatus::Run) > 0 {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> StatementCacheStats {
+        StatementCacheStats {
+            capacity: u32::try_from(self.capacity.load(Ordering::Relaxed)).unwrap_or(u32::MAX),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Resets the hit and miss counts, but not the capacity.
+    pub fn reset(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ConnExt;
+
+    struct TrackedConn {
+        conn: Connection,
+        tracker: StatementCacheTracker,
+    }
+
+    impl ConnExt for TrackedConn {
+        fn conn(&self) -> &Connection {
+            &self.conn
+        }
+
+        fn statement_cache_tracker(&self) -> Option<&StatementCacheTracker> {
+            Some(&self.tracker)
+        }
+    }
+
+    #[test]
+    fn test_statement_cache_stats() {
+        let conn = TrackedConn {
+            conn: Connection::open_in_memory().unwrap(),
+            tracker: StatementCacheTracker::default(),
+        };
+        conn.tracker.set_capacity(&conn.conn, 2);
+        conn.execute_one("CREATE TABLE t(x)").unwrap();
+
+        let insert = "INSERT INTO t(x) VALUES(?)";
+        conn.execute_cached(insert, [1]).unwrap();
+        conn.execute_cached(insert, [2]).unwrap();
+        let count = |conn: &TrackedConn| {
+            conn.query_row_and_then_cachable(
+                "SELECT COUNT(*) FROM t",
+                [],
+                |row| row.get::<_, i64>(0),
+                true,
+            )
+            .unwrap()
+        };
+        assert_eq!(count(&conn), 2);
+        assert_eq!(
+            conn.tracker.stats(),
+            StatementCacheStats {
+                capacity: 2,
+                hits: 1,
+                misses: 2,
+            }
+        );
+
+        // Uncached statements aren't counted.
+        conn.query_rows_and_then::<_, rusqlite::Error, _, _>("SELECT x FROM t", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .unwrap();
+        assert_eq!(conn.tracker.stats().hits + conn.tracker.stats().misses, 3);
+
+        // With room for only one statement, alternating between two evicts
+        // each of them every time.
+        conn.tracker.reset();
+        conn.tracker.set_capacity(&conn.conn, 1);
+        for i in 0..3 {
+            conn.execute_cached(insert, [i]).unwrap();
+            count(&conn);
+        }
+        assert_eq!(
+            conn.tracker.stats(),
+            StatementCacheStats {
+                capacity: 1,
+                hits: 0,
+                misses: 6,
+            }
+        );
+    }
+
+    #[test]
+    fn test_untracked_conn() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert!(conn.statement_cache_tracker().is_none());
+        conn.execute_cached("CREATE TABLE t(x)", []).unwrap();
+    }
+}
@@ -3,37 +3,181 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use once_cell::sync::Lazy;
-use std::{io::Read, sync::Once};
+use std::{io::Read, sync::Arc, sync::Once, time::SystemTime};
 use viaduct::{settings::GLOBAL_SETTINGS, Backend};
 
 // Note: we don't `use` things from reqwest or the viaduct crate because
 // it would be rather confusing given that we have the same name for
 // most things as them.
 
-static CLIENT: Lazy<reqwest::blocking::Client> = Lazy::new(|| {
+// The same limit as `reqwest::redirect::Policy::default()`.
+const MAX_REDIRECTS: usize = 10;
+
+fn client_builder() -> reqwest::blocking::ClientBuilder {
     let settings = GLOBAL_SETTINGS.read();
-    let mut builder = reqwest::blocking::ClientBuilder::new()
+    let builder = reqwest::blocking::ClientBuilder::new()
         .timeout(settings.read_timeout)
-        .connect_timeout(settings.connect_timeout)
-        .redirect(if settings.follow_redirects {
-            reqwest::redirect::Policy::default()
-        } else {
-            reqwest::redirect::Policy::none()
-        });
+        .connect_timeout(settings.connect_timeout);
     if cfg!(target_os = "ios") {
         // The FxA servers rely on the UA agent to filter
         // some push messages directed to iOS devices.
         // This is obviously a terrible hack and we should
         // probably do https://github.com/mozilla/application-services/issues/1326
         // instead, but this will unblock us for now.
-        builder = builder.user_agent("Firefox-iOS-FxA/24");
+        builder.user_agent("Firefox-iOS-FxA/24")
+    } else {
+        builder
     }
+}
+
+static CLIENT: Lazy<reqwest::blocking::Client> = Lazy::new(|| {
+    let follow_redirects = GLOBAL_SETTINGS.read().follow_redirects;
     // Note: no cookie or cache support.
-    builder
+    client_builder()
+        .redirect(if follow_redirects {
+            reqwest::redirect::Policy::custom(|attempt| {
+                // This client doesn't check pins, so redirects to pinned
+                // hosts would skip them.
+                if !GLOBAL_SETTINGS
+                    .read()
+                    .spki_pins_for(attempt.url())
+                    .is_empty()
+                {
+                    attempt.error("Refusing to follow a redirect to a pinned host")
+                } else if attempt.previous().len() >= MAX_REDIRECTS {
+                    attempt.error("Too many redirects")
+                } else {
+                    attempt.follow()
+                }
+            })
+        } else {
+            reqwest::redirect::Policy::none()
+        })
         .build()
         .expect("Failed to initialize global reqwest::Client")
 });
 
+// The client for requests to pinned hosts. It uses rustls rather than
+// native-tls, because rustls lets us check the pins while verifying the
+// server's certificate chain, on the connection the request is sent over.
+static PINNING_CLIENT: Lazy<reqwest::blocking::Client> = Lazy::new(|| {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    let tls_config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(PinningVerifier {
+            inner: rustls::client::WebPkiVerifier::new(roots, None),
+        }))
+        .with_no_client_auth();
+    let follow_redirects = GLOBAL_SETTINGS.read().follow_redirects;
+    client_builder()
+        // Redirects are safe to follow here, since the verifier checks the
+        // pins for every host this client connects to.
+        .redirect(if follow_redirects {
+            reqwest::redirect::Policy::limited(MAX_REDIRECTS)
+        } else {
+            reqwest::redirect::Policy::none()
+        })
+        .use_preconfigured_tls(tls_config)
+        .build()
+        .expect("Failed to initialize pinning reqwest::Client")
+});
+
+/// The error a [`PinningVerifier`] fails the handshake with, so that we can
+/// report it as `SpkiPinMismatch` rather than a generic network error.
+#[derive(Debug)]
+struct PinMismatch;
+
+impl std::fmt::Display for PinMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Certificate chain doesn't match the pinned keys")
+    }
+}
+
+impl std::error::Error for PinMismatch {}
+
+/// Verifies certificate chains as usual, then checks that the chain for a
+/// pinned host has one of its keys. As with OkHttp, any certificate in the
+/// chain can match: the server's own, the intermediates it sent, or the
+/// trust anchor which issued one of them.
+struct PinningVerifier {
+    inner: rustls::client::WebPkiVerifier,
+}
+
+impl rustls::client::ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        server_name: &rustls::ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        let host = match server_name {
+            rustls::ServerName::DnsName(name) => name.as_ref().to_ascii_lowercase(),
+            rustls::ServerName::IpAddress(addr) => addr.to_string(),
+            _ => return Ok(verified),
+        };
+        let settings = GLOBAL_SETTINGS.read();
+        let pins = match settings.spki_pins.get(&host) {
+            Some(pins) if !pins.is_empty() => pins,
+            _ => return Ok(verified),
+        };
+        let chain = || std::iter::once(end_entity).chain(intermediates);
+        let chain_matches = chain().any(|cert| {
+            viaduct::SpkiPin::from_certificate(&cert.0).map_or(false, |pin| pins.contains(&pin))
+        });
+        let anchor_matches = || {
+            chain()
+                .filter_map(|cert| viaduct::pinning::certificate_issuer(&cert.0))
+                .any(|issuer| {
+                    webpki_roots::TLS_SERVER_ROOTS.iter().any(|anchor| {
+                        anchor.subject == issuer
+                            && pins.contains(&viaduct::SpkiPin::from_spki_contents(anchor.spki))
+                    })
+                })
+        };
+        if chain_matches || anchor_matches() {
+            Ok(verified)
+        } else {
+            log::error!(
+                "Certificate chain for {} doesn't match its pinned keys",
+                host
+            );
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::Other(Arc::new(PinMismatch)),
+            ))
+        }
+    }
+}
+
+/// Whether the pinning verifier rejected the connection for `error`.
+fn is_pin_mismatch(error: &reqwest::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(e) = source {
+        if e.is::<PinMismatch>() {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
 #[allow(clippy::unnecessary_wraps)] // not worth the time to untangle
 fn into_reqwest(request: viaduct::Request) -> Result<reqwest::blocking::Request, viaduct::Error> {
     let method = match request.method {
@@ -65,10 +209,20 @@ impl Backend for ReqwestBackend {
     fn send(&self, request: viaduct::Request) -> Result<viaduct::Response, viaduct::Error> {
         viaduct::note_backend("reqwest (untrusted)");
         let request_method = request.method;
+        let pinned = !GLOBAL_SETTINGS
+            .read()
+            .spki_pins_for(&request.url)
+            .is_empty();
         let req = into_reqwest(request)?;
-        let mut resp = CLIENT
-            .execute(req)
-            .map_err(|e| viaduct::Error::NetworkError(e.to_string()))?;
+        let client = if pinned { &PINNING_CLIENT } else { &CLIENT };
+        let mut resp = client.execute(req).map_err(|e| {
+            if is_pin_mismatch(&e) {
+                let host = e.url().and_then(|url| url.host_str()).unwrap_or_default();
+                viaduct::Error::SpkiPinMismatch(host.to_string())
+            } else {
+                viaduct::Error::NetworkError(e.to_string())
+            }
+        })?;
         let status = resp.status().as_u16();
         let url = resp.url().clone();
         let mut body = Vec::with_capacity(resp.content_length().unwrap_or_default() as usize);
@@ -3,13 +3,15 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use super::{
-    request::{NormalResponseHandler, UploadInfo},
+    request::{BatchPoster, InfoConfiguration, NormalResponseHandler, PostQueue, UploadInfo},
     CollState, Sync15ClientResponse, Sync15StorageClient,
 };
-use crate::bso::{IncomingBso, OutgoingBso, OutgoingEncryptedBso};
-use crate::engine::CollectionRequest;
-use crate::error::{self, Error, Result};
-use crate::{CollectionName, KeyBundle, ServerTimestamp};
+use crate::bso::{IncomingBso, IncomingEncryptedBso, OutgoingBso, OutgoingEncryptedBso};
+use crate::engine::{CollectionRequest, RequestOrder};
+use crate::error::{self, Error, ErrorResponse, Result};
+use crate::telemetry;
+use crate::{CollectionName, Guid, KeyBundle, ServerTimestamp};
+use std::collections::HashSet;
 
 fn encrypt_outgoing(o: Vec<OutgoingBso>, key: &KeyBundle) -> Result<Vec<OutgoingEncryptedBso>> {
     o.into_iter()
@@ -17,19 +19,72 @@ fn encrypt_outgoing(o: Vec<OutgoingBso>, key: &KeyBundle) -> Result<Vec<Outgoing
         .collect()
 }
 
+/// How many records we ask the server for at a time.
+pub(crate) const DOWNLOAD_PAGE_SIZE: usize = 1000;
+
+pub fn fetch_incoming_encrypted(
+    client: &Sync15StorageClient,
+    collection_request: CollectionRequest,
+) -> Result<Vec<IncomingEncryptedBso>> {
+    match client.get_encrypted_records(collection_request)? {
+        Sync15ClientResponse::Success { record, .. } => Ok(record),
+        other => Err(other.create_storage_error()),
+    }
+}
+
+/// Fetches the records `collection_request` asks for at most `page_size` at a time,
+/// passing each page to `f` before fetching the next, so the whole collection is never
+/// held in memory at once.
+pub fn fetch_incoming_encrypted_pages(
+    client: &Sync15StorageClient,
+    collection_request: CollectionRequest,
+    page_size: usize,
+    mut f: impl FnMut(Vec<IncomingEncryptedBso>) -> Result<()>,
+) -> Result<()> {
+    // If the engine asked for a limited number of records, that's how many we fetch in
+    // total, in its order. Otherwise, we ask for the oldest first, so each page follows on
+    // from the previous one.
+    let (mut remaining, order) = match collection_request.limit {
+        Some(limit) => (Some(limit.num), limit.order),
+        None => (None, RequestOrder::Oldest),
+    };
+    let mut offset = None;
+    let mut xius = None;
+    loop {
+        let num = remaining.map_or(page_size, |remaining| remaining.min(page_size));
+        let request = collection_request.clone().limit(num, order);
+        let (response, next_offset) =
+            client.get_encrypted_records_page(request, offset.as_deref(), xius)?;
+        let records = match response {
+            Sync15ClientResponse::Success {
+                record,
+                last_modified,
+                ..
+            } => {
+                // Every page must come from the collection as it was for the first one,
+                // otherwise we could skip or repeat records.
+                xius.get_or_insert(last_modified);
+                record
+            }
+            other => return Err(other.create_storage_error()),
+        };
+        if let Some(remaining) = remaining.as_mut() {
+            *remaining = remaining.saturating_sub(records.len());
+        }
+        f(records)?;
+        match next_offset {
+            Some(next_offset) if remaining != Some(0) => offset = Some(next_offset),
+            _ => return Ok(()),
+        }
+    }
+}
+
 pub fn fetch_incoming(
     client: &Sync15StorageClient,
     state: &CollState,
     collection_request: CollectionRequest,
 ) -> Result<Vec<IncomingBso>> {
-    let (records, _timestamp) = match client.get_encrypted_records(collection_request)? {
-        Sync15ClientResponse::Success {
-            record,
-            last_modified,
-            ..
-        } => (record, last_modified),
-        other => return Err(other.create_storage_error()),
-    };
+    let records = fetch_incoming_encrypted(client, collection_request)?;
     let mut result = Vec::with_capacity(records.len());
     for record in records {
         // if we see a HMAC error, we've made an explicit decision to
@@ -90,25 +145,30 @@ impl<'a> CollectionUpdate<'a> {
 
     /// Returns a list of the IDs that failed if allowed_dropped_records is true, otherwise
     /// returns an empty vec.
-    pub fn upload(self) -> error::Result<UploadInfo> {
-        let mut failed = vec![];
-        let mut q = self.client.new_post_queue(
-            &self.collection,
+    ///
+    /// If the server rejects a post or batch commit as too large, even though it was within
+    /// the advertised limits, the records it didn't store are retried in batches half the
+    /// size, down to a record at a time. Fully atomic uploads can't be split, so they fail.
+    /// Each retry is counted in `telem`, even if the upload fails in the end.
+    pub fn upload(self, telem: &mut telemetry::EngineOutgoing) -> error::Result<UploadInfo> {
+        let client = self.client;
+        let collection = &self.collection;
+        let allow_failed = !self.fully_atomic;
+        let info = upload_with_retries(
             &self.state.config,
             self.xius,
-            NormalResponseHandler::new(!self.fully_atomic),
+            self.to_update,
+            self.fully_atomic,
+            telem,
+            |config, ts| {
+                client.new_post_queue(
+                    collection,
+                    config,
+                    ts,
+                    NormalResponseHandler::new(allow_failed),
+                )
+            },
         )?;
-
-        for record in self.to_update.into_iter() {
-            let enqueued = q.enqueue(&record)?;
-            if !enqueued && self.fully_atomic {
-                return Err(Error::RecordTooLargeError);
-            }
-        }
-
-        q.flush(true)?;
-        let mut info = q.completed_upload_info();
-        info.failed_ids.append(&mut failed);
         if self.fully_atomic {
             assert_eq!(
                 info.failed_ids.len(),
@@ -119,3 +179,247 @@ impl<'a> CollectionUpdate<'a> {
         Ok(info)
     }
 }
+
+fn upload_with_retries<P: BatchPoster>(
+    config: &InfoConfiguration,
+    xius: ServerTimestamp,
+    mut to_upload: Vec<OutgoingEncryptedBso>,
+    fully_atomic: bool,
+    telem: &mut telemetry::EngineOutgoing,
+    mut new_post_queue: impl FnMut(
+        &InfoConfiguration,
+        ServerTimestamp,
+    ) -> Result<PostQueue<P, NormalResponseHandler>>,
+) -> Result<UploadInfo> {
+    let mut config = config.clone();
+    let mut info = UploadInfo {
+        successful_ids: vec![],
+        failed_ids: vec![],
+        oversized: vec![],
+        modified_timestamp: xius,
+    };
+    loop {
+        let mut q = new_post_queue(&config, info.modified_timestamp)?;
+        let result = (|| {
+            for record in to_upload.iter() {
+                let enqueued = q.enqueue(record)?;
+                if !enqueued && fully_atomic {
+                    return Err(Error::RecordTooLargeError);
+                }
+            }
+            q.flush(true)
+        })();
+        let downgraded = match result {
+            Ok(()) => None,
+            Err(Error::StorageHttpError(ErrorResponse::PayloadTooLarge { route, status }))
+                if !fully_atomic =>
+            {
+                match downgraded_config(&config, to_upload.len()) {
+                    Some(downgraded) => {
+                        log::warn!(
+                            "Server rejected upload to {} as too large (status={}), retrying with at most {} records per batch",
+                            route,
+                            status,
+                            downgraded.max_total_records
+                        );
+                        q.discard_uncommitted();
+                        Some(downgraded)
+                    }
+                    None => {
+                        return Err(Error::StorageHttpError(ErrorResponse::PayloadTooLarge {
+                            route,
+                            status,
+                        }))
+                    }
+                }
+            }
+            Err(e) => return Err(e),
+        };
+        let mut completed = q.completed_upload_info();
+        info.successful_ids.append(&mut completed.successful_ids);
+        info.failed_ids.append(&mut completed.failed_ids);
+        info.modified_timestamp = completed.modified_timestamp;
+        info.oversized.append(&mut completed.oversized);
+        // `failed_ids` includes the oversized records, so they're reported once and never
+        // retried; smaller batches won't help them fit.
+        let done: HashSet<&Guid> = info
+            .successful_ids
+            .iter()
+            .chain(info.failed_ids.iter())
+            .collect();
+        to_upload.retain(|record| !done.contains(&record.envelope.id));
+        let Some(downgraded) = downgraded else {
+            break;
+        };
+        telem.downgraded(1);
+        config = downgraded;
+    }
+    Ok(info)
+}
+
+/// The limits to retry with after the server rejected a post or batch commit of
+/// `num_records` records, or `None` if we were already sending one at a time.
+fn downgraded_config(config: &InfoConfiguration, num_records: usize) -> Option<InfoConfiguration> {
+    let max_post_records = config.max_post_records.min(num_records);
+    let max_total_records = config.max_total_records.min(num_records);
+    if max_post_records <= 1 && max_total_records <= 1 {
+        return None;
+    }
+    Some(InfoConfiguration {
+        max_post_records: (max_post_records / 2).max(1),
+        max_total_records: (max_total_records / 2).max(1),
+        ..config.clone()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bso::OutgoingEnvelope;
+    use crate::client::request::PostResponse;
+    use crate::EncryptedPayload;
+    use std::cell::RefCell;
+
+    // A server which rejects any post with more than `max_records` records as too large,
+    // and stores everything else.
+    struct FakeServer {
+        max_records: usize,
+        posts: RefCell<Vec<usize>>,
+    }
+
+    impl BatchPoster for &FakeServer {
+        fn post<P, O>(
+            &self,
+            body: Vec<u8>,
+            _: ServerTimestamp,
+            _: Option<String>,
+            _: bool,
+            _: &PostQueue<P, O>,
+        ) -> Result<PostResponse> {
+            let records: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+            self.posts.borrow_mut().push(records.len());
+            if records.len() > self.max_records {
+                return Ok(Sync15ClientResponse::Error(
+                    ErrorResponse::PayloadTooLarge {
+                        route: "test/path".into(),
+                        status: 413,
+                    },
+                ));
+            }
+            let ids: Vec<_> = records.iter().map(|r| r["id"].clone()).collect();
+            Ok(Sync15ClientResponse::Success {
+                status: 200,
+                record: serde_json::from_value(serde_json::json!({ "success": ids })).unwrap(),
+                last_modified: ServerTimestamp(1000),
+                route: "test/path".into(),
+            })
+        }
+    }
+
+    fn make_record(id: &str, ciphertext_len: usize) -> OutgoingEncryptedBso {
+        OutgoingEncryptedBso::new(
+            OutgoingEnvelope {
+                id: id.into(),
+                sortindex: None,
+                ttl: None,
+            },
+            EncryptedPayload {
+                iv: "".into(),
+                hmac: "".into(),
+                ciphertext: "x".repeat(ciphertext_len),
+            },
+        )
+    }
+
+    fn test_config() -> InfoConfiguration {
+        InfoConfiguration {
+            max_post_records: 4,
+            max_total_records: 4,
+            max_record_payload_bytes: 1000,
+            ..InfoConfiguration::default()
+        }
+    }
+
+    fn upload_to(
+        server: &FakeServer,
+        records: Vec<OutgoingEncryptedBso>,
+        telem: &mut telemetry::EngineOutgoing,
+    ) -> Result<UploadInfo> {
+        upload_with_retries(
+            &test_config(),
+            ServerTimestamp(0),
+            records,
+            false,
+            telem,
+            |config, ts| {
+                Ok(PostQueue::new(
+                    config,
+                    ts,
+                    server,
+                    NormalResponseHandler::new(true),
+                ))
+            },
+        )
+    }
+
+    #[test]
+    fn test_upload_retries_smaller() {
+        let server = FakeServer {
+            max_records: 2,
+            posts: RefCell::default(),
+        };
+        let mut records = vec![make_record("big", 2000)];
+        records.extend((0..5).map(|i| make_record(&format!("rec{}", i), 10)));
+        let mut telem = telemetry::EngineOutgoing::new();
+        let info = upload_to(&server, records, &mut telem).unwrap();
+
+        // The first post was rejected, then everything else went in halves.
+        assert_eq!(*server.posts.borrow(), vec![4, 2, 2, 1]);
+        let mut successful: Vec<_> = info.successful_ids.iter().map(|id| id.as_str()).collect();
+        successful.sort_unstable();
+        assert_eq!(successful, vec!["rec0", "rec1", "rec2", "rec3", "rec4"]);
+        // The oversized record is only reported once, however many times we retry.
+        assert_eq!(info.failed_ids, vec![Guid::from("big")]);
+        assert_eq!(info.oversized.len(), 1);
+        assert_eq!(serde_json::to_value(&telem).unwrap()["downgrades"], 1);
+    }
+
+    #[test]
+    fn test_upload_gives_up_at_one_record() {
+        let server = FakeServer {
+            max_records: 0,
+            posts: RefCell::default(),
+        };
+        let records = vec![make_record("rec0", 10), make_record("rec1", 10)];
+        let mut telem = telemetry::EngineOutgoing::new();
+        assert!(matches!(
+            upload_to(&server, records, &mut telem),
+            Err(Error::StorageHttpError(
+                ErrorResponse::PayloadTooLarge { .. }
+            ))
+        ));
+        assert_eq!(*server.posts.borrow(), vec![2, 1]);
+        // The retry is counted even though the upload failed.
+        assert_eq!(serde_json::to_value(&telem).unwrap()["downgrades"], 1);
+    }
+
+    #[test]
+    fn test_downgraded_config() {
+        let config = InfoConfiguration {
+            max_post_records: 100,
+            ..InfoConfiguration::default()
+        };
+        let downgraded = downgraded_config(&config, 1000).unwrap();
+        assert_eq!(downgraded.max_post_records, 50);
+        assert_eq!(downgraded.max_total_records, 500);
+        assert_eq!(downgraded.max_request_bytes, config.max_request_bytes);
+
+        // The limits never go below a record at a time...
+        let downgraded = downgraded_config(&downgraded, 3).unwrap();
+        assert_eq!(downgraded.max_post_records, 1);
+        assert_eq!(downgraded.max_total_records, 1);
+        // ...and there's nothing left to try once they get there.
+        assert!(downgraded_config(&downgraded, 3).is_none());
+        assert!(downgraded_config(&config, 1).is_none());
+    }
+}
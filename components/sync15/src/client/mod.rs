@@ -17,6 +17,7 @@ mod coll_state;
 mod coll_update;
 mod collection_keys;
 mod request;
+mod staging;
 mod state;
 mod status;
 mod storage_client;
@@ -26,9 +27,12 @@ mod token;
 mod util;
 
 pub(crate) use coll_state::{CollState, LocalCollStateMachine};
-pub(crate) use coll_update::{fetch_incoming, CollectionUpdate};
+pub(crate) use coll_update::{
+    fetch_incoming, fetch_incoming_encrypted_pages, CollectionUpdate, DOWNLOAD_PAGE_SIZE,
+};
 pub(crate) use collection_keys::CollectionKeys;
 pub(crate) use request::InfoConfiguration;
+pub(crate) use staging::{IncomingStaging, STAGING_BATCH_SIZE, STAGING_THRESHOLD};
 pub(crate) use state::GlobalState;
 pub use status::{ServiceStatus, SyncResult};
 pub use storage_client::{
@@ -141,6 +141,20 @@ pub struct UploadResult {
 
 pub type PostResponse = Sync15ClientResponse<UploadResult>;
 
+/// A record which we didn't upload because it's bigger than one of the limits
+/// the server advertises in `info/configuration`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OversizedRecord {
+    pub id: Guid,
+    /// The name of the limit in `info/configuration`, e.g. `max_record_payload_bytes`.
+    pub limit: &'static str,
+    pub limit_bytes: usize,
+    /// The size of the record, measured the way the limit is: the size of the
+    /// payload for most limits, but of the whole serialized record for
+    /// `max_request_bytes`.
+    pub size: usize,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum BatchState {
     Unsupported,
@@ -159,6 +173,7 @@ pub struct PostQueue<Post, OnResponse> {
     queued: Vec<u8>,
     batch: BatchState,
     last_modified: ServerTimestamp,
+    oversized: Vec<OversizedRecord>,
 }
 
 pub trait BatchPoster {
@@ -247,6 +262,48 @@ where
             max_payload_bytes: config.max_record_payload_bytes,
             max_request_bytes: config.max_request_bytes,
             queued: Vec::new(),
+            oversized: Vec::new(),
+        }
+    }
+
+    /// The records which `enqueue` refused because they were too large.
+    pub fn oversized_records(&self) -> &[OversizedRecord] {
+        &self.oversized
+    }
+
+    fn record_oversized(
+        &mut self,
+        record: &OutgoingEncryptedBso,
+        limit: &'static str,
+        limit_bytes: usize,
+        size: usize,
+    ) {
+        log::warn!(
+            "Record {} too large to submit to server ({} b, {} is {} b)",
+            record.envelope.id,
+            size,
+            limit,
+            limit_bytes
+        );
+        self.oversized.push(OversizedRecord {
+            id: record.envelope.id.clone(),
+            limit,
+            limit_bytes,
+            size,
+        });
+    }
+
+    // Returns the first payload limit that a record of this size can never
+    // fit under, even in an otherwise empty post or batch.
+    fn payload_limit_exceeded(&self, payload_length: usize) -> Option<(&'static str, usize)> {
+        if payload_length >= self.max_payload_bytes {
+            Some(("max_record_payload_bytes", self.max_payload_bytes))
+        } else if self.post_limits.can_never_add(payload_length) {
+            Some(("max_post_bytes", self.post_limits.max_bytes))
+        } else if self.batch_limits.can_never_add(payload_length) {
+            Some(("max_total_bytes", self.batch_limits.max_bytes))
+        } else {
+            None
         }
     }
 
@@ -258,14 +315,8 @@ where
     pub fn enqueue(&mut self, record: &OutgoingEncryptedBso) -> Result<bool> {
         let payload_length = record.serialized_payload_len();
 
-        if self.post_limits.can_never_add(payload_length)
-            || self.batch_limits.can_never_add(payload_length)
-            || payload_length >= self.max_payload_bytes
-        {
-            log::warn!(
-                "Single record too large to submit to server ({} b)",
-                payload_length
-            );
+        if let Some((limit, limit_bytes)) = self.payload_limit_exceeded(payload_length) {
+            self.record_oversized(record, limit, limit_bytes, payload_length);
             return Ok(false);
         }
 
@@ -299,10 +350,8 @@ where
 
         if item_len >= self.max_request_bytes {
             self.queued.truncate(item_start);
-            log::warn!(
-                "Single record too large to submit to server ({} b)",
-                item_len
-            );
+            let max_request_bytes = self.max_request_bytes;
+            self.record_oversized(record, "max_request_bytes", max_request_bytes, item_len);
             return Ok(false);
         }
 
@@ -452,11 +501,21 @@ where
 #[derive(Clone)]
 pub struct UploadInfo {
     pub successful_ids: Vec<Guid>,
+    /// Includes the ids of any `oversized` records.
     pub failed_ids: Vec<Guid>,
+    pub oversized: Vec<OversizedRecord>,
     pub modified_timestamp: ServerTimestamp,
 }
 
 impl<Poster> PostQueue<Poster, NormalResponseHandler> {
+    /// Forget the responses for posts in a batch which was never committed,
+    /// so that `completed_upload_info` only reports what the server actually
+    /// stored or rejected.
+    pub fn discard_uncommitted(&mut self) {
+        self.on_response.pending_success.clear();
+        self.on_response.pending_failed.clear();
+    }
+
     // TODO: should take by move
     pub fn completed_upload_info(&mut self) -> UploadInfo {
         let mut result = UploadInfo {
@@ -464,8 +523,10 @@ impl<Poster> PostQueue<Poster, NormalResponseHandler> {
             failed_ids: Vec::with_capacity(
                 self.on_response.failed_ids.len()
                     + self.on_response.pending_failed.len()
-                    + self.on_response.pending_success.len(),
+                    + self.on_response.pending_success.len()
+                    + self.oversized.len(),
             ),
+            oversized: std::mem::take(&mut self.oversized),
             modified_timestamp: self.last_modified,
         };
 
@@ -480,6 +541,9 @@ impl<Poster> PostQueue<Poster, NormalResponseHandler> {
         result
             .failed_ids
             .append(&mut self.on_response.pending_success);
+        result
+            .failed_ids
+            .extend(result.oversized.iter().map(|r| r.id.clone()));
 
         result
     }
@@ -854,6 +918,15 @@ mod test {
         assert!(!enqueued, "Should not have fit");
         pq.enqueue(&make_record(payload_size)).unwrap();
         pq.flush(true).unwrap();
+        assert_eq!(
+            pq.oversized_records(),
+            &[OversizedRecord {
+                id: "".into(),
+                limit: "max_record_payload_bytes",
+                limit_bytes: 150,
+                size: 151,
+            }]
+        );
 
         let t = tester.borrow();
         assert!(t.cur_batch.is_none());
@@ -0,0 +1,186 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A disk-backed staging area for incoming records.
+//!
+//! On a first sync of a very large collection (eg, history), decrypting every
+//! incoming record and handing them all to the engine at once means holding
+//! the entire collection in memory twice over, which low-end devices can't
+//! always afford. Instead, we write the records - still encrypted, so no
+//! cleartext ever hits the disk - into a temporary SQLite database, then hand
+//! them to the engine in fixed-size batches, decrypting each batch as we go.
+//! This works for any engine, because `SyncEngine::stage_incoming()` already
+//! expects to be called multiple times per sync.
+
+use crate::bso::{IncomingBso, IncomingEncryptedBso, IncomingEnvelope};
+use crate::error::Result;
+use crate::{EncryptedPayload, KeyBundle, ServerTimestamp};
+use rusqlite::{named_params, Connection};
+
+/// Collections with more incoming records than this are staged on disk rather
+/// than being decrypted and handed to the engine in one go.
+pub(crate) const STAGING_THRESHOLD: usize = 1000;
+
+/// How many records we hand to the engine in each call to `stage_incoming()`.
+pub(crate) const STAGING_BATCH_SIZE: usize = 500;
+
+const CREATE_TABLE_SQL: &str = "
+    CREATE TABLE incoming_staging (
+        id INTEGER PRIMARY KEY,
+        guid TEXT NOT NULL,
+        modified INTEGER NOT NULL,
+        sortindex INTEGER,
+        ttl INTEGER,
+        payload TEXT NOT NULL
+    )";
+
+pub(crate) struct IncomingStaging {
+    db: Connection,
+}
+
+impl IncomingStaging {
+    /// Creates a new, empty, staging area. An empty filename gives us a
+    /// private temporary database which SQLite keeps on disk and deletes when
+    /// the connection is closed.
+    pub fn new() -> Result<Self> {
+        let db = Connection::open("")?;
+        db.execute_batch(CREATE_TABLE_SQL)?;
+        Ok(Self { db })
+    }
+
+    /// Writes the records to the staging area, consuming them so the memory
+    /// held by each can be released as soon as it's been written.
+    pub fn stage(&mut self, records: Vec<IncomingEncryptedBso>) -> Result<()> {
+        let tx = self.db.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO incoming_staging(guid, modified, sortindex, ttl, payload)
+                 VALUES(:guid, :modified, :sortindex, :ttl, :payload)",
+            )?;
+            for record in records {
+                stmt.execute(named_params! {
+                    ":guid": record.envelope.id.as_str(),
+                    ":modified": record.envelope.modified.as_millis(),
+                    ":sortindex": record.envelope.sortindex,
+                    ":ttl": record.envelope.ttl,
+                    ":payload": serde_json::to_string(&record.payload)?,
+                })?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The number of records currently staged.
+    pub fn len(&self) -> Result<usize> {
+        Ok(self
+            .db
+            .query_row("SELECT COUNT(*) FROM incoming_staging", [], |row| {
+                row.get::<_, i64>(0)
+            })? as usize)
+    }
+
+    /// Decrypts the staged records in batches of at most `batch_size`, in the
+    /// order they were staged, and passes each batch to `f`.
+    pub fn for_each_batch(
+        &self,
+        key: &KeyBundle,
+        batch_size: usize,
+        mut f: impl FnMut(Vec<IncomingBso>) -> Result<()>,
+    ) -> Result<()> {
+        let mut stmt = self.db.prepare(
+            "SELECT id, guid, modified, sortindex, ttl, payload
+             FROM incoming_staging
+             WHERE id > :last_id
+             ORDER BY id
+             LIMIT :limit",
+        )?;
+        let mut last_id = 0i64;
+        loop {
+            let mut batch = Vec::with_capacity(batch_size);
+            let mut rows = stmt.query(named_params! {
+                ":last_id": last_id,
+                ":limit": batch_size as i64,
+            })?;
+            while let Some(row) = rows.next()? {
+                last_id = row.get("id")?;
+                let envelope = IncomingEnvelope {
+                    id: row.get::<_, String>("guid")?.into(),
+                    modified: ServerTimestamp::from_millis(row.get("modified")?),
+                    sortindex: row.get("sortindex")?,
+                    ttl: row.get("ttl")?,
+                };
+                let payload: EncryptedPayload =
+                    serde_json::from_str(&row.get::<_, String>("payload")?)?;
+                // As for records we don't stage, a HMAC error here is left for
+                // the global state machine to handle.
+                batch.push(IncomingEncryptedBso::new(envelope, payload).into_decrypted(key)?);
+            }
+            if batch.is_empty() {
+                return Ok(());
+            }
+            f(batch)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Guid;
+
+    fn make_records(key: &KeyBundle, count: usize) -> Vec<IncomingEncryptedBso> {
+        (0..count)
+            .map(|i| {
+                let payload = EncryptedPayload::from_cleartext(key, i.to_string()).unwrap();
+                IncomingEncryptedBso::new(
+                    IncomingEnvelope {
+                        id: format!("record{}", i).into(),
+                        modified: ServerTimestamp::from_millis(1000 + i as i64),
+                        sortindex: Some(i as i32),
+                        ttl: None,
+                    },
+                    payload,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stage_and_read_batches() {
+        let key = KeyBundle::new_random().unwrap();
+        let mut staging = IncomingStaging::new().unwrap();
+        staging.stage(make_records(&key, 7)).unwrap();
+        staging.stage(make_records(&key, 0)).unwrap();
+        assert_eq!(staging.len().unwrap(), 7);
+
+        let mut batches = Vec::new();
+        staging
+            .for_each_batch(&key, 3, |batch| {
+                batches.push(batch);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            batches.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![3, 3, 1]
+        );
+        let records = batches.into_iter().flatten().collect::<Vec<_>>();
+        for (i, record) in records.iter().enumerate() {
+            assert_eq!(record.envelope.id, Guid::from(format!("record{}", i)));
+            assert_eq!(record.envelope.modified.as_millis(), 1000 + i as i64);
+            assert_eq!(record.envelope.sortindex, Some(i as i32));
+            assert_eq!(record.payload, i.to_string());
+        }
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let key = KeyBundle::new_random().unwrap();
+        let mut staging = IncomingStaging::new().unwrap();
+        staging.stage(make_records(&key, 2)).unwrap();
+        let other_key = KeyBundle::new_random().unwrap();
+        assert!(staging.for_each_batch(&other_key, 10, |_| Ok(())).is_err());
+    }
+}
@@ -44,6 +44,14 @@ fn parse_seconds(seconds_str: &str) -> Option<u32> {
     }
 }
 
+// The storage server's body for a 400 is one of the legacy "weave" error
+// codes, and this is the one it uses when a post or batch is over its limits.
+const WEAVE_SIZE_LIMIT_EXCEEDED: i64 = 17;
+
+fn is_size_limit_error(resp: &Response) -> bool {
+    resp.json::<i64>().ok() == Some(WEAVE_SIZE_LIMIT_EXCEEDED)
+}
+
 impl<T> Sync15ClientResponse<T> {
     pub fn from_response(resp: Response, backoff_listener: &BackoffListener) -> error::Result<Self>
     where
@@ -96,6 +104,12 @@ impl<T> Sync15ClientResponse<T> {
                 404 => Sync15ClientResponse::Error(ErrorResponse::NotFound { route }),
                 401 => Sync15ClientResponse::Error(ErrorResponse::Unauthorized { route }),
                 412 => Sync15ClientResponse::Error(ErrorResponse::PreconditionFailed { route }),
+                413 => {
+                    Sync15ClientResponse::Error(ErrorResponse::PayloadTooLarge { route, status })
+                }
+                400 if is_size_limit_error(&resp) => {
+                    Sync15ClientResponse::Error(ErrorResponse::PayloadTooLarge { route, status })
+                }
                 500..=600 => {
                     Sync15ClientResponse::Error(ErrorResponse::ServerError { route, status })
                 }
@@ -291,6 +305,11 @@ impl Sync15StorageClient {
         })
     }
 
+    /// Use a new OAuth access token the next time we need a tokenserver token.
+    pub fn set_access_token(&mut self, access_token: String) {
+        self.tsc.set_access_token(access_token);
+    }
+
     pub fn get_encrypted_records(
         &self,
         collection_request: CollectionRequest,
@@ -298,6 +317,44 @@ impl Sync15StorageClient {
         self.collection_request(Method::Get, collection_request)
     }
 
+    /// Fetches one page of the records `collection_request` asks for, starting from the
+    /// `offset` returned with the previous page. Returns the offset of the next page too, if
+    /// there are more records.
+    ///
+    /// If `xius` is given, the server fails the request when the collection was changed
+    /// since then.
+    pub fn get_encrypted_records_page(
+        &self,
+        collection_request: CollectionRequest,
+        offset: Option<&str>,
+        xius: Option<ServerTimestamp>,
+    ) -> error::Result<(
+        Sync15ClientResponse<Vec<IncomingEncryptedBso>>,
+        Option<String>,
+    )> {
+        let mut url = build_collection_request_url(
+            Url::parse(&self.tsc.api_endpoint()?)?,
+            collection_request,
+        )?;
+        if let Some(offset) = offset {
+            url.query_pairs_mut().append_pair("offset", offset);
+        }
+        let mut req = self.build_request(Method::Get, url)?;
+        if let Some(xius) = xius {
+            req = req.header(header_names::X_IF_UNMODIFIED_SINCE, format!("{}", xius))?;
+        }
+        log::trace!("request: GET {} ({:?})", req.url.path(), req.url.query());
+        let resp = req.send()?;
+        let next_offset = resp
+            .headers
+            .get(header_names::X_WEAVE_NEXT_OFFSET)
+            .map(ToOwned::to_owned);
+        Ok((
+            Sync15ClientResponse::from_response(resp, &self.backoff)?,
+            next_offset,
+        ))
+    }
+
     #[inline]
     fn authorized(&self, req: Request) -> error::Result<Request> {
         let hawk_header_value = self.tsc.authorization(&req)?;
@@ -407,7 +464,8 @@ impl Sync15StorageClient {
         self.tsc.hashed_uid()
     }
 
-    pub(crate) fn wipe_remote_engine(&self, engine: &str) -> error::Result<()> {
+    /// Delete an engine's collection from the server.
+    pub fn wipe_remote_engine(&self, engine: &str) -> error::Result<()> {
         let s = self.tsc.api_endpoint()? + "/";
         let url = Url::parse(&s)?.join(&format!("storage/{}", engine))?;
         log::debug!("Wiping: {:?}", url);
@@ -536,6 +594,35 @@ mod test {
         assert_eq!(parse_seconds("4294967296"), None);
     }
 
+    #[test]
+    fn test_payload_too_large() {
+        let classify = |status: u16, body: &str| {
+            let resp = Response {
+                request_method: Method::Post,
+                url: Url::parse("https://example.com/sync/storage/bookmarks").unwrap(),
+                status,
+                headers: viaduct::Headers::new(),
+                body: body.as_bytes().to_vec(),
+            };
+            match Sync15ClientResponse::<Value>::from_response(resp, &new_backoff_listener()) {
+                Ok(Sync15ClientResponse::Error(e)) => e,
+                _ => panic!("expected an error response"),
+            }
+        };
+        assert!(matches!(
+            classify(413, ""),
+            ErrorResponse::PayloadTooLarge { status: 413, .. }
+        ));
+        assert!(matches!(
+            classify(400, "17"),
+            ErrorResponse::PayloadTooLarge { status: 400, .. }
+        ));
+        assert!(matches!(
+            classify(400, "8"),
+            ErrorResponse::RequestFailed { status: 400, .. }
+        ));
+    }
+
     #[test]
     fn test_query_building() {
         use crate::engine::RequestOrder;
@@ -2,13 +2,17 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use super::{CollectionUpdate, GlobalState, LocalCollStateMachine, Sync15StorageClient};
+use super::{
+    CollectionUpdate, GlobalState, IncomingStaging, LocalCollStateMachine, Sync15StorageClient,
+    DOWNLOAD_PAGE_SIZE, STAGING_BATCH_SIZE, STAGING_THRESHOLD,
+};
 use crate::clients_engine;
 use crate::engine::SyncEngine;
 use crate::error::Error;
 use crate::telemetry;
 use crate::KeyBundle;
 use interrupt_support::Interruptee;
+use std::time::{Duration, Instant};
 
 #[allow(clippy::too_many_arguments)]
 pub fn synchronize_with_clients_engine(
@@ -41,32 +45,83 @@ pub fn synchronize_with_clients_engine(
         engine.prepare_for_sync(&|| clients.get_client_data())?;
     }
     interruptee.err_if_interrupted()?;
+    // Staging and applying incoming records are timed together, as engines
+    // differ in how much of the work they do in each.
+    let mut incoming_count = 0;
+    let mut apply_took = Duration::ZERO;
     // We assume an "engine" manages exactly one "collection" with the engine's name.
     match engine.get_collection_request(coll_state.last_modified)? {
         None => {
             log::info!("skipping incoming for {} - not needed.", collection);
         }
         Some(collection_request) => {
-            // Ideally we would "batch" incoming records (eg, fetch just 1000 at a time)
-            // and ask the engine to "stage" them as they come in - but currently we just read
-            // them all in one request.
-
-            // Doing this batching will involve specifying a "limit=" param and
-            // "x-if-unmodified-since" for each request, looking for an
-            // "X-Weave-Next-Offset header in the response and using that in subsequent
-            // requests.
+            // We fetch the records a page at a time, following the "X-Weave-Next-Offset"
+            // header in each response, and sending "X-If-Unmodified-Since" so the server
+            // fails the request if the collection changes between pages.
             // See https://mozilla-services.readthedocs.io/en/latest/storage/apis-1.5.html#syncstorage-paging
             //
-            // But even if we had that, we need to deal with a 412 response on a subsequent batch,
-            // so we can't know if we've staged *every* record for that timestamp; the next
-            // sync must use an earlier one.
+            // If that happens, this sync fails. The engine is only told the server timestamp
+            // at the very end, so the next sync fetches everything again.
             //
-            // For this reason, an engine can't really trust a server timestamp until the
-            // very end when we know we've staged them all.
-            let incoming = super::fetch_incoming(client, &coll_state, collection_request)?;
-            log::info!("Downloaded {} remote changes", incoming.len());
-            engine.stage_incoming(incoming, telem_engine)?;
-            interruptee.err_if_interrupted()?;
+            // Pages are kept in memory until there are too many to comfortably decrypt at
+            // once. From then on they're staged (still encrypted) on disk, and fed to the
+            // engine in batches, so we never hold the entire collection in memory.
+            let started = Instant::now();
+            let mut staging_took = Duration::ZERO;
+            let mut records = Vec::new();
+            let mut staging: Option<IncomingStaging> = None;
+            super::fetch_incoming_encrypted_pages(
+                client,
+                collection_request,
+                DOWNLOAD_PAGE_SIZE,
+                |page| {
+                    let started = Instant::now();
+                    match staging.as_mut() {
+                        Some(staging) => staging.stage(page)?,
+                        None => {
+                            records.extend(page);
+                            if records.len() > STAGING_THRESHOLD {
+                                let mut new_staging = IncomingStaging::new()?;
+                                new_staging.stage(std::mem::take(&mut records))?;
+                                staging = Some(new_staging);
+                            }
+                        }
+                    }
+                    staging_took += started.elapsed();
+                    interruptee.err_if_interrupted()?;
+                    Ok(())
+                },
+            )?;
+            telem_engine
+                .timings()
+                .downloaded(started.elapsed().saturating_sub(staging_took));
+            if let Some(staging) = staging {
+                log::info!(
+                    "Downloaded and staged {} remote changes on disk",
+                    staging.len()?
+                );
+                staging.for_each_batch(&coll_state.key, STAGING_BATCH_SIZE, |batch| {
+                    incoming_count += batch.len();
+                    let started = Instant::now();
+                    engine.stage_incoming(batch, telem_engine)?;
+                    apply_took += started.elapsed();
+                    interruptee.err_if_interrupted()?;
+                    Ok(())
+                })?;
+            } else {
+                log::info!("Downloaded {} remote changes", records.len());
+                let started = Instant::now();
+                let incoming = records
+                    .into_iter()
+                    .map(|record| record.into_decrypted(&coll_state.key))
+                    .collect::<Result<Vec<_>, _>>()?;
+                telem_engine.timings().serialized(started.elapsed());
+                incoming_count = incoming.len();
+                let started = Instant::now();
+                engine.stage_incoming(incoming, telem_engine)?;
+                apply_took = started.elapsed();
+                interruptee.err_if_interrupted()?;
+            }
         }
     };
 
@@ -75,7 +130,10 @@ pub fn synchronize_with_clients_engine(
     // but that's not clear - see the discussion at
     // https://github.com/mozilla/application-services/pull/5441/files/f36274f455a6299f10e7ce56b167882c369aa806#r1189267540
     log::info!("Applying changes");
+    let started = Instant::now();
     let outgoing = engine.apply(coll_state.last_modified, telem_engine)?;
+    apply_took += started.elapsed();
+    telem_engine.timings().applied(apply_took, incoming_count);
     interruptee.err_if_interrupted()?;
 
     // XXX - this upload strategy is buggy due to batching. With enough records, we will commit
@@ -84,21 +142,32 @@ pub fn synchronize_with_clients_engine(
     // Most stuff below should be called per-batch rather than at the successful end of all
     // batches, but that's not trivial.
     log::info!("Uploading {} outgoing changes", outgoing.len());
-    let upload_info = CollectionUpdate::new_from_changeset(
+    let started = Instant::now();
+    let update = CollectionUpdate::new_from_changeset(
         client,
         &coll_state,
         collection,
         outgoing,
         fully_atomic,
-    )?
-    .upload()?;
+    )?;
+    telem_engine.timings().serialized(started.elapsed());
+    let started = Instant::now();
+    let mut telem_outgoing = telemetry::EngineOutgoing::new();
+    let upload_info = match update.upload(&mut telem_outgoing) {
+        Ok(upload_info) => upload_info,
+        Err(e) => {
+            // Keep any retries the failed upload made.
+            telem_engine.outgoing(telem_outgoing);
+            return Err(e);
+        }
+    };
+    telem_engine.timings().uploaded(started.elapsed());
     log::info!(
         "Upload success ({} records success, {} records failed)",
         upload_info.successful_ids.len(),
         upload_info.failed_ids.len()
     );
 
-    let mut telem_outgoing = telemetry::EngineOutgoing::new();
     telem_outgoing.sent(upload_info.successful_ids.len() + upload_info.failed_ids.len());
     telem_outgoing.failed(upload_info.failed_ids.len());
     telem_engine.outgoing(telem_outgoing);
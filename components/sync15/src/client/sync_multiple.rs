@@ -35,6 +35,24 @@ impl ClientInfo {
             client: Sync15StorageClient::new(ci.clone())?,
         })
     }
+
+    // Try to update our client for a new `client_init`, returning false if
+    // it's for a different account or server and can't be reused. OAuth access
+    // tokens are refreshed much more often than tokenserver tokens expire, so a
+    // change of only the access token means we keep our tokenserver token.
+    fn update(&mut self, ci: &Sync15StorageClientInit) -> bool {
+        if self.client_init.key_id != ci.key_id
+            || self.client_init.tokenserver_url != ci.tokenserver_url
+        {
+            return false;
+        }
+        if self.client_init.access_token != ci.access_token {
+            log::debug!("Using a new access token with our existing client");
+            self.client.set_access_token(ci.access_token.clone());
+            self.client_init.access_token = ci.access_token.clone();
+        }
+        true
+    }
 }
 
 /// Info we want callers to engine *in memory* for us so that subsequent
@@ -58,6 +76,10 @@ impl MemoryCachedState {
         // Leave the backoff time, as there's no reason to think it's not still
         // true.
     }
+    /// The storage client used by the last sync, if its state hasn't been cleared since.
+    pub fn storage_client(&self) -> Option<&Sync15StorageClient> {
+        self.last_client_info.as_ref().map(|info| &info.client)
+    }
     pub fn get_next_sync_after(&self) -> Option<SystemTime> {
         self.next_sync_after
     }
@@ -423,12 +445,12 @@ impl<'info, 'res, 'pgs, 'mcs> SyncMultipleDriver<'info, 'res, 'pgs, 'mcs> {
 
     fn prepare_client_info(&mut self) -> result::Result<ClientInfo, Error> {
         let mut client_info = match self.mem_cached_state.last_client_info.take() {
-            Some(client_info) => {
+            Some(mut client_info) => {
                 // if our storage_init has changed it probably means the user has
                 // changed, courtesy of the 'kid' in the structure. Thus, we can't
                 // reuse the client or the memory cached state. We do keep the disk
                 // state as currently that's only the declined list.
-                if client_info.client_init != *self.storage_init {
+                if !client_info.update(self.storage_init) {
                     log::info!("Discarding all state as the account might have changed");
                     *self.mem_cached_state = MemoryCachedState::default();
                     ClientInfo::new(self.storage_init)?
@@ -489,3 +511,30 @@ impl<'info, 'res, 'pgs, 'mcs> SyncMultipleDriver<'info, 'res, 'pgs, 'mcs> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    fn client_init(key_id: &str, access_token: &str) -> Sync15StorageClientInit {
+        Sync15StorageClientInit {
+            key_id: key_id.to_string(),
+            access_token: access_token.to_string(),
+            tokenserver_url: Url::parse("https://token.services.mozilla.com/").unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_client_info_update() {
+        let mut client_info = ClientInfo::new(&client_init("kid", "token")).unwrap();
+        assert!(client_info.update(&client_init("kid", "token")));
+
+        // A new access token for the same account keeps the client.
+        assert!(client_info.update(&client_init("kid", "new-token")));
+        assert_eq!(client_info.client_init, client_init("kid", "new-token"));
+
+        // A new key id means the account (or its keys) changed.
+        assert!(!client_info.update(&client_init("other-kid", "new-token")));
+    }
+}
@@ -4,17 +4,22 @@
 
 use crate::error::{self, Error as ErrorKind, Result};
 use crate::ServerTimestamp;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use rc_crypto::hawk;
 use serde_derive::*;
 use std::borrow::{Borrow, Cow};
 use std::cell::RefCell;
 use std::fmt;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use url::Url;
 use viaduct::{header_names, Request};
 
 const RETRY_AFTER_DEFAULT_MS: u64 = 10000;
 
+// Hawk rejects requests whose timestamp is more than a minute away from the
+// server's clock, so that's when a skewed clock is worth logging.
+const CLOCK_SKEW_WARNING_MS: i64 = 60_000;
+
 // The TokenserverToken is the token as received directly from the token server
 // and deserialized from JSON.
 #[derive(Deserialize, Clone, PartialEq, Eq)]
@@ -95,6 +100,10 @@ impl TokenServerFetcher {
             key_id,
         }
     }
+
+    fn set_access_token(&mut self, access_token: String) {
+        self.access_token = access_token;
+    }
 }
 
 impl TokenFetcher for TokenServerFetcher {
@@ -148,6 +157,9 @@ struct TokenContext {
     credentials: hawk::Credentials,
     server_timestamp: ServerTimestamp,
     valid_until: SystemTime,
+    // How far the server's clock is ahead of ours (negative if it's behind),
+    // as measured when we fetched the token.
+    clock_skew_ms: i64,
 }
 
 // hawk::Credentials doesn't implement debug -_-
@@ -158,6 +170,7 @@ impl fmt::Debug for TokenContext {
             .field("credentials", &"(omitted)")
             .field("server_timestamp", &self.server_timestamp)
             .field("valid_until", &self.valid_until)
+            .field("clock_skew_ms", &self.clock_skew_ms)
             .finish()
     }
 }
@@ -168,12 +181,14 @@ impl TokenContext {
         credentials: hawk::Credentials,
         server_timestamp: ServerTimestamp,
         valid_until: SystemTime,
+        clock_skew_ms: i64,
     ) -> Self {
         Self {
             token,
             credentials,
             server_timestamp,
             valid_until,
+            clock_skew_ms,
         }
     }
 
@@ -187,7 +202,19 @@ impl TokenContext {
         now < self.valid_until
     }
 
-    fn authorization(&self, req: &Request) -> Result<String> {
+    // Our best guess at what the server thinks the time is.
+    fn server_now(&self, now: SystemTime) -> SystemTime {
+        if self.clock_skew_ms >= 0 {
+            now + Duration::from_millis(self.clock_skew_ms as u64)
+        } else {
+            now - Duration::from_millis(self.clock_skew_ms.unsigned_abs())
+        }
+    }
+
+    // Note that we only need to correct the timestamps we sign. Everything we
+    // send in `X-If-Unmodified-Since` comes from the server's own responses,
+    // so is never mixed with our local clock.
+    fn authorization(&self, req: &Request, now: SystemTime) -> Result<String> {
         let url = &req.url;
 
         let path_and_query = match url.query() {
@@ -206,10 +233,17 @@ impl TokenContext {
             )
         })?;
 
+        // This matches the nonce `make_header()` would have generated.
+        let mut nonce = [0u8; 10];
+        rc_crypto::rand::fill(&mut nonce)?;
         let header =
             hawk::RequestBuilder::new(req.method.as_str(), host, port, path_and_query.borrow())
                 .request()
-                .make_header(&self.credentials)?;
+                .make_header_full(
+                    &self.credentials,
+                    self.server_now(now),
+                    URL_SAFE_NO_PAD.encode(nonce),
+                )?;
 
         Ok(format!("Hawk {}", header))
     }
@@ -260,7 +294,20 @@ impl<TF: TokenFetcher> TokenProviderImpl<TF> {
     fn fetch_context(&self) -> Result<TokenContext> {
         let result = self.fetcher.fetch_token()?;
         let token = result.token;
-        let valid_until = SystemTime::now() + Duration::from_secs(token.duration);
+        let now = self.fetcher.now();
+        let valid_until = now + Duration::from_secs(token.duration);
+        // The timestamp is from the tokenserver rather than the storage node,
+        // but they're expected to agree to well within hawk's tolerance.
+        let clock_skew_ms = match now.duration_since(UNIX_EPOCH) {
+            Ok(local) => result.server_timestamp.as_millis() - local.as_millis() as i64,
+            Err(_) => 0,
+        };
+        if clock_skew_ms.abs() > CLOCK_SKEW_WARNING_MS {
+            log::warn!(
+                "Local clock differs from the server's by {}ms, correcting",
+                clock_skew_ms
+            );
+        }
 
         let credentials = hawk::Credentials {
             id: token.id.clone(),
@@ -272,6 +319,7 @@ impl<TF: TokenFetcher> TokenProviderImpl<TF> {
             credentials,
             result.server_timestamp,
             valid_until,
+            clock_skew_ms,
         ))
     }
 
@@ -384,7 +432,7 @@ impl<TF: TokenFetcher> TokenProviderImpl<TF> {
     }
 
     fn authorization(&self, req: &Request) -> Result<String> {
-        self.with_token(|ctx| ctx.authorization(req))
+        self.with_token(|ctx| ctx.authorization(req, self.fetcher.now()))
     }
 
     fn api_endpoint(&self) -> Result<String> {
@@ -420,6 +468,12 @@ impl TokenProvider {
     pub fn api_endpoint(&self) -> Result<String> {
         self.imp.api_endpoint()
     }
+
+    /// Use a new OAuth access token for future tokenserver requests. Any token
+    /// we already hold keeps being used until it expires.
+    pub fn set_access_token(&mut self, access_token: String) {
+        self.imp.fetcher.set_access_token(access_token);
+    }
 }
 
 #[cfg(test)]
@@ -552,6 +606,48 @@ mod tests {
         assert_eq!(counter.get(), 2);
     }
 
+    #[test]
+    fn test_clock_skew() {
+        let now = SystemTime::now();
+        let local_millis = now.duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+        let server_offset: Cell<i64> = Cell::new(-3_600_000);
+        let fetch = || {
+            Ok(TokenFetchResult {
+                token: TokenserverToken {
+                    id: "id".to_string(),
+                    key: "key".to_string(),
+                    api_endpoint: "https://example.com/1.5/1".to_string(),
+                    uid: 1,
+                    duration: 10,
+                    hashed_fxa_uid: "hash".to_string(),
+                },
+                server_timestamp: ServerTimestamp(local_millis + server_offset.get()),
+            })
+        };
+        let tsc = make_tsc(fetch, || now);
+        let ts_for_header = || {
+            let req =
+                Request::get(Url::parse("https://example.com/1.5/1/info/collections").unwrap());
+            let header = tsc.authorization(&req).unwrap();
+            let ts = header
+                .split(", ")
+                .find_map(|part| part.strip_prefix("ts=\""))
+                .and_then(|ts| ts.strip_suffix('"'))
+                .unwrap()
+                .parse::<i64>()
+                .unwrap();
+            ts - local_millis / 1000
+        };
+
+        // Our clock is an hour fast, so the signed timestamp is pulled back.
+        assert_eq!(ts_for_header(), -3600);
+
+        // The skew is measured again with each new token.
+        server_offset.set(90_000);
+        *tsc.current_state.borrow_mut() = TokenState::NoToken;
+        assert_eq!(ts_for_header(), 90);
+    }
+
     #[test]
     fn test_server_url() {
         assert_eq!(
@@ -11,6 +11,7 @@ use crate::client::{
 };
 use crate::client_types::{ClientData, RemoteClient};
 use crate::engine::CollectionRequest;
+use crate::telemetry;
 use crate::{error::Result, Guid, KeyBundle};
 use interrupt_support::Interruptee;
 
@@ -305,6 +306,8 @@ impl<'a> Engine<'a> {
         self.recent_clients = driver.recent_clients;
 
         self.interruptee.err_if_interrupted()?;
+        // We don't record telemetry for the clients engine, and its uploads are fully
+        // atomic, so they're never retried in smaller batches anyway.
         let upload_info = CollectionUpdate::new_from_changeset(
             storage_client,
             &coll_state,
@@ -312,7 +315,7 @@ impl<'a> Engine<'a> {
             outgoing,
             true,
         )?
-        .upload()?;
+        .upload(&mut telemetry::EngineOutgoing::new())?;
 
         log::info!(
             "Upload success ({} records success, {} records failed)",
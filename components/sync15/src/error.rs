@@ -13,6 +13,9 @@ pub enum ErrorResponse {
     Unauthorized { route: String },
     // 412
     PreconditionFailed { route: String },
+    // 413, or a 400 with the "size limit exceeded" error code: the server
+    // refused a post or batch commit which was within the limits it advertises.
+    PayloadTooLarge { route: String, status: u16 },
     // 5XX
     ServerError { route: String, status: u16 }, // TODO: info for "retry-after" and backoff handling etc here.
     // Other HTTP responses.
@@ -31,6 +34,10 @@ pub enum Error {
     #[error("SHA256 HMAC Mismatch error")]
     HmacMismatch,
 
+    #[cfg(feature = "crypto")]
+    #[error("Failed to unwrap key backup; wrong passphrase or corrupt backup")]
+    KeyBackupUnwrapError,
+
     #[cfg(feature = "crypto")]
     #[error("Crypto/NSS error: {0}")]
     CryptoError(#[from] rc_crypto::Error),
@@ -122,6 +129,10 @@ pub enum Error {
     #[error("URL parse error: {0}")]
     MalformedUrl(#[from] url::ParseError),
 
+    #[cfg(feature = "sync-client")]
+    #[error("Error staging incoming records: {0}")]
+    StagingError(#[from] rusqlite::Error),
+
     #[error("The operation was interrupted.")]
     Interrupted(#[from] Interrupted),
 }
@@ -0,0 +1,184 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Passphrase-wrapped backups of a `KeyBundle`.
+//!
+//! Everything on the sync server is encrypted with keys derived from the
+//! user's account, so a user who loses access to all their devices (and hence
+//! their keys) also loses their synced data. A wrapped backup lets them keep a
+//! "recovery key" somewhere safe: the key bundle, encrypted with AES-256-GCM
+//! under a key stretched from a passphrase with PBKDF2-SHA256.
+//!
+//! The backup is a JSON string, tagged with a version so that we can change
+//! the KDF or its parameters later while still reading old backups.
+
+use crate::error::{Error, Result};
+use crate::KeyBundle;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rc_crypto::{
+    aead::{self, OpeningKey, SealingKey},
+    pbkdf2, rand,
+};
+use serde_derive::*;
+
+/// The number of PBKDF2 iterations for new backups. Stored in the backup, so
+/// this can be increased without breaking existing ones.
+const PBKDF2_ITERATIONS: u32 = 600_000;
+/// The range of iterations we accept when unwrapping. The count comes from the backup, so
+/// without an upper bound a corrupt or malicious one could keep us busy for hours, and
+/// without a lower bound one could be brute-forced cheaply.
+const MIN_PBKDF2_ITERATIONS: u32 = 100_000;
+const MAX_PBKDF2_ITERATIONS: u32 = 10 * PBKDF2_ITERATIONS;
+const SALT_LEN: usize = 16;
+/// Binds the ciphertext to its purpose, so a backup can't be confused with
+/// anything else sealed under the same passphrase.
+const AAD: &[u8] = b"sync15-key-bundle-backup-v1";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "version")]
+enum WrappedKeyBackup {
+    V1 {
+        iterations: u32,
+        salt: String,
+        nonce: String,
+        ciphertext: String,
+    },
+}
+
+fn derive_wrapping_key(passphrase: &str, salt: &[u8], iterations: u32) -> Result<Vec<u8>> {
+    let mut key = vec![0u8; aead::AES_256_GCM.key_len()];
+    pbkdf2::derive(
+        passphrase.as_bytes(),
+        salt,
+        iterations,
+        pbkdf2::HashAlgorithm::SHA256,
+        &mut key,
+    )?;
+    Ok(key)
+}
+
+impl KeyBundle {
+    /// Wraps this key bundle under `passphrase`, returning a string which can
+    /// be stored anywhere and later passed to `from_wrapped_backup`.
+    pub fn to_wrapped_backup(&self, passphrase: &str) -> Result<String> {
+        self.wrap_with_iterations(passphrase, PBKDF2_ITERATIONS)
+    }
+
+    fn wrap_with_iterations(&self, passphrase: &str, iterations: u32) -> Result<String> {
+        let mut salt = [0u8; SALT_LEN];
+        rand::fill(&mut salt)?;
+        let mut nonce = vec![0u8; aead::AES_256_GCM.nonce_len()];
+        rand::fill(&mut nonce)?;
+
+        let wrapping_key = derive_wrapping_key(passphrase, &salt, iterations)?;
+        let key = SealingKey::new(&aead::AES_256_GCM, &wrapping_key)?;
+        let ciphertext = aead::seal(
+            &key,
+            aead::Nonce::try_assume_unique_for_key(&aead::AES_256_GCM, &nonce)?,
+            aead::Aad::from(AAD),
+            &[self.encryption_key(), self.hmac_key()].concat(),
+        )?;
+        let backup = WrappedKeyBackup::V1 {
+            iterations,
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        Ok(serde_json::to_string(&backup)?)
+    }
+
+    /// Unwraps a backup created by `to_wrapped_backup`. Fails with
+    /// `Error::KeyBackupUnwrapError` if the passphrase is wrong, the backup
+    /// has been tampered with, or its iteration count is out of range.
+    pub fn from_wrapped_backup(backup: &str, passphrase: &str) -> Result<KeyBundle> {
+        match serde_json::from_str(backup)? {
+            WrappedKeyBackup::V1 {
+                iterations,
+                salt,
+                nonce,
+                ciphertext,
+            } => {
+                if !(MIN_PBKDF2_ITERATIONS..=MAX_PBKDF2_ITERATIONS).contains(&iterations) {
+                    return Err(Error::KeyBackupUnwrapError);
+                }
+                let wrapping_key =
+                    derive_wrapping_key(passphrase, &STANDARD.decode(salt)?, iterations)?;
+                let key = OpeningKey::new(&aead::AES_256_GCM, &wrapping_key)?;
+                let nonce = STANDARD.decode(nonce)?;
+                let ksync = aead::open(
+                    &key,
+                    aead::Nonce::try_assume_unique_for_key(&aead::AES_256_GCM, &nonce)?,
+                    aead::Aad::from(AAD),
+                    &STANDARD.decode(ciphertext)?,
+                )
+                .map_err(|_| Error::KeyBackupUnwrapError)?;
+                KeyBundle::from_ksync_bytes(&ksync)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backup_roundtrip() {
+        let key_bundle = KeyBundle::new_random().unwrap();
+        let backup = key_bundle
+            .wrap_with_iterations("hunter2", MIN_PBKDF2_ITERATIONS)
+            .unwrap();
+        assert_eq!(
+            KeyBundle::from_wrapped_backup(&backup, "hunter2").unwrap(),
+            key_bundle
+        );
+        // Each backup is salted differently.
+        assert_ne!(
+            key_bundle
+                .wrap_with_iterations("hunter2", MIN_PBKDF2_ITERATIONS)
+                .unwrap(),
+            backup
+        );
+    }
+
+    #[test]
+    fn test_backup_wrong_passphrase() {
+        let key_bundle = KeyBundle::new_random().unwrap();
+        let backup = key_bundle
+            .wrap_with_iterations("hunter2", MIN_PBKDF2_ITERATIONS)
+            .unwrap();
+        assert!(matches!(
+            KeyBundle::from_wrapped_backup(&backup, "hunter3"),
+            Err(Error::KeyBackupUnwrapError)
+        ));
+    }
+
+    #[test]
+    fn test_backup_iterations_out_of_range() {
+        let key_bundle = KeyBundle::new_random().unwrap();
+        let backup = key_bundle
+            .wrap_with_iterations("hunter2", MIN_PBKDF2_ITERATIONS)
+            .unwrap();
+        let with_iterations = |iterations: u32| {
+            let mut value: serde_json::Value = serde_json::from_str(&backup).unwrap();
+            value["iterations"] = iterations.into();
+            value.to_string()
+        };
+        for iterations in [0, MIN_PBKDF2_ITERATIONS - 1, MAX_PBKDF2_ITERATIONS + 1] {
+            assert!(matches!(
+                KeyBundle::from_wrapped_backup(&with_iterations(iterations), "hunter2"),
+                Err(Error::KeyBackupUnwrapError)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_backup_unknown_version() {
+        let backup = r#"{"version":"V2","kdf":"argon2id"}"#;
+        assert!(matches!(
+            KeyBundle::from_wrapped_backup(backup, "hunter2"),
+            Err(Error::JsonError(_))
+        ));
+    }
+}
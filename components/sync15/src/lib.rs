@@ -22,10 +22,14 @@ mod enc_payload;
 pub mod engine;
 mod error;
 #[cfg(feature = "crypto")]
+mod key_backup;
+#[cfg(feature = "crypto")]
 mod key_bundle;
 mod record_types;
 mod server_timestamp;
 pub mod telemetry;
+#[cfg(all(feature = "sync-engine", any(test, feature = "testing")))]
+pub mod testing;
 
 pub use crate::client_types::{ClientData, RemoteClient};
 pub use crate::device_type::DeviceType;
@@ -9,7 +9,7 @@ use crate::error::Error;
 #[cfg(feature = "sync-client")]
 use crate::error::ErrorResponse;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::time;
 
 use serde::{ser, Serialize, Serializer};
@@ -245,6 +245,45 @@ pub enum SyncFailure {
     Http { code: u16 },
 }
 
+/// A coarse, stable classification of a [SyncFailure], so failures can be
+/// aggregated across releases even as the errors behind them change. The
+/// serialized names must never change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum FailureKind {
+    #[serde(rename = "shutdown")]
+    Shutdown,
+
+    #[serde(rename = "auth")]
+    Auth,
+
+    /// A 4xx response from the server.
+    #[serde(rename = "clienthttp")]
+    ClientHttp,
+
+    /// A 5xx response from the server, including when it asked us to back off.
+    #[serde(rename = "serverhttp")]
+    ServerHttp,
+
+    #[serde(rename = "unexpected")]
+    Unexpected,
+
+    #[serde(rename = "other")]
+    Other,
+}
+
+impl SyncFailure {
+    pub fn kind(&self) -> FailureKind {
+        match self {
+            SyncFailure::Shutdown => FailureKind::Shutdown,
+            SyncFailure::Auth { .. } => FailureKind::Auth,
+            SyncFailure::Http { code } if *code >= 500 => FailureKind::ServerHttp,
+            SyncFailure::Http { .. } => FailureKind::ClientHttp,
+            SyncFailure::Unexpected { .. } => FailureKind::Unexpected,
+            SyncFailure::Other { .. } => FailureKind::Other,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -280,6 +319,31 @@ mod test {
             serde_json::json!({"name": "httperror", "code": 500}),
         );
     }
+
+    #[test]
+    fn kinds() {
+        assert_eq!(SyncFailure::Shutdown.kind(), FailureKind::Shutdown);
+        assert_eq!(
+            SyncFailure::Auth { from: "storage" }.kind(),
+            FailureKind::Auth
+        );
+        assert_eq!(
+            SyncFailure::Http { code: 412 }.kind(),
+            FailureKind::ClientHttp
+        );
+        assert_eq!(
+            SyncFailure::Http { code: 503 }.kind(),
+            FailureKind::ServerHttp
+        );
+        assert_eq!(
+            SyncFailure::Other {
+                error: "dunno".to_string()
+            }
+            .kind(),
+            FailureKind::Other
+        );
+        assert_json(&FailureKind::ClientHttp, serde_json::json!("clienthttp"));
+    }
 }
 
 /// Incoming record for an engine's sync
@@ -380,6 +444,11 @@ pub struct EngineOutgoing {
 
     #[serde(skip_serializing_if = "crate::skip_if_default")]
     failed: usize,
+
+    /// How many times the upload was retried in smaller batches, because the
+    /// server rejected one as too large.
+    #[serde(skip_serializing_if = "crate::skip_if_default")]
+    downgrades: usize,
 }
 
 impl EngineOutgoing {
@@ -398,6 +467,116 @@ impl EngineOutgoing {
     pub fn failed(&mut self, n: usize) {
         self.failed += n;
     }
+
+    #[inline]
+    pub fn downgraded(&mut self, n: usize) {
+        self.downgrades += n;
+    }
+}
+
+// The inclusive upper bounds, in milliseconds, of the buckets in a
+// `Histogram`. Longer durations are counted in the last bucket.
+const HISTOGRAM_BUCKETS: [u64; 15] = [
+    1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000, 10000, 20000, 60000,
+];
+
+/// A distribution of durations, in milliseconds. Each non-empty bucket is
+/// serialized with its upper bound as the key.
+#[derive(Debug, Default, Serialize)]
+pub struct Histogram {
+    sum: u64,
+    values: BTreeMap<u64, u32>,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, took: time::Duration) {
+        let ms = took.as_millis().try_into().unwrap_or(u64::MAX);
+        let bucket = HISTOGRAM_BUCKETS
+            .iter()
+            .copied()
+            .find(|&bound| ms <= bound)
+            .unwrap_or(HISTOGRAM_BUCKETS[HISTOGRAM_BUCKETS.len() - 1]);
+        self.sum = self.sum.saturating_add(ms);
+        *self.values.entry(bucket).or_default() += 1;
+    }
+
+    /// The number of durations recorded. Mostly useful for testing.
+    pub fn count(&self) -> u32 {
+        self.values.values().sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// Where an engine's sync spent its time. Each stage is recorded as a
+/// histogram, as some, like serialization, happen more than once in a sync.
+#[derive(Debug, Default, Serialize)]
+pub struct EngineTimings {
+    /// How long applying incoming records took, scaled to 100 records, so
+    /// syncs of different sizes can be compared.
+    #[serde(rename = "applyPer100")]
+    #[serde(skip_serializing_if = "Histogram::is_empty")]
+    apply_per_100: Histogram,
+
+    /// How long encrypting and decrypting records took.
+    #[serde(skip_serializing_if = "Histogram::is_empty")]
+    serialization: Histogram,
+
+    #[serde(skip_serializing_if = "Histogram::is_empty")]
+    download: Histogram,
+
+    #[serde(skip_serializing_if = "Histogram::is_empty")]
+    upload: Histogram,
+}
+
+impl EngineTimings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A helper used via skip_serializing_if
+    fn is_empty(&self) -> bool {
+        self.apply_per_100.is_empty()
+            && self.serialization.is_empty()
+            && self.download.is_empty()
+            && self.upload.is_empty()
+    }
+
+    /// Record that applying `records` incoming records took `took`. Nothing
+    /// is recorded if there weren't any records.
+    pub fn applied(&mut self, took: time::Duration, records: usize) {
+        if records > 0 {
+            let records = u32::try_from(records).unwrap_or(u32::MAX);
+            self.apply_per_100.record(took * 100 / records);
+        }
+    }
+
+    #[inline]
+    pub fn serialized(&mut self, took: time::Duration) {
+        self.serialization.record(took);
+    }
+
+    #[inline]
+    pub fn downloaded(&mut self, took: time::Duration) {
+        self.download.record(took);
+    }
+
+    #[inline]
+    pub fn uploaded(&mut self, took: time::Duration) {
+        self.upload.record(took);
+    }
+
+    /// Get the histogram of apply times. Mostly useful for testing.
+    #[inline]
+    pub fn get_apply_per_100(&self) -> &Histogram {
+        &self.apply_per_100
+    }
 }
 
 /// One engine's sync.
@@ -418,6 +597,13 @@ pub struct Engine {
     #[serde(rename = "failureReason")]
     failure: Option<SyncFailure>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "failureKind")]
+    failure_kind: Option<FailureKind>,
+
+    #[serde(skip_serializing_if = "EngineTimings::is_empty")]
+    timings: EngineTimings,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     validation: Option<Validation>,
 }
@@ -430,6 +616,8 @@ impl Engine {
             incoming: None,
             outgoing: Vec::new(),
             failure: None,
+            failure_kind: None,
+            timings: EngineTimings::new(),
             validation: None,
         }
     }
@@ -455,6 +643,7 @@ impl Engine {
         // first is the most important and all others stem from that.
         let failure = err.into();
         if self.failure.is_none() {
+            self.failure_kind = Some(failure.kind());
             self.failure = Some(failure);
         } else {
             log::warn!(
@@ -465,6 +654,14 @@ impl Engine {
         }
     }
 
+    pub fn timings(&mut self) -> &mut EngineTimings {
+        &mut self.timings
+    }
+
+    pub fn get_timings(&self) -> &EngineTimings {
+        &self.timings
+    }
+
     pub fn validation(&mut self, v: Validation) {
         assert!(self.validation.is_none());
         self.validation = Some(v);
@@ -572,6 +769,41 @@ mod engine_tests {
             &e,
             serde_json::json!({"name": "TestEngine", "when": 0.0, "outgoing": [{"sent": 2, "failed": 1}]}),
         );
+
+        let mut o = EngineOutgoing::new();
+        o.sent(3);
+        o.downgraded(2);
+        let mut e = Engine::new("TestEngine");
+        e.outgoing(o);
+        e.finished();
+        assert_json(
+            &e,
+            serde_json::json!({"name": "TestEngine", "when": 0.0, "outgoing": [{"sent": 3, "downgrades": 2}]}),
+        );
+    }
+
+    #[test]
+    fn test_timings() {
+        let mut e = Engine::new("TestEngine");
+        e.timings().applied(time::Duration::from_millis(30), 300);
+        e.timings().applied(time::Duration::from_millis(30), 30);
+        e.timings().applied(time::Duration::from_millis(30), 0);
+        e.timings().downloaded(time::Duration::from_millis(150));
+        e.timings().uploaded(time::Duration::from_secs(120));
+        e.finished();
+        assert_eq!(e.get_timings().get_apply_per_100().count(), 2);
+        assert_json(
+            &e,
+            serde_json::json!({
+                "name": "TestEngine",
+                "when": 0.0,
+                "timings": {
+                    "applyPer100": {"sum": 110, "values": {"10": 1, "100": 1}},
+                    "download": {"sum": 150, "values": {"200": 1}},
+                    "upload": {"sum": 120000, "values": {"60000": 1}}
+                }
+            }),
+        );
     }
 
     #[test]
@@ -583,7 +815,8 @@ mod engine_tests {
             &e,
             serde_json::json!({"name": "TestEngine",
              "when": 0.0,
-             "failureReason": {"name": "httperror", "code": 500}
+             "failureReason": {"name": "httperror", "code": 500},
+             "failureKind": "serverhttp"
             }),
         );
     }
@@ -620,6 +853,10 @@ pub struct SyncTelemetry {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "failureReason")]
     failure: Option<SyncFailure>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "failureKind")]
+    failure_kind: Option<FailureKind>,
 }
 
 impl SyncTelemetry {
@@ -634,6 +871,7 @@ impl SyncTelemetry {
 
     pub fn failure(&mut self, failure: SyncFailure) {
         assert!(self.failure.is_none());
+        self.failure_kind = Some(failure.kind());
         self.failure = Some(failure);
     }
 
@@ -674,7 +912,8 @@ mod sync_tests {
                     "failureReason": {
                         "name": "httperror",
                         "code": 500
-                    }
+                    },
+                    "failureKind": "serverhttp"
                 }]
             }),
         );
@@ -723,7 +962,8 @@ mod sync_tests {
                 "failureReason": {
                     "name": "httperror",
                     "code": 500
-                }
+                },
+                "failureKind": "serverhttp"
             }),
         );
     }
@@ -836,6 +1076,9 @@ impl<'a> From<&'a Error> for SyncFailure {
                 ErrorResponse::Unauthorized { .. } => SyncFailure::Auth { from: "storage" },
                 ErrorResponse::PreconditionFailed { .. } => SyncFailure::Http { code: 412 },
                 ErrorResponse::ServerError { status, .. } => SyncFailure::Http { code: *status },
+                ErrorResponse::PayloadTooLarge { status, .. } => {
+                    SyncFailure::Http { code: *status }
+                }
                 ErrorResponse::RequestFailed { status, .. } => SyncFailure::Http { code: *status },
             },
             #[cfg(feature = "crypto")]
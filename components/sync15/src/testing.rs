@@ -0,0 +1,413 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A kit for testing [SyncEngine] implementations without a sync server.
+//!
+//! [FakeServer] holds the records for a single collection in memory. Tests put records on it,
+//! call [FakeServer::sync] with their engine, then inspect what the engine uploaded. `sync()`
+//! drives the engine through the same steps, in the same order, as a real sync:
+//!
+//! * If the engine's [EngineSyncAssociation] doesn't match the server's sync IDs, the engine
+//!   is `reset()` with them.
+//! * If client data has been set with [FakeServer::set_client_data], `prepare_for_sync()`.
+//! * `get_collection_request()`, and `stage_incoming()` with the records matching that request.
+//! * `apply()`, with the collection's timestamp.
+//! * The outgoing records are stored on the server, with a new timestamp, then
+//!   `set_uploaded()` and `sync_finished()`.
+//!
+//! There's no encryption, and uploads always succeed - this is for testing how an engine
+//! handles records, not how the sync client talks to the server.
+//!
+//! ```rust,ignore
+//! let mut server = FakeServer::new("tabs");
+//! server.put_record(json!({"id": "device-1", "clientName": "phone", "tabs": []}));
+//! let outcome = server.sync(&engine)?;
+//! assert_eq!(outcome.incoming, vec![Guid::new("device-1")]);
+//! ```
+
+use crate::bso::{IncomingBso, IncomingEnvelope, IncomingKind, OutgoingBso};
+use crate::client_types::ClientData;
+use crate::engine::{CollSyncIds, EngineSyncAssociation, RequestOrder, SyncEngine};
+use crate::{telemetry, CollectionName, Guid, ServerTimestamp};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A record as stored on the [FakeServer].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerRecord {
+    pub id: Guid,
+    pub modified: ServerTimestamp,
+    pub sortindex: Option<i32>,
+    pub ttl: Option<u32>,
+    pub payload: String,
+}
+
+impl ServerRecord {
+    pub fn to_incoming(&self) -> IncomingBso {
+        IncomingBso::new(
+            IncomingEnvelope {
+                id: self.id.clone(),
+                modified: self.modified,
+                sortindex: self.sortindex,
+                ttl: self.ttl,
+            },
+            self.payload.clone(),
+        )
+    }
+
+    /// The record's content, or None if it's a tombstone or doesn't parse as a `T`.
+    pub fn content<T: for<'de> serde::Deserialize<'de>>(&self) -> Option<T> {
+        self.to_incoming().into_content::<T>().content()
+    }
+
+    pub fn is_tombstone(&self) -> bool {
+        matches!(
+            self.to_incoming().into_content::<serde_json::Value>().kind,
+            IncomingKind::Tombstone
+        )
+    }
+}
+
+/// What happened during a [FakeServer::sync].
+#[derive(Debug)]
+pub struct SyncOutcome {
+    /// The IDs of the records passed to `stage_incoming()`. Empty if the engine didn't ask for
+    /// any records.
+    pub incoming: Vec<Guid>,
+    /// The records returned by `apply()`, as stored on the server.
+    pub uploaded: Vec<ServerRecord>,
+    /// The engine was reset because its sync IDs didn't match the server's.
+    pub reset: bool,
+    pub telemetry: telemetry::Engine,
+}
+
+pub struct FakeServer {
+    collection: CollectionName,
+    sync_ids: CollSyncIds,
+    records: BTreeMap<Guid, ServerRecord>,
+    last_modified: ServerTimestamp,
+    client_data: Option<ClientData>,
+}
+
+impl FakeServer {
+    pub fn new(collection: impl Into<CollectionName>) -> Self {
+        Self {
+            collection: collection.into(),
+            sync_ids: CollSyncIds {
+                global: Guid::random(),
+                coll: Guid::random(),
+            },
+            records: BTreeMap::new(),
+            last_modified: ServerTimestamp::EPOCH,
+            client_data: None,
+        }
+    }
+
+    /// The collection's timestamp; the time of the last change to any record.
+    pub fn last_modified(&self) -> ServerTimestamp {
+        self.last_modified
+    }
+
+    pub fn sync_ids(&self) -> &CollSyncIds {
+        &self.sync_ids
+    }
+
+    /// Changes the sync IDs, as if another client had reset the collection or the whole
+    /// account. The next sync will reset the engine.
+    pub fn set_sync_ids(&mut self, sync_ids: CollSyncIds) {
+        self.sync_ids = sync_ids;
+    }
+
+    /// When set, each sync calls `prepare_for_sync()` with this data, as the sync manager does.
+    pub fn set_client_data(&mut self, client_data: ClientData) {
+        self.client_data = Some(client_data);
+    }
+
+    /// Puts a record with the given content, which must have an `id` field, as if another
+    /// client had uploaded it.
+    pub fn put_record<T: Serialize>(&mut self, content: T) -> Guid {
+        let bso = OutgoingBso::from_content_with_id(content).expect("content should have an id");
+        self.put_bso(bso)
+    }
+
+    /// Puts a tombstone, as if another client had deleted the record.
+    pub fn put_tombstone(&mut self, id: Guid) {
+        self.put_bso(OutgoingBso::new_tombstone(id.into()));
+    }
+
+    /// Puts a record, as if another client had uploaded it.
+    pub fn put_bso(&mut self, bso: OutgoingBso) -> Guid {
+        let modified = self.advance();
+        self.store(bso, modified).id
+    }
+
+    pub fn get_record(&self, id: &Guid) -> Option<&ServerRecord> {
+        self.records.get(id)
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &ServerRecord> {
+        self.records.values()
+    }
+
+    /// Removes every record, as if another client had wiped the collection.
+    pub fn wipe(&mut self) {
+        self.records.clear();
+        self.advance();
+    }
+
+    /// Syncs the engine with the records on the server.
+    pub fn sync(&mut self, engine: &dyn SyncEngine) -> Result<SyncOutcome> {
+        assert_eq!(
+            engine.collection_name(),
+            self.collection,
+            "engine syncs a different collection"
+        );
+        let mut telem = telemetry::Engine::new(self.collection.clone());
+
+        let assoc = EngineSyncAssociation::Connected(self.sync_ids.clone());
+        let reset = engine.get_sync_assoc()? != assoc;
+        if reset {
+            engine.reset(&assoc)?;
+        }
+        if let Some(client_data) = &self.client_data {
+            engine.prepare_for_sync(&|| client_data.clone())?;
+        }
+
+        let mut incoming = Vec::new();
+        if let Some(request) = engine.get_collection_request(self.last_modified)? {
+            assert_eq!(
+                request.collection, self.collection,
+                "engine requested a different collection"
+            );
+            let mut records: Vec<&ServerRecord> = self
+                .records
+                .values()
+                .filter(|r| request.newer.map_or(true, |ts| r.modified > ts))
+                .filter(|r| request.older.map_or(true, |ts| r.modified < ts))
+                .filter(|r| request.ids.as_ref().map_or(true, |ids| ids.contains(&r.id)))
+                .collect();
+            if let Some(limit) = request.limit {
+                match limit.order {
+                    RequestOrder::Oldest => records.sort_by_key(|r| r.modified.0),
+                    RequestOrder::Newest => {
+                        records.sort_by_key(|r| std::cmp::Reverse(r.modified.0))
+                    }
+                    RequestOrder::Index => {
+                        records.sort_by_key(|r| std::cmp::Reverse(r.sortindex.unwrap_or(0)))
+                    }
+                }
+                records.truncate(limit.num);
+            }
+            incoming = records.iter().map(|r| r.id.clone()).collect();
+            engine.stage_incoming(
+                records.iter().map(|r| r.to_incoming()).collect(),
+                &mut telem,
+            )?;
+        }
+
+        let outgoing = engine.apply(self.last_modified, &mut telem)?;
+        let mut uploaded = Vec::with_capacity(outgoing.len());
+        if !outgoing.is_empty() {
+            let modified = self.advance();
+            for bso in outgoing {
+                uploaded.push(self.store(bso, modified));
+            }
+            let mut telem_outgoing = telemetry::EngineOutgoing::new();
+            telem_outgoing.sent(uploaded.len());
+            telem.outgoing(telem_outgoing);
+        }
+        engine.set_uploaded(
+            self.last_modified,
+            uploaded.iter().map(|r| r.id.clone()).collect(),
+        )?;
+        engine.sync_finished()?;
+
+        Ok(SyncOutcome {
+            incoming,
+            uploaded,
+            reset,
+            telemetry: telem,
+        })
+    }
+
+    // Every change gets a new timestamp, so engines can't miss a change by asking for
+    // records newer than the last one they saw.
+    fn advance(&mut self) -> ServerTimestamp {
+        self.last_modified = ServerTimestamp(self.last_modified.0 + 1000);
+        self.last_modified
+    }
+
+    fn store(&mut self, bso: OutgoingBso, modified: ServerTimestamp) -> ServerRecord {
+        let record = ServerRecord {
+            id: bso.envelope.id,
+            modified,
+            sortindex: bso.envelope.sortindex,
+            ttl: bso.envelope.ttl,
+            payload: bso.payload,
+        };
+        self.records.insert(record.id.clone(), record.clone());
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::CollectionRequest;
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::json;
+    use std::cell::RefCell;
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    struct Item {
+        id: Guid,
+        value: u32,
+    }
+
+    // An engine which keeps its items in memory, and uploads any item it has changed.
+    #[derive(Default)]
+    struct MemoryEngine {
+        items: RefCell<BTreeMap<Guid, Item>>,
+        changed: RefCell<Vec<Guid>>,
+        staged: RefCell<Vec<IncomingBso>>,
+        last_sync: RefCell<ServerTimestamp>,
+        assoc: RefCell<Option<CollSyncIds>>,
+    }
+
+    impl MemoryEngine {
+        fn set(&self, id: &str, value: u32) {
+            let id = Guid::new(id);
+            self.items.borrow_mut().insert(
+                id.clone(),
+                Item {
+                    id: id.clone(),
+                    value,
+                },
+            );
+            self.changed.borrow_mut().push(id);
+        }
+    }
+
+    impl SyncEngine for MemoryEngine {
+        fn collection_name(&self) -> CollectionName {
+            "items".into()
+        }
+
+        fn stage_incoming(
+            &self,
+            inbound: Vec<IncomingBso>,
+            _telem: &mut telemetry::Engine,
+        ) -> Result<()> {
+            self.staged.borrow_mut().extend(inbound);
+            Ok(())
+        }
+
+        fn apply(
+            &self,
+            timestamp: ServerTimestamp,
+            _telem: &mut telemetry::Engine,
+        ) -> Result<Vec<OutgoingBso>> {
+            for incoming in self.staged.borrow_mut().drain(..) {
+                let id = incoming.envelope.id.clone();
+                match incoming.into_content::<Item>().content() {
+                    Some(item) => self.items.borrow_mut().insert(id, item),
+                    None => self.items.borrow_mut().remove(&id),
+                };
+            }
+            *self.last_sync.borrow_mut() = timestamp;
+            let items = self.items.borrow();
+            self.changed
+                .borrow_mut()
+                .drain(..)
+                .map(|id| Ok(OutgoingBso::from_content_with_id(&items[&id])?))
+                .collect()
+        }
+
+        fn set_uploaded(&self, new_timestamp: ServerTimestamp, _ids: Vec<Guid>) -> Result<()> {
+            *self.last_sync.borrow_mut() = new_timestamp;
+            Ok(())
+        }
+
+        fn get_collection_request(
+            &self,
+            server_timestamp: ServerTimestamp,
+        ) -> Result<Option<CollectionRequest>> {
+            let since = *self.last_sync.borrow();
+            Ok((since != server_timestamp).then(|| {
+                CollectionRequest::new("items".into())
+                    .full()
+                    .newer_than(since)
+            }))
+        }
+
+        fn get_sync_assoc(&self) -> Result<EngineSyncAssociation> {
+            Ok(match &*self.assoc.borrow() {
+                Some(ids) => EngineSyncAssociation::Connected(ids.clone()),
+                None => EngineSyncAssociation::Disconnected,
+            })
+        }
+
+        fn reset(&self, assoc: &EngineSyncAssociation) -> Result<()> {
+            *self.last_sync.borrow_mut() = ServerTimestamp::EPOCH;
+            *self.assoc.borrow_mut() = match assoc {
+                EngineSyncAssociation::Connected(ids) => Some(ids.clone()),
+                EngineSyncAssociation::Disconnected => None,
+            };
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_fake_server_sync() -> Result<()> {
+        let mut server = FakeServer::new("items");
+        let engine = MemoryEngine::default();
+        server.put_record(json!({"id": "aaaaaaaaaaaa", "value": 1}));
+        engine.set("bbbbbbbbbbbb", 2);
+
+        // The first sync resets the engine, downloads everything and uploads local changes.
+        let outcome = server.sync(&engine)?;
+        assert!(outcome.reset);
+        assert_eq!(outcome.incoming, vec![Guid::new("aaaaaaaaaaaa")]);
+        assert_eq!(outcome.uploaded.len(), 1);
+        assert_eq!(
+            outcome.uploaded[0].content::<Item>(),
+            Some(Item {
+                id: Guid::new("bbbbbbbbbbbb"),
+                value: 2
+            })
+        );
+        assert_eq!(engine.items.borrow().len(), 2);
+        assert_eq!(server.records().count(), 2);
+
+        // Nothing changed, so there's nothing to download or upload.
+        let outcome = server.sync(&engine)?;
+        assert!(!outcome.reset);
+        assert!(outcome.incoming.is_empty());
+        assert!(outcome.uploaded.is_empty());
+
+        // Only records changed since the last sync are downloaded.
+        server.put_tombstone(Guid::new("aaaaaaaaaaaa"));
+        let outcome = server.sync(&engine)?;
+        assert_eq!(outcome.incoming, vec![Guid::new("aaaaaaaaaaaa")]);
+        assert!(server
+            .get_record(&Guid::new("aaaaaaaaaaaa"))
+            .unwrap()
+            .is_tombstone());
+        assert_eq!(
+            engine.items.borrow().keys().collect::<Vec<_>>(),
+            vec![&Guid::new("bbbbbbbbbbbb")]
+        );
+
+        // New sync IDs reset the engine, which then downloads everything again.
+        server.set_sync_ids(CollSyncIds {
+            global: Guid::random(),
+            coll: Guid::random(),
+        });
+        let outcome = server.sync(&engine)?;
+        assert!(outcome.reset);
+        assert_eq!(outcome.incoming.len(), 2);
+        Ok(())
+    }
+}
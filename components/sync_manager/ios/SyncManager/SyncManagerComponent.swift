@@ -23,6 +23,10 @@ open class SyncManagerComponent {
         return api.getAvailableEngines()
     }
 
+    public func getDeviceTypeEngineDefaults(kind: DeviceType) -> [String: Bool] {
+        return api.getDeviceTypeEngineDefaults(kind: kind)
+    }
+
     public static func reportSyncTelemetry(syncResult: SyncResult) throws {
         if let json = syncResult.telemetryJson {
             let telemetry = try RustSyncTelemetryPing.fromJSONString(jsonObjectText: json)
@@ -0,0 +1,301 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Per-device-type engine defaults.
+//!
+//! meta/global's declined list is global to the account, so it's the wrong
+//! tool for "don't sync addresses on phones unless I ask for it". Instead, some
+//! engines are off by default for some device types, and each device tracks
+//! which of those defaults the user has overridden. That local state lives in
+//! the persisted state we hand back to the app after every sync, alongside
+//! the state sync15 itself needs persisted.
+
+use crate::quarantine::QuarantineState;
+use serde_derive::*;
+use serde_json::Value as JsonValue;
+use std::collections::{HashMap, HashSet};
+use sync15::engine::SyncEngineId;
+use sync15::DeviceType;
+
+/// Returns whether `engine` should be synced on a device of the given type
+/// when the user hasn't said otherwise.
+pub(crate) fn enabled_by_default(kind: DeviceType, engine: &SyncEngineId) -> bool {
+    !matches!(
+        (kind, engine),
+        (
+            DeviceType::Mobile | DeviceType::Tablet,
+            SyncEngineId::Addresses
+        )
+    )
+}
+
+/// Returns the default enabled state of every engine for a device type.
+pub(crate) fn device_type_engine_defaults(kind: DeviceType) -> HashMap<String, bool> {
+    SyncEngineId::iter()
+        .map(|engine| (engine.name().to_string(), enabled_by_default(kind, &engine)))
+        .collect()
+}
+
+/// The key of our own state in the state we ask the app to persist for us.
+///
+/// The persisted state is sync15's, with ours stored in this extra field.
+/// sync15 ignores fields it doesn't know about, so an older version which
+/// doesn't know about ours can still read the state if the app is downgraded.
+const MANAGER_STATE_KEY: &str = "sync_manager";
+
+/// What sync15 persists before its first sync, for when we need to persist
+/// our state before it has any.
+fn default_sync15_state() -> serde_json::Map<String, JsonValue> {
+    let JsonValue::Object(state) = serde_json::json!({"schema_version": "V2", "declined": null})
+    else {
+        unreachable!()
+    };
+    state
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PersistedManagerState {
+    #[serde(default)]
+    device_engine_choices: HashMap<String, bool>,
+    #[serde(default)]
+    last_declined: Option<Vec<String>>,
+    #[serde(default)]
+    engine_health: QuarantineState,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct ManagerState {
+    /// The opaque state sync15 wants round-tripped.
+    pub sync15: Option<String>,
+    /// The engines the user has explicitly enabled or disabled on this device,
+    /// overriding the defaults for its device type.
+    pub device_engine_choices: HashMap<String, bool>,
+    /// meta/global's declined list as of the last sync, so we can tell when
+    /// another device changes it.
+    pub last_declined: Option<Vec<String>>,
+    /// Consecutive failures, and any quarantine, for each engine.
+    pub quarantine: QuarantineState,
+}
+
+impl ManagerState {
+    pub fn from_persisted(persisted: Option<String>) -> Self {
+        let Some(persisted) = persisted else {
+            return Self::default();
+        };
+        let mut sync15 = match serde_json::from_str(&persisted) {
+            Ok(JsonValue::Object(sync15)) => sync15,
+            // Something we can't make sense of - it's sync15's problem.
+            _ => {
+                return Self {
+                    sync15: Some(persisted),
+                    ..Default::default()
+                }
+            }
+        };
+        let ours: PersistedManagerState = sync15
+            .remove(MANAGER_STATE_KEY)
+            .and_then(|ours| serde_json::from_value(ours).ok())
+            .unwrap_or_default();
+        Self {
+            sync15: Some(JsonValue::Object(sync15).to_string()),
+            device_engine_choices: ours.device_engine_choices,
+            last_declined: ours.last_declined,
+            quarantine: ours.engine_health,
+        }
+    }
+
+    pub fn to_persisted(&self) -> String {
+        let mut state = match self.sync15.as_deref().map(serde_json::from_str) {
+            Some(Ok(JsonValue::Object(sync15))) => sync15,
+            // sync15 hasn't given us a state yet, or gave us one we can't add
+            // ours to, in which case it will start afresh next time anyway.
+            _ => default_sync15_state(),
+        };
+        let ours = PersistedManagerState {
+            device_engine_choices: self.device_engine_choices.clone(),
+            last_declined: self.last_declined.clone(),
+            engine_health: self.quarantine.clone(),
+        };
+        state.insert(
+            MANAGER_STATE_KEY.to_string(),
+            serde_json::to_value(ours).unwrap(),
+        );
+        JsonValue::Object(state).to_string()
+    }
+
+    /// Should this device sync `engine`? This only considers the local
+    /// choices - engines declined in meta/global are skipped by sync15.
+    pub fn is_engine_enabled(&self, kind: DeviceType, engine: &SyncEngineId) -> bool {
+        self.device_engine_choices
+            .get(engine.name())
+            .copied()
+            .unwrap_or_else(|| enabled_by_default(kind, engine))
+    }
+
+    /// Records the user's choices for this device, returning the changes we
+    /// need to make to meta/global as a result: opting in to an engine which
+    /// is globally declined un-declines it, otherwise opting in would do
+    /// nothing.
+    pub fn apply_device_engine_changes(
+        &mut self,
+        changes: HashMap<String, bool>,
+    ) -> HashMap<String, bool> {
+        let declined: HashSet<&String> = self.last_declined.iter().flatten().collect();
+        let global_changes = changes
+            .iter()
+            .filter(|(name, enabled)| **enabled && declined.contains(name))
+            .map(|(name, _)| (name.clone(), true))
+            .collect();
+        self.device_engine_choices.extend(changes);
+        global_changes
+    }
+
+    /// Records the declined list after a sync, returning the engines whose
+    /// state was changed by some other device since the last one (true if
+    /// enabled, false if declined). Changes we made ourselves via
+    /// `our_changes` aren't reported.
+    pub fn update_declined(
+        &mut self,
+        declined: Option<Vec<String>>,
+        our_changes: &HashMap<String, bool>,
+    ) -> HashMap<String, bool> {
+        let mut remote_changes = HashMap::new();
+        if let (Some(old), Some(new)) = (&self.last_declined, &declined) {
+            let old: HashSet<&String> = old.iter().collect();
+            let new: HashSet<&String> = new.iter().collect();
+            for name in old.difference(&new) {
+                remote_changes.insert((*name).clone(), true);
+            }
+            for name in new.difference(&old) {
+                remote_changes.insert((*name).clone(), false);
+            }
+            remote_changes.retain(|name, _| !our_changes.contains_key(name));
+        }
+        if declined.is_some() {
+            self.last_declined = declined;
+        }
+        remote_changes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        assert!(!enabled_by_default(
+            DeviceType::Mobile,
+            &SyncEngineId::Addresses
+        ));
+        assert!(enabled_by_default(
+            DeviceType::Desktop,
+            &SyncEngineId::Addresses
+        ));
+        assert!(enabled_by_default(DeviceType::Mobile, &SyncEngineId::Tabs));
+        let defaults = device_type_engine_defaults(DeviceType::Tablet);
+        assert_eq!(defaults.len(), SyncEngineId::iter().count());
+        assert_eq!(defaults.get("addresses"), Some(&false));
+        assert_eq!(defaults.get("history"), Some(&true));
+    }
+
+    #[test]
+    fn test_persisted_roundtrip() {
+        let mut state = ManagerState {
+            sync15: Some(r#"{"declined":["tabs"],"schema_version":"V2"}"#.into()),
+            device_engine_choices: HashMap::from([("addresses".to_string(), true)]),
+            last_declined: Some(vec!["tabs".into()]),
+            ..Default::default()
+        };
+        state.quarantine.record_results(
+            [],
+            [&"history".to_string()],
+            std::time::SystemTime::UNIX_EPOCH,
+        );
+        assert_eq!(
+            ManagerState::from_persisted(Some(state.to_persisted())),
+            state
+        );
+        assert_eq!(ManagerState::from_persisted(None), ManagerState::default());
+    }
+
+    #[test]
+    fn test_persisted_state_readable_by_sync15() {
+        // The state we persist is still sync15's, so older versions which
+        // only know about that can read it.
+        let state = ManagerState {
+            sync15: Some(r#"{"schema_version":"V2","declined":["addons"]}"#.into()),
+            device_engine_choices: HashMap::from([("addresses".to_string(), true)]),
+            last_declined: Some(vec!["addons".into()]),
+            ..Default::default()
+        };
+        let persisted: JsonValue = serde_json::from_str(&state.to_persisted()).unwrap();
+        assert_eq!(persisted["schema_version"], "V2");
+        assert_eq!(persisted["declined"], serde_json::json!(["addons"]));
+
+        // Even before sync15 has given us a state of its own.
+        let state = ManagerState {
+            device_engine_choices: HashMap::from([("addresses".to_string(), true)]),
+            ..Default::default()
+        };
+        let persisted: JsonValue = serde_json::from_str(&state.to_persisted()).unwrap();
+        assert_eq!(persisted["schema_version"], "V2");
+        assert_eq!(persisted["declined"], JsonValue::Null);
+    }
+
+    #[test]
+    fn test_legacy_persisted_state() {
+        // What sync15 persisted before we added our state to it.
+        let legacy = r#"{"schema_version":"V2","declined":["addons"]}"#;
+        let state = ManagerState::from_persisted(Some(legacy.into()));
+        let sync15: JsonValue = serde_json::from_str(state.sync15.as_deref().unwrap()).unwrap();
+        assert_eq!(sync15, serde_json::from_str::<JsonValue>(legacy).unwrap());
+        assert!(state.device_engine_choices.is_empty());
+        assert_eq!(state.last_declined, None);
+
+        let garbage = "not json";
+        let state = ManagerState::from_persisted(Some(garbage.into()));
+        assert_eq!(state.sync15.as_deref(), Some(garbage));
+    }
+
+    #[test]
+    fn test_device_engine_choices() {
+        let mut state = ManagerState {
+            last_declined: Some(vec!["history".into()]),
+            ..Default::default()
+        };
+        assert!(!state.is_engine_enabled(DeviceType::Mobile, &SyncEngineId::Addresses));
+        let global = state.apply_device_engine_changes(HashMap::from([
+            ("addresses".to_string(), true),
+            ("history".to_string(), true),
+            ("tabs".to_string(), false),
+        ]));
+        // Only the globally declined engine needs meta/global changing.
+        assert_eq!(global, HashMap::from([("history".to_string(), true)]));
+        assert!(state.is_engine_enabled(DeviceType::Mobile, &SyncEngineId::Addresses));
+        assert!(!state.is_engine_enabled(DeviceType::Desktop, &SyncEngineId::Tabs));
+    }
+
+    #[test]
+    fn test_remote_declined_changes() {
+        let mut state = ManagerState::default();
+        // No previous list, so no way of knowing what changed.
+        let changes = state.update_declined(Some(vec!["tabs".into()]), &HashMap::new());
+        assert!(changes.is_empty());
+        let changes = state.update_declined(
+            Some(vec!["history".into(), "bookmarks".into()]),
+            &HashMap::from([("bookmarks".to_string(), false)]),
+        );
+        assert_eq!(
+            changes,
+            HashMap::from([("tabs".to_string(), true), ("history".to_string(), false)])
+        );
+        // A sync which didn't get as far as meta/global keeps the old list.
+        assert!(state.update_declined(None, &HashMap::new()).is_empty());
+        assert_eq!(
+            state.last_declined,
+            Some(vec!["history".into(), "bookmarks".into()])
+        );
+    }
+}
@@ -5,8 +5,10 @@
 #![allow(unknown_lints)]
 #![warn(rust_2018_idioms)]
 
+mod engine_defaults;
 pub mod error;
 pub mod manager;
+mod quarantine;
 mod types;
 
 pub use sync15::DeviceType;
@@ -2,8 +2,11 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use crate::engine_defaults::{device_type_engine_defaults, ManagerState};
 use crate::error::*;
-use crate::types::{ServiceStatus, SyncEngineSelection, SyncParams, SyncReason, SyncResult};
+use crate::types::{
+    EngineWipeResult, ServiceStatus, SyncEngineSelection, SyncParams, SyncReason, SyncResult,
+};
 use crate::{reset, reset_all, wipe};
 use error_support::breadcrumb;
 use parking_lot::Mutex;
@@ -16,6 +19,7 @@ use sync15::client::{
 };
 use sync15::clients_engine::{Command, CommandProcessor, CommandStatus, Settings};
 use sync15::engine::{EngineSyncAssociation, SyncEngine, SyncEngineId};
+use sync15::DeviceType;
 
 #[derive(Default)]
 pub struct SyncManager {
@@ -63,6 +67,61 @@ impl SyncManager {
         Ok(())
     }
 
+    /// Wipe the data of every registered engine, and disconnect them from sync.
+    ///
+    /// If `remote` is true, each engine's collection is deleted from the sync
+    /// server first, using the connection from the last sync, and its local data
+    /// is kept if that fails, since otherwise it would just be downloaded again.
+    /// Other devices keep their copy of the data either way.
+    pub fn wipe_all_engines(&self, remote: bool) -> Vec<EngineWipeResult> {
+        breadcrumb!("SyncManager wipe_all_engines(remote: {})", remote);
+        // Hold the lock for the whole wipe, so a sync can't run in the middle of it.
+        let state = self.mem_cached_state.lock();
+        let client = state.as_ref().and_then(|s| s.storage_client());
+        // Engines are wiped in priority order, which wipes bookmarks before history,
+        // so that pages which were only kept because they were bookmarked go too.
+        self.iter_registered_engines()
+            .map(|(engine_id, engine)| {
+                let mut result = EngineWipeResult {
+                    engine: engine_id.to_string(),
+                    remote_wiped: None,
+                    local_wiped: false,
+                    error: None,
+                };
+                if remote {
+                    let wiped = match client {
+                        Some(client) => client
+                            .wipe_remote_engine(&engine.collection_name())
+                            .map_err(|e| e.to_string()),
+                        None => Err("Not connected to the sync server".to_string()),
+                    };
+                    result.remote_wiped = Some(wiped.is_ok());
+                    if let Err(e) = wiped {
+                        log::warn!("Not wiping {}, as the server wipe failed: {}", engine_id, e);
+                        result.error = Some(e);
+                        return result;
+                    }
+                }
+                match engine
+                    .wipe()
+                    .and_then(|_| engine.reset(&EngineSyncAssociation::Disconnected))
+                {
+                    Ok(()) => result.local_wiped = true,
+                    Err(e) => {
+                        error_support::report_error!(
+                            "sync-manager-wipe",
+                            "Failed to wipe {}: {}",
+                            engine_id,
+                            e
+                        );
+                        result.error = Some(e.to_string());
+                    }
+                }
+                result
+            })
+            .collect()
+    }
+
     /// Disconnect engines from sync, deleting/resetting the sync-related data
     pub fn disconnect(&self) {
         breadcrumb!("SyncManager disconnect()");
@@ -83,10 +142,33 @@ impl SyncManager {
     }
 
     /// Perform a sync.  See [SyncParams] and [SyncResult] for details on how this works
-    pub fn sync(&self, params: SyncParams) -> Result<SyncResult> {
+    pub fn sync(&self, mut params: SyncParams) -> Result<SyncResult> {
         breadcrumb!("SyncManager::sync started");
         let mut state = self.mem_cached_state.lock();
-        let engines = self.calc_engines_to_sync(&params.engines)?;
+        let mut manager_state = ManagerState::from_persisted(params.persisted_state.take());
+        let global_changes = manager_state
+            .apply_device_engine_changes(params.device_engine_changes.take().unwrap_or_default());
+        for (name, enabled) in global_changes {
+            params.enabled_changes.entry(name).or_insert(enabled);
+        }
+        let our_changes = params.enabled_changes.clone();
+        params.persisted_state = manager_state.sync15.clone();
+        let device_type = params.device_settings.kind;
+        let mut engines = self.calc_engines_to_sync(&params.engines, |engine_id| {
+            manager_state.is_engine_enabled(device_type, engine_id)
+        })?;
+        if !matches!(params.reason, SyncReason::User | SyncReason::EnabledChange) {
+            let now = SystemTime::now();
+            engines.retain(|engine| {
+                let quarantined = manager_state
+                    .quarantine
+                    .is_quarantined(&engine.collection_name(), now);
+                if quarantined {
+                    log::info!("Skipping {}, as it's quarantined", engine.collection_name());
+                }
+                !quarantined
+            });
+        }
         let next_sync_after = state.as_ref().and_then(|mcs| mcs.get_next_sync_after());
         let result = if !backoff_in_effect(next_sync_after, &params) {
             log::info!("No backoff in effect (or we decided to ignore it), starting sync");
@@ -105,10 +187,39 @@ impl SyncManager {
                 persisted_state: params.persisted_state.unwrap_or_default(),
                 // It would be nice to record telemetry here.
                 telemetry_json: None,
+                remote_enabled_changes: None,
+                quarantined_engines: Vec::new(),
             })
         };
         breadcrumb!("SyncManager sync ended");
-        result
+        result.map(|mut result| {
+            manager_state.sync15 = Some(result.persisted_state).filter(|s| !s.is_empty());
+            let remote_changes =
+                manager_state.update_declined(result.declined.clone(), &our_changes);
+            if !remote_changes.is_empty() {
+                log::info!("Declined engines changed remotely: {:?}", remote_changes);
+            }
+            result.remote_enabled_changes = Some(remote_changes);
+            // If we couldn't talk to the server, that's not the engines' fault.
+            let now = SystemTime::now();
+            if result.status.is_ok() {
+                manager_state.quarantine.record_results(
+                    &result.successful,
+                    result.failures.keys(),
+                    now,
+                );
+            }
+            result.quarantined_engines = manager_state.quarantine.quarantined_engines(now);
+            result.persisted_state = manager_state.to_persisted();
+            result
+        })
+    }
+
+    /// Get the engines which are enabled by default for a device type. Engines
+    /// disabled by default aren't synced unless the user opts in to them via
+    /// `SyncParams::device_engine_changes`.
+    pub fn get_device_type_engine_defaults(&self, kind: DeviceType) -> HashMap<String, bool> {
+        device_type_engine_defaults(kind)
     }
 
     fn do_sync(
@@ -191,6 +302,8 @@ impl SyncManager {
             next_sync_allowed_at: result.next_sync_after,
             persisted_state: disk_cached_state.unwrap_or_default(),
             telemetry_json: Some(telemetry_json),
+            remote_enabled_changes: None,
+            quarantined_engines: Vec::new(),
         })
     }
 
@@ -204,9 +317,12 @@ impl SyncManager {
             .collect()
     }
 
+    /// Engines which aren't enabled on this device are skipped when syncing
+    /// all engines, but an explicit selection always wins.
     fn calc_engines_to_sync(
         &self,
         selection: &SyncEngineSelection,
+        is_enabled_on_device: impl Fn(&SyncEngineId) -> bool,
     ) -> Result<Vec<Box<dyn SyncEngine>>> {
         // BTreeMap to ensure we sync the engines in priority order.
         let mut engine_map: BTreeMap<_, _> = self.iter_registered_engines().collect();
@@ -218,21 +334,29 @@ impl SyncManager {
                 .map(|engine_id| engine_id.name())
                 .collect::<Vec<_>>(),
         );
-        if let SyncEngineSelection::Some {
-            engines: engine_names,
-        } = selection
-        {
-            // Validate selection and convert to SyncEngineId
-            let mut selected_engine_ids: HashSet<SyncEngineId> = HashSet::new();
-            for name in engine_names {
-                let engine_id = Self::get_engine_id(name)?;
-                if !engine_map.contains_key(&engine_id) {
-                    return Err(SyncManagerError::UnsupportedFeature(name.to_string()));
+        match selection {
+            SyncEngineSelection::All => engine_map.retain(|engine_id, _| {
+                let enabled = is_enabled_on_device(engine_id);
+                if !enabled {
+                    log::info!("Skipping {}, as it's not enabled on this device", engine_id);
                 }
-                selected_engine_ids.insert(engine_id);
+                enabled
+            }),
+            SyncEngineSelection::Some {
+                engines: engine_names,
+            } => {
+                // Validate selection and convert to SyncEngineId
+                let mut selected_engine_ids: HashSet<SyncEngineId> = HashSet::new();
+                for name in engine_names {
+                    let engine_id = Self::get_engine_id(name)?;
+                    if !engine_map.contains_key(&engine_id) {
+                        return Err(SyncManagerError::UnsupportedFeature(name.to_string()));
+                    }
+                    selected_engine_ids.insert(engine_id);
+                }
+                // Filter engines based on the selection
+                engine_map.retain(|engine_id, _| selected_engine_ids.contains(engine_id))
             }
-            // Filter engines based on the selection
-            engine_map.retain(|engine_id, _| selected_engine_ids.contains(engine_id))
         }
         Ok(engine_map.into_values().collect())
     }
@@ -316,6 +440,49 @@ impl CommandProcessor for SyncClient {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_wipe_all_engines() {
+        let store = Arc::new(tabs::TabsStore::new_with_mem_path("test_wipe_all_engines"));
+        Arc::clone(&store).register_with_sync_manager();
+        store.set_local_tabs(vec![tabs::RemoteTabRecord {
+            title: "Example".to_string(),
+            url_history: vec!["https://example.com".to_string()],
+            icon: None,
+            last_used: 0,
+            inactive: false,
+        }]);
+        let manager = SyncManager::new();
+        let tabs_result = |results: Vec<EngineWipeResult>| {
+            results
+                .into_iter()
+                .find(|result| result.engine == "tabs")
+                .expect("tabs should be registered")
+        };
+
+        // We haven't synced, so can't wipe the server, and the local data is kept.
+        let result = tabs_result(manager.wipe_all_engines(true));
+        assert_eq!(result.remote_wiped, Some(false));
+        assert!(!result.local_wiped);
+        assert!(result.error.is_some());
+        let storage = store.storage.lock().unwrap();
+        assert!(storage.prepare_local_tabs_for_upload().is_some());
+        drop(storage);
+
+        let result = tabs_result(manager.wipe_all_engines(false));
+        assert_eq!(
+            result,
+            EngineWipeResult {
+                engine: "tabs".to_string(),
+                remote_wiped: None,
+                local_wiped: true,
+                error: None,
+            }
+        );
+        let storage = store.storage.lock().unwrap();
+        assert!(storage.prepare_local_tabs_for_upload().is_none());
+    }
 
     #[test]
     fn test_engine_id_sanity() {
@@ -0,0 +1,166 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Engine failure quarantine.
+//!
+//! An engine which fails every time it syncs (say, because of a bad record
+//! it can't apply) still costs every sync the time it takes to fail. Once an
+//! engine has failed `QUARANTINE_THRESHOLD` syncs in a row, we stop syncing
+//! it for a while, backing off further each time it fails again, and report
+//! it in the `SyncResult` so the app can tell the user. The first success
+//! lifts the quarantine.
+//!
+//! Like the per-device engine choices, this lives in the state the app
+//! persists for us, so it survives restarts.
+
+use crate::types::EngineQuarantine;
+use serde_derive::*;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// How many consecutive failures it takes for an engine to be quarantined.
+const QUARANTINE_THRESHOLD: u32 = 3;
+/// How long the first quarantine lasts. Each subsequent failure doubles it.
+const INITIAL_QUARANTINE: Duration = Duration::from_secs(60 * 60);
+const MAX_QUARANTINE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct EngineHealth {
+    pub consecutive_failures: u32,
+    pub quarantined_until: Option<SystemTime>,
+}
+
+/// The health of each engine, keyed by the engine names reported in
+/// `SyncResult`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct QuarantineState {
+    engines: HashMap<String, EngineHealth>,
+}
+
+impl QuarantineState {
+    pub fn is_quarantined(&self, engine: &str, now: SystemTime) -> bool {
+        self.engines
+            .get(engine)
+            .and_then(|health| health.quarantined_until)
+            .map_or(false, |until| until > now)
+    }
+
+    /// Records the outcome of a sync. Only engines which actually synced
+    /// should be passed - skipped engines keep their failure count, so that
+    /// failing again after the quarantine ends backs off for longer.
+    pub fn record_results<'a>(
+        &mut self,
+        successful: impl IntoIterator<Item = &'a String>,
+        failed: impl IntoIterator<Item = &'a String>,
+        now: SystemTime,
+    ) {
+        for engine in successful {
+            if self.engines.remove(engine).is_some() {
+                log::info!("{} synced successfully, clearing its failures", engine);
+            }
+        }
+        for engine in failed {
+            let health = self.engines.entry(engine.clone()).or_default();
+            health.consecutive_failures += 1;
+            if health.consecutive_failures >= QUARANTINE_THRESHOLD {
+                let duration = quarantine_duration(health.consecutive_failures);
+                log::warn!(
+                    "{} has failed {} syncs in a row, quarantining it for {:?}",
+                    engine,
+                    health.consecutive_failures,
+                    duration
+                );
+                health.quarantined_until = Some(now + duration);
+            }
+        }
+    }
+
+    /// The engines which are currently quarantined, sorted by name.
+    pub fn quarantined_engines(&self, now: SystemTime) -> Vec<EngineQuarantine> {
+        let mut quarantined: Vec<_> = self
+            .engines
+            .iter()
+            .filter_map(|(engine, health)| {
+                let until = health.quarantined_until.filter(|until| *until > now)?;
+                Some(EngineQuarantine {
+                    engine: engine.clone(),
+                    consecutive_failures: health.consecutive_failures,
+                    quarantined_until: until,
+                })
+            })
+            .collect();
+        quarantined.sort_by(|a, b| a.engine.cmp(&b.engine));
+        quarantined
+    }
+}
+
+fn quarantine_duration(consecutive_failures: u32) -> Duration {
+    let doublings = consecutive_failures.saturating_sub(QUARANTINE_THRESHOLD);
+    2u32.checked_pow(doublings)
+        .and_then(|factor| INITIAL_QUARANTINE.checked_mul(factor))
+        .map_or(MAX_QUARANTINE, |duration| duration.min(MAX_QUARANTINE))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quarantine() {
+        let history = "history".to_string();
+        let tabs = "tabs".to_string();
+        let mut state = QuarantineState::default();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+        for _ in 1..QUARANTINE_THRESHOLD {
+            state.record_results([&tabs], [&history], now);
+            assert!(!state.is_quarantined("history", now));
+        }
+        state.record_results([&tabs], [&history], now);
+        assert!(state.is_quarantined("history", now));
+        assert!(!state.is_quarantined("tabs", now));
+        assert_eq!(
+            state.quarantined_engines(now),
+            vec![EngineQuarantine {
+                engine: history.clone(),
+                consecutive_failures: QUARANTINE_THRESHOLD,
+                quarantined_until: now + INITIAL_QUARANTINE,
+            }]
+        );
+
+        // Once the quarantine has expired, it's tried again; failing again
+        // backs off for longer.
+        let later = now + INITIAL_QUARANTINE;
+        assert!(!state.is_quarantined("history", later));
+        assert!(state.quarantined_engines(later).is_empty());
+        state.record_results([], [&history], later);
+        assert_eq!(
+            state.quarantined_engines(later)[0].quarantined_until,
+            later + INITIAL_QUARANTINE * 2
+        );
+
+        // A success clears it.
+        state.record_results([&history], [], later);
+        assert!(!state.is_quarantined("history", later));
+        assert_eq!(state, QuarantineState::default());
+    }
+
+    #[test]
+    fn test_quarantine_duration() {
+        assert_eq!(
+            quarantine_duration(QUARANTINE_THRESHOLD),
+            INITIAL_QUARANTINE
+        );
+        assert_eq!(
+            quarantine_duration(QUARANTINE_THRESHOLD + 2),
+            INITIAL_QUARANTINE * 4
+        );
+        assert_eq!(
+            quarantine_duration(QUARANTINE_THRESHOLD + 10),
+            MAX_QUARANTINE
+        );
+        assert_eq!(quarantine_duration(u32::MAX), MAX_QUARANTINE);
+    }
+}
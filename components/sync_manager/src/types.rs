@@ -26,6 +26,11 @@ pub struct SyncParams {
     // Information about the current device, such as its name, formfactor and
     // FxA device ID.
     pub device_settings: DeviceSettings,
+    // Engines the user has enabled (true) or disabled (false) on this device
+    // since the last sync, overriding the defaults for its device type. Unlike
+    // `enabled_changes`, this only affects this device, except that enabling
+    // an engine which is declined for the account un-declines it.
+    pub device_engine_changes: Option<HashMap<String, bool>>,
 }
 
 #[derive(Debug)]
@@ -81,6 +86,22 @@ pub struct SyncResult {
     pub next_sync_allowed_at: Option<SystemTime>,
     // JSON string encoding a `SyncTelemetryPing` object
     pub telemetry_json: Option<String>,
+    // Engines whose declined state was changed by another device since the
+    // last sync: true if it was enabled, false if it was declined.
+    pub remote_enabled_changes: Option<HashMap<String, bool>>,
+    // Engines which have failed several syncs in a row, and so are being
+    // skipped until their quarantine ends.
+    pub quarantined_engines: Vec<EngineQuarantine>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineQuarantine {
+    pub engine: String,
+    // How many syncs in a row the engine has failed.
+    pub consecutive_failures: u32,
+    // The engine won't be synced before this time, unless the user asks for
+    // a sync.
+    pub quarantined_until: SystemTime,
 }
 
 #[derive(Debug)]
@@ -98,3 +119,15 @@ impl ServiceStatus {
         matches!(self, ServiceStatus::Ok)
     }
 }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineWipeResult {
+    pub engine: String,
+    // Whether the engine's collection was deleted from the sync server, or
+    // None if a remote wipe wasn't requested.
+    pub remote_wiped: Option<bool>,
+    // Whether the engine's local data was wiped.
+    pub local_wiped: bool,
+    // Why the engine wasn't wiped, if it wasn't.
+    pub error: Option<String>,
+}
@@ -44,7 +44,7 @@ impl UniffiCustomTypeConverter for TabsGuid {
 }
 
 pub use crate::storage::{ClientRemoteTabs, RemoteTabRecord, TabsDeviceType};
-pub use crate::store::{RemoteCommandStore, TabsStore};
+pub use crate::store::{RecentRemoteTab, RemoteCommandStore, TabsStore};
 pub use error::{ApiResult, Error, Result, TabsApiError};
 use sync15::DeviceType;
 
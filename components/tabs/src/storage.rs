@@ -35,6 +35,7 @@ pub(crate) const TABS_CLIENT_TTL: u32 = 15_552_000; // 180 days, same as CLIENTS
 const FAR_FUTURE: i64 = 4_102_405_200_000; // 2100/01/01
 const MAX_PAYLOAD_SIZE: usize = 512 * 1024; // Twice as big as desktop, still smaller than server max (2MB)
 const MAX_TITLE_CHAR_LENGTH: usize = 512; // We put an upper limit on title sizes for tabs to reduce memory
+const MS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
 
 #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RemoteTab {
@@ -54,6 +55,10 @@ pub struct ClientRemoteTabs {
     pub client_name: String,
     pub device_type: DeviceType,
     pub last_modified: i64,
+    // The number of whole days between this client last uploading its tabs and our last sync.
+    // Both times come from the server's clock, so this doesn't depend on the clock of either
+    // device, and it's 0 if we haven't synced.
+    pub days_inactive: u32,
     pub remote_tabs: Vec<RemoteTab>,
 }
 
@@ -222,6 +227,17 @@ impl TabsStorage {
                 Ok(None) => HashMap::default(),
                 Ok(Some(json)) => serde_json::from_str(&json).unwrap(),
             };
+        let last_sync = match self.get_meta::<i64>(schema::LAST_SYNC_META_KEY) {
+            Err(e) => {
+                error_support::report_error!(
+                    "tabs-read-remote",
+                    "Failed to get last sync time: {}",
+                    e
+                );
+                return None;
+            }
+            Ok(last_sync) => last_sync,
+        };
         for (record, last_modified) in records {
             let id = record.id.clone();
             let crt = if let Some(remote_client) = remote_clients.get(&id) {
@@ -247,7 +263,12 @@ impl TabsStorage {
                 );
                 ClientRemoteTabs::from_record(id, last_modified, record)
             };
-            crts.push(crt);
+            crts.push(ClientRemoteTabs {
+                days_inactive: last_sync.map_or(0, |last_sync| {
+                    ((last_sync - crt.last_modified).max(0) / MS_PER_DAY) as u32
+                }),
+                ..crt
+            });
         }
         // Filter out any tabs the user requested to be closed on other devices but those devices
         // have not yet actually closed the tab, so we hide them from the user until such time
@@ -731,7 +752,7 @@ mod tests {
     use std::time::Duration;
 
     use super::*;
-    use crate::{sync::record::TabsRecordTab, PendingCommand};
+    use crate::{sync::record::TabsRecordTab, PendingCommand, TabsStore};
 
     impl RemoteCommand {
         fn close_tab(url: &str) -> Self {
@@ -1023,6 +1044,70 @@ mod tests {
         assert_eq!(remote_tabs[0].client_id, "device-1");
     }
 
+    #[test]
+    fn test_days_inactive() {
+        env_logger::try_init().ok();
+        let store = TabsStore::new_with_mem_path("test_days_inactive");
+        let last_synced = 1643764207000_i64;
+        let records = [
+            ("device-1", last_synced),
+            ("device-2", last_synced - 3 * MS_PER_DAY - 1000),
+            ("device-3", last_synced - 40 * MS_PER_DAY),
+        ];
+        {
+            let mut storage = store.storage.lock().unwrap();
+            let db = storage.open_or_create().unwrap();
+            for (guid, last_modified) in records {
+                let record = TabsRecord {
+                    id: guid.to_string(),
+                    client_name: guid.to_string(),
+                    tabs: vec![],
+                };
+                db.execute(
+                    "INSERT INTO tabs (guid, record, last_modified) VALUES (:guid, :record, :last_modified);",
+                    rusqlite::named_params! {
+                        ":guid": guid,
+                        ":record": serde_json::to_string(&record).unwrap(),
+                        ":last_modified": last_modified,
+                    },
+                ).unwrap();
+            }
+        }
+        let days_inactive = |crts: Vec<ClientRemoteTabs>| {
+            let mut days = crts
+                .into_iter()
+                .map(|crt| (crt.client_id, crt.days_inactive))
+                .collect::<Vec<_>>();
+            days.sort();
+            days
+        };
+        // We don't know how old the records are until we've synced.
+        assert!(store.get_all().iter().all(|crt| crt.days_inactive == 0));
+
+        store
+            .storage
+            .lock()
+            .unwrap()
+            .put_meta(schema::LAST_SYNC_META_KEY, &last_synced)
+            .unwrap();
+        assert_eq!(
+            days_inactive(store.get_all()),
+            vec![
+                ("device-1".to_string(), 0),
+                ("device-2".to_string(), 3),
+                ("device-3".to_string(), 40),
+            ]
+        );
+        assert_eq!(
+            days_inactive(store.get_all_active(30)),
+            vec![("device-1".to_string(), 0), ("device-2".to_string(), 3)]
+        );
+        assert_eq!(
+            days_inactive(store.get_all_active(0)),
+            vec![("device-1".to_string(), 0)]
+        );
+    }
+
     fn pending_url_command(device_id: &str, url: &str, ts: Timestamp) -> PendingCommand {
         PendingCommand {
             device_id: device_id.to_string(),
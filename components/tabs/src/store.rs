@@ -4,13 +4,24 @@
 
 use crate::storage::{ClientRemoteTabs, RemoteTab, TabsStorage};
 use crate::{ApiResult, PendingCommand, RemoteCommand};
+use std::collections::HashSet;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
+use sync15::DeviceType;
 
 pub struct TabsStore {
     pub storage: Mutex<TabsStorage>,
 }
 
+/// A tab open on another device, as returned by `TabsStore::get_recent_remote_tabs()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecentRemoteTab {
+    pub client_id: String,
+    pub client_name: String,
+    pub device_type: DeviceType,
+    pub tab: RemoteTab,
+}
+
 impl TabsStore {
     pub fn new(db_path: impl AsRef<Path>) -> Self {
         Self {
@@ -36,10 +47,31 @@ impl TabsStore {
         }
     }
 
+    // like get_all, but skips clients which haven't uploaded their tabs in more than
+    // `max_days_inactive` days, so long-forgotten devices don't clutter the UI.
+    pub fn get_all_active(&self, max_days_inactive: u32) -> Vec<ClientRemoteTabs> {
+        self.get_all()
+            .into_iter()
+            .filter(|crt| crt.days_inactive <= max_days_inactive)
+            .collect()
+    }
+
     pub fn remote_tabs(&self) -> Option<Vec<ClientRemoteTabs>> {
         self.storage.lock().unwrap().get_remote_tabs()
     }
 
+    // The most recently used tabs across all remote devices, for "tab pickup" style UIs.
+    // Tabs whose URL is in `local_urls` (ie, already open on this device) are skipped, and
+    // if `dedupe_by_url` is set, only the most recently used tab for each URL is returned.
+    pub fn get_recent_remote_tabs(
+        &self,
+        limit: u32,
+        dedupe_by_url: bool,
+        local_urls: Vec<String>,
+    ) -> Vec<RecentRemoteTab> {
+        rank_recent_tabs(self.get_all(), limit, dedupe_by_url, local_urls)
+    }
+
     pub fn new_remote_command_store(self: Arc<Self>) -> Arc<RemoteCommandStore> {
         Arc::new(RemoteCommandStore {
             store: Arc::clone(&self),
@@ -47,6 +79,45 @@ impl TabsStore {
     }
 }
 
+fn rank_recent_tabs(
+    clients: Vec<ClientRemoteTabs>,
+    limit: u32,
+    dedupe_by_url: bool,
+    local_urls: Vec<String>,
+) -> Vec<RecentRemoteTab> {
+    let mut tabs: Vec<RecentRemoteTab> = clients
+        .into_iter()
+        .flat_map(|crt| {
+            let ClientRemoteTabs {
+                client_id,
+                client_name,
+                device_type,
+                remote_tabs,
+                ..
+            } = crt;
+            remote_tabs.into_iter().map(move |tab| RecentRemoteTab {
+                client_id: client_id.clone(),
+                client_name: client_name.clone(),
+                device_type,
+                tab,
+            })
+        })
+        .collect();
+    // Most recently used first. The sort is stable, so ties keep the order we got them in.
+    tabs.sort_by(|a, b| b.tab.last_used.cmp(&a.tab.last_used));
+
+    // The first entry in the url_history is the tab's current URL.
+    let mut seen: HashSet<String> = local_urls.into_iter().collect();
+    tabs.into_iter()
+        .filter(|t| match t.tab.url_history.first() {
+            None => false,
+            Some(url) if dedupe_by_url => seen.insert(url.clone()),
+            Some(url) => !seen.contains(url),
+        })
+        .take(limit as usize)
+        .collect()
+}
+
 pub struct RemoteCommandStore {
     // it's a shame we can't hold a TabsStorage.
     store: Arc<TabsStore>,
@@ -113,3 +184,100 @@ impl RemoteCommandStore {
             .set_pending_command_sent(command)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tab(url: &str, last_used: i64) -> RemoteTab {
+        RemoteTab {
+            title: url.to_string(),
+            url_history: vec![url.to_string()],
+            last_used,
+            ..Default::default()
+        }
+    }
+
+    fn client(id: &str, remote_tabs: Vec<RemoteTab>) -> ClientRemoteTabs {
+        ClientRemoteTabs {
+            client_id: id.to_string(),
+            client_name: format!("{id} name"),
+            device_type: DeviceType::Desktop,
+            last_modified: 0,
+            days_inactive: 0,
+            remote_tabs,
+        }
+    }
+
+    fn summarize(tabs: &[RecentRemoteTab]) -> Vec<(&str, &str, i64)> {
+        tabs.iter()
+            .map(|t| {
+                (
+                    t.client_id.as_str(),
+                    t.tab.url_history[0].as_str(),
+                    t.tab.last_used,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rank_recent_tabs() {
+        let clients = || {
+            vec![
+                client(
+                    "a",
+                    vec![
+                        tab("https://example.com/", 10),
+                        tab("https://mozilla.org/", 40),
+                        RemoteTab {
+                            url_history: vec![],
+                            last_used: 100,
+                            ..Default::default()
+                        },
+                    ],
+                ),
+                client(
+                    "b",
+                    vec![
+                        tab("https://example.com/", 30),
+                        tab("https://local.example/", 50),
+                        tab("https://firefox.com/", 20),
+                    ],
+                ),
+            ]
+        };
+        let local = vec!["https://local.example/".to_string()];
+
+        let tabs = rank_recent_tabs(clients(), 10, true, local.clone());
+        assert_eq!(
+            summarize(&tabs),
+            vec![
+                ("a", "https://mozilla.org/", 40),
+                ("b", "https://example.com/", 30),
+                ("b", "https://firefox.com/", 20),
+            ]
+        );
+        assert_eq!(tabs[0].client_name, "a name");
+
+        let tabs = rank_recent_tabs(clients(), 10, false, local.clone());
+        assert_eq!(
+            summarize(&tabs),
+            vec![
+                ("a", "https://mozilla.org/", 40),
+                ("b", "https://example.com/", 30),
+                ("b", "https://firefox.com/", 20),
+                ("a", "https://example.com/", 10),
+            ]
+        );
+
+        let tabs = rank_recent_tabs(clients(), 2, true, vec![]);
+        assert_eq!(
+            summarize(&tabs),
+            vec![
+                ("b", "https://local.example/", 50),
+                ("a", "https://mozilla.org/", 40),
+            ]
+        );
+    }
+}
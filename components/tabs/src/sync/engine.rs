@@ -51,6 +51,7 @@ impl ClientRemoteTabs {
             client_name: remote_client.device_name.clone(),
             device_type: remote_client.device_type,
             last_modified: last_modified.as_millis(),
+            days_inactive: 0,
             remote_tabs: record.tabs.iter().map(RemoteTab::from_record_tab).collect(),
         }
     }
@@ -69,6 +70,7 @@ impl ClientRemoteTabs {
             client_name: record.client_name,
             device_type: DeviceType::Unknown,
             last_modified: last_modified.as_millis(),
+            days_inactive: 0,
             remote_tabs: record.tabs.iter().map(RemoteTab::from_record_tab).collect(),
         }
     }
@@ -229,6 +231,7 @@ impl SyncEngine for TabsEngine {
                 client_name,
                 device_type,
                 last_modified: 0, // ignored for outgoing records.
+                days_inactive: 0,
                 remote_tabs: local_tabs.to_vec(),
             };
             log::trace!("outgoing {:?}", local_record);
@@ -327,6 +330,7 @@ pub mod test {
     use super::*;
     use serde_json::json;
     use sync15::bso::IncomingBso;
+    use sync15::testing::FakeServer;
 
     #[test]
     fn test_incoming_tabs() {
@@ -523,4 +527,61 @@ pub mod test {
             "didn't set a zero timestamp"
         )
     }
+
+    #[test]
+    fn test_sync_with_fake_server() {
+        env_logger::try_init().ok();
+
+        let store = Arc::new(TabsStore::new_with_mem_path("test-fake-server"));
+        let engine = TabsEngine::new(Arc::clone(&store));
+        let mut server = FakeServer::new("tabs");
+        server.set_client_data(ClientData {
+            local_client_id: "my-device".to_string(),
+            recent_clients: HashMap::from([(
+                "my-device".to_string(),
+                RemoteClient {
+                    fxa_device_id: None,
+                    device_name: "my device".to_string(),
+                    device_type: DeviceType::Mobile,
+                },
+            )]),
+        });
+        server.put_record(json!({
+            "id": "other-device",
+            "clientName": "other device",
+            "tabs": [{
+                "title": "the title",
+                "urlHistory": ["https://mozilla.org/"],
+                "lastUsed": 1643764207,
+            }],
+        }));
+        store.set_local_tabs(vec![RemoteTab {
+            title: "my tab".to_string(),
+            url_history: vec!["https://example.com/".to_string()],
+            icon: None,
+            last_used: 1643764208000,
+            inactive: false,
+        }]);
+
+        let outcome = server.sync(&engine).expect("should sync");
+        assert_eq!(outcome.incoming, vec![Guid::new("other-device")]);
+        assert_eq!(outcome.uploaded.len(), 1);
+        let uploaded = outcome.uploaded[0]
+            .content::<TabsRecord>()
+            .expect("should be a tabs record");
+        assert_eq!(uploaded.id, "my-device");
+        assert_eq!(uploaded.client_name, "my device");
+        assert_eq!(uploaded.tabs[0].title, "my tab");
+        assert_eq!(outcome.uploaded[0].ttl, Some(TABS_CLIENT_TTL));
+        assert_eq!(
+            engine.get_last_sync().unwrap(),
+            Some(server.last_modified())
+        );
+
+        // Our own record is on the server now, but we shouldn't treat it as a remote client.
+        let mut storage = store.storage.lock().unwrap();
+        let remote = storage.get_remote_tabs().expect("should work");
+        assert_eq!(remote.len(), 1);
+        assert_eq!(remote[0].client_id, "other-device");
+    }
 }
@@ -68,6 +68,20 @@ object RustHttpConfig {
         }
     }
 
+    /**
+     * Pins `host` to the public key `pin`, given in the `sha256/<base64>` format
+     * that OkHttp's `CertificatePinner` also takes. Call this once for each key to
+     * pin a host to several keys, before any requests are made. Requests to the
+     * host then carry its pins for the client to enforce.
+     *
+     * Returns false if `pin` isn't valid.
+     */
+    fun addSpkiPin(host: String, pin: String): Boolean {
+        return lock.read {
+            LibViaduct.INSTANCE.viaduct_add_spki_pin(host, pin) != 0.toByte()
+        }
+    }
+
     internal fun convertRequest(request: MsgTypes.Request): Request {
         val headers = MutableHeaders()
         for (h in request.headersMap) {
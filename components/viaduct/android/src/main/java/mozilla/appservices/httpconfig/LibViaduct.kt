@@ -32,6 +32,9 @@ internal interface LibViaduct : Library {
     // No return value, never fails.
     fun viaduct_allow_android_emulator_loopback()
 
+    // Returns 0 if the pin is invalid.
+    fun viaduct_add_spki_pin(host: String, pin: String): Byte
+
     fun viaduct_log_error(s: String)
 }
 
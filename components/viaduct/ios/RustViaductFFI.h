@@ -7,3 +7,4 @@
 #include <Foundation/NSObjCRuntime.h>
 
 void viaduct_use_reqwest_backend();
+uint8_t viaduct_add_spki_pin(const char *host, const char *pin);
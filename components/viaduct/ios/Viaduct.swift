@@ -24,4 +24,12 @@ public class Viaduct {
         // use_reqwest_backend is backend by a CallOnce.
         viaduct_use_reqwest_backend()
     }
+
+    /// Pins `host` to the public key `pin`, given in the `sha256/<base64>` format.
+    /// Call this once for each key to pin a host to several keys, before any
+    /// requests are made. Returns false if `pin` isn't valid.
+    @discardableResult
+    public func addSpkiPin(host: String, pin: String) -> Bool {
+        return viaduct_add_spki_pin(host, pin) != 0
+    }
 }
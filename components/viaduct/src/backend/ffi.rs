@@ -3,7 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::{backend::Backend, settings::GLOBAL_SETTINGS};
-use crate::{msg_types, Error};
+use crate::{msg_types, Error, SpkiPin};
 use ffi_support::{ByteBuffer, FfiStr};
 
 ffi_support::implement_into_ffi_by_protobuf!(msg_types::Request);
@@ -11,6 +11,11 @@ ffi_support::implement_into_ffi_by_protobuf!(msg_types::Request);
 impl From<crate::Request> for msg_types::Request {
     fn from(request: crate::Request) -> Self {
         let settings = GLOBAL_SETTINGS.read();
+        let spki_pins = settings
+            .spki_pins_for(&request.url)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
         msg_types::Request {
             url: request.url.to_string(),
             body: request.body,
@@ -22,6 +27,7 @@ impl From<crate::Request> for msg_types::Request {
             use_caches: settings.use_caches,
             connect_timeout_secs: settings.connect_timeout.map_or(0, |d| d.as_secs() as i32),
             read_timeout_secs: settings.read_timeout.map_or(0, |d| d.as_secs() as i32),
+            spki_pins,
         }
     }
 }
@@ -206,4 +212,25 @@ pub extern "C" fn viaduct_allow_android_emulator_loopback() {
     error.consume_and_log_if_error();
 }
 
+/// Pins `host` to the public key `pin`, given in the `sha256/<base64>` format.
+/// Calling this more than once for a host pins it to all of the keys. Like the
+/// other settings, pins should be added before any requests are made. Returns 0
+/// if `pin` isn't valid.
+#[no_mangle]
+pub extern "C" fn viaduct_add_spki_pin(host: FfiStr<'_>, pin: FfiStr<'_>) -> u8 {
+    let mut error = ffi_support::ExternError::default();
+    let added = ffi_support::call_with_output(&mut error, || match SpkiPin::parse(pin.as_str()) {
+        Ok(pin) => {
+            GLOBAL_SETTINGS.write().add_spki_pin(host.as_str(), pin);
+            true
+        }
+        Err(e) => {
+            log::error!("Not pinning {}: {}", host.as_str(), e);
+            false
+        }
+    });
+    error.consume_and_log_if_error();
+    added
+}
+
 ffi_support::define_bytebuffer_destructor!(viaduct_destroy_bytebuffer);
@@ -0,0 +1,789 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! An optional cache for `GET` requests.
+//!
+//! Components which fetch the same resources over and over (for example, Remote Settings
+//! collections, or suggestions) can send their requests through a [`ResponseCache`]
+//! instead of implementing their own caching. The cache honors the `Cache-Control`,
+//! `Age`, `ETag` and `Last-Modified` response headers:
+//!
+//! - Responses are served from the cache while they're fresh, according to their
+//!   `max-age`.
+//! - Once they're stale, they're revalidated with `If-None-Match` or `If-Modified-Since`,
+//!   and a `304 Not Modified` response is answered with the cached body.
+//! - Responses with `Cache-Control: no-store` or `Vary: *` are never stored.
+//! - Responses to requests with credentials (an `Authorization` or `Cookie` header) are only
+//!   stored if they're marked `Cache-Control: public`, since they're likely to be personal.
+//! - When a response has a `Vary` header, the cached copy is only used for requests whose
+//!   headers named by it match those of the request it was the response to.
+//!
+//! Requests can opt out with their own `Cache-Control` header: `no-store` skips the cache
+//! altogether, and `no-cache` always revalidates.
+//!
+//! Each cache has a namespace, which keeps the entries for different components apart. A
+//! cache can keep its entries in memory only, or also on disk, in a directory named after
+//! the namespace, so they outlive the process. The entries on disk are limited to a total
+//! size, and the least recently used are removed to make room.
+//!
+//! Only successful (`200 OK`) responses to `GET` requests without a body are cached;
+//! everything else is sent straight to the network.
+
+use crate::{header_names, status_codes, Error, Header, HeaderName, Headers, Method};
+use crate::{Request, Response};
+use parking_lot::Mutex;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The default number of entries kept in memory.
+const DEFAULT_MAX_MEMORY_ENTRIES: usize = 100;
+/// The default total size of the entries kept on disk.
+const DEFAULT_MAX_DISK_BYTES: u64 = 50 * 1024 * 1024;
+
+/// A cache for `GET` responses. See the [module docs](self) for how it behaves.
+pub struct ResponseCache {
+    namespace: String,
+    dir: Option<PathBuf>,
+    max_memory_entries: usize,
+    max_disk_bytes: u64,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl ResponseCache {
+    /// Create a cache which keeps its entries in memory.
+    pub fn new(namespace: &str) -> Self {
+        Self {
+            namespace: sanitize_namespace(namespace),
+            dir: None,
+            max_memory_entries: DEFAULT_MAX_MEMORY_ENTRIES,
+            max_disk_bytes: DEFAULT_MAX_DISK_BYTES,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Create a cache which also keeps its entries on disk, in a directory named after
+    /// `namespace` inside `dir`. The directory is created when needed.
+    pub fn with_disk(namespace: &str, dir: impl Into<PathBuf>) -> Self {
+        let mut cache = Self::new(namespace);
+        cache.dir = Some(dir.into().join(&cache.namespace));
+        cache
+    }
+
+    /// Set how many entries are kept in memory. When the cache is full, the entry which
+    /// was stored the longest time ago is dropped from memory (but not from disk).
+    pub fn max_memory_entries(mut self, max: usize) -> Self {
+        self.max_memory_entries = max.max(1);
+        self
+    }
+
+    /// Set the total size of the entries kept on disk. When a new entry takes the cache over
+    /// the limit, the entries which were used the longest time ago are removed.
+    pub fn max_disk_bytes(mut self, max: u64) -> Self {
+        self.max_disk_bytes = max;
+        self
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Send a request, using the cache when possible.
+    pub fn send(&self, request: Request) -> Result<Response, Error> {
+        self.send_with(request, now_secs(), crate::backend::send)
+    }
+
+    /// Remove all the entries, from memory and disk.
+    pub fn clear(&self) {
+        self.entries.lock().clear();
+        if let Some(dir) = &self.dir {
+            if let Err(e) = fs::remove_dir_all(dir) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to clear response cache {}: {}", self.namespace, e);
+                }
+            }
+        }
+    }
+
+    fn send_with(
+        &self,
+        mut request: Request,
+        now: u64,
+        send: impl FnOnce(Request) -> Result<Response, Error>,
+    ) -> Result<Response, Error> {
+        let request_cc = CacheControl::parse(&request.headers);
+        if request.method != Method::Get
+            || request.body.is_some()
+            || request_cc.no_store
+            // The caller is doing its own revalidation.
+            || request.headers.get(header_names::IF_NONE_MATCH).is_some()
+            || request.headers.get(header_names::IF_MODIFIED_SINCE).is_some()
+        {
+            return send(request);
+        }
+        let key = request.url.to_string();
+        // An entry stored for a request with different values of the headers the response
+        // varies on is no use for this one, even to revalidate.
+        let cached = self.get(&key).filter(|entry| entry.matches(&request));
+        if let Some(entry) = &cached {
+            if !request_cc.no_cache && entry.is_fresh(now) {
+                log::trace!("Response cache {}: hit for {}", self.namespace, key);
+                return Ok(entry.to_response(&request));
+            }
+            if let Some(etag) = entry.headers.get(header_names::ETAG) {
+                request
+                    .headers
+                    .insert_header(Header::new_unchecked(header_names::IF_NONE_MATCH, etag));
+            }
+            if let Some(last_modified) = entry.headers.get(header_names::LAST_MODIFIED) {
+                request.headers.insert_header(Header::new_unchecked(
+                    header_names::IF_MODIFIED_SINCE,
+                    last_modified,
+                ));
+            }
+        }
+        let response = send(request.clone())?;
+        match (response.status, cached) {
+            (status_codes::NOT_MODIFIED, Some(mut entry)) if entry.has_validator() => {
+                log::trace!("Response cache {}: revalidated {}", self.namespace, key);
+                entry.refresh(&response.headers, now);
+                let refreshed = entry.to_response(&request);
+                self.put(key, entry);
+                Ok(refreshed)
+            }
+            (status_codes::OK, _) => {
+                if let Some(entry) = CacheEntry::from_response(&request, &response, now) {
+                    self.put(key, entry);
+                } else {
+                    self.remove(&key);
+                }
+                Ok(response)
+            }
+            _ => Ok(response),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let in_memory = self.entries.lock().get(key).cloned();
+        if let Some(entry) = in_memory {
+            self.touch_on_disk(key);
+            return Some(entry);
+        }
+        let entry = self.read_from_disk(key)?;
+        self.touch_on_disk(key);
+        self.put_in_memory(key.to_string(), entry.clone());
+        Some(entry)
+    }
+
+    fn put(&self, key: String, entry: CacheEntry) {
+        self.write_to_disk(&key, &entry);
+        self.put_in_memory(key, entry);
+    }
+
+    fn put_in_memory(&self, key: String, entry: CacheEntry) {
+        let mut entries = self.entries.lock();
+        if !entries.contains_key(&key) && entries.len() >= self.max_memory_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, e)| e.stored_at)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, entry);
+    }
+
+    fn remove(&self, key: &str) {
+        self.entries.lock().remove(key);
+        if let Some(path) = self.path_for(key) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    // The modification time of an entry's file is when it was last used, which is what we
+    // evict by.
+    fn touch_on_disk(&self, key: &str) {
+        if let Some(path) = self.path_for(key) {
+            let _ = fs::File::options()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_modified(SystemTime::now()));
+        }
+    }
+
+    fn path_for(&self, key: &str) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{:016x}.cache", fnv1a(key.as_bytes()))))
+    }
+
+    fn read_from_disk(&self, key: &str) -> Option<CacheEntry> {
+        let path = self.path_for(key)?;
+        let data = fs::read(path).ok()?;
+        match CacheEntry::decode(key, &data) {
+            Some(entry) => Some(entry),
+            None => {
+                // Either corrupt, or another URL with the same hash; either way, we
+                // can't use it.
+                log::warn!("Response cache {}: ignoring bad entry", self.namespace);
+                None
+            }
+        }
+    }
+
+    fn write_to_disk(&self, key: &str, entry: &CacheEntry) {
+        let (Some(dir), Some(path)) = (&self.dir, self.path_for(key)) else {
+            return;
+        };
+        // Write to a temporary file first, so readers never see half an entry.
+        let tmp = path.with_extension("tmp");
+        let result = fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&tmp, entry.encode(key)))
+            .and_then(|_| fs::rename(&tmp, &path))
+            .and_then(|_| self.evict_from_disk(dir));
+        if let Err(e) = result {
+            // A cache which can't be written is only slower, so this isn't an error.
+            log::warn!("Response cache {}: failed to write: {}", self.namespace, e);
+        }
+    }
+
+    // Remove the least recently used entries until the rest fit in `max_disk_bytes`.
+    fn evict_from_disk(&self, dir: &Path) -> std::io::Result<()> {
+        let mut files = Vec::new();
+        for dir_entry in fs::read_dir(dir)? {
+            let path = dir_entry?.path();
+            if path.extension().map_or(true, |ext| ext != "cache") {
+                continue;
+            }
+            let metadata = fs::metadata(&path)?;
+            files.push((metadata.modified()?, metadata.len(), path));
+        }
+        let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+        if total <= self.max_disk_bytes {
+            return Ok(());
+        }
+        files.sort();
+        for (_, len, path) in files {
+            if total <= self.max_disk_bytes {
+                break;
+            }
+            log::trace!("Response cache {}: evicting {:?}", self.namespace, path);
+            fs::remove_file(path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    public: bool,
+    max_age: Option<u64>,
+}
+
+impl CacheControl {
+    fn parse(headers: &Headers) -> Self {
+        let mut cc = Self::default();
+        let Some(value) = headers.get(header_names::CACHE_CONTROL) else {
+            return cc;
+        };
+        for directive in value.split(',') {
+            let (name, arg) = match directive.split_once('=') {
+                Some((name, arg)) => (name.trim(), Some(arg.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+            match name.to_ascii_lowercase().as_str() {
+                "no-store" => cc.no_store = true,
+                "no-cache" => cc.no_cache = true,
+                "public" => cc.public = true,
+                "max-age" => cc.max_age = arg.and_then(|a| a.parse().ok()),
+                _ => {}
+            }
+        }
+        cc
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CacheEntry {
+    headers: Headers,
+    body: Vec<u8>,
+    /// The values the request had for each of the headers named by the response's `Vary`.
+    vary: Vec<(HeaderName, Option<String>)>,
+    /// When the response was stored or last revalidated, in seconds since the epoch.
+    stored_at: u64,
+}
+
+impl CacheEntry {
+    fn from_response(request: &Request, response: &Response, now: u64) -> Option<Self> {
+        let cc = CacheControl::parse(&response.headers);
+        if cc.no_store || response.headers.get(header_names::VARY) == Some("*") {
+            return None;
+        }
+        let has_credentials = request.headers.get(header_names::AUTHORIZATION).is_some()
+            || request.headers.get(header_names::COOKIE).is_some();
+        if has_credentials && !cc.public {
+            return None;
+        }
+        let vary = vary_header_names(&response.headers)?
+            .into_iter()
+            .map(|name| {
+                let value = request.headers.get(name.clone()).map(str::to_string);
+                (name, value)
+            })
+            .collect();
+        let entry = Self {
+            headers: response.headers.clone(),
+            body: response.body.clone(),
+            vary,
+            stored_at: now,
+        };
+        // There's no point keeping a response we can neither reuse nor revalidate.
+        (entry.freshness_lifetime() > 0 || entry.has_validator()).then_some(entry)
+    }
+
+    fn freshness_lifetime(&self) -> u64 {
+        let cc = CacheControl::parse(&self.headers);
+        if cc.no_cache {
+            return 0;
+        }
+        let age = self
+            .headers
+            .try_get::<u64, _>(header_names::AGE)
+            .unwrap_or_default();
+        cc.max_age.unwrap_or_default().saturating_sub(age)
+    }
+
+    /// Can this entry be used for `request`, according to the response's `Vary` header?
+    fn matches(&self, request: &Request) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| request.headers.get(name.clone()) == value.as_deref())
+    }
+
+    fn is_fresh(&self, now: u64) -> bool {
+        now < self.stored_at.saturating_add(self.freshness_lifetime())
+    }
+
+    fn has_validator(&self) -> bool {
+        self.headers.get(header_names::ETAG).is_some()
+            || self.headers.get(header_names::LAST_MODIFIED).is_some()
+    }
+
+    // A 304 response carries the headers which changed, like a new `Cache-Control`.
+    fn refresh(&mut self, headers: &Headers, now: u64) {
+        for header in headers.iter() {
+            self.headers.insert_header(header.clone());
+        }
+        if headers.get(header_names::AGE).is_none() {
+            self.headers
+                .insert_header(Header::new_unchecked(header_names::AGE, "0"));
+        }
+        self.stored_at = now;
+    }
+
+    fn to_response(&self, request: &Request) -> Response {
+        Response {
+            request_method: request.method,
+            url: request.url.clone(),
+            status: status_codes::OK,
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+        }
+    }
+
+    // Entries are stored as a line of JSON metadata, followed by the body.
+    fn encode(&self, key: &str) -> Vec<u8> {
+        let headers: Vec<_> = self
+            .headers
+            .iter()
+            .map(|h| (h.name().as_str(), h.value()))
+            .collect();
+        let vary: Vec<_> = self
+            .vary
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+            .collect();
+        let mut data = json!({
+            "url": key,
+            "storedAt": self.stored_at,
+            "headers": headers,
+            "vary": vary,
+        })
+        .to_string()
+        .into_bytes();
+        data.push(b'\n');
+        data.extend_from_slice(&self.body);
+        data
+    }
+
+    fn decode(key: &str, data: &[u8]) -> Option<Self> {
+        let split = data.iter().position(|&b| b == b'\n')?;
+        let meta: serde_json::Value = serde_json::from_slice(&data[..split]).ok()?;
+        if meta["url"].as_str()? != key {
+            return None;
+        }
+        let mut headers = Headers::new();
+        for pair in meta["headers"].as_array()? {
+            let name = HeaderName::new(pair[0].as_str()?.to_string()).ok()?;
+            headers.insert_header(Header::new_unchecked(name, pair[1].as_str()?));
+        }
+        let mut vary = Vec::new();
+        for pair in meta["vary"].as_array()? {
+            let name = HeaderName::new(pair[0].as_str()?.to_string()).ok()?;
+            vary.push((name, pair[1].as_str().map(str::to_string)));
+        }
+        Some(Self {
+            headers,
+            body: data[split + 1..].to_vec(),
+            vary,
+            stored_at: meta["storedAt"].as_u64()?,
+        })
+    }
+}
+
+// The names of the request headers a response varies on, or `None` if any are invalid.
+fn vary_header_names(headers: &Headers) -> Option<Vec<HeaderName>> {
+    let Some(value) = headers.get(header_names::VARY) else {
+        return Some(Vec::new());
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| HeaderName::new(name.to_string()).ok())
+        .collect()
+}
+
+// The namespace becomes a directory name, so keep it to characters which are safe there.
+fn sanitize_namespace(namespace: &str) -> String {
+    namespace
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// FNV-1a, which is stable across releases, unlike `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use url::Url;
+
+    const URL: &str = "https://example.com/resource";
+
+    fn response(status: u16, headers: &[(&'static str, &str)], body: &str) -> Response {
+        let mut h = Headers::new();
+        for (name, value) in headers {
+            h.insert(*name, *value).unwrap();
+        }
+        Response {
+            request_method: Method::Get,
+            url: Url::parse(URL).unwrap(),
+            status,
+            headers: h,
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    // Sends a request through the cache, returning the response and the request which
+    // reached the "network", if any.
+    fn send(
+        cache: &ResponseCache,
+        request: Request,
+        now: u64,
+        network: Response,
+    ) -> (Response, Option<Request>) {
+        let sent = RefCell::new(None);
+        let response = cache
+            .send_with(request, now, |r| {
+                *sent.borrow_mut() = Some(r);
+                Ok(network)
+            })
+            .unwrap();
+        (response, sent.into_inner())
+    }
+
+    fn get() -> Request {
+        Request::get(Url::parse(URL).unwrap())
+    }
+
+    #[test]
+    fn test_max_age() {
+        let cache = ResponseCache::new("test");
+        let fresh = response(200, &[("Cache-Control", "max-age=60")], "one");
+        let (resp, sent) = send(&cache, get(), 1000, fresh.clone());
+        assert_eq!(resp.text(), "one");
+        assert!(sent.is_some());
+
+        // Served from the cache while it's fresh.
+        let (resp, sent) = send(&cache, get(), 1059, response(500, &[], ""));
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.text(), "one");
+        assert!(sent.is_none());
+
+        // Once it's stale, it's fetched again, without validators since there are none.
+        let (resp, sent) = send(
+            &cache,
+            get(),
+            1060,
+            response(200, &[("Cache-Control", "max-age=60")], "two"),
+        );
+        assert_eq!(resp.text(), "two");
+        assert_eq!(sent.unwrap().headers.get(header_names::IF_NONE_MATCH), None);
+
+        // The `Age` header counts against `max-age`.
+        let cache = ResponseCache::new("test");
+        let aged = response(200, &[("Cache-Control", "max-age=60"), ("Age", "50")], "");
+        send(&cache, get(), 1000, aged);
+        assert!(send(&cache, get(), 1009, fresh.clone()).1.is_none());
+        assert!(send(&cache, get(), 1010, fresh).1.is_some());
+    }
+
+    #[test]
+    fn test_revalidation() {
+        let cache = ResponseCache::new("test");
+        let headers = [("ETag", "\"v1\""), ("Last-Modified", "yesterday")];
+        send(&cache, get(), 1000, response(200, &headers, "body"));
+
+        // `no-cache` responses are always revalidated, and a 304 gets the cached body.
+        let (resp, sent) = send(
+            &cache,
+            get(),
+            1001,
+            response(304, &[("Cache-Control", "max-age=10")], ""),
+        );
+        let sent = sent.unwrap();
+        assert_eq!(
+            sent.headers.get(header_names::IF_NONE_MATCH),
+            Some("\"v1\"")
+        );
+        assert_eq!(
+            sent.headers.get(header_names::IF_MODIFIED_SINCE),
+            Some("yesterday")
+        );
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.text(), "body");
+
+        // The 304's headers were merged in, so it's now fresh for 10 seconds.
+        assert!(send(&cache, get(), 1010, response(500, &[], ""))
+            .1
+            .is_none());
+
+        // A new response replaces the entry.
+        let (resp, _) = send(
+            &cache,
+            get(),
+            1011,
+            response(200, &[("ETag", "\"v2\"")], "new body"),
+        );
+        assert_eq!(resp.text(), "new body");
+        let (_, sent) = send(&cache, get(), 1012, response(304, &[], ""));
+        assert_eq!(
+            sent.unwrap().headers.get(header_names::IF_NONE_MATCH),
+            Some("\"v2\"")
+        );
+    }
+
+    #[test]
+    fn test_not_cached() {
+        let cache = ResponseCache::new("test");
+        let fresh = response(200, &[("Cache-Control", "max-age=60")], "");
+
+        // Only GETs without a body are cached.
+        send(
+            &cache,
+            Request::post(Url::parse(URL).unwrap()),
+            1000,
+            fresh.clone(),
+        );
+        send(&cache, get().body("x"), 1000, fresh.clone());
+        assert!(cache.entries.lock().is_empty());
+
+        // Nor are responses which ask not to be, can't be reused, or aren't 200s.
+        for headers in [
+            &[("Cache-Control", "no-store, max-age=60")][..],
+            &[("Cache-Control", "max-age=60"), ("Vary", "*")],
+            &[("Cache-Control", "max-age=0")],
+            &[],
+        ] {
+            send(&cache, get(), 1000, response(200, headers, ""));
+            assert!(cache.entries.lock().is_empty());
+        }
+        send(&cache, get(), 1000, response(404, &[("ETag", "\"x\"")], ""));
+        assert!(cache.entries.lock().is_empty());
+
+        // Requests can skip or revalidate the cache.
+        send(&cache, get(), 1000, fresh.clone());
+        let no_store = get().header("Cache-Control", "no-store").unwrap();
+        assert!(send(&cache, no_store, 1001, fresh.clone()).1.is_some());
+        let no_cache = get().header("Cache-Control", "no-cache").unwrap();
+        assert!(send(&cache, no_cache, 1001, fresh.clone()).1.is_some());
+        assert!(send(&cache, get(), 1001, fresh).1.is_none());
+    }
+
+    #[test]
+    fn test_credentials() {
+        let cache = ResponseCache::new("test");
+        let fresh = response(200, &[("Cache-Control", "max-age=60")], "");
+        let public = response(200, &[("Cache-Control", "public, max-age=60")], "");
+
+        // Responses to requests with credentials are only stored if they're public.
+        for (name, value) in [("Authorization", "Bearer token"), ("Cookie", "session=1")] {
+            let request = get().header(name, value).unwrap();
+            send(&cache, request, 1000, fresh.clone());
+            assert!(cache.entries.lock().is_empty());
+        }
+        let request = get().header("Authorization", "Bearer token").unwrap();
+        send(&cache, request, 1000, public);
+        assert!(send(&cache, get(), 1001, fresh).1.is_none());
+    }
+
+    #[test]
+    fn test_vary() {
+        let cache = ResponseCache::new("test");
+        let fresh = response(
+            200,
+            &[("Cache-Control", "max-age=60"), ("Vary", "Accept-Language")],
+            "en",
+        );
+        let english = || get().header("Accept-Language", "en").unwrap();
+        send(&cache, english(), 1000, fresh.clone());
+        assert!(send(&cache, english(), 1001, fresh.clone()).1.is_none());
+
+        // A request with a different value, or without the header, isn't answered from the
+        // cache, and isn't revalidated against the entry either.
+        let french = get().header("Accept-Language", "fr").unwrap();
+        let (_, sent) = send(&cache, french, 1001, response(500, &[], ""));
+        assert_eq!(sent.unwrap().headers.get(header_names::IF_NONE_MATCH), None);
+        assert!(send(&cache, get(), 1001, response(500, &[], ""))
+            .1
+            .is_some());
+        assert!(send(&cache, english(), 1001, fresh).1.is_none());
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let cache = ResponseCache::new("test").max_memory_entries(2);
+        for (i, path) in ["a", "b", "c"].iter().enumerate() {
+            let request = Request::get(Url::parse(URL).unwrap().join(path).unwrap());
+            let fresh = response(200, &[("Cache-Control", "max-age=60")], "");
+            send(&cache, request, 1000 + i as u64, fresh);
+        }
+        let entries = cache.entries.lock();
+        assert_eq!(entries.len(), 2);
+        assert!(!entries.contains_key("https://example.com/a"));
+    }
+
+    #[test]
+    fn test_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::with_disk("remote settings", dir.path());
+        assert_eq!(cache.namespace(), "remote_settings");
+        let headers = [("Cache-Control", "max-age=60"), ("ETag", "\"v1\"")];
+        send(
+            &cache,
+            get(),
+            1000,
+            response(200, &headers, "body\nwith lines"),
+        );
+        assert!(dir.path().join("remote_settings").is_dir());
+
+        // A new cache with the same namespace finds the entry.
+        let cache = ResponseCache::with_disk("remote settings", dir.path());
+        let (resp, sent) = send(&cache, get(), 1001, response(500, &[], ""));
+        assert!(sent.is_none());
+        assert_eq!(resp.text(), "body\nwith lines");
+        assert_eq!(resp.headers.get(header_names::ETAG), Some("\"v1\""));
+
+        // But one with a different namespace doesn't.
+        let other = ResponseCache::with_disk("suggest", dir.path());
+        assert!(send(&other, get(), 1001, response(500, &[], ""))
+            .1
+            .is_some());
+
+        cache.clear();
+        assert!(!dir.path().join("remote_settings").exists());
+        let cache = ResponseCache::with_disk("remote settings", dir.path());
+        assert!(send(&cache, get(), 1001, response(500, &[], ""))
+            .1
+            .is_some());
+    }
+
+    #[test]
+    fn test_disk_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let body = "x".repeat(1000);
+        // Room for two entries, but not three.
+        let cache = ResponseCache::with_disk("test", dir.path())
+            .max_memory_entries(1)
+            .max_disk_bytes(2500);
+        let request = |path| Request::get(Url::parse(URL).unwrap().join(path).unwrap());
+        let fresh = response(200, &[("Cache-Control", "max-age=60")], &body);
+        let modified_at = |path| {
+            fs::metadata(cache.path_for(request(path).url.as_ref()).unwrap())
+                .unwrap()
+                .modified()
+                .unwrap()
+        };
+        send(&cache, request("a"), 1000, fresh.clone());
+        send(&cache, request("b"), 1000, fresh.clone());
+        // Using `a` makes `b` the least recently used.
+        let before = modified_at("a");
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        assert!(send(&cache, request("a"), 1001, fresh.clone()).1.is_none());
+        assert!(modified_at("a") > before);
+        send(&cache, request("c"), 1002, fresh.clone());
+
+        let cache = ResponseCache::with_disk("test", dir.path());
+        assert!(send(&cache, request("a"), 1003, fresh.clone()).1.is_none());
+        assert!(send(&cache, request("b"), 1003, fresh.clone()).1.is_some());
+        assert!(send(&cache, request("c"), 1003, fresh).1.is_none());
+    }
+
+    #[test]
+    fn test_parse_cache_control() {
+        let mut headers = Headers::new();
+        headers
+            .insert("Cache-Control", "public, MAX-AGE=\"30\", no-cache")
+            .unwrap();
+        assert_eq!(
+            CacheControl::parse(&headers),
+            CacheControl {
+                no_store: false,
+                no_cache: true,
+                public: true,
+                max_age: Some(30),
+            }
+        );
+        assert_eq!(
+            CacheControl::parse(&Headers::new()),
+            CacheControl::default()
+        );
+    }
+}
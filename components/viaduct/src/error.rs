@@ -26,6 +26,13 @@ pub enum Error {
 
     #[error("[no-sentry] Validation error: URL does not use TLS protocol.")]
     NonTlsUrl,
+
+    #[error("Invalid public key pin: {0}")]
+    InvalidSpkiPin(String),
+
+    /// The server's certificate didn't have any of the keys pinned for its host.
+    #[error("[no-sentry] Certificate for {0} doesn't match its pinned keys")]
+    SpkiPinMismatch(String),
 }
 
 impl From<url::ParseError> for Error {
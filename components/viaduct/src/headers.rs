@@ -378,11 +378,17 @@ pub mod consts {
     headers!(
         (ACCEPT_ENCODING, "accept-encoding"),
         (ACCEPT, "accept"),
+        (AGE, "age"),
         (AUTHORIZATION, "authorization"),
+        (CACHE_CONTROL, "cache-control"),
         (CONTENT_TYPE, "content-type"),
+        (COOKIE, "cookie"),
         (ETAG, "etag"),
+        (IF_MODIFIED_SINCE, "if-modified-since"),
         (IF_NONE_MATCH, "if-none-match"),
+        (LAST_MODIFIED, "last-modified"),
         (USER_AGENT, "user-agent"),
+        (VARY, "vary"),
         // non-standard, but it's convenient to have these.
         (RETRY_AFTER, "retry-after"),
         (X_IF_UNMODIFIED_SINCE, "x-if-unmodified-since"),
@@ -10,12 +10,16 @@ use url::Url;
 mod headers;
 
 mod backend;
+pub mod cache;
 pub mod error;
+pub mod pinning;
 pub mod settings;
 pub use error::*;
 
 pub use backend::{note_backend, set_backend, Backend};
+pub use cache::ResponseCache;
 pub use headers::{consts as header_names, Header, HeaderName, Headers, InvalidHeaderName};
+pub use pinning::SpkiPin;
 pub use settings::GLOBAL_SETTINGS;
 
 #[allow(clippy::derive_partial_eq_without_eq)]
@@ -87,6 +91,12 @@ impl Request {
         crate::backend::send(self)
     }
 
+    /// Send the request through a [`ResponseCache`], which may answer it without
+    /// using the network. See [`ResponseCache::send`].
+    pub fn send_cached(self, cache: &ResponseCache) -> Result<Response, Error> {
+        cache.send(self)
+    }
+
     /// Alias for `Request::new(Method::Get, url)`, for convenience.
     pub fn get(url: Url) -> Self {
         Self::new(Method::Get, url)
@@ -365,5 +375,7 @@ pub mod status_codes {
         (503, SERVICE_UNAVAILABLE),
         (504, GATEWAY_TIMEOUT),
         (505, HTTP_VERSION_NOT_SUPPORTED),
+        // From https://www.rfc-editor.org/rfc/rfc6585#section-6
+        (511, NETWORK_AUTHENTICATION_REQUIRED),
     ];
 }
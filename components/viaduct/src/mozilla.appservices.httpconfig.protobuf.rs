@@ -20,6 +20,11 @@ pub struct Request {
     pub connect_timeout_secs: i32,
     #[prost(int32, required, tag = "8")]
     pub read_timeout_secs: i32,
+    /// The public key pins for the request's host, in the `sha256/<base64>`
+    /// format. If there are any, the request must fail unless the server's
+    /// certificate has one of these keys.
+    #[prost(string, repeated, tag = "9")]
+    pub spki_pins: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 /// Nested message and enum types in `Request`.
 pub mod request {
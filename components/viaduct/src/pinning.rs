@@ -0,0 +1,237 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Public key pins for hosts which need them.
+//!
+//! Products which require it can pin high-value endpoints, like the FxA and
+//! tokenserver hosts, to a set of public keys with
+//! [`Settings::spki_pins`](crate::settings::Settings::spki_pins). Requests
+//! to a pinned host fail unless the server's certificate has one of the
+//! pinned keys. Pins use the `sha256/<base64>` format from HPKP, which is
+//! also what OkHttp's `CertificatePinner` takes, so the same pins can be
+//! given to the Android backend.
+//!
+//! Like OkHttp, a host's pins match if any certificate in the server's
+//! verified chain has one of the pinned keys, so leaf, intermediate and root
+//! keys can all be pinned. The reqwest backend checks the pins while verifying
+//! the chain during the TLS handshake. Requests sent through the FFI backend
+//! carry the pins for their host in `spki_pins`, and the application's fetch
+//! client is responsible for enforcing them. Applications add pins with
+//! `viaduct_add_spki_pin()`.
+
+use crate::Error;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+const PIN_PREFIX: &str = "sha256/";
+
+/// The SHA-256 hash of a DER-encoded SubjectPublicKeyInfo.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpkiPin([u8; 32]);
+
+impl SpkiPin {
+    /// Parses a pin in the `sha256/<base64>` format.
+    pub fn parse(pin: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidSpkiPin(pin.to_string());
+        let encoded = pin.strip_prefix(PIN_PREFIX).ok_or_else(invalid)?;
+        let hash = STANDARD.decode(encoded).map_err(|_| invalid())?;
+        Ok(Self(hash.try_into().map_err(|_| invalid())?))
+    }
+
+    /// Returns the pin for a DER-encoded SubjectPublicKeyInfo.
+    pub fn from_spki(spki: &[u8]) -> Self {
+        Self(Sha256::digest(spki).into())
+    }
+
+    /// Returns the pin for the public key in a DER-encoded X.509 certificate.
+    pub fn from_certificate(cert: &[u8]) -> Result<Self, Error> {
+        let spki = spki_from_certificate(cert)
+            .ok_or_else(|| Error::BackendError("Couldn't parse the server certificate".into()))?;
+        Ok(Self::from_spki(spki))
+    }
+
+    /// Returns the pin for a SubjectPublicKeyInfo given without its outer
+    /// SEQUENCE tag and length, which is how trust anchors usually store it.
+    pub fn from_spki_contents(contents: &[u8]) -> Self {
+        let mut spki = vec![DER_SEQUENCE];
+        let len = contents.len();
+        if len < 0x80 {
+            spki.push(len as u8);
+        } else {
+            let len_bytes = len.to_be_bytes();
+            let skip = len_bytes.iter().take_while(|&&byte| byte == 0).count();
+            spki.push(0x80 | (len_bytes.len() - skip) as u8);
+            spki.extend_from_slice(&len_bytes[skip..]);
+        }
+        spki.extend_from_slice(contents);
+        Self::from_spki(&spki)
+    }
+}
+
+/// Returns the issuer name of a DER-encoded X.509 certificate, without its
+/// outer SEQUENCE tag and length, to find the trust anchor which issued it.
+pub fn certificate_issuer(cert: &[u8]) -> Option<&[u8]> {
+    let mut fields = tbs_certificate_fields(cert)?;
+    // Skip the serial number and signature algorithm.
+    for _ in 0..2 {
+        fields = split_der_element(fields)?.1;
+    }
+    der_sequence(fields).map(|issuer| issuer.contents)
+}
+
+impl fmt::Display for SpkiPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", PIN_PREFIX, STANDARD.encode(self.0))
+    }
+}
+
+impl fmt::Debug for SpkiPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SpkiPin({})", self)
+    }
+}
+
+const DER_SEQUENCE: u8 = 0x30;
+const DER_EXPLICIT_0: u8 = 0xa0;
+
+struct DerElement<'a> {
+    tag: u8,
+    // The whole element, including the tag and length.
+    bytes: &'a [u8],
+    contents: &'a [u8],
+}
+
+/// Splits the DER element at the start of `der` from whatever comes after it.
+fn split_der_element(der: &[u8]) -> Option<(DerElement<'_>, &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&first_len_byte, mut rest) = rest.split_first()?;
+    let len = if first_len_byte < 0x80 {
+        usize::from(first_len_byte)
+    } else {
+        let num_len_bytes = usize::from(first_len_byte & 0x7f);
+        if num_len_bytes == 0 || num_len_bytes > 4 || rest.len() < num_len_bytes {
+            return None;
+        }
+        let (len_bytes, after_len) = rest.split_at(num_len_bytes);
+        rest = after_len;
+        len_bytes
+            .iter()
+            .fold(0usize, |len, &byte| (len << 8) | usize::from(byte))
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let header_len = der.len() - rest.len();
+    let (contents, rest) = rest.split_at(len);
+    let element = DerElement {
+        tag,
+        bytes: &der[..header_len + len],
+        contents,
+    };
+    Some((element, rest))
+}
+
+fn der_sequence(der: &[u8]) -> Option<DerElement<'_>> {
+    split_der_element(der)
+        .map(|(element, _)| element)
+        .filter(|element| element.tag == DER_SEQUENCE)
+}
+
+/// Returns the fields of a certificate's tbsCertificate, starting from the
+/// serial number.
+fn tbs_certificate_fields(cert: &[u8]) -> Option<&[u8]> {
+    let cert = der_sequence(cert)?;
+    let mut fields = der_sequence(cert.contents)?.contents;
+    if fields.first() == Some(&DER_EXPLICIT_0) {
+        fields = split_der_element(fields)?.1;
+    }
+    Some(fields)
+}
+
+/// Finds the SubjectPublicKeyInfo in a DER-encoded X.509 certificate. We only
+/// need the one field, so this walks the structure rather than parsing it:
+///
+/// ```text
+/// Certificate ::= SEQUENCE {
+///     tbsCertificate SEQUENCE {
+///         version [0] EXPLICIT Version DEFAULT v1,
+///         serialNumber, signature, issuer, validity, subject,
+///         subjectPublicKeyInfo SEQUENCE { ... },
+///         ...
+///     },
+///     ...
+/// }
+/// ```
+fn spki_from_certificate(cert: &[u8]) -> Option<&[u8]> {
+    let mut fields = tbs_certificate_fields(cert)?;
+    // Skip the serial number, signature algorithm, issuer, validity and subject.
+    for _ in 0..5 {
+        fields = split_der_element(fields)?.1;
+    }
+    der_sequence(fields).map(|spki| spki.bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A self-signed certificate for example.com, and the pin for its key from
+    // `openssl x509 -pubkey -noout | openssl pkey -pubin -outform der |
+    // openssl dgst -sha256 -binary | base64`.
+    const CERT: &str = "MIIBhDCCASmgAwIBAgIUYCYVdxD1bRBab6y6oFFh4sxlPZUwCgYIKoZIzj0EAwIwFjEUMBIGA1UEAwwLZXhhbXBsZS5jb20wIBcNMjYxMDE2MTYwNzI3WhgPMjEyNjA5MjIxNjA3MjdaMBYxFDASBgNVBAMMC2V4YW1wbGUuY29tMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE8yyI1I6WSb9d/5I48l72Zsuwfp9eAI4flsppyJ85FXA0SEhOmTE7vp7FKOcf8F6xII1Ln2ejvgusLCUgqK0cQ6NTMFEwHQYDVR0OBBYEFAcL2wciTfGyAC61O/rVgQFbuSZnMB8GA1UdIwQYMBaAFAcL2wciTfGyAC61O/rVgQFbuSZnMA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSQAwRgIhAPMNyf7R+V5kRenhveJ1Ox8KqO78MG0WsZqJrQ6kgVV3AiEAqpisTe6PHsKjMuWPBFrqOV/d/WGWhVMbEAQPQyuPGZ4=";
+    const PIN: &str = "sha256/dT5PkW3KVms+q5xzuBWPRBRBBIuQ0D1BEXme/0lyNt0=";
+
+    #[test]
+    fn test_pin_from_certificate() {
+        let cert = STANDARD.decode(CERT).unwrap();
+        let pin = SpkiPin::from_certificate(&cert).unwrap();
+        assert_eq!(pin, SpkiPin::parse(PIN).unwrap());
+        assert_eq!(pin.to_string(), PIN);
+
+        assert!(SpkiPin::from_certificate(&cert[..100]).is_err());
+        assert!(SpkiPin::from_certificate(b"").is_err());
+
+        let spki = spki_from_certificate(&cert).unwrap();
+        let (spki, _) = split_der_element(spki).unwrap();
+        assert_eq!(SpkiPin::from_spki_contents(spki.contents), pin);
+        // A long enough SubjectPublicKeyInfo needs a multi-byte length.
+        let contents = [0u8; 300];
+        let mut long_spki = vec![DER_SEQUENCE, 0x82, 0x01, 0x2c];
+        long_spki.extend_from_slice(&contents);
+        assert_eq!(
+            SpkiPin::from_spki_contents(&contents),
+            SpkiPin::from_spki(&long_spki)
+        );
+    }
+
+    #[test]
+    fn test_certificate_issuer() {
+        let cert = STANDARD.decode(CERT).unwrap();
+        // The certificate is self-signed, so the issuer is its own subject,
+        // `CN=example.com`.
+        let issuer = certificate_issuer(&cert).unwrap();
+        let (rdn, rest) = split_der_element(issuer).unwrap();
+        assert_eq!(rdn.tag, 0x31);
+        assert!(rest.is_empty());
+        assert!(issuer.windows(11).any(|w| w == b"example.com"));
+        assert!(certificate_issuer(&cert[..40]).is_none());
+    }
+
+    #[test]
+    fn test_parse_pin() {
+        for invalid in [
+            "dT5PkW3KVms+q5xzuBWPRBRBBIuQ0D1BEXme/0lyNt0=",
+            "sha1/dT5PkW3KVms+q5xzuBWPRBRBBIuQ0D1BEXme/0lyNt0=",
+            "sha256/not base64",
+            "sha256/dT5PkW3KVms+q5xzuBWPRBRBBIuQ0D1B",
+        ] {
+            assert!(
+                matches!(SpkiPin::parse(invalid), Err(Error::InvalidSpkiPin(_))),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
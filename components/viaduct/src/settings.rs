@@ -2,8 +2,10 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use crate::pinning::SpkiPin;
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
+use std::collections::HashMap;
 use std::time::Duration;
 use url::Url;
 
@@ -26,6 +28,27 @@ pub struct Settings {
     // For testing purposes, we allow exactly one additional Url which is
     // allowed to not be https.
     pub addn_allowed_insecure_url: Option<Url>,
+    // Public key pins, by host name. Requests to these hosts fail unless the
+    // server's certificate has one of the host's keys. These should be set
+    // when the backend is initialized, before any requests are made.
+    pub spki_pins: HashMap<String, Vec<SpkiPin>>,
+}
+
+impl Settings {
+    /// The pins for `url`'s host, which are empty if it isn't pinned.
+    pub fn spki_pins_for(&self, url: &Url) -> &[SpkiPin] {
+        url.host_str()
+            .and_then(|host| self.spki_pins.get(host))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Pins `host` to `pin`, in addition to any keys it's already pinned to.
+    pub fn add_spki_pin(&mut self, host: &str, pin: SpkiPin) {
+        let pins = self.spki_pins.entry(host.to_ascii_lowercase()).or_default();
+        if !pins.contains(&pin) {
+            pins.push(pin);
+        }
+    }
 }
 
 #[cfg(target_os = "ios")]
@@ -42,5 +65,6 @@ pub static GLOBAL_SETTINGS: Lazy<RwLock<Settings>> = Lazy::new(|| {
         follow_redirects: true,
         use_caches: false,
         addn_allowed_insecure_url: None,
+        spki_pins: HashMap::new(),
     })
 });
@@ -9,6 +9,7 @@ mod api;
 mod db;
 pub mod error;
 mod ffi;
+mod listeners;
 mod migration;
 mod schema;
 pub mod store;
@@ -24,6 +25,7 @@ pub use api::SYNC_QUOTA_BYTES;
 pub use api::SYNC_QUOTA_BYTES_PER_ITEM;
 
 pub use crate::error::{QuotaReason, WebExtStorageApiError};
+pub use crate::listeners::StorageChangeListener;
 pub use crate::store::WebExtStorageStore;
 pub use api::UsageInfo;
 pub use api::{StorageChanges, StorageValueChange};
@@ -0,0 +1,53 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::api::StorageChanges;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Implemented by the application to be told when an extension's data
+/// changes, either because the extension itself called `set`, `remove` or
+/// `clear`, or because a sync applied changes from another device.
+pub trait StorageChangeListener: Send + Sync {
+    fn on_changed(&self, ext_id: String, changes: StorageChanges);
+}
+
+/// The listeners registered with a store, keyed by extension ID. This is
+/// shared between the store and its bridged engine, so that changes applied
+/// by a sync are reported too.
+#[derive(Default)]
+pub(crate) struct ChangeListeners {
+    listeners: Mutex<HashMap<String, Arc<dyn StorageChangeListener>>>,
+}
+
+impl ChangeListeners {
+    /// Registers `listener` for `ext_id`, replacing any existing listener for
+    /// that extension.
+    pub(crate) fn register(&self, ext_id: &str, listener: Box<dyn StorageChangeListener>) {
+        self.listeners
+            .lock()
+            .insert(ext_id.to_string(), Arc::from(listener));
+    }
+
+    /// Returns true if there was a listener for `ext_id`.
+    pub(crate) fn unregister(&self, ext_id: &str) -> bool {
+        self.listeners.lock().remove(ext_id).is_some()
+    }
+
+    /// Tells the listener for `ext_id`, if there is one, about `changes`.
+    /// This should only be called after the changes have been committed, and
+    /// without holding the database lock, because the listener might call
+    /// back into the store.
+    pub(crate) fn notify(&self, ext_id: &str, changes: &StorageChanges) {
+        if changes.is_empty() {
+            return;
+        }
+        // Clone the listener out so it isn't called with our lock held.
+        let listener = self.listeners.lock().get(ext_id).cloned();
+        if let Some(listener) = listener {
+            listener.on_changed(ext_id.to_string(), changes.clone());
+        }
+    }
+}
@@ -5,6 +5,7 @@
 use crate::api::{self, StorageChanges};
 use crate::db::{StorageDb, ThreadSafeStorageDb};
 use crate::error::*;
+use crate::listeners::{ChangeListeners, StorageChangeListener};
 use crate::migration::{migrate, MigrationInfo};
 use crate::sync;
 use std::path::Path;
@@ -30,6 +31,7 @@ use serde_json::Value as JsonValue;
 /// around the same object.
 pub struct WebExtStorageStore {
     db: Arc<ThreadSafeStorageDb>,
+    listeners: Arc<ChangeListeners>,
 }
 
 impl WebExtStorageStore {
@@ -39,6 +41,7 @@ impl WebExtStorageStore {
         let db = StorageDb::new(db_path)?;
         Ok(Self {
             db: Arc::new(ThreadSafeStorageDb::new(db)),
+            listeners: Arc::default(),
         })
     }
 
@@ -48,6 +51,7 @@ impl WebExtStorageStore {
         let db = StorageDb::new_memory(db_path)?;
         Ok(Self {
             db: Arc::new(ThreadSafeStorageDb::new(db)),
+            listeners: Arc::default(),
         })
     }
 
@@ -63,6 +67,8 @@ impl WebExtStorageStore {
         let tx = db.unchecked_transaction()?;
         let result = api::set(&tx, ext_id, val)?;
         tx.commit()?;
+        drop(db);
+        self.listeners.notify(ext_id, &result);
         Ok(result)
     }
 
@@ -103,6 +109,8 @@ impl WebExtStorageStore {
         let tx = db.unchecked_transaction()?;
         let result = api::remove(&tx, ext_id, keys)?;
         tx.commit()?;
+        drop(db);
+        self.listeners.notify(ext_id, &result);
         Ok(result)
     }
 
@@ -114,6 +122,8 @@ impl WebExtStorageStore {
         let tx = db.unchecked_transaction()?;
         let result = api::clear(&tx, ext_id)?;
         tx.commit()?;
+        drop(db);
+        self.listeners.notify(ext_id, &result);
         Ok(result)
     }
 
@@ -124,9 +134,24 @@ impl WebExtStorageStore {
         api::get_bytes_in_use(&db, ext_id, keys)
     }
 
+    /// Registers a listener which is called with the changes whenever the
+    /// data for `ext_id` is changed by `set`, `remove` or `clear`, or by a
+    /// sync. Replaces any listener already registered for `ext_id`.
+    ///
+    /// Listeners are called after the change has been committed, on the
+    /// thread which made it.
+    pub fn register_change_listener(&self, ext_id: &str, listener: Box<dyn StorageChangeListener>) {
+        self.listeners.register(ext_id, listener)
+    }
+
+    /// Removes the listener for `ext_id`. Returns false if there wasn't one.
+    pub fn unregister_change_listener(&self, ext_id: &str) -> bool {
+        self.listeners.unregister(ext_id)
+    }
+
     /// Returns a bridged sync engine for Desktop for this store.
     pub fn bridged_engine(&self) -> sync::BridgedEngine {
-        sync::BridgedEngine::new(&self.db)
+        sync::BridgedEngine::new(&self.db, Arc::clone(&self.listeners))
     }
 
     /// Closes the store and its database connection. See the docs for
@@ -210,9 +235,48 @@ pub mod test {
         ensure_send::<WebExtStorageStore>();
     }
 
+    #[derive(Default)]
+    struct RecordingListener {
+        calls: parking_lot::Mutex<Vec<(String, StorageChanges)>>,
+    }
+
+    impl StorageChangeListener for Arc<RecordingListener> {
+        fn on_changed(&self, ext_id: String, changes: StorageChanges) {
+            self.calls.lock().push((ext_id, changes));
+        }
+    }
+
+    #[test]
+    fn test_change_listeners() -> Result<()> {
+        let store = new_mem_store();
+        let listener = Arc::new(RecordingListener::default());
+        store.register_change_listener("ext-a", Box::new(Arc::clone(&listener)));
+
+        let set_changes = store.set("ext-a", serde_json::json!({"foo": "bar"}))?;
+        // Other extensions' changes aren't reported.
+        store.set("ext-b", serde_json::json!({"foo": "bar"}))?;
+        // Nor are calls which don't change anything.
+        store.remove("ext-a", serde_json::json!("missing"))?;
+        let clear_changes = store.clear("ext-a")?;
+        assert_eq!(
+            *listener.calls.lock(),
+            vec![
+                ("ext-a".to_string(), set_changes),
+                ("ext-a".to_string(), clear_changes),
+            ]
+        );
+
+        assert!(store.unregister_change_listener("ext-a"));
+        assert!(!store.unregister_change_listener("ext-a"));
+        store.set("ext-a", serde_json::json!({"foo": "baz"}))?;
+        assert_eq!(listener.calls.lock().len(), 2);
+        Ok(())
+    }
+
     pub fn new_mem_store() -> WebExtStorageStore {
         WebExtStorageStore {
             db: Arc::new(ThreadSafeStorageDb::new(crate::db::test::new_mem_db())),
+            listeners: Arc::default(),
         }
     }
 }
@@ -10,6 +10,7 @@ use sync15::engine::ApplyResults;
 use sync_guid::Guid as SyncGuid;
 
 use crate::db::{delete_meta, get_meta, put_meta, ThreadSafeStorageDb};
+use crate::listeners::ChangeListeners;
 use crate::schema;
 use crate::sync::incoming::{apply_actions, get_incoming, plan_incoming, stage_incoming};
 use crate::sync::outgoing::{get_outgoing, record_uploaded, stage_outgoing};
@@ -28,13 +29,16 @@ const SYNC_ID_META_KEY: &str = "sync_id";
 /// engines all took lifetime params to ensure they don't outlive the store.
 pub struct BridgedEngine {
     db: Weak<ThreadSafeStorageDb>,
+    listeners: Arc<ChangeListeners>,
 }
 
 impl BridgedEngine {
-    /// Creates a bridged engine for syncing.
-    pub fn new(db: &Arc<ThreadSafeStorageDb>) -> Self {
+    /// Creates a bridged engine for syncing. Changes applied by a sync are
+    /// reported to `listeners`.
+    pub(crate) fn new(db: &Arc<ThreadSafeStorageDb>, listeners: Arc<ChangeListeners>) -> Self {
         BridgedEngine {
             db: Arc::downgrade(db),
+            listeners,
         }
     }
 
@@ -112,7 +116,7 @@ impl sync15::engine::BridgedEngine for BridgedEngine {
     fn store_incoming(&self, incoming_bsos: Vec<IncomingBso>) -> Result<()> {
         let shared_db = self.thread_safe_storage_db()?;
         let db = shared_db.lock();
-        let signal = db.begin_interrupt_scope()?;
+        let signal = shared_db.begin_interrupt_scope()?;
         let tx = db.unchecked_transaction()?;
         let incoming_content: Vec<_> = incoming_bsos
             .into_iter()
@@ -126,25 +130,35 @@ impl sync15::engine::BridgedEngine for BridgedEngine {
     fn apply(&self) -> Result<ApplyResults> {
         let shared_db = self.thread_safe_storage_db()?;
         let db = shared_db.lock();
-        let signal = db.begin_interrupt_scope()?;
+        let signal = shared_db.begin_interrupt_scope()?;
 
         let tx = db.unchecked_transaction()?;
         let incoming = get_incoming(&tx)?;
-        let actions = incoming
+        let actions: Vec<_> = incoming
             .into_iter()
             .map(|(item, state)| (item, plan_incoming(state)))
             .collect();
+        let applied: Vec<_> = actions
+            .iter()
+            .filter_map(|(_, action)| action.changes())
+            .map(|(ext_id, changes)| (ext_id.to_string(), changes.clone()))
+            .collect();
         apply_actions(&tx, actions, &signal)?;
         stage_outgoing(&tx)?;
         tx.commit()?;
 
-        Ok(get_outgoing(&db, &signal)?.into())
+        let outgoing = get_outgoing(&db, &signal)?;
+        drop(db);
+        for (ext_id, changes) in applied {
+            self.listeners.notify(&ext_id, &changes);
+        }
+        Ok(outgoing.into())
     }
 
     fn set_uploaded(&self, _server_modified_millis: i64, ids: &[SyncGuid]) -> Result<()> {
         let shared_db = self.thread_safe_storage_db()?;
         let db = shared_db.lock();
-        let signal = db.begin_interrupt_scope()?;
+        let signal = shared_db.begin_interrupt_scope()?;
         let tx = db.unchecked_transaction()?;
         record_uploaded(&tx, ids, &signal)?;
         tx.commit()?;
@@ -270,7 +284,7 @@ mod tests {
     #[test]
     fn test_wipe() -> Result<()> {
         let strong = new_mem_thread_safe_storage_db();
-        let engine = super::BridgedEngine::new(&strong);
+        let engine = super::BridgedEngine::new(&strong, Default::default());
 
         setup_mock_data(&engine)?;
 
@@ -288,7 +302,7 @@ mod tests {
     #[test]
     fn test_reset() -> Result<()> {
         let strong = new_mem_thread_safe_storage_db();
-        let engine = super::BridgedEngine::new(&strong);
+        let engine = super::BridgedEngine::new(&strong, Default::default());
 
         setup_mock_data(&engine)?;
         put_meta(
@@ -311,7 +325,7 @@ mod tests {
     #[test]
     fn test_ensure_missing_sync_id() -> Result<()> {
         let strong = new_mem_thread_safe_storage_db();
-        let engine = super::BridgedEngine::new(&strong);
+        let engine = super::BridgedEngine::new(&strong, Default::default());
 
         setup_mock_data(&engine)?;
 
@@ -326,7 +340,7 @@ mod tests {
     #[test]
     fn test_ensure_new_sync_id() -> Result<()> {
         let strong = new_mem_thread_safe_storage_db();
-        let engine = super::BridgedEngine::new(&strong);
+        let engine = super::BridgedEngine::new(&strong, Default::default());
 
         setup_mock_data(&engine)?;
 
@@ -349,7 +363,7 @@ mod tests {
     #[test]
     fn test_ensure_same_sync_id() -> Result<()> {
         let strong = new_mem_thread_safe_storage_db();
-        let engine = super::BridgedEngine::new(&strong);
+        let engine = super::BridgedEngine::new(&strong, Default::default());
 
         setup_mock_data(&engine)?;
         assert_not_reset(&engine)?;
@@ -369,7 +383,7 @@ mod tests {
     #[test]
     fn test_reset_sync_id() -> Result<()> {
         let strong = new_mem_thread_safe_storage_db();
-        let engine = super::BridgedEngine::new(&strong);
+        let engine = super::BridgedEngine::new(&strong, Default::default());
 
         setup_mock_data(&engine)?;
         put_meta(
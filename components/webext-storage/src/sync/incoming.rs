@@ -248,6 +248,23 @@ pub enum IncomingAction {
     Nothing,
 }
 
+impl IncomingAction {
+    /// Returns the extension and the changes we'll record for it, if this
+    /// action changes any local data.
+    pub fn changes(&self) -> Option<(&str, &StorageChanges)> {
+        match self {
+            IncomingAction::DeleteLocally { ext_id, changes }
+            | IncomingAction::TakeRemote {
+                ext_id, changes, ..
+            }
+            | IncomingAction::Merge {
+                ext_id, changes, ..
+            } => Some((ext_id, changes)),
+            IncomingAction::Same { .. } | IncomingAction::Nothing => None,
+        }
+    }
+}
+
 /// Takes the state of an item and returns the action we should take for it.
 pub fn plan_incoming(s: IncomingState) -> IncomingAction {
     match s {
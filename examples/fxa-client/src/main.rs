@@ -110,6 +110,7 @@ fn load_account(cli: &Cli, scopes: &[&str]) -> Result<FirefoxAccount> {
         redirect_uri: REDIRECT_URI.into(),
         client_id: CLIENT_ID.into(),
         token_server_url_override: None,
+        extra_headers: None,
     };
     fxa_creds::get_cli_fxa(config, CREDENTIALS_PATH, scopes).map(|cli| cli.account)
 }
@@ -72,7 +72,11 @@ impl LegacyPlace {
                 .with_at(types::Timestamp((v.date / 1000) as u64))
                 .with_title(self._title.clone())
                 .with_is_remote(rand::random::<f64>() < options.remote_probability);
-            places::storage::history::apply_observation_direct(db, obs)?;
+            places::storage::history::apply_observation_direct(
+                db,
+                obs,
+                places::HiddenVisitPolicy::default(),
+            )?;
         }
         Ok(())
     }
@@ -193,7 +193,11 @@ fn create_fake_visits(db: &PlacesDb, num_sites: usize, num_visits: usize) -> Res
                 .with_at(Some(st.into()))
                 .with_visit_type(places::VisitType::Link);
             st = st.checked_sub(Duration::new(1, 0)).unwrap();
-            places::storage::history::apply_observation_direct(db, obs)?;
+            places::storage::history::apply_observation_direct(
+                db,
+                obs,
+                places::HiddenVisitPolicy::default(),
+            )?;
             if interrupt_support::ShutdownInterruptee.was_interrupted() {
                 println!("Interrupted");
                 return Ok(());
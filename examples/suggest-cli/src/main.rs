@@ -117,6 +117,7 @@ fn query(store: &SuggestStore, provider: SuggestionProviderArg, input: String) {
         providers: vec![provider.into()],
         keyword: input,
         limit: None,
+        provider_limits: None,
     };
     let suggestions = store
         .query(query)
@@ -1,11 +1,16 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
-use criterion::Criterion;
+use criterion::{BenchmarkId, Criterion};
 use places::api::{
     matcher::{match_url, search_frecent, SearchParams},
     places_api::ConnectionType,
 };
+use places::history_sync::record::HistoryRecordVisit;
+use places::storage::{
+    bookmarks,
+    history::{self, history_sync},
+};
 use places::PlacesDb;
 use sql_support::ConnExt;
 use std::rc::Rc;
@@ -41,7 +46,11 @@ fn init_db(db: &mut PlacesDb) -> places::Result<()> {
                 .with_is_remote(i < 10)
                 .with_visit_type(places::VisitType::Link)
                 .with_at(Timestamp(now.0 - day_ms * (1 + i)));
-            places::storage::history::apply_observation_direct(db, obs)?;
+            places::storage::history::apply_observation_direct(
+                db,
+                obs,
+                places::HiddenVisitPolicy::default(),
+            )?;
         }
     }
     places::storage::delete_pending_temp_tables(db)?;
@@ -64,7 +73,8 @@ impl TestDb {
             file,
             ConnectionType::ReadWrite,
             0,
-            Arc::new(parking_lot::Mutex::new(())),
+            Arc::new(places::db::WriteQueue::new()),
+            places::PlacesLimits::default(),
         )
         .unwrap();
         println!("Populating test database...");
@@ -130,3 +140,47 @@ pub fn bench_match_url(c: &mut Criterion) {
         match_url(db, "https://hg.mozilla.org/mozilla-central").unwrap()
     });
 }
+
+// What a sync does for each incoming history record, while the user types URLs into the
+// awesomebar. Applying the same visits again doesn't add them twice, so each iteration does
+// the same work.
+fn awesomebar_and_sync(db: &PlacesDb, urls: &[url::Url], visits: &[HistoryRecordVisit]) {
+    for url in urls {
+        match_url(db, url.as_str()).unwrap();
+        history::get_visited(db, vec![url.clone()]).unwrap();
+
+        let guid = history::url_to_guid(db, url).unwrap().unwrap();
+        history_sync::fetch_visits(db, url, 20).unwrap();
+        history_sync::apply_synced_visits(db, &guid, url, &None, visits, &Default::default())
+            .unwrap();
+        bookmarks::fetch::fetch_bookmarks_by_url(db, url).unwrap();
+    }
+}
+
+pub fn bench_statement_cache(c: &mut Criterion) {
+    let test_db = TestDb::new();
+    let urls: Vec<_> = get_dummy_data()
+        .into_iter()
+        .take(10)
+        .map(|entry| url::Url::parse(&entry.url).unwrap())
+        .collect();
+    let now: Timestamp = std::time::SystemTime::now().into();
+    let visits = [HistoryRecordVisit {
+        date: Timestamp(now.0 - 60 * 60 * 1000).into(),
+        transition: places::VisitType::Link as u8,
+        unknown_fields: Default::default(),
+    }];
+    let mut group = c.benchmark_group("awesomebar + sync");
+    // A cache too small for the workload's statements prepares them over and over, which
+    // shows up as a slower run than the larger caches.
+    for capacity in [4, 16, places::db::db::STATEMENT_CACHE_CAPACITY] {
+        let db = &test_db.db;
+        db.set_statement_cache_capacity(capacity);
+        group.bench_with_input(
+            BenchmarkId::new("statement cache", capacity),
+            &capacity,
+            |b, _| b.iter(|| awesomebar_and_sync(db, &urls, &visits)),
+        );
+    }
+    group.finish();
+}
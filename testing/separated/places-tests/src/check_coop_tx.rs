@@ -3,7 +3,8 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use places::api::places_api::ConnectionType;
-use places::{PlacesDb, Result};
+use places::db::WriteQueue;
+use places::{PlacesDb, PlacesLimits, Result};
 use std::fs::remove_file;
 use std::sync::mpsc::sync_channel;
 use std::sync::Arc;
@@ -28,13 +29,27 @@ fn test_coop_tx() {
     let _ = remove_file(path); // ignore error
     let _ = env_logger::try_init();
 
-    let coop_tx_lock = Arc::new(parking_lot::Mutex::new(()));
+    let write_queue = Arc::new(WriteQueue::new());
 
-    let dbmain = PlacesDb::open(path, ConnectionType::ReadWrite, 0, coop_tx_lock.clone()).unwrap();
+    let dbmain = PlacesDb::open(
+        path,
+        ConnectionType::ReadWrite,
+        0,
+        write_queue.clone(),
+        PlacesLimits::default(),
+    )
+    .unwrap();
     let (tx, rx) = sync_channel(0);
 
     let child = thread::spawn(move || {
-        let db1 = PlacesDb::open(path, ConnectionType::Sync, 0, coop_tx_lock.clone()).unwrap();
+        let db1 = PlacesDb::open(
+            path,
+            ConnectionType::Sync,
+            0,
+            write_queue.clone(),
+            PlacesLimits::default(),
+        )
+        .unwrap();
         // assert_eq!(rx.recv().unwrap(), 0);
         let mut t = db1
             .begin_transaction()
@@ -0,0 +1,131 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+use places::{
+    api::places_api::{ConnectionType, PlacesApi},
+    import::common::HistoryImportProgressListener,
+    storage::history::{self, get_visit_infos},
+    Result, VisitTransitionSet, VisitType,
+};
+use rusqlite::Connection;
+use std::path::Path;
+use std::sync::Mutex;
+use tempfile::tempdir;
+use types::Timestamp;
+use url::Url;
+
+// Seconds between the Unix epoch and Safari's, 2001-01-01.
+const SAFARI_EPOCH_OFFSET: f64 = 978_307_200.0;
+
+fn empty_safari_db(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch(include_str!("./safari_schema.sql"))?;
+    Ok(conn)
+}
+
+fn safari_time(ts: Timestamp) -> f64 {
+    ts.as_millis() as f64 / 1000.0 - SAFARI_EPOCH_OFFSET
+}
+
+#[derive(Default)]
+struct RecordingProgress(Mutex<Vec<(u32, u32)>>);
+
+impl HistoryImportProgressListener for RecordingProgress {
+    fn on_progress(&self, num_processed: u32, num_total: u32) {
+        self.0.lock().unwrap().push((num_processed, num_total));
+    }
+}
+
+#[test]
+fn test_safari_import() -> Result<()> {
+    let tmpdir = tempdir().unwrap();
+    let safari_path = tmpdir.path().join("History.db");
+    let safari_db = empty_safari_db(&safari_path)?;
+
+    let first_visit_ts = Timestamp(Timestamp::now().as_millis() - 60_000);
+    let second_visit_ts = Timestamp(first_visit_ts.as_millis() + 10_000);
+    let redirect_ts = Timestamp(first_visit_ts.as_millis() + 20_000);
+    safari_db.execute_batch(
+        "INSERT INTO history_items(id, url) VALUES
+            (1, 'https://example.com/'),
+            (2, 'http://mozilla.org/'),
+            (3, 'https://www.mozilla.org/'),
+            (4, 'not a url');",
+    )?;
+    let mut stmt = safari_db.prepare(
+        "INSERT INTO history_visits(
+            id, history_item, visit_time, title, redirect_source, redirect_destination, origin
+        ) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )?;
+    let no_id: Option<i64> = None;
+    stmt.execute((
+        1,
+        1,
+        safari_time(first_visit_ts),
+        "Old title",
+        no_id,
+        no_id,
+        0,
+    ))?;
+    stmt.execute((
+        2,
+        1,
+        safari_time(second_visit_ts),
+        "Example",
+        no_id,
+        no_id,
+        1,
+    ))?;
+    stmt.execute((3, 2, safari_time(redirect_ts), "", no_id, no_id, 0))?;
+    stmt.execute((4, 3, safari_time(redirect_ts), "Mozilla", Some(3), no_id, 0))?;
+    stmt.execute((5, 4, safari_time(redirect_ts), "Bad", no_id, no_id, 0))?;
+    drop(stmt);
+    safari_db.execute(
+        "UPDATE history_visits SET redirect_destination = 4 WHERE id = 3",
+        [],
+    )?;
+    drop(safari_db);
+
+    let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
+    let conn = places_api.open_connection(ConnectionType::ReadWrite)?;
+    let progress = RecordingProgress::default();
+    let result = places::import::import_safari_history(&conn, &safari_path, Some(&progress))?;
+    assert_eq!(result.num_total, 5);
+    assert_eq!(result.num_succeeded, 4);
+    assert_eq!(result.num_failed, 1);
+    assert_eq!(*progress.0.lock().unwrap(), vec![(5, 5)]);
+
+    let places_db = places_api.open_connection(ConnectionType::ReadOnly)?;
+    assert_eq!(
+        history::get_visit_count(&places_db, VisitTransitionSet::empty())?,
+        4
+    );
+    // The redirect source is hidden, so isn't included here.
+    let visit_infos = get_visit_infos(
+        &places_db,
+        Timestamp(first_visit_ts.as_millis() - 1),
+        Timestamp::now(),
+        VisitTransitionSet::empty(),
+    )?;
+    assert_eq!(visit_infos.len(), 3);
+    assert_eq!(visit_infos[0].url.as_str(), "https://example.com/");
+    assert_eq!(visit_infos[0].title, Some("Example".to_owned()));
+    assert_eq!(visit_infos[0].timestamp, first_visit_ts);
+    assert!(!visit_infos[0].is_remote);
+    assert_eq!(visit_infos[1].timestamp, second_visit_ts);
+    assert!(visit_infos[1].is_remote);
+    assert_eq!(visit_infos[2].url.as_str(), "https://www.mozilla.org/");
+    assert_eq!(visit_infos[2].visit_type, VisitType::RedirectTemporary);
+    let visited =
+        history::get_visited(&places_db, vec![Url::parse("http://mozilla.org/").unwrap()])?;
+    assert!(visited[0]);
+
+    // Importing again doesn't duplicate any visits.
+    let result = places::import::import_safari_history(&conn, &safari_path, None)?;
+    assert_eq!(result.num_succeeded, 0);
+    assert_eq!(
+        history::get_visit_count(&places_db, VisitTransitionSet::empty())?,
+        4
+    );
+    Ok(())
+}
@@ -0,0 +1,29 @@
+-- A cut-down version of Safari's History.db schema, with just the tables we read from.
+CREATE TABLE history_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL UNIQUE,
+    domain_expansion TEXT NULL,
+    visit_count INTEGER NOT NULL DEFAULT 0,
+    daily_visit_counts BLOB NOT NULL DEFAULT x'',
+    weekly_visit_counts BLOB NULL,
+    autocomplete_triggers BLOB NULL,
+    should_recompute_derived_visit_counts INTEGER NOT NULL DEFAULT 0,
+    visit_count_score INTEGER NOT NULL DEFAULT 0,
+    status_code INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE history_visits (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    history_item INTEGER NOT NULL REFERENCES history_items(id) ON DELETE CASCADE,
+    visit_time REAL NOT NULL,
+    title TEXT NULL,
+    load_successful BOOLEAN NOT NULL DEFAULT 1,
+    http_non_get BOOLEAN NOT NULL DEFAULT 0,
+    synthesized BOOLEAN NOT NULL DEFAULT 0,
+    redirect_source INTEGER NULL UNIQUE REFERENCES history_visits(id) ON DELETE CASCADE,
+    redirect_destination INTEGER NULL UNIQUE REFERENCES history_visits(id) ON DELETE CASCADE,
+    origin INTEGER NOT NULL DEFAULT 0,
+    generation INTEGER NOT NULL DEFAULT 0,
+    attributes INTEGER NOT NULL DEFAULT 0,
+    score INTEGER NOT NULL DEFAULT 0
+);
@@ -4,3 +4,4 @@
 
 mod check_coop_tx;
 mod ios_history;
+mod safari_history;
@@ -8,9 +8,12 @@ use fxa_client::{Device, FxaConfig, FxaServer};
 use logins::LoginStore;
 use std::collections::HashMap;
 use std::sync::Arc;
-use sync15::{client::{SetupStorageClient, Sync15StorageClient}, DeviceType};
+use sync15::{
+    client::{SetupStorageClient, Sync15StorageClient},
+    DeviceType,
+};
 use sync_manager::{
-    manager::SyncManager, SyncEngineSelection, SyncParams, SyncReason, DeviceSettings
+    manager::SyncManager, DeviceSettings, SyncEngineSelection, SyncParams, SyncReason,
 };
 use tabs::TabsStore;
 
@@ -36,11 +39,21 @@ impl TestClient {
     pub fn new(cli: Arc<CliFxa>, device_name: &str) -> Result<Self> {
         // XXX - not clear if/how this device gets cleaned up - we never disconnect from the account!
         // And this is messy - I think it reflects that the public device api should be improved?
-        let device = match cli.account.get_devices(false)?.into_iter().find(|d| d.is_current_device) {
+        let device = match cli
+            .account
+            .get_devices(false)?
+            .into_iter()
+            .find(|d| d.is_current_device)
+        {
             Some(d) => d,
             None => {
-                cli.account.initialize_device(device_name, DeviceType::Desktop, vec![])?;
-                cli.account.get_devices(true)?.into_iter().find(|d| d.is_current_device).ok_or_else(|| anyhow::Error::msg("can't find new device"))?
+                cli.account
+                    .initialize_device(device_name, DeviceType::Desktop, vec![])?;
+                cli.account
+                    .get_devices(true)?
+                    .into_iter()
+                    .find(|d| d.is_current_device)
+                    .ok_or_else(|| anyhow::Error::msg("can't find new device"))?
             }
         };
 
@@ -78,6 +91,7 @@ impl TestClient {
                 name: self.device.display_name.clone(),
                 kind: self.device.device_type,
             },
+            device_engine_changes: None,
         };
         let result = self.sync_manager.sync(params)?;
         // We expect all syncs in these tests to pass, so let's catch that here
@@ -135,10 +149,12 @@ pub struct TestUser {
 
 impl TestUser {
     pub fn new(cli: Arc<CliFxa>, client_count: usize) -> Result<Self> {
-        let clients = (0..client_count).map(|client_num| {
-            let name = format!("Testing Device {client_num}");
-            TestClient::new(cli.clone(), &name)
-        }).collect::<Result<_>>()?;
+        let clients = (0..client_count)
+            .map(|client_num| {
+                let name = format!("Testing Device {client_num}");
+                TestClient::new(cli.clone(), &name)
+            })
+            .collect::<Result<_>>()?;
         Ok(Self { clients })
     }
 }
@@ -159,10 +175,13 @@ impl FxaConfigUrl {
             FxaConfigUrl::Stage => FxaConfig::stage(client_id, redirect),
             FxaConfigUrl::Release => FxaConfig::release(client_id, redirect),
             FxaConfigUrl::Custom(url) => FxaConfig {
-                server: FxaServer::Custom { url: url.to_string() },
+                server: FxaServer::Custom {
+                    url: url.to_string(),
+                },
                 client_id: client_id.to_string(),
                 redirect_uri: redirect.to_string(),
                 token_server_url_override: None,
+                extra_headers: None,
             },
         }
     }